@@ -0,0 +1,85 @@
+//! Install hot paths for packages with many files.
+//!
+//! Run with `cargo bench --bench install`.
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use semver::Version;
+use std::fs;
+use std::path::Path;
+use uhpm::db::PackageDB;
+use uhpm::package::conflicts::Claim;
+use uhpm::package::installer::create_symlinks;
+use uhpm::package::{LinkMode, Package, Source};
+
+const FILE_COUNTS: [usize; 2] = [1_000, 10_000];
+
+/// Creates a package directory with `count` files and a symlist linking
+/// each of them into `target_dir`
+fn make_package(root: &Path, target_dir: &Path, count: usize) {
+    let share = root.join("share");
+    fs::create_dir_all(&share).unwrap();
+
+    let mut symlist = String::new();
+    for i in 0..count {
+        let rel = format!("share/file{}.txt", i);
+        fs::write(root.join(&rel), "x").unwrap();
+        symlist.push_str(&format!("{} {}/file{}.txt\n", rel, target_dir.display(), i));
+    }
+    fs::write(root.join("symlist"), symlist).unwrap();
+}
+
+fn bench_create_symlinks(c: &mut Criterion) {
+    let mut group = c.benchmark_group("create_symlinks");
+    group.sample_size(10);
+
+    for count in FILE_COUNTS {
+        let tmp = tempfile::tempdir().unwrap();
+        let pkg_root = tmp.path().join("pkg");
+        let targets = tmp.path().join("targets");
+        make_package(&pkg_root, &targets, count);
+
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter(|| create_symlinks(&pkg_root, false, &Claim::default()).unwrap());
+        });
+    }
+    group.finish();
+}
+
+fn bench_add_package_full(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("add_package_full");
+    group.sample_size(10);
+
+    for count in FILE_COUNTS {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = rt.block_on(async {
+            PackageDB::new(&tmp.path().join("packages.db"))
+                .unwrap()
+                .init()
+                .await
+                .unwrap()
+        });
+        let pkg = Package::new(
+            "bench",
+            Version::parse("1.0.0").unwrap(),
+            "bench",
+            Source::Raw("bench".to_string()),
+            "",
+            vec![],
+        );
+        let files: Vec<String> = (0..count)
+            .map(|i| format!("/tmp/uhpm-bench/file{}.txt", i))
+            .collect();
+
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter(|| {
+                rt.block_on(db.add_package_full(&pkg, &files, LinkMode::Symlink))
+                    .unwrap()
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_create_symlinks, bench_add_package_full);
+criterion_main!(benches);
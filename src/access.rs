@@ -0,0 +1,66 @@
+//! # Read-Only Mode
+//!
+//! uhpm runs read-only when asked to with `--read-only`, or when the uhpm
+//! home cannot be written to (a read-only mount, another user's
+//! installation). Query commands then work without touching anything:
+//!
+//! - the package database is opened read-only and not migrated;
+//! - repository databases are not downloaded, verified caches are used;
+//! - usage is not recorded and bin links are not moved.
+//!
+//! Commands that change the installation fail before doing anything with
+//! [`UhpmError::ReadOnly`].
+
+use crate::error::UhpmError;
+use crate::platform;
+use std::fs::{self, OpenOptions};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Switches the process to read-only mode
+pub fn enable() {
+    READ_ONLY.store(true, Ordering::Relaxed);
+}
+
+/// Whether the process runs read-only
+pub fn is_read_only() -> bool {
+    READ_ONLY.load(Ordering::Relaxed)
+}
+
+/// Fails in read-only mode
+pub fn ensure_writable() -> Result<(), UhpmError> {
+    if is_read_only() {
+        return Err(UhpmError::ReadOnly(platform::uhpm_home()));
+    }
+    Ok(())
+}
+
+/// Whether files can be created in `dir`, or in its closest existing
+/// ancestor if it does not exist yet
+pub fn writable(dir: &Path) -> bool {
+    let Some(existing) = dir.ancestors().find(|p| p.exists()) else {
+        return false;
+    };
+    let probe = existing.join(format!(".uhpm-write-test-{}", std::process::id()));
+    match OpenOptions::new().write(true).create_new(true).open(&probe) {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_writable_probes_closest_existing_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        assert!(writable(&tmp.path().join("not/yet/created")));
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,714 @@
+//! # Package Archives
+//!
+//! Creation, extraction and inspection of `.uhp` archives, shared by the
+//! installer, the `uhpm pkg` / `uhpmk` tooling and tests.
+//!
+//! A `.uhp` archive is a tar stream with `uhp.toml` at its root, compressed
+//! with gzip (the default) or zstd, or left uncompressed. The compression is
+//! detected from the stream's magic bytes when reading, so readers never
+//! depend on the file name.
+//!
+//! Archives packed as `tar czf pkg.uhp pkg/` carry everything one level
+//! down; [`extract_package`] and [`read_manifest`] detect a single top-level
+//! directory holding `uhp.toml` and strip it transparently.
+//!
+//! ## Extraction limits
+//! [`extract`] checks every entry before writing it and stops at the
+//! first one that breaks a rule, so a hostile archive cannot write outside
+//! the destination or fill the disk:
+//! - paths must be relative and free of `..`, and so must hard link
+//!   targets;
+//! - only files, directories and links are extracted, never devices or
+//!   FIFOs;
+//! - the sizes of all entries together and their number are capped by
+//!   [`ExtractLimits`] (`extract` in `config.ron`).
+//!
+//! [`decompress`] applies the same size cap to the single file it writes.
+//!
+//! ## Packing options
+//! [`ArchiveOptions`] also set the compression level (gzip 0-9, zstd 1-22;
+//! the codec's default otherwise) and paths to leave out. An exclude
+//! pattern uses the wildcards of [`crate::package::pattern`]; one with a `/`
+//! is matched against the whole path relative to the packed directory,
+//! one without against each file name. Excluding a directory leaves out
+//! everything below it.
+//!
+//! ## Deterministic mode
+//! With [`ArchiveOptions::deterministic`] entries are written in sorted
+//! order with zeroed ownership, normalized permissions (`0755` for
+//! directories and executables, `0644` otherwise) and one timestamp for
+//! all of them: the epoch, or [`ArchiveOptions::mtime`] (`uhpm pkg pack
+//! --reproducible` passes `SOURCE_DATE_EPOCH`). Packing the same directory
+//! twice yields identical bytes, so repositories can deduplicate archives
+//! by checksum.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use std::path::Path;
+//! use uhpm::archive::{self, ArchiveOptions, Compression};
+//!
+//! let opts = ArchiveOptions::new()
+//!     .compression(Compression::Zstd)
+//!     .level(19)
+//!     .exclude("*.o")
+//!     .deterministic(true);
+//! archive::create(Path::new("my_package"), Path::new("my_package-0.1.0.uhp"), &opts).unwrap();
+//! let files = archive::list(Path::new("my_package-0.1.0.uhp")).unwrap();
+//! ```
+
+use crate::package::pattern;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use walkdir::WalkDir;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression of the tar stream
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    Gzip,
+    Zstd,
+    None,
+}
+
+impl Compression {
+    /// Detects the compression of an archive from its first bytes
+    pub fn detect(path: &Path) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        let mut file = File::open(path)?;
+        let read = file.read(&mut magic)?;
+        Ok(if read >= 4 && magic == ZSTD_MAGIC {
+            Compression::Zstd
+        } else if read >= 2 && magic[..2] == GZIP_MAGIC {
+            Compression::Gzip
+        } else {
+            Compression::None
+        })
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gzip" | "gz" => Ok(Compression::Gzip),
+            "zstd" | "zst" => Ok(Compression::Zstd),
+            "none" => Ok(Compression::None),
+            other => Err(format!(
+                "unknown compression `{}`, expected gzip, zstd or none",
+                other
+            )),
+        }
+    }
+}
+
+/// Writes the decompressed content of `src` to `dst`, detecting the
+/// compression like archives; uncompressed files are copied
+///
+/// Fails once the output grows beyond the size cap of `limits`, so a small
+/// compressed file cannot fill the disk.
+pub fn decompress(src: &Path, dst: &Path, limits: &ExtractLimits) -> io::Result<u64> {
+    let file = BufReader::new(File::open(src)?);
+    let reader: Box<dyn Read> = match Compression::detect(src)? {
+        Compression::Gzip => Box::new(GzDecoder::new(file)),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(file)?),
+        Compression::None => Box::new(file),
+    };
+    let max_size = limits.max_size_mb.saturating_mul(1024 * 1024);
+    let written = io::copy(
+        &mut reader.take(max_size.saturating_add(1)),
+        &mut File::create(dst)?,
+    )?;
+    if written > max_size {
+        return Err(rejected(
+            src,
+            &format!("it decompresses to more than {} MiB", limits.max_size_mb),
+        ));
+    }
+    Ok(written)
+}
+
+/// Options for [`create()`]
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveOptions {
+    compression: Compression,
+    level: Option<i32>,
+    exclude: Vec<String>,
+    deterministic: bool,
+    mtime: u64,
+}
+
+impl ArchiveOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the compression, gzip by default
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets the compression level; out of range levels are clamped
+    pub fn level(mut self, level: i32) -> Self {
+        self.level = Some(level);
+        self
+    }
+
+    /// Leaves out paths matching `pattern`
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    /// Enables reproducible output
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Sets the timestamp of every entry in deterministic mode, in seconds
+    /// since the epoch
+    pub fn mtime(mut self, mtime: u64) -> Self {
+        self.mtime = mtime;
+        self
+    }
+
+    /// Whether `rel`, relative to the packed directory, is excluded
+    fn excludes(&self, rel: &Path) -> bool {
+        let path = rel.to_string_lossy();
+        let name = rel
+            .file_name()
+            .map(|n| n.to_string_lossy())
+            .unwrap_or_default();
+        self.exclude.iter().any(|p| {
+            if p.contains('/') {
+                pattern::matches(p.trim_matches('/'), &path)
+            } else {
+                pattern::matches(p, &name)
+            }
+        })
+    }
+}
+
+/// Opens an archive for reading, transparently decompressing it
+fn open(path: &Path) -> io::Result<tar::Archive<Box<dyn Read>>> {
+    let file = BufReader::new(File::open(path)?);
+    let reader: Box<dyn Read> = match Compression::detect(path)? {
+        Compression::Gzip => Box::new(GzDecoder::new(file)),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(file)?),
+        Compression::None => Box::new(file),
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+/// Archive entry path without a leading `./`
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect()
+}
+
+/// Packs the contents of `src_dir` into `output`
+///
+/// The encoders are finished explicitly, so an error writing the end of
+/// the compressed stream is reported instead of leaving a truncated
+/// archive behind.
+pub fn create(src_dir: &Path, output: &Path, opts: &ArchiveOptions) -> io::Result<()> {
+    let file = File::create(output)?;
+    match opts.compression {
+        Compression::Gzip => {
+            let level = opts.level.map_or(flate2::Compression::default(), |l| {
+                flate2::Compression::new(l.clamp(0, 9) as u32)
+            });
+            let mut builder = tar::Builder::new(GzEncoder::new(file, level));
+            append_dir(&mut builder, src_dir, opts)?;
+            builder.into_inner()?.finish()?.flush()
+        }
+        Compression::Zstd => {
+            let level = opts.level.map_or(0, |l| l.clamp(1, 22));
+            let mut builder = tar::Builder::new(zstd::stream::write::Encoder::new(file, level)?);
+            append_dir(&mut builder, src_dir, opts)?;
+            builder.into_inner()?.finish()?.flush()
+        }
+        Compression::None => {
+            let mut builder = tar::Builder::new(file);
+            append_dir(&mut builder, src_dir, opts)?;
+            builder.into_inner()?.flush()
+        }
+    }
+}
+
+/// Appends the contents of `src_dir` to `builder` as [`create()`] packs them
+fn append_dir<W: Write>(
+    builder: &mut tar::Builder<W>,
+    src_dir: &Path,
+    opts: &ArchiveOptions,
+) -> io::Result<()> {
+    let walk = WalkDir::new(src_dir)
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| {
+            e.path()
+                .strip_prefix(src_dir)
+                .map_or(true, |rel| !opts.excludes(rel))
+        });
+    for entry in walk {
+        let entry = entry.map_err(io::Error::other)?;
+        let rel = entry
+            .path()
+            .strip_prefix(src_dir)
+            .map_err(io::Error::other)?;
+        if opts.deterministic {
+            append_deterministic(builder, entry.path(), rel, opts.mtime)?;
+        } else {
+            builder.append_path_with_name(entry.path(), rel)?;
+        }
+    }
+    Ok(())
+}
+
+/// Appends `path` as `rel` with the timestamp `mtime`, zeroed ownership
+/// and normalized permissions
+fn append_deterministic<W: Write>(
+    builder: &mut tar::Builder<W>,
+    path: &Path,
+    rel: &Path,
+    mtime: u64,
+) -> io::Result<()> {
+    let meta = fs::symlink_metadata(path)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_mtime(mtime);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_username("")?;
+    header.set_groupname("")?;
+
+    if meta.file_type().is_symlink() {
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_mode(0o777);
+        header.set_size(0);
+        builder.append_link(&mut header, rel, fs::read_link(path)?)?;
+    } else if meta.is_dir() {
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_mode(0o755);
+        header.set_size(0);
+        builder.append_data(&mut header, rel, io::empty())?;
+    } else {
+        let executable = crate::platform::is_executable(path, &meta);
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_mode(if executable { 0o755 } else { 0o644 });
+        header.set_size(meta.len());
+        builder.append_data(&mut header, rel, File::open(path)?)?;
+    }
+    Ok(())
+}
+
+/// Caps on what [`extract`] writes
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ExtractLimits {
+    /// Total size of the extracted files in MiB
+    pub max_size_mb: u64,
+    /// Number of entries, directories included
+    pub max_files: u64,
+}
+
+impl Default for ExtractLimits {
+    fn default() -> Self {
+        Self {
+            max_size_mb: 4096,
+            max_files: 100_000,
+        }
+    }
+}
+
+fn rejected(path: &Path, reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("refusing to extract {}: {}", path.display(), reason),
+    )
+}
+
+/// Whether `path` stays below the directory it is extracted into
+fn is_contained(path: &Path) -> bool {
+    path.components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+/// Extracts `archive` into `dest`, creating it if needed, within `limits`
+///
+/// Fails on the first entry that breaks the rules in the module docs;
+/// entries extracted before it are left in `dest`.
+pub fn extract(archive: &Path, dest: &Path, limits: &ExtractLimits) -> io::Result<()> {
+    fs::create_dir_all(dest)?;
+    let max_size = limits.max_size_mb.saturating_mul(1024 * 1024);
+    let (mut size, mut count) = (0u64, 0u64);
+    let mut tar = open(archive)?;
+    let mut dirs = Vec::new();
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let kind = entry.header().entry_type();
+        match kind {
+            tar::EntryType::Regular
+            | tar::EntryType::Directory
+            | tar::EntryType::Symlink
+            | tar::EntryType::Link => {}
+            tar::EntryType::XGlobalHeader => continue,
+            _ => return Err(rejected(&path, "not a file, directory or link")),
+        }
+        if !is_contained(&path) {
+            return Err(rejected(&path, "path leaves the destination"));
+        }
+        if kind == tar::EntryType::Link
+            && !entry
+                .link_name()?
+                .is_some_and(|target| is_contained(&target))
+        {
+            return Err(rejected(&path, "hard link leaves the destination"));
+        }
+
+        count += 1;
+        size = size.saturating_add(entry.size());
+        if count > limits.max_files {
+            return Err(rejected(
+                &path,
+                &format!("archive has more than {} entries", limits.max_files),
+            ));
+        }
+        if size > max_size {
+            return Err(rejected(
+                &path,
+                &format!("archive unpacks to more than {} MiB", limits.max_size_mb),
+            ));
+        }
+
+        // Directories last, deepest first, so read-only ones are filled
+        // before their permissions are set
+        if kind == tar::EntryType::Directory {
+            dirs.push(entry);
+        } else {
+            entry.unpack_in(dest)?;
+        }
+    }
+    for mut dir in dirs.into_iter().rev() {
+        dir.unpack_in(dest)?;
+    }
+    Ok(())
+}
+
+/// Lists the paths stored in `archive`
+pub fn list(archive: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut tar = open(archive)?;
+    let mut paths = Vec::new();
+    for entry in tar.entries()? {
+        let path = normalize(&entry?.path()?);
+        if !path.as_os_str().is_empty() {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
+
+/// Reads a single file from `archive` without extracting the rest
+pub fn read_file(archive: &Path, name: &Path) -> io::Result<Option<Vec<u8>>> {
+    let mut tar = open(archive)?;
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        if normalize(&entry.path()?) == name {
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data)?;
+            return Ok(Some(data));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the lone top-level directory of `paths` if it, rather than the
+/// archive root, holds `uhp.toml`
+pub fn nested_root(paths: &[PathBuf]) -> Option<PathBuf> {
+    let manifest = Path::new("uhp.toml");
+    if paths.iter().any(|p| p == manifest) {
+        return None;
+    }
+    let mut tops = paths.iter().filter_map(|p| p.components().next());
+    let first = tops.next()?;
+    if !tops.all(|c| c == first) {
+        return None;
+    }
+    let root = PathBuf::from(first.as_os_str());
+    paths.contains(&root.join(manifest)).then_some(root)
+}
+
+/// Extracts a package archive into `dest` within `limits`, stripping a
+/// nested top-level directory (see [`nested_root`])
+///
+/// Returns whether a directory was stripped.
+pub fn extract_package(archive: &Path, dest: &Path, limits: &ExtractLimits) -> io::Result<bool> {
+    extract(archive, dest, limits)?;
+    if dest.join("uhp.toml").exists() {
+        return Ok(false);
+    }
+
+    let entries = fs::read_dir(dest)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    let [root] = entries.as_slice() else {
+        return Ok(false);
+    };
+    if !root.is_dir() || !root.join("uhp.toml").exists() {
+        return Ok(false);
+    }
+
+    // Move the directory aside first, it may contain an entry named like itself
+    let mut aside = dest.as_os_str().to_owned();
+    aside.push(".nested");
+    fs::rename(root, &aside)?;
+    fs::remove_dir(dest)?;
+    fs::rename(&aside, dest)?;
+    Ok(true)
+}
+
+/// Reads `uhp.toml` from a package archive, also when it is nested in a
+/// single top-level directory
+pub fn read_manifest(archive: &Path) -> io::Result<Option<Vec<u8>>> {
+    let manifest = Path::new("uhp.toml");
+    if let Some(data) = read_file(archive, manifest)? {
+        return Ok(Some(data));
+    }
+    match nested_root(&list(archive)?) {
+        Some(root) => read_file(archive, &root.join(manifest)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_dir() -> tempfile::TempDir {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(tmp.path().join("uhp.toml"), "name = \"x\"").unwrap();
+        fs::create_dir(tmp.path().join("bin")).unwrap();
+        fs::write(tmp.path().join("bin/x"), "#!/bin/sh").unwrap();
+        tmp
+    }
+
+    #[test]
+    fn test_roundtrip_all_compressions() {
+        let src = sample_dir();
+        let out = tempfile::tempdir().unwrap();
+
+        for compression in [Compression::Gzip, Compression::Zstd, Compression::None] {
+            let archive = out.path().join("pkg.uhp");
+            create(
+                src.path(),
+                &archive,
+                &ArchiveOptions::new().compression(compression),
+            )
+            .unwrap();
+            assert_eq!(Compression::detect(&archive).unwrap(), compression);
+
+            let mut files = list(&archive).unwrap();
+            files.sort();
+            assert_eq!(
+                files,
+                vec![
+                    PathBuf::from("bin"),
+                    PathBuf::from("bin/x"),
+                    PathBuf::from("uhp.toml")
+                ]
+            );
+            assert_eq!(
+                read_file(&archive, Path::new("uhp.toml")).unwrap().unwrap(),
+                b"name = \"x\""
+            );
+
+            let dest = out.path().join("extracted");
+            extract(&archive, &dest, &ExtractLimits::default()).unwrap();
+            assert!(dest.join("bin/x").exists());
+            fs::remove_dir_all(&dest).unwrap();
+        }
+    }
+
+    /// Writes an uncompressed archive with raw entry names, which
+    /// `tar::Builder` would refuse for `..`
+    fn raw_archive(path: &Path, entries: &[(&str, tar::EntryType, &[u8])]) {
+        let mut builder = tar::Builder::new(File::create(path).unwrap());
+        for (name, kind, data) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_entry_type(*kind);
+            header.set_mode(0o644);
+            header.set_size(data.len() as u64);
+            header.set_cksum();
+            builder.append(&header, *data).unwrap();
+        }
+        builder.finish().unwrap();
+    }
+
+    #[test]
+    fn test_extract_rejects_unsafe_entries() {
+        let tmp = tempfile::tempdir().unwrap();
+        let archive = tmp.path().join("pkg.uhp");
+        let dest = tmp.path().join("out/dest");
+        let limits = ExtractLimits::default();
+        let file = tar::EntryType::Regular;
+
+        raw_archive(&archive, &[("ok", file, b"x"), ("../evil", file, b"x")]);
+        let err = extract(&archive, &dest, &limits).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(dest.join("ok").exists());
+        assert!(!tmp.path().join("out/evil").exists());
+
+        raw_archive(&archive, &[("/abs", file, b"x")]);
+        assert!(extract(&archive, &dest, &limits).is_err());
+        raw_archive(&archive, &[("pipe", tar::EntryType::Fifo, b"")]);
+        assert!(extract(&archive, &dest, &limits).is_err());
+        assert!(!dest.join("pipe").exists());
+
+        let big = vec![0u8; 2 * 1024 * 1024];
+        raw_archive(&archive, &[("big", file, &big)]);
+        let small = ExtractLimits {
+            max_size_mb: 1,
+            max_files: 2,
+        };
+        assert!(extract(&archive, &dest, &small).is_err());
+        assert!(!dest.join("big").exists());
+        raw_archive(
+            &archive,
+            &[("a", file, b""), ("b", file, b""), ("c", file, b"")],
+        );
+        assert!(extract(&archive, &dest, &small).is_err());
+        assert!(!dest.join("c").exists());
+
+        let src = sample_dir();
+        create(src.path(), &archive, &ArchiveOptions::new()).unwrap();
+        extract(&archive, &tmp.path().join("good"), &small).unwrap_err();
+        extract(&archive, &tmp.path().join("good"), &limits).unwrap();
+        assert!(tmp.path().join("good/bin/x").exists());
+    }
+
+    #[test]
+    fn test_decompress_is_limited() {
+        let tmp = tempfile::tempdir().unwrap();
+        let packed = tmp.path().join("data.gz");
+        let mut encoder =
+            GzEncoder::new(File::create(&packed).unwrap(), flate2::Compression::best());
+        encoder.write_all(&vec![0u8; 2 * 1024 * 1024]).unwrap();
+        encoder.finish().unwrap();
+
+        let out = tmp.path().join("data");
+        let small = ExtractLimits {
+            max_size_mb: 1,
+            max_files: 1,
+        };
+        let err = decompress(&packed, &out, &small).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            decompress(&packed, &out, &ExtractLimits::default()).unwrap(),
+            2 * 1024 * 1024
+        );
+    }
+
+    #[test]
+    fn test_nested_root_is_stripped() {
+        let src = sample_dir();
+        let parent = tempfile::tempdir().unwrap();
+        fs::rename(src.path(), parent.path().join("pkg")).unwrap();
+        fs::create_dir(parent.path().join("pkg/pkg")).unwrap();
+
+        let out = tempfile::tempdir().unwrap();
+        let archive = out.path().join("pkg.uhp");
+        create(parent.path(), &archive, &ArchiveOptions::new()).unwrap();
+
+        assert_eq!(
+            nested_root(&list(&archive).unwrap()),
+            Some(PathBuf::from("pkg"))
+        );
+        assert_eq!(read_manifest(&archive).unwrap().unwrap(), b"name = \"x\"");
+
+        let dest = out.path().join("extracted");
+        assert!(extract_package(&archive, &dest, &ExtractLimits::default()).unwrap());
+        assert!(dest.join("uhp.toml").exists());
+        assert!(dest.join("bin/x").exists());
+        assert!(dest.join("pkg").is_dir());
+    }
+
+    #[test]
+    fn test_deterministic_output_is_stable() {
+        let src = sample_dir();
+        let out = tempfile::tempdir().unwrap();
+        let opts = ArchiveOptions::new().deterministic(true);
+
+        let a = out.path().join("a.uhp");
+        let b = out.path().join("b.uhp");
+        create(src.path(), &a, &opts).unwrap();
+        filetime_touch(&src.path().join("bin/x"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let perms = fs::Permissions::from_mode(0o600);
+            fs::set_permissions(src.path().join("uhp.toml"), perms).unwrap();
+        }
+        create(src.path(), &b, &opts).unwrap();
+        assert_eq!(fs::read(&a).unwrap(), fs::read(&b).unwrap());
+
+        let c = out.path().join("c.uhp");
+        create(src.path(), &c, &opts.clone().mtime(1_700_000_000)).unwrap();
+        assert_ne!(fs::read(&a).unwrap(), fs::read(&c).unwrap());
+        let mut tar = open(&c).unwrap();
+        for entry in tar.entries().unwrap() {
+            let header = entry.unwrap().header().clone();
+            assert_eq!(header.mtime().unwrap(), 1_700_000_000);
+            assert_eq!(header.uid().unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn test_level_and_excludes() {
+        let src = sample_dir();
+        fs::create_dir(src.path().join("target")).unwrap();
+        fs::write(src.path().join("target/big.o"), "obj").unwrap();
+        fs::write(src.path().join("bin/x.o"), "obj").unwrap();
+        let out = tempfile::tempdir().unwrap();
+        let archive = out.path().join("pkg.uhp");
+
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            let opts = ArchiveOptions::new()
+                .compression(compression)
+                .level(99)
+                .exclude("target")
+                .exclude("bin/*.o");
+            create(src.path(), &archive, &opts).unwrap();
+            let mut files = list(&archive).unwrap();
+            files.sort();
+            assert_eq!(
+                files,
+                vec![
+                    PathBuf::from("bin"),
+                    PathBuf::from("bin/x"),
+                    PathBuf::from("uhp.toml")
+                ]
+            );
+        }
+        assert_eq!("zstd".parse(), Ok(Compression::Zstd));
+        assert!("lzma".parse::<Compression>().is_err());
+    }
+
+    fn filetime_touch(path: &Path) {
+        let data = fs::read(path).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        fs::write(path, data).unwrap();
+    }
+}
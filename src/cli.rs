@@ -1,19 +1,100 @@
+use crate::archive::{ArchiveOptions, Compression};
+use crate::config::Config;
+use crate::error::{SigningError, UhpmError};
+use crate::history::UndoSource;
+use crate::package::bin_layout::{self, BinLayout};
+use crate::package::dry_run::Change;
+use crate::package::info::PackageInfo;
+use crate::package::licenses::{self, PackageLicense};
+use crate::package::links::{self, LinkAction, Relinked};
+use crate::package::listing::{InstalledPackage, ListFilter};
+use crate::package::maintenance::{Details, MaintenanceReport, Status, Step as MaintenanceStep};
+use crate::package::packer::{self, LintLevel};
+use crate::package::remover::{self, RemovalSummary, human_size};
+use crate::package::toolchain::{self, ToolState, Toolchain, ToolchainReport};
+use crate::package::updater::UpdateSummary;
+use crate::package::verify::Problem;
+use crate::package::{
+    LinkMode, VersionSpec, archive_cache, conflicts, docs, env, installer, pattern,
+};
+use crate::query::{Field, Query};
+use crate::repo::index::SearchHit;
+use crate::repo::not_found::{CacheState, NotFoundReport};
+use crate::repo::{Refresh, share};
 use crate::service::PackageService;
-use crate::{error, info, lprintln};
+use crate::signing;
+use crate::usage::UsageKind;
+use crate::{error, info, lcprintln, lformat, lprintln, warn};
+use batch::Batch;
 use clap::CommandFactory;
 use clap::{Parser, Subcommand};
 use clap_complete::{
     generate,
-    shells::{Bash, Fish, Zsh},
+    shells::{Bash, Fish, PowerShell, Zsh},
 };
+use color::{ColorChoice, Role, paint};
+use inquire::{InquireError, MultiSelect};
+use std::collections::BTreeMap;
 use std::io;
 use std::path::PathBuf;
+use table::{Align, Table};
+
+pub mod batch;
+pub mod color;
+pub mod completions;
+pub mod errors;
+pub mod progress;
+pub mod setup;
+pub mod table;
+
+/// Exit code table shown at the end of `--help`
+const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  success
+  1  generic failure
+  2  package, version or repository not found
+  3  network failure
+  4  conflict with the current state
+  5  verification failed";
 
 #[derive(Parser)]
-#[command(name = "uhpm", version, about = "Universal Home Package Manager")]
+#[command(
+    name = "uhpm",
+    version,
+    about = "Universal Home Package Manager",
+    after_help = EXIT_CODES_HELP
+)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+    /// When to use colors in output
+    #[arg(long, value_enum, global = true, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+    /// Fail on problems that are otherwise only reported, such as removing
+    /// a package that is not installed
+    #[arg(long, global = true)]
+    pub strict: bool,
+    /// Show the underlying causes of errors
+    #[arg(long, global = true)]
+    pub verbose: bool,
+    /// Go on with the remaining packages after one fails
+    #[arg(long, global = true, conflicts_with = "fail_fast")]
+    pub keep_going: bool,
+    /// Stop at the first package that fails (default unless `keep_going`
+    /// is set in the config)
+    #[arg(long, global = true)]
+    pub fail_fast: bool,
+    /// Never write to the uhpm home; commands that change it fail
+    #[arg(long, global = true)]
+    pub read_only: bool,
+    /// Only print what install, remove, autoremove, update, gc or clean
+    /// would change
+    #[arg(short = 'n', long, global = true)]
+    pub dry_run: bool,
+    /// Use an isolated installation in DIR instead of the uhpm home;
+    /// links go below DIR as well (default: `$UHPM_ROOT`)
+    #[arg(long, global = true, value_name = "DIR")]
+    pub root: Option<PathBuf>,
 }
 
 #[derive(Subcommand)]
@@ -21,155 +102,2688 @@ pub enum Commands {
     Install {
         #[arg(short, long)]
         file: Option<PathBuf>,
-        #[arg(value_name = "PACKAGE")]
+        /// Package names, optionally with a version or constraint: `foo@1.2.0`, `foo@^2`
+        #[arg(value_name = "PACKAGE[@VERSION]")]
         package: Vec<String>,
+        /// Version or constraint for packages given without `@`
         #[arg(short, long)]
         version: Option<String>,
         #[arg(short, long)]
         extract: bool,
         #[arg(short, long)]
         direct: bool,
+        /// Place files as symlinks, also for a package installed with
+        /// `--direct`
+        #[arg(long, conflicts_with = "direct")]
+        symlink: bool,
+        /// Take over files that other installed packages link to the same
+        /// destination
+        #[arg(long)]
+        overwrite: bool,
+        /// Build the packages with the repository's `uhpbuild` scripts
+        /// instead of downloading them
+        #[arg(long, conflicts_with_all = ["file", "extract"])]
+        from_source: bool,
+        /// Install from a bundle written by `uhpm bundle`, without
+        /// contacting the repositories
+        #[arg(
+            long,
+            value_name = "FILE",
+            conflicts_with_all = ["file", "package", "extract", "from_source"]
+        )]
+        bundle: Option<PathBuf>,
     },
     Remove {
+        /// Package names, `name@version`, or wildcard patterns such as `'node-*'`
         #[arg(value_name = "PACKAGE")]
         packages: Vec<String>,
         #[arg(short, long)]
         direct: bool,
+        /// Do not ask for confirmation before removing pattern matches
+        #[arg(short, long)]
+        yes: bool,
+        /// Also delete installed copies modified since installation
+        #[arg(long)]
+        force: bool,
+    },
+    /// Remove packages installed as dependencies that nothing needs anymore
+    Autoremove {
+        #[arg(short, long)]
+        direct: bool,
+        /// Do not ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Remove old package versions that have not been used for a while
+    ///
+    /// The current version and the previous one are always kept.
+    Gc {
+        /// Days a version must have been unused (default: `gc_unused_days`
+        /// in config.ron)
+        #[arg(long, value_name = "DAYS")]
+        older_than: Option<u64>,
+        #[arg(short, long)]
+        direct: bool,
+        /// Do not ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    List {
+        /// Only packages whose name matches one of the patterns, e.g. `'lib*'`
+        #[arg(value_name = "PATTERN")]
+        patterns: Vec<String>,
+        /// Group versions under each package
+        #[arg(short, long, conflicts_with_all = ["json", "outdated", "explicit"])]
+        tree: bool,
+        /// Print the packages as JSON
+        #[arg(long)]
+        json: bool,
+        /// Only packages with a newer version in the repositories
+        #[arg(long)]
+        outdated: bool,
+        /// Only packages installed on request, not as dependencies
+        #[arg(long)]
+        explicit: bool,
     },
-    List,
     Update {
         #[arg(short, long)]
         file: Option<PathBuf>,
+        #[arg(value_name = "PACKAGE[@VERSION]")]
+        packages: Vec<String>,
+        #[arg(short, long)]
+        direct: bool,
+        /// Place files as symlinks, also for a package installed with
+        /// `--direct`
+        #[arg(long, conflicts_with = "direct")]
+        symlink: bool,
+        /// Do not ask for confirmation before downgrading
+        #[arg(short, long)]
+        yes: bool,
+        /// Update every installed package
+        #[arg(short, long, conflicts_with = "packages")]
+        all: bool,
+        /// Also update locally installed packages from `--from <DIR>`
+        #[arg(long, requires = "from")]
+        include_local: bool,
+        /// Directory with newer `.uhp` files for locally installed packages
+        #[arg(long, value_name = "DIR", requires = "include_local")]
+        from: Option<PathBuf>,
+    },
+    /// Update all packages, or the given ones, to their newest versions
+    /// after showing what changes
+    Upgrade {
         #[arg(value_name = "PACKAGE")]
         packages: Vec<String>,
         #[arg(short, long)]
         direct: bool,
+        /// Place files as symlinks, also for a package installed with
+        /// `--direct`
+        #[arg(long, conflicts_with = "direct")]
+        symlink: bool,
+        /// Do not ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Hold a package at its current version, or at a version or
+    /// constraint updates must match; without arguments, list the pins
+    Pin {
+        #[arg(value_name = "PACKAGE[@VERSION]")]
+        target: Option<String>,
+    },
+    /// Let a pinned package be updated again
+    Unpin {
+        #[arg(value_name = "PACKAGE")]
+        package: String,
     },
     Switch {
+        /// Installed version or constraint, e.g. `foo@1.4.2` or `foo@~1.4`
         #[arg(value_name = "PACKAGE@VERSION")]
         target: String,
         #[arg(short, long)]
         direct: bool,
+        /// Place files as symlinks, also for a package installed with
+        /// `--direct`
+        #[arg(long, conflicts_with = "direct")]
+        symlink: bool,
+    },
+    /// List the recorded transactions, or the changes of one
+    History {
+        #[arg(value_name = "ID")]
+        id: Option<i64>,
+    },
+    /// Bring the packages of a transaction back to their previous versions
+    Rollback {
+        #[arg(value_name = "ID")]
+        id: i64,
+        #[arg(short, long)]
+        direct: bool,
+        /// Do not ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Undo the last transactions, skipping steps that are no longer
+    /// possible
+    Undo {
+        /// Number of transactions to undo
+        #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        last: u32,
+        #[arg(short, long)]
+        direct: bool,
+        /// Do not ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Run a package's binary without linking it
+    Run {
+        #[arg(value_name = "PACKAGE[@VERSION]")]
+        package: String,
+        /// Arguments passed to the package binary
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Search repositories for packages by name
+    Search {
+        #[arg(value_name = "QUERY")]
+        query: String,
+        /// Pick matches interactively and install them
+        #[arg(short, long)]
+        install: bool,
+        #[arg(short, long, requires = "install")]
+        direct: bool,
+    },
+    /// Extract a .uhp archive without installing it
+    Extract {
+        #[arg(value_name = "PKG.UHP")]
+        archive: PathBuf,
+        /// Target directory, `./<name>-<version>` by default
+        #[arg(value_name = "DEST")]
+        dest: Option<PathBuf>,
+    },
+    /// Write a package and all its dependencies to one file for installing
+    /// offline with `uhpm install --bundle`
+    Bundle {
+        #[arg(value_name = "PACKAGE[@VERSION]")]
+        package: String,
+        /// Bundle file, `./<name>-<version>.bundle.tar` by default
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Show details of a package, installed or available
+    Info {
+        #[arg(value_name = "PACKAGE")]
+        package: String,
+    },
+    /// Show the documentation of an installed package
+    Docs {
+        #[arg(value_name = "PACKAGE")]
+        package: String,
+        /// List the documentation files instead of showing the first one
+        #[arg(short, long)]
+        list: bool,
+    },
+    /// Summarize the licenses of installed packages
+    Licenses {
+        /// Fail if a package requires one of these licenses (SPDX ids)
+        #[arg(long, value_name = "LICENSE", value_delimiter = ',')]
+        deny: Vec<String>,
+    },
+    /// Print the environment of the installed packages
+    ///
+    /// Prints `export` lines for `eval "$(uhpm env)"` by default.
+    Env {
+        /// Print the environment as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show which package provides a command on PATH
+    Which {
+        #[arg(value_name = "COMMAND")]
+        command: String,
+    },
+    /// Show which installed package owns a file or link
+    Owns {
+        #[arg(value_name = "PATH")]
+        path: PathBuf,
     },
+    /// Select fields of the installed packages, e.g.
+    /// 'name,version,size where current=1 and size>10MB'
+    Query {
+        #[arg(value_name = "QUERY")]
+        query: String,
+        /// Print the rows as JSON objects
+        #[arg(long)]
+        json: bool,
+    },
+    /// Check the installation for problems
+    Check {
+        /// Look for dangling symlinks into the package store
+        #[arg(long)]
+        links: bool,
+        /// Delete dangling links
+        #[arg(long, requires = "links", conflicts_with = "relink")]
+        delete: bool,
+        /// Re-point dangling links at the current package versions
+        #[arg(long, requires = "links")]
+        relink: bool,
+    },
+    /// Remove and recreate the links of the given packages, or of all
+    /// installed packages, from their symlists
+    Relink {
+        #[arg(value_name = "PACKAGE")]
+        packages: Vec<String>,
+    },
+    /// Verify the links and files of the installed packages
+    Verify {
+        /// Recreate missing links and re-point misdirected ones
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Run the periodic housekeeping in one go: cache eviction, repository
+    /// refresh, gc, database vacuum, link check and update check
+    Maintain {
+        /// Steps to leave out, e.g. `--skip refresh,updates`
+        #[arg(long, value_name = "STEP", value_delimiter = ',')]
+        skip: Vec<MaintenanceStep>,
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Delete cached data that is no longer needed
+    ///
+    /// At least one cache to clean must be chosen.
+    Clean {
+        /// Delete the cached databases of repositories that are no longer
+        /// configured
+        #[arg(long, required = true)]
+        repo_cache: bool,
+    },
+    /// Show statistics about the installation
+    Stats {
+        /// Show the locally recorded usage counters instead
+        #[arg(long)]
+        usage: bool,
+        /// Delete the recorded usage counters
+        #[arg(long, requires = "usage")]
+        reset: bool,
+    },
+    /// Manage repositories
+    Repo {
+        #[command(subcommand)]
+        command: RepoCommands,
+    },
+    /// Manage named profiles, separate installations of which one at a
+    /// time is linked into the home directory
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommands,
+    },
+    /// Access to the package database for other tools
+    Db {
+        #[command(subcommand)]
+        command: DbCommands,
+    },
+    /// Check the tools a project declares in its `uhpm.toml`
+    Toolchain {
+        #[command(subcommand)]
+        command: ToolchainCommands,
+    },
+    /// Package authoring tools (same as `uhpmk`)
+    Pkg {
+        #[command(subcommand)]
+        command: PkgCommands,
+    },
+    /// Print the completion script for a shell, or install it
+    #[command(
+        alias = "completion",
+        args_conflicts_with_subcommands = true,
+        subcommand_negates_reqs = true
+    )]
     Completions {
-        shell: String,
+        /// bash, zsh, fish or powershell
+        #[arg(required = true)]
+        shell: Option<String>,
+        #[command(subcommand)]
+        command: Option<CompletionCommands>,
+    },
+    /// Choose locations, repositories and shell integration interactively
+    Setup,
+}
+
+impl Commands {
+    /// Name of the subcommand as typed on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            Commands::Install { .. } => "install",
+            Commands::Remove { .. } => "remove",
+            Commands::Autoremove { .. } => "autoremove",
+            Commands::Gc { .. } => "gc",
+            Commands::List { .. } => "list",
+            Commands::Update { .. } => "update",
+            Commands::Upgrade { .. } => "upgrade",
+            Commands::Pin { .. } => "pin",
+            Commands::Unpin { .. } => "unpin",
+            Commands::Switch { .. } => "switch",
+            Commands::History { .. } => "history",
+            Commands::Rollback { .. } => "rollback",
+            Commands::Undo { .. } => "undo",
+            Commands::Run { .. } => "run",
+            Commands::Search { .. } => "search",
+            Commands::Extract { .. } => "extract",
+            Commands::Bundle { .. } => "bundle",
+            Commands::Info { .. } => "info",
+            Commands::Docs { .. } => "docs",
+            Commands::Licenses { .. } => "licenses",
+            Commands::Env { .. } => "env",
+            Commands::Which { .. } => "which",
+            Commands::Owns { .. } => "owns",
+            Commands::Query { .. } => "query",
+            Commands::Check { .. } => "check",
+            Commands::Relink { .. } => "relink",
+            Commands::Verify { .. } => "verify",
+            Commands::Maintain { .. } => "maintain",
+            Commands::Clean { .. } => "clean",
+            Commands::Stats { .. } => "stats",
+            Commands::Repo { .. } => "repo",
+            Commands::Profile { .. } => "profile",
+            Commands::Db { .. } => "db",
+            Commands::Toolchain { .. } => "toolchain",
+            Commands::Pkg { .. } => "pkg",
+            Commands::Completions { .. } => "completions",
+            Commands::Setup => "setup",
+        }
+    }
+
+    /// Whether the first-run setup should be offered before the command
+    pub fn wants_first_run_setup(&self) -> bool {
+        !matches!(
+            self,
+            Commands::Completions { .. }
+                | Commands::Env { .. }
+                | Commands::Db { .. }
+                | Commands::Setup
+        )
+    }
+
+    /// Whether the command writes to the uhpm home or the installed files,
+    /// and so cannot run read-only
+    pub fn needs_write_access(&self) -> bool {
+        self.modifies_installation()
+            || matches!(
+                self,
+                Commands::Setup
+                    | Commands::Repo {
+                        command: RepoCommands::Add { .. }
+                            | RepoCommands::Remove { .. }
+                            | RepoCommands::Priority { .. }
+                            | RepoCommands::Pin { .. }
+                            | RepoCommands::Unpin { .. }
+                            | RepoCommands::Refresh { .. }
+                            | RepoCommands::Import { .. }
+                    }
+                    | Commands::Stats { reset: true, .. }
+            )
+    }
+
+    /// Whether the command can report its changes with `--dry-run`
+    /// instead of making them
+    pub fn supports_dry_run(&self) -> bool {
+        matches!(
+            self,
+            Commands::Install {
+                extract: false,
+                from_source: false,
+                bundle: None,
+                ..
+            } | Commands::Remove { .. }
+                | Commands::Autoremove { .. }
+                | Commands::Gc { .. }
+                | Commands::Update { .. }
+                | Commands::Upgrade { .. }
+                | Commands::Clean { .. }
+        )
+    }
+
+    /// Whether the command changes the installation and must hold the
+    /// process lock
+    pub fn modifies_installation(&self) -> bool {
+        matches!(
+            self,
+            Commands::Install { .. }
+                | Commands::Remove { .. }
+                | Commands::Autoremove { .. }
+                | Commands::Gc { .. }
+                | Commands::Update { .. }
+                | Commands::Upgrade { .. }
+                | Commands::Switch { .. }
+                | Commands::Pin { target: Some(_) }
+                | Commands::Unpin { .. }
+                | Commands::Relink { .. }
+                | Commands::Rollback { .. }
+                | Commands::Undo { .. }
+                | Commands::Maintain { .. }
+                | Commands::Clean { .. }
+                | Commands::Check { delete: true, .. }
+                | Commands::Check { relink: true, .. }
+                | Commands::Verify { repair: true }
+                | Commands::Profile {
+                    command: ProfileCommands::Use { .. }
+                        | ProfileCommands::Create { .. }
+                        | ProfileCommands::Delete { .. }
+                }
+                | Commands::Toolchain {
+                    command: ToolchainCommands::Check { install: true, .. }
+                }
+        )
+    }
+}
+
+#[derive(Subcommand)]
+pub enum CompletionCommands {
+    /// Write the completion script where the shell loads it from
+    Install {
+        /// Shell to install for, detected from `$SHELL` by default
+        #[arg(long, value_enum)]
+        shell: Option<clap_complete::Shell>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RepoCommands {
+    /// Add a repository
+    Add {
+        #[arg(value_name = "NAME")]
+        name: String,
+        /// `https://...`, `file://...` or an absolute path
+        #[arg(value_name = "URL")]
+        url: String,
+        /// Change the URL of an existing repository
+        #[arg(long)]
+        replace: bool,
+    },
+    /// Remove a repository and its cached database
+    Remove {
+        #[arg(value_name = "NAME")]
+        name: String,
+    },
+    /// List the configured repositories
+    List,
+    /// Set the priority of a repository; higher priorities are preferred
+    Priority {
+        #[arg(value_name = "NAME")]
+        name: String,
+        #[arg(value_name = "PRIORITY", allow_hyphen_values = true)]
+        priority: i32,
+    },
+    /// Always take a package from one repository
+    Pin {
+        #[arg(value_name = "PACKAGE")]
+        package: String,
+        #[arg(value_name = "REPO")]
+        repo: String,
+    },
+    /// Remove the pin of a package
+    Unpin {
+        #[arg(value_name = "PACKAGE")]
+        package: String,
+    },
+    /// Download the repository databases that changed on the server
+    Refresh {
+        /// Download them even if the server reports them unchanged
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print the repositories as a TOML snippet to share, without credentials
+    Export,
+    /// Add the repositories of a snippet made by `uhpm repo export`
+    Import {
+        /// Snippet file, `-` for standard input
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+        /// Require keys to have this fingerprint (may be repeated)
+        #[arg(long, value_name = "SHA256")]
+        fingerprint: Vec<String>,
+        /// Replace repositories already configured with another URL or key
+        #[arg(long)]
+        replace: bool,
+        /// Do not ask for confirmation; keys then need `--fingerprint`
+        #[arg(short, long)]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ProfileCommands {
+    /// Create a profile with the configuration and repositories of the
+    /// active one
+    Create {
+        #[arg(value_name = "NAME")]
+        name: String,
+    },
+    /// Make a profile active, replacing the links of the packages of the
+    /// active profile with its own
+    Use {
+        #[arg(value_name = "NAME")]
+        name: String,
+    },
+    /// List the profiles; the active one is marked with `*`
+    List,
+    /// Delete an inactive profile with all its packages
+    Delete {
+        #[arg(value_name = "NAME")]
+        name: String,
+        /// Do not ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DbCommands {
+    /// Print the path of the package database; read it through the
+    /// `uhpm_*_v1` views
+    Path,
+}
+
+#[derive(Subcommand)]
+pub enum ToolchainCommands {
+    /// Compare the required tools with the installed versions
+    Check {
+        /// Toolchain file (default: `uhpm.toml` in the current directory or
+        /// a parent)
+        #[arg(short, long, value_name = "FILE")]
+        file: Option<PathBuf>,
+        /// Install or switch to the required versions
+        #[arg(short, long)]
+        install: bool,
+        #[arg(short, long)]
+        direct: bool,
+        /// Print the result as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PkgCommands {
+    /// Create a package skeleton
+    Init {
+        #[arg(value_name = "DIR", default_value = ".")]
+        dir: PathBuf,
+    },
+    /// Check a package directory for problems
+    Lint {
+        #[arg(value_name = "DIR", default_value = ".")]
+        dir: PathBuf,
+    },
+    /// Pack a package directory into a .uhp archive
+    Pack {
+        #[arg(value_name = "DIR", default_value = ".")]
+        dir: PathBuf,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Compression of the archive: gzip, zstd or none
+        #[arg(long, value_name = "KIND", default_value = "gzip")]
+        compression: Compression,
+        /// Compression level (gzip 0-9, zstd 1-22)
+        #[arg(long, value_name = "N")]
+        level: Option<i32>,
+        /// Leave out paths matching a wildcard pattern (repeatable)
+        #[arg(long, value_name = "PATTERN")]
+        exclude: Vec<String>,
+        /// Normalize timestamps (to `SOURCE_DATE_EPOCH` if set), ownership,
+        /// permissions and order so repacking yields identical bytes
+        #[arg(long, alias = "deterministic")]
+        reproducible: bool,
+        /// Write a detached signature (`.uhp.sig`) next to the archive
+        #[arg(long)]
+        sign: bool,
+        /// Minisign secret key file or GPG key id to sign with
+        #[arg(long, value_name = "KEY", requires = "sign")]
+        key: Option<String>,
     },
 }
 
 impl Cli {
+    /// Runs the command
+    ///
+    /// Problems that are only reported by default make the command fail at
+    /// the end when `--strict` is set; the first one decides the exit code.
     pub async fn run(&self, service: &PackageService) -> Result<(), Box<dyn std::error::Error>> {
+        let mut problems = Vec::new();
+        match service.sync_bin_layout().await {
+            Ok(0) => {}
+            Ok(moved) => {
+                lprintln!("cli.bin_layout.migrated", moved);
+                let layout = BinLayout::configured();
+                if !bin_layout::on_path(&layout) {
+                    lcprintln!(
+                        Role::Warning,
+                        "cli.bin_layout.not_on_path",
+                        layout.dir().display()
+                    );
+                }
+            }
+            Err(e) => {
+                warn!("cli.bin_layout.migration_failed", &e);
+                problems.push(e);
+            }
+        }
+        service
+            .record_usage(UsageKind::Command, self.command.name())
+            .await;
+        let before = if self.command.modifies_installation() && !self.dry_run {
+            service.history_snapshot().await.ok()
+        } else {
+            None
+        };
+        let result = self.execute(service, &mut problems).await;
+        if let Some(before) = before {
+            let command = std::env::args().skip(1).collect::<Vec<_>>().join(" ");
+            service.record_history(&command, &before).await;
+        }
+        result?;
+
+        if self.strict && !problems.is_empty() {
+            lcprintln!(Role::Error, "cli.strict.failed", problems.len());
+            return Err(Box::new(problems.remove(0)));
+        }
+        Ok(())
+    }
+
+    /// Whether batch commands go on after a package fails
+    fn keep_going(&self) -> bool {
+        if self.fail_fast {
+            false
+        } else {
+            self.keep_going || Config::load_or_default().keep_going
+        }
+    }
+
+    async fn execute(
+        &self,
+        service: &PackageService,
+        problems: &mut Vec<UhpmError>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         match &self.command {
             Commands::Install {
                 file,
                 package,
-                version, //TODO: сделать package@0.0.0 а не это говно
+                version,
                 extract,
                 direct,
+                symlink,
+                overwrite,
+                from_source,
+                bundle,
             } => {
-                if let Some(path) = file {
+                if *overwrite {
+                    conflicts::allow_overwrite();
+                }
+                let link = LinkMode::from_flags(*direct, *symlink);
+                if let Some(path) = bundle {
+                    info!("cli.install.from_bundle", path.display());
+                    let (name, version) = service.install_from_bundle(path, link).await?;
+                    lcprintln!(
+                        Role::Success,
+                        "cli.install.bundle_done",
+                        name,
+                        version.to_string()
+                    );
+                } else if let Some(path) = file {
                     info!("cli.install.from_file", path.display());
                     if *extract {
-                        service.extract_package(path).await?;
+                        let dest = service.extract_package(path, None).await?;
+                        lprintln!("cli.extract.done", dest.display());
+                    } else if self.dry_run {
+                        let changes = service.plan_install_from_file(path).await?;
+                        print_changes(&path.display().to_string(), &changes);
                     } else {
-                        service.install_from_file(path, *direct).await?;
+                        let name = installer::read_meta(path)?.name().to_string();
+                        let direct = service.direct_for(&name, link).await?;
+                        service.install_from_file(path, direct).await?;
                     }
                 } else if !package.is_empty() {
-                    for pkg_name in package {
+                    let mut batch = Batch::new(package.len(), self.keep_going());
+                    for arg in package {
+                        if !batch.proceed() {
+                            break;
+                        }
+                        let (pkg_name, spec) = match arg.split_once('@') {
+                            Some((name, spec)) => (name, Some(spec)),
+                            None => (arg.as_str(), version.as_deref()),
+                        };
+                        if *from_source {
+                            let result = async {
+                                let spec = spec
+                                    .map(|spec| spec.parse::<VersionSpec>())
+                                    .transpose()
+                                    .map_err(|e| UhpmError::Parse(e.to_string()))?;
+                                let direct = service.direct_for(pkg_name, link).await?;
+                                service
+                                    .install_from_source(pkg_name, spec.as_ref(), direct)
+                                    .await
+                            }
+                            .await;
+                            if let Some(version) = batch.record(pkg_name, result) {
+                                lcprintln!(
+                                    Role::Success,
+                                    "cli.install.built",
+                                    pkg_name,
+                                    version.to_string()
+                                );
+                            }
+                            continue;
+                        }
                         info!("cli.install.from_repo", pkg_name);
-                        service
-                            .install_from_repo(pkg_name, version.as_deref(), *direct)
-                            .await?;
+                        let result = async {
+                            let resolved = match spec {
+                                Some(spec) => {
+                                    let spec: VersionSpec =
+                                        spec.parse().map_err(|e: semver::Error| {
+                                            UhpmError::Parse(e.to_string())
+                                        })?;
+                                    Some(
+                                        service
+                                            .resolve_available(pkg_name, &spec)
+                                            .await?
+                                            .to_string(),
+                                    )
+                                }
+                                None => None,
+                            };
+                            if self.dry_run {
+                                let changes = service
+                                    .plan_install_from_repo(pkg_name, resolved.as_deref())
+                                    .await?;
+                                print_changes(pkg_name, &changes);
+                                return Ok(());
+                            }
+                            let direct = service.direct_for(pkg_name, link).await?;
+                            service
+                                .install_from_repo(pkg_name, resolved.as_deref(), direct)
+                                .await
+                        }
+                        .await;
+                        batch.record(pkg_name, explain_missing(service, pkg_name, result).await);
                     }
+                    batch.finish()?;
                 } else {
                     error!("cli.install.no_file_or_package");
+                    problems.push(UhpmError::Validation("no package given".to_string()));
                 }
             }
 
-            Commands::Remove { packages, direct } => {
+            Commands::Remove {
+                packages,
+                direct,
+                yes,
+                force,
+            } => {
+                if *force {
+                    remover::allow_removing_modified();
+                }
                 if packages.is_empty() {
                     error!("cli.remove.no_packages");
+                    problems.push(UhpmError::Validation("no package given".to_string()));
                 } else {
-                    for pkg_name in packages {
+                    let mut targets = Vec::new();
+                    for arg in packages {
+                        if arg.contains('@') || !pattern::is_pattern(arg) {
+                            targets.push(arg.clone());
+                            continue;
+                        }
+                        let matches = service.installed_matching(arg).await?;
+                        if matches.is_empty() {
+                            warn!("cli.remove.no_matches", arg);
+                            problems.push(UhpmError::NotFound(arg.clone()));
+                            continue;
+                        }
+                        lprintln!("cli.remove.matches", arg);
+                        for name in &matches {
+                            lprintln!("cli.remove.match", name);
+                        }
+                        let prompt = lformat!("cli.remove.confirm_matches", matches.len());
+                        if !*yes && !confirm(&prompt)? {
+                            lprintln!("cli.remove.cancelled", arg);
+                            continue;
+                        }
+                        targets.extend(matches);
+                    }
+
+                    let mut total = RemovalSummary::default();
+                    let mut batch = Batch::new(targets.len(), self.keep_going());
+                    for pkg_name in &targets {
+                        if !batch.proceed() {
+                            break;
+                        }
                         if pkg_name.contains('@') {
                             let parts: Vec<&str> = pkg_name.split('@').collect();
                             if parts.len() == 2 {
                                 let (pkg_name, pkg_version) = (parts[0], parts[1]);
                                 info!("cli.remove.parts", pkg_name, pkg_version);
-                                service
-                                    .remove_package_version(pkg_name, pkg_version, *direct)
-                                    .await?;
+                                let result = if self.dry_run {
+                                    plan_removal(service, pkg_name, Some(pkg_version)).await
+                                } else {
+                                    service
+                                        .remove_package_version(pkg_name, pkg_version, *direct)
+                                        .await
+                                };
+                                let result = hint_similar(service, pkg_name, result).await;
+                                if let Some(summary) = batch.record(pkg_name, result) {
+                                    total.merge(summary);
+                                }
                             } else {
                                 error!("cli.remove.invalid_format", pkg_name);
+                                problems.push(UhpmError::Validation(pkg_name.to_string()));
                             }
+                        } else if !service.is_installed(pkg_name).await? {
+                            warn!("cli.remove.not_installed", pkg_name);
+                            print_suggestions(service, pkg_name).await;
+                            problems.push(UhpmError::NotFound(pkg_name.to_string()));
                         } else {
                             info!("cli.remove.removing", pkg_name);
-                            service.remove_package(pkg_name, *direct).await?;
+                            let result = if self.dry_run {
+                                plan_removal(service, pkg_name, None).await
+                            } else {
+                                service.remove_package(pkg_name, *direct).await
+                            };
+                            let result = hint_similar(service, pkg_name, result).await;
+                            if let Some(summary) = batch.record(pkg_name, result) {
+                                total.merge(summary);
+                            }
                         }
                     }
+                    if !self.dry_run {
+                        lprintln!(
+                            "cli.remove.summary",
+                            total.files,
+                            total.symlinks,
+                            human_size(total.bytes)
+                        );
+                    }
+                    batch.finish()?;
                 }
             }
 
-            Commands::List => {
-                let packages = service.list_packages().await?;
+            Commands::List {
+                tree: true,
+                patterns,
+                ..
+            } => {
+                let filter = ListFilter {
+                    patterns: patterns.clone(),
+                    ..Default::default()
+                };
+                let mut packages = service.package_tree().await?;
+                packages.retain(|p| filter.matches_name(&p.name));
                 if packages.is_empty() {
                     lprintln!("cli.list.no_packages");
-                } else {
-                    lprintln!("cli.list.installed_packages");
-                    for (name, version, current) in packages {
-                        let marker = if current { '*' } else { ' ' };
-                        lprintln!("cli.list.package_format", name, version, marker);
+                    return Ok(());
+                }
+
+                let mut table = Table::new([
+                    lformat!("cli.table.package"),
+                    String::new(),
+                    lformat!("cli.table.version"),
+                    lformat!("cli.table.size"),
+                    lformat!("cli.table.origin"),
+                ])
+                .align(3, Align::Right)
+                .max_width(4, 48);
+                for package in packages {
+                    for (i, node) in package.versions.iter().enumerate() {
+                        let name = if i == 0 { package.name.as_str() } else { "" };
+                        let size = match node.size {
+                            Some(bytes) => human_size(bytes),
+                            None => lformat!("cli.list.missing"),
+                        };
+                        let origin = match &node.provenance {
+                            Some(p) => p.repo().map(str::to_string).unwrap_or(p.to_string()),
+                            None => "-".to_string(),
+                        };
+                        let (marker, role) = current_marker(node.current);
+                        table.styled_row(
+                            role,
+                            [name.to_string(), marker, node.version.clone(), size, origin],
+                        );
                     }
                 }
+                table.print();
+            }
+            Commands::List {
+                tree: false,
+                patterns,
+                json,
+                outdated,
+                explicit,
+            } => {
+                let filter = ListFilter {
+                    patterns: patterns.clone(),
+                    explicit: *explicit,
+                    outdated: *outdated,
+                    sizes: self.verbose || *json,
+                };
+                let packages = service.list_packages(&filter).await?;
+                if *json {
+                    let out = serde_json::to_string_pretty(&packages)
+                        .map_err(|e| UhpmError::Parse(e.to_string()))?;
+                    println!("{}", out);
+                    return Ok(());
+                }
+                if packages.is_empty() {
+                    lprintln!("cli.list.no_packages");
+                    return Ok(());
+                }
+                print_package_list(&packages, self.verbose, *outdated);
             }
 
             Commands::Update {
                 file,
                 packages,
                 direct,
+                symlink,
+                yes,
+                all,
+                include_local,
+                from,
             } => {
+                let link = LinkMode::from_flags(*direct, *symlink);
                 if let Some(path) = file {
                     info!("cli.update.from_file", path.display());
-                    service.install_from_file(path, *direct).await?;
+                    if self.dry_run {
+                        let changes = service.plan_install_from_file(path).await?;
+                        print_changes(&path.display().to_string(), &changes);
+                    } else {
+                        let name = installer::read_meta(path)?.name().to_string();
+                        let direct = service.direct_for(&name, link).await?;
+                        service.install_from_file(path, direct).await?;
+                    }
+                } else if *all && self.dry_run {
+                    if *include_local {
+                        return Err(UhpmError::Validation(
+                            "--dry-run does not cover --include-local".to_string(),
+                        )
+                        .into());
+                    }
+                    for package in service.list_packages(&ListFilter::default()).await? {
+                        if !package.current {
+                            continue;
+                        }
+                        let name = package.name;
+                        match service.plan_update(&name, None).await {
+                            Ok(changes) => print_changes(&name, &changes),
+                            Err(UhpmError::NoNewVersion(_)) => print_changes(&name, &[]),
+                            Err(e) => {
+                                error!("cli.update.error", &name, &e);
+                                problems.push(e);
+                            }
+                        }
+                    }
+                } else if *all {
+                    let keep_going = self.keep_going();
+                    let mut summary = service.update_all(link, keep_going).await?;
+                    if let (true, Some(dir)) = (*include_local, from)
+                        && (keep_going || summary.failed.is_empty())
+                    {
+                        let local = service.update_local_from_dir(dir, link, keep_going).await?;
+                        summary
+                            .skipped_local
+                            .retain(|name| !local.updated.contains(name));
+                        summary.updated.extend(local.updated);
+                        summary.failed.extend(local.failed);
+                    }
+                    print_update_summary(&summary);
+                    if let Some((name, err)) = summary.failed.into_iter().next() {
+                        return Err(UhpmError::Package(format!("{}: {}", name, err)).into());
+                    }
                 } else {
+                    let mut batch = Batch::new(packages.len(), self.keep_going());
                     for package in packages {
-                        match service.update_package(package, *direct).await {
-                            Ok(()) => info!("cli.update.success", package),
-                            Err(e) => error!("cli.update.error", package, e),
+                        if !batch.proceed() {
+                            break;
+                        }
+                        let result = match package.split_once('@') {
+                            Some((pkg_name, pkg_version)) => {
+                                let version = match semver::Version::parse(pkg_version) {
+                                    Ok(v) => v,
+                                    Err(e) => {
+                                        error!("cli.update.invalid_version", pkg_version, &e);
+                                        problems.push(UhpmError::Parse(e.to_string()));
+                                        continue;
+                                    }
+                                };
+                                if self.dry_run {
+                                    plan_update(service, pkg_name, Some(&version)).await
+                                } else if !*yes
+                                    && !confirm_downgrade(service, pkg_name, &version).await?
+                                {
+                                    lprintln!("cli.update.cancelled", pkg_name);
+                                    continue;
+                                } else {
+                                    async {
+                                        let direct = service.direct_for(pkg_name, link).await?;
+                                        service.update_package_to(pkg_name, &version, direct).await
+                                    }
+                                    .await
+                                }
+                            }
+                            None if self.dry_run => plan_update(service, package, None).await,
+                            None => {
+                                async {
+                                    let direct = service.direct_for(package, link).await?;
+                                    service.update_package(package, direct).await
+                                }
+                                .await
+                            }
+                        };
+                        let pkg_name = package.split('@').next().unwrap_or(package);
+                        match hint_similar(service, pkg_name, result).await {
+                            Ok(()) => {
+                                info!("cli.update.success", package);
+                                batch.record(package, Ok(()));
+                            }
+                            Err(e) => {
+                                error!("cli.update.error", package, &e);
+                                batch.record(package, Err::<(), _>(e));
+                            }
                         }
                     }
+                    batch.finish()?;
                 }
             }
 
-            Commands::Switch { target, direct } => {
+            Commands::Upgrade {
+                packages,
+                direct,
+                symlink,
+                yes,
+            } => {
+                for name in packages {
+                    if service.current_version(name).await?.is_none() {
+                        return Err(UhpmError::NotFound(name.clone()).into());
+                    }
+                }
+                let updates = service.available_updates(packages).await?;
+                let mut skipped_local = service.local_packages().await?;
+                skipped_local.retain(|name| packages.is_empty() || packages.contains(name));
+                let mut held = service.held_packages().await?;
+                held.retain(|(name, _)| packages.is_empty() || packages.contains(name));
+                if updates.is_empty() {
+                    for name in &skipped_local {
+                        lprintln!("cli.update.skipped_local", name);
+                    }
+                    for (name, pin) in &held {
+                        lprintln!("cli.update.held", name, pin);
+                    }
+                    lprintln!("cli.upgrade.up_to_date");
+                    return Ok(());
+                }
+
+                let mut table = Table::new([
+                    lformat!("cli.table.package"),
+                    lformat!("cli.table.installed"),
+                    String::new(),
+                    lformat!("cli.table.latest"),
+                    lformat!("cli.table.repo"),
+                ]);
+                for update in &updates {
+                    table.styled_row(
+                        Role::Update,
+                        [
+                            update.name.as_str(),
+                            update.installed.as_str(),
+                            "→",
+                            update.latest.as_str(),
+                            update.repo.as_str(),
+                        ],
+                    );
+                }
+                table.print();
+                if self.dry_run {
+                    return Ok(());
+                }
+                if !*yes && !confirm(&lformat!("cli.upgrade.confirm", updates.len()))? {
+                    lprintln!("cli.upgrade.cancelled");
+                    return Ok(());
+                }
+
+                let link = LinkMode::from_flags(*direct, *symlink);
+                let mut summary = service.upgrade(updates, link, self.keep_going()).await?;
+                summary.skipped_local = skipped_local;
+                summary.held = held;
+                print_update_summary(&summary);
+                if let Some((name, err)) = summary.failed.into_iter().next() {
+                    return Err(UhpmError::Package(format!("{}: {}", name, err)).into());
+                }
+            }
+
+            Commands::Pin { target: None } => {
+                let pins = service.version_pins().await?;
+                if pins.is_empty() {
+                    lprintln!("cli.pin.none");
+                    return Ok(());
+                }
+                let mut table =
+                    Table::new([lformat!("cli.table.package"), lformat!("cli.table.version")]);
+                for (name, pin) in pins {
+                    table.row([name, pin]);
+                }
+                table.print();
+            }
+
+            Commands::Pin {
+                target: Some(target),
+            } => {
+                let (name, version) = match target.split_once('@') {
+                    Some((name, version)) => (name, Some(version)),
+                    None => (target.as_str(), None),
+                };
+                let pin = service.pin_version(name, version).await?;
+                lcprintln!(Role::Success, "cli.pin.pinned", name, &pin);
+            }
+
+            Commands::Unpin { package } => {
+                if service.unpin_version(package).await? {
+                    lcprintln!(Role::Success, "cli.pin.unpinned", package);
+                } else {
+                    lprintln!("cli.pin.not_pinned", package);
+                }
+            }
+
+            Commands::Switch {
+                target,
+                direct,
+                symlink,
+            } => {
                 let parts: Vec<&str> = target.split('@').collect();
                 if parts.len() != 2 {
                     error!("cli.switch.invalid_format", target);
+                    problems.push(UhpmError::Validation(target.to_string()));
                     return Ok(());
                 }
 
                 let pkg_name = parts[0];
                 let pkg_version = parts[1];
 
-                match semver::Version::parse(pkg_version) {
-                    Ok(version) => {
-                        info!("cli.switch.switching", pkg_name, pkg_version);
-                        service.switch_version(pkg_name, version, *direct).await?;
+                match pkg_version.parse::<VersionSpec>() {
+                    Ok(spec) => {
+                        let result = async {
+                            let version = service.resolve_installed(pkg_name, &spec).await?;
+                            info!("cli.switch.switching", pkg_name, &version.to_string());
+                            let link = LinkMode::from_flags(*direct, *symlink);
+                            let direct = service.direct_for(pkg_name, link).await?;
+                            service.switch_version(pkg_name, version, direct).await
+                        }
+                        .await;
+                        hint_similar(service, pkg_name, result).await?;
                         info!("cli.switch.success", pkg_name, pkg_version);
                     }
                     Err(e) => {
-                        error!("cli.switch.invalid_version", pkg_version, e);
+                        error!("cli.switch.invalid_version", pkg_version, &e);
+                        problems.push(UhpmError::Parse(e.to_string()));
                     }
                 }
             }
 
-            Commands::Completions { shell } => match shell.to_lowercase().as_str() {
-                "bash" => generate(Bash, &mut Cli::command(), "uhpm", &mut io::stdout()),
-                "zsh" => generate(Zsh, &mut Cli::command(), "uhpm", &mut io::stdout()),
-                "fish" => generate(Fish, &mut Cli::command(), "uhpm", &mut io::stdout()),
-                other => println!("Unsupported shell: {}", other),
-            },
-        }
+            Commands::History { id: None } => {
+                let transactions = service.history().await?;
+                if transactions.is_empty() {
+                    lprintln!("cli.history.empty");
+                    return Ok(());
+                }
+                let mut table = Table::new([
+                    lformat!("cli.table.id"),
+                    lformat!("cli.table.date"),
+                    lformat!("cli.table.command"),
+                    lformat!("cli.table.changes"),
+                ])
+                .align(0, Align::Right);
+                for transaction in &transactions {
+                    let changes: Vec<String> = transaction
+                        .changes
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect();
+                    table.row([
+                        transaction.id.to_string(),
+                        history_date(&transaction.timestamp),
+                        transaction.command.clone(),
+                        changes.join(", "),
+                    ]);
+                }
+                table.print();
+            }
 
-        Ok(())
+            Commands::History { id: Some(id) } => {
+                let transaction = service.transaction(*id).await?;
+                lcprintln!(
+                    Role::Header,
+                    "cli.history.transaction",
+                    transaction.id,
+                    history_date(&transaction.timestamp),
+                    &transaction.command
+                );
+                for change in &transaction.changes {
+                    println!("  {}", change);
+                }
+            }
+
+            Commands::Rollback { id, direct, yes } => {
+                let plan = service.rollback_plan(*id).await?;
+                if plan.is_empty() {
+                    lprintln!("cli.rollback.nothing", id);
+                    return Ok(());
+                }
+                lcprintln!(Role::Header, "cli.rollback.header", id);
+                for change in &plan {
+                    println!("  {}", change);
+                }
+                if !*yes && !confirm(&lformat!("cli.rollback.confirm"))? {
+                    lprintln!("cli.rollback.cancelled");
+                    return Ok(());
+                }
+                let mut batch = Batch::new(plan.len(), self.keep_going());
+                for change in &plan {
+                    if !batch.proceed() {
+                        break;
+                    }
+                    let result = service.rollback_change(change, *direct).await;
+                    batch.record(&change.name, result);
+                }
+                batch.finish()?;
+                lprintln!("cli.rollback.success", id);
+            }
+
+            Commands::Undo { last, direct, yes } => {
+                let (transactions, steps) = service.undo_plan(*last as usize).await?;
+                if transactions.is_empty() {
+                    lprintln!("cli.undo.no_history");
+                    return Ok(());
+                }
+                lcprintln!(Role::Header, "cli.undo.header", transactions.len());
+                for transaction in &transactions {
+                    println!(
+                        "  #{} {} {}",
+                        transaction.id,
+                        history_date(&transaction.timestamp),
+                        transaction.command
+                    );
+                }
+                if steps.is_empty() {
+                    lprintln!("cli.undo.nothing");
+                    return Ok(());
+                }
+                lcprintln!(Role::Header, "cli.undo.steps");
+                for step in &steps {
+                    let source = match step.source {
+                        UndoSource::Switch => "cli.undo.source.switch",
+                        UndoSource::Cache => "cli.undo.source.cache",
+                        UndoSource::Repository => "cli.undo.source.repository",
+                        UndoSource::Remove => "cli.undo.source.remove",
+                    };
+                    println!("  {} ({})", step.change, lformat!(source));
+                }
+                if !*yes && !confirm(&lformat!("cli.undo.confirm"))? {
+                    lprintln!("cli.undo.cancelled");
+                    return Ok(());
+                }
+                let mut skipped = 0;
+                for step in &steps {
+                    if let Err(e) = service.rollback_change(&step.change, *direct).await {
+                        skipped += 1;
+                        lcprintln!(
+                            Role::Warning,
+                            "cli.undo.skipped",
+                            step.change.to_string(),
+                            e.to_string()
+                        );
+                    }
+                }
+                lprintln!("cli.undo.summary", steps.len() - skipped, skipped);
+            }
+
+            Commands::Run { package, args } => {
+                let (pkg_name, version) = match package.split_once('@') {
+                    Some((name, ver)) => (name, Some(ver)),
+                    None => (package.as_str(), None),
+                };
+                let result = service.run_package(pkg_name, version, args).await;
+                if let Err(e) = hint_similar(service, pkg_name, result).await {
+                    error!("cli.run.error", pkg_name, &e);
+                    problems.push(e);
+                }
+            }
+
+            Commands::Search {
+                query,
+                install,
+                direct,
+            } => {
+                let hits = service.search(query).await?;
+                if hits.is_empty() {
+                    lprintln!("cli.search.no_results", query);
+                } else if *install {
+                    for hit in select_packages(hits)? {
+                        info!("cli.install.from_repo", &hit.name);
+                        service
+                            .install_from_repo(&hit.name, Some(&hit.version), *direct)
+                            .await?;
+                    }
+                } else {
+                    let mut table = Table::new([
+                        lformat!("cli.table.package"),
+                        lformat!("cli.table.versions"),
+                        lformat!("cli.table.repo"),
+                        lformat!("cli.table.description"),
+                    ]);
+                    for hit in hits {
+                        table.row([
+                            hit.name,
+                            hit.versions.join(", "),
+                            hit.repo,
+                            hit.description.unwrap_or_default(),
+                        ]);
+                    }
+                    table.print();
+                }
+            }
+
+            Commands::Extract { archive, dest } => {
+                let dest = service.extract_package(archive, dest.as_deref()).await?;
+                lprintln!("cli.extract.done", dest.display());
+            }
+
+            Commands::Bundle { package, output } => {
+                let (pkg_name, version) = match package.split_once('@') {
+                    Some((name, spec)) => {
+                        let spec: VersionSpec = spec
+                            .parse()
+                            .map_err(|e: semver::Error| UhpmError::Parse(e.to_string()))?;
+                        let version = service.resolve_available(name, &spec).await?;
+                        (name, Some(version.to_string()))
+                    }
+                    None => (package.as_str(), None),
+                };
+                let result = service
+                    .bundle(pkg_name, version.as_deref(), output.as_deref())
+                    .await;
+                let (path, manifest) = explain_missing(service, pkg_name, result).await?;
+                lcprintln!(
+                    Role::Success,
+                    "cli.bundle.written",
+                    path.display(),
+                    manifest.packages.len()
+                );
+            }
+
+            Commands::Autoremove { direct, yes } => {
+                let orphans = service.orphaned_packages().await?;
+                if orphans.is_empty() {
+                    lprintln!("cli.autoremove.none");
+                } else {
+                    lprintln!("cli.autoremove.found", orphans.len());
+                    for name in &orphans {
+                        lprintln!("cli.remove.match", name);
+                    }
+                    if self.dry_run {
+                        for name in &orphans {
+                            print_changes(name, &service.plan_removal(name, None).await?);
+                        }
+                    } else if *yes || confirm(&lformat!("cli.autoremove.confirm"))? {
+                        let mut total = RemovalSummary::default();
+                        let mut batch = Batch::new(orphans.len(), self.keep_going());
+                        for name in &orphans {
+                            if !batch.proceed() {
+                                break;
+                            }
+                            let result = service.remove_package(name, *direct).await;
+                            if let Some(summary) = batch.record(name, result) {
+                                total.merge(summary);
+                            }
+                        }
+                        lprintln!(
+                            "cli.remove.summary",
+                            total.files,
+                            total.symlinks,
+                            human_size(total.bytes)
+                        );
+                        batch.finish()?;
+                    } else {
+                        lprintln!("cli.autoremove.cancelled");
+                    }
+                }
+            }
+
+            Commands::Gc {
+                older_than,
+                direct,
+                yes,
+            } => {
+                let days = older_than.unwrap_or_else(|| Config::load_or_default().gc_unused_days);
+                let stale = service.stale_versions(days).await?;
+                if stale.is_empty() {
+                    lprintln!("cli.gc.none", days);
+                    return Ok(());
+                }
+                lprintln!("cli.gc.found", stale.len(), days);
+                let mut table = Table::new([
+                    lformat!("cli.table.package"),
+                    lformat!("cli.table.version"),
+                    lformat!("cli.table.last_used"),
+                ]);
+                for v in &stale {
+                    table.row([
+                        v.name.clone(),
+                        v.version.to_string(),
+                        v.last_used
+                            .map(|t| t.format("%Y-%m-%d").to_string())
+                            .unwrap_or_default(),
+                    ]);
+                }
+                table.print();
+                if self.dry_run {
+                    return Ok(());
+                }
+                if *yes || confirm(&lformat!("cli.gc.confirm"))? {
+                    let mut total = RemovalSummary::default();
+                    let mut batch = Batch::new(stale.len(), self.keep_going());
+                    for v in &stale {
+                        if !batch.proceed() {
+                            break;
+                        }
+                        let result = service
+                            .remove_package_version(&v.name, &v.version.to_string(), *direct)
+                            .await;
+                        let label = format!("{}@{}", v.name, v.version);
+                        if let Some(summary) = batch.record(&label, result) {
+                            total.merge(summary);
+                        }
+                    }
+                    lprintln!(
+                        "cli.remove.summary",
+                        total.files,
+                        total.symlinks,
+                        human_size(total.bytes)
+                    );
+                    batch.finish()?;
+                } else {
+                    lprintln!("cli.autoremove.cancelled");
+                }
+            }
+
+            Commands::Info { package } => {
+                let info = service.package_info(package).await;
+                print_package_info(&hint_similar(service, package, info).await?);
+            }
+
+            Commands::Docs { package, list } => {
+                let docs = service.package_docs(package).await;
+                let docs = hint_similar(service, package, docs).await?;
+                match docs.first() {
+                    None => lprintln!("cli.docs.none", package),
+                    Some(_) if *list => {
+                        for doc in &docs {
+                            println!("{}", doc.display());
+                        }
+                    }
+                    Some(primary) => docs::show(primary)?,
+                }
+            }
+
+            Commands::Licenses { deny } => {
+                let entries = service.licenses().await?;
+                if entries.is_empty() {
+                    lprintln!("cli.list.no_packages");
+                    return Ok(());
+                }
+
+                let mut by_license: BTreeMap<String, Vec<&PackageLicense>> = BTreeMap::new();
+                for entry in &entries {
+                    let key = match &entry.license {
+                        Some(l) if !entry.is_unknown() => l.clone(),
+                        _ => lformat!("cli.licenses.unknown"),
+                    };
+                    by_license.entry(key).or_default().push(entry);
+                }
+
+                let mut table = Table::new([
+                    lformat!("cli.table.license"),
+                    lformat!("cli.table.count"),
+                    lformat!("cli.table.packages"),
+                ])
+                .align(1, Align::Right)
+                .max_width(2, 60);
+                let mut denied = Vec::new();
+                for (license, group) in &by_license {
+                    let role = if group[0].is_unknown() {
+                        Role::Warning
+                    } else if licenses::is_denied(license, deny) {
+                        denied.extend(group.iter().map(|e| e.package.as_str()));
+                        Role::Error
+                    } else {
+                        Role::Plain
+                    };
+                    let names: Vec<&str> = group.iter().map(|e| e.package.as_str()).collect();
+                    table.styled_row(
+                        role,
+                        [license.clone(), group.len().to_string(), names.join(", ")],
+                    );
+                }
+                table.print();
+
+                let unknown: Vec<&str> = entries
+                    .iter()
+                    .filter(|e| e.is_unknown())
+                    .map(|e| e.package.as_str())
+                    .collect();
+                if !unknown.is_empty() {
+                    lcprintln!(
+                        Role::Warning,
+                        "cli.licenses.unknown_packages",
+                        unknown.join(", ")
+                    );
+                    problems.push(UhpmError::Validation(format!(
+                        "unknown licenses: {}",
+                        unknown.join(", ")
+                    )));
+                }
+                if !denied.is_empty() {
+                    return Err(Box::new(UhpmError::Conflict(format!(
+                        "denied licenses: {}",
+                        denied.join(", ")
+                    ))));
+                }
+            }
+
+            Commands::Env { json } => {
+                let environment = service.environment().await?;
+                if *json {
+                    let out = serde_json::to_string_pretty(&environment)
+                        .map_err(|e| UhpmError::Parse(e.to_string()))?;
+                    println!("{}", out);
+                } else {
+                    print!("{}", env::to_shell(&environment));
+                }
+            }
+
+            Commands::Which { command } => match service.which(command).await? {
+                None => lprintln!("cli.which.not_found", command),
+                Some(report) => {
+                    lprintln!("cli.which.path", report.path.display());
+                    if report.target != report.path {
+                        lprintln!("cli.which.target", report.target.display());
+                    }
+                    match &report.provider {
+                        Some(p) => {
+                            lprintln!("cli.which.provided_by", &p.package, &p.version);
+                            match &p.current_version {
+                                Some(_) if p.is_current() => {
+                                    lcprintln!(Role::Current, "cli.which.current")
+                                }
+                                Some(current) => {
+                                    lcprintln!(Role::Warning, "cli.which.not_current", current)
+                                }
+                                None => lprintln!("cli.which.not_in_db", &p.package),
+                            }
+                        }
+                        None => lprintln!("cli.which.unmanaged"),
+                    }
+                    for path in &report.shadowed {
+                        lprintln!("cli.which.shadowed", path.display());
+                    }
+                }
+            },
+
+            Commands::Query { query, json } => {
+                let query = Query::parse(query)?;
+                let rows = service.query(&query).await?;
+                if *json {
+                    let out = serde_json::to_string_pretty(&query.to_json(&rows))
+                        .map_err(|e| UhpmError::Parse(e.to_string()))?;
+                    println!("{}", out);
+                } else if rows.is_empty() {
+                    lprintln!("cli.query.none");
+                } else {
+                    let mut table = Table::new(query.fields.iter().map(|f| f.name()));
+                    for (idx, field) in query.fields.iter().enumerate() {
+                        if matches!(field, Field::Files | Field::Size) {
+                            table = table.align(idx, Align::Right);
+                        }
+                    }
+                    for row in &rows {
+                        table.row(query.fields.iter().zip(row).map(|(f, v)| query_cell(*f, v)));
+                    }
+                    table.print();
+                }
+            }
+
+            Commands::Owns { path } => {
+                let report = service.owns(path).await?;
+                lprintln!("cli.which.path", report.path.display());
+                if let Some(target) = &report.target {
+                    if report.dangling {
+                        lcprintln!(Role::Warning, "cli.owns.dangling", target.display());
+                    } else {
+                        lprintln!("cli.which.target", target.display());
+                    }
+                }
+                let Some(p) = &report.provider else {
+                    lprintln!("cli.owns.unowned");
+                    return Ok(());
+                };
+                lprintln!("cli.which.provided_by", &p.package, &p.version);
+                match &p.current_version {
+                    Some(_) if p.is_current() => lcprintln!(Role::Current, "cli.which.current"),
+                    Some(current) => lcprintln!(Role::Warning, "cli.which.not_current", current),
+                    None => lprintln!("cli.which.not_in_db", &p.package),
+                }
+                if !report.recorded {
+                    lprintln!("cli.owns.not_recorded");
+                }
+            }
+
+            Commands::Check {
+                links,
+                delete,
+                relink,
+            } => {
+                if !*links {
+                    lprintln!("cli.check.nothing");
+                    return Ok(());
+                }
+                let action = if *relink {
+                    LinkAction::Relink
+                } else if *delete {
+                    LinkAction::Delete
+                } else {
+                    LinkAction::Report
+                };
+
+                let dangling = service.find_dangling_links().await?;
+                if dangling.is_empty() {
+                    lcprintln!(Role::Success, "cli.check.links_ok");
+                    return Ok(());
+                }
+                for l in &dangling {
+                    lcprintln!(
+                        Role::Warning,
+                        "cli.check.dangling",
+                        l.link.display(),
+                        l.target.display()
+                    );
+                    if let Some(replacement) = &l.replacement {
+                        lprintln!("cli.check.replacement", replacement.display());
+                    }
+                }
+                let fixed = links::fix(&dangling, action)?;
+                lprintln!("cli.check.summary", dangling.len(), fixed);
+            }
+
+            Commands::Relink { packages } => {
+                let names = if packages.is_empty() {
+                    service.installed_matching("*").await?
+                } else {
+                    packages.clone()
+                };
+                let mut batch = Batch::new(names.len(), self.keep_going());
+                for name in &names {
+                    if !batch.proceed() {
+                        break;
+                    }
+                    match batch.record(name, service.relink(name).await) {
+                        Some(Relinked::Links { removed, created }) => {
+                            lprintln!("cli.relink.done", name, removed, created)
+                        }
+                        Some(Relinked::Copies) => lprintln!("cli.relink.copies", name),
+                        None => {}
+                    }
+                }
+                batch.finish()?;
+            }
+
+            Commands::Verify { repair } => {
+                let checks = service.verify().await?;
+                let mut broken = 0;
+                let mut repairable = 0;
+                let mut repaired = 0;
+                for check in checks.iter().filter(|c| !c.problems.is_empty()) {
+                    let role = if check.is_broken() {
+                        broken += 1;
+                        Role::Warning
+                    } else {
+                        Role::Plain
+                    };
+                    lcprintln!(role, "cli.verify.package", &check.name, &check.version);
+                    for problem in &check.problems {
+                        print_problem(problem);
+                        if !problem.is_repairable() {
+                            continue;
+                        }
+                        repairable += 1;
+                        if *repair && service.repair(problem)? {
+                            repaired += 1;
+                            lcprintln!(Role::Success, "cli.verify.repaired");
+                        }
+                    }
+                }
+                if broken == 0 {
+                    lcprintln!(Role::Success, "cli.verify.ok", checks.len());
+                } else {
+                    lprintln!("cli.verify.summary", checks.len(), broken, repaired);
+                    if !*repair && repairable > 0 {
+                        lprintln!("cli.verify.hint", repairable);
+                    }
+                }
+            }
+
+            Commands::Repo { command } => match command {
+                RepoCommands::Add { name, url, replace } => {
+                    service.add_repo(name, url, *replace)?;
+                    lcprintln!(Role::Success, "cli.repo.added", name);
+                }
+                RepoCommands::Remove { name } => {
+                    let url = service.remove_repo(name)?;
+                    lcprintln!(Role::Success, "cli.repo.removed", name, url);
+                }
+                RepoCommands::List => {
+                    let repos = service.list_repos()?;
+                    if repos.is_empty() {
+                        lprintln!("cli.repo.none");
+                    } else {
+                        let mut table = Table::new([
+                            lformat!("cli.table.name"),
+                            lformat!("cli.table.url"),
+                            lformat!("cli.table.priority"),
+                            lformat!("cli.table.pins"),
+                        ])
+                        .align(2, Align::Right);
+                        for (name, entry) in repos {
+                            table.row([
+                                name,
+                                entry.url,
+                                entry.priority.to_string(),
+                                entry.pins.join(", "),
+                            ]);
+                        }
+                        table.print();
+                    }
+                }
+                RepoCommands::Priority { name, priority } => {
+                    service.set_repo_priority(name, *priority)?;
+                    lcprintln!(Role::Success, "cli.repo.priority_set", name, priority);
+                }
+                RepoCommands::Pin { package, repo } => {
+                    service.pin_package(package, Some(repo))?;
+                    lcprintln!(Role::Success, "cli.repo.pinned", package, repo);
+                }
+                RepoCommands::Unpin { package } => {
+                    if service.pin_package(package, None)? {
+                        lcprintln!(Role::Success, "cli.repo.unpinned", package);
+                    } else {
+                        lprintln!("cli.repo.not_pinned", package);
+                    }
+                }
+                RepoCommands::Refresh { force } => {
+                    let results = service.refresh_repos(*force).await?;
+                    if results.is_empty() {
+                        lprintln!("cli.repo.none");
+                    }
+                    let mut batch = Batch::new(results.len(), true);
+                    for (name, result) in results {
+                        match batch.record(&name, result) {
+                            Some(Refresh::Updated) => {
+                                lcprintln!(Role::Success, "cli.repo.refresh_updated", &name)
+                            }
+                            Some(Refresh::Unchanged) => {
+                                lprintln!("cli.repo.refresh_unchanged", &name)
+                            }
+                            None => {}
+                        }
+                    }
+                    batch.finish()?;
+                }
+                RepoCommands::Export => print!("{}", service.export_repos().await?),
+                RepoCommands::Import {
+                    file,
+                    fingerprint,
+                    replace,
+                    yes,
+                } => {
+                    let snippet = if file.as_os_str() == "-" {
+                        io::read_to_string(io::stdin())?
+                    } else {
+                        std::fs::read_to_string(file)?
+                    };
+                    let shared = share::parse(&snippet, fingerprint)?;
+                    if shared.repos.is_empty() {
+                        lprintln!("cli.repo.import_empty");
+                        return Ok(());
+                    }
+                    for (name, repo) in &shared.repos {
+                        lprintln!("cli.repo.import_entry", name, &repo.url);
+                        match &repo.fingerprint {
+                            Some(fp) if repo.key.is_some() => {
+                                lprintln!("cli.repo.import_key", fp)
+                            }
+                            _ => lcprintln!(Role::Warning, "cli.repo.import_unsigned"),
+                        }
+                    }
+                    let unverified = share::unverified_keys(&shared, fingerprint);
+                    let prompt = if unverified.is_empty() {
+                        lformat!("cli.repo.import_confirm")
+                    } else {
+                        let names = unverified.join(", ");
+                        lcprintln!(Role::Warning, "cli.repo.import_unverified", &names);
+                        if *yes {
+                            return Err(SigningError::Unconfirmed(names).into());
+                        }
+                        lformat!("cli.repo.import_trust_confirm")
+                    };
+                    if !*yes && !confirm(&prompt)? {
+                        lprintln!("cli.repo.import_cancelled");
+                        return Ok(());
+                    }
+                    let changed = service.import_repos(&shared, *replace).await?;
+                    lcprintln!(Role::Success, "cli.repo.imported", changed.len());
+                }
+            },
+
+            Commands::Profile { command } => match command {
+                ProfileCommands::Create { name } => {
+                    let home = service.create_profile(name)?;
+                    lcprintln!(Role::Success, "cli.profile.created", name, home.display());
+                }
+                ProfileCommands::Use { name } => match service.use_profile(name).await? {
+                    Some((removed, linked)) => {
+                        lcprintln!(Role::Success, "cli.profile.switched", name, removed, linked)
+                    }
+                    None => lprintln!("cli.profile.already_active", name),
+                },
+                ProfileCommands::List => {
+                    let (profiles, active) = service.list_profiles();
+                    for name in profiles {
+                        if name == active {
+                            println!("{}", paint(Role::Current, &format!("* {}", name)));
+                        } else {
+                            println!("  {}", name);
+                        }
+                    }
+                }
+                ProfileCommands::Delete { name, yes } => {
+                    let home = service.deletable_profile(name)?;
+                    let prompt = lformat!("cli.profile.delete_confirm", name, home.display());
+                    if *yes || confirm(&prompt)? {
+                        service.delete_profile(name)?;
+                        lcprintln!(Role::Success, "cli.profile.deleted", name);
+                    } else {
+                        lprintln!("cli.profile.aborted");
+                    }
+                }
+            },
+            Commands::Db { command } => match command {
+                DbCommands::Path => println!("{}", service.db_path().display()),
+            },
+
+            Commands::Toolchain {
+                command:
+                    ToolchainCommands::Check {
+                        file,
+                        install,
+                        direct,
+                        json,
+                    },
+            } => {
+                let path = match file {
+                    Some(path) => path.clone(),
+                    None => toolchain::find(&std::env::current_dir()?).ok_or_else(|| {
+                        UhpmError::Validation(format!(
+                            "no {} in the current directory or its parents",
+                            toolchain::FILE_NAME
+                        ))
+                    })?,
+                };
+                let chain = Toolchain::load(&path)?;
+                let mut report = service.check_toolchain(&chain).await?;
+                if *install && !report.is_satisfied() {
+                    let pending: Vec<_> = report
+                        .tools
+                        .iter()
+                        .filter(|t| t.state != ToolState::Ok)
+                        .collect();
+                    let mut batch = Batch::new(pending.len(), self.keep_going());
+                    for tool in pending {
+                        if !batch.proceed() {
+                            break;
+                        }
+                        match (&tool.state, &tool.matching) {
+                            (ToolState::Inactive, Some(v)) => {
+                                info!("cli.toolchain.switching", &tool.name, v)
+                            }
+                            _ => info!("cli.toolchain.installing", &tool.name, &tool.required),
+                        }
+                        let result = service
+                            .satisfy_tool(tool, &chain.tools[&tool.name], *direct)
+                            .await;
+                        batch.record(&tool.name, hint_similar(service, &tool.name, result).await);
+                    }
+                    batch.finish()?;
+                    report = service.check_toolchain(&chain).await?;
+                }
+
+                if *json {
+                    let out = serde_json::to_string_pretty(&report)
+                        .map_err(|e| UhpmError::Parse(e.to_string()))?;
+                    println!("{}", out);
+                } else {
+                    print_toolchain(&report);
+                }
+                if !report.is_satisfied() {
+                    let unmet = report.tools.iter().filter(|t| t.state != ToolState::Ok);
+                    return Err(Box::new(UhpmError::Conflict(format!(
+                        "toolchain {} is not satisfied: {}",
+                        report.file.display(),
+                        unmet
+                            .map(|t| t.name.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ))));
+                }
+            }
+
+            Commands::Pkg { command } => match command {
+                PkgCommands::Init { dir } => {
+                    let meta = packer::init(dir)?;
+                    lprintln!("cli.pkg.initialized", meta.display());
+                }
+                PkgCommands::Lint { dir } => {
+                    let issues = packer::lint(dir);
+                    for issue in &issues {
+                        match issue.level {
+                            LintLevel::Error => {
+                                lcprintln!(Role::Error, "cli.pkg.lint_error", &issue.message)
+                            }
+                            LintLevel::Warning => {
+                                lcprintln!(Role::Warning, "cli.pkg.lint_warning", &issue.message)
+                            }
+                        }
+                    }
+                    if issues.is_empty() {
+                        lprintln!("cli.pkg.lint_ok");
+                    }
+                }
+                PkgCommands::Pack {
+                    dir,
+                    output,
+                    compression,
+                    level,
+                    exclude,
+                    reproducible,
+                    sign,
+                    key,
+                } => {
+                    let mut opts = ArchiveOptions::new()
+                        .compression(*compression)
+                        .deterministic(*reproducible);
+                    if let Some(epoch) = std::env::var("SOURCE_DATE_EPOCH")
+                        .ok()
+                        .and_then(|v| v.trim().parse().ok())
+                    {
+                        opts = opts.mtime(epoch);
+                    }
+                    if let Some(level) = level {
+                        opts = opts.level(*level);
+                    }
+                    for pattern in exclude {
+                        opts = opts.exclude(pattern);
+                    }
+                    let archive = packer::pack(dir, output.as_deref(), &opts)?;
+                    lprintln!("cli.pkg.packed", archive.display());
+                    if *reproducible {
+                        lprintln!("cli.pkg.sha256", archive_cache::sha256_file(&archive)?);
+                    }
+                    if *sign {
+                        let sig =
+                            signing::sign(&archive, key.as_deref()).map_err(UhpmError::from)?;
+                        lprintln!("cli.pkg.signed", sig.display());
+                    }
+                }
+            },
+
+            Commands::Maintain { skip, json } => {
+                let report = service.maintain(skip).await;
+                if *json {
+                    let out = serde_json::to_string_pretty(&report)
+                        .map_err(|e| UhpmError::Parse(e.to_string()))?;
+                    println!("{}", out);
+                } else {
+                    print_maintenance(&report);
+                }
+                let failed: Vec<&str> = report.failed().map(|s| s.step.as_str()).collect();
+                if !failed.is_empty() {
+                    return Err(UhpmError::Package(format!(
+                        "maintenance steps failed: {}",
+                        failed.join(", ")
+                    ))
+                    .into());
+                }
+            }
+
+            Commands::Clean { repo_cache } => {
+                if *repo_cache {
+                    clean_repo_cache(service, self.dry_run).await?;
+                }
+            }
+
+            Commands::Stats { usage: true, reset } => {
+                if *reset {
+                    service.clear_usage().await?;
+                    lprintln!("cli.stats.usage_reset");
+                    return Ok(());
+                }
+                let report = service.usage_report().await?;
+                if report.is_empty() {
+                    lprintln!("cli.stats.no_usage");
+                } else {
+                    for (title, entries) in [
+                        (lformat!("cli.stats.commands"), &report.commands),
+                        (lformat!("cli.stats.installs"), &report.installs),
+                        (lformat!("cli.stats.updates"), &report.updates),
+                    ] {
+                        if entries.is_empty() {
+                            continue;
+                        }
+                        lcprintln!(Role::Header, "cli.stats.section", title);
+                        let mut table = Table::new([
+                            lformat!("cli.table.name"),
+                            lformat!("cli.table.count"),
+                            lformat!("cli.table.last_used"),
+                        ])
+                        .align(1, Align::Right);
+                        for entry in entries {
+                            table.row([
+                                entry.name.clone(),
+                                entry.count.to_string(),
+                                entry.last_used.clone(),
+                            ]);
+                        }
+                        table.print();
+                    }
+                }
+                if !Config::load_or_default().record_usage {
+                    lprintln!("cli.stats.recording_disabled");
+                }
+                lprintln!("cli.stats.local_only");
+            }
+
+            Commands::Stats { usage: false, .. } => {
+                let stats = service.install_stats().await?;
+                lprintln!("cli.stats.packages", stats.packages, stats.versions);
+                lprintln!("cli.stats.files", stats.files);
+            }
+
+            Commands::Completions {
+                command: Some(CompletionCommands::Install { shell }),
+                ..
+            } => {
+                let (shell, path) = completions::install(*shell)?;
+                lprintln!(
+                    "cli.completions.installed",
+                    shell.to_string(),
+                    path.display()
+                );
+                match shell {
+                    clap_complete::Shell::Bash => lprintln!("cli.completions.bash_note"),
+                    clap_complete::Shell::Zsh => {
+                        lprintln!("cli.completions.zsh_note", path.parent().unwrap().display())
+                    }
+                    _ => lprintln!("cli.completions.restart_note"),
+                }
+            }
+
+            Commands::Completions { shell, .. } => {
+                match shell.as_deref().unwrap_or_default().to_lowercase().as_str() {
+                    "bash" => generate(Bash, &mut Cli::command(), "uhpm", &mut io::stdout()),
+                    "zsh" => generate(Zsh, &mut Cli::command(), "uhpm", &mut io::stdout()),
+                    "fish" => generate(Fish, &mut Cli::command(), "uhpm", &mut io::stdout()),
+                    "powershell" | "pwsh" => {
+                        generate(PowerShell, &mut Cli::command(), "uhpm", &mut io::stdout())
+                    }
+                    other => println!("Unsupported shell: {}", other),
+                }
+            }
+
+            Commands::Setup => setup::run()?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Passes `result` through, printing close package names first if it is a
+/// not-found error for `name`.
+async fn hint_similar<T>(
+    service: &PackageService,
+    name: &str,
+    result: Result<T, UhpmError>,
+) -> Result<T, UhpmError> {
+    if let Err(UhpmError::NotFound(_)) = &result {
+        print_suggestions(service, name).await;
+    }
+    result
+}
+
+/// Explains a package the repositories do not carry: where it was looked
+/// for, in which order, how current those indexes were, and similar names.
+async fn explain_missing<T>(
+    service: &PackageService,
+    name: &str,
+    result: Result<T, UhpmError>,
+) -> Result<T, UhpmError> {
+    if let Err(UhpmError::NotFound(_)) = &result {
+        match service.not_found_report(name).await {
+            Ok(report) => print_not_found(&report),
+            Err(_) => print_suggestions(service, name).await,
+        }
+    }
+    result
+}
+
+fn print_not_found(report: &NotFoundReport) {
+    if report.searched.is_empty() {
+        lcprintln!(Role::Warning, "cli.not_found.no_repos");
+    } else {
+        lprintln!("cli.not_found.searched", &report.package);
+        let mut table = Table::new([
+            lformat!("cli.table.repo"),
+            lformat!("cli.table.priority"),
+            lformat!("cli.table.index"),
+        ])
+        .align(1, Align::Right);
+        for repo in &report.searched {
+            let (state, role) = match &repo.cache {
+                CacheState::Refreshed => (lformat!("cli.not_found.refreshed"), Role::Plain),
+                CacheState::Stale { modified } => {
+                    let mut state = lformat!("cli.not_found.stale");
+                    if let Some(modified) = modified {
+                        state.push_str(&modified.format(" (%Y-%m-%d %H:%M)").to_string());
+                    }
+                    (state, Role::Warning)
+                }
+                CacheState::Rejected => (lformat!("cli.not_found.rejected"), Role::Error),
+                CacheState::Missing => (lformat!("cli.not_found.missing"), Role::Warning),
+                CacheState::Excluded { pinned_to } => {
+                    (lformat!("cli.not_found.excluded", pinned_to), Role::Plain)
+                }
+            };
+            table.styled_row(role, [repo.name.clone(), repo.priority.to_string(), state]);
+        }
+        table.print();
+        if report.has_outdated_index() {
+            lprintln!("cli.not_found.refresh_hint");
+        }
+    }
+    if !report.suggestions.is_empty() {
+        lprintln!("cli.suggest.did_you_mean", report.suggestions.join(", "));
+    }
+}
+
+/// Prints package names close to `name`, if there are any.
+async fn print_suggestions(service: &PackageService, name: &str) {
+    let suggestions = service.suggest_packages(name).await;
+    if !suggestions.is_empty() {
+        lprintln!("cli.suggest.did_you_mean", suggestions.join(", "));
+    }
+}
+
+/// Returns the process exit code for an error returned by [`Cli::run`].
+pub fn exit_code(err: &(dyn std::error::Error + 'static)) -> u8 {
+    err.downcast_ref::<UhpmError>()
+        .map_or(crate::error::exit_code::GENERIC, UhpmError::exit_code)
+}
+
+/// Deletes, or with `dry_run` lists, the stale repository caches
+async fn clean_repo_cache(service: &PackageService, dry_run: bool) -> Result<(), UhpmError> {
+    let stale = if dry_run {
+        service.stale_repo_cache().await?
+    } else {
+        service.clean_repo_cache().await?
+    };
+    if stale.is_empty() {
+        lprintln!("cli.clean.repo_cache_none");
+        return Ok(());
+    }
+    let mut table = Table::new([
+        lformat!("cli.table.repo"),
+        lformat!("cli.table.url"),
+        lformat!("cli.table.size"),
+    ])
+    .align(2, Align::Right);
+    for cache in &stale {
+        table.row([
+            cache.name.clone(),
+            cache.source.clone().unwrap_or_default(),
+            human_size(cache.size),
+        ]);
+    }
+    table.print();
+    let size = human_size(stale.iter().map(|c| c.size).sum());
+    if dry_run {
+        lprintln!("cli.clean.repo_cache_would_remove", stale.len(), size);
+    } else {
+        lprintln!("cli.clean.repo_cache_removed", stale.len(), size);
+    }
+    Ok(())
+}
+
+/// Local date and time of a history timestamp
+fn history_date(timestamp: &str) -> String {
+    chrono::DateTime::parse_from_rfc3339(timestamp)
+        .map(|t| {
+            t.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_else(|_| timestamp.to_string())
+}
+
+/// Prints the changes a `--dry-run` of an operation on `subject` found
+fn print_changes(subject: &str, changes: &[Change]) {
+    if changes.is_empty() {
+        lprintln!("cli.dry_run.nothing", subject);
+        return;
+    }
+    lcprintln!(Role::Header, "cli.dry_run.header", subject);
+    for change in changes {
+        match change {
+            Change::Download { url } => lprintln!("cli.dry_run.download", url),
+            Change::Install {
+                name,
+                version,
+                root,
+            } => lprintln!("cli.dry_run.install", name, version, root.display()),
+            Change::Link { source, target } => {
+                lprintln!("cli.dry_run.link", target.display(), source.display())
+            }
+            Change::Unlink { target } => lprintln!("cli.dry_run.unlink", target.display()),
+            Change::Remove {
+                name,
+                version,
+                root,
+            } => lprintln!("cli.dry_run.remove", name, version, root.display()),
+            Change::SetCurrent { name, version } => {
+                lprintln!("cli.dry_run.set_current", name, version)
+            }
+        }
+    }
+}
+
+/// Prints the changes removing a package would make, for `remove --dry-run`
+async fn plan_removal(
+    service: &PackageService,
+    name: &str,
+    version: Option<&str>,
+) -> Result<RemovalSummary, UhpmError> {
+    let changes = service.plan_removal(name, version).await?;
+    print_changes(name, &changes);
+    Ok(RemovalSummary::default())
+}
+
+/// Prints the changes updating a package would make, for `update --dry-run`
+async fn plan_update(
+    service: &PackageService,
+    name: &str,
+    version: Option<&semver::Version>,
+) -> Result<(), UhpmError> {
+    let changes = service.plan_update(name, version).await?;
+    print_changes(name, &changes);
+    Ok(())
+}
+
+/// Returns the current-version marker and row color for a listed version.
+/// Prints the table of `uhpm list`; `verbose` adds when, how big and where
+/// from, `outdated` the newer version
+fn print_package_list(packages: &[InstalledPackage], verbose: bool, outdated: bool) {
+    let mut headers = vec![
+        lformat!("cli.table.package"),
+        lformat!("cli.table.version"),
+        String::new(),
+    ];
+    if outdated {
+        headers.push(lformat!("cli.table.latest"));
+    }
+    if verbose {
+        headers.extend([
+            lformat!("cli.table.installed"),
+            lformat!("cli.table.size"),
+            lformat!("cli.table.repo"),
+            lformat!("cli.table.reason"),
+        ]);
+    }
+    let size_column = headers.len().saturating_sub(3);
+    let mut table = Table::new(headers);
+    if verbose {
+        table = table.align(size_column, Align::Right);
+    }
+    for package in packages {
+        let (marker, role) = current_marker(package.current);
+        let mut row = vec![package.name.clone(), package.version.clone(), marker];
+        if outdated {
+            row.push(package.latest.clone().unwrap_or_default());
+        }
+        if verbose {
+            row.extend([
+                package
+                    .installed_at
+                    .as_deref()
+                    .map_or("-".to_string(), history_date),
+                package
+                    .size
+                    .map_or_else(|| lformat!("cli.list.missing"), human_size),
+                package.repo.clone().unwrap_or("-".to_string()),
+                package.reason.clone(),
+            ]);
+        }
+        table.styled_row(role, row);
+    }
+    table.print();
+}
+
+fn current_marker(current: bool) -> (String, Role) {
+    if current {
+        ("*".to_string(), Role::Current)
+    } else {
+        (String::new(), Role::Plain)
+    }
+}
+
+/// Formats a value selected by `uhpm query` for the table.
+fn query_cell(field: Field, value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => "-".to_string(),
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Number(n) if field == Field::Size => human_size(n.as_u64().unwrap_or(0)),
+        other => other.to_string(),
+    }
+}
+
+/// Prints a maintenance report, a line per step
+fn print_maintenance(report: &MaintenanceReport) {
+    for step in &report.steps {
+        let name = step.step.as_str();
+        match (step.status, &step.details) {
+            (Status::Skipped, _) => lprintln!("cli.maintain.skipped", name),
+            (_, None) => lcprintln!(
+                Role::Error,
+                "cli.maintain.failed",
+                name,
+                step.error.clone().unwrap_or_default()
+            ),
+            (status, Some(details)) => {
+                let role = if status == Status::Failed {
+                    Role::Warning
+                } else {
+                    Role::Success
+                };
+                match details {
+                    Details::Cache {
+                        repo_caches_removed,
+                        bytes_freed,
+                        run_cache_purged,
+                    } => lcprintln!(
+                        role,
+                        "cli.maintain.cache",
+                        repo_caches_removed.len(),
+                        human_size(*bytes_freed),
+                        run_cache_purged
+                    ),
+                    Details::Refresh {
+                        updated,
+                        unchanged,
+                        failed,
+                    } => lcprintln!(
+                        role,
+                        "cli.maintain.refresh",
+                        updated.len(),
+                        unchanged.len(),
+                        failed.len()
+                    ),
+                    Details::Gc {
+                        removed,
+                        bytes_freed,
+                        failed,
+                    } => lcprintln!(
+                        role,
+                        "cli.maintain.gc",
+                        removed.len(),
+                        human_size(*bytes_freed),
+                        failed.len()
+                    ),
+                    Details::Vacuum {
+                        size_before,
+                        size_after,
+                    } => lcprintln!(
+                        role,
+                        "cli.maintain.vacuum",
+                        human_size(*size_before),
+                        human_size(*size_after)
+                    ),
+                    Details::Links { dangling } => {
+                        lcprintln!(role, "cli.maintain.links", dangling.len());
+                        for link in dangling {
+                            lprintln!("cli.maintain.item", link.display());
+                        }
+                    }
+                    Details::Updates { available } => {
+                        lcprintln!(role, "cli.maintain.updates", available.len());
+                        for update in available {
+                            lprintln!(
+                                "cli.maintain.update",
+                                &update.name,
+                                &update.installed,
+                                &update.latest
+                            );
+                        }
+                    }
+                }
+                if let Details::Refresh { failed, .. } | Details::Gc { failed, .. } = details {
+                    for failure in failed {
+                        lprintln!("cli.maintain.failure", &failure.name, &failure.error);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Prints a problem found by `uhpm verify`.
+fn print_problem(problem: &Problem) {
+    match problem {
+        Problem::MissingDir { root } => lprintln!("cli.verify.missing_dir", root.display()),
+        Problem::MissingFile { path, .. } => lprintln!("cli.verify.missing_file", path.display()),
+        Problem::WrongTarget {
+            path,
+            expected: Some(expected),
+            actual,
+        } => lprintln!(
+            "cli.verify.wrong_target",
+            path.display(),
+            actual.display(),
+            expected.display()
+        ),
+        Problem::WrongTarget {
+            path,
+            expected: None,
+            actual,
+        } => lprintln!("cli.verify.dangling", path.display(), actual.display()),
+        Problem::Modified { file } => lprintln!("cli.verify.modified", file.display()),
+        Problem::Deleted { file } => lprintln!("cli.verify.deleted", file.display()),
+        Problem::NoManifest => lprintln!("cli.verify.no_manifest"),
+    }
+}
+
+/// Prints a `label: value` line of `uhpm info`.
+fn print_field(label_key: &str, value: impl std::fmt::Display) {
+    let label = format!("{}:", lformat!(label_key));
+    println!("{:<14} {}", label, value);
+}
+
+/// Prints the details of a package for `uhpm info`.
+fn print_toolchain(report: &ToolchainReport) {
+    lprintln!("cli.toolchain.file", report.file.display());
+    if report.tools.is_empty() {
+        lprintln!("cli.toolchain.empty", report.file.display());
+        return;
+    }
+    let mut table = Table::new([
+        lformat!("cli.table.tool"),
+        lformat!("cli.table.required"),
+        lformat!("cli.table.installed"),
+        lformat!("cli.table.status"),
+    ]);
+    for tool in &report.tools {
+        let state = match tool.state {
+            ToolState::Ok => "cli.toolchain.state.ok",
+            ToolState::Inactive => "cli.toolchain.state.inactive",
+            ToolState::Mismatch => "cli.toolchain.state.mismatch",
+            ToolState::Missing => "cli.toolchain.state.missing",
+        };
+        table.row([
+            tool.name.clone(),
+            tool.required.clone(),
+            tool.current
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_else(|| "-".to_string()),
+            lformat!(state),
+        ]);
+    }
+    table.print();
+    if report.is_satisfied() {
+        lcprintln!(Role::Success, "cli.toolchain.satisfied");
+    } else {
+        lprintln!("cli.toolchain.hint");
+    }
+}
+
+fn print_package_info(info: &PackageInfo) {
+    match info {
+        PackageInfo::Installed(installed) => {
+            let pkg = &installed.package;
+            print_field("cli.info.name", pkg.name());
+            print_field("cli.info.version", pkg.version());
+            if installed.versions.len() > 1 {
+                print_field("cli.info.installed_versions", installed.versions.join(", "));
+            }
+            print_field("cli.info.author", pkg.author());
+            print_field("cli.info.source", pkg.src().as_str());
+            if let Some(provenance) = &installed.provenance {
+                print_field("cli.info.installed_from", provenance);
+            }
+            print_field("cli.info.checksum", pkg.checksum());
+            let dependencies: Vec<String> = pkg
+                .dependencies()
+                .iter()
+                .map(|(name, version)| format!("{}@{}", name, version))
+                .collect();
+            let dependencies = if dependencies.is_empty() {
+                lformat!("cli.info.none")
+            } else {
+                dependencies.join(", ")
+            };
+            print_field("cli.info.dependencies", dependencies);
+            print_field("cli.info.files", installed.files);
+            let installed_at = installed
+                .installed_at
+                .clone()
+                .unwrap_or_else(|| lformat!("cli.info.unknown"));
+            print_field("cli.info.installed_at", installed_at);
+        }
+        PackageInfo::Available(hit) => {
+            print_field("cli.info.name", &hit.name);
+            print_field("cli.info.available_versions", hit.versions.join(", "));
+            print_field("cli.info.repo", &hit.repo);
+            if let Some(description) = &hit.description {
+                print_field("cli.info.description", description);
+            }
+            lcprintln!(
+                Role::Warning,
+                "cli.info.not_installed",
+                &hit.name,
+                &hit.name
+            );
+        }
+    }
+}
+
+/// Prints the consolidated result of a bulk update.
+fn print_update_summary(summary: &UpdateSummary) {
+    for name in &summary.updated {
+        lcprintln!(Role::Update, "cli.update.updated", name);
+    }
+    for (name, err) in &summary.failed {
+        lcprintln!(Role::Error, "cli.update.failed", name, err);
+    }
+    for name in &summary.skipped_local {
+        lprintln!("cli.update.skipped_local", name);
+    }
+    for (name, pin) in &summary.held {
+        lprintln!("cli.update.held", name, pin);
+    }
+    lprintln!(
+        "cli.update.summary",
+        summary.updated.len(),
+        summary.failed.len(),
+        summary.skipped_local.len()
+    );
+}
+
+/// Lets the user pick packages from search results with a filterable list.
+///
+/// Cancelling the prompt selects nothing.
+fn select_packages(hits: Vec<SearchHit>) -> Result<Vec<SearchHit>, InquireError> {
+    match MultiSelect::new(&lformat!("cli.search.select"), hits).prompt() {
+        Ok(selected) => Ok(selected),
+        Err(InquireError::OperationCanceled | InquireError::OperationInterrupted) => {
+            lprintln!("cli.search.cancelled");
+            Ok(Vec::new())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Asks the user to confirm a yes/no question; anything but `y`/`yes` is a no.
+fn confirm(prompt: &str) -> io::Result<bool> {
+    use std::io::Write;
+
+    print!("{} [y/N] ", prompt);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Asks for confirmation if moving to `target` would be a downgrade.
+async fn confirm_downgrade(
+    service: &PackageService,
+    pkg_name: &str,
+    target: &semver::Version,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let current = service
+        .current_version(pkg_name)
+        .await?
+        .and_then(|v| semver::Version::parse(&v).ok());
+
+    match current {
+        Some(current) if target < &current => {
+            let prompt = lformat!(
+                "cli.update.downgrade_confirm",
+                pkg_name,
+                &current.to_string(),
+                &target.to_string()
+            );
+            Ok(confirm(&prompt)?)
+        }
+        _ => Ok(true),
     }
 }
@@ -0,0 +1,120 @@
+//! # Batch Outcomes
+//!
+//! Commands taking several packages (`install`, `remove`, `update`) either
+//! stop at the first package that fails or go on with the rest:
+//!
+//! - `--fail-fast` stops (the default);
+//! - `--keep-going` goes on;
+//! - `keep_going: true` in `config.ron` makes going on the default.
+//!
+//! Either way the command ends with a summary of what succeeded, failed or
+//! was skipped, and fails if any package did.
+
+use super::color::Role;
+use crate::error::UhpmError;
+use crate::lcprintln;
+
+/// Results of a command run on several packages
+#[derive(Debug)]
+pub struct Batch {
+    keep_going: bool,
+    total: usize,
+    succeeded: Vec<String>,
+    failed: Vec<(String, UhpmError)>,
+}
+
+impl Batch {
+    /// Starts a batch of `total` packages
+    pub fn new(total: usize, keep_going: bool) -> Self {
+        Self {
+            keep_going,
+            total,
+            succeeded: Vec::new(),
+            failed: Vec::new(),
+        }
+    }
+
+    /// Records the result for `name`, passing on the value if it succeeded
+    pub fn record<T>(&mut self, name: &str, result: Result<T, UhpmError>) -> Option<T> {
+        match result {
+            Ok(value) => {
+                self.succeeded.push(name.to_string());
+                Some(value)
+            }
+            Err(e) => {
+                self.failed.push((name.to_string(), e));
+                None
+            }
+        }
+    }
+
+    /// Whether the next package should be processed
+    pub fn proceed(&self) -> bool {
+        self.keep_going || self.failed.is_empty()
+    }
+
+    /// Packages that were skipped or not reached
+    pub fn skipped(&self) -> usize {
+        self.total - self.succeeded.len() - self.failed.len()
+    }
+
+    /// Prints the summary, if there was more than one package, and fails
+    /// with the first error
+    pub fn finish(mut self) -> Result<(), UhpmError> {
+        if self.total > 1 {
+            for (name, err) in &self.failed {
+                lcprintln!(Role::Error, "cli.batch.failed", name, err.to_string());
+            }
+            let role = if self.failed.is_empty() {
+                Role::Success
+            } else {
+                Role::Error
+            };
+            lcprintln!(
+                role,
+                "cli.batch.summary",
+                self.succeeded.len(),
+                self.failed.len(),
+                self.skipped()
+            );
+        }
+        if self.failed.is_empty() {
+            Ok(())
+        } else {
+            Err(self.failed.remove(0).1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(keep_going: bool) -> Batch {
+        let mut batch = Batch::new(3, keep_going);
+        for (name, ok) in [("a", true), ("b", false), ("c", true)] {
+            if !batch.proceed() {
+                break;
+            }
+            let result = if ok {
+                Ok(())
+            } else {
+                Err(UhpmError::NotFound(name.to_string()))
+            };
+            batch.record(name, result);
+        }
+        batch
+    }
+
+    #[test]
+    fn test_fail_fast_and_keep_going() {
+        let fast = run(false);
+        assert_eq!(fast.succeeded, ["a"]);
+        assert_eq!(fast.skipped(), 1);
+
+        let going = run(true);
+        assert_eq!(going.succeeded, ["a", "c"]);
+        assert_eq!(going.skipped(), 0);
+        assert!(matches!(going.finish(), Err(UhpmError::NotFound(name)) if name == "b"));
+    }
+}
@@ -0,0 +1,172 @@
+//! # Terminal Colors
+//!
+//! Colorizes CLI output by semantic role (current version, errors,
+//! available updates, …). Colors are enabled according to `--color`:
+//!
+//! - `auto` (default) — only when stdout is a terminal and `NO_COLOR` is
+//!   unset or empty
+//! - `always` / `never` — force colors on or off
+//!
+//! The color of each role comes from the `theme` section of the config:
+//!
+//! ```ron
+//! theme: (current: green, error: red, update: yellow),
+//! ```
+
+use clap::ValueEnum;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::io::IsTerminal;
+
+/// When to colorize output
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Resolves the choice against the environment
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                    && std::io::stdout().is_terminal()
+            }
+        }
+    }
+}
+
+/// A terminal color usable in themes
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Color {
+    Plain,
+    Bold,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+}
+
+impl Color {
+    fn ansi(self) -> Option<&'static str> {
+        match self {
+            Color::Plain => None,
+            Color::Bold => Some("1"),
+            Color::Red => Some("31"),
+            Color::Green => Some("32"),
+            Color::Yellow => Some("33"),
+            Color::Blue => Some("34"),
+            Color::Magenta => Some("35"),
+            Color::Cyan => Some("36"),
+        }
+    }
+}
+
+/// Semantic role of a piece of output
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    /// Uncolored output
+    Plain,
+    /// The current version of a package
+    Current,
+    /// Failures
+    Error,
+    /// Non-fatal problems
+    Warning,
+    /// Available or applied updates
+    Update,
+    /// Completed operations
+    Success,
+    /// Section headers and package names
+    Header,
+}
+
+/// Colors assigned to each output role
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Theme {
+    pub current: Color,
+    pub error: Color,
+    pub warning: Color,
+    pub update: Color,
+    pub success: Color,
+    pub header: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            current: Color::Green,
+            error: Color::Red,
+            warning: Color::Yellow,
+            update: Color::Yellow,
+            success: Color::Green,
+            header: Color::Bold,
+        }
+    }
+}
+
+impl Theme {
+    pub fn color(&self, role: Role) -> Color {
+        match role {
+            Role::Plain => Color::Plain,
+            Role::Current => self.current,
+            Role::Error => self.error,
+            Role::Warning => self.warning,
+            Role::Update => self.update,
+            Role::Success => self.success,
+            Role::Header => self.header,
+        }
+    }
+}
+
+/// Active theme, `None` when colors are disabled
+static ACTIVE: OnceCell<Option<Theme>> = OnceCell::new();
+
+/// Sets up colors for this process; later calls have no effect
+pub fn init(choice: ColorChoice, theme: Theme) {
+    let _ = ACTIVE.set(choice.enabled().then_some(theme));
+}
+
+/// Returns `true` if output is colorized
+pub fn enabled() -> bool {
+    matches!(ACTIVE.get(), Some(Some(_)))
+}
+
+/// Wraps `text` in the escape codes of `role`'s color, if colors are enabled
+pub fn paint(role: Role, text: &str) -> String {
+    match ACTIVE.get() {
+        Some(Some(theme)) => paint_with(theme.color(role), text),
+        _ => text.to_string(),
+    }
+}
+
+fn paint_with(color: Color, text: &str) -> String {
+    match color.ansi() {
+        Some(code) => format!("\x1b[{}m{}\x1b[0m", code, text),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_theme_overrides() {
+        let theme: Theme = ron::from_str("(current: cyan, header: plain)").unwrap();
+        assert_eq!(theme.current, Color::Cyan);
+        assert_eq!(theme.error, Color::Red);
+
+        assert_eq!(paint_with(theme.current, "1.0.0"), "\x1b[36m1.0.0\x1b[0m");
+        assert_eq!(paint_with(theme.header, "pkg"), "pkg");
+    }
+}
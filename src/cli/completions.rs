@@ -0,0 +1,104 @@
+//! # Completion Installation
+//!
+//! `uhpm completions <shell>` prints a completion script; `uhpm completions
+//! install` writes it where the shell of the user (from `$SHELL`, or
+//! `--shell`) picks it up without further configuration:
+//!
+//! | Shell | File                                                                  |
+//! |-------|-----------------------------------------------------------------------|
+//! | bash  | `${BASH_COMPLETION_USER_DIR:-${XDG_DATA_HOME:-~/.local/share}/bash-completion}/completions/uhpm` |
+//! | zsh   | `~/.zfunc/_uhpm` (the directory must be on `fpath`)                   |
+//! | fish  | `${XDG_CONFIG_HOME:-~/.config}/fish/completions/uhpm.fish`            |
+//!
+//! Other shells have no conventional user directory; their scripts are
+//! only printed.
+
+use crate::cli::Cli;
+use crate::error::UhpmError;
+use clap::CommandFactory;
+use clap_complete::{Shell, generate};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where the completion script for `shell` goes, given the home directory
+/// and a lookup of environment variables
+pub fn target(shell: Shell, home: &Path, env: impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    let var = |name: &str| env(name).filter(|v| !v.is_empty()).map(PathBuf::from);
+    match shell {
+        Shell::Bash => {
+            let dir = var("BASH_COMPLETION_USER_DIR").unwrap_or_else(|| {
+                var("XDG_DATA_HOME")
+                    .unwrap_or_else(|| home.join(".local/share"))
+                    .join("bash-completion")
+            });
+            Some(dir.join("completions/uhpm"))
+        }
+        Shell::Zsh => Some(home.join(".zfunc/_uhpm")),
+        Shell::Fish => Some(
+            var("XDG_CONFIG_HOME")
+                .unwrap_or_else(|| home.join(".config"))
+                .join("fish/completions/uhpm.fish"),
+        ),
+        _ => None,
+    }
+}
+
+/// The completion script for `shell`
+pub fn script(shell: Shell) -> Vec<u8> {
+    let mut script = Vec::new();
+    generate(shell, &mut Cli::command(), "uhpm", &mut script);
+    script
+}
+
+/// Writes the completion script for `shell`, detected from `$SHELL` if not
+/// given; returns the shell and the file written
+pub fn install(shell: Option<Shell>) -> Result<(Shell, PathBuf), UhpmError> {
+    let shell = shell.or_else(Shell::from_env).ok_or_else(|| {
+        UhpmError::Validation("could not detect the shell, pass --shell".to_string())
+    })?;
+    let home =
+        dirs::home_dir().ok_or_else(|| UhpmError::Config("home directory not found".into()))?;
+    let path = target(shell, &home, |name| std::env::var(name).ok()).ok_or_else(|| {
+        UhpmError::Validation(format!(
+            "{} has no completion directory, use `uhpm completions {}` instead",
+            shell, shell
+        ))
+    })?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, script(shell))?;
+    Ok((shell, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completion_targets() {
+        let home = Path::new("/home/u");
+        let none = |_: &str| None;
+        assert_eq!(
+            target(Shell::Bash, home, none),
+            Some(PathBuf::from(
+                "/home/u/.local/share/bash-completion/completions/uhpm"
+            ))
+        );
+        assert_eq!(
+            target(Shell::Bash, home, |name| (name == "XDG_DATA_HOME")
+                .then(|| "/data".to_string())),
+            Some(PathBuf::from("/data/bash-completion/completions/uhpm"))
+        );
+        assert_eq!(
+            target(Shell::Zsh, home, none),
+            Some(PathBuf::from("/home/u/.zfunc/_uhpm"))
+        );
+        assert_eq!(
+            target(Shell::Fish, home, none),
+            Some(PathBuf::from("/home/u/.config/fish/completions/uhpm.fish"))
+        );
+        assert_eq!(target(Shell::PowerShell, home, none), None);
+        assert!(!script(Shell::Fish).is_empty());
+    }
+}
@@ -0,0 +1,227 @@
+//! # Error Reports
+//!
+//! Turns an error returned by [`Cli::run`](super::Cli::run) into what the
+//! user sees on stderr: a localized message, a hint on what to do about it
+//! where there is one, and with `--verbose` the chain of underlying causes.
+//!
+//! ```text
+//! error: package not found: foo
+//! hint: `uhpm search foo` lists similar packages in the repositories
+//! ```
+
+use super::color::{self, Role};
+use crate::config::Config;
+use crate::error::{RepoError, SigningError, UhpmError};
+use crate::log::{LOGGER, format_ordered};
+use crate::platform;
+use std::error::Error;
+use std::io;
+
+/// What is shown for an error
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub message: String,
+    pub hint: Option<String>,
+    /// Messages of the underlying errors, outermost first
+    pub causes: Vec<String>,
+}
+
+/// Looks up `key` and substitutes `args` as displayed, not debug-formatted
+fn localized(key: &str, args: &[String]) -> String {
+    format_ordered(&LOGGER.msg(key), args)
+}
+
+fn config_path() -> String {
+    Config::get_config_path()
+        .unwrap_or_else(|_| platform::uhpm_home().join("config.ron"))
+        .display()
+        .to_string()
+}
+
+fn repos_path() -> String {
+    platform::uhpm_home()
+        .join("repos.ron")
+        .display()
+        .to_string()
+}
+
+fn database(e: &sqlx::Error) -> (String, Option<String>) {
+    let db_path = platform::uhpm_home().join("packages.db");
+    (
+        localized("cli.errors.database", &[e.to_string()]),
+        Some(localized(
+            "cli.errors.hint.database",
+            &[db_path.display().to_string()],
+        )),
+    )
+}
+
+fn io_error(e: &io::Error) -> (String, Option<String>) {
+    let hint = (e.kind() == io::ErrorKind::PermissionDenied).then(|| {
+        localized(
+            "cli.errors.hint.permission",
+            &[platform::uhpm_home().display().to_string()],
+        )
+    });
+    (localized("cli.errors.io", &[e.to_string()]), hint)
+}
+
+fn not_found(name: &str) -> (String, Option<String>) {
+    (
+        localized("cli.errors.not_found", &[name.to_string()]),
+        Some(localized("cli.errors.hint.search", &[name.to_string()])),
+    )
+}
+
+fn signing(e: &SigningError) -> (String, Option<String>) {
+    let message = localized("cli.errors.signing", &[e.to_string()]);
+    let hint = match e {
+        SigningError::Unsigned(_) => {
+            localized("cli.errors.hint.require_signatures", &[config_path()])
+        }
+        SigningError::NoTrustedKeys(dir) => {
+            localized("cli.errors.hint.trusted_keys", &[dir.display().to_string()])
+        }
+        SigningError::Invalid(_) => localized("cli.errors.hint.invalid_signature", &[]),
+        SigningError::FingerprintMismatch(_) => localized("cli.errors.hint.fingerprint", &[]),
+        SigningError::Unconfirmed(_) => localized("cli.errors.hint.confirm_key", &[]),
+        SigningError::ToolMissing(tool) => {
+            localized("cli.errors.hint.install_tool", std::slice::from_ref(tool))
+        }
+        SigningError::Io(_) | SigningError::SignFailed(_) => return (message, None),
+    };
+    (message, Some(hint))
+}
+
+fn uhpm(e: &UhpmError) -> (String, Option<String>) {
+    match e {
+        UhpmError::Config(e) => (
+            localized("cli.errors.config", &[e.to_string()]),
+            Some(localized("cli.errors.hint.config", &[config_path()])),
+        ),
+        UhpmError::Database(e) => database(e),
+        UhpmError::Repository(RepoError::NotFound(name)) | UhpmError::NotFound(name) => {
+            not_found(name)
+        }
+        UhpmError::Repository(e @ RepoError::Integrity(_)) => (
+            localized("cli.errors.repository", &[e.to_string()]),
+            Some(localized("cli.errors.hint.integrity", &[])),
+        ),
+        UhpmError::Repository(e @ RepoError::Fetch { .. }) => (
+            localized("cli.errors.repository", &[e.to_string()]),
+            Some(localized("cli.errors.hint.network", &[repos_path()])),
+        ),
+        UhpmError::Repository(e) => (
+            localized("cli.errors.repository", &[e.to_string()]),
+            Some(localized("cli.errors.hint.repos", &[repos_path()])),
+        ),
+        UhpmError::Network(e) => (
+            localized("cli.errors.network", &[e.to_string()]),
+            Some(localized("cli.errors.hint.network", &[repos_path()])),
+        ),
+        UhpmError::Io(e) => io_error(e),
+        UhpmError::Package(msg) => (
+            localized("cli.errors.package", std::slice::from_ref(msg)),
+            None,
+        ),
+        UhpmError::Parse(msg) => (
+            localized("cli.errors.parse", std::slice::from_ref(msg)),
+            None,
+        ),
+        UhpmError::Validation(msg) => (
+            localized("cli.errors.validation", std::slice::from_ref(msg)),
+            None,
+        ),
+        UhpmError::NoNewVersion(name) => (
+            localized("cli.errors.no_new_version", std::slice::from_ref(name)),
+            None,
+        ),
+        UhpmError::Conflict(msg) => (
+            localized("cli.errors.conflict", std::slice::from_ref(msg)),
+            None,
+        ),
+        UhpmError::Signing(e) => signing(e),
+        UhpmError::ReadOnly(home) => (
+            localized("cli.errors.read_only", &[home.display().to_string()]),
+            Some(localized("cli.errors.hint.read_only", &[])),
+        ),
+    }
+}
+
+/// Describes an error for the user
+pub fn report(err: &(dyn Error + 'static)) -> Report {
+    let (message, hint) = if let Some(e) = err.downcast_ref::<UhpmError>() {
+        uhpm(e)
+    } else if let Some(e) = err.downcast_ref::<sqlx::Error>() {
+        database(e)
+    } else if let Some(e) = err.downcast_ref::<io::Error>() {
+        io_error(e)
+    } else {
+        (err.to_string(), None)
+    };
+
+    // A cause whose text the message repeats verbatim adds nothing
+    let mut causes = Vec::new();
+    let mut source = err.source();
+    while let Some(cause) = source {
+        let text = cause.to_string();
+        if text != message {
+            causes.push(text);
+        }
+        source = cause.source();
+    }
+    Report {
+        message,
+        hint,
+        causes,
+    }
+}
+
+/// Prints an error to stderr and returns the exit code for it
+///
+/// The causes are only shown when `verbose` is set.
+pub fn print(err: &(dyn Error + 'static), verbose: bool) -> u8 {
+    let report = report(err);
+    eprintln!(
+        "{}",
+        color::paint(
+            Role::Error,
+            &localized("cli.report.error", &[report.message])
+        )
+    );
+    if verbose {
+        for cause in &report.causes {
+            eprintln!(
+                "  {}",
+                localized("cli.report.caused_by", std::slice::from_ref(cause))
+            );
+        }
+    }
+    if let Some(hint) = report.hint {
+        eprintln!(
+            "{}",
+            color::paint(Role::Warning, &localized("cli.report.hint", &[hint]))
+        );
+    }
+    super::exit_code(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_hints_and_causes() {
+        let err = UhpmError::NotFound("foo".to_string());
+        let shown = report(&err);
+        assert!(shown.message.contains("foo"));
+        assert!(shown.hint.is_some_and(|h| h.contains("uhpm search foo")));
+        assert!(shown.causes.is_empty());
+
+        let err = UhpmError::Repository(RepoError::Io(io::Error::other("disk full")));
+        let shown = report(&err);
+        assert!(shown.message.contains("disk full"));
+        assert!(shown.hint.is_some_and(|h| h.contains("repos.ron")));
+        assert_eq!(shown.causes, ["IO error: disk full", "disk full"]);
+    }
+}
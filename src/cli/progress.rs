@@ -0,0 +1,121 @@
+//! # CLI Progress Rendering
+//!
+//! Renders library [`ProgressEvent`]s as `indicatif` progress bars: one
+//! line per running download with bytes received, transfer speed and ETA,
+//! below them the count of finished downloads.
+
+use crate::events::{ProgressEvent, ProgressSink};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Progress sink drawing download bars on the terminal
+#[derive(Default)]
+pub struct IndicatifSink {
+    state: Mutex<Bars>,
+}
+
+#[derive(Default)]
+struct Bars {
+    multi: MultiProgress,
+    /// Finished downloads of the batch
+    overall: Option<ProgressBar>,
+    /// Running downloads by URL
+    files: HashMap<String, ProgressBar>,
+}
+
+impl IndicatifSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Last path segment of `url`, shown in front of its bar
+fn file_name(url: &str) -> &str {
+    url.rsplit('/').next().unwrap_or(url)
+}
+
+fn bytes_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{prefix:30!} [{bar:30.cyan/blue}] {bytes}/{total_bytes} {binary_bytes_per_sec} ETA {eta}",
+    )
+    .unwrap()
+    .progress_chars("##-")
+}
+
+fn unknown_size_style() -> ProgressStyle {
+    ProgressStyle::with_template("{prefix:30!} {spinner} {bytes} {binary_bytes_per_sec}").unwrap()
+}
+
+impl Bars {
+    /// Ends the bar of `url` and counts it as done
+    fn finish_file(&mut self, url: &str) {
+        if let Some(bar) = self.files.remove(url) {
+            bar.finish_and_clear();
+            self.multi.remove(&bar);
+        }
+        if let Some(overall) = &self.overall {
+            overall.inc(1);
+        }
+    }
+}
+
+impl ProgressSink for IndicatifSink {
+    fn emit(&self, event: ProgressEvent) {
+        let Ok(mut bars) = self.state.lock() else {
+            return;
+        };
+
+        match event {
+            ProgressEvent::DownloadsQueued { total } => {
+                let overall = bars.multi.add(ProgressBar::new(total as u64));
+                overall.set_style(
+                    ProgressStyle::with_template("[{bar:40.cyan/blue}] {pos}/{len} {msg}")
+                        .unwrap()
+                        .progress_chars("##-"),
+                );
+                bars.overall = Some(overall);
+            }
+            ProgressEvent::DownloadStarted { url } => {
+                let bar = ProgressBar::no_length().with_style(unknown_size_style());
+                bar.set_prefix(file_name(&url).to_string());
+                let bar = match &bars.overall {
+                    Some(overall) => bars.multi.insert_before(overall, bar),
+                    None => bars.multi.add(bar),
+                };
+                bars.files.insert(url, bar);
+            }
+            ProgressEvent::DownloadProgress {
+                url,
+                downloaded,
+                total,
+            } => {
+                if let Some(bar) = bars.files.get(&url) {
+                    if let Some(total) = total
+                        && bar.length() != Some(total)
+                    {
+                        bar.set_length(total);
+                        bar.set_style(bytes_style());
+                    }
+                    bar.set_position(downloaded);
+                }
+            }
+            ProgressEvent::DownloadFinished { url } => {
+                bars.finish_file(&url);
+                if let Some(overall) = &bars.overall {
+                    overall.set_message(format!("Downloaded: {}", file_name(&url)));
+                }
+            }
+            ProgressEvent::DownloadFailed { url, .. } => bars.finish_file(&url),
+            ProgressEvent::DownloadsCompleted => {
+                for (_, bar) in bars.files.drain() {
+                    bar.finish_and_clear();
+                }
+                if let Some(overall) = bars.overall.take() {
+                    overall.finish_with_message("Download complete");
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! # First-Run Setup
+//!
+//! When uhpm runs for the first time (no uhpm home yet) from a terminal, it
+//! asks how to set itself up before creating anything:
+//!
+//! - where executables are linked ([`BinMode`]) and where packages are
+//!   unpacked;
+//! - whether installed archives are kept in the cache;
+//! - which repositories to use, written to `repos.ron`;
+//! - whether to put the bin directory on `PATH` in the shell's startup file.
+//!
+//! Without a terminal, or when a prompt is cancelled, the defaults are
+//! written instead, so later commands always find a `config.ron` and a
+//! (possibly empty) `repos.ron`. `uhpm setup` runs the wizard again.
+
+use super::color::Role;
+use crate::config::Config;
+use crate::error::UhpmError;
+use crate::package::bin_layout::{self, BinLayout, BinMode};
+use crate::repo::{RepoConfig, RepoMap};
+use crate::{lcprintln, lformat, lprintln, platform};
+use inquire::{Confirm, InquireError, Select, Text};
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+/// Marker of the lines added to a shell startup file
+const SHELL_MARKER: &str = "# added by uhpm setup";
+
+/// Answers to the setup questions
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SetupChoices {
+    pub bin_mode: BinMode,
+    pub tmp_dir: Option<PathBuf>,
+    pub keep_archives: bool,
+    pub repos: RepoMap,
+    /// Startup file to add the bin directory to `PATH` in
+    pub shell_rc: Option<PathBuf>,
+}
+
+/// Whether uhpm has never been set up
+pub fn needed() -> bool {
+    !platform::uhpm_home().exists()
+}
+
+/// Sets uhpm up on first use: interactively from a terminal, with the
+/// defaults otherwise
+pub fn first_run() -> Result<(), UhpmError> {
+    if io::stdin().is_terminal() && io::stdout().is_terminal() {
+        lcprintln!(Role::Header, "cli.setup.welcome");
+        run()
+    } else {
+        apply(&platform::uhpm_home(), &SetupChoices::default())
+    }
+}
+
+/// Runs the wizard and applies the answers
+pub fn run() -> Result<(), UhpmError> {
+    let home = platform::uhpm_home();
+    lprintln!("cli.setup.home", home.display());
+    let choices = match ask() {
+        Ok(choices) => choices,
+        Err(InquireError::OperationCanceled | InquireError::OperationInterrupted) => {
+            lprintln!("cli.setup.cancelled");
+            SetupChoices::default()
+        }
+        Err(e) => return Err(UhpmError::Io(io::Error::other(e))),
+    };
+    apply(&home, &choices)?;
+    lcprintln!(
+        Role::Success,
+        "cli.setup.done",
+        home.join("config.ron").display()
+    );
+    Ok(())
+}
+
+/// Asks the setup questions
+fn ask() -> Result<SetupChoices, InquireError> {
+    let shared = lformat!("cli.setup.bin_shared", bin_layout::shared_dir().display());
+    let private = lformat!("cli.setup.bin_private", bin_layout::private_dir().display());
+    let bin_mode = if Select::new(&lformat!("cli.setup.bin"), vec![shared.clone(), private])
+        .prompt()?
+        == shared
+    {
+        BinMode::Shared
+    } else {
+        BinMode::Private
+    };
+
+    let tmp_dir = Text::new(&lformat!("cli.setup.tmp_dir"))
+        .with_help_message(&lformat!("cli.setup.tmp_dir_help"))
+        .prompt()?;
+    let tmp_dir = Some(tmp_dir.trim())
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from);
+
+    let keep_archives = Confirm::new(&lformat!("cli.setup.keep_archives"))
+        .with_default(false)
+        .prompt()?;
+
+    let mut repos = RepoMap::new();
+    loop {
+        let name = Text::new(&lformat!("cli.setup.repo_name")).prompt()?;
+        let name = name.trim();
+        if name.is_empty() {
+            break;
+        }
+        let url = Text::new(&lformat!("cli.setup.repo_url", name)).prompt()?;
+        repos.insert(
+            name.to_string(),
+            url.trim().trim_end_matches('/').to_string(),
+        );
+    }
+
+    let layout = BinLayout {
+        mode: bin_mode,
+        prefix: String::new(),
+    };
+    let shell_rc = match shell_rc() {
+        Some(rc) if !bin_layout::on_path(&layout) => {
+            let question = lformat!("cli.setup.path", layout.dir().display(), rc.display());
+            Confirm::new(&question)
+                .with_default(true)
+                .prompt()?
+                .then_some(rc)
+        }
+        _ => None,
+    };
+
+    Ok(SetupChoices {
+        bin_mode,
+        tmp_dir,
+        keep_archives,
+        repos,
+        shell_rc,
+    })
+}
+
+/// Startup file of the user's shell, from `$SHELL`
+fn shell_rc() -> Option<PathBuf> {
+    let shell = std::env::var("SHELL").ok()?;
+    let home = dirs::home_dir()?;
+    match Path::new(&shell).file_name()?.to_str()? {
+        "bash" => Some(home.join(".bashrc")),
+        "zsh" => Some(home.join(".zshrc")),
+        "fish" => Some(home.join(".config/fish/conf.d/uhpm.fish")),
+        _ => None,
+    }
+}
+
+/// Writes the configuration, repositories and shell integration into the
+/// uhpm home at `home`
+///
+/// The answers are written over an existing `config.ron`, keeping its
+/// other settings, and chosen repositories are added to an existing
+/// `repos.ron`.
+pub fn apply(home: &Path, choices: &SetupChoices) -> Result<(), UhpmError> {
+    fs::create_dir_all(home)?;
+
+    let config_path = home.join("config.ron");
+    let mut config = Config::load_from_path(&config_path).unwrap_or_default();
+    config.bin.mode = choices.bin_mode;
+    config.tmp_dir = choices.tmp_dir.clone();
+    config.cache.keep_archives = choices.keep_archives;
+    config
+        .save_to_path(&config_path)
+        .map_err(|e| UhpmError::Io(io::Error::other(e)))?;
+
+    let repos_path = home.join("repos.ron");
+    if !choices.repos.is_empty() || !repos_path.exists() {
+        let mut repos = RepoConfig::load(&repos_path)?;
+        for (name, url) in &choices.repos {
+            repos.add(name, url, true)?;
+        }
+        repos.save()?;
+    }
+
+    if let Some(rc) = &choices.shell_rc {
+        let layout = BinLayout {
+            mode: choices.bin_mode,
+            prefix: String::new(),
+        };
+        add_to_path(rc, &layout.dir())?;
+    }
+    Ok(())
+}
+
+/// Appends a line putting `dir` on `PATH` to a shell startup file, once
+fn add_to_path(rc: &Path, dir: &Path) -> io::Result<()> {
+    if fs::read_to_string(rc).is_ok_and(|content| content.contains(SHELL_MARKER)) {
+        return Ok(());
+    }
+    let line = if rc.extension().is_some_and(|ext| ext == "fish") {
+        format!("fish_add_path \"{}\"", dir.display())
+    } else {
+        format!("export PATH=\"{}:$PATH\"", dir.display())
+    };
+    if let Some(parent) = rc.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(rc)?;
+    writeln!(file, "\n{}\n{}", SHELL_MARKER, line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_writes_config_repos_and_path() {
+        let tmp = tempfile::tempdir().unwrap();
+        let home = tmp.path().join("uhpm");
+        let rc = tmp.path().join(".bashrc");
+        let mut choices = SetupChoices {
+            bin_mode: BinMode::Private,
+            keep_archives: true,
+            shell_rc: Some(rc.clone()),
+            ..Default::default()
+        };
+        choices
+            .repos
+            .insert("main".to_string(), "https://example.com/repo".to_string());
+
+        apply(&home, &choices).unwrap();
+        let mut config = Config::load_from_path(home.join("config.ron")).unwrap();
+        config.require_signatures = true;
+        config.save_to_path(home.join("config.ron")).unwrap();
+        apply(&home, &choices).unwrap();
+
+        let config = Config::load_from_path(home.join("config.ron")).unwrap();
+        assert_eq!(config.bin.mode, BinMode::Private);
+        assert!(config.cache.keep_archives);
+        assert!(config.require_signatures);
+        let repos = RepoConfig::load(&home.join("repos.ron")).unwrap();
+        assert_eq!(repos.repos()["main"], "https://example.com/repo");
+        let rc = fs::read_to_string(rc).unwrap();
+        assert_eq!(rc.matches(SHELL_MARKER).count(), 1);
+        assert!(rc.contains("export PATH="));
+    }
+}
@@ -0,0 +1,180 @@
+//! # Table Rendering
+//!
+//! Aligned, column-based output for listing commands. Cell widths are
+//! measured in terminal columns, so CJK text and emoji line up, and cells
+//! wider than a column's limit are truncated with `…`.
+//!
+//! Colors are applied per row after padding, so escape codes never affect
+//! alignment.
+
+use crate::cli::color::{self, Role};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Gap between columns
+const SEPARATOR: &str = "  ";
+
+/// Horizontal alignment of a column
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Align {
+    #[default]
+    Left,
+    Right,
+}
+
+#[derive(Debug)]
+struct Column {
+    header: String,
+    align: Align,
+    max_width: Option<usize>,
+}
+
+/// A table of text cells
+#[derive(Debug, Default)]
+pub struct Table {
+    columns: Vec<Column>,
+    rows: Vec<(Role, Vec<String>)>,
+}
+
+impl Table {
+    /// Creates a table with the given column headers
+    pub fn new<S: Into<String>>(headers: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            columns: headers
+                .into_iter()
+                .map(|h| Column {
+                    header: h.into(),
+                    align: Align::Left,
+                    max_width: None,
+                })
+                .collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Sets the alignment of column `idx`
+    pub fn align(mut self, idx: usize, align: Align) -> Self {
+        self.columns[idx].align = align;
+        self
+    }
+
+    /// Limits column `idx` to `width` terminal columns
+    pub fn max_width(mut self, idx: usize, width: usize) -> Self {
+        self.columns[idx].max_width = Some(width.max(1));
+        self
+    }
+
+    /// Appends an uncolored row
+    pub fn row<S: Into<String>>(&mut self, cells: impl IntoIterator<Item = S>) {
+        self.styled_row(Role::Plain, cells);
+    }
+
+    /// Appends a row colored by `role`
+    ///
+    /// Missing cells are left empty; extra cells are ignored.
+    pub fn styled_row<S: Into<String>>(&mut self, role: Role, cells: impl IntoIterator<Item = S>) {
+        let mut cells: Vec<String> = cells.into_iter().map(Into::into).collect();
+        cells.resize(self.columns.len(), String::new());
+        self.rows.push((role, cells));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Renders the header and all rows, one line each
+    pub fn render(&self) -> Vec<String> {
+        let widths: Vec<usize> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, col)| {
+                let natural = self
+                    .rows
+                    .iter()
+                    .map(|(_, cells)| cells[i].width())
+                    .chain(std::iter::once(col.header.width()))
+                    .max()
+                    .unwrap_or(0);
+                col.max_width.map_or(natural, |max| natural.min(max))
+            })
+            .collect();
+
+        let headers: Vec<String> = self.columns.iter().map(|c| c.header.clone()).collect();
+        std::iter::once(color::paint(Role::Header, &self.line(&headers, &widths)))
+            .chain(
+                self.rows
+                    .iter()
+                    .map(|(role, cells)| color::paint(*role, &self.line(cells, &widths))),
+            )
+            .collect()
+    }
+
+    /// Prints the table to stdout
+    pub fn print(&self) {
+        for line in self.render() {
+            println!("{}", line);
+        }
+    }
+
+    fn line(&self, cells: &[String], widths: &[usize]) -> String {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&self.columns)
+            .zip(widths)
+            .map(|((cell, col), &width)| pad(&truncate(cell, width), width, col.align))
+            .collect();
+        padded.join(SEPARATOR).trim_end().to_string()
+    }
+}
+
+/// Cuts `text` to at most `width` terminal columns, ending in `…` if shortened
+pub fn truncate(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+
+    let mut out = String::new();
+    let mut used = 0;
+    for ch in text.chars() {
+        let w = ch.width().unwrap_or(0);
+        if used + w + 1 > width {
+            break;
+        }
+        out.push(ch);
+        used += w;
+    }
+    out.push('…');
+    out
+}
+
+fn pad(text: &str, width: usize, align: Align) -> String {
+    let fill = " ".repeat(width.saturating_sub(text.width()));
+    match align {
+        Align::Left => format!("{}{}", text, fill),
+        Align::Right => format!("{}{}", fill, text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_aligns_columns() {
+        let mut table = Table::new(["NAME", "SIZE"]).align(1, Align::Right);
+        table.row(["firefox", "1.2 MiB"]);
+        table.row(["日本", "3 B"]);
+
+        assert_eq!(
+            table.render(),
+            vec!["NAME        SIZE", "firefox  1.2 MiB", "日本         3 B"]
+        );
+    }
+
+    #[test]
+    fn test_truncate_wide_chars() {
+        assert_eq!(truncate("abcdef", 4), "abc…");
+        assert_eq!(truncate("日本語", 4), "日…");
+        assert_eq!(truncate("abc", 3), "abc");
+    }
+}
@@ -3,8 +3,20 @@
 //! This module defines the configuration structure for UHPM (Universal Home Package Manager).
 //! It handles loading, saving, and managing configuration settings including repository URLs
 //! and update sources.
+//!
+//! It also keeps the list of named [`Profiles`]: separate installations,
+//! each with its own configuration, repository list, database and package
+//! store, of which one at a time has its links in place.
 
+use crate::archive::ExtractLimits;
+use crate::cli::color::Theme;
+use crate::package::LinkMode;
+use crate::package::bin_layout::BinLayout;
+use crate::package::conflicts::ReplacePolicy;
+use crate::package::requirements::RequirementPolicy;
+use crate::package::sandbox::SandboxConfig;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use thiserror::Error;
@@ -27,16 +39,169 @@ pub enum ConfigError {
     /// Configuration file not found.
     #[error("Configuration file not found: {0}")]
     NotFound(String),
+
+    /// No profile with this name exists.
+    #[error("Profile not found: {0}")]
+    ProfileNotFound(String),
+
+    /// A profile with this name exists already.
+    #[error("Profile already exists: {0}")]
+    ProfileExists(String),
+
+    /// The name cannot be used for a profile.
+    #[error("Invalid profile name: {0} (letters, digits, `-`, `_` and `.` only)")]
+    InvalidProfileName(String),
 }
 
 /// Represents the UHPM configuration.
 ///
 /// Contains settings for package management including update sources
 /// and repository configuration.
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
 pub struct Config {
     /// URL source for UHPM updates
     pub update_source: String,
+
+    /// HTTP gateways tried in order when fetching `ipfs://` package URLs
+    pub ipfs_gateways: Vec<String>,
+
+    /// Update behaviour: blackout windows and per-package hooks
+    pub update: UpdateConfig,
+
+    /// Hours an ephemeral package fetched by `uhpm run` is kept before re-fetching
+    pub run_cache_ttl_hours: u64,
+
+    /// Local cache behaviour
+    pub cache: CacheConfig,
+
+    /// Colors used for CLI output
+    pub theme: Theme,
+
+    /// Base directory for unpacking packages, `~/.uhpm/tmp` when unset.
+    /// Packages are unpacked into its `uhpm` subdirectory; a location on
+    /// another filesystem than `~/.uhpm` makes installs copy instead of rename
+    pub tmp_dir: Option<PathBuf>,
+
+    /// What to do when a package link would replace a file uhpm does not manage
+    pub replace_policy: ReplacePolicy,
+
+    /// How files of packages without a recorded mode are placed when
+    /// neither `--direct` nor `--symlink` is given: `symlink` or `copy`
+    pub link_mode: LinkMode,
+
+    /// Where executables are linked: the shared `$XDG_BIN_HOME` or the
+    /// private `~/.uhpm/bin`, with an optional name prefix
+    pub bin: BinLayout,
+
+    /// Isolation of package hooks and build scripts, see
+    /// [`sandbox`](crate::package::sandbox)
+    pub sandbox: SandboxConfig,
+
+    /// Count commands, installs and updates in the local database for
+    /// `uhpm stats --usage`; nothing is ever transmitted
+    pub record_usage: bool,
+
+    /// Refuse to install packages without a valid signature from a key in
+    /// `~/.uhpm/keys`, see [`signing`](crate::signing)
+    pub require_signatures: bool,
+
+    /// Go on with the remaining packages when one of several fails, see
+    /// [`batch`](crate::cli::batch); `--fail-fast` overrides it
+    pub keep_going: bool,
+
+    /// Days after which `uhpm gc` removes an unused old version, see
+    /// [`retention`](crate::package::retention)
+    pub gc_unused_days: u64,
+
+    /// Retries of interrupted or failed package downloads
+    pub download: DownloadConfig,
+
+    /// Run the `hooks/post-install`, `hooks/pre-remove` and
+    /// `hooks/post-upgrade` scripts packages ship, see
+    /// [`hooks`](crate::package::hooks)
+    pub allow_hooks: bool,
+
+    /// Seconds after which a package hook script is killed
+    pub hook_timeout_secs: u64,
+
+    /// Caps on the size and number of files a package archive may unpack
+    /// to, see [`archive`](crate::archive)
+    pub extract: ExtractLimits,
+
+    /// Packages kept out of search, dependency resolution and updates,
+    /// e.g. `"foo<2.0"`, see [`ignore`](crate::package::ignore)
+    pub ignore: Vec<String>,
+
+    /// What to do when a package needs programs this machine lacks: `warn`,
+    /// `fail` or `ignore`, see [`requirements`](crate::package::requirements)
+    pub runtime_requirements: RequirementPolicy,
+
+    /// Warn about packaged binaries whose interpreter, libraries or glibc
+    /// version this machine lacks, see [`elf`](crate::package::elf)
+    pub scan_binaries: bool,
+}
+
+/// Settings for package downloads.
+///
+/// A failed download is retried `retries` times, waiting `backoff_ms`
+/// before the first retry and twice as long before each further one.
+/// Interrupted transfers resume where they stopped if the server supports
+/// range requests.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DownloadConfig {
+    pub retries: u32,
+    pub backoff_ms: u64,
+}
+
+impl Default for DownloadConfig {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            backoff_ms: 500,
+        }
+    }
+}
+
+/// Settings for the local package cache.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Keep installed `.uhp` archives in `~/.uhpm/cache/archives`, so older
+    /// versions can be reinstalled without the repository serving them
+    pub keep_archives: bool,
+}
+
+/// Settings controlling when and how packages are updated.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct UpdateConfig {
+    /// Local time windows during which updates are refused,
+    /// e.g. `"Mon 09:00-17:00"`, `"Mon-Fri 12:00-13:00"` or `"* 22:00-06:00"`
+    pub blackout: Vec<String>,
+
+    /// Shell commands run before/after updating a package, keyed by package name
+    pub hooks: HashMap<String, UpdateHooks>,
+}
+
+/// Commands executed around the update of a single package.
+///
+/// Both run through `sh -c` with `UHPM_PKG_NAME`, `UHPM_PKG_VERSION` and
+/// `UHPM_PKG_DIR` set. They are the user's own commands, so unlike package
+/// scripts they are not sandboxed.
+/// A failing `pre` command aborts the update of that package.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct UpdateHooks {
+    pub pre: Option<String>,
+    pub post: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Config {
@@ -44,6 +209,30 @@ impl Config {
     pub fn new() -> Self {
         Self {
             update_source: String::new(),
+            ipfs_gateways: vec![
+                "https://ipfs.io/ipfs/".to_string(),
+                "https://dweb.link/ipfs/".to_string(),
+            ],
+            update: UpdateConfig::default(),
+            run_cache_ttl_hours: 24,
+            cache: CacheConfig::default(),
+            theme: Theme::default(),
+            tmp_dir: None,
+            replace_policy: ReplacePolicy::default(),
+            link_mode: LinkMode::default(),
+            bin: BinLayout::default(),
+            sandbox: SandboxConfig::default(),
+            record_usage: true,
+            require_signatures: false,
+            keep_going: false,
+            gc_unused_days: 30,
+            download: DownloadConfig::default(),
+            allow_hooks: false,
+            hook_timeout_secs: 300,
+            extract: ExtractLimits::default(),
+            ignore: Vec::new(),
+            runtime_requirements: RequirementPolicy::default(),
+            scan_binaries: true,
         }
     }
 
@@ -67,6 +256,12 @@ impl Config {
         Ok(config)
     }
 
+    /// Loads configuration from the default location, falling back to
+    /// default values when the file is missing or cannot be parsed.
+    pub fn load_or_default() -> Self {
+        Self::load().unwrap_or_default()
+    }
+
     /// Saves configuration to the default location (`~/.uhpm/config.ron`).
     pub fn save(&self) -> Result<(), ConfigError> {
         let config_path = Self::get_config_path()?;
@@ -91,14 +286,7 @@ impl Config {
 
     /// Returns the default configuration path (`~/.uhpm/config.ron`).
     pub fn get_config_path() -> Result<PathBuf, ConfigError> {
-        let home_dir = dirs::home_dir()
-            .ok_or_else(|| ConfigError::NotFound("Home directory not found".to_string()))?;
-
-        let mut config_path = home_dir;
-        config_path.push(".uhpm");
-        config_path.push("config.ron");
-
-        Ok(config_path)
+        Ok(crate::platform::uhpm_home().join("config.ron"))
     }
 
     /// Creates a default configuration file if it doesn't exist.
@@ -114,6 +302,121 @@ impl Config {
     }
 }
 
+/// Name of the profile whose data lives directly in the uhpm home
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// The active profile, stored in `profiles.ron` in the base uhpm home
+/// ([`platform::base_home`](crate::platform::base_home))
+///
+/// Profiles other than [`DEFAULT_PROFILE`] live in `profiles/<name>` below
+/// it and are complete uhpm homes of their own. Only the links of the
+/// active profile's packages are in place; `uhpm profile use` swaps them.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct Profiles {
+    /// Active profile; the default one when unset
+    pub active: Option<String>,
+}
+
+impl Profiles {
+    fn path(base: &Path) -> PathBuf {
+        base.join("profiles.ron")
+    }
+
+    /// Reads the profile state of the uhpm home `base`, the default one when
+    /// it is missing or cannot be parsed
+    pub fn load(base: &Path) -> Self {
+        fs::read_to_string(Self::path(base))
+            .ok()
+            .and_then(|s| ron::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    /// Saves the profile state to the uhpm home `base`
+    pub fn save(&self, base: &Path) -> Result<(), ConfigError> {
+        let ron_str = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())?;
+        fs::write(Self::path(base), ron_str)?;
+        Ok(())
+    }
+
+    /// Name of the active profile
+    pub fn active_name(&self) -> &str {
+        self.active.as_deref().unwrap_or(DEFAULT_PROFILE)
+    }
+
+    /// Makes `name` the active profile
+    pub fn set_active(&mut self, name: &str) {
+        self.active = (name != DEFAULT_PROFILE).then(|| name.to_string());
+    }
+}
+
+/// Whether `name` can be used for a profile
+pub fn is_valid_profile_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Data directory of the profile `name` in the uhpm home `base`
+pub fn profile_home(base: &Path, name: &str) -> PathBuf {
+    if name == DEFAULT_PROFILE {
+        base.to_path_buf()
+    } else {
+        base.join("profiles").join(name)
+    }
+}
+
+/// Names of the profiles in the uhpm home `base`, the default one first
+pub fn list_profiles(base: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(base.join("profiles"))
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .filter(|e| e.path().is_dir())
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .filter(|name| is_valid_profile_name(name))
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names.insert(0, DEFAULT_PROFILE.to_string());
+    names
+}
+
+/// Creates the profile `name` in the uhpm home `base` and returns its data
+/// directory
+///
+/// The new profile starts with the `config.ron` and `repos.ron` of the
+/// uhpm home `from` and no packages.
+pub fn create_profile(base: &Path, name: &str, from: &Path) -> Result<PathBuf, ConfigError> {
+    if !is_valid_profile_name(name) {
+        return Err(ConfigError::InvalidProfileName(name.to_string()));
+    }
+    let home = profile_home(base, name);
+    if name == DEFAULT_PROFILE || home.exists() {
+        return Err(ConfigError::ProfileExists(name.to_string()));
+    }
+    fs::create_dir_all(&home)?;
+    for file in ["config.ron", "repos.ron"] {
+        if from.join(file).is_file() {
+            fs::copy(from.join(file), home.join(file))?;
+        }
+    }
+    Ok(home)
+}
+
+/// Data directory of the existing profile `name` in the uhpm home `base`
+pub fn existing_profile(base: &Path, name: &str) -> Result<PathBuf, ConfigError> {
+    let home = profile_home(base, name);
+    if is_valid_profile_name(name) && home.is_dir() {
+        Ok(home)
+    } else {
+        Err(ConfigError::ProfileNotFound(name.to_string()))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -139,6 +442,17 @@ mod tests {
         assert_eq!(loaded_config.update_source, "https://example.com/updates");
     }
 
+    #[test]
+    fn test_config_missing_fields_use_defaults() {
+        let tmp_dir = tempdir().unwrap();
+        let config_path = tmp_dir.path().join("config.ron");
+        fs::write(&config_path, "(update_source: \"https://example.com\")").unwrap();
+
+        let loaded_config = Config::load_from_path(&config_path).unwrap();
+        assert_eq!(loaded_config.update_source, "https://example.com");
+        assert_eq!(loaded_config.ipfs_gateways, Config::new().ipfs_gateways);
+    }
+
     #[test]
     fn test_config_not_found() {
         let tmp_dir = tempdir().unwrap();
@@ -147,4 +461,39 @@ mod tests {
         let result = Config::load_from_path(&non_existent_path);
         assert!(matches!(result, Err(ConfigError::NotFound(_))));
     }
+
+    #[test]
+    fn test_profiles() {
+        let tmp_dir = tempdir().unwrap();
+        let base = tmp_dir.path();
+        fs::write(base.join("repos.ron"), "{}").unwrap();
+
+        let home = create_profile(base, "work", base).unwrap();
+        assert_eq!(home, base.join("profiles/work"));
+        assert!(home.join("repos.ron").exists());
+        assert!(!home.join("config.ron").exists());
+        assert!(matches!(
+            create_profile(base, "work", base),
+            Err(ConfigError::ProfileExists(_))
+        ));
+        assert!(matches!(
+            create_profile(base, DEFAULT_PROFILE, base),
+            Err(ConfigError::ProfileExists(_))
+        ));
+        assert!(matches!(
+            create_profile(base, "../x", base),
+            Err(ConfigError::InvalidProfileName(_))
+        ));
+        assert_eq!(list_profiles(base), ["default", "work"]);
+        assert_eq!(existing_profile(base, DEFAULT_PROFILE).unwrap(), base);
+        assert!(existing_profile(base, "home").is_err());
+
+        let mut profiles = Profiles::load(base);
+        assert_eq!(profiles.active_name(), DEFAULT_PROFILE);
+        profiles.set_active("work");
+        profiles.save(base).unwrap();
+        assert_eq!(Profiles::load(base).active_name(), "work");
+        profiles.set_active(DEFAULT_PROFILE);
+        assert_eq!(profiles.active, None);
+    }
 }
@@ -0,0 +1,196 @@
+//! # Crash Reports
+//!
+//! [`install_hook()`] replaces the panic hook of the `uhpm` binary. When
+//! uhpm panics it still prints the usual panic message, then:
+//!
+//! - releases the process lock (see [`crate::lock`]) so the next run is not
+//!   refused;
+//! - writes a report with the command, the package being worked on and a
+//!   backtrace to `~/.uhpm/crash/crash-<time>-<pid>.txt`;
+//! - tells the user where the report is.
+//!
+//! The package database needs no flushing: every write is committed as it
+//! happens, and SQLite rolls back an interrupted one the next time the
+//! database is opened.
+//!
+//! The context is kept up to date with [`set_operation()`] and
+//! [`set_package()`] as work progresses.
+
+use crate::log::{LOGGER, format_ordered};
+use crate::platform;
+use once_cell::sync::Lazy;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// What uhpm was doing, for the report
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CrashContext {
+    /// The CLI command being run
+    pub operation: Option<String>,
+    /// The package being installed, updated or removed
+    pub package: Option<String>,
+    /// The lock file held by this process
+    pub lock: Option<PathBuf>,
+}
+
+static CONTEXT: Lazy<Mutex<CrashContext>> = Lazy::new(|| Mutex::new(CrashContext::default()));
+
+fn update(f: impl FnOnce(&mut CrashContext)) {
+    // A poisoned context is still good enough for a report
+    let mut context = CONTEXT.lock().unwrap_or_else(|e| e.into_inner());
+    f(&mut context);
+}
+
+/// Records the command being run
+pub fn set_operation(operation: &str) {
+    update(|c| c.operation = Some(operation.to_string()));
+}
+
+/// Records the package being worked on
+pub fn set_package(package: &str) {
+    update(|c| c.package = Some(package.to_string()));
+}
+
+/// Records the lock file held by this process
+pub fn hold_lock(path: &Path) {
+    update(|c| c.lock = Some(path.to_path_buf()));
+}
+
+/// Forgets the lock file once it is released
+pub fn release_lock() {
+    update(|c| c.lock = None);
+}
+
+/// Directory holding crash reports
+pub fn crash_dir() -> PathBuf {
+    platform::uhpm_home().join("crash")
+}
+
+/// Writes a crash report into `dir` and returns its path
+pub fn write_report(
+    dir: &Path,
+    context: &CrashContext,
+    message: &str,
+    location: Option<&str>,
+    backtrace: &str,
+) -> io::Result<PathBuf> {
+    let now = chrono::Local::now();
+    let mut report = String::new();
+    let none = "-".to_string();
+    let _ = writeln!(report, "uhpm {} crash report", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(report, "time: {}", now.to_rfc3339());
+    let _ = writeln!(
+        report,
+        "platform: {} {}",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    );
+    let _ = writeln!(
+        report,
+        "operation: {}",
+        context.operation.as_ref().unwrap_or(&none)
+    );
+    let _ = writeln!(
+        report,
+        "package: {}",
+        context.package.as_ref().unwrap_or(&none)
+    );
+    let _ = writeln!(report, "panic: {}", message);
+    let _ = writeln!(report, "location: {}", location.unwrap_or("-"));
+    let _ = writeln!(report, "\nbacktrace:\n{}", backtrace);
+
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!(
+        "crash-{}-{}.txt",
+        now.format("%Y%m%d-%H%M%S"),
+        std::process::id()
+    ));
+    fs::write(&path, report)?;
+    Ok(path)
+}
+
+/// Text of a panic payload
+fn payload_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// Installs the panic hook, keeping the default panic message
+pub fn install_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+
+        let context = CONTEXT
+            .lock()
+            .map(|c| c.clone())
+            .unwrap_or_else(|e| e.into_inner().clone());
+        if let Some(lock) = &context.lock {
+            let _ = fs::remove_file(lock);
+            release_lock();
+        }
+
+        let location = info
+            .location()
+            .map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column()));
+        let backtrace = std::backtrace::Backtrace::force_capture().to_string();
+        let message = payload_message(info.payload());
+        match write_report(
+            &crash_dir(),
+            &context,
+            &message,
+            location.as_deref(),
+            &backtrace,
+        ) {
+            Ok(path) => eprintln!(
+                "{}",
+                format_ordered(
+                    &LOGGER.msg("crash.report_written"),
+                    &[path.display().to_string()]
+                )
+            ),
+            Err(e) => eprintln!(
+                "{}",
+                format_ordered(&LOGGER.msg("crash.report_failed"), &[e.to_string()])
+            ),
+        }
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_contains_context() {
+        let tmp = tempfile::tempdir().unwrap();
+        let context = CrashContext {
+            operation: Some("install".to_string()),
+            package: Some("foo@1.2.0".to_string()),
+            lock: None,
+        };
+        let path = write_report(
+            tmp.path(),
+            &context,
+            "called `Option::unwrap()` on a `None` value",
+            Some("src/package/installer.rs:10:5"),
+            "0: main",
+        )
+        .unwrap();
+
+        assert!(path.starts_with(tmp.path()));
+        let report = fs::read_to_string(path).unwrap();
+        assert!(report.contains("operation: install"));
+        assert!(report.contains("package: foo@1.2.0"));
+        assert!(report.contains("location: src/package/installer.rs:10:5"));
+        assert!(report.contains("0: main"));
+    }
+}
@@ -14,13 +14,52 @@
 //! - **`packages`**
 //!   - Stores package metadata (name, version, author, source, checksum).
 //!   - Marks which version is currently active via the `current` column.
+//!   - Records where a version came from via the `origin` column
+//!     (`repo` or `file`) and the exact source in `provenance` (JSON).
+//!   - Records whether a package was requested by the user or pulled in as
+//!     a dependency via the `install_reason` column.
+//!   - Records when a version was last current (`last_switched`) and last
+//!     started by `uhpm run` (`last_run`).
 //!
 //! - **`installed_files`**
 //!   - Maps installed package files to their owning package and version.
+//!   - Records the SHA-256 and size of each file's content at install
+//!     time (`sha256`, `size`), so that changes made by the user can be
+//!     detected.
 //!
 //! - **`dependencies`**
 //!   - Tracks package dependencies by name and version.
 //!
+//! - **`dependency_closure`**
+//!   - Transitive dependencies of each installed version with the exact
+//!     versions resolved at install time.
+//!
+//! - **`pins`**
+//!   - Versions `uhpm pin` holds packages at: an exact version or a
+//!     constraint that updates must match.
+//!
+//! ## Views for other tools
+//!
+//! The tables above are internal and change between releases. Tools that
+//! want to read the installation (`uhpm db path` prints where the
+//! database is) should open it read-only and use these views instead.
+//! Their names carry a version: the columns of a view never change, a
+//! change means a new view next to the old one. The views are recreated
+//! whenever uhpm opens the database for writing, so they always match
+//! the migrated tables.
+//!
+//! | View                   | Columns                                                                                                      |
+//! |------------------------|--------------------------------------------------------------------------------------------------------------|
+//! | `uhpm_packages_v1`     | `name`, `version`, `current`, `author`, `source`, `checksum`, `origin`, `reason`, `installed_at`, `last_switched`, `last_run` |
+//! | `uhpm_files_v1`        | `name`, `version`, `path`, `link_mode`, `sha256`, `size`                                                     |
+//! | `uhpm_dependencies_v1` | `name`, `version`, `dependency`, `dependency_version`, `required_by`, `depth`                                |
+//! | `uhpm_history_v1`      | `id`, `timestamp`, `command`, `package`, `old_version`, `new_version`                                        |
+//!
+//! `current` is `0` or `1`; timestamps are RFC 3339; `link_mode`, `sha256`
+//! and `size` are `NULL` for files recorded by older versions of uhpm;
+//! `old_version` is `NULL` for a package the transaction installed,
+//! `new_version` for one it removed.
+//!
 //! ## Example
 //! ```rust,no_run
 //! use uhpm::db::PackageDB;
@@ -37,15 +76,90 @@
 //! println!("Installed packages: {:?}", packages);
 //! # });
 //! ```
+//!
+//! ## Other backends
+//! The package records themselves are also reachable through the
+//! [`storage::PackageStore`] trait, which `PackageDB` implements. Feature
+//! flags add further backends for embedders: `json-store` enables
+//! [`json_store::JsonStore`], a single JSON file for tiny environments
+//! without SQLite. [`storage::open`] opens the backend the library was
+//! built with. The `uhpm` binary always uses SQLite.
 
-use crate::package::{Package, Source};
-use crate::{debug, info};
+use crate::package::closure::ClosureEntry;
+use crate::package::retention::VersionUse;
+use crate::package::{InstallOrigin, InstallReason, LinkMode, Package, Provenance, Source};
+use crate::query::PackageRecord;
+use crate::{access, debug, info};
 use semver::Version;
 use sqlx::Row;
 use sqlx::SqlitePool;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
+#[cfg(feature = "json-store")]
+pub mod json_store;
+pub mod storage;
+
+/// Versioned read-only views for other tools, see the module docs
+const VIEWS: [(&str, &str); 4] = [
+    (
+        "uhpm_packages_v1",
+        "SELECT name, version, current, author, src AS source, checksum, origin, \
+         install_reason AS reason, installed_at, last_switched, last_run FROM packages",
+    ),
+    (
+        "uhpm_files_v1",
+        "SELECT package_name AS name, package_version AS version, file_path AS path, \
+         link_mode, sha256, size FROM installed_files",
+    ),
+    (
+        "uhpm_dependencies_v1",
+        "SELECT package_name AS name, package_version AS version, dependency_name AS dependency, \
+         dependency_version, required_by, depth FROM dependency_closure",
+    ),
+    (
+        "uhpm_history_v1",
+        "SELECT h.id, h.timestamp, h.command, c.package_name AS package, c.old_version, \
+         c.new_version FROM history h JOIN history_changes c ON c.history_id = h.id",
+    ),
+];
+
+/// Rows per multi-row `INSERT` into `installed_files`, kept well below
+/// SQLite's bound parameter limit.
+pub const FILE_INSERT_BATCH: usize = 300;
+
+/// Inserts installed file rows for a package version in batches and
+/// records `link_mode` as the mode of the package.
+async fn insert_files(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    pkg_name: &str,
+    pkg_version: &str,
+    installed_files: &[String],
+    link_mode: LinkMode,
+) -> Result<(), sqlx::Error> {
+    for batch in installed_files.chunks(FILE_INSERT_BATCH) {
+        debug!("db.add_package_full.adding_files", batch.len());
+        let mut builder = sqlx::QueryBuilder::<sqlx::Sqlite>::new(
+            "INSERT OR REPLACE INTO installed_files (package_name, package_version, file_path, link_mode) ",
+        );
+        builder.push_values(batch, |mut row, file_path| {
+            row.push_bind(pkg_name)
+                .push_bind(pkg_version)
+                .push_bind(file_path)
+                .push_bind(link_mode.as_str());
+        });
+        builder.build().execute(&mut **tx).await?;
+    }
+    sqlx::query("UPDATE packages SET link_mode = ? WHERE name = ?")
+        .bind(link_mode.as_str())
+        .bind(pkg_name)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
 /// Represents the UHPM package database.
 ///
 /// Internally, this is an SQLite database stored on disk,
@@ -68,7 +182,7 @@ impl PackageDB {
     pub fn new(path: &Path) -> Result<Self, std::io::Error> {
         debug!("db.new.creating", path);
 
-        if !path.exists() {
+        if !path.exists() && !access::is_read_only() {
             if let Some(parent) = path.parent() {
                 fs::create_dir_all(parent)?;
             }
@@ -88,13 +202,44 @@ impl PackageDB {
     ///
     /// # Errors
     /// Returns [`sqlx::Error`] if the database connection or table creation fails.
+    ///
+    /// In read-only mode (see [`crate::access`]) the database is opened
+    /// read-only and left as it is; a missing database is replaced by an
+    /// empty one in memory.
     pub async fn init(mut self) -> Result<Self, sqlx::Error> {
+        if access::is_read_only() {
+            return self.open_read_only().await;
+        }
         let path_str = self.path.to_str().expect("Invalid UTF-8 path");
         let db_url = format!("sqlite://{}", path_str);
         debug!("db.init.connecting", &db_url);
 
         self.pool = SqlitePool::connect(&db_url).await?;
+        self.create_schema().await?;
+
+        info!("db.init.success", &self.path);
+        Ok(self)
+    }
+
+    async fn open_read_only(mut self) -> Result<Self, sqlx::Error> {
+        debug!("db.init.read_only", &self.path);
+        if self.path.exists() {
+            let options = SqliteConnectOptions::new()
+                .filename(&self.path)
+                .read_only(true);
+            self.pool = SqlitePool::connect_with(options).await?;
+        } else {
+            self.pool = SqlitePoolOptions::new()
+                .max_connections(1)
+                .connect("sqlite::memory:")
+                .await?;
+            self.create_schema().await?;
+        }
+        Ok(self)
+    }
 
+    /// Creates missing tables and columns
+    async fn create_schema(&self) -> Result<(), sqlx::Error> {
         debug!("db.init.ensuring_tables");
         sqlx::query(
             r#"
@@ -138,8 +283,182 @@ impl PackageDB {
         .execute(&self.pool)
         .await?;
 
-        info!("db.init.success", &self.path);
-        Ok(self)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS dependency_closure (
+                package_name TEXT NOT NULL,
+                package_version TEXT NOT NULL,
+                dependency_name TEXT NOT NULL,
+                dependency_version TEXT NOT NULL,
+                required_by TEXT NOT NULL,
+                depth INTEGER NOT NULL,
+                installed BOOLEAN NOT NULL,
+                PRIMARY KEY(package_name, package_version, dependency_name)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS package_docs (
+                package_name TEXT NOT NULL,
+                package_version TEXT NOT NULL,
+                doc_path TEXT NOT NULL,
+                position INTEGER NOT NULL,
+                PRIMARY KEY(package_name, package_version, doc_path)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS package_manifest (
+                package_name TEXT NOT NULL,
+                package_version TEXT NOT NULL,
+                path TEXT NOT NULL,
+                sha256 TEXT NOT NULL,
+                PRIMARY KEY(package_name, package_version, path)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS usage_counters (
+                kind TEXT NOT NULL,
+                name TEXT NOT NULL,
+                count INTEGER NOT NULL,
+                last_used TEXT NOT NULL,
+                PRIMARY KEY(kind, name)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp TEXT NOT NULL,
+                command TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS history_changes (
+                history_id INTEGER NOT NULL,
+                package_name TEXT NOT NULL,
+                old_version TEXT,
+                new_version TEXT,
+                PRIMARY KEY(history_id, package_name),
+                FOREIGN KEY(history_id) REFERENCES history(id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS pins (
+                package_name TEXT PRIMARY KEY,
+                version TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        self.ensure_column("packages", "origin", "TEXT NOT NULL DEFAULT 'repo'")
+            .await?;
+        self.ensure_column("packages", "provenance", "TEXT").await?;
+        self.ensure_column("packages", "installed_at", "TEXT")
+            .await?;
+        self.ensure_column(
+            "packages",
+            "install_reason",
+            "TEXT NOT NULL DEFAULT 'explicit'",
+        )
+        .await?;
+        self.ensure_column("packages", "last_switched", "TEXT")
+            .await?;
+        self.ensure_column("packages", "last_run", "TEXT").await?;
+        self.ensure_column("packages", "link_mode", "TEXT").await?;
+        self.ensure_column("installed_files", "link_mode", "TEXT")
+            .await?;
+        self.ensure_column("installed_files", "sha256", "TEXT")
+            .await?;
+        self.ensure_column("installed_files", "size", "INTEGER")
+            .await?;
+        self.create_views().await
+    }
+
+    /// Recreates the [views](VIEWS) for other tools against the current
+    /// tables
+    async fn create_views(&self) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        for (name, select) in VIEWS {
+            sqlx::query(&format!("DROP VIEW IF EXISTS {}", name))
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query(&format!("CREATE VIEW {} AS {}", name, select))
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await
+    }
+
+    /// Path of the database file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Rebuilds the database file to reclaim the space of deleted rows;
+    /// returns its size before and after in bytes
+    pub async fn vacuum(&self) -> Result<(u64, u64), sqlx::Error> {
+        let size = || std::fs::metadata(&self.path).map_or(0, |m| m.len());
+        let before = size();
+        sqlx::query("VACUUM").execute(&self.pool).await?;
+        Ok((before, size()))
+    }
+
+    /// Adds a column to an existing table if it is missing.
+    ///
+    /// Used to migrate databases created by older UHPM versions.
+    async fn ensure_column(
+        &self,
+        table: &str,
+        column: &str,
+        definition: &str,
+    ) -> Result<(), sqlx::Error> {
+        let rows = sqlx::query(&format!("PRAGMA table_info({})", table))
+            .fetch_all(&self.pool)
+            .await?;
+        let exists = rows
+            .iter()
+            .any(|row| row.get::<String, _>("name") == column);
+
+        if !exists {
+            debug!("db.ensure_column.adding", table, column);
+            sqlx::query(&format!(
+                "ALTER TABLE {} ADD COLUMN {} {}",
+                table, column, definition
+            ))
+            .execute(&self.pool)
+            .await?;
+        }
+        Ok(())
     }
 
     /// Returns a reference to the connection pool.
@@ -165,10 +484,15 @@ impl PackageDB {
     }
 
     /// Adds a package with its dependencies and installed files.
+    ///
+    /// Everything is written in one transaction; installed files are
+    /// inserted in batches of [`FILE_INSERT_BATCH`] rows, each tagged with
+    /// the link mode they were placed with.
     pub async fn add_package_full(
         &self,
         pkg: &Package,
         installed_files: &[String],
+        link_mode: LinkMode,
     ) -> Result<(), sqlx::Error> {
         info!(
             "db.add_package_full.adding",
@@ -177,7 +501,21 @@ impl PackageDB {
             installed_files.len()
         );
 
-        self.add_package(pkg).await?;
+        let version = pkg.version().to_string();
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            "INSERT OR REPLACE INTO packages (name, version, author, src, checksum, current, installed_at, install_reason) VALUES (?, ?, ?, ?, ?, 0, ?, COALESCE((SELECT install_reason FROM packages WHERE name = ? LIMIT 1), 'explicit'))"
+        )
+        .bind(pkg.name())
+        .bind(&version)
+        .bind(pkg.author())
+        .bind(pkg.src().as_str())
+        .bind(pkg.checksum())
+        .bind(chrono::Local::now().to_rfc3339())
+        .bind(pkg.name())
+        .execute(&mut *tx)
+        .await?;
 
         // Dependencies
         for (dep_name, dep_version) in pkg.dependencies() {
@@ -188,28 +526,372 @@ impl PackageDB {
             sqlx::query(
                 "INSERT OR REPLACE INTO dependencies (package_name, dependency_name, dependency_version) VALUES (?, ?, ?)"
             )
-            .bind(&pkg.name())
+            .bind(pkg.name())
             .bind(dep_name)
-            .bind(&dep_version.to_string())
+            .bind(dep_version.to_string())
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        insert_files(&mut tx, pkg.name(), &version, installed_files, link_mode).await?;
+
+        tx.commit().await?;
+        info!("db.add_package_full.success", pkg.name());
+        Ok(())
+    }
+
+    /// Replaces the recorded installed files of a package version.
+    pub async fn set_installed_files(
+        &self,
+        pkg_name: &str,
+        pkg_version: &str,
+        installed_files: &[String],
+        link_mode: LinkMode,
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM installed_files WHERE package_name = ? AND package_version = ?")
+            .bind(pkg_name)
+            .bind(pkg_version)
+            .execute(&mut *tx)
+            .await?;
+        insert_files(&mut tx, pkg_name, pkg_version, installed_files, link_mode).await?;
+        tx.commit().await
+    }
+
+    /// Replaces the recorded dependency closure of a package version.
+    ///
+    /// Runs in a single transaction, so a closure is either stored
+    /// completely or not at all.
+    pub async fn set_dependency_closure(
+        &self,
+        pkg_name: &str,
+        pkg_version: &str,
+        closure: &[ClosureEntry],
+    ) -> Result<(), sqlx::Error> {
+        debug!(
+            "db.set_dependency_closure.storing",
+            pkg_name,
+            pkg_version,
+            closure.len()
+        );
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "DELETE FROM dependency_closure WHERE package_name = ? AND package_version = ?",
+        )
+        .bind(pkg_name)
+        .bind(pkg_version)
+        .execute(&mut *tx)
+        .await?;
+        for entry in closure {
+            sqlx::query(
+                "INSERT INTO dependency_closure (package_name, package_version, dependency_name, dependency_version, required_by, depth, installed) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(pkg_name)
+            .bind(pkg_version)
+            .bind(&entry.name)
+            .bind(&entry.version)
+            .bind(&entry.required_by)
+            .bind(entry.depth)
+            .bind(entry.installed)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+
+    /// Records the SHA-256 and size of installed files of a package version.
+    pub async fn set_file_hashes(
+        &self,
+        pkg_name: &str,
+        pkg_version: &str,
+        hashes: &[(String, String, u64)],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        for (file_path, sha256, size) in hashes {
+            sqlx::query(
+                "UPDATE installed_files SET sha256 = ?, size = ? WHERE package_name = ? AND package_version = ? AND file_path = ?",
+            )
+            .bind(sha256)
+            .bind(*size as i64)
+            .bind(pkg_name)
+            .bind(pkg_version)
+            .bind(file_path)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+
+    /// Changes the recorded path of an installed file.
+    pub async fn rename_installed_file(
+        &self,
+        pkg_name: &str,
+        pkg_version: &str,
+        old_path: &str,
+        new_path: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE installed_files SET file_path = ? WHERE package_name = ? AND package_version = ? AND file_path = ?",
+        )
+        .bind(new_path)
+        .bind(pkg_name)
+        .bind(pkg_version)
+        .bind(old_path)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Forgets that packages other than `pkg_name` installed `file_path`,
+    /// after `pkg_name` took the file over.
+    pub async fn disown_file(&self, file_path: &str, pkg_name: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM installed_files WHERE file_path = ? AND package_name != ?")
+            .bind(file_path)
+            .bind(pkg_name)
             .execute(&self.pool)
             .await?;
+        Ok(())
+    }
+
+    /// Returns the dependency closure recorded when a package version was installed.
+    pub async fn get_dependency_closure(
+        &self,
+        pkg_name: &str,
+        pkg_version: &str,
+    ) -> Result<Vec<ClosureEntry>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT dependency_name, dependency_version, required_by, depth, installed
+             FROM dependency_closure
+             WHERE package_name = ? AND package_version = ?
+             ORDER BY depth, dependency_name",
+        )
+        .bind(pkg_name)
+        .bind(pkg_version)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| ClosureEntry {
+                name: row.get("dependency_name"),
+                version: row.get("dependency_version"),
+                required_by: row.get("required_by"),
+                depth: row.get("depth"),
+                installed: row.get("installed"),
+            })
+            .collect())
+    }
+
+    /// Replaces the recorded documentation paths of a package version.
+    ///
+    /// Paths are relative to the package directory; their order is kept.
+    pub async fn set_package_docs(
+        &self,
+        pkg_name: &str,
+        pkg_version: &str,
+        docs: &[String],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM package_docs WHERE package_name = ? AND package_version = ?")
+            .bind(pkg_name)
+            .bind(pkg_version)
+            .execute(&mut *tx)
+            .await?;
+        for (position, doc) in docs.iter().enumerate() {
+            sqlx::query(
+                "INSERT OR REPLACE INTO package_docs (package_name, package_version, doc_path, position) VALUES (?, ?, ?, ?)",
+            )
+            .bind(pkg_name)
+            .bind(pkg_version)
+            .bind(doc)
+            .bind(position as i64)
+            .execute(&mut *tx)
+            .await?;
         }
+        tx.commit().await
+    }
+
+    /// Replaces the manifest of a package version: the SHA-256 of every file
+    /// in its package directory, by path relative to it.
+    pub async fn set_package_manifest(
+        &self,
+        pkg_name: &str,
+        pkg_version: &str,
+        entries: &[(String, String)],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM package_manifest WHERE package_name = ? AND package_version = ?")
+            .bind(pkg_name)
+            .bind(pkg_version)
+            .execute(&mut *tx)
+            .await?;
+        for (path, sha256) in entries {
+            sqlx::query(
+                "INSERT INTO package_manifest (package_name, package_version, path, sha256) VALUES (?, ?, ?, ?)",
+            )
+            .bind(pkg_name)
+            .bind(pkg_version)
+            .bind(path)
+            .bind(sha256)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+
+    /// Returns the manifest of a package version as `(path, sha256)`, by path.
+    pub async fn get_package_manifest(
+        &self,
+        pkg_name: &str,
+        pkg_version: &str,
+    ) -> Result<Vec<(String, String)>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT path, sha256 FROM package_manifest WHERE package_name = ? AND package_version = ? ORDER BY path",
+        )
+        .bind(pkg_name)
+        .bind(pkg_version)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.get("path"), row.get("sha256")))
+            .collect())
+    }
+
+    /// Returns the documentation paths recorded for a package version.
+    pub async fn get_package_docs(
+        &self,
+        pkg_name: &str,
+        pkg_version: &str,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT doc_path FROM package_docs WHERE package_name = ? AND package_version = ? ORDER BY position",
+        )
+        .bind(pkg_name)
+        .bind(pkg_version)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(|row| row.get("doc_path")).collect())
+    }
+
+    /// Increments a local usage counter, e.g. kind `command`, name `install`.
+    ///
+    /// `last_used` is an RFC 3339 timestamp.
+    pub async fn record_usage(
+        &self,
+        kind: &str,
+        name: &str,
+        last_used: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO usage_counters (kind, name, count, last_used) VALUES (?, ?, 1, ?)
+             ON CONFLICT(kind, name) DO UPDATE SET count = count + 1, last_used = excluded.last_used",
+        )
+        .bind(kind)
+        .bind(name)
+        .bind(last_used)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the usage counters of a kind as `(name, count, last_used)`,
+    /// most used first.
+    pub async fn get_usage(&self, kind: &str) -> Result<Vec<(String, i64, String)>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT name, count, last_used FROM usage_counters WHERE kind = ? ORDER BY count DESC, name",
+        )
+        .bind(kind)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.get("name"), row.get("count"), row.get("last_used")))
+            .collect())
+    }
+
+    /// Deletes all usage counters.
+    pub async fn clear_usage(&self) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM usage_counters")
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Records a transaction with its package changes as
+    /// `(name, old_version, new_version)` and returns its id.
+    ///
+    /// `timestamp` is an RFC 3339 timestamp.
+    pub async fn add_history(
+        &self,
+        timestamp: &str,
+        command: &str,
+        changes: &[(String, Option<String>, Option<String>)],
+    ) -> Result<i64, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let id = sqlx::query("INSERT INTO history (timestamp, command) VALUES (?, ?)")
+            .bind(timestamp)
+            .bind(command)
+            .execute(&mut *tx)
+            .await?
+            .last_insert_rowid();
+        for (name, old_version, new_version) in changes {
+            sqlx::query(
+                "INSERT INTO history_changes (history_id, package_name, old_version, new_version) VALUES (?, ?, ?, ?)",
+            )
+            .bind(id)
+            .bind(name)
+            .bind(old_version)
+            .bind(new_version)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    /// Returns all transactions as `(id, timestamp, command)`, newest first.
+    pub async fn list_history(&self) -> Result<Vec<(i64, String, String)>, sqlx::Error> {
+        let rows = sqlx::query("SELECT id, timestamp, command FROM history ORDER BY id DESC")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.get("id"), row.get("timestamp"), row.get("command")))
+            .collect())
+    }
 
-        // Installed files
-        for file_path in installed_files {
-            debug!("db.add_package_full.adding_file", file_path);
-            sqlx::query(
-                "INSERT OR REPLACE INTO installed_files (package_name, package_version, file_path) VALUES (?, ?, ?)",
-            )
-            .bind(&pkg.name())
-            .bind(&pkg.version().to_string())
-            .bind(file_path)
-            .execute(&self.pool)
+    /// Returns the `(timestamp, command)` of a transaction.
+    pub async fn get_history(&self, id: i64) -> Result<Option<(String, String)>, sqlx::Error> {
+        let row = sqlx::query("SELECT timestamp, command FROM history WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
             .await?;
-        }
+        Ok(row.map(|row| (row.get("timestamp"), row.get("command"))))
+    }
 
-        info!("db.add_package_full.success", pkg.name());
-        Ok(())
+    /// Returns the package changes of a transaction as
+    /// `(name, old_version, new_version)`, by name.
+    pub async fn get_history_changes(
+        &self,
+        id: i64,
+    ) -> Result<Vec<(String, Option<String>, Option<String>)>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT package_name, old_version, new_version FROM history_changes WHERE history_id = ? ORDER BY package_name",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                (
+                    row.get("package_name"),
+                    row.get("old_version"),
+                    row.get("new_version"),
+                )
+            })
+            .collect())
     }
 
     /// Returns all files installed by a package (specific version).
@@ -240,6 +922,102 @@ impl PackageDB {
         Ok(files)
     }
 
+    /// Returns the files installed by a package version with their link mode.
+    ///
+    /// Files recorded before link modes were tracked have no mode.
+    pub async fn get_installed_files_with_mode(
+        &self,
+        pkg_name: &str,
+        pkg_version: &str,
+    ) -> Result<Vec<(String, Option<LinkMode>)>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT file_path, link_mode FROM installed_files WHERE package_name = ? AND package_version = ?",
+        )
+        .bind(pkg_name)
+        .bind(pkg_version)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let mode = row
+                    .get::<Option<String>, _>("link_mode")
+                    .and_then(|m| LinkMode::parse(&m));
+                (row.get::<String, _>("file_path"), mode)
+            })
+            .collect())
+    }
+
+    /// Returns the SHA-256 and size recorded for the installed files of a
+    /// package version, by path.
+    ///
+    /// Files installed before hashes were recorded, and directories, are
+    /// missing.
+    pub async fn get_file_hashes(
+        &self,
+        pkg_name: &str,
+        pkg_version: &str,
+    ) -> Result<BTreeMap<String, (String, u64)>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT file_path, sha256, size FROM installed_files WHERE package_name = ? AND package_version = ? AND sha256 IS NOT NULL",
+        )
+        .bind(pkg_name)
+        .bind(pkg_version)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let size = row.get::<Option<i64>, _>("size").unwrap_or(0);
+                (
+                    row.get::<String, _>("file_path"),
+                    (row.get::<String, _>("sha256"), size as u64),
+                )
+            })
+            .collect())
+    }
+
+    /// Returns how the files of a package version were placed, if recorded.
+    pub async fn get_link_mode(
+        &self,
+        pkg_name: &str,
+        pkg_version: &str,
+    ) -> Result<Option<LinkMode>, sqlx::Error> {
+        let row = sqlx::query(
+            "SELECT link_mode FROM installed_files WHERE package_name = ? AND package_version = ? AND link_mode IS NOT NULL LIMIT 1",
+        )
+        .bind(pkg_name)
+        .bind(pkg_version)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.and_then(|r| LinkMode::parse(&r.get::<String, _>("link_mode"))))
+    }
+
+    /// Returns the mode the files of a package were last placed with, if
+    /// recorded.
+    ///
+    /// Databases from before the mode was recorded per package fall back
+    /// to the mode of any of its installed files.
+    pub async fn get_package_link_mode(
+        &self,
+        pkg_name: &str,
+    ) -> Result<Option<LinkMode>, sqlx::Error> {
+        let row = sqlx::query(
+            "SELECT COALESCE(
+                (SELECT link_mode FROM packages WHERE name = ? AND link_mode IS NOT NULL LIMIT 1),
+                (SELECT link_mode FROM installed_files WHERE package_name = ? AND link_mode IS NOT NULL LIMIT 1)
+            ) AS link_mode",
+        )
+        .bind(pkg_name)
+        .bind(pkg_name)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row
+            .get::<Option<String>, _>("link_mode")
+            .and_then(|mode| LinkMode::parse(&mode)))
+    }
+
     /// Returns all files installed by all versions of a package.
     pub async fn get_all_installed_files(
         &self,
@@ -259,6 +1037,38 @@ impl PackageDB {
         Ok(files)
     }
 
+    /// Returns every file path recorded for any installed package.
+    pub async fn list_all_installed_files(&self) -> Result<Vec<String>, sqlx::Error> {
+        let rows = sqlx::query("SELECT DISTINCT file_path FROM installed_files")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| row.get::<String, _>("file_path"))
+            .collect())
+    }
+
+    /// Returns the package name and version that installed the given file, if any.
+    pub async fn find_file_owner(
+        &self,
+        file_path: &str,
+    ) -> Result<Option<(String, String)>, sqlx::Error> {
+        debug!("db.find_file_owner.fetching", file_path);
+        let row = sqlx::query(
+            "SELECT package_name, package_version FROM installed_files WHERE file_path = ? LIMIT 1",
+        )
+        .bind(file_path)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|r| {
+            (
+                r.get::<String, _>("package_name"),
+                r.get::<String, _>("package_version"),
+            )
+        }))
+    }
+
     /// Removes a specific version of a package and its associated data from the database.
     pub async fn remove_package_version(
         &self,
@@ -275,6 +1085,23 @@ impl PackageDB {
             .bind(pkg_name)
             .execute(&self.pool)
             .await?;
+        sqlx::query("DELETE FROM package_docs WHERE package_name = ? AND package_version = ?")
+            .bind(pkg_name)
+            .bind(pkg_version)
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("DELETE FROM package_manifest WHERE package_name = ? AND package_version = ?")
+            .bind(pkg_name)
+            .bind(pkg_version)
+            .execute(&self.pool)
+            .await?;
+        sqlx::query(
+            "DELETE FROM dependency_closure WHERE package_name = ? AND package_version = ?",
+        )
+        .bind(pkg_name)
+        .bind(pkg_version)
+        .execute(&self.pool)
+        .await?;
         sqlx::query("DELETE FROM packages WHERE name = ? AND version = ?")
             .bind(pkg_name)
             .bind(pkg_version)
@@ -284,7 +1111,8 @@ impl PackageDB {
         Ok(())
     }
 
-    /// Removes all versions of a package and its associated data from the database.
+    /// Removes all versions of a package and its associated data, including
+    /// its pin, from the database.
     pub async fn remove_package(&self, pkg_name: &str) -> Result<(), sqlx::Error> {
         info!("db.remove_package.removing", pkg_name);
         sqlx::query("DELETE FROM installed_files WHERE package_name = ?")
@@ -295,6 +1123,22 @@ impl PackageDB {
             .bind(pkg_name)
             .execute(&self.pool)
             .await?;
+        sqlx::query("DELETE FROM dependency_closure WHERE package_name = ?")
+            .bind(pkg_name)
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("DELETE FROM package_docs WHERE package_name = ?")
+            .bind(pkg_name)
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("DELETE FROM package_manifest WHERE package_name = ?")
+            .bind(pkg_name)
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("DELETE FROM pins WHERE package_name = ?")
+            .bind(pkg_name)
+            .execute(&self.pool)
+            .await?;
         sqlx::query("DELETE FROM packages WHERE name = ?")
             .bind(pkg_name)
             .execute(&self.pool)
@@ -469,17 +1313,204 @@ impl PackageDB {
         Ok(Some(package))
     }
 
-    /// Sets a specific version of a package as the current version.
-    pub async fn set_current_version(
+    /// Records where a specific package version was installed from.
+    pub async fn set_package_origin(
         &self,
         pkg_name: &str,
         version: &str,
+        origin: InstallOrigin,
     ) -> Result<(), sqlx::Error> {
-        info!("db.set_current_version.setting", version, pkg_name);
-        sqlx::query("UPDATE packages SET current = 0 WHERE name = ?")
+        debug!(
+            "db.set_package_origin.setting",
+            pkg_name,
+            version,
+            origin.as_str()
+        );
+        sqlx::query("UPDATE packages SET origin = ? WHERE name = ? AND version = ?")
+            .bind(origin.as_str())
+            .bind(pkg_name)
+            .bind(version)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Returns the origin of the current version of a package, if installed.
+    pub async fn get_package_origin(
+        &self,
+        pkg_name: &str,
+    ) -> Result<Option<InstallOrigin>, sqlx::Error> {
+        let row = sqlx::query("SELECT origin FROM packages WHERE name = ? AND current = 1")
+            .bind(pkg_name)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|r| InstallOrigin::from_str_lossy(&r.get::<String, _>("origin"))))
+    }
+
+    /// Records the exact provenance of a package version, stored as JSON.
+    pub async fn set_package_provenance(
+        &self,
+        pkg_name: &str,
+        version: &str,
+        provenance: &Provenance,
+    ) -> Result<(), sqlx::Error> {
+        let json =
+            serde_json::to_string(provenance).map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+        debug!(
+            "db.set_package_provenance.setting",
+            pkg_name, version, &json
+        );
+        sqlx::query(
+            "UPDATE packages SET provenance = ?, origin = ? WHERE name = ? AND version = ?",
+        )
+        .bind(json)
+        .bind(provenance.origin().as_str())
+        .bind(pkg_name)
+        .bind(version)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the recorded provenance of a package version.
+    ///
+    /// Packages installed before provenance was tracked return `None`.
+    pub async fn get_package_provenance(
+        &self,
+        pkg_name: &str,
+        version: &str,
+    ) -> Result<Option<Provenance>, sqlx::Error> {
+        let row = sqlx::query("SELECT provenance FROM packages WHERE name = ? AND version = ?")
+            .bind(pkg_name)
+            .bind(version)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row
+            .and_then(|r| r.get::<Option<String>, _>("provenance"))
+            .and_then(|json| serde_json::from_str(&json).ok()))
+    }
+
+    /// Returns when a package version was installed, as an RFC 3339 timestamp.
+    ///
+    /// Versions installed before install times were recorded return `None`.
+    pub async fn get_installed_at(
+        &self,
+        pkg_name: &str,
+        version: &str,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let row = sqlx::query("SELECT installed_at FROM packages WHERE name = ? AND version = ?")
+            .bind(pkg_name)
+            .bind(version)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.and_then(|r| r.get::<Option<String>, _>("installed_at")))
+    }
+
+    /// Records why a package is installed, for all of its versions.
+    pub async fn set_install_reason(
+        &self,
+        pkg_name: &str,
+        reason: InstallReason,
+    ) -> Result<(), sqlx::Error> {
+        debug!("db.set_install_reason.setting", pkg_name, reason.as_str());
+        sqlx::query("UPDATE packages SET install_reason = ? WHERE name = ?")
+            .bind(reason.as_str())
+            .bind(pkg_name)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Returns why a package is installed, `None` if it is not.
+    pub async fn get_install_reason(
+        &self,
+        pkg_name: &str,
+    ) -> Result<Option<InstallReason>, sqlx::Error> {
+        let row = sqlx::query("SELECT install_reason FROM packages WHERE name = ? LIMIT 1")
+            .bind(pkg_name)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|r| InstallReason::from_str_lossy(&r.get::<String, _>("install_reason"))))
+    }
+
+    /// Returns the install reason of every installed package.
+    pub async fn list_install_reasons(
+        &self,
+    ) -> Result<BTreeMap<String, InstallReason>, sqlx::Error> {
+        let rows = sqlx::query("SELECT DISTINCT name, install_reason FROM packages")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows
+            .into_iter()
+            .map(|r| {
+                (
+                    r.get::<String, _>("name"),
+                    InstallReason::from_str_lossy(&r.get::<String, _>("install_reason")),
+                )
+            })
+            .collect())
+    }
+
+    /// Holds a package at `version`, an exact version or a constraint,
+    /// replacing an earlier pin.
+    pub async fn set_pin(&self, pkg_name: &str, version: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("INSERT OR REPLACE INTO pins (package_name, version) VALUES (?, ?)")
+            .bind(pkg_name)
+            .bind(version)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Removes the pin of a package; returns `false` if it had none.
+    pub async fn remove_pin(&self, pkg_name: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM pins WHERE package_name = ?")
             .bind(pkg_name)
             .execute(&self.pool)
             .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Returns every pin as `(package, version)`, sorted by package.
+    pub async fn list_pins(&self) -> Result<Vec<(String, String)>, sqlx::Error> {
+        let rows = sqlx::query("SELECT package_name, version FROM pins ORDER BY package_name")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows
+            .into_iter()
+            .map(|r| (r.get("package_name"), r.get("version")))
+            .collect())
+    }
+
+    /// Returns every recorded dependency as `(package, dependency)` names.
+    pub async fn list_dependency_edges(&self) -> Result<Vec<(String, String)>, sqlx::Error> {
+        let rows = sqlx::query("SELECT package_name, dependency_name FROM dependencies")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows
+            .into_iter()
+            .map(|r| (r.get("package_name"), r.get("dependency_name")))
+            .collect())
+    }
+
+    /// Sets a specific version of a package as the current version.
+    ///
+    /// The previous and the new current version are stamped as used now.
+    pub async fn set_current_version(
+        &self,
+        pkg_name: &str,
+        version: &str,
+    ) -> Result<(), sqlx::Error> {
+        info!("db.set_current_version.setting", version, pkg_name);
+        let now = chrono::Local::now().to_rfc3339();
+        sqlx::query(
+            "UPDATE packages SET last_switched = CASE WHEN current = 1 OR version = ? THEN ? ELSE last_switched END, current = 0 WHERE name = ?",
+        )
+        .bind(version)
+        .bind(&now)
+        .bind(pkg_name)
+        .execute(&self.pool)
+        .await?;
 
         sqlx::query("UPDATE packages SET current = 1 WHERE name = ? AND version = ?")
             .bind(pkg_name)
@@ -491,6 +1522,75 @@ impl PackageDB {
         Ok(())
     }
 
+    /// Records that a package version was started by `uhpm run`.
+    pub async fn set_last_run(
+        &self,
+        pkg_name: &str,
+        version: &str,
+        at: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE packages SET last_run = ? WHERE name = ? AND version = ?")
+            .bind(at)
+            .bind(pkg_name)
+            .bind(version)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Lists all installed versions with the latest of their install,
+    /// switch and run times.
+    pub async fn list_version_use(&self) -> Result<Vec<VersionUse>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT name, version, current, installed_at, last_switched, last_run FROM packages",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|r| {
+                let version = Version::parse(&r.get::<String, _>("version")).ok()?;
+                let last_used = ["installed_at", "last_switched", "last_run"]
+                    .iter()
+                    .filter_map(|column| r.get::<Option<String>, _>(*column))
+                    .filter_map(|ts| chrono::DateTime::parse_from_rfc3339(&ts).ok())
+                    .max();
+                Some(VersionUse {
+                    name: r.get("name"),
+                    version,
+                    current: r.get("current"),
+                    last_used,
+                })
+            })
+            .collect())
+    }
+
+    /// Lists all installed versions with their metadata and the number of
+    /// installed files, for `uhpm query`.
+    pub async fn list_package_records(&self) -> Result<Vec<PackageRecord>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT p.name, p.version, p.current, p.author, p.origin, p.install_reason, p.installed_at, p.last_run, \
+             (SELECT COUNT(*) FROM installed_files f WHERE f.package_name = p.name AND f.package_version = p.version) AS files \
+             FROM packages p",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|r| PackageRecord {
+                name: r.get("name"),
+                version: r.get("version"),
+                current: r.get("current"),
+                author: r.get("author"),
+                origin: r.get("origin"),
+                reason: r.get("install_reason"),
+                installed_at: r.get("installed_at"),
+                last_run: r.get("last_run"),
+                files: r.get::<i64, _>("files") as u64,
+            })
+            .collect())
+    }
+
     /// Retrieves a specific version of a package by name and version string.
     pub async fn get_package_by_version(
         &self,
@@ -549,3 +1649,109 @@ impl PackageDB {
         Ok(Some(package))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_views_survive_reopening() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("packages.db");
+        let db = PackageDB::new(&path).unwrap().init().await.unwrap();
+        db.add_history(
+            "2025-06-01T12:00:00+00:00",
+            "install jq",
+            &[("jq".to_string(), None, Some("1.7.0".to_string()))],
+        )
+        .await
+        .unwrap();
+        drop(db);
+
+        let db = PackageDB::new(&path).unwrap().init().await.unwrap();
+        for (name, _) in VIEWS {
+            sqlx::query(&format!("SELECT * FROM {}", name))
+                .fetch_all(&db.pool)
+                .await
+                .unwrap();
+        }
+        let row = sqlx::query("SELECT command, package, old_version FROM uhpm_history_v1")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(row.get::<String, _>("package"), "jq");
+        assert_eq!(row.get::<Option<String>, _>("old_version"), None);
+        assert_eq!(db.path(), path);
+    }
+
+    #[tokio::test]
+    async fn test_package_link_mode_follows_last_placement() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = PackageDB::new(&tmp.path().join("packages.db"))
+            .unwrap()
+            .init()
+            .await
+            .unwrap();
+        assert_eq!(db.get_package_link_mode("tool").await.unwrap(), None);
+
+        let pkg = Package::new(
+            "tool",
+            Version::new(1, 0, 0),
+            "a",
+            Source::Raw(String::new()),
+            "",
+            Vec::new(),
+        );
+        db.add_package_full(&pkg, &[], LinkMode::Copy)
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_package_link_mode("tool").await.unwrap(),
+            Some(LinkMode::Copy)
+        );
+        db.set_installed_files(
+            "tool",
+            "1.0.0",
+            &["/bin/tool".to_string()],
+            LinkMode::Symlink,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            db.get_package_link_mode("tool").await.unwrap(),
+            Some(LinkMode::Symlink)
+        );
+        assert_eq!(
+            LinkMode::resolve(&db, "tool", Some(LinkMode::Copy))
+                .await
+                .unwrap(),
+            LinkMode::Copy
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pins() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = PackageDB::new(&tmp.path().join("packages.db"))
+            .unwrap()
+            .init()
+            .await
+            .unwrap();
+        db.set_pin("jq", "1.7.0").await.unwrap();
+        db.set_pin("fd", "~8.2").await.unwrap();
+        db.set_pin("jq", "1.7.1").await.unwrap();
+        assert_eq!(
+            db.list_pins().await.unwrap(),
+            [
+                ("fd".to_string(), "~8.2".to_string()),
+                ("jq".to_string(), "1.7.1".to_string())
+            ]
+        );
+        assert!(db.remove_pin("fd").await.unwrap());
+        assert!(!db.remove_pin("fd").await.unwrap());
+        assert_eq!(db.list_pins().await.unwrap().len(), 1);
+
+        db.remove_package("jq").await.unwrap();
+        assert!(db.list_pins().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,236 @@
+//! # JSON Package Store
+//!
+//! A [`PackageStore`] kept in a single JSON file, for embedded
+//! environments too small for SQLite. Enabled by the `json-store` feature.
+//!
+//! The whole file is read when the store is opened and rewritten after
+//! every change, through a temporary file renamed into place, so a crash
+//! never leaves it half written. That is fine for the few dozen packages
+//! such environments hold, not for large installations.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use uhpm::db::json_store::JsonStore;
+//! use uhpm::db::storage::PackageStore;
+//! use std::path::Path;
+//!
+//! # tokio_test::block_on(async {
+//! let store = JsonStore::open(Path::new("/tmp/uhpm.json")).unwrap();
+//! let packages = store.list_packages().await.unwrap();
+//! # });
+//! ```
+
+use crate::db::storage::PackageStore;
+use crate::error::StorageError;
+use crate::package::{LinkMode, Package, Source};
+use async_trait::async_trait;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tokio::sync::Mutex;
+
+/// A package version as stored in the file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Record {
+    name: String,
+    version: String,
+    author: String,
+    src: Source,
+    checksum: String,
+    #[serde(default)]
+    dependencies: Vec<(String, String)>,
+    #[serde(default)]
+    current: bool,
+    #[serde(default)]
+    files: Vec<String>,
+    link_mode: LinkMode,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct State {
+    packages: Vec<Record>,
+}
+
+/// Package store in a JSON file
+pub struct JsonStore {
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+impl JsonStore {
+    /// Opens the store at `path`; a missing file is an empty store
+    pub fn open(path: &Path) -> Result<Self, StorageError> {
+        let state = match fs::read(path) {
+            Ok(data) => {
+                serde_json::from_slice(&data).map_err(|e| StorageError::Format(e.to_string()))?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => State::default(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Path of the store file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn save(&self, state: &State) -> Result<(), StorageError> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let data =
+            serde_json::to_vec_pretty(state).map_err(|e| StorageError::Format(e.to_string()))?;
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PackageStore for JsonStore {
+    async fn add_package(
+        &self,
+        pkg: &Package,
+        installed_files: &[String],
+        link_mode: LinkMode,
+    ) -> Result<(), StorageError> {
+        let mut state = self.state.lock().await;
+        let version = pkg.version().to_string();
+        state
+            .packages
+            .retain(|r| !(r.name == pkg.name() && r.version == version));
+        for record in state.packages.iter_mut().filter(|r| r.name == pkg.name()) {
+            record.link_mode = link_mode;
+        }
+        state.packages.push(Record {
+            name: pkg.name().to_string(),
+            version,
+            author: pkg.author().to_string(),
+            src: pkg.src().clone(),
+            checksum: pkg.checksum().to_string(),
+            dependencies: pkg
+                .dependencies()
+                .into_iter()
+                .map(|(name, version)| (name, version.to_string()))
+                .collect(),
+            current: false,
+            files: installed_files.to_vec(),
+            link_mode,
+        });
+        self.save(&state)
+    }
+
+    async fn remove_package_version(&self, name: &str, version: &str) -> Result<(), StorageError> {
+        let mut state = self.state.lock().await;
+        state
+            .packages
+            .retain(|r| !(r.name == name && r.version == version));
+        self.save(&state)
+    }
+
+    async fn remove_package(&self, name: &str) -> Result<(), StorageError> {
+        let mut state = self.state.lock().await;
+        state.packages.retain(|r| r.name != name);
+        self.save(&state)
+    }
+
+    async fn list_packages(&self) -> Result<Vec<(String, String, bool)>, StorageError> {
+        let state = self.state.lock().await;
+        Ok(state
+            .packages
+            .iter()
+            .map(|r| (r.name.clone(), r.version.clone(), r.current))
+            .collect())
+    }
+
+    async fn set_current_version(&self, name: &str, version: &str) -> Result<(), StorageError> {
+        let mut state = self.state.lock().await;
+        for record in state.packages.iter_mut().filter(|r| r.name == name) {
+            record.current = record.version == version;
+        }
+        self.save(&state)
+    }
+
+    async fn get_current_package(&self, name: &str) -> Result<Option<Package>, StorageError> {
+        let state = self.state.lock().await;
+        Ok(state
+            .packages
+            .iter()
+            .find(|r| r.name == name && r.current)
+            .map(|r| {
+                Package::new(
+                    r.name.clone(),
+                    Version::parse(&r.version).unwrap_or_else(|_| Version::new(0, 0, 0)),
+                    r.author.clone(),
+                    r.src.clone(),
+                    r.checksum.clone(),
+                    r.dependencies
+                        .iter()
+                        .filter_map(|(name, version)| {
+                            Some((name.clone(), Version::parse(version).ok()?))
+                        })
+                        .collect(),
+                )
+            }))
+    }
+
+    async fn get_installed_files(
+        &self,
+        name: &str,
+        version: &str,
+    ) -> Result<Vec<String>, StorageError> {
+        let state = self.state.lock().await;
+        Ok(state
+            .packages
+            .iter()
+            .find(|r| r.name == name && r.version == version)
+            .map(|r| r.files.clone())
+            .unwrap_or_default())
+    }
+
+    async fn get_link_mode(&self, name: &str) -> Result<Option<LinkMode>, StorageError> {
+        let state = self.state.lock().await;
+        Ok(state
+            .packages
+            .iter()
+            .find(|r| r.name == name)
+            .map(|r| r.link_mode))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_json_store() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("store/packages.json");
+        let store = JsonStore::open(&path).unwrap();
+        crate::db::storage::exercise(&store).await;
+
+        let src = Source::Url("https://example.org/kept-2.0.0.uhp".to_string());
+        let pkg = Package::new("kept", Version::new(2, 0, 0), "a", src.clone(), "", vec![]);
+        store.add_package(&pkg, &[], LinkMode::Copy).await.unwrap();
+        store.set_current_version("kept", "2.0.0").await.unwrap();
+        let reopened = JsonStore::open(&path).unwrap();
+        assert_eq!(
+            reopened.list_packages().await.unwrap(),
+            [("kept".to_string(), "2.0.0".to_string(), true)]
+        );
+        let kept = reopened.get_current_package("kept").await.unwrap().unwrap();
+        assert_eq!(kept.src(), &src);
+
+        fs::write(&path, "not json").unwrap();
+        assert!(matches!(
+            JsonStore::open(&path),
+            Err(StorageError::Format(_))
+        ));
+    }
+}
@@ -0,0 +1,238 @@
+//! # Package Storage Backends
+//!
+//! [`PackageStore`] is the part of the package database every backend
+//! provides: the installed package versions with their metadata, which
+//! one is current, the files they placed and how. [`PackageDB`] implements
+//! it on SQLite and stays the default; other backends are enabled by
+//! feature flags (see the [module docs](crate::db)).
+//!
+//! Everything beyond that, such as history, usage counters, manifests or
+//! the views for other tools, is only available on [`PackageDB`].
+//!
+//! [`open`] picks the backend the crate was built with.
+//! [`installer::install_to_store`] and [`remover::remove_from_store`] install
+//! and remove packages on any backend, without the features that need
+//! [`PackageDB`].
+//!
+//! [`installer::install_to_store`]: crate::package::installer::install_to_store
+//! [`remover::remove_from_store`]: crate::package::remover::remove_from_store
+
+use crate::db::PackageDB;
+use crate::error::StorageError;
+use crate::package::{LinkMode, Package};
+use async_trait::async_trait;
+use std::path::Path;
+
+/// Records of installed packages
+#[async_trait]
+pub trait PackageStore: Send + Sync {
+    /// Adds or replaces a package version with the files it placed as
+    /// `link_mode`, which becomes the mode of the package; the version is
+    /// not made current
+    async fn add_package(
+        &self,
+        pkg: &Package,
+        installed_files: &[String],
+        link_mode: LinkMode,
+    ) -> Result<(), StorageError>;
+
+    /// Removes a version of a package
+    async fn remove_package_version(&self, name: &str, version: &str) -> Result<(), StorageError>;
+
+    /// Removes all versions of a package
+    async fn remove_package(&self, name: &str) -> Result<(), StorageError>;
+
+    /// Every installed version as `(name, version, current)`
+    async fn list_packages(&self) -> Result<Vec<(String, String, bool)>, StorageError>;
+
+    /// Makes `version` the current version of a package
+    async fn set_current_version(&self, name: &str, version: &str) -> Result<(), StorageError>;
+
+    /// Metadata of the current version of a package
+    async fn get_current_package(&self, name: &str) -> Result<Option<Package>, StorageError>;
+
+    /// Files placed by a package version
+    async fn get_installed_files(
+        &self,
+        name: &str,
+        version: &str,
+    ) -> Result<Vec<String>, StorageError>;
+
+    /// Mode the files of a package were last placed with
+    async fn get_link_mode(&self, name: &str) -> Result<Option<LinkMode>, StorageError>;
+}
+
+/// Opens the package store in `home`
+///
+/// That is `packages.json` when built with the `json-store` feature and
+/// the SQLite `packages.db` otherwise.
+pub async fn open(home: &Path) -> Result<Box<dyn PackageStore>, StorageError> {
+    #[cfg(feature = "json-store")]
+    {
+        let store = crate::db::json_store::JsonStore::open(&home.join("packages.json"))?;
+        Ok(Box::new(store))
+    }
+    #[cfg(not(feature = "json-store"))]
+    {
+        let db = PackageDB::new(&home.join("packages.db"))?.init().await?;
+        Ok(Box::new(db))
+    }
+}
+
+#[async_trait]
+impl PackageStore for PackageDB {
+    async fn add_package(
+        &self,
+        pkg: &Package,
+        installed_files: &[String],
+        link_mode: LinkMode,
+    ) -> Result<(), StorageError> {
+        Ok(self
+            .add_package_full(pkg, installed_files, link_mode)
+            .await?)
+    }
+
+    async fn remove_package_version(&self, name: &str, version: &str) -> Result<(), StorageError> {
+        Ok(PackageDB::remove_package_version(self, name, version).await?)
+    }
+
+    async fn remove_package(&self, name: &str) -> Result<(), StorageError> {
+        Ok(PackageDB::remove_package(self, name).await?)
+    }
+
+    async fn list_packages(&self) -> Result<Vec<(String, String, bool)>, StorageError> {
+        Ok(PackageDB::list_packages(self).await?)
+    }
+
+    async fn set_current_version(&self, name: &str, version: &str) -> Result<(), StorageError> {
+        Ok(PackageDB::set_current_version(self, name, version).await?)
+    }
+
+    async fn get_current_package(&self, name: &str) -> Result<Option<Package>, StorageError> {
+        Ok(PackageDB::get_current_package(self, name).await?)
+    }
+
+    async fn get_installed_files(
+        &self,
+        name: &str,
+        version: &str,
+    ) -> Result<Vec<String>, StorageError> {
+        Ok(PackageDB::get_installed_files(self, name, version).await?)
+    }
+
+    async fn get_link_mode(&self, name: &str) -> Result<Option<LinkMode>, StorageError> {
+        Ok(self.get_package_link_mode(name).await?)
+    }
+}
+
+/// Runs the same operations against any backend, so they all behave alike
+#[cfg(test)]
+pub(crate) async fn exercise(store: &dyn PackageStore) {
+    use crate::package::Source;
+    use semver::Version;
+
+    let meta = |version: &str| {
+        Package::new(
+            "tool",
+            Version::parse(version).unwrap(),
+            "a",
+            Source::Raw(String::new()),
+            "",
+            vec![("lib".to_string(), Version::new(1, 0, 0))],
+        )
+    };
+
+    store
+        .add_package(&meta("1.0.0"), &["/bin/tool".to_string()], LinkMode::Copy)
+        .await
+        .unwrap();
+    store
+        .add_package(&meta("1.1.0"), &[], LinkMode::Symlink)
+        .await
+        .unwrap();
+    assert!(store.get_current_package("tool").await.unwrap().is_none());
+    store.set_current_version("tool", "1.0.0").await.unwrap();
+
+    let current = store.get_current_package("tool").await.unwrap().unwrap();
+    assert_eq!(current.version().to_string(), "1.0.0");
+    assert_eq!(current.dependencies().len(), 1);
+    assert_eq!(
+        store.get_installed_files("tool", "1.0.0").await.unwrap(),
+        ["/bin/tool"]
+    );
+    assert_eq!(
+        store.get_link_mode("tool").await.unwrap(),
+        Some(LinkMode::Symlink)
+    );
+    let mut packages = store.list_packages().await.unwrap();
+    packages.sort();
+    assert_eq!(
+        packages,
+        [
+            ("tool".to_string(), "1.0.0".to_string(), true),
+            ("tool".to_string(), "1.1.0".to_string(), false),
+        ]
+    );
+
+    store.remove_package_version("tool", "1.1.0").await.unwrap();
+    assert_eq!(store.list_packages().await.unwrap().len(), 1);
+    store.remove_package("tool").await.unwrap();
+    assert!(store.list_packages().await.unwrap().is_empty());
+    assert_eq!(store.get_link_mode("tool").await.unwrap(), None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[tokio::test]
+    async fn test_sqlite_store() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = PackageDB::new(&tmp.path().join("packages.db"))
+            .unwrap()
+            .init()
+            .await
+            .unwrap();
+        exercise(&db).await;
+    }
+
+    #[tokio::test]
+    async fn test_install_to_store() {
+        use crate::package::{installer, remover};
+
+        let tmp = tempfile::tempdir().unwrap();
+        let home = tmp.path().join("home");
+        let pkg = tmp.path().join("tool");
+        fs::create_dir_all(pkg.join("bin")).unwrap();
+        fs::write(pkg.join("bin/tool"), "#!/bin/sh").unwrap();
+        fs::write(
+            pkg.join("uhp.toml"),
+            crate::package::test_manifest("tool", "1.0.0"),
+        )
+        .unwrap();
+        let link = home.join(".local/bin/tool");
+        fs::write(pkg.join("symlist"), format!("bin/tool {}", link.display())).unwrap();
+        let archive = tmp.path().join("tool-1.0.0.uhp");
+        crate::archive::create(&pkg, &archive, &crate::archive::ArchiveOptions::new()).unwrap();
+
+        crate::platform::with_root(&home, async {
+            let store = open(&home).await.unwrap();
+            installer::install_to_store(&archive, store.as_ref(), false)
+                .await
+                .unwrap();
+            assert!(link.exists());
+            assert_eq!(
+                store.list_packages().await.unwrap(),
+                [("tool".to_string(), "1.0.0".to_string(), true)]
+            );
+
+            remover::remove_from_store("tool", store.as_ref())
+                .await
+                .unwrap();
+            assert!(fs::symlink_metadata(&link).is_err());
+            assert!(store.list_packages().await.unwrap().is_empty());
+        })
+        .await;
+    }
+}
@@ -0,0 +1,166 @@
+//! # D-Bus Notifications
+//!
+//! With the `dbus` feature, uhpm announces finished installs, removals and
+//! updates on the session bus, so that desktop environments can refresh
+//! their application menus or show a notification without polling the
+//! package database. The signals are emitted from the object
+//! [`OBJECT_PATH`] with the interface [`INTERFACE`]:
+//!
+//! | Signal             | Arguments                  |
+//! |--------------------|----------------------------|
+//! | `PackageInstalled` | `name: s`, `version: s`    |
+//! | `PackageRemoved`   | `name: s`, `version: s`    |
+//! | `PackageUpdated`   | `name: s`                  |
+//!
+//! Listen with e.g.
+//! `dbus-monitor "type='signal',interface='io.github.bircoder432.Uhpm1'"`.
+//!
+//! [`DbusSink`] is a [`ProgressSink`] that hands the events to a task
+//! owning the bus connection; [`DbusTask::finish`] waits for the pending
+//! signals before the process exits. Without a session bus nothing is
+//! announced.
+
+use crate::debug;
+use crate::events::{ProgressEvent, ProgressSink};
+use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
+use tokio::task::JoinHandle;
+use zbus::Connection;
+use zbus::names::BusName;
+
+/// Object path the signals are emitted from
+pub const OBJECT_PATH: &str = "/io/github/bircoder432/Uhpm1";
+/// Interface of the signals
+pub const INTERFACE: &str = "io.github.bircoder432.Uhpm1";
+
+/// A signal announcing a finished operation
+#[derive(Debug, Clone, PartialEq)]
+pub enum Signal {
+    PackageInstalled { name: String, version: String },
+    PackageRemoved { name: String, version: String },
+    PackageUpdated { name: String },
+}
+
+impl Signal {
+    /// The signal announcing `event`, if it ends an operation
+    pub fn from_event(event: &ProgressEvent) -> Option<Self> {
+        match event {
+            ProgressEvent::InstallFinished { package, version } => Some(Signal::PackageInstalled {
+                name: package.clone(),
+                version: version.clone(),
+            }),
+            ProgressEvent::RemoveFinished { package, version } => Some(Signal::PackageRemoved {
+                name: package.clone(),
+                version: version.clone(),
+            }),
+            ProgressEvent::UpdateFinished { package } => Some(Signal::PackageUpdated {
+                name: package.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Member name of the signal
+    pub fn member(&self) -> &'static str {
+        match self {
+            Signal::PackageInstalled { .. } => "PackageInstalled",
+            Signal::PackageRemoved { .. } => "PackageRemoved",
+            Signal::PackageUpdated { .. } => "PackageUpdated",
+        }
+    }
+
+    async fn emit(&self, connection: &Connection) -> zbus::Result<()> {
+        let destination: Option<BusName> = None;
+        match self {
+            Signal::PackageInstalled { name, version }
+            | Signal::PackageRemoved { name, version } => {
+                connection
+                    .emit_signal(
+                        destination,
+                        OBJECT_PATH,
+                        INTERFACE,
+                        self.member(),
+                        &(name, version),
+                    )
+                    .await
+            }
+            Signal::PackageUpdated { name } => {
+                connection
+                    .emit_signal(destination, OBJECT_PATH, INTERFACE, self.member(), &(name,))
+                    .await
+            }
+        }
+    }
+}
+
+/// Progress sink announcing finished operations on the session bus
+pub struct DbusSink {
+    tx: UnboundedSender<Signal>,
+}
+
+/// Task emitting the signals of a [`DbusSink`]
+pub struct DbusTask {
+    handle: JoinHandle<()>,
+}
+
+impl DbusSink {
+    /// Connects to the session bus
+    pub async fn connect() -> zbus::Result<(Self, DbusTask)> {
+        let connection = Connection::session().await?;
+        let (tx, mut rx) = unbounded_channel::<Signal>();
+        let handle = tokio::spawn(async move {
+            while let Some(signal) = rx.recv().await {
+                if let Err(e) = signal.emit(&connection).await {
+                    debug!("dbus.emit_failed", signal.member(), e);
+                }
+            }
+        });
+        Ok((Self { tx }, DbusTask { handle }))
+    }
+}
+
+impl DbusTask {
+    /// Waits until the pending signals are emitted
+    ///
+    /// The sink must be dropped first (e.g. with
+    /// [`events::clear_sink`](crate::events::clear_sink)).
+    pub async fn finish(self) {
+        let _ = self.handle.await;
+    }
+}
+
+impl ProgressSink for DbusSink {
+    fn emit(&self, event: ProgressEvent) {
+        if let Some(signal) = Signal::from_event(&event) {
+            // A finished task just means the bus went away
+            let _ = self.tx.send(signal);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signals_for_finished_operations() {
+        let installed = ProgressEvent::InstallFinished {
+            package: "jq".to_string(),
+            version: "1.7.0".to_string(),
+        };
+        assert_eq!(
+            Signal::from_event(&installed),
+            Some(Signal::PackageInstalled {
+                name: "jq".to_string(),
+                version: "1.7.0".to_string(),
+            })
+        );
+        let updated = ProgressEvent::UpdateFinished {
+            package: "jq".to_string(),
+        };
+        assert_eq!(
+            Signal::from_event(&updated).unwrap().member(),
+            "PackageUpdated"
+        );
+        assert!(Signal::from_event(&ProgressEvent::DownloadsCompleted).is_none());
+    }
+}
@@ -15,6 +15,18 @@ pub enum SwitchError {
     Symlist(#[from] crate::symlist::SymlistError),
     #[error("Package not found: {0} version {1}")]
     PackageNotFound(String, Version),
+    #[error("Conflict: {0}")]
+    Conflict(String),
+}
+
+#[derive(Error, Debug)]
+pub enum HookError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Hook `{0}` exited with status {1}")]
+    Failed(String, i32),
+    #[error("Hook `{0}` did not finish within {1} s")]
+    TimedOut(String, u64),
 }
 
 #[derive(Error, Debug)]
@@ -31,6 +43,38 @@ pub enum UpdaterError {
     Fetch(#[from] FetchError),
     #[error("No newer version available for package: {0}")]
     NoNewVersion(String),
+    #[error("Package {0} version {1} not found in any repository")]
+    VersionNotFound(String, String),
+    #[error("Switch error: {0}")]
+    Switch(#[from] SwitchError),
+    #[error("Install error: {0}")]
+    Install(String),
+    #[error("Hook error: {0}")]
+    Hook(#[from] HookError),
+    #[error("Updates are blocked by blackout window: {0}")]
+    Blackout(String),
+    #[error("Package {0} is pinned to {1}")]
+    Held(String, String),
+}
+
+#[derive(Error, Debug)]
+pub enum SigningError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Package is not signed: {0}")]
+    Unsigned(PathBuf),
+    #[error("No trusted keys for this signature in {0}")]
+    NoTrustedKeys(PathBuf),
+    #[error("Signature does not match any trusted key: {0}")]
+    Invalid(PathBuf),
+    #[error("`{0}` is required for package signatures but was not found")]
+    ToolMissing(String),
+    #[error("Failed to sign {0}")]
+    SignFailed(PathBuf),
+    #[error("Key of repository {0} does not match its fingerprint")]
+    FingerprintMismatch(String),
+    #[error("Key of repository {0} was not confirmed")]
+    Unconfirmed(String),
 }
 
 #[derive(Error, Debug)]
@@ -55,6 +99,54 @@ pub enum UhpmError {
     NoNewVersion(String),
     #[error("Validation error: {0}")]
     Validation(String),
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    #[error("Signature error: {0}")]
+    Signing(#[from] SigningError),
+    #[error("{0} is read-only")]
+    ReadOnly(PathBuf),
+}
+
+/// Exit codes of the `uhpm` binary
+pub mod exit_code {
+    /// Success
+    pub const OK: u8 = 0;
+    /// Any failure without a more specific code
+    pub const GENERIC: u8 = 1;
+    /// A package, version or repository was not found
+    pub const NOT_FOUND: u8 = 2;
+    /// A download or other network operation failed
+    pub const NETWORK: u8 = 3;
+    /// The operation conflicts with the current state
+    pub const CONFLICT: u8 = 4;
+    /// Package metadata or input failed verification
+    pub const VERIFICATION: u8 = 5;
+}
+
+impl UhpmError {
+    /// Returns the process exit code for this error
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            UhpmError::NotFound(_) | UhpmError::Repository(RepoError::NotFound(_)) => {
+                exit_code::NOT_FOUND
+            }
+            UhpmError::Repository(e @ RepoError::Fetch { source, .. }) => match e.status() {
+                Some(reqwest::StatusCode::NOT_FOUND) => exit_code::NOT_FOUND,
+                _ if matches!(source, FetchError::Http(_)) => exit_code::NETWORK,
+                _ => exit_code::GENERIC,
+            },
+            UhpmError::Network(_) => exit_code::NETWORK,
+            UhpmError::Conflict(_) => exit_code::CONFLICT,
+            UhpmError::Validation(_)
+            | UhpmError::Parse(_)
+            | UhpmError::Repository(RepoError::Integrity(_)) => exit_code::VERIFICATION,
+            UhpmError::Signing(SigningError::Io(_) | SigningError::ToolMissing(_)) => {
+                exit_code::GENERIC
+            }
+            UhpmError::Signing(_) => exit_code::VERIFICATION,
+            _ => exit_code::GENERIC,
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -67,6 +159,17 @@ pub enum RemoveError {
     Io(#[from] std::io::Error),
 }
 
+/// Errors of a [`PackageStore`](crate::db::storage::PackageStore) backend
+#[derive(Error, Debug)]
+pub enum StorageError {
+    #[error("Database error: {0}")]
+    Db(#[from] sqlx::Error),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid store file: {0}")]
+    Format(String),
+}
+
 #[derive(Error, Debug)]
 pub enum ConfigError {
     #[error("IO error: {0}")]
@@ -85,6 +188,40 @@ pub enum RepoError {
     Db(#[from] sqlx::Error),
     #[error("Package not found: {0}")]
     NotFound(String),
+    #[error("Integrity check failed: {0}")]
+    Integrity(String),
+    #[error("Repository {repo}: failed to fetch {url}: {}", fetch_detail(.source))]
+    Fetch {
+        repo: String,
+        url: String,
+        #[source]
+        source: FetchError,
+    },
+    #[error("Repository {repo}: cannot read index {}: {source}", .path.display())]
+    Index {
+        repo: String,
+        path: PathBuf,
+        #[source]
+        source: Box<RepoError>,
+    },
+}
+
+impl RepoError {
+    /// Name of the repository the error is about, if known
+    pub fn repo(&self) -> Option<&str> {
+        match self {
+            RepoError::Fetch { repo, .. } | RepoError::Index { repo, .. } => Some(repo),
+            _ => None,
+        }
+    }
+
+    /// HTTP status the repository answered with, if it answered at all
+    pub fn status(&self) -> Option<reqwest::StatusCode> {
+        match self {
+            RepoError::Fetch { source, .. } => source.status(),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -95,6 +232,28 @@ pub enum FetchError {
     Io(#[from] std::io::Error),
     #[error("Installer error: {0}")]
     Installer(String),
+    #[error("Unsupported URL scheme: {0}")]
+    UnsupportedScheme(String),
+    #[error("All mirrors failed for: {0}")]
+    AllMirrorsFailed(String),
+}
+
+impl FetchError {
+    /// HTTP status of a request that got an answer other than success
+    pub fn status(&self) -> Option<reqwest::StatusCode> {
+        match self {
+            FetchError::Http(e) => e.status(),
+            _ => None,
+        }
+    }
+}
+
+/// The status alone says what went wrong; the URL is shown by the caller
+fn fetch_detail(error: &FetchError) -> String {
+    match error.status() {
+        Some(status) => format!("HTTP {}", status),
+        None => error.to_string(),
+    }
 }
 
 #[derive(Error, Debug)]
@@ -117,6 +276,33 @@ impl From<SwitchError> for UhpmError {
             SwitchError::PackageNotFound(name, version) => {
                 UhpmError::NotFound(format!("Package {} version {} not found", name, version))
             }
+            SwitchError::Conflict(message) => UhpmError::Conflict(message),
+        }
+    }
+}
+
+impl From<crate::config::ConfigError> for UhpmError {
+    fn from(error: crate::config::ConfigError) -> Self {
+        use crate::config::ConfigError as SettingsError;
+        match error {
+            SettingsError::Io(e) => UhpmError::Io(e),
+            SettingsError::Ron(e) => UhpmError::Parse(e.to_string()),
+            SettingsError::RonError(e) => UhpmError::Parse(e.to_string()),
+            SettingsError::NotFound(path) => UhpmError::Config(ConfigError::NotFound(path)),
+            e @ SettingsError::ProfileExists(_) => UhpmError::Conflict(e.to_string()),
+            e @ (SettingsError::ProfileNotFound(_) | SettingsError::InvalidProfileName(_)) => {
+                UhpmError::Validation(e.to_string())
+            }
+        }
+    }
+}
+
+impl From<StorageError> for UhpmError {
+    fn from(error: StorageError) -> Self {
+        match error {
+            StorageError::Db(e) => UhpmError::Database(e),
+            StorageError::Io(e) => UhpmError::Io(e),
+            StorageError::Format(e) => UhpmError::Parse(e),
         }
     }
 }
@@ -130,6 +316,20 @@ impl From<UpdaterError> for UhpmError {
             UpdaterError::Db(e) => UhpmError::Database(e),
             UpdaterError::Fetch(e) => UhpmError::from(e),
             UpdaterError::NoNewVersion(name) => UhpmError::NoNewVersion(name),
+            UpdaterError::VersionNotFound(name, version) => {
+                UhpmError::NotFound(format!("Package {} version {} not found", name, version))
+            }
+            UpdaterError::Switch(e) => UhpmError::from(e),
+            UpdaterError::Install(msg) => UhpmError::Package(msg),
+            UpdaterError::Hook(e) => UhpmError::Package(e.to_string()),
+            UpdaterError::Blackout(window) => UhpmError::Conflict(format!(
+                "Updates are blocked by blackout window: {}",
+                window
+            )),
+            UpdaterError::Held(name, pin) => UhpmError::Conflict(format!(
+                "{} is pinned to {} (uhpm unpin {} releases it)",
+                name, pin, name
+            )),
         }
     }
 }
@@ -140,6 +340,12 @@ impl From<FetchError> for UhpmError {
             FetchError::Http(e) => UhpmError::Network(e),
             FetchError::Io(e) => UhpmError::Io(e),
             FetchError::Installer(msg) => UhpmError::Package(msg),
+            FetchError::UnsupportedScheme(url) => {
+                UhpmError::Validation(format!("Unsupported URL scheme: {}", url))
+            }
+            FetchError::AllMirrorsFailed(url) => {
+                UhpmError::NotFound(format!("All mirrors failed for: {}", url))
+            }
         }
     }
 }
@@ -174,3 +380,19 @@ impl From<&str> for ConfigError {
         ConfigError::NotFound(s.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes() {
+        assert_eq!(UhpmError::NotFound("x".into()).exit_code(), 2);
+        assert_eq!(
+            UhpmError::from(UpdaterError::Blackout("* 00:00-23:59".into())).exit_code(),
+            exit_code::CONFLICT
+        );
+        assert_eq!(UhpmError::Parse("x".into()).exit_code(), 5);
+        assert_eq!(UhpmError::Package("x".into()).exit_code(), 1);
+    }
+}
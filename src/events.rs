@@ -0,0 +1,142 @@
+//! # Progress Events
+//!
+//! Library code reports progress by emitting [`ProgressEvent`]s instead of
+//! drawing progress bars itself. Front ends (CLI, TUI, daemon) register a
+//! [`ProgressSink`] and decide how to render them.
+//!
+//! ## Example
+//! ```rust
+//! use std::sync::Arc;
+//! use uhpm::events::{self, ChannelSink, ProgressEvent};
+//!
+//! let (sink, mut rx) = ChannelSink::new();
+//! events::set_sink(Arc::new(sink));
+//!
+//! events::emit(ProgressEvent::DownloadsCompleted);
+//! assert!(matches!(rx.try_recv(), Ok(ProgressEvent::DownloadsCompleted)));
+//! # events::clear_sink();
+//! ```
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::sync::{Arc, RwLock};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+
+/// Progress notifications emitted by fetcher, installer, remover and updater.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    /// A batch of downloads is about to start
+    DownloadsQueued { total: usize },
+    /// A single download started
+    DownloadStarted { url: String },
+    /// Bytes of a download received so far; `total` is known when the
+    /// server sends a content length
+    DownloadProgress {
+        url: String,
+        downloaded: u64,
+        total: Option<u64>,
+    },
+    /// A single download finished successfully
+    DownloadFinished { url: String },
+    /// A single download failed
+    DownloadFailed { url: String, error: String },
+    /// All downloads of the current batch are done
+    DownloadsCompleted,
+    /// Package installation started
+    InstallStarted { package: String, version: String },
+    /// Package installation finished
+    InstallFinished { package: String, version: String },
+    /// Package removal started
+    RemoveStarted { package: String, version: String },
+    /// Package removal finished
+    RemoveFinished { package: String, version: String },
+    /// What a finished removal deleted
+    RemovalSummary {
+        package: String,
+        version: String,
+        files: u64,
+        symlinks: u64,
+        bytes: u64,
+    },
+    /// A newer version of an installed package was found
+    UpdateAvailable {
+        package: String,
+        current: String,
+        latest: String,
+    },
+    /// Package update finished
+    UpdateFinished { package: String },
+}
+
+/// Receiver of progress events.
+///
+/// Implementations must be thread-safe: events may be emitted from
+/// concurrently running tasks.
+pub trait ProgressSink: Send + Sync {
+    fn emit(&self, event: ProgressEvent);
+}
+
+/// Sink forwarding events into a tokio channel, for daemon/TUI consumers.
+pub struct ChannelSink {
+    tx: UnboundedSender<ProgressEvent>,
+}
+
+impl ChannelSink {
+    /// Creates a sink together with the receiving end of its channel
+    pub fn new() -> (Self, UnboundedReceiver<ProgressEvent>) {
+        let (tx, rx) = unbounded_channel();
+        (Self { tx }, rx)
+    }
+}
+
+impl ProgressSink for ChannelSink {
+    fn emit(&self, event: ProgressEvent) {
+        // A dropped receiver just means nobody is listening anymore
+        let _ = self.tx.send(event);
+    }
+}
+
+/// Sink passing every event on to several sinks, e.g. progress bars and
+/// desktop notifications.
+pub struct FanoutSink {
+    sinks: Vec<Arc<dyn ProgressSink>>,
+}
+
+impl FanoutSink {
+    pub fn new(sinks: Vec<Arc<dyn ProgressSink>>) -> Self {
+        Self { sinks }
+    }
+}
+
+impl ProgressSink for FanoutSink {
+    fn emit(&self, event: ProgressEvent) {
+        for sink in &self.sinks {
+            sink.emit(event.clone());
+        }
+    }
+}
+
+static SINK: Lazy<RwLock<Option<Arc<dyn ProgressSink>>>> = Lazy::new(|| RwLock::new(None));
+
+/// Registers the global progress sink, replacing any previous one
+pub fn set_sink(sink: Arc<dyn ProgressSink>) {
+    if let Ok(mut guard) = SINK.write() {
+        *guard = Some(sink);
+    }
+}
+
+/// Removes the global progress sink; subsequent events are dropped
+pub fn clear_sink() {
+    if let Ok(mut guard) = SINK.write() {
+        *guard = None;
+    }
+}
+
+/// Emits an event to the registered sink, if any
+pub fn emit(event: ProgressEvent) {
+    let sink = SINK.read().ok().and_then(|guard| guard.clone());
+    if let Some(sink) = sink {
+        sink.emit(event);
+    }
+}
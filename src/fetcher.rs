@@ -1,25 +1,49 @@
 //! # Package Fetcher
 //!
 //! This module handles downloading packages from our UHP repositories.
+//!
+//! Besides `http(s)://` and `file://` URLs, packages may be referenced as
+//! `ipfs://<cid>` (see [`ipfs`]).
+//!
+//! HTTP downloads are streamed to a `.part` file next to their destination
+//! and renamed once complete. Transient failures are retried with
+//! exponential backoff as configured in
+//! [`DownloadConfig`](crate::config::DownloadConfig), and a retry resumes
+//! the partial file with a `Range` request when the server supports it,
+//! guarded by `If-Range` so a file that changed meanwhile starts over.
 
+pub mod ipfs;
+
+use crate::config::Config;
 use crate::db::PackageDB;
 use crate::error::FetchError;
+use crate::events::{self, ProgressEvent};
+use crate::package::Provenance;
 use crate::package::installer;
-use crate::{error, info};
+use crate::{debug, error, info, signing, warn};
 use futures::stream::{FuturesUnordered, StreamExt};
-use indicatif::{ProgressBar, ProgressStyle};
+use reqwest::StatusCode;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED, RANGE};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Semaphore;
 
 /// Скачивает пакет из нашего репозитория
 async fn download_package(url: &str) -> Result<PathBuf, FetchError> {
     if let Some(stripped) = url.strip_prefix("file://") {
         // Локальный файл
         Ok(PathBuf::from(stripped))
+    } else if ipfs::is_ipfs_url(url) {
+        ipfs::fetch(url, &std::env::temp_dir()).await
+    } else if ipfs::is_torrent_url(url) {
+        Err(FetchError::UnsupportedScheme(url.to_string()))
     } else if url.starts_with("http://") || url.starts_with("https://") {
         // HTTP скачивание
-        let resp = reqwest::get(url).await?.bytes().await?;
         let tmp_dir = std::env::temp_dir();
         let filename = Path::new(url)
             .file_name()
@@ -31,7 +55,8 @@ async fn download_package(url: &str) -> Result<PathBuf, FetchError> {
                 ))
             })?;
         let tmp_path = tmp_dir.join(filename);
-        fs::write(&tmp_path, &resp).await?;
+        download_with_retry(url, &tmp_path, RetryPolicy::configured()).await?;
+        download_signature(url, &tmp_path).await;
         Ok(tmp_path)
     } else {
         // Прямой путь к файлу
@@ -39,16 +64,50 @@ async fn download_package(url: &str) -> Result<PathBuf, FetchError> {
     }
 }
 
+/// Downloads the detached signature `<url>.sig` next to `archive`, if the
+/// server has one
+///
+/// A stale signature from an earlier download is removed first, so an
+/// unsigned package never passes as signed. For a local path the
+/// signature next to it is copied.
+pub(crate) async fn download_signature(url: &str, archive: &Path) {
+    let sig_path = signing::signature_path(archive);
+    let _ = fs::remove_file(&sig_path).await;
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        let local = signing::signature_path(Path::new(url.strip_prefix("file://").unwrap_or(url)));
+        if local.exists()
+            && let Err(e) = fs::copy(&local, &sig_path).await
+        {
+            debug!("fetcher.signature.failed", url, e);
+        }
+        return;
+    }
+    let response = match reqwest::get(format!("{}.sig", url)).await {
+        Ok(response) if response.status().is_success() => response,
+        _ => {
+            debug!("fetcher.signature.none", url);
+            return;
+        }
+    };
+    match response.bytes().await {
+        Ok(bytes) => {
+            if let Err(e) = fs::write(&sig_path, &bytes).await {
+                debug!("fetcher.signature.failed", url, e);
+            }
+        }
+        Err(e) => debug!("fetcher.signature.failed", url, e),
+    }
+}
+
 /// Скачивает uhpbuild скрипты для сборки из исходников
 pub async fn download_source_build_script(url: &str) -> Result<PathBuf, FetchError> {
     if let Some(stripped) = url.strip_prefix("file://") {
         Ok(PathBuf::from(stripped))
     } else if url.starts_with("http://") || url.starts_with("https://") {
-        let resp = reqwest::get(url).await?.bytes().await?;
         let tmp_dir = std::env::temp_dir();
         let filename = "uhpbuild.sh"; // Стандартное имя для скрипта сборки
         let tmp_path = tmp_dir.join(filename);
-        fs::write(&tmp_path, &resp).await?;
+        download_with_retry(url, &tmp_path, RetryPolicy::configured()).await?;
 
         // Делаем скрипт исполняемым
         #[cfg(unix)]
@@ -66,19 +125,18 @@ pub async fn download_source_build_script(url: &str) -> Result<PathBuf, FetchErr
 }
 
 /// Скачивает несколько пакетов параллельно
+///
+/// Progress is reported through [`events`](crate::events).
 pub async fn fetch_packages(urls: &[String]) -> HashMap<String, PathBuf> {
-    let bar = ProgressBar::new(urls.len() as u64);
-    bar.set_style(
-        ProgressStyle::default_bar()
-            .template("[{bar:40.cyan/blue}] {pos}/{len} {msg}")
-            .unwrap()
-            .progress_chars("##-"),
-    );
+    events::emit(ProgressEvent::DownloadsQueued { total: urls.len() });
 
     let mut futures = FuturesUnordered::new();
     for url in urls {
         let url_clone = url.clone();
         futures.push(async move {
+            events::emit(ProgressEvent::DownloadStarted {
+                url: url_clone.clone(),
+            });
             let path = download_package(&url_clone).await;
             (url_clone, path)
         });
@@ -89,16 +147,18 @@ pub async fn fetch_packages(urls: &[String]) -> HashMap<String, PathBuf> {
         match res {
             Ok(path) => {
                 results.insert(url.clone(), path);
-                bar.inc(1);
-                bar.set_message(format!("Downloaded: {}", url));
+                events::emit(ProgressEvent::DownloadFinished { url });
             }
             Err(e) => {
-                error!("fetcher.download.failed", url, e);
-                bar.inc(1);
+                error!("fetcher.download.failed", &url, &e);
+                events::emit(ProgressEvent::DownloadFailed {
+                    url,
+                    error: e.to_string(),
+                });
             }
         }
     }
-    bar.finish_with_message("Download complete");
+    events::emit(ProgressEvent::DownloadsCompleted);
     results
 }
 
@@ -110,7 +170,7 @@ pub async fn install_fetched_packages(
 ) -> Result<(), FetchError> {
     for (url, path) in packages {
         info!("fetcher.install.from_url", url);
-        installer::install(path, package_db, direct)
+        installer::install_transactional(path, package_db, direct, Provenance::from_url(url))
             .await
             .map_err(|e| {
                 FetchError::Installer(format!("Installation failed for {}: {:?}", url, e))
@@ -119,14 +179,100 @@ pub async fn install_fetched_packages(
     Ok(())
 }
 
+/// Number of packages downloaded at the same time by [`fetch_and_install_parallel`]
+pub const DOWNLOAD_CONCURRENCY: usize = 4;
+
 /// Скачивает и устанавливает пакеты параллельно
+///
+/// Runs as a pipeline with bounded stages:
+/// 1. up to [`DOWNLOAD_CONCURRENCY`] packages in flight, each downloaded
+///    and then verified and unpacked ([`installer::prepare_pooled`]) on
+///    one of [`installer::PREPARE_CONCURRENCY`] blocking threads;
+/// 2. prepared packages committed to the store and database one at a time
+///    ([`installer::commit`]) in the order of `urls`, so callers pass
+///    dependencies first.
+///
+/// Downloads keep running while earlier packages are committed. The first
+/// failed download or install aborts the pipeline: packages after it, which
+/// may depend on it, are not committed, what was already unpacked is
+/// discarded and the error is returned.
 pub async fn fetch_and_install_parallel(
     urls: &[String],
     package_db: &PackageDB,
     direct: bool,
 ) -> Result<(), FetchError> {
-    let downloaded = fetch_packages(urls).await;
-    install_fetched_packages(&downloaded, package_db, direct).await?;
+    let (tx, mut rx) = tokio::sync::mpsc::channel(installer::PREPARE_CONCURRENCY);
+    let urls = urls.to_vec();
+    let pool = Arc::new(Semaphore::new(installer::PREPARE_CONCURRENCY));
+
+    let producer = tokio::spawn(crate::platform::in_current_root(async move {
+        events::emit(ProgressEvent::DownloadsQueued { total: urls.len() });
+
+        let mut prepared = futures::stream::iter(urls)
+            .map(|url| {
+                let pool = pool.clone();
+                async move {
+                    events::emit(ProgressEvent::DownloadStarted { url: url.clone() });
+                    match download_package(&url).await {
+                        Ok(path) => {
+                            events::emit(ProgressEvent::DownloadFinished { url: url.clone() });
+                            let res = installer::prepare_pooled(path, &pool).await.map_err(|e| {
+                                FetchError::Installer(format!(
+                                    "Installation failed for {}: {:?}",
+                                    url, e
+                                ))
+                            });
+                            (url, res)
+                        }
+                        Err(e) => {
+                            error!("fetcher.download.failed", &url, &e);
+                            events::emit(ProgressEvent::DownloadFailed {
+                                url: url.clone(),
+                                error: e.to_string(),
+                            });
+                            (url, Err(e))
+                        }
+                    }
+                }
+            })
+            .buffered(DOWNLOAD_CONCURRENCY)
+            .boxed();
+
+        while let Some(item) = prepared.next().await {
+            if let Err(tokio::sync::mpsc::error::SendError((_, Ok(unsent)))) = tx.send(item).await {
+                installer::discard(unsent);
+                break;
+            }
+        }
+        events::emit(ProgressEvent::DownloadsCompleted);
+    }));
+
+    while let Some((url, prepared)) = rx.recv().await {
+        info!("fetcher.install.from_url", &url);
+        let result = match prepared {
+            Ok(prepared) => {
+                installer::commit(prepared, package_db, direct, Provenance::from_url(&url))
+                    .await
+                    .map_err(|e| {
+                        FetchError::Installer(format!("Installation failed for {}: {:?}", url, e))
+                    })
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            producer.abort();
+            while let Some((_, pending)) = rx.recv().await {
+                if let Ok(pending) = pending {
+                    installer::discard(pending);
+                }
+            }
+            return Err(e);
+        }
+    }
+
+    producer
+        .await
+        .map_err(|e| FetchError::Installer(e.to_string()))?;
     Ok(())
 }
 
@@ -188,8 +334,7 @@ pub async fn download_file_to_path(url: &str, destination: &Path) -> Result<(),
         }
     } else if url.starts_with("http://") || url.starts_with("https://") {
         // HTTP скачивание напрямую в указанный путь
-        let resp = reqwest::get(url).await?.bytes().await?;
-        fs::write(destination, &resp).await?;
+        download_with_retry(url, destination, RetryPolicy::configured()).await?;
     } else {
         // Прямой путь к файлу - копируем если пути разные
         let source_path = PathBuf::from(url);
@@ -202,17 +347,376 @@ pub async fn download_file_to_path(url: &str, destination: &Path) -> Result<(),
     Ok(())
 }
 
+/// How often and how patiently failed downloads are retried
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    /// Wait before the first retry; doubled for each further one
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// The policy configured in `download`
+    pub fn configured() -> Self {
+        let download = Config::load_or_default().download;
+        Self {
+            retries: download.retries,
+            backoff: Duration::from_millis(download.backoff_ms),
+        }
+    }
+
+    /// Wait before retry number `attempt`, counting from 0
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.backoff.saturating_mul(1 << attempt.min(16))
+    }
+}
+
+/// Whether a failed download may succeed when tried again
+fn is_transient(error: &FetchError) -> bool {
+    match error {
+        FetchError::Http(e) => e
+            .status()
+            .is_none_or(|s| s.is_server_error() || s == StatusCode::TOO_MANY_REQUESTS),
+        _ => false,
+    }
+}
+
+/// Where a download to `destination` is kept until it is complete
+fn partial_path(destination: &Path) -> PathBuf {
+    let mut name = destination.as_os_str().to_owned();
+    name.push(".part");
+    PathBuf::from(name)
+}
+
+/// Where the validator of the partial file `partial` is kept, sent as
+/// `If-Range` to resume it
+fn validator_path(partial: &Path) -> PathBuf {
+    let mut name = partial.as_os_str().to_owned();
+    name.push(".validator");
+    PathBuf::from(name)
+}
+
+/// Validator a later range request for `response` can be made against: a
+/// strong `ETag`, or else `Last-Modified`
+fn resume_validator(response: &reqwest::Response) -> Option<String> {
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    header(ETAG)
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| header(LAST_MODIFIED))
+}
+
+/// Bytes received between two [`ProgressEvent::DownloadProgress`] events
+const PROGRESS_STEP: u64 = 64 * 1024;
+
+/// Streams `url` to `destination` in one attempt, continuing the partial
+/// file of an earlier attempt if the server answers the range request
+///
+/// The range request carries the validator of the partial file as
+/// `If-Range`, so a resource that changed in between is sent whole
+/// instead of being spliced onto old bytes; a partial file without a
+/// validator is not resumed. Progress is reported through
+/// [`events`](crate::events).
+async fn stream_to_file(
+    client: &reqwest::Client,
+    url: &str,
+    destination: &Path,
+) -> Result<(), FetchError> {
+    let partial = partial_path(destination);
+    let validator_file = validator_path(&partial);
+    let validator = fs::read_to_string(&validator_file).await.ok();
+    let offset = match &validator {
+        Some(_) => fs::metadata(&partial).await.map_or(0, |m| m.len()),
+        None => 0,
+    };
+    let mut request = client.get(url);
+    if let Some(validator) = validator.filter(|_| offset > 0) {
+        request = request
+            .header(RANGE, format!("bytes={}-", offset))
+            .header(IF_RANGE, validator);
+    }
+    let mut response = request.send().await?;
+    if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        // The partial file does not fit the resource, start over
+        fs::remove_file(&partial).await?;
+        response = client.get(url).send().await?;
+    }
+    let mut response = response.error_for_status()?;
+
+    let resumed = response.status() == StatusCode::PARTIAL_CONTENT;
+    let mut downloaded = 0;
+    if resumed {
+        info!("fetcher.download.resume", url, offset);
+        downloaded = offset;
+    } else {
+        // A full answer replaces whatever was received before
+        let _ = fs::remove_file(&partial).await;
+        match resume_validator(&response) {
+            Some(validator) => fs::write(&validator_file, validator).await?,
+            None => {
+                let _ = fs::remove_file(&validator_file).await;
+            }
+        }
+    }
+    let total = response.content_length().map(|len| downloaded + len);
+    let progress = |downloaded| {
+        events::emit(ProgressEvent::DownloadProgress {
+            url: url.to_string(),
+            downloaded,
+            total,
+        })
+    };
+    progress(downloaded);
+    let mut reported = downloaded;
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(&partial)
+        .await?;
+    while let Some(chunk) = response.chunk().await? {
+        file.write_all(&chunk).await?;
+        downloaded += chunk.len() as u64;
+        if downloaded - reported >= PROGRESS_STEP {
+            progress(downloaded);
+            reported = downloaded;
+        }
+    }
+    if downloaded != reported {
+        progress(downloaded);
+    }
+    file.flush().await?;
+    drop(file);
+    fs::rename(&partial, destination).await?;
+    let _ = fs::remove_file(&validator_file).await;
+    Ok(())
+}
+
+/// Downloads `url` to `destination` without holding it in memory
+///
+/// Connection errors, server errors and `429 Too Many Requests` are
+/// retried according to `policy`; each retry resumes the partial file
+/// when the server supports range requests.
+pub async fn download_with_retry(
+    url: &str,
+    destination: &Path,
+    policy: RetryPolicy,
+) -> Result<(), FetchError> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    let client = reqwest::Client::new();
+    let mut attempt = 0;
+    loop {
+        match stream_to_file(&client, url, destination).await {
+            Err(e) if attempt < policy.retries && is_transient(&e) => {
+                let delay = policy.delay(attempt);
+                attempt += 1;
+                warn!("fetcher.download.retry", url, attempt, delay.as_millis(), e);
+                tokio::time::sleep(delay).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// HTTP validators of a download, sent back to ask whether it changed
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Validators {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// Downloads `url` to `destination` unless the server reports that it has
+/// not changed since `known` were received
+///
+/// Returns the validators of the new download, or `None` for `304 Not
+/// Modified`. Other URLs are always copied and have no validators.
+pub async fn download_if_changed(
+    url: &str,
+    destination: &Path,
+    known: &Validators,
+) -> Result<Option<Validators>, FetchError> {
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        download_file_to_path_with_dirs(url, destination).await?;
+        return Ok(Some(Validators::default()));
+    }
+
+    let mut request = reqwest::Client::new().get(url);
+    if let Some(etag) = &known.etag {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+    if let Some(date) = &known.last_modified {
+        request = request.header(IF_MODIFIED_SINCE, date);
+    }
+    let response = request.send().await?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        debug!("fetcher.not_modified", url);
+        return Ok(None);
+    }
+    let response = response.error_for_status()?;
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    let validators = Validators {
+        etag: header(ETAG),
+        last_modified: header(LAST_MODIFIED),
+    };
+
+    info!("fetcher.download_to_path", url, destination.display());
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::write(destination, response.bytes().await?).await?;
+    info!("fetcher.download_complete", destination.display());
+    Ok(Some(validators))
+}
+
 /// Скачивает файл по ссылке в указанный путь с созданием родительских директорий
 pub async fn download_file_to_path_with_dirs(
     url: &str,
     destination: &Path,
 ) -> Result<(), FetchError> {
     // Создаем родительские директории если нужно
-    if let Some(parent) = destination.parent() {
-        if !parent.exists() {
-            fs::create_dir_all(parent).await?;
-        }
+    if let Some(parent) = destination.parent()
+        && !parent.exists()
+    {
+        fs::create_dir_all(parent).await?;
     }
 
     download_file_to_path(url, destination).await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    /// Serves `requests` requests, answering 304 to a matching
+    /// `If-None-Match`
+    fn serve(requests: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut matched = false;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    matched |= line.to_ascii_lowercase() == "if-none-match: \"v1\"\r\n";
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let response = if matched {
+                    "HTTP/1.1 304 Not Modified\r\nContent-Length: 0\r\n\r\n"
+                } else {
+                    "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 5\r\n\r\nindex"
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        format!("http://{}/repository.db", addr)
+    }
+
+    #[tokio::test]
+    async fn test_download_if_changed_sends_validators() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dest = tmp.path().join("cache/repository.db");
+        let url = serve(2);
+
+        let validators = download_if_changed(&url, &dest, &Validators::default())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(validators.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "index");
+
+        std::fs::remove_file(&dest).unwrap();
+        assert!(
+            download_if_changed(&url, &dest, &validators)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(!dest.exists());
+    }
+
+    /// Cuts the first response off after 4 of 10 bytes and serves the
+    /// rest to a range request; returns the URL and the received `Range`
+    /// and `If-Range` headers
+    fn serve_interrupted() -> (String, std::sync::mpsc::Receiver<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let (mut range, mut if_range) = (String::new(), String::new());
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.to_ascii_lowercase().starts_with("range:") {
+                        range = line[6..].trim().to_string();
+                    }
+                    if line.to_ascii_lowercase().starts_with("if-range:") {
+                        if_range = line[9..].trim().to_string();
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let response = if range.is_empty() {
+                    "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 10\r\n\r\n0123"
+                } else {
+                    "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 4-9/10\r\nContent-Length: 6\r\n\r\n456789"
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+                tx.send((range, if_range)).unwrap();
+            }
+        });
+        (format!("http://{}/tool-1.0.0.uhp", addr), rx)
+    }
+
+    #[tokio::test]
+    async fn test_download_with_retry_resumes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dest = tmp.path().join("tool-1.0.0.uhp");
+        let (url, ranges) = serve_interrupted();
+        let policy = RetryPolicy {
+            retries: 2,
+            backoff: Duration::from_millis(1),
+        };
+
+        download_with_retry(&url, &dest, policy).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "0123456789");
+        assert!(!partial_path(&dest).exists());
+        assert!(!validator_path(&partial_path(&dest)).exists());
+        let requests: Vec<_> = ranges.iter().take(2).collect();
+        assert_eq!(requests[0], (String::new(), String::new()));
+        assert_eq!(requests[1], ("bytes=4-".to_string(), "\"v1\"".to_string()));
+        assert_eq!(policy.delay(3), Duration::from_millis(8));
+    }
+}
@@ -0,0 +1,144 @@
+//! # IPFS Backend
+//!
+//! Experimental support for `ipfs://<cid>[/path]` package URLs.
+//!
+//! Content is retrieved through HTTP gateways: the local IPFS node first,
+//! then every gateway listed in `Config.ipfs_gateways`. Gateways act as
+//! mirrors, so a package stays reachable when no daemon is running.
+//!
+//! Peer-to-peer schemes without a gateway story (`magnet:` links and
+//! `.torrent` files) are recognized and rejected with
+//! [`FetchError::UnsupportedScheme`].
+
+use crate::config::Config;
+use crate::error::FetchError;
+use crate::{debug, info, warn};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// URL scheme handled by this backend
+pub const SCHEME: &str = "ipfs://";
+
+/// Gateway of a locally running IPFS daemon, always tried first
+pub const LOCAL_GATEWAY: &str = "http://127.0.0.1:8080/ipfs/";
+
+/// Returns `true` if the URL points at IPFS content
+pub fn is_ipfs_url(url: &str) -> bool {
+    url.starts_with(SCHEME)
+}
+
+/// Returns `true` for BitTorrent references, which are not supported yet
+pub fn is_torrent_url(url: &str) -> bool {
+    url.starts_with("magnet:") || url.ends_with(".torrent")
+}
+
+/// Builds the list of HTTP URLs to try for an `ipfs://` URL
+///
+/// The local gateway comes first, followed by `gateways` in order.
+/// Trailing slashes on gateway entries are optional.
+pub fn gateway_urls(url: &str, gateways: &[String]) -> Vec<String> {
+    let content_path = url.strip_prefix(SCHEME).unwrap_or(url);
+
+    std::iter::once(LOCAL_GATEWAY)
+        .chain(gateways.iter().map(String::as_str))
+        .map(|gw| format!("{}/{}", gw.trim_end_matches('/'), content_path))
+        .collect()
+}
+
+/// Chooses the local file name for downloaded IPFS content
+///
+/// The last path segment is used when it already carries the `.uhp`
+/// extension; otherwise the CID is used so the installer accepts the file.
+fn file_name_for(url: &str) -> String {
+    let content_path = url.strip_prefix(SCHEME).unwrap_or(url);
+    let last = content_path.rsplit('/').next().unwrap_or(content_path);
+
+    if last.ends_with(".uhp") {
+        last.to_string()
+    } else {
+        let cid = content_path.split('/').next().unwrap_or("package");
+        format!("{}.uhp", cid)
+    }
+}
+
+/// Downloads IPFS content into `dest_dir`, trying each gateway in turn
+///
+/// # Errors
+/// Returns [`FetchError::AllMirrorsFailed`] if no gateway served the content.
+pub async fn fetch(url: &str, dest_dir: &Path) -> Result<PathBuf, FetchError> {
+    let config = Config::load_or_default();
+    let dest = dest_dir.join(file_name_for(url));
+
+    for candidate in gateway_urls(url, &config.ipfs_gateways) {
+        debug!("fetcher.ipfs.trying_gateway", &candidate);
+
+        let resp = match reqwest::get(&candidate).await {
+            Ok(resp) => resp,
+            Err(e) => {
+                warn!("fetcher.ipfs.gateway_failed", &candidate, e);
+                continue;
+            }
+        };
+
+        let resp = match resp.error_for_status() {
+            Ok(resp) => resp,
+            Err(e) => {
+                warn!("fetcher.ipfs.gateway_failed", &candidate, e);
+                continue;
+            }
+        };
+
+        match resp.bytes().await {
+            Ok(bytes) => {
+                fs::write(&dest, &bytes).await?;
+                info!("fetcher.ipfs.fetched", url, &candidate);
+                return Ok(dest);
+            }
+            Err(e) => {
+                warn!("fetcher.ipfs.gateway_failed", &candidate, e);
+            }
+        }
+    }
+
+    Err(FetchError::AllMirrorsFailed(url.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gateway_urls_order() {
+        let gateways = vec![
+            "https://ipfs.io/ipfs/".to_string(),
+            "https://dweb.link/ipfs".to_string(),
+        ];
+        let urls = gateway_urls("ipfs://bafyabc/pkg-1.0.0.uhp", &gateways);
+
+        assert_eq!(
+            urls,
+            vec![
+                "http://127.0.0.1:8080/ipfs/bafyabc/pkg-1.0.0.uhp",
+                "https://ipfs.io/ipfs/bafyabc/pkg-1.0.0.uhp",
+                "https://dweb.link/ipfs/bafyabc/pkg-1.0.0.uhp",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_file_name_for() {
+        assert_eq!(
+            file_name_for("ipfs://bafyabc/pkg-1.0.0.uhp"),
+            "pkg-1.0.0.uhp"
+        );
+        assert_eq!(file_name_for("ipfs://bafyabc"), "bafyabc.uhp");
+    }
+
+    #[test]
+    fn test_scheme_detection() {
+        assert!(is_ipfs_url("ipfs://bafyabc"));
+        assert!(!is_ipfs_url("https://example.com/pkg.uhp"));
+        assert!(is_torrent_url("magnet:?xt=urn:btih:abc"));
+        assert!(is_torrent_url("https://example.com/pkg.torrent"));
+    }
+}
@@ -0,0 +1,249 @@
+//! # Transaction History
+//!
+//! Every command that changes the installation is recorded as a
+//! transaction in the `history` table of the package database: when it
+//! ran, the command line, and for each package whose current version
+//! changed the version before and after. Comparing the current versions
+//! before and after the command catches every kind of change the same
+//! way, whether the command installed, removed, updated, switched or
+//! rolled back.
+//!
+//! `uhpm history` lists the transactions, `uhpm rollback <id>` brings the
+//! packages a transaction changed back to their versions from before it:
+//! a version still in the store is switched to, a removed one is
+//! installed again, and a package that was new is removed. A rollback is
+//! a transaction of its own and can be rolled back in turn.
+//!
+//! `uhpm undo --last <n>` does the same for the last `n` transactions
+//! together: every package they changed goes back to its version from
+//! before the oldest of them that changed it (see [`undo_targets`]).
+//! Removed versions are reinstalled from the archive cache when possible.
+//! Steps that fail, e.g. because a version is in neither the cache nor a
+//! repository anymore, are skipped and reported; the others still run.
+
+use crate::db::PackageDB;
+use crate::error::UhpmError;
+use crate::{access, warn};
+use semver::Version;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Current version of every installed package
+pub type Snapshot = BTreeMap<String, String>;
+
+/// How a transaction changed a package
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionChange {
+    pub name: String,
+    /// Current version before, `None` if the package was not installed
+    pub before: Option<String>,
+    /// Current version after, `None` if the package was removed
+    pub after: Option<String>,
+}
+
+impl fmt::Display for VersionChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.before, &self.after) {
+            (None, Some(after)) => write!(f, "+{} {}", self.name, after),
+            (Some(before), None) => write!(f, "-{} {}", self.name, before),
+            (Some(before), Some(after)) => write!(f, "{} {} -> {}", self.name, before, after),
+            (None, None) => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Where an undo step gets the version it restores from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UndoSource {
+    /// The version is still in the package store and becomes current
+    Switch,
+    /// The version is reinstalled from the archive cache
+    Cache,
+    /// The version is reinstalled from the repositories
+    Repository,
+    /// The package is removed
+    Remove,
+}
+
+/// A change that undoes part of the recent transactions
+#[derive(Debug, Clone, PartialEq)]
+pub struct UndoStep {
+    pub change: VersionChange,
+    pub source: UndoSource,
+}
+
+/// A recorded transaction
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transaction {
+    pub id: i64,
+    /// RFC 3339 timestamp
+    pub timestamp: String,
+    /// Command line, without the program name
+    pub command: String,
+    pub changes: Vec<VersionChange>,
+}
+
+/// Current versions of the installed packages
+///
+/// A package without a current version counts with its highest one.
+pub async fn snapshot(db: &PackageDB) -> Result<Snapshot, UhpmError> {
+    let mut snapshot = Snapshot::new();
+    let mut highest: BTreeMap<String, Version> = BTreeMap::new();
+    for (name, version, current) in db.list_packages().await? {
+        if current {
+            snapshot.insert(name, version);
+        } else if let Ok(parsed) = Version::parse(&version)
+            && highest.get(&name).is_none_or(|v| &parsed > v)
+        {
+            highest.insert(name, parsed);
+        }
+    }
+    for (name, version) in highest {
+        snapshot.entry(name).or_insert_with(|| version.to_string());
+    }
+    Ok(snapshot)
+}
+
+/// Packages whose current version differs between two snapshots, by name
+pub fn diff(before: &Snapshot, after: &Snapshot) -> Vec<VersionChange> {
+    let mut names: Vec<&String> = before.keys().chain(after.keys()).collect();
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .filter(|name| before.get(*name) != after.get(*name))
+        .map(|name| VersionChange {
+            name: name.clone(),
+            before: before.get(name).cloned(),
+            after: after.get(name).cloned(),
+        })
+        .collect()
+}
+
+/// Records what `command` changed since the snapshot `before`, if
+/// anything; returns the id of the new transaction
+///
+/// Nothing is recorded in read-only mode. Failures are logged, the
+/// command itself already ran.
+pub async fn record(db: &PackageDB, command: &str, before: &Snapshot) -> Option<i64> {
+    if access::is_read_only() {
+        return None;
+    }
+    let result = async {
+        let changes = diff(before, &snapshot(db).await?);
+        if changes.is_empty() {
+            return Ok(None);
+        }
+        let rows: Vec<_> = changes
+            .into_iter()
+            .map(|c| (c.name, c.before, c.after))
+            .collect();
+        let now = chrono::Local::now().to_rfc3339();
+        Ok::<_, UhpmError>(Some(db.add_history(&now, command, &rows).await?))
+    }
+    .await;
+    result.unwrap_or_else(|e| {
+        warn!("history.record_failed", command, e);
+        None
+    })
+}
+
+fn changes(rows: Vec<(String, Option<String>, Option<String>)>) -> Vec<VersionChange> {
+    rows.into_iter()
+        .map(|(name, before, after)| VersionChange {
+            name,
+            before,
+            after,
+        })
+        .collect()
+}
+
+/// All transactions, newest first
+pub async fn list(db: &PackageDB) -> Result<Vec<Transaction>, UhpmError> {
+    let mut transactions = Vec::new();
+    for (id, timestamp, command) in db.list_history().await? {
+        transactions.push(Transaction {
+            id,
+            timestamp,
+            command,
+            changes: changes(db.get_history_changes(id).await?),
+        });
+    }
+    Ok(transactions)
+}
+
+/// The transaction with `id`
+pub async fn get(db: &PackageDB, id: i64) -> Result<Transaction, UhpmError> {
+    let (timestamp, command) = db
+        .get_history(id)
+        .await?
+        .ok_or_else(|| UhpmError::NotFound(format!("transaction {}", id)))?;
+    Ok(Transaction {
+        id,
+        timestamp,
+        command,
+        changes: changes(db.get_history_changes(id).await?),
+    })
+}
+
+/// Versions to restore to undo `transactions`, given newest first: for
+/// each package they changed, its current version from before the oldest
+/// of them that changed it (`None` if it was not installed)
+pub fn undo_targets(transactions: &[Transaction]) -> BTreeMap<String, Option<String>> {
+    let mut targets = BTreeMap::new();
+    for change in transactions.iter().flat_map(|t| &t.changes) {
+        targets.insert(change.name.clone(), change.before.clone());
+    }
+    targets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot_of(entries: &[(&str, &str)]) -> Snapshot {
+        entries
+            .iter()
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_snapshots() {
+        let before = snapshot_of(&[("fd", "9.0.0"), ("jq", "1.6.0"), ("rg", "14.0.0")]);
+        let after = snapshot_of(&[("bat", "0.24.0"), ("jq", "1.6.0"), ("rg", "14.1.0")]);
+
+        let changes: Vec<String> = diff(&before, &after)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(changes, ["+bat 0.24.0", "-fd 9.0.0", "rg 14.0.0 -> 14.1.0"]);
+        assert!(diff(&after, &after).is_empty());
+    }
+
+    #[test]
+    fn test_undo_targets_reach_back_to_oldest_change() {
+        let transaction = |id, before: &Snapshot, after: &Snapshot| Transaction {
+            id,
+            timestamp: String::new(),
+            command: String::new(),
+            changes: diff(before, after),
+        };
+        let first = snapshot_of(&[("jq", "1.6.0")]);
+        let second = snapshot_of(&[("jq", "1.7.0"), ("rg", "14.0.0")]);
+        let third = snapshot_of(&[("jq", "1.7.1"), ("rg", "14.0.0")]);
+        // Newest first, as listed by `list`
+        let transactions = [
+            transaction(2, &second, &third),
+            transaction(1, &first, &second),
+        ];
+
+        let targets = undo_targets(&transactions);
+        assert_eq!(targets["jq"].as_deref(), Some("1.6.0"));
+        assert_eq!(targets["rg"], None);
+        assert_eq!(
+            undo_targets(&transactions[..1])["jq"].as_deref(),
+            Some("1.7.0")
+        );
+    }
+}
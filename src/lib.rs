@@ -1,20 +1,33 @@
+pub mod access;
+pub mod archive;
 pub mod cli;
 pub mod config;
+pub mod crash;
 pub mod db;
+#[cfg(feature = "dbus")]
+pub mod dbus;
 pub mod error;
+pub mod events;
 pub mod fetcher;
+pub mod history;
 pub mod locale;
+pub mod lock;
 pub mod log;
 pub mod package;
+pub mod platform;
+pub mod query;
 pub mod repo;
+pub mod resolver;
 pub mod service;
+pub mod signing;
+pub mod suggest;
 pub mod symlist;
+pub mod usage;
 
 use std::fs;
 
 pub fn clear_tmp() -> std::io::Result<()> {
-    let mut tmp_dir = dirs::home_dir().unwrap();
-    tmp_dir.push(".uhpm/tmp");
+    let tmp_dir = package::installer::tmp_dir();
 
     if tmp_dir.exists() {
         fs::remove_dir_all(&tmp_dir)?;
@@ -40,9 +40,7 @@ impl Locale {
         let path = Path::new("locale").join(format!("{}.ron", lang));
 
         #[cfg(not(debug_assertions))]
-        let path = dirs::home_dir()
-            .unwrap()
-            .join(".uhpm")
+        let path = crate::platform::uhpm_home()
             .join("locale")
             .join(format!("{}.ron", lang));
 
@@ -0,0 +1,115 @@
+//! # Process Lock
+//!
+//! Commands that change the installation hold `~/.uhpm/uhpm.lock` while
+//! they run so that two uhpm processes never modify packages at the same
+//! time. The file holds the id of the owning process; a lock left behind
+//! by a process that no longer runs is taken over.
+//!
+//! The lock is released when the [`Lock`] is dropped, and by the panic hook
+//! (see [`crate::crash`]) if the process goes down before that.
+
+use crate::error::UhpmError;
+use crate::{crash, debug, platform, warn};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Location of the lock file
+pub fn lock_path() -> PathBuf {
+    platform::uhpm_home().join("uhpm.lock")
+}
+
+/// A held lock, released on drop
+#[derive(Debug)]
+pub struct Lock {
+    path: PathBuf,
+}
+
+impl Lock {
+    /// Takes the lock at the default location
+    pub fn acquire() -> Result<Self, UhpmError> {
+        Self::acquire_at(&lock_path())
+    }
+
+    /// Takes the lock at `path`
+    ///
+    /// # Errors
+    /// [`UhpmError::Conflict`] if another running process holds it.
+    pub fn acquire_at(path: &Path) -> Result<Self, UhpmError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(path) {
+                Ok(mut file) => {
+                    write!(file, "{}", std::process::id())?;
+                    debug!("lock.acquired", path.display());
+                    crash::hold_lock(path);
+                    return Ok(Self {
+                        path: path.to_path_buf(),
+                    });
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    let owner = fs::read_to_string(path)
+                        .ok()
+                        .and_then(|pid| pid.trim().parse::<u32>().ok());
+                    match owner {
+                        Some(pid) if platform::process_alive(pid) => {
+                            return Err(UhpmError::Conflict(format!(
+                                "another uhpm process (pid {}) is running; if it is not, delete {}",
+                                pid,
+                                path.display()
+                            )));
+                        }
+                        _ => {
+                            warn!("lock.stale", path.display());
+                            match fs::remove_file(path) {
+                                Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                                    return Err(e.into());
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        crash::release_lock();
+        if let Err(e) = fs::remove_file(&self.path) {
+            debug!("lock.release_failed", self.path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_excludes_and_takes_over_stale() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("uhpm.lock");
+
+        let lock = Lock::acquire_at(&path).unwrap();
+        assert!(matches!(
+            Lock::acquire_at(&path),
+            Err(UhpmError::Conflict(_))
+        ));
+        drop(lock);
+        assert!(!path.exists());
+
+        // Above the largest process id Linux hands out
+        fs::write(&path, "999999999").unwrap();
+        let _lock = Lock::acquire_at(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            std::process::id().to_string()
+        );
+    }
+}
@@ -1,7 +1,7 @@
 //! log.rs
 //!
 //! Localized logging with auto-detected system locale.
-//! Provides macros: info!(), warn!(), debug!(), error!(), lprintln!(), lprint!().
+//! Provides macros: info!(), warn!(), debug!(), error!(), lprintln!(), lcprintln!(), lprint!(), lformat!().
 //! Supports multiple arguments of any type and substitutes them in order.
 
 use crate::locale::Locale;
@@ -97,6 +97,19 @@ macro_rules! lprintln {
     };
 }
 
+/// Like `lprintln!`, but colors the whole line by a [`Role`](crate::cli::color::Role)
+#[macro_export]
+macro_rules! lcprintln {
+    ($role:expr, $key:expr $(, $arg:expr)*) => {
+        {
+            let template = $crate::log::LOGGER.msg($key);
+            let args: Vec<String> = vec![$($crate::log::fmt_debug($arg)),*];
+            let msg = $crate::log::format_ordered(&template, &args);
+            println!("{}", $crate::cli::color::paint($role, &msg));
+        }
+    };
+}
+
 #[macro_export]
 macro_rules! lprint {
     ($key:expr $(, $arg:expr)*) => {
@@ -108,3 +121,14 @@ macro_rules! lprint {
         }
     };
 }
+
+#[macro_export]
+macro_rules! lformat {
+    ($key:expr $(, $arg:expr)*) => {
+        {
+            let template = $crate::log::LOGGER.msg($key);
+            let args: Vec<String> = vec![$($crate::log::fmt_debug($arg)),*];
+            $crate::log::format_ordered(&template, &args)
+        }
+    };
+}
@@ -1,17 +1,92 @@
 use clap::Parser;
-use dirs;
+use std::process::ExitCode;
+use std::sync::Arc;
 use uhpm::cli::Cli;
+use uhpm::cli::color;
+use uhpm::cli::progress::IndicatifSink;
+use uhpm::cli::setup;
+use uhpm::config::Config;
 use uhpm::db::PackageDB;
+use uhpm::error::UhpmError;
+use uhpm::events::{self, FanoutSink, ProgressSink};
+use uhpm::lock::Lock;
 use uhpm::service::PackageService;
-use uhpm::{debug, info};
+use uhpm::{access, cli, crash, debug, info, platform, warn};
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tracing_subscriber::fmt::init();
+async fn main() -> ExitCode {
+    crash::install_hook();
+    let args = Cli::parse();
+    if let Some(root) = &args.root
+        && let Err(e) = platform::set_root(root)
+    {
+        return ExitCode::from(cli::errors::print(&UhpmError::Io(e), args.verbose));
+    }
+    crash::set_operation(args.command.name());
+    color::init(args.color, Config::load_or_default().theme);
+    // Logs go to stderr so that output such as `repo export` can be piped
+    tracing_subscriber::fmt()
+        .with_ansi(color::enabled())
+        .with_writer(std::io::stderr)
+        .init();
+    #[allow(unused_mut)]
+    let mut sinks: Vec<Arc<dyn ProgressSink>> = vec![Arc::new(IndicatifSink::new())];
+    #[cfg(feature = "dbus")]
+    let dbus = if args.command.modifies_installation() && !args.dry_run {
+        match uhpm::dbus::DbusSink::connect().await {
+            Ok((sink, task)) => {
+                sinks.push(Arc::new(sink));
+                Some(task)
+            }
+            Err(e) => {
+                debug!("main.dbus_unavailable", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    events::set_sink(Arc::new(FanoutSink::new(sinks)));
+
+    let result = run(&args).await;
+    #[cfg(feature = "dbus")]
+    if let Some(task) = dbus {
+        // Drops the D-Bus sink, so that its task ends once the pending
+        // signals are out
+        events::clear_sink();
+        task.finish().await;
+    }
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => ExitCode::from(cli::errors::print(e.as_ref(), args.verbose)),
+    }
+}
 
-    let mut db_path = dirs::home_dir().ok_or("Could not determine home directory")?;
-    db_path.push(".uhpm");
-    db_path.push("packages.db");
+async fn run(args: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let home = platform::uhpm_home();
+    if args.dry_run {
+        if args.command.needs_write_access() && !args.command.supports_dry_run() {
+            return Err(UhpmError::Validation(format!(
+                "--dry-run is not supported by {}",
+                args.command.name()
+            ))
+            .into());
+        }
+        // Planning only reads; nothing may be written on the way
+        access::enable();
+    } else if args.read_only || !access::writable(&home) {
+        if !args.read_only {
+            warn!("main.read_only_detected", home.display());
+        }
+        access::enable();
+        if args.command.needs_write_access() {
+            return Err(UhpmError::ReadOnly(home).into());
+        }
+    } else if args.command.wants_first_run_setup() && setup::needed() {
+        setup::first_run()?;
+    }
+
+    let db_path = platform::uhpm_home().join("packages.db");
 
     debug!("main.info.using_package_db");
     debug!("main.info.db_path_is", db_path.display());
@@ -21,8 +96,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     info!("main.info.uhpm_started");
 
-    let args = Cli::parse();
-    args.run(&package_service).await?;
+    let _lock = if args.command.modifies_installation() && !args.dry_run {
+        Some(Lock::acquire()?)
+    } else {
+        None
+    };
 
-    Ok(())
+    args.run(&package_service).await
 }
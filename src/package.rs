@@ -1,17 +1,53 @@
 //! # Package Module
 
 use crate::error::MetaParseError;
+use crate::package::assets::Assets;
+use crate::package::systemd::SystemdUnit;
 use semver::Version;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
+pub mod app_bundles;
+pub mod archive_cache;
+pub mod assets;
+pub mod autoremove;
+pub mod bin_layout;
+pub mod builder;
+pub mod bundle;
+pub mod closure;
+pub mod conflicts;
+pub mod docs;
+pub mod dry_run;
+pub mod elf;
+pub mod env;
+pub mod hooks;
+pub mod ignore;
+pub mod info;
 pub mod installer;
+pub mod licenses;
+pub mod links;
+pub mod listing;
+pub mod maintenance;
+pub mod packer;
+pub mod pattern;
+pub mod profile;
 pub mod remover;
+pub mod requirements;
+pub mod retention;
+pub mod runner;
+pub mod sandbox;
 pub mod switcher;
+pub mod systemd;
+pub mod toolchain;
+pub mod transaction;
+pub mod tree;
 pub mod updater;
+pub mod verify;
+pub mod which;
 
 /// Represents the source of a package.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(tag = "type", content = "value")]
 pub enum Source {
     Url(String),
@@ -28,6 +64,209 @@ impl Source {
     }
 }
 
+/// Where an installed package version came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstallOrigin {
+    /// Downloaded from a configured repository
+    Repo,
+    /// Installed from a local `.uhp` file
+    File,
+}
+
+impl InstallOrigin {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InstallOrigin::Repo => "repo",
+            InstallOrigin::File => "file",
+        }
+    }
+
+    /// Parses a stored origin, treating unknown values as [`InstallOrigin::Repo`]
+    pub fn from_str_lossy(s: &str) -> Self {
+        match s {
+            "file" => InstallOrigin::File,
+            _ => InstallOrigin::Repo,
+        }
+    }
+}
+
+/// Why a package is installed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstallReason {
+    /// Requested by the user
+    Explicit,
+    /// Pulled in to satisfy another package's dependencies
+    Dependency,
+}
+
+impl InstallReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InstallReason::Explicit => "explicit",
+            InstallReason::Dependency => "dependency",
+        }
+    }
+
+    /// Parses a stored reason, treating unknown values as [`InstallReason::Explicit`]
+    pub fn from_str_lossy(s: &str) -> Self {
+        match s {
+            "dependency" => InstallReason::Dependency,
+            _ => InstallReason::Explicit,
+        }
+    }
+}
+
+/// How a package's files were placed outside the package store.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkMode {
+    /// Symlinks into the package directory
+    #[default]
+    Symlink,
+    /// Plain copies (`--direct`)
+    Copy,
+}
+
+impl LinkMode {
+    pub fn from_direct(direct: bool) -> Self {
+        if direct {
+            LinkMode::Copy
+        } else {
+            LinkMode::Symlink
+        }
+    }
+
+    pub fn is_copy(self) -> bool {
+        self == LinkMode::Copy
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkMode::Symlink => "symlink",
+            LinkMode::Copy => "copy",
+        }
+    }
+
+    /// Parses a stored link mode; unknown values yield `None`
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "symlink" => Some(LinkMode::Symlink),
+            "copy" => Some(LinkMode::Copy),
+            _ => None,
+        }
+    }
+
+    /// Mode for placing the files of `pkg_name`: `choice` (`--direct` or
+    /// `--symlink`) when given, else the mode recorded for the package, else
+    /// `link_mode` in `config.ron`
+    pub async fn resolve(
+        db: &crate::db::PackageDB,
+        pkg_name: &str,
+        choice: Option<LinkMode>,
+    ) -> Result<LinkMode, sqlx::Error> {
+        if let Some(mode) = choice {
+            return Ok(mode);
+        }
+        Ok(match db.get_package_link_mode(pkg_name).await? {
+            Some(mode) => mode,
+            None => crate::config::Config::load_or_default().link_mode,
+        })
+    }
+
+    /// The mode `--direct` or `--symlink` ask for, if any
+    pub fn from_flags(direct: bool, symlink: bool) -> Option<Self> {
+        match (direct, symlink) {
+            (true, _) => Some(LinkMode::Copy),
+            (_, true) => Some(LinkMode::Symlink),
+            _ => None,
+        }
+    }
+}
+
+/// Where exactly an installed package version came from.
+///
+/// Unlike the free-form [`Package::src`] field this is recorded by the
+/// installer itself and stored as JSON in the `provenance` column.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Provenance {
+    /// Downloaded from a repository; `repo` is the name from `repos.ron`
+    Repo { repo: Option<String>, url: String },
+    /// Installed from a local archive, with the git revision of its directory
+    File {
+        path: String,
+        git_rev: Option<String>,
+    },
+    /// Reinstalled from the archive cache
+    Cache { archive: String },
+}
+
+impl Provenance {
+    /// Provenance of a package downloaded from `url`
+    pub fn from_url(url: &str) -> Self {
+        Provenance::Repo {
+            repo: crate::repo::repo_name_for_url(url),
+            url: url.to_string(),
+        }
+    }
+
+    /// Provenance of a local archive, recording the git revision if it lives in a work tree
+    pub fn from_local_file(path: &Path) -> Self {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let git_rev = path.parent().and_then(|dir| {
+            std::process::Command::new("git")
+                .arg("-C")
+                .arg(dir)
+                .args(["rev-parse", "HEAD"])
+                .output()
+                .ok()
+                .filter(|out| out.status.success())
+                .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
+        });
+        Provenance::File {
+            path: path.to_string_lossy().to_string(),
+            git_rev,
+        }
+    }
+
+    /// Coarse origin used by `update` to skip local packages
+    pub fn origin(&self) -> InstallOrigin {
+        match self {
+            Provenance::File { .. } => InstallOrigin::File,
+            Provenance::Repo { .. } | Provenance::Cache { .. } => InstallOrigin::Repo,
+        }
+    }
+
+    /// Repository name, if the package came from a known repository
+    pub fn repo(&self) -> Option<&str> {
+        match self {
+            Provenance::Repo { repo, .. } => repo.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Provenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Provenance::Repo {
+                repo: Some(repo),
+                url,
+            } => write!(f, "repo {} ({})", repo, url),
+            Provenance::Repo { repo: None, url } => write!(f, "{}", url),
+            Provenance::File {
+                path,
+                git_rev: Some(rev),
+            } => write!(f, "file {} (git {})", path, rev),
+            Provenance::File {
+                path,
+                git_rev: None,
+            } => write!(f, "file {}", path),
+            Provenance::Cache { archive } => write!(f, "cached archive {}", archive),
+        }
+    }
+}
+
 /// Represents a dependency with name and version
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Dependency {
@@ -45,6 +284,27 @@ pub struct Package {
     checksum: String,
     #[serde(default)]
     dependencies: Vec<Dependency>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    systemd: Vec<SystemdUnit>,
+    #[serde(default, skip_serializing_if = "Assets::is_empty")]
+    assets: Assets,
+    /// Primary executable, relative to the package root (used by `uhpm run`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bin: Option<String>,
+    /// SPDX license expression, e.g. `MIT OR Apache-2.0`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    license: Option<String>,
+    /// Documentation files, relative to the package root (used by `uhpm docs`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    docs: Vec<String>,
+    /// Environment variables to export, `{root}` is the package root (used
+    /// by `uhpm env`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    env: BTreeMap<String, String>,
+    /// Programs needed at runtime and their versions, e.g. `python3 =
+    /// ">=3.10"`, see [`requirements`]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    requires: BTreeMap<String, String>,
 }
 
 impl Package {
@@ -68,6 +328,13 @@ impl Package {
             src,
             checksum: checksum.into(),
             dependencies: deps,
+            systemd: Vec::new(),
+            assets: Assets::default(),
+            bin: None,
+            license: None,
+            docs: Vec::new(),
+            env: BTreeMap::new(),
+            requires: BTreeMap::new(),
         }
     }
 
@@ -98,6 +365,40 @@ impl Package {
             .collect()
     }
 
+    /// Systemd user units shipped by the package
+    pub fn systemd_units(&self) -> &[SystemdUnit] {
+        &self.systemd
+    }
+
+    /// Typed font, icon and MIME assets shipped by the package
+    pub fn assets(&self) -> &Assets {
+        &self.assets
+    }
+
+    /// Primary executable declared by the package, if any
+    pub fn bin(&self) -> Option<&str> {
+        self.bin.as_deref()
+    }
+
+    /// SPDX license expression declared by the package, if any
+    pub fn license(&self) -> Option<&str> {
+        self.license.as_deref()
+    }
+
+    /// Documentation files declared by the package
+    pub fn docs(&self) -> &[String] {
+        &self.docs
+    }
+
+    /// Environment variables declared by the package
+    pub fn env(&self) -> &BTreeMap<String, String> {
+        &self.env
+    }
+
+    pub fn requires(&self) -> &BTreeMap<String, String> {
+        &self.requires
+    }
+
     pub fn from_toml_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
         let data = fs::read_to_string(path)?;
         let pkg: Package = toml::from_str(&data)?;
@@ -112,6 +413,13 @@ impl Package {
             src: Source::Raw("TODO".to_string()),
             checksum: "TODO".to_string(),
             dependencies: vec![],
+            systemd: vec![],
+            assets: Assets::default(),
+            bin: None,
+            license: None,
+            docs: Vec::new(),
+            env: BTreeMap::new(),
+            requires: BTreeMap::new(),
         }
     }
 
@@ -133,9 +441,72 @@ pub fn meta_parser(meta_path: &Path) -> Result<Package, MetaParseError> {
     Ok(pkg)
 }
 
+/// Root of the package store (`~/.uhpm/packages`)
+pub fn packages_dir() -> PathBuf {
+    crate::platform::uhpm_home().join("packages")
+}
+
+/// A version given on the command line after `@`
+///
+/// Either an exact version (`1.4.2`) or a constraint in Cargo syntax
+/// (`^2`, `~1.4`, `>=1.2, <2`, `1.*`). A bare version is always exact,
+/// unlike in Cargo where `1.4.2` means `^1.4.2`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VersionSpec {
+    Exact(Version),
+    Req(semver::VersionReq),
+}
+
+impl std::str::FromStr for VersionSpec {
+    type Err = semver::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Version::parse(s) {
+            Ok(version) => Ok(VersionSpec::Exact(version)),
+            Err(_) => semver::VersionReq::parse(s).map(VersionSpec::Req),
+        }
+    }
+}
+
+impl VersionSpec {
+    pub fn matches(&self, version: &Version) -> bool {
+        match self {
+            VersionSpec::Exact(exact) => exact == version,
+            VersionSpec::Req(req) => req.matches(version),
+        }
+    }
+
+    /// Picks the highest matching version
+    pub fn best_match<'a>(
+        &self,
+        versions: impl IntoIterator<Item = &'a Version>,
+    ) -> Option<&'a Version> {
+        versions.into_iter().filter(|v| self.matches(v)).max()
+    }
+}
+
+impl std::fmt::Display for VersionSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VersionSpec::Exact(version) => write!(f, "{}", version),
+            VersionSpec::Req(req) => write!(f, "{}", req),
+        }
+    }
+}
+
 pub fn get_pkg_path(pkg_name: &str, pkg_ver: Version) -> PathBuf {
-    let packages_path: PathBuf = dirs::home_dir().unwrap().join(".uhpm").join("packages");
-    packages_path.join(format!("{}-{}", pkg_name, pkg_ver.to_string()))
+    packages_dir().join(format!("{}-{}", pkg_name, pkg_ver.to_string()))
+}
+
+/// `uhp.toml` of a package without dependencies, for tests that need one
+/// on disk
+#[cfg(test)]
+pub(crate) fn test_manifest(name: &str, version: &str) -> String {
+    format!(
+        "name = \"{}\"\nauthor = \"a\"\nversion = \"{}\"\nchecksum = \"\"\n\n\
+         [src]\ntype = \"Raw\"\nvalue = \"\"\n",
+        name, version
+    )
 }
 
 #[cfg(test)]
@@ -179,6 +550,23 @@ version = "1.0.0"
         assert_eq!(pkg.dependencies()[0].1, Version::parse("1.0.0").unwrap());
     }
 
+    #[test]
+    fn test_systemd_units_parsing() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let toml_path = tmp_dir.path().join("uhp.toml");
+        let content = format!(
+            "{}\n[[systemd]]\nunit = \"share/systemd/app.service\"\nenable = true\n",
+            sample_package_toml()
+        );
+        fs::write(&toml_path, content).unwrap();
+
+        let pkg = Package::from_toml_file(&toml_path).unwrap();
+        assert_eq!(pkg.systemd_units().len(), 1);
+        assert_eq!(pkg.systemd_units()[0].name(), Some("app.service"));
+        assert!(pkg.systemd_units()[0].enable);
+        assert!(!pkg.systemd_units()[0].start);
+    }
+
     #[test]
     fn test_meta_parser() {
         let tmp_dir = tempfile::tempdir().unwrap();
@@ -243,4 +631,38 @@ version = "1.0.0"
         let deserialized: Package = toml::from_str(&toml_str).unwrap();
         assert_eq!(pkg.name(), deserialized.name());
     }
+
+    #[test]
+    fn test_provenance_roundtrip() {
+        let prov = Provenance::Repo {
+            repo: Some("main".to_string()),
+            url: "file:///srv/repo/pkg.uhp".to_string(),
+        };
+        let json = serde_json::to_string(&prov).unwrap();
+        assert!(json.contains("\"kind\":\"repo\""));
+        assert_eq!(serde_json::from_str::<Provenance>(&json).unwrap(), prov);
+        assert_eq!(prov.origin(), InstallOrigin::Repo);
+        assert_eq!(prov.repo(), Some("main"));
+    }
+
+    #[test]
+    fn test_version_spec() {
+        let exact: VersionSpec = "1.4.2".parse().unwrap();
+        assert_eq!(exact, VersionSpec::Exact(Version::new(1, 4, 2)));
+        assert!(!exact.matches(&Version::new(1, 4, 3)));
+
+        let versions = [
+            Version::new(1, 4, 0),
+            Version::new(1, 4, 9),
+            Version::new(1, 5, 0),
+            Version::new(2, 1, 0),
+        ];
+        let tilde: VersionSpec = "~1.4".parse().unwrap();
+        assert_eq!(tilde.best_match(&versions), Some(&Version::new(1, 4, 9)));
+        let caret: VersionSpec = "^2".parse().unwrap();
+        assert_eq!(caret.best_match(&versions), Some(&Version::new(2, 1, 0)));
+        let none: VersionSpec = "^3".parse().unwrap();
+        assert_eq!(none.best_match(&versions), None);
+        assert!("not a version".parse::<VersionSpec>().is_err());
+    }
 }
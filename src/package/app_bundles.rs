@@ -0,0 +1,135 @@
+//! # macOS App Bundles
+//!
+//! `.app` bundles shipped by a package, either at the package root or in
+//! an `Applications/` directory, are linked into `~/Applications` so they
+//! show up in Finder, Launchpad and Spotlight without a symlist entry.
+//!
+//! On other platforms [`platform::applications_dir()`] is `None` and the
+//! bundles are left alone. An existing bundle in `~/Applications` that is
+//! not a link is never replaced, and only copies the package recorded are
+//! ever removed.
+
+use crate::package::LinkMode;
+use crate::{debug, platform, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directories searched for bundles, relative to the package root
+const BUNDLE_DIRS: [&str; 2] = ["", "Applications"];
+
+/// Finds the `.app` bundles of an unpacked package
+pub fn bundles(package_root: &Path) -> Vec<PathBuf> {
+    let mut found: Vec<PathBuf> = BUNDLE_DIRS
+        .iter()
+        .filter_map(|dir| fs::read_dir(package_root.join(dir)).ok())
+        .flat_map(|entries| entries.flatten())
+        .map(|e| e.path())
+        .filter(|p| p.is_dir() && p.extension().is_some_and(|ext| ext == "app"))
+        .collect();
+    found.sort();
+    found
+}
+
+/// `(bundle, link)` pairs for the package's bundles
+fn placements(package_root: &Path, apps_dir: &Path) -> Vec<(PathBuf, PathBuf)> {
+    bundles(package_root)
+        .into_iter()
+        .filter_map(|bundle| {
+            let name = bundle.file_name()?.to_owned();
+            Some((bundle, apps_dir.join(name)))
+        })
+        .collect()
+}
+
+/// Paths the package's bundles are linked to, empty without an
+/// applications directory
+pub fn targets(package_root: &Path) -> Vec<PathBuf> {
+    platform::applications_dir()
+        .map(|apps_dir| {
+            placements(package_root, &apps_dir)
+                .into_iter()
+                .map(|(_, dst)| dst)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Links (or copies, in `direct` mode) the package's bundles into the
+/// applications directory
+///
+/// Returns the created paths so they can be tracked as installed files.
+pub fn link(package_root: &Path, direct: bool) -> std::io::Result<Vec<PathBuf>> {
+    let Some(apps_dir) = platform::applications_dir() else {
+        return Ok(Vec::new());
+    };
+
+    let mut linked = Vec::new();
+    for (bundle, dst) in placements(package_root, &apps_dir) {
+        match fs::symlink_metadata(&dst) {
+            Ok(meta) if meta.file_type().is_symlink() => fs::remove_file(&dst)?,
+            Ok(_) => {
+                warn!("package.app_bundles.exists", dst.display());
+                continue;
+            }
+            Err(_) => {}
+        }
+        fs::create_dir_all(&apps_dir)?;
+        if direct {
+            super::installer::copy_tree(&bundle, &dst)?;
+        } else {
+            platform::symlink(&bundle, &dst)?;
+        }
+        debug!("package.app_bundles.linked", dst.display());
+        linked.push(dst);
+    }
+    Ok(linked)
+}
+
+/// Removes the package's bundles from the applications directory
+///
+/// Links into `package_root` are removed; a copied bundle only if
+/// `recorded`, the installed files of the version with their modes, lists
+/// it as a copy, so a user's own bundle of the same name is never deleted.
+pub fn unlink(package_root: &Path, recorded: &[(String, Option<LinkMode>)]) -> std::io::Result<()> {
+    let Some(apps_dir) = platform::applications_dir() else {
+        return Ok(());
+    };
+
+    let is_recorded_copy = |dst: &Path| {
+        recorded
+            .iter()
+            .any(|(file, mode)| *mode == Some(LinkMode::Copy) && Path::new(file) == dst)
+    };
+    for (_, dst) in placements(package_root, &apps_dir) {
+        match fs::read_link(&dst) {
+            Ok(target) if target.starts_with(package_root) => fs::remove_file(&dst)?,
+            Ok(_) => continue,
+            Err(_) if dst.is_dir() && is_recorded_copy(&dst) => fs::remove_dir_all(&dst)?,
+            Err(_) => continue,
+        }
+        debug!("package.app_bundles.unlinked", dst.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundles_found_at_root_and_in_applications() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir_all(tmp.path().join("Tool.app/Contents")).unwrap();
+        fs::create_dir_all(tmp.path().join("Applications/Viewer.app")).unwrap();
+        fs::create_dir_all(tmp.path().join("bin")).unwrap();
+        fs::write(tmp.path().join("notes.app"), "").unwrap();
+
+        assert_eq!(
+            bundles(tmp.path()),
+            vec![
+                tmp.path().join("Applications/Viewer.app"),
+                tmp.path().join("Tool.app"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,117 @@
+//! # Archive Cache
+//!
+//! When `cache.keep_archives` is enabled in `config.ron`, every installed
+//! `.uhp` archive is kept in `~/.uhpm/cache/archives`:
+//!
+//! ```text
+//! ~/.uhpm/cache/archives/<sha256>.uhp
+//! ~/.uhpm/cache/archives/by-name/<name>-<version>.uhp -> ../<sha256>.uhp
+//! ```
+//!
+//! Archives are stored by content checksum, so identical archives are kept
+//! once, together with their detached signature (`.uhp.sig`) if they have
+//! one. The `by-name` links let rollback, downgrade and reinstall find an
+//! archive without asking the repository, even after the package was removed.
+
+use crate::{debug, platform, signing};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Directory holding cached archives
+pub fn cache_dir() -> PathBuf {
+    platform::uhpm_home().join("cache/archives")
+}
+
+/// Computes the hex-encoded SHA-256 of a file
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Stores an archive in `dir` and links it under `by-name`
+///
+/// Returns the checksum of the archive.
+pub fn store_in(dir: &Path, archive: &Path, name: &str, version: &str) -> io::Result<String> {
+    let checksum = sha256_file(archive)?;
+    let blob_name = format!("{}.uhp", checksum);
+    let blob = dir.join(&blob_name);
+    if !blob.exists() {
+        fs::create_dir_all(dir)?;
+        fs::copy(archive, &blob)?;
+    }
+    let sig = signing::signature_path(archive);
+    if sig.exists() {
+        fs::copy(&sig, signing::signature_path(&blob))?;
+    }
+
+    let by_name = dir.join("by-name");
+    fs::create_dir_all(&by_name)?;
+    let link = by_name.join(format!("{}-{}.uhp", name, version));
+    if fs::symlink_metadata(&link).is_ok() {
+        fs::remove_file(&link)?;
+    }
+    let link_sig = signing::signature_path(&link);
+    if fs::symlink_metadata(&link_sig).is_ok() {
+        fs::remove_file(&link_sig)?;
+    }
+    let blob_sig = signing::signature_path(&blob);
+    if platform::must_copy() {
+        fs::copy(&blob, &link)?;
+        if blob_sig.exists() {
+            fs::copy(&blob_sig, &link_sig)?;
+        }
+    } else {
+        platform::symlink(&Path::new("..").join(&blob_name), &link)?;
+        if blob_sig.exists() {
+            platform::symlink(
+                &Path::new("..").join(format!("{}.sig", blob_name)),
+                &link_sig,
+            )?;
+        }
+    }
+
+    debug!("package.archive_cache.stored", name, version, &checksum);
+    Ok(checksum)
+}
+
+/// Stores an archive in the default cache directory
+pub fn store(archive: &Path, name: &str, version: &str) -> io::Result<String> {
+    store_in(&cache_dir(), archive, name, version)
+}
+
+/// Finds a cached archive for a package version in `dir`
+pub fn lookup_in(dir: &Path, name: &str, version: &str) -> Option<PathBuf> {
+    let link = dir
+        .join("by-name")
+        .join(format!("{}-{}.uhp", name, version));
+    link.exists().then_some(link)
+}
+
+/// Finds a cached archive for a package version
+pub fn lookup(name: &str, version: &str) -> Option<PathBuf> {
+    lookup_in(&cache_dir(), name, version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_and_lookup() {
+        let tmp = tempfile::tempdir().unwrap();
+        let archive = tmp.path().join("pkg.uhp");
+        fs::write(&archive, b"archive").unwrap();
+        let cache = tmp.path().join("cache");
+
+        let checksum = store_in(&cache, &archive, "pkg", "1.0.0").unwrap();
+        assert!(cache.join(format!("{}.uhp", checksum)).exists());
+
+        let found = lookup_in(&cache, "pkg", "1.0.0").unwrap();
+        assert_eq!(fs::read(found).unwrap(), b"archive");
+        assert!(lookup_in(&cache, "pkg", "2.0.0").is_none());
+    }
+}
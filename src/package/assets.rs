@@ -0,0 +1,281 @@
+//! # Typed Assets
+//!
+//! Fonts, icons and MIME definitions declared in `uhp.toml` are placed in
+//! their XDG locations by the installer, so packagers don't have to
+//! hand-write symlist entries and cache refresh hooks for them.
+//!
+//! ```toml
+//! [assets]
+//! fonts = ["fonts/MyFont.ttf"]
+//! mime = ["mime/my-app.xml"]
+//!
+//! [[assets.icons]]
+//! file = "icons/my-app.svg"
+//! size = "scalable"
+//! ```
+//!
+//! | Category | Target                                                |
+//! |----------|-------------------------------------------------------|
+//! | fonts    | `$XDG_DATA_HOME/fonts/<package>/<file>`¹              |
+//! | icons    | `$XDG_DATA_HOME/icons/<theme>/<size>/<context>/<file>`|
+//! | mime     | `$XDG_DATA_HOME/mime/packages/<file>`                 |
+//!
+//! ¹ `~/Library/Fonts/<package>/<file>` on macOS.
+//!
+//! Files already at a target are handled by the replace policy of
+//! [`conflicts`](crate::package::conflicts), as for symlist entries.
+//!
+//! After linking or unlinking, `fc-cache`, `gtk-update-icon-cache` and
+//! `update-mime-database` are run for the affected categories. Missing
+//! tools are logged and ignored.
+
+use crate::package::Package;
+use crate::package::conflicts::Claim;
+use crate::{debug, info, platform, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Asset declarations of a package
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Assets {
+    /// Font files, relative to the package root
+    pub fonts: Vec<String>,
+    /// Icons with their theme placement
+    pub icons: Vec<IconAsset>,
+    /// Shared MIME-info XML files, relative to the package root
+    pub mime: Vec<String>,
+}
+
+impl Assets {
+    pub fn is_empty(&self) -> bool {
+        self.fonts.is_empty() && self.icons.is_empty() && self.mime.is_empty()
+    }
+}
+
+/// An icon and where it belongs in an icon theme
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IconAsset {
+    /// Icon file, relative to the package root
+    pub file: String,
+    /// Icon theme, `hicolor` by default
+    #[serde(default = "default_theme")]
+    pub theme: String,
+    /// Size directory such as `48x48`, `scalable` by default
+    #[serde(default = "default_size")]
+    pub size: String,
+    /// Icon context such as `apps` or `mimetypes`, `apps` by default
+    #[serde(default = "default_context")]
+    pub context: String,
+}
+
+fn default_theme() -> String {
+    "hicolor".to_string()
+}
+
+fn default_size() -> String {
+    "scalable".to_string()
+}
+
+fn default_context() -> String {
+    "apps".to_string()
+}
+
+/// Returns `$XDG_DATA_HOME`, defaulting to the platform data directory
+///
+/// With an alternate root the environment is ignored.
+fn data_home() -> PathBuf {
+    if platform::root().is_some() {
+        return platform::default_data_home();
+    }
+    std::env::var("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| platform::default_data_home())
+}
+
+fn file_name(rel: &str) -> Option<&str> {
+    Path::new(rel).file_name().and_then(|n| n.to_str())
+}
+
+/// Computes `(source, target)` pairs for every declared asset
+pub fn placements(package_root: &Path, pkg: &Package) -> Vec<(PathBuf, PathBuf)> {
+    let assets = pkg.assets();
+    let data = data_home();
+    let mut pairs = Vec::new();
+
+    for font in &assets.fonts {
+        if let Some(name) = file_name(font) {
+            pairs.push((
+                package_root.join(font),
+                platform::fonts_dir().join(pkg.name()).join(name),
+            ));
+        }
+    }
+    for icon in &assets.icons {
+        if let Some(name) = file_name(&icon.file) {
+            pairs.push((
+                package_root.join(&icon.file),
+                data.join("icons")
+                    .join(&icon.theme)
+                    .join(&icon.size)
+                    .join(&icon.context)
+                    .join(name),
+            ));
+        }
+    }
+    for mime in &assets.mime {
+        if let Some(name) = file_name(mime) {
+            pairs.push((
+                package_root.join(mime),
+                data.join("mime/packages").join(name),
+            ));
+        }
+    }
+
+    pairs
+}
+
+/// Places the package's assets and refreshes the affected caches
+///
+/// A file already at a target is only replaced as `claim` allows.
+/// Returns the created paths so they can be tracked as installed files.
+pub fn link(
+    package_root: &Path,
+    pkg: &Package,
+    direct: bool,
+    claim: &Claim,
+) -> std::io::Result<Vec<PathBuf>> {
+    if pkg.assets().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut linked = Vec::new();
+    for (src, dst) in placements(package_root, pkg) {
+        if !src.exists() {
+            warn!("package.assets.not_found", src.display());
+            continue;
+        }
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        claim.clear(package_root, &dst)?;
+        if direct {
+            fs::copy(&src, &dst)?;
+        } else {
+            platform::symlink(&src, &dst)?;
+        }
+        debug!("package.assets.linked", dst.display());
+        linked.push(dst);
+    }
+
+    refresh(pkg);
+    Ok(linked)
+}
+
+/// Removes the package's assets that point into `package_root`
+pub fn unlink(package_root: &Path, pkg: &Package, direct: bool) -> std::io::Result<()> {
+    if pkg.assets().is_empty() {
+        return Ok(());
+    }
+
+    for (_, dst) in placements(package_root, pkg) {
+        let owned = match fs::read_link(&dst) {
+            Ok(target) => target.starts_with(package_root),
+            Err(_) => direct && dst.is_file(),
+        };
+        if owned {
+            fs::remove_file(&dst)?;
+            debug!("package.assets.unlinked", dst.display());
+        }
+    }
+
+    refresh(pkg);
+    Ok(())
+}
+
+/// Runs cache refresh tools for the asset categories the package uses
+pub fn refresh(pkg: &Package) {
+    let assets = pkg.assets();
+    let data = data_home();
+
+    if !assets.fonts.is_empty() {
+        run_tool("fc-cache", &[&platform::fonts_dir().to_string_lossy()]);
+    }
+    if !assets.icons.is_empty() {
+        let mut themes: Vec<&str> = assets.icons.iter().map(|i| i.theme.as_str()).collect();
+        themes.sort();
+        themes.dedup();
+        for theme in themes {
+            let theme_dir = data.join("icons").join(theme);
+            run_tool(
+                "gtk-update-icon-cache",
+                &["-f", "-t", &theme_dir.to_string_lossy()],
+            );
+        }
+    }
+    if !assets.mime.is_empty() {
+        run_tool(
+            "update-mime-database",
+            &[&data.join("mime").to_string_lossy()],
+        );
+    }
+}
+
+fn run_tool(tool: &str, args: &[&str]) {
+    info!("package.assets.refreshing", tool);
+    match Command::new(tool).args(args).status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => warn!("package.assets.tool_failed", tool, status.code()),
+        Err(e) => warn!("package.assets.tool_unavailable", tool, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_icon_defaults() {
+        let assets: Assets = toml::from_str(
+            r#"
+fonts = ["fonts/A.ttf"]
+
+[[icons]]
+file = "icons/app.svg"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(assets.fonts, vec!["fonts/A.ttf"]);
+        assert_eq!(assets.icons[0].theme, "hicolor");
+        assert_eq!(assets.icons[0].size, "scalable");
+        assert_eq!(assets.icons[0].context, "apps");
+        assert!(assets.mime.is_empty());
+        assert!(!assets.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_user_files_are_not_replaced() {
+        let tmp = tempfile::tempdir().unwrap();
+        let package_root = tmp.path().join("app-1.0.0");
+        fs::create_dir_all(package_root.join("mime")).unwrap();
+        fs::write(package_root.join("mime/app.xml"), "<mime-info/>").unwrap();
+        let pkg: Package = toml::from_str(&format!(
+            "{}\n[assets]\nmime = [\"mime/app.xml\"]\n",
+            crate::package::test_manifest("app", "1.0.0")
+        ))
+        .unwrap();
+
+        platform::with_root(&tmp.path().join("home"), async {
+            let (_, dst) = placements(&package_root, &pkg).remove(0);
+            fs::create_dir_all(dst.parent().unwrap()).unwrap();
+            fs::write(&dst, "mine").unwrap();
+
+            assert!(link(&package_root, &pkg, false, &Claim::default()).is_err());
+            assert_eq!(fs::read_to_string(&dst).unwrap(), "mine");
+        })
+        .await;
+    }
+}
@@ -0,0 +1,84 @@
+//! # Orphan Packages
+//!
+//! Every installed package records why it is installed (see
+//! [`InstallReason`]): packages requested by the user are explicit, packages
+//! pulled in by the resolver to satisfy another package are dependencies.
+//!
+//! A dependency becomes an orphan once no installed package depends on it
+//! anymore. Removing orphans can orphan their own dependencies in turn, so
+//! [`orphans()`] repeats until nothing changes. `uhpm autoremove` removes
+//! the result in the order returned, dependents before their dependencies.
+
+use crate::db::PackageDB;
+use crate::error::UhpmError;
+use crate::package::InstallReason;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Packages installed only as dependencies that nothing installed needs,
+/// including those orphaned by removing the others
+///
+/// `edges` are `(package, dependency)` names.
+pub fn orphans(
+    reasons: &BTreeMap<String, InstallReason>,
+    edges: &[(String, String)],
+) -> Vec<String> {
+    let mut remaining: BTreeSet<&String> = reasons.keys().collect();
+    let mut found = Vec::new();
+    loop {
+        let needed: BTreeSet<&String> = edges
+            .iter()
+            .filter(|(pkg, dep)| pkg != dep && remaining.contains(pkg))
+            .map(|(_, dep)| dep)
+            .collect();
+        let round: Vec<&String> = remaining
+            .iter()
+            .copied()
+            .filter(|name| reasons[*name] == InstallReason::Dependency && !needed.contains(name))
+            .collect();
+        if round.is_empty() {
+            return found;
+        }
+        for name in round {
+            remaining.remove(name);
+            found.push(name.clone());
+        }
+    }
+}
+
+/// Orphaned packages of the installation in `db`
+pub async fn find(db: &PackageDB) -> Result<Vec<String>, UhpmError> {
+    let reasons = db.list_install_reasons().await?;
+    let edges = db.list_dependency_edges().await?;
+    Ok(orphans(&reasons, &edges))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edge(pkg: &str, dep: &str) -> (String, String) {
+        (pkg.to_string(), dep.to_string())
+    }
+
+    #[test]
+    fn test_orphans_cascade() {
+        let reasons: BTreeMap<String, InstallReason> = [
+            ("app", InstallReason::Explicit),
+            ("libssl", InstallReason::Dependency),
+            ("libz", InstallReason::Dependency),
+            ("old-tool-lib", InstallReason::Dependency),
+            ("libc-shim", InstallReason::Dependency),
+        ]
+        .into_iter()
+        .map(|(name, reason)| (name.to_string(), reason))
+        .collect();
+        let edges = [
+            edge("app", "libssl"),
+            edge("libssl", "libz"),
+            edge("old-tool-lib", "libc-shim"),
+            edge("removed-app", "libz"),
+        ];
+
+        assert_eq!(orphans(&reasons, &edges), ["old-tool-lib", "libc-shim"]);
+    }
+}
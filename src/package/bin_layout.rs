@@ -0,0 +1,212 @@
+//! # Bin Link Layout
+//!
+//! Decides where package executables are linked. Symlist targets directly
+//! inside the shared bin directory (`$XDG_BIN_HOME`, `~/.local/bin` by
+//! default) are mapped through the configured layout:
+//!
+//! ```ron
+//! bin: (mode: private, prefix: "uhpm-"),
+//! ```
+//!
+//! - `shared` (default) keeps them in `$XDG_BIN_HOME`;
+//! - `private` moves them to `~/.uhpm/bin`, which has to be added to `PATH`,
+//!   so uhpm never competes with other tools over the same file names;
+//!   all [profiles](crate::config::Profiles) share it;
+//! - `prefix` is prepended to every link name, in either mode.
+//!
+//! The layout last applied is stored in `~/.uhpm/bin_layout.ron`. When the
+//! configuration changes, [`sync()`] moves the existing links of installed
+//! packages to their new location and updates the database.
+
+use crate::config::Config;
+use crate::db::PackageDB;
+use crate::error::UhpmError;
+use crate::symlist::expand_vars;
+use crate::{info, platform, warn};
+use serde::{Deserialize, Serialize};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where bin links are placed
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BinMode {
+    /// `$XDG_BIN_HOME`, shared with other tools
+    #[default]
+    Shared,
+    /// `~/.uhpm/bin`, owned by uhpm alone
+    Private,
+}
+
+/// Bin directory and link naming
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct BinLayout {
+    pub mode: BinMode,
+    /// Prepended to every link name, e.g. `uhpm-`
+    pub prefix: String,
+}
+
+/// The shared user bin directory (`$XDG_BIN_HOME`)
+pub fn shared_dir() -> PathBuf {
+    expand_vars("$XDG_BIN_HOME")
+}
+
+/// The private bin directory (`~/.uhpm/bin`)
+pub fn private_dir() -> PathBuf {
+    platform::base_home().join("bin")
+}
+
+fn state_path() -> PathBuf {
+    platform::uhpm_home().join("bin_layout.ron")
+}
+
+impl BinLayout {
+    /// The configured layout
+    pub fn configured() -> Self {
+        Config::load_or_default().bin
+    }
+
+    /// The layout existing links were created with
+    pub fn applied() -> Self {
+        fs::read_to_string(state_path())
+            .ok()
+            .and_then(|s| ron::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    /// Directory bin links are placed in
+    pub fn dir(&self) -> PathBuf {
+        match self.mode {
+            BinMode::Shared => shared_dir(),
+            BinMode::Private => private_dir(),
+        }
+    }
+
+    /// Location of the link for an executable called `name`
+    pub fn link_path(&self, name: &OsStr) -> PathBuf {
+        let mut file_name = OsString::from(&self.prefix);
+        file_name.push(name);
+        self.dir().join(file_name)
+    }
+
+    /// Maps a symlist target in the shared bin directory into this layout
+    ///
+    /// Other targets are returned unchanged.
+    pub fn relocate(&self, target: PathBuf) -> PathBuf {
+        match target.file_name() {
+            Some(name) if target.parent() == Some(shared_dir().as_path()) => self.link_path(name),
+            _ => target,
+        }
+    }
+
+    /// Name of the executable behind a link created with this layout
+    fn executable_name(&self, link: &Path) -> Option<OsString> {
+        if link.parent() != Some(self.dir().as_path()) {
+            return None;
+        }
+        let name = link.file_name()?.to_str()?;
+        name.strip_prefix(self.prefix.as_str()).map(OsString::from)
+    }
+}
+
+/// Moves a link or copied file, also across filesystems
+fn move_link(from: &Path, to: &Path) -> std::io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            if from.is_symlink() {
+                platform::symlink(&fs::read_link(from)?, to)?;
+            } else {
+                fs::copy(from, to)?;
+            }
+            fs::remove_file(from)
+        }
+        result => result,
+    }
+}
+
+/// Moves the recorded bin links of all installed packages from one layout
+/// to another
+///
+/// Returns the number of links moved. A link whose new location is taken
+/// by another file is left in place.
+pub async fn migrate(db: &PackageDB, from: &BinLayout, to: &BinLayout) -> Result<usize, UhpmError> {
+    let mut moved = 0;
+    for (name, version, _) in db.list_packages().await? {
+        for file in db.get_installed_files(&name, &version).await? {
+            let old = PathBuf::from(&file);
+            let Some(exe) = from.executable_name(&old) else {
+                continue;
+            };
+            let new = to.link_path(&exe);
+            if new == old {
+                continue;
+            }
+            if fs::symlink_metadata(&old).is_ok() {
+                if fs::symlink_metadata(&new).is_ok() {
+                    warn!(
+                        "package.bin_layout.target_taken",
+                        old.display(),
+                        new.display()
+                    );
+                    continue;
+                }
+                move_link(&old, &new)?;
+                info!("package.bin_layout.moved", old.display(), new.display());
+                moved += 1;
+            }
+            db.rename_installed_file(&name, &version, &file, &new.to_string_lossy())
+                .await?;
+        }
+    }
+    Ok(moved)
+}
+
+/// Migrates links if the configured layout differs from the applied one
+///
+/// Returns the number of links moved.
+pub async fn sync(db: &PackageDB) -> Result<usize, UhpmError> {
+    let applied = BinLayout::applied();
+    let configured = BinLayout::configured();
+    if applied == configured {
+        return Ok(0);
+    }
+
+    let moved = migrate(db, &applied, &configured).await?;
+    let state = ron::ser::to_string(&configured).map_err(|e| UhpmError::Parse(e.to_string()))?;
+    fs::write(state_path(), state)?;
+    Ok(moved)
+}
+
+/// Whether the directory of `layout` is on `PATH`
+pub fn on_path(layout: &BinLayout) -> bool {
+    let dir = layout.dir();
+    std::env::var_os("PATH").is_some_and(|path| std::env::split_paths(&path).any(|p| p == dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relocate_and_reverse() {
+        let layout = BinLayout {
+            mode: BinMode::Private,
+            prefix: "uhpm-".to_string(),
+        };
+        let link = layout.relocate(shared_dir().join("tool"));
+        assert_eq!(link, private_dir().join("uhpm-tool"));
+        assert_eq!(layout.executable_name(&link), Some(OsString::from("tool")));
+
+        let other = PathBuf::from("/opt/share/app.desktop");
+        assert_eq!(layout.relocate(other.clone()), other);
+        assert_eq!(
+            BinLayout::default().relocate(shared_dir().join("tool")),
+            shared_dir().join("tool")
+        );
+    }
+}
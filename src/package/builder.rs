@@ -0,0 +1,236 @@
+//! # Building From Source
+//!
+//! `uhpm install --from-source <package>` builds a package on the spot
+//! instead of downloading the repository's archive. The `sources` table of
+//! a repository maps a package version to the URL of its build script
+//! (`uhpbuild`); the highest version matching the request wins.
+//!
+//! A repository vouches for a script with its SHA-256 in the `sources`
+//! table or with a detached signature `<url>.sig` from a trusted key (see
+//! [`signing`]); a script with neither is refused before it runs.
+//!
+//! The script is downloaded into an empty work directory
+//! (`<tmp_dir>/build-<name>-<version>`, see [`installer::tmp_dir`]) and run
+//! through `sh` in its `build/` subdirectory with:
+//! - `UHPM_PKG_NAME`, `UHPM_PKG_VERSION` — the package being built
+//! - `UHPM_BUILD_DIR` — the directory it runs in, for sources and objects
+//! - `UHPM_DEST` — the directory to fill with the package as
+//!   `uhpm pkg pack` expects it: `uhp.toml`, `symlist` and the files
+//!
+//! It runs in the [`sandbox`] with only the work directory writable; a
+//! script that downloads its sources needs `allow_network: true` there.
+//! `UHPM_DEST` is packed with [`packer::pack`], so lint errors stop the
+//! build, and the archive must carry the requested name and version. A
+//! failed build keeps the work directory for inspection. The archive is
+//! installed without a signature check, as no repository key can have
+//! signed it; the check of the script stands in for it.
+
+use crate::archive::ArchiveOptions;
+use crate::error::{SigningError, UhpmError};
+use crate::package::{VersionSpec, archive_cache, installer, packer, sandbox};
+use crate::repo::{self, RepoDB};
+use crate::{fetcher, info, signing};
+use semver::Version;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Build script of a package version offered by a repository
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildSource {
+    pub name: String,
+    pub version: Version,
+    /// Repository, as named in `repos.ron`
+    pub repo: String,
+    /// URL of the `uhpbuild` script
+    pub url: String,
+    /// SHA-256 of the script, if the repository lists one
+    pub sha256: Option<String>,
+}
+
+/// Finds the build script of the highest version of `name` matching
+/// `spec` (any version without one) in the repository databases `dbs`
+pub async fn find_source(
+    dbs: &[PathBuf],
+    name: &str,
+    spec: Option<&VersionSpec>,
+) -> Result<BuildSource, UhpmError> {
+    let mut best: Option<BuildSource> = None;
+    for db_path in dbs.iter().filter(|p| p.exists()) {
+        let repo_db = RepoDB::new(db_path).await?;
+        for (pkg, version, url, sha256) in repo_db.list_sources().await? {
+            let Ok(version) = Version::parse(&version) else {
+                continue;
+            };
+            if pkg != name
+                || spec
+                    .is_some_and(|spec| spec.best_match(std::slice::from_ref(&version)).is_none())
+                || best.as_ref().is_some_and(|b| b.version >= version)
+            {
+                continue;
+            }
+            best = Some(BuildSource {
+                name: pkg,
+                version,
+                repo: repo::cache_name(db_path),
+                url,
+                sha256,
+            });
+        }
+    }
+    best.ok_or_else(|| {
+        UhpmError::NotFound(match spec {
+            Some(spec) => format!("{}@{}", name, spec),
+            None => name.to_string(),
+        })
+    })
+}
+
+/// Work directory of the build of `source`
+pub fn work_dir(source: &BuildSource) -> PathBuf {
+    installer::tmp_dir().join(format!("build-{}-{}", source.name, source.version))
+}
+
+/// Checks the downloaded build script of `source` against its checksum,
+/// or without one against its detached signature
+async fn verify_script(source: &BuildSource, script: &Path) -> Result<(), UhpmError> {
+    if let Some(expected) = &source.sha256 {
+        if !archive_cache::sha256_file(script)?.eq_ignore_ascii_case(expected) {
+            return Err(UhpmError::Validation(format!(
+                "build script of {}@{} does not match its checksum",
+                source.name, source.version
+            )));
+        }
+        return Ok(());
+    }
+    fetcher::download_signature(&source.url, script).await;
+    match signing::verify(script) {
+        Ok(_) => Ok(()),
+        Err(SigningError::Unsigned(_)) => Err(UhpmError::Validation(format!(
+            "build script of {}@{} has neither a checksum nor a signature",
+            source.name, source.version
+        ))),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Downloads and runs the build script of `source` in `work` and packs the
+/// result; returns the archive
+///
+/// `work` is emptied first.
+pub async fn build(source: &BuildSource, work: &Path) -> Result<PathBuf, UhpmError> {
+    if work.exists() {
+        fs::remove_dir_all(work)?;
+    }
+    let build_dir = work.join("build");
+    let dest = work.join("dest");
+    fs::create_dir_all(&build_dir)?;
+    fs::create_dir_all(&dest)?;
+
+    let script = work.join("uhpbuild");
+    fetcher::download_file_to_path(&source.url, &script).await?;
+    verify_script(source, &script).await?;
+
+    let version = source.version.to_string();
+    info!("package.builder.running", &source.name, &version);
+    let cmd = format!("sh {}", script.display());
+    let status = sandbox::shell_command(&cmd, &[work])?
+        .current_dir(&build_dir)
+        .env("UHPM_PKG_NAME", &source.name)
+        .env("UHPM_PKG_VERSION", &version)
+        .env("UHPM_BUILD_DIR", &build_dir)
+        .env("UHPM_DEST", &dest)
+        .status()
+        .await?;
+    if !status.success() {
+        return Err(UhpmError::Package(format!(
+            "build script of {}@{} exited with status {}",
+            source.name,
+            version,
+            status.code().unwrap_or(-1)
+        )));
+    }
+
+    let archive = packer::pack(
+        &dest,
+        Some(&work.join(format!("{}-{}.uhp", source.name, version))),
+        &ArchiveOptions::new(),
+    )?;
+    let meta = installer::read_meta(&archive)?;
+    if meta.name() != source.name || *meta.version() != source.version {
+        return Err(UhpmError::Validation(format!(
+            "build script of {}@{} produced {}@{}",
+            source.name,
+            version,
+            meta.name(),
+            meta.version()
+        )));
+    }
+    info!("package.builder.built", &source.name, &version);
+    Ok(archive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_find_source_and_build() {
+        let tmp = tempfile::tempdir().unwrap();
+        let script = tmp.path().join("uhpbuild.sh");
+        fs::write(
+            &script,
+            r#"mkdir -p "$UHPM_DEST/bin"
+echo "echo built" > "$UHPM_DEST/bin/$UHPM_PKG_NAME"
+chmod +x "$UHPM_DEST/bin/$UHPM_PKG_NAME"
+printf 'name = "%s"\nauthor = "a"\nversion = "%s"\nchecksum = ""\n\n[src]\ntype = "Raw"\nvalue = ""\n' \
+    "$UHPM_PKG_NAME" "$UHPM_PKG_VERSION" > "$UHPM_DEST/uhp.toml"
+echo "bin/$UHPM_PKG_NAME \$HOME/.local/bin/$UHPM_PKG_NAME" > "$UHPM_DEST/symlist"
+"#,
+        )
+        .unwrap();
+        let db_path = tmp.path().join("main/repository.db");
+        let repo_db = RepoDB::new(&db_path).await.unwrap();
+        let url = script.to_string_lossy().to_string();
+        let sha256 = archive_cache::sha256_file(&script).unwrap();
+        for version in ["1.0.0", "1.2.0", "2.0.0"] {
+            repo_db
+                .add_source("tool", version, &url, Some(&sha256))
+                .await
+                .unwrap();
+        }
+
+        let dbs = [db_path];
+        let spec: VersionSpec = "^1".parse().unwrap();
+        let source = find_source(&dbs, "tool", Some(&spec)).await.unwrap();
+        assert_eq!(source.version, Version::new(1, 2, 0));
+        assert_eq!(source.repo, "main");
+        let latest = find_source(&dbs, "tool", None).await.unwrap();
+        assert_eq!(latest.version, Version::new(2, 0, 0));
+        assert!(matches!(
+            find_source(&dbs, "other", None).await,
+            Err(UhpmError::NotFound(_))
+        ));
+
+        let archive = build(&source, &tmp.path().join("work")).await.unwrap();
+        let meta = installer::read_meta(&archive).unwrap();
+        assert_eq!(meta.name(), "tool");
+        assert_eq!(meta.version().to_string(), "1.2.0");
+
+        fs::write(&script, "exit 3\n").unwrap();
+        assert!(matches!(
+            build(&source, &tmp.path().join("work")).await,
+            Err(UhpmError::Validation(_))
+        ));
+        let mut source = source;
+        source.sha256 = Some(archive_cache::sha256_file(&script).unwrap());
+        assert!(matches!(
+            build(&source, &tmp.path().join("work")).await,
+            Err(UhpmError::Package(_))
+        ));
+        source.sha256 = None;
+        assert!(matches!(
+            build(&source, &tmp.path().join("work")).await,
+            Err(UhpmError::Validation(_))
+        ));
+    }
+}
@@ -0,0 +1,266 @@
+//! # Offline Bundles
+//!
+//! `uhpm bundle foo` writes `foo-<version>.bundle.tar`: the archive of
+//! `foo` and of every package in its dependency closure, resolved from the
+//! repositories whether or not they are installed here, together with an
+//! install manifest. `uhpm install --bundle <file>` installs from it
+//! without touching the network, e.g. on an air-gapped machine.
+//!
+//! The tar is not compressed, the archives in it already are:
+//!
+//! ```text
+//! bundle.toml
+//! packages/<name>-<version>.uhp
+//! packages/<name>-<version>.uhp.sig   (if the archive was signed)
+//! ```
+//!
+//! `bundle.toml` lists the packages in install order, dependencies first,
+//! each with the SHA-256 of its archive:
+//!
+//! ```toml
+//! format = 1
+//! package = "foo"
+//!
+//! [[packages]]
+//! name = "lib"
+//! version = "1.2.0"
+//! file = "packages/lib-1.2.0.uhp"
+//! sha256 = "…"
+//! ```
+//!
+//! A bundle is extracted within the `extract` limits of `config.ron`, and
+//! every checksum is verified before anything is installed.
+
+use crate::archive::{self, ArchiveOptions, Compression};
+use crate::config::Config;
+use crate::error::UhpmError;
+use crate::package::{archive_cache, installer};
+use crate::signing;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Version of the `bundle.toml` layout
+pub const FORMAT: u32 = 1;
+
+/// Name of the install manifest in a bundle
+pub const MANIFEST: &str = "bundle.toml";
+
+/// A package archive in a bundle
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BundleEntry {
+    pub name: String,
+    pub version: Version,
+    /// Path of the archive in the bundle
+    pub file: String,
+    pub sha256: String,
+}
+
+/// The install manifest of a bundle
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BundleManifest {
+    pub format: u32,
+    /// The package the bundle was made for; the others are its
+    /// dependencies
+    pub package: String,
+    /// In install order, dependencies first
+    pub packages: Vec<BundleEntry>,
+}
+
+impl BundleManifest {
+    /// Entry of the package the bundle was made for
+    pub fn root(&self) -> Option<&BundleEntry> {
+        self.packages.iter().find(|e| e.name == self.package)
+    }
+}
+
+/// Writes a bundle of `archives`, given as `(name, version, archive)` in
+/// install order, for `package` to `output`
+pub fn create(
+    package: &str,
+    archives: &[(String, Version, PathBuf)],
+    output: &Path,
+) -> Result<BundleManifest, UhpmError> {
+    let staging = installer::tmp_dir().join(format!("bundle-{}", package));
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::create_dir_all(staging.join("packages"))?;
+
+    let result = (|| -> Result<BundleManifest, UhpmError> {
+        let mut manifest = BundleManifest {
+            format: FORMAT,
+            package: package.to_string(),
+            packages: Vec::new(),
+        };
+        for (name, version, archive) in archives {
+            let file = format!("packages/{}-{}.uhp", name, version);
+            fs::copy(archive, staging.join(&file))?;
+            let sig = signing::signature_path(archive);
+            if sig.exists() {
+                fs::copy(&sig, signing::signature_path(&staging.join(&file)))?;
+            }
+            manifest.packages.push(BundleEntry {
+                name: name.clone(),
+                version: version.clone(),
+                sha256: archive_cache::sha256_file(archive)?,
+                file,
+            });
+        }
+        let toml =
+            toml::to_string_pretty(&manifest).map_err(|e| UhpmError::Parse(e.to_string()))?;
+        fs::write(staging.join(MANIFEST), toml)?;
+
+        let opts = ArchiveOptions::new()
+            .compression(Compression::None)
+            .deterministic(true);
+        archive::create(&staging, output, &opts)?;
+        Ok(manifest)
+    })();
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+/// A bundle extracted for installation
+#[derive(Debug)]
+pub struct OpenedBundle {
+    /// Directory the bundle was extracted into
+    pub dir: PathBuf,
+    pub manifest: BundleManifest,
+}
+
+impl OpenedBundle {
+    /// Path of the extracted archive of `entry`
+    pub fn archive(&self, entry: &BundleEntry) -> PathBuf {
+        self.dir.join(&entry.file)
+    }
+
+    /// Removes the extracted bundle
+    pub fn discard(self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Whether `file` names an archive directly below `packages/`
+fn is_package_file(file: &str) -> bool {
+    file.strip_prefix("packages/").is_some_and(|name| {
+        name.ends_with(".uhp") && !name.contains(['/', '\\']) && !name.starts_with('.')
+    })
+}
+
+/// Checks the manifest of a bundle extracted into `dir` against its
+/// archives
+fn check(dir: &Path, manifest: &BundleManifest) -> Result<(), UhpmError> {
+    if manifest.format != FORMAT {
+        return Err(UhpmError::Validation(format!(
+            "unsupported bundle format {}",
+            manifest.format
+        )));
+    }
+    if manifest.root().is_none() {
+        return Err(UhpmError::Validation(format!(
+            "bundle does not contain {}",
+            manifest.package
+        )));
+    }
+    for entry in &manifest.packages {
+        if !is_package_file(&entry.file) {
+            return Err(UhpmError::Validation(format!(
+                "invalid archive path in bundle: {}",
+                entry.file
+            )));
+        }
+        let path = dir.join(&entry.file);
+        if !path.is_file() {
+            return Err(UhpmError::NotFound(format!("{} in bundle", entry.file)));
+        }
+        if archive_cache::sha256_file(&path)? != entry.sha256 {
+            return Err(UhpmError::Validation(format!(
+                "{} in bundle does not match its checksum",
+                entry.file
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Extracts `bundle` and verifies its archives
+pub fn open(bundle: &Path) -> Result<OpenedBundle, UhpmError> {
+    let stem = bundle
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "bundle".to_string());
+    let dir = installer::tmp_dir().join(format!("bundle-{}", stem));
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+
+    let opened = (|| -> Result<BundleManifest, UhpmError> {
+        archive::extract(bundle, &dir, &Config::load_or_default().extract)?;
+        let data = fs::read_to_string(dir.join(MANIFEST)).map_err(|_| {
+            UhpmError::Validation(format!("{} has no {}", bundle.display(), MANIFEST))
+        })?;
+        let manifest: BundleManifest =
+            toml::from_str(&data).map_err(|e| UhpmError::Parse(e.to_string()))?;
+        check(&dir, &manifest)?;
+        Ok(manifest)
+    })();
+    match opened {
+        Ok(manifest) => Ok(OpenedBundle { dir, manifest }),
+        Err(e) => {
+            let _ = fs::remove_dir_all(&dir);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_bundle_roundtrip() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        crate::platform::with_root(&root.join("home"), async {
+            let lib = root.join("lib.uhp");
+            let app = root.join("app.uhp");
+            fs::write(&lib, "lib archive").unwrap();
+            fs::write(&app, "app archive").unwrap();
+            let archives = [
+                ("lib".to_string(), Version::new(1, 2, 0), lib),
+                ("app".to_string(), Version::new(0, 3, 0), app),
+            ];
+            let output = root.join("app-0.3.0.bundle.tar");
+            let manifest = create("app", &archives, &output).unwrap();
+            assert_eq!(manifest.packages[0].file, "packages/lib-1.2.0.uhp");
+
+            let opened = open(&output).unwrap();
+            assert_eq!(opened.manifest, manifest);
+            assert_eq!(
+                opened.manifest.root().unwrap().version,
+                Version::new(0, 3, 0)
+            );
+            let lib_archive = opened.archive(&opened.manifest.packages[0]);
+            assert_eq!(fs::read_to_string(&lib_archive).unwrap(), "lib archive");
+
+            fs::write(&lib_archive, "tampered").unwrap();
+            assert!(matches!(
+                check(&opened.dir, &opened.manifest),
+                Err(UhpmError::Validation(_))
+            ));
+            opened.discard();
+        })
+        .await;
+    }
+
+    #[test]
+    fn test_package_file_paths() {
+        assert!(is_package_file("packages/lib-1.2.0.uhp"));
+        assert!(!is_package_file("packages/../../etc/passwd.uhp"));
+        assert!(!is_package_file("lib-1.2.0.uhp"));
+        assert!(!is_package_file("packages/lib-1.2.0.tar"));
+    }
+}
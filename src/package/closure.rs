@@ -0,0 +1,120 @@
+//! # Dependency Closure
+//!
+//! Computes the transitive set of dependencies of a package with the exact
+//! versions present at install time. The installer stores the closure in
+//! the `dependency_closure` table, so later queries (`why`, rollback,
+//! lockfile export) can answer without re-resolving against repositories.
+//!
+//! Resolution order for each dependency:
+//! 1. the currently installed version, if the dependency is installed;
+//! 2. otherwise the version declared by the dependent package.
+//!
+//! Dependencies of a dependency are read from its `uhp.toml` in the
+//! package store; dependencies that are not installed end the walk.
+
+use crate::db::PackageDB;
+use crate::package::{Package, get_pkg_path, meta_parser};
+use semver::Version;
+use std::collections::{HashSet, VecDeque};
+
+/// One entry of a dependency closure
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClosureEntry {
+    /// Dependency name
+    pub name: String,
+    /// Exact version resolved at install time
+    pub version: String,
+    /// Package that pulled this dependency in
+    pub required_by: String,
+    /// Distance from the root package (direct dependencies have depth 1)
+    pub depth: u32,
+    /// Whether the dependency was installed when the closure was recorded
+    pub installed: bool,
+}
+
+/// A package waiting to be expanded: its name, its dependencies and
+/// their depth
+type Pending = (String, Vec<(String, Version)>, u32);
+
+/// Resolves the dependency closure of `pkg` against the installed packages
+pub async fn resolve(pkg: &Package, db: &PackageDB) -> Result<Vec<ClosureEntry>, sqlx::Error> {
+    let mut closure = Vec::new();
+    let mut seen: HashSet<String> = HashSet::from([pkg.name().to_string()]);
+    let mut queue: VecDeque<Pending> =
+        VecDeque::from([(pkg.name().to_string(), pkg.dependencies(), 1)]);
+
+    while let Some((parent, deps, depth)) = queue.pop_front() {
+        for (dep_name, declared) in deps {
+            if !seen.insert(dep_name.clone()) {
+                continue;
+            }
+
+            let installed = db
+                .get_package_version(&dep_name)
+                .await?
+                .and_then(|v| Version::parse(&v).ok());
+            let version = installed.clone().unwrap_or(declared);
+
+            if installed.is_some() {
+                let meta_path = get_pkg_path(&dep_name, version.clone()).join("uhp.toml");
+                if let Ok(meta) = meta_parser(&meta_path) {
+                    queue.push_back((dep_name.clone(), meta.dependencies(), depth + 1));
+                }
+            }
+
+            closure.push(ClosureEntry {
+                name: dep_name,
+                version: version.to_string(),
+                required_by: parent.clone(),
+                depth,
+                installed: installed.is_some(),
+            });
+        }
+    }
+
+    Ok(closure)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::Source;
+
+    #[tokio::test]
+    async fn test_closure_roundtrip_uninstalled_deps() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = PackageDB::new(&tmp.path().join("packages.db"))
+            .unwrap()
+            .init()
+            .await
+            .unwrap();
+
+        let pkg = Package::new(
+            "app",
+            Version::parse("1.0.0").unwrap(),
+            "author",
+            Source::Raw("test".to_string()),
+            "",
+            vec![("lib".to_string(), Version::parse("0.3.0").unwrap())],
+        );
+        let closure = resolve(&pkg, &db).await.unwrap();
+        assert_eq!(
+            closure,
+            vec![ClosureEntry {
+                name: "lib".to_string(),
+                version: "0.3.0".to_string(),
+                required_by: "app".to_string(),
+                depth: 1,
+                installed: false,
+            }]
+        );
+
+        db.set_dependency_closure("app", "1.0.0", &closure)
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_dependency_closure("app", "1.0.0").await.unwrap(),
+            closure
+        );
+    }
+}
@@ -0,0 +1,519 @@
+//! # Link Target Conflicts
+//!
+//! Before a package is linked, every destination that already exists is
+//! checked: its symlist entries as well as its systemd units, assets and
+//! app bundles. Files uhpm put there itself — symlinks into the
+//! package store, or files recorded as installed by a package and still
+//! as it placed them — are replaced as before. Anything else is a user
+//! file, such as one put in place of a package's link, handled according
+//! to `Config.replace_policy`:
+//!
+//! | Policy      | Behaviour                                              |
+//! |-------------|--------------------------------------------------------|
+//! | `error`     | Abort the install before anything is touched (default) |
+//! | `backup`    | Rename the file to `<file>.uhpm-backup` and continue   |
+//! | `overwrite` | Replace the file                                       |
+//!
+//! Files recorded as installed by *another* package are a conflict
+//! between the two packages: linking would silently take the file away
+//! from its owner, and removing the owner later would delete it. Such an
+//! install is aborted naming the owners, unless `install --overwrite`
+//! allows it; the file then belongs to the new package.
+//!
+//! Every placement of links — installs, version switches, `uhpm relink`
+//! and profile switches — first runs both checks with [`claim`], and the
+//! resulting [`Claim`] is what lets the linker replace a file. A file that
+//! shows up after the check is handled by the policy again.
+
+use crate::config::Config;
+use crate::db::PackageDB;
+use crate::error::UhpmError;
+use crate::package::{remover, systemd, transaction};
+use crate::{info, symlist, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static OVERWRITE_OWNED: AtomicBool = AtomicBool::new(false);
+
+/// Lets packages take over files installed by other packages
+pub fn allow_overwrite() {
+    OVERWRITE_OWNED.store(true, Ordering::Relaxed);
+}
+
+/// Whether packages may take over files installed by other packages
+pub fn overwrite_allowed() -> bool {
+    OVERWRITE_OWNED.load(Ordering::Relaxed)
+}
+
+/// A link destination already installed by another package
+#[derive(Debug, Clone, PartialEq)]
+pub struct OwnedTarget {
+    pub path: PathBuf,
+    pub owner: String,
+    pub owner_version: String,
+}
+
+/// What to do with user files in the way of package links
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplacePolicy {
+    #[default]
+    Error,
+    Backup,
+    Overwrite,
+}
+
+/// Suffix appended to backed-up user files
+pub const BACKUP_SUFFIX: &str = ".uhpm-backup";
+
+/// Whether `dst` was installed by a package of `db`, whose store is
+/// `store`, and is still what the package placed there
+async fn placed_by_owner(dst: &Path, store: &Path, db: &PackageDB) -> Result<bool, UhpmError> {
+    let Some((owner, version)) = db.find_file_owner(&dst.to_string_lossy()).await? else {
+        return Ok(false);
+    };
+    let mode = db.get_link_mode(&owner, &version).await?;
+    let owner_dir = store.join(format!("{}-{}", owner, version));
+    Ok(remover::placed_by_package(dst, mode, &owner_dir))
+}
+
+/// Every link destination of the package at `package_root`: its symlist
+/// entries, systemd units, assets and app bundles
+fn destinations(package_root: &Path) -> Vec<PathBuf> {
+    match systemd::installed_meta(package_root) {
+        Some(meta) => transaction::link_targets(package_root, &meta),
+        None => symlist::load_symlist(&package_root.join("symlist"), package_root)
+            .map(|entries| entries.into_iter().map(|(_, dst)| dst).collect())
+            .unwrap_or_default(),
+    }
+}
+
+/// Returns link destinations of the package that exist and are not uhpm's
+///
+/// `package_root` may be the unpacked or the installed package directory;
+/// `store` is the package store of `db`, usually
+/// [`packages_dir`](crate::package::packages_dir).
+pub async fn foreign_targets(
+    package_root: &Path,
+    store: &Path,
+    db: &PackageDB,
+) -> Result<Vec<PathBuf>, UhpmError> {
+    let mut foreign = Vec::new();
+    for dst in destinations(package_root) {
+        let Ok(meta) = fs::symlink_metadata(&dst) else {
+            continue;
+        };
+        if meta.file_type().is_symlink()
+            && fs::read_link(&dst).is_ok_and(|target| target.starts_with(store))
+        {
+            continue;
+        }
+        if placed_by_owner(&dst, store, db).await? {
+            continue;
+        }
+        foreign.push(dst);
+    }
+    Ok(foreign)
+}
+
+/// Returns link destinations of package `name` that another package
+/// installed
+pub async fn owned_targets(
+    package_root: &Path,
+    name: &str,
+    db: &PackageDB,
+) -> Result<Vec<OwnedTarget>, UhpmError> {
+    let mut owned = Vec::new();
+    for dst in destinations(package_root) {
+        if let Some((owner, owner_version)) = db.find_file_owner(&dst.to_string_lossy()).await?
+            && owner != name
+        {
+            owned.push(OwnedTarget {
+                path: dst,
+                owner,
+                owner_version,
+            });
+        }
+    }
+    Ok(owned)
+}
+
+/// Fails if package `name` would replace files of other packages
+///
+/// With [`allow_overwrite`] the files are taken over instead; they are
+/// returned, so that the caller can [`disown`] them once the package is
+/// recorded.
+///
+/// # Errors
+/// Returns [`UhpmError::Conflict`] listing the files and their owners.
+pub async fn check_owners(
+    package_root: &Path,
+    name: &str,
+    db: &PackageDB,
+) -> Result<Vec<PathBuf>, UhpmError> {
+    let owned = owned_targets(package_root, name, db).await?;
+    if owned.is_empty() {
+        return Ok(Vec::new());
+    }
+    if !overwrite_allowed() {
+        let files: Vec<String> = owned
+            .iter()
+            .map(|t| {
+                format!(
+                    "{} (owned by {} {})",
+                    t.path.display(),
+                    t.owner,
+                    t.owner_version
+                )
+            })
+            .collect();
+        return Err(UhpmError::Conflict(format!(
+            "{} would replace files of other packages: {} (use --overwrite to take them over)",
+            name,
+            files.join(", ")
+        )));
+    }
+    for target in &owned {
+        warn!(
+            "package.conflicts.taking_over",
+            target.path.display(),
+            &target.owner
+        );
+    }
+    Ok(owned.into_iter().map(|t| t.path).collect())
+}
+
+/// Records `paths`, taken over by package `name`, as no longer installed
+/// by their previous owners
+pub async fn disown(paths: &[PathBuf], name: &str, db: &PackageDB) -> Result<(), UhpmError> {
+    for path in paths {
+        db.disown_file(&path.to_string_lossy(), name).await?;
+    }
+    Ok(())
+}
+
+/// Applies `policy` to user files the package would replace
+///
+/// # Errors
+/// Returns [`UhpmError::Conflict`] listing the files under
+/// [`ReplacePolicy::Error`].
+pub async fn resolve(
+    package_root: &Path,
+    store: &Path,
+    db: &PackageDB,
+    policy: ReplacePolicy,
+) -> Result<(), UhpmError> {
+    let foreign = foreign_targets(package_root, store, db).await?;
+    if foreign.is_empty() {
+        return Ok(());
+    }
+
+    match policy {
+        ReplacePolicy::Error => {
+            let files: Vec<String> = foreign.iter().map(|p| p.display().to_string()).collect();
+            return Err(UhpmError::Conflict(format!(
+                "existing files not managed by uhpm would be replaced: {} \
+                 (set replace_policy to backup or overwrite)",
+                files.join(", ")
+            )));
+        }
+        ReplacePolicy::Backup => {
+            for path in &foreign {
+                let backup = backup_path(path);
+                fs::rename(path, &backup)?;
+                info!(
+                    "package.conflicts.backed_up",
+                    path.display(),
+                    backup.display()
+                );
+            }
+        }
+        ReplacePolicy::Overwrite => {
+            for path in &foreign {
+                warn!("package.conflicts.overwriting", path.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What a package may replace when its links are created, see [`claim`]
+#[derive(Debug, Clone, Default)]
+pub struct Claim {
+    policy: ReplacePolicy,
+    /// Package store links into which are uhpm's
+    store: Option<PathBuf>,
+    /// Existing destinations a package installed and still as it placed
+    /// them
+    recorded: HashSet<PathBuf>,
+    /// Files taken over from other packages, to [`disown`] once the package
+    /// is recorded
+    pub taken_over: Vec<PathBuf>,
+}
+
+impl Claim {
+    /// A claim made without the package database: only links into `store`
+    /// count as uhpm's, everything else is handled by
+    /// `Config.replace_policy`
+    pub fn for_store(store: &Path) -> Self {
+        Self {
+            policy: Config::load_or_default().replace_policy,
+            store: Some(store.to_path_buf()),
+            ..Default::default()
+        }
+    }
+
+    /// Makes way for a link of the package at `package_root` at `dst`
+    ///
+    /// Links into the store and files packages placed are removed;
+    /// anything else is a user file, handled by the replace policy.
+    pub fn clear(&self, package_root: &Path, dst: &Path) -> io::Result<()> {
+        let Ok(meta) = fs::symlink_metadata(dst) else {
+            return Ok(());
+        };
+        let managed = self.recorded.contains(dst)
+            || (meta.file_type().is_symlink()
+                && fs::read_link(dst).is_ok_and(|target| {
+                    self.store
+                        .as_ref()
+                        .is_some_and(|store| target.starts_with(store))
+                        || target.starts_with(package_root)
+                }));
+        if !managed {
+            match self.policy {
+                ReplacePolicy::Error => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!(
+                            "existing file not managed by uhpm would be replaced: {} \
+                             (set replace_policy to backup or overwrite)",
+                            dst.display()
+                        ),
+                    ));
+                }
+                ReplacePolicy::Backup => {
+                    let backup = backup_path(dst);
+                    fs::rename(dst, &backup)?;
+                    info!(
+                        "package.conflicts.backed_up",
+                        dst.display(),
+                        backup.display()
+                    );
+                    return Ok(());
+                }
+                ReplacePolicy::Overwrite => warn!("package.conflicts.overwriting", dst.display()),
+            }
+        }
+        fs::remove_file(dst)
+    }
+}
+
+/// Checks the link destinations of package `name` at `package_root`
+/// before they are linked
+///
+/// Runs [`check_owners`] and applies `Config.replace_policy` with
+/// [`resolve`]; the returned [`Claim`] is handed to
+/// [`create_symlinks`](crate::package::installer::create_symlinks).
+/// `store` is the package store of `db`, which is
+/// [`packages_dir`](crate::package::packages_dir) except while switching
+/// profiles.
+pub async fn claim(
+    package_root: &Path,
+    store: &Path,
+    name: &str,
+    db: &PackageDB,
+) -> Result<Claim, UhpmError> {
+    let taken_over = check_owners(package_root, name, db).await?;
+    let policy = Config::load_or_default().replace_policy;
+    resolve(package_root, store, db, policy).await?;
+
+    let mut recorded = HashSet::new();
+    for dst in destinations(package_root) {
+        if fs::symlink_metadata(&dst).is_ok() && placed_by_owner(&dst, store, db).await? {
+            recorded.insert(dst);
+        }
+    }
+    Ok(Claim {
+        policy,
+        store: Some(store.to_path_buf()),
+        recorded,
+        taken_over,
+    })
+}
+
+/// First free `<path>.uhpm-backup[.N]` name
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(BACKUP_SUFFIX);
+    let base = PathBuf::from(name);
+
+    let mut candidate = base.clone();
+    let mut n = 1;
+    while fs::symlink_metadata(&candidate).is_ok() {
+        let mut numbered = base.as_os_str().to_os_string();
+        numbered.push(format!(".{}", n));
+        candidate = PathBuf::from(numbered);
+        n += 1;
+    }
+    candidate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backup_path_is_unique() {
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join("config");
+        assert_eq!(backup_path(&file), tmp.path().join("config.uhpm-backup"));
+
+        fs::write(tmp.path().join("config.uhpm-backup"), "").unwrap();
+        assert_eq!(backup_path(&file), tmp.path().join("config.uhpm-backup.1"));
+    }
+
+    #[tokio::test]
+    async fn test_files_of_other_packages_conflict() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = PackageDB::new(&tmp.path().join("packages.db"))
+            .unwrap()
+            .init()
+            .await
+            .unwrap();
+        let target = tmp.path().join("bin/tool");
+        db.set_installed_files(
+            "tool-a",
+            "1.0.0",
+            &[target.to_string_lossy().to_string()],
+            crate::package::LinkMode::Symlink,
+        )
+        .await
+        .unwrap();
+
+        let root = tmp.path().join("tool-b");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(
+            root.join("symlist"),
+            format!("bin/tool {}\n", target.display()),
+        )
+        .unwrap();
+
+        let owned = owned_targets(&root, "tool-b", &db).await.unwrap();
+        assert_eq!(
+            owned,
+            [OwnedTarget {
+                path: target.clone(),
+                owner: "tool-a".to_string(),
+                owner_version: "1.0.0".to_string(),
+            }]
+        );
+        assert!(matches!(
+            check_owners(&root, "tool-b", &db).await,
+            Err(UhpmError::Conflict(_))
+        ));
+        assert!(
+            owned_targets(&root, "tool-a", &db)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        // A file the user put in place of the link of tool-a is not its own
+        fs::create_dir_all(target.parent().unwrap()).unwrap();
+        fs::write(&target, "mine").unwrap();
+        let store = tmp.path().join("packages");
+        assert_eq!(
+            foreign_targets(&root, &store, &db).await.unwrap(),
+            [target.clone()]
+        );
+
+        disown(&[target], "tool-b", &db).await.unwrap();
+        assert!(
+            owned_targets(&root, "tool-b", &db)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_user_units_are_foreign() {
+        let tmp = tempfile::tempdir().unwrap();
+        let home = tmp.path().join("home");
+        crate::platform::with_root(&home, async {
+            let db = PackageDB::new(&home.join("packages.db"))
+                .unwrap()
+                .init()
+                .await
+                .unwrap();
+            let root = tmp.path().join("daemon");
+            fs::create_dir_all(root.join("units")).unwrap();
+            fs::write(root.join("units/daemon.service"), "[Service]").unwrap();
+            fs::write(
+                root.join("uhp.toml"),
+                format!(
+                    "{}\n[[systemd]]\nunit = \"units/daemon.service\"\n",
+                    crate::package::test_manifest("daemon", "1.0.0")
+                ),
+            )
+            .unwrap();
+
+            let unit = systemd::user_unit_dir().join("daemon.service");
+            fs::create_dir_all(unit.parent().unwrap()).unwrap();
+            fs::write(&unit, "[Service]\nExecStart=/usr/bin/mine").unwrap();
+            let store = home.join("packages");
+            assert_eq!(
+                foreign_targets(&root, &store, &db).await.unwrap(),
+                [unit.clone()]
+            );
+            assert!(matches!(
+                resolve(&root, &store, &db, ReplacePolicy::Error).await,
+                Err(UhpmError::Conflict(_))
+            ));
+            assert!(unit.exists());
+        })
+        .await;
+    }
+
+    #[test]
+    fn test_claim_applies_policy_to_user_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().join("tool-1.0.0");
+        fs::create_dir_all(&root).unwrap();
+        let file = tmp.path().join("config");
+        fs::write(&file, "mine").unwrap();
+
+        let claim = Claim::default();
+        assert!(claim.clear(&root, &file).is_err());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "mine");
+
+        let backup = Claim {
+            policy: ReplacePolicy::Backup,
+            ..Claim::default()
+        };
+        backup.clear(&root, &file).unwrap();
+        assert!(fs::symlink_metadata(&file).is_err());
+        assert_eq!(
+            fs::read_to_string(tmp.path().join("config.uhpm-backup")).unwrap(),
+            "mine"
+        );
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(root.join("config"), &file).unwrap();
+            claim.clear(&root, &file).unwrap();
+            assert!(fs::symlink_metadata(&file).is_err());
+        }
+    }
+
+    #[test]
+    fn test_policy_names() {
+        let policy: ReplacePolicy = ron::from_str("backup").unwrap();
+        assert_eq!(policy, ReplacePolicy::Backup);
+        assert_eq!(ReplacePolicy::default(), ReplacePolicy::Error);
+    }
+}
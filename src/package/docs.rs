@@ -0,0 +1,135 @@
+//! # Package Documentation
+//!
+//! Packages may ship a README and further documentation. Files listed in
+//! the `docs` field of `uhp.toml` are used as declared:
+//!
+//! ```toml
+//! docs = ["README.md", "doc/usage.md"]
+//! ```
+//!
+//! Without that field, `README*` files at the package root and every file
+//! below `doc/`, `docs/` and `share/doc/` are picked up. The paths are
+//! recorded in the database at install time and shown by `uhpm docs <pkg>`,
+//! through `$PAGER` when stdout is a terminal.
+
+use crate::package::Package;
+use crate::warn;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::process::Command;
+use walkdir::WalkDir;
+
+/// Directories searched for documentation, relative to the package root
+const DOC_DIRS: [&str; 3] = ["doc", "docs", "share/doc"];
+
+/// Pager used when `$PAGER` is unset
+const DEFAULT_PAGER: &str = "less";
+
+/// Finds the documentation files of an unpacked package
+///
+/// Returns paths relative to `package_root`, the primary document (the
+/// first declared one or a README) first.
+pub fn discover(package_root: &Path, pkg: &Package) -> Vec<String> {
+    if !pkg.docs().is_empty() {
+        return pkg
+            .docs()
+            .iter()
+            .filter(|doc| {
+                let exists = package_root.join(doc).is_file();
+                if !exists {
+                    warn!("package.docs.not_found", doc);
+                }
+                exists
+            })
+            .cloned()
+            .collect();
+    }
+
+    let mut readmes: Vec<String> = fs::read_dir(package_root)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|e| e.path().is_file())
+                .filter_map(|e| e.file_name().into_string().ok())
+                .filter(|name| name.to_ascii_uppercase().starts_with("README"))
+                .collect()
+        })
+        .unwrap_or_default();
+    readmes.sort();
+
+    let mut docs = readmes;
+    for dir in DOC_DIRS {
+        let mut found: Vec<String> = WalkDir::new(package_root.join(dir))
+            .into_iter()
+            .flatten()
+            .filter(|e| e.file_type().is_file())
+            .filter_map(|e| {
+                e.path()
+                    .strip_prefix(package_root)
+                    .ok()
+                    .map(|p| p.to_string_lossy().to_string())
+            })
+            .collect();
+        found.sort();
+        docs.extend(found);
+    }
+    docs
+}
+
+/// Shows a document, through `$PAGER` if stdout is a terminal
+///
+/// Falls back to printing the file when the pager cannot be started.
+pub fn show(path: &Path) -> io::Result<()> {
+    if io::stdout().is_terminal() {
+        let pager = std::env::var("PAGER")
+            .ok()
+            .filter(|p| !p.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_PAGER.to_string());
+        // The pager may carry arguments, e.g. `less -R`
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$1\"", pager))
+            .arg("sh")
+            .arg(path)
+            .status();
+        match status {
+            Ok(status) if status.code() != Some(127) => return Ok(()),
+            Ok(_) => warn!("package.docs.pager_unavailable", &pager),
+            Err(e) => warn!("package.docs.pager_failed", &pager, e),
+        }
+    }
+
+    let mut file = fs::File::open(path)?;
+    io::copy(&mut file, &mut io::stdout().lock())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::Source;
+    use semver::Version;
+
+    #[test]
+    fn test_discover_prefers_readme() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(tmp.path().join("README.md"), "# x").unwrap();
+        fs::write(tmp.path().join("LICENSE"), "").unwrap();
+        fs::create_dir_all(tmp.path().join("share/doc/x")).unwrap();
+        fs::write(tmp.path().join("share/doc/x/usage.txt"), "").unwrap();
+
+        let pkg = Package::new(
+            "x",
+            Version::parse("1.0.0").unwrap(),
+            "author",
+            Source::Raw("test".to_string()),
+            "",
+            vec![],
+        );
+        assert_eq!(
+            discover(tmp.path(), &pkg),
+            vec!["README.md", "share/doc/x/usage.txt"]
+        );
+    }
+}
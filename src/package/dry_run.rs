@@ -0,0 +1,248 @@
+//! # Dry Runs
+//!
+//! With the global `--dry-run` flag `install`, `remove`, `autoremove`,
+//! `update` and `gc` only report what they would do. The plan is worked out
+//! like the real operation: repositories are resolved and archives are
+//! fetched to the temporary directory, since their `uhp.toml` and `symlist`
+//! decide about dependencies and links. The uhpm home, the link targets and
+//! the package database stay untouched; the process runs
+//! [read-only](crate::access) meanwhile.
+//!
+//! The result is a list of [`Change`]s in the order they would happen.
+//! Links are computed from the symlists; files placed by systemd units,
+//! assets and app bundles are only listed when removed, from the record of
+//! installed files.
+
+use crate::archive;
+use crate::db::PackageDB;
+use crate::error::UhpmError;
+use crate::package::{Package, get_pkg_path};
+use crate::symlist::{self, Link};
+use semver::Version;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A change an operation would make
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change {
+    /// An archive would be downloaded
+    Download { url: String },
+    /// A package version would be placed in the store and recorded
+    Install {
+        name: String,
+        version: String,
+        root: PathBuf,
+    },
+    /// A link (or copy) to a package file would be created
+    Link { source: PathBuf, target: PathBuf },
+    /// An installed file or link would be deleted
+    Unlink { target: PathBuf },
+    /// A package version would be deleted from the store and the database
+    Remove {
+        name: String,
+        version: String,
+        root: PathBuf,
+    },
+    /// The current version of a package would change
+    SetCurrent { name: String, version: String },
+}
+
+/// Whether fetching `url` downloads anything; local paths are used in place
+fn is_download(url: &str) -> bool {
+    url.contains("://") && !url.starts_with("file://")
+}
+
+/// Links declared by the `symlist` in `archive`, which holds `meta`
+pub fn archive_links(archive: &Path, meta: &Package) -> Result<Vec<Link>, UhpmError> {
+    let root = get_pkg_path(meta.name(), meta.version().clone());
+    let name = Path::new("symlist");
+    let content = match archive::read_file(archive, name)? {
+        Some(content) => Some(content),
+        None => match archive::nested_root(&archive::list(archive)?) {
+            Some(nested) => archive::read_file(archive, &nested.join(name))?,
+            None => None,
+        },
+    };
+    let Some(content) = content else {
+        return Ok(Vec::new());
+    };
+    symlist::parse_links(
+        &String::from_utf8_lossy(&content),
+        &root,
+        Some(meta.version()),
+    )
+    .map_err(|e| UhpmError::Parse(e.to_string()))
+}
+
+/// Links declared by the installed package at `root`
+pub fn installed_links(root: &Path) -> Vec<Link> {
+    symlist::load_links(&root.join("symlist"), root).unwrap_or_default()
+}
+
+fn link_changes(links: Vec<Link>) -> impl Iterator<Item = Change> {
+    links.into_iter().map(|link| Change::Link {
+        source: link.source,
+        target: link.target,
+    })
+}
+
+/// Changes of fetching `meta` from `url` and placing it in the store
+pub fn place(meta: &Package, url: Option<&str>) -> Vec<Change> {
+    let mut changes = Vec::new();
+    if let Some(url) = url.filter(|url| is_download(url)) {
+        changes.push(Change::Download {
+            url: url.to_string(),
+        });
+    }
+    changes.push(Change::Install {
+        name: meta.name().to_string(),
+        version: meta.version().to_string(),
+        root: get_pkg_path(meta.name(), meta.version().clone()),
+    });
+    changes
+}
+
+/// Changes of installing `meta` from `archive`, fetched from `url`
+///
+/// Links are only created for a package that is not installed yet
+/// (`fresh`); another version of an installed package just becomes the
+/// current one.
+pub fn install(
+    meta: &Package,
+    archive: &Path,
+    url: Option<&str>,
+    fresh: bool,
+) -> Result<Vec<Change>, UhpmError> {
+    let mut changes = place(meta, url);
+    if fresh {
+        changes.extend(link_changes(archive_links(archive, meta)?));
+    }
+    changes.push(Change::SetCurrent {
+        name: meta.name().to_string(),
+        version: meta.version().to_string(),
+    });
+    Ok(changes)
+}
+
+/// Changes of switching `name` from the installed version at `from` to
+/// `version` with `links`: the unversioned links move, versioned ones stay
+pub fn switch(name: &str, from: Option<&Path>, links: Vec<Link>, version: &Version) -> Vec<Change> {
+    let mut changes: Vec<Change> = from
+        .map(installed_links)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|link| !link.versioned && fs::symlink_metadata(&link.target).is_ok())
+        .map(|link| Change::Unlink {
+            target: link.target,
+        })
+        .collect();
+    changes.extend(link_changes(links));
+    changes.push(Change::SetCurrent {
+        name: name.to_string(),
+        version: version.to_string(),
+    });
+    changes
+}
+
+/// Changes of removing version `version` of `name`
+///
+/// The recorded files that still exist are deleted with the package; when
+/// other versions remain, the latest becomes the current one.
+pub async fn removal(name: &str, version: &str, db: &PackageDB) -> Result<Vec<Change>, UhpmError> {
+    let mut changes: Vec<Change> = db
+        .get_installed_files_with_mode(name, version)
+        .await?
+        .into_iter()
+        .map(|(file, _)| PathBuf::from(file))
+        .filter(|path| fs::symlink_metadata(path).is_ok())
+        .map(|target| Change::Unlink { target })
+        .collect();
+    let root = crate::package::packages_dir().join(format!("{}-{}", name, version));
+    changes.push(Change::Remove {
+        name: name.to_string(),
+        version: version.to_string(),
+        root: root.clone(),
+    });
+
+    let remaining = db
+        .list_packages()
+        .await?
+        .into_iter()
+        .filter(|(pkg, v, _)| pkg == name && v != version)
+        .filter_map(|(_, v, _)| Version::parse(&v).ok())
+        .max();
+    if let Some(latest) = remaining {
+        let current = db.get_package_version(name).await?;
+        let from = (current.as_deref() == Some(version)).then_some(root.as_path());
+        changes.extend(switch(
+            name,
+            from,
+            installed_links(&get_pkg_path(name, latest.clone())),
+            &latest,
+        ));
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::ArchiveOptions;
+
+    #[test]
+    fn test_install_changes_from_archive() {
+        let tmp = tempfile::tempdir().unwrap();
+        let src = tmp.path().join("demo");
+        fs::create_dir_all(src.join("bin")).unwrap();
+        fs::write(
+            src.join("uhp.toml"),
+            crate::package::test_manifest("demo", "1.2.0"),
+        )
+        .unwrap();
+        fs::write(
+            src.join("symlist"),
+            "bin/demo /opt/bin/demo\nbin/demo /opt/bin/demo-{major}.{minor}\n",
+        )
+        .unwrap();
+        let archive = tmp.path().join("demo.uhp");
+        archive::create(&src, &archive, &ArchiveOptions::default()).unwrap();
+        let meta = crate::package::installer::read_meta(&archive).unwrap();
+
+        let changes = install(&meta, &archive, Some("https://example.com/demo.uhp"), true).unwrap();
+        let root = get_pkg_path("demo", Version::new(1, 2, 0));
+        assert_eq!(
+            changes,
+            [
+                Change::Download {
+                    url: "https://example.com/demo.uhp".to_string()
+                },
+                Change::Install {
+                    name: "demo".to_string(),
+                    version: "1.2.0".to_string(),
+                    root: root.clone(),
+                },
+                Change::Link {
+                    source: root.join("bin/demo"),
+                    target: PathBuf::from("/opt/bin/demo"),
+                },
+                Change::Link {
+                    source: root.join("bin/demo"),
+                    target: PathBuf::from("/opt/bin/demo-1.2"),
+                },
+                Change::SetCurrent {
+                    name: "demo".to_string(),
+                    version: "1.2.0".to_string(),
+                },
+            ]
+        );
+
+        let update = install(&meta, &archive, Some("file:///srv/demo.uhp"), false).unwrap();
+        assert_eq!(update.len(), 2);
+        assert_eq!(
+            serde_json::to_value(&update[1]).unwrap()["change"],
+            "set_current"
+        );
+    }
+}
@@ -0,0 +1,551 @@
+//! # ELF Compatibility Scan
+//!
+//! Before the links of a package go live, the installer reads the ELF
+//! headers of the executables and shared libraries it ships and warns about
+//! what this machine cannot load:
+//!
+//! - a program interpreter (`PT_INTERP`, e.g. `/lib64/ld-linux-x86-64.so.2`)
+//!   that does not exist;
+//! - a `DT_NEEDED` library found neither in the package nor in the
+//!   directories the dynamic loader searches: `$LD_LIBRARY_PATH`, those
+//!   listed in `/etc/ld.so.conf` and the default `lib` directories;
+//! - `GLIBC_x.y` symbol versions newer than the glibc found by
+//!   [`requirements`](super::requirements).
+//!
+//! The headers are parsed here instead of asking `ldd`, which would run the
+//! loader of the package. The scan only warns; `scan_binaries: false` in
+//! `config.ron` turns it off, and it is skipped on platforms that do not
+//! load ELF binaries.
+
+use crate::package::pattern;
+use crate::package::requirements::{self, Found};
+use crate::{debug, platform, warn};
+use once_cell::sync::Lazy;
+use semver::Version;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+const ELF_MAGIC: &[u8; 4] = b"\x7fELF";
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_STRTAB: u64 = 5;
+const DT_VERNEED: u64 = 0x6fff_fffe;
+const DT_VERNEEDNUM: u64 = 0x6fff_ffff;
+
+/// What a binary needs from the dynamic loader
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ElfInfo {
+    /// Program interpreter, absent in static binaries and most libraries
+    pub interpreter: Option<String>,
+    /// Libraries listed as `DT_NEEDED`, by soname
+    pub needed: Vec<String>,
+    /// Newest `GLIBC_x.y` symbol version the binary refers to
+    pub glibc: Option<Version>,
+}
+
+/// Why a packaged binary will not load on this machine
+#[derive(Debug, Clone, PartialEq)]
+pub enum Issue {
+    Interpreter(String),
+    Library(String),
+    Glibc { required: Version, found: Version },
+}
+
+/// An [`Issue`] of one file, relative to the package root
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinaryIssue {
+    pub file: PathBuf,
+    pub issue: Issue,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::Interpreter(path) => write!(f, "interpreter {} not found", path),
+            Issue::Library(name) => write!(f, "library {} not found", name),
+            Issue::Glibc { required, found } => {
+                write!(f, "needs glibc {}, found {}", required, found)
+            }
+        }
+    }
+}
+
+/// Reads integers of the width and byte order of one ELF file
+struct Reader<'a> {
+    data: &'a [u8],
+    is64: bool,
+    le: bool,
+}
+
+impl Reader<'_> {
+    fn bytes<const N: usize>(&self, at: usize) -> Option<[u8; N]> {
+        self.data.get(at..at.checked_add(N)?)?.try_into().ok()
+    }
+
+    fn u16(&self, at: usize) -> Option<u16> {
+        let b = self.bytes(at)?;
+        Some(if self.le {
+            u16::from_le_bytes(b)
+        } else {
+            u16::from_be_bytes(b)
+        })
+    }
+
+    fn u32(&self, at: usize) -> Option<u32> {
+        let b = self.bytes(at)?;
+        Some(if self.le {
+            u32::from_le_bytes(b)
+        } else {
+            u32::from_be_bytes(b)
+        })
+    }
+
+    fn u64(&self, at: usize) -> Option<u64> {
+        let b = self.bytes(at)?;
+        Some(if self.le {
+            u64::from_le_bytes(b)
+        } else {
+            u64::from_be_bytes(b)
+        })
+    }
+
+    /// An address or size: 4 bytes in 32-bit files, 8 in 64-bit ones
+    fn word(&self, at: usize) -> Option<u64> {
+        if self.is64 {
+            self.u64(at)
+        } else {
+            self.u32(at).map(u64::from)
+        }
+    }
+
+    fn str(&self, at: usize) -> Option<&str> {
+        let rest = self.data.get(at..)?;
+        let end = rest.iter().position(|&b| b == 0)?;
+        std::str::from_utf8(&rest[..end]).ok()
+    }
+}
+
+struct Segment {
+    kind: u32,
+    offset: u64,
+    vaddr: u64,
+    filesz: u64,
+}
+
+/// File offset of the virtual address `addr`
+fn file_offset(segments: &[Segment], addr: u64) -> Option<usize> {
+    let load = segments
+        .iter()
+        .find(|s| s.kind == PT_LOAD && addr >= s.vaddr && addr - s.vaddr < s.filesz)?;
+    usize::try_from(load.offset.checked_add(addr - load.vaddr)?).ok()
+}
+
+/// Parses the loader requirements of an ELF image, `None` if `data` is
+/// not one or is malformed
+pub fn parse(data: &[u8]) -> Option<ElfInfo> {
+    if data.get(..4)? != ELF_MAGIC {
+        return None;
+    }
+    let is64 = match data.get(4)? {
+        1 => false,
+        2 => true,
+        _ => return None,
+    };
+    let le = match data.get(5)? {
+        1 => true,
+        2 => false,
+        _ => return None,
+    };
+    let r = Reader { data, is64, le };
+
+    let (phoff, phentsize, phnum) = if is64 {
+        (r.u64(32)?, r.u16(54)?, r.u16(56)?)
+    } else {
+        (r.u32(28)?.into(), r.u16(42)?, r.u16(44)?)
+    };
+    let phoff = usize::try_from(phoff).ok()?;
+    let mut segments = Vec::new();
+    for i in 0..usize::from(phnum) {
+        let at = phoff.checked_add(i * usize::from(phentsize))?;
+        if at >= data.len() {
+            return None;
+        }
+        segments.push(if is64 {
+            Segment {
+                kind: r.u32(at)?,
+                offset: r.u64(at + 8)?,
+                vaddr: r.u64(at + 16)?,
+                filesz: r.u64(at + 32)?,
+            }
+        } else {
+            Segment {
+                kind: r.u32(at)?,
+                offset: r.u32(at + 4)?.into(),
+                vaddr: r.u32(at + 8)?.into(),
+                filesz: r.u32(at + 16)?.into(),
+            }
+        });
+    }
+
+    let mut info = ElfInfo::default();
+    if let Some(interp) = segments.iter().find(|s| s.kind == PT_INTERP) {
+        info.interpreter = r
+            .str(usize::try_from(interp.offset).ok()?)
+            .map(String::from);
+    }
+    let Some(dynamic) = segments.iter().find(|s| s.kind == PT_DYNAMIC) else {
+        return Some(info);
+    };
+
+    let word = if is64 { 8 } else { 4 };
+    let start = usize::try_from(dynamic.offset).ok()?;
+    let end = start
+        .saturating_add(usize::try_from(dynamic.filesz).ok()?)
+        .min(data.len());
+    let (mut needed, mut strtab, mut verneed, mut verneednum) = (Vec::new(), None, None, 0);
+    let mut at = start;
+    while at < end {
+        let (Some(tag), Some(val)) = (r.word(at), r.word(at + word)) else {
+            break;
+        };
+        match tag {
+            DT_NULL => break,
+            DT_NEEDED => needed.push(val),
+            DT_STRTAB => strtab = Some(val),
+            DT_VERNEED => verneed = Some(val),
+            DT_VERNEEDNUM => verneednum = val,
+            _ => {}
+        }
+        at += 2 * word;
+    }
+
+    let strtab = file_offset(&segments, strtab?)?;
+    let string = |offset: u64| {
+        usize::try_from(offset)
+            .ok()
+            .and_then(|offset| r.str(strtab.checked_add(offset)?))
+    };
+    info.needed = needed
+        .into_iter()
+        .filter_map(|offset| string(offset).map(String::from))
+        .collect();
+
+    // Elf_Verneed entries, each followed by its Elf_Vernaux list; both are
+    // laid out the same in 32- and 64-bit files
+    let mut entry = verneed.and_then(|addr| file_offset(&segments, addr));
+    for _ in 0..verneednum {
+        let Some(at) = entry.filter(|&at| at < data.len()) else {
+            break;
+        };
+        let (Some(count), Some(aux), Some(next)) = (r.u16(at + 2), r.u32(at + 8), r.u32(at + 12))
+        else {
+            break;
+        };
+        let mut aux_at = at.checked_add(aux as usize);
+        for _ in 0..count {
+            let Some(a) = aux_at.filter(|&a| a < data.len()) else {
+                break;
+            };
+            let (Some(name), Some(aux_next)) = (r.u32(a + 8), r.u32(a + 12)) else {
+                break;
+            };
+            if let Some(version) = string(name.into())
+                .and_then(|name| name.strip_prefix("GLIBC_"))
+                .and_then(requirements::find_version)
+                .filter(|version| info.glibc.as_ref().is_none_or(|newest| version > newest))
+            {
+                info.glibc = Some(version);
+            }
+            aux_at = (aux_next != 0)
+                .then(|| a.checked_add(aux_next as usize))
+                .flatten();
+        }
+        entry = (next != 0).then(|| at.checked_add(next as usize)).flatten();
+    }
+    Some(info)
+}
+
+/// Reads the loader requirements of `path`, `None` if it is not an ELF
+/// file
+pub fn read(path: &Path) -> io::Result<Option<ElfInfo>> {
+    let mut file = File::open(path)?;
+    let mut magic = [0u8; 4];
+    if file.read_exact(&mut magic).is_err() || &magic != ELF_MAGIC {
+        return Ok(None);
+    }
+    let mut data = magic.to_vec();
+    file.read_to_end(&mut data)?;
+    Ok(parse(&data))
+}
+
+/// Directories listed in an `ld.so.conf` file and the files it includes
+fn read_ld_conf(path: &Path, dirs: &mut Vec<PathBuf>, depth: usize) {
+    if depth > 8 {
+        return;
+    }
+    let Ok(text) = fs::read_to_string(path) else {
+        return;
+    };
+    let base = path.parent().unwrap_or(Path::new("/"));
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if let Some(pattern) = line.strip_prefix("include") {
+            let pattern = base.join(pattern.trim());
+            let (Some(dir), Some(name)) = (pattern.parent(), pattern.file_name()) else {
+                continue;
+            };
+            let name = name.to_string_lossy();
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+            let mut confs: Vec<PathBuf> = entries
+                .flatten()
+                .filter(|e| pattern::matches(&name, &e.file_name().to_string_lossy()))
+                .map(|e| e.path())
+                .collect();
+            confs.sort();
+            for conf in confs {
+                read_ld_conf(&conf, dirs, depth + 1);
+            }
+        } else if line.starts_with('/') {
+            dirs.push(PathBuf::from(line));
+        }
+    }
+}
+
+/// Directories the dynamic loader of this machine searches
+static LIBRARY_DIRS: Lazy<Vec<PathBuf>> = Lazy::new(|| {
+    let mut dirs = Vec::new();
+    if let Some(path) = std::env::var_os("LD_LIBRARY_PATH") {
+        dirs.extend(std::env::split_paths(&path).filter(|d| !d.as_os_str().is_empty()));
+    }
+    read_ld_conf(Path::new("/etc/ld.so.conf"), &mut dirs, 0);
+    for dir in ["/lib", "/usr/lib", "/lib64", "/usr/lib64", "/usr/local/lib"] {
+        dirs.push(PathBuf::from(dir));
+    }
+    dirs
+});
+
+fn on_system(library: &str) -> bool {
+    if library.contains('/') {
+        return Path::new(library).exists();
+    }
+    LIBRARY_DIRS.iter().any(|dir| dir.join(library).exists())
+}
+
+/// Whether a file is worth reading: executables and shared libraries
+fn is_candidate(path: &Path, meta: &fs::Metadata) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    platform::is_executable(path, meta) || name.ends_with(".so") || name.contains(".so.")
+}
+
+/// Binaries under the package directory `root` that will not load on this
+/// machine
+///
+/// Libraries are looked up by name anywhere in the package before the
+/// system directories, which covers `$ORIGIN` run paths without expanding
+/// them.
+pub fn scan(root: &Path) -> Vec<BinaryIssue> {
+    if !platform::loads_elf() {
+        return Vec::new();
+    }
+    let entries: Vec<walkdir::DirEntry> = WalkDir::new(root).into_iter().flatten().collect();
+    let bundled: HashSet<String> = entries
+        .iter()
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .collect();
+
+    let mut issues = Vec::new();
+    for entry in entries.iter().filter(|e| e.file_type().is_file()) {
+        let path = entry.path();
+        let Ok(meta) = entry.metadata() else { continue };
+        if !is_candidate(path, &meta) {
+            continue;
+        }
+        let info = match read(path) {
+            Ok(Some(info)) => info,
+            Ok(None) => continue,
+            Err(e) => {
+                debug!("package.elf.unreadable", path.display(), e);
+                continue;
+            }
+        };
+        let file = path.strip_prefix(root).unwrap_or(path).to_path_buf();
+        let mut report = |issue| {
+            issues.push(BinaryIssue {
+                file: file.clone(),
+                issue,
+            })
+        };
+        if let Some(interp) = info.interpreter.filter(|i| !Path::new(i).exists()) {
+            report(Issue::Interpreter(interp));
+        }
+        for library in info.needed {
+            if !bundled.contains(&library) && !on_system(&library) {
+                report(Issue::Library(library));
+            }
+        }
+        match (info.glibc, requirements::detect("glibc", true)) {
+            (Some(required), Found::Version(found)) if required > found => {
+                report(Issue::Glibc { required, found })
+            }
+            _ => {}
+        }
+    }
+    issues
+}
+
+/// Warns about the binaries under `root` this machine cannot load, see
+/// [`scan`]
+pub fn warn_unloadable(root: &Path, package: &str) {
+    for BinaryIssue { file, issue } in scan(root) {
+        warn!(
+            "package.elf.unloadable",
+            package,
+            file.display(),
+            issue.to_string()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const BASE: u64 = 0x40_0000;
+
+    fn put(file: &mut [u8], at: usize, bytes: &[u8]) {
+        file[at..at + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn intern(strtab: &mut Vec<u8>, s: &str) -> u64 {
+        let offset = strtab.len() as u64;
+        strtab.extend_from_slice(s.as_bytes());
+        strtab.push(0);
+        offset
+    }
+
+    /// A 64-bit little-endian shared object with one `PT_LOAD` covering
+    /// the whole file
+    fn sample_elf(interp: &str, needed: &[&str], glibc: &[&str]) -> Vec<u8> {
+        let mut strtab = vec![0];
+        let needed: Vec<u64> = needed.iter().map(|n| intern(&mut strtab, n)).collect();
+        let libc = intern(&mut strtab, "libc.so.6");
+        let versions: Vec<u64> = glibc.iter().map(|v| intern(&mut strtab, v)).collect();
+
+        let phoff = 64;
+        let interp_off = phoff + 3 * 56;
+        let dyn_off = interp_off + interp.len() + 1;
+        let dyn_size = (needed.len() + 4) * 16;
+        let strtab_off = dyn_off + dyn_size;
+        let verneed_off = strtab_off + strtab.len();
+        let end = verneed_off + 16 + versions.len() * 16;
+
+        let mut f = vec![0u8; end];
+        put(&mut f, 0, b"\x7fELF\x02\x01\x01");
+        put(&mut f, 16, &3u16.to_le_bytes());
+        put(&mut f, 32, &(phoff as u64).to_le_bytes());
+        put(&mut f, 54, &56u16.to_le_bytes());
+        put(&mut f, 56, &3u16.to_le_bytes());
+        let segments = [
+            (PT_LOAD, 0, end),
+            (PT_INTERP, interp_off, interp.len() + 1),
+            (PT_DYNAMIC, dyn_off, dyn_size),
+        ];
+        for (i, (kind, offset, size)) in segments.into_iter().enumerate() {
+            let at = phoff + i * 56;
+            put(&mut f, at, &kind.to_le_bytes());
+            put(&mut f, at + 8, &(offset as u64).to_le_bytes());
+            put(&mut f, at + 16, &(BASE + offset as u64).to_le_bytes());
+            put(&mut f, at + 32, &(size as u64).to_le_bytes());
+        }
+        put(&mut f, interp_off, interp.as_bytes());
+
+        let mut dynamic: Vec<(u64, u64)> = needed.iter().map(|&n| (DT_NEEDED, n)).collect();
+        dynamic.push((DT_STRTAB, BASE + strtab_off as u64));
+        dynamic.push((DT_VERNEED, BASE + verneed_off as u64));
+        dynamic.push((DT_VERNEEDNUM, 1));
+        for (i, (tag, val)) in dynamic.into_iter().enumerate() {
+            put(&mut f, dyn_off + i * 16, &tag.to_le_bytes());
+            put(&mut f, dyn_off + i * 16 + 8, &val.to_le_bytes());
+        }
+        put(&mut f, strtab_off, &strtab);
+
+        put(&mut f, verneed_off, &1u16.to_le_bytes());
+        put(
+            &mut f,
+            verneed_off + 2,
+            &(versions.len() as u16).to_le_bytes(),
+        );
+        put(&mut f, verneed_off + 4, &(libc as u32).to_le_bytes());
+        put(&mut f, verneed_off + 8, &16u32.to_le_bytes());
+        for (i, name) in versions.iter().enumerate() {
+            let at = verneed_off + 16 + i * 16;
+            put(&mut f, at + 8, &(*name as u32).to_le_bytes());
+            let next: u32 = if i + 1 < versions.len() { 16 } else { 0 };
+            put(&mut f, at + 12, &next.to_le_bytes());
+        }
+        f
+    }
+
+    #[test]
+    fn test_parse_elf() {
+        let data = sample_elf(
+            "/lib64/ld-linux-x86-64.so.2",
+            &["libfoo.so.1", "libc.so.6"],
+            &["GLIBC_2.34", "GLIBC_PRIVATE", "GLIBC_2.2.5"],
+        );
+        let info = parse(&data).unwrap();
+        assert_eq!(
+            info.interpreter.as_deref(),
+            Some("/lib64/ld-linux-x86-64.so.2")
+        );
+        assert_eq!(info.needed, ["libfoo.so.1", "libc.so.6"]);
+        assert_eq!(info.glibc, Some(Version::new(2, 34, 0)));
+
+        assert_eq!(parse(b"#!/bin/sh\n"), None);
+        assert_eq!(parse(&data[..40]), None);
+    }
+
+    #[test]
+    fn test_scan_reports_missing_loader_parts() {
+        if !platform::loads_elf() {
+            return;
+        }
+        let dir = tempdir().unwrap();
+        let lib = dir.path().join("lib");
+        fs::create_dir(&lib).unwrap();
+        let elf = sample_elf(
+            "/uhpm-surely-missing/ld.so",
+            &["libfoo.so.1", "libuhpm-surely-missing.so.0"],
+            &[],
+        );
+        fs::write(lib.join("libtool.so"), elf).unwrap();
+        fs::write(lib.join("libfoo.so.1"), "").unwrap();
+
+        let issues = scan(dir.path());
+        assert_eq!(
+            issues,
+            [
+                BinaryIssue {
+                    file: PathBuf::from("lib/libtool.so"),
+                    issue: Issue::Interpreter("/uhpm-surely-missing/ld.so".into()),
+                },
+                BinaryIssue {
+                    file: PathBuf::from("lib/libtool.so"),
+                    issue: Issue::Library("libuhpm-surely-missing.so.0".into()),
+                },
+            ]
+        );
+    }
+}
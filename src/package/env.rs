@@ -0,0 +1,186 @@
+//! # Package Environment
+//!
+//! `uhpm env` prints the environment the installed packages need: the bin
+//! directory on `PATH` and the variables packages declare in the `[env]`
+//! table of their `uhp.toml`, where `{root}` stands for the package root:
+//!
+//! ```toml
+//! [env]
+//! JAVA_HOME = "{root}"
+//! MANPATH = "{root}/share/man:"
+//! ```
+//!
+//! By default the result is a list of POSIX `export` lines for `eval`;
+//! with `--json` it is an [`Environment`] document for tools such as
+//! direnv, prompts or editors. Only current package versions count, and
+//! when two packages set the same variable the one sorted last by name
+//! wins. `PATH`, `LD_*` and `DYLD_*` are never taken from packages, so an
+//! installed package cannot redirect which programs or libraries load.
+
+use crate::db::PackageDB;
+use crate::error::UhpmError;
+use crate::package::bin_layout::{self, BinLayout};
+use crate::package::{Package, get_pkg_path, meta_parser};
+use crate::platform;
+use semver::Version;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// The environment of the installation
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Environment {
+    /// The uhpm home
+    pub home: PathBuf,
+    /// Directories to put on `PATH`
+    pub bin_dirs: Vec<PathBuf>,
+    /// Whether the bin directory already is on `PATH`
+    pub bin_on_path: bool,
+    /// Variables of all packages merged
+    pub exports: BTreeMap<String, String>,
+    /// Current package versions, sorted by name
+    pub packages: Vec<PackageEnv>,
+}
+
+/// Current version of an installed package and the variables it sets
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PackageEnv {
+    pub name: String,
+    pub version: String,
+    pub root: PathBuf,
+    pub env: BTreeMap<String, String>,
+}
+
+/// Whether `name` can be exported by a shell
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Whether `name` controls which programs and libraries run, so a package
+/// must not set it: `PATH`, `LD_*` and `DYLD_*`
+fn is_protected(name: &str) -> bool {
+    name == "PATH" || name.starts_with("LD_") || name.starts_with("DYLD_")
+}
+
+/// Variables declared by `meta` with `{root}` replaced by `root`; names a
+/// shell cannot export and protected ones (see [`is_protected`]) are
+/// dropped
+pub fn package_exports(meta: &Package, root: &Path) -> BTreeMap<String, String> {
+    let root = root.display().to_string();
+    meta.env()
+        .iter()
+        .filter(|(name, _)| is_valid_name(name) && !is_protected(name))
+        .map(|(name, value)| (name.clone(), value.replace("{root}", &root)))
+        .collect()
+}
+
+/// Computes the environment of the installation in `db`
+///
+/// Packages whose metadata cannot be read are listed without variables.
+pub async fn collect(db: &PackageDB) -> Result<Environment, UhpmError> {
+    let mut packages = Vec::new();
+    for (name, version, current) in db.list_packages().await? {
+        if !current {
+            continue;
+        }
+        let root = match Version::parse(&version) {
+            Ok(v) => get_pkg_path(&name, v),
+            Err(_) => continue,
+        };
+        let env = meta_parser(&root.join("uhp.toml"))
+            .map(|meta| package_exports(&meta, &root))
+            .unwrap_or_default();
+        packages.push(PackageEnv {
+            name,
+            version,
+            root,
+            env,
+        });
+    }
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let layout = BinLayout::configured();
+    Ok(Environment {
+        home: platform::uhpm_home(),
+        bin_dirs: vec![layout.dir()],
+        bin_on_path: bin_layout::on_path(&layout),
+        exports: merge(&packages),
+        packages,
+    })
+}
+
+fn merge(packages: &[PackageEnv]) -> BTreeMap<String, String> {
+    packages.iter().flat_map(|pkg| pkg.env.clone()).collect()
+}
+
+/// Quotes `value` for a POSIX shell
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Renders the environment as POSIX `export` lines
+pub fn to_shell(env: &Environment) -> String {
+    let mut out = String::new();
+    for dir in &env.bin_dirs {
+        out.push_str(&format!(
+            "export PATH={}\"${{PATH:+:$PATH}}\"\n",
+            quote(&dir.display().to_string())
+        ));
+    }
+    for (name, value) in &env.exports {
+        out.push_str(&format!("export {}={}\n", name, quote(value)));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, env: &[(&str, &str)]) -> PackageEnv {
+        PackageEnv {
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+            root: PathBuf::from(format!("/pkgs/{}", name)),
+            env: env
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_merge_and_render_shell() {
+        let packages = vec![
+            package("jdk", &[("JAVA_HOME", "/pkgs/jdk"), ("EDITOR", "vi")]),
+            package("zed", &[("EDITOR", "it's zed")]),
+        ];
+        let env = Environment {
+            home: PathBuf::from("/home/u/.uhpm"),
+            bin_dirs: vec![PathBuf::from("/home/u/.local/bin")],
+            bin_on_path: false,
+            exports: merge(&packages),
+            packages,
+        };
+
+        assert_eq!(
+            to_shell(&env),
+            "export PATH='/home/u/.local/bin'\"${PATH:+:$PATH}\"\n\
+             export EDITOR='it'\\''s zed'\n\
+             export JAVA_HOME='/pkgs/jdk'\n"
+        );
+        let json: serde_json::Value = serde_json::to_value(&env).unwrap();
+        assert_eq!(json["packages"][0]["env"]["JAVA_HOME"], "/pkgs/jdk");
+        assert_eq!(json["exports"]["EDITOR"], "it's zed");
+
+        assert!(is_valid_name("JAVA_HOME") && !is_valid_name("A;rm -rf") && !is_valid_name("1X"));
+        assert!(
+            is_protected("PATH") && is_protected("LD_PRELOAD") && is_protected("DYLD_LIBRARY_PATH")
+        );
+        assert!(!is_protected("MANPATH") && !is_protected("OLD_PWD"));
+    }
+}
@@ -0,0 +1,177 @@
+//! # Package Hooks
+//!
+//! Runs user-configured shell commands around package operations, and the
+//! scripts packages ship themselves.
+//!
+//! Commands are executed through the platform shell (`sh -c`, PowerShell on
+//! Windows) with the following environment:
+//! - `UHPM_PKG_NAME` — package name
+//! - `UHPM_PKG_VERSION` — package version the hook relates to
+//! - `UHPM_PKG_DIR` — directory of that version in the package store
+//!
+//! They are the user's own commands and run unrestricted, like a shell
+//! would run them.
+//!
+//! ## Package scripts
+//! A `.uhp` archive may contain `hooks/post-install` (run once the package
+//! is installed for the first time), `hooks/pre-remove` (run before a
+//! version is removed; failing aborts the removal) and
+//! `hooks/post-upgrade` (run by `uhpm update` once the new version is
+//! current). They run through `sh` in the package root with
+//! `UHPM_PKG_NAME`, `UHPM_PKG_VERSION` and `UHPM_PKG_ROOT` set, in the
+//! [`sandbox`] with only the package root writable in the home directory,
+//! and are killed after `hook_timeout_secs`. Since they are
+//! code from the package author, they only run with `allow_hooks: true` in
+//! `config.ron`; otherwise skipping one is reported.
+
+use crate::config::Config;
+use crate::error::HookError;
+use crate::package::{packages_dir, sandbox};
+use crate::{debug, info, platform, warn};
+use std::path::Path;
+use std::time::Duration;
+
+/// Scripts a package may ship in its `hooks/` directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageHook {
+    PostInstall,
+    PreRemove,
+    PostUpgrade,
+}
+
+impl PackageHook {
+    /// File name inside `hooks/`
+    pub fn file_name(self) -> &'static str {
+        match self {
+            PackageHook::PostInstall => "post-install",
+            PackageHook::PreRemove => "pre-remove",
+            PackageHook::PostUpgrade => "post-upgrade",
+        }
+    }
+}
+
+/// Runs the `hook` script of version `pkg_version` of `pkg_name`, installed
+/// at `root`, if the package ships one
+///
+/// Returns whether a script ran.
+///
+/// # Errors
+/// Returns [`HookError::Failed`] if the script exits with a non-zero
+/// status and [`HookError::TimedOut`] if it is killed.
+pub async fn run_package_hook(
+    hook: PackageHook,
+    root: &Path,
+    pkg_name: &str,
+    pkg_version: &str,
+) -> Result<bool, HookError> {
+    let script = Path::new("hooks").join(hook.file_name());
+    if !root.join(&script).is_file() {
+        return Ok(false);
+    }
+    let config = Config::load_or_default();
+    if !config.allow_hooks {
+        warn!("package.hooks.script_skipped", hook.file_name(), pkg_name);
+        return Ok(false);
+    }
+    let timeout = Duration::from_secs(config.hook_timeout_secs);
+    run_script(&script, root, pkg_name, pkg_version, timeout).await?;
+    Ok(true)
+}
+
+/// Runs `script`, relative to `root`, killing it after `timeout`
+async fn run_script(
+    script: &Path,
+    root: &Path,
+    pkg_name: &str,
+    pkg_version: &str,
+    timeout: Duration,
+) -> Result<(), HookError> {
+    info!("package.hooks.running", script.display(), pkg_name);
+    let cmd = format!("sh {}", script.display());
+    let mut child = sandbox::shell_command(&cmd, &[root])?
+        .current_dir(root)
+        .env("UHPM_PKG_NAME", pkg_name)
+        .env("UHPM_PKG_VERSION", pkg_version)
+        .env("UHPM_PKG_ROOT", root)
+        .kill_on_drop(true)
+        .spawn()?;
+    let status = match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(status) => status?,
+        Err(_) => {
+            let _ = child.kill().await;
+            return Err(HookError::TimedOut(cmd, timeout.as_secs()));
+        }
+    };
+    debug!("package.hooks.finished", &cmd, status.code());
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(HookError::Failed(cmd, status.code().unwrap_or(-1)))
+    }
+}
+
+/// Runs a user-configured hook command for a package, outside the sandbox
+///
+/// # Errors
+/// Returns [`HookError::Failed`] if the command exits with a non-zero status.
+pub async fn run_command(cmd: &str, pkg_name: &str, pkg_version: &str) -> Result<(), HookError> {
+    info!("package.hooks.running", cmd, pkg_name);
+
+    let pkg_dir = packages_dir().join(format!("{}-{}", pkg_name, pkg_version));
+    let status = platform::shell_command(cmd)
+        .env("UHPM_PKG_NAME", pkg_name)
+        .env("UHPM_PKG_VERSION", pkg_version)
+        .env("UHPM_PKG_DIR", &pkg_dir)
+        .status()
+        .await?;
+
+    debug!("package.hooks.finished", cmd, status.code());
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(HookError::Failed(
+            cmd.to_string(),
+            status.code().unwrap_or(-1),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[tokio::test]
+    async fn test_package_script_environment_and_timeout() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("hooks")).unwrap();
+        let script = Path::new("hooks/post-install");
+        fs::write(
+            root.join(script),
+            "echo \"$UHPM_PKG_NAME $UHPM_PKG_VERSION\" > installed\n",
+        )
+        .unwrap();
+
+        run_script(script, root, "tool", "1.2.0", Duration::from_secs(10))
+            .await
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("installed")).unwrap(),
+            "tool 1.2.0\n"
+        );
+
+        fs::write(root.join(script), "sleep 5\n").unwrap();
+        assert!(matches!(
+            run_script(script, root, "tool", "1.2.0", Duration::from_millis(200)).await,
+            Err(HookError::TimedOut(..))
+        ));
+        assert!(
+            !run_package_hook(PackageHook::PreRemove, root, "tool", "1.2.0")
+                .await
+                .unwrap()
+        );
+    }
+}
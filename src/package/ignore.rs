@@ -0,0 +1,153 @@
+//! # Ignored Packages
+//!
+//! `ignore` in `config.ron` lists packages uhpm keeps away unless they are
+//! asked for by name, e.g. `ignore: ["telemetry-tool", "foo<2.0"]`:
+//!
+//! - search results leave them out;
+//! - the resolver never picks them to satisfy a dependency, the
+//!   resolution fails naming the entry instead;
+//! - `uhpm update` never offers them as the new version.
+//!
+//! An entry is a package name, which may use the wildcards of
+//! [`pattern`], optionally followed by the versions to ignore in the
+//! syntax of [`VersionSpec`]: a requirement such as `<2.0` or `>=1.4, <1.6`
+//! right after the name, or an exact version after `@` (`foo@1.2.3`).
+//! Without versions every version is ignored. `uhpm install foo` and
+//! `uhpm update foo@<version>` still work for an ignored package.
+
+use crate::config::Config;
+use crate::package::{VersionSpec, pattern};
+use crate::warn;
+use semver::Version;
+use std::fmt;
+use std::str::FromStr;
+
+/// One entry of the `ignore` list
+#[derive(Debug, Clone, PartialEq)]
+pub struct IgnoreRule {
+    /// Package name or wildcard pattern
+    pub name: String,
+    /// Ignored versions; every version when unset
+    pub versions: Option<VersionSpec>,
+}
+
+impl IgnoreRule {
+    /// Whether the rule covers `version` of the package `name`
+    ///
+    /// A version that is not valid semver is only covered by a rule
+    /// without versions.
+    pub fn matches(&self, name: &str, version: &str) -> bool {
+        pattern::matches(&self.name, name)
+            && self.versions.as_ref().is_none_or(|spec| {
+                Version::parse(version).is_ok_and(|version| spec.matches(&version))
+            })
+    }
+}
+
+impl FromStr for IgnoreRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s.find(['<', '>', '=', '~', '^', '@']).unwrap_or(s.len());
+        let name = s[..split].trim();
+        if name.is_empty() {
+            return Err(format!("`{}` names no package", s));
+        }
+        let versions = s[split..].trim_start_matches('@').trim();
+        let versions = if versions.is_empty() {
+            None
+        } else {
+            Some(
+                versions
+                    .parse()
+                    .map_err(|e| format!("`{}`: invalid versions: {}", s, e))?,
+            )
+        };
+        Ok(Self {
+            name: name.to_string(),
+            versions,
+        })
+    }
+}
+
+impl fmt::Display for IgnoreRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.versions {
+            Some(VersionSpec::Exact(version)) => write!(f, "{}@{}", self.name, version),
+            Some(VersionSpec::Req(req)) => write!(f, "{}{}", self.name, req),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// The configured `ignore` list
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IgnoreList {
+    rules: Vec<IgnoreRule>,
+}
+
+impl IgnoreList {
+    /// Parses `entries`; invalid ones are reported and skipped
+    pub fn new(entries: &[String]) -> Self {
+        let rules = entries
+            .iter()
+            .filter_map(|entry| match entry.parse() {
+                Ok(rule) => Some(rule),
+                Err(e) => {
+                    warn!("package.ignore.invalid_entry", e);
+                    None
+                }
+            })
+            .collect();
+        Self { rules }
+    }
+
+    /// The list from `config.ron`
+    pub fn load() -> Self {
+        Self::new(&Config::load_or_default().ignore)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The first rule covering `version` of the package `name`
+    pub fn rule(&self, name: &str, version: &str) -> Option<&IgnoreRule> {
+        self.rules.iter().find(|rule| rule.matches(name, version))
+    }
+
+    /// Whether `version` of the package `name` is ignored
+    pub fn ignores(&self, name: &str, version: &str) -> bool {
+        self.rule(name, version).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules() {
+        let list = IgnoreList::new(&[
+            "telemetry-*".to_string(),
+            "foo<2.0".to_string(),
+            "bar @ 1.2.3".to_string(),
+            "<1.0".to_string(),
+            "baz>=x".to_string(),
+        ]);
+        assert_eq!(list.rules.len(), 3);
+
+        assert!(list.ignores("telemetry-tool", "5.0.0"));
+        assert!(list.ignores("telemetry-tool", "nightly"));
+        assert!(list.ignores("foo", "1.9.9"));
+        assert!(!list.ignores("foo", "2.0.0"));
+        assert!(!list.ignores("foo", "nightly"));
+        assert!(list.ignores("bar", "1.2.3"));
+        assert!(!list.ignores("bar", "1.2.4"));
+        assert!(!list.ignores("food", "1.0.0"));
+
+        assert_eq!(list.rule("foo", "1.0.0").unwrap().to_string(), "foo<2.0");
+        assert_eq!(list.rule("bar", "1.2.3").unwrap().to_string(), "bar@1.2.3");
+    }
+}
@@ -0,0 +1,98 @@
+//! # Package Details
+//!
+//! Collects what `uhpm info <package>` shows. Installed packages are
+//! described from the local database: metadata of the current version,
+//! where it came from, how many files it installed and when. Packages that
+//! are not installed fall back to what the repositories list about them.
+
+use crate::db::PackageDB;
+use crate::error::UhpmError;
+use crate::package::{Package, Provenance, tree};
+use crate::repo::index::SearchHit;
+
+/// Details of an installed package
+#[derive(Debug)]
+pub struct InstalledInfo {
+    /// Metadata of the current version
+    pub package: Package,
+    /// Where the current version was installed from, if recorded
+    pub provenance: Option<Provenance>,
+    /// Files and links installed by the current version
+    pub files: usize,
+    /// When the current version was installed, if recorded
+    pub installed_at: Option<String>,
+    /// All installed versions, newest first
+    pub versions: Vec<String>,
+}
+
+/// What is known about a package
+#[derive(Debug)]
+pub enum PackageInfo {
+    Installed(Box<InstalledInfo>),
+    /// Not installed, but offered by a repository
+    Available(SearchHit),
+}
+
+/// Details of the current version of `name`, `None` if it is not installed
+pub async fn installed(db: &PackageDB, name: &str) -> Result<Option<InstalledInfo>, UhpmError> {
+    let Some(package) = db.get_current_package(name).await? else {
+        return Ok(None);
+    };
+    let version = package.version().to_string();
+    let versions = tree::group(db.list_packages().await?)
+        .remove(name)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(version, _)| version)
+        .collect();
+
+    Ok(Some(InstalledInfo {
+        provenance: db.get_package_provenance(name, &version).await?,
+        files: db.get_installed_files(name, &version).await?.len(),
+        installed_at: db.get_installed_at(name, &version).await?,
+        versions,
+        package,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::{LinkMode, Source};
+    use semver::Version;
+
+    #[tokio::test]
+    async fn test_installed_details() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = PackageDB::new(&tmp.path().join("packages.db"))
+            .unwrap()
+            .init()
+            .await
+            .unwrap();
+        assert!(installed(&db, "tool").await.unwrap().is_none());
+
+        for version in ["1.0.0", "1.1.0"] {
+            let pkg = Package::new(
+                "tool",
+                Version::parse(version).unwrap(),
+                "alice",
+                Source::Raw("https://example.com/tool".to_string()),
+                "abc123",
+                vec![("libfoo".to_string(), Version::new(2, 0, 0))],
+            );
+            let files = ["/home/u/.local/bin/tool".to_string()];
+            db.add_package_full(&pkg, &files, LinkMode::Symlink)
+                .await
+                .unwrap();
+        }
+        db.set_current_version("tool", "1.0.0").await.unwrap();
+
+        let info = installed(&db, "tool").await.unwrap().unwrap();
+        assert_eq!(info.package.version(), &Version::new(1, 0, 0));
+        assert_eq!(info.package.author(), "alice");
+        assert_eq!(info.package.dependencies().len(), 1);
+        assert_eq!(info.files, 1);
+        assert!(info.installed_at.is_some());
+        assert_eq!(info.versions, ["1.1.0", "1.0.0"]);
+    }
+}
@@ -7,6 +7,7 @@
 //!
 //! - [`InstallError`]: Enumeration of possible installation errors
 //! - [`install()`]: Main installation function for package archives
+//! - [`install_at()`]: Installs into an isolated installation at a given root
 //! - [`create_symlinks()`]: Creates symbolic links for package files
 //! - [`unpack()`]: Extracts package archives to temporary directory
 //!
@@ -16,23 +17,46 @@
 //! 2. **Metadata Parsing**: Package metadata is read from `uhp.toml` file
 //! 3. **Version Check**: Verifies if package is already installed
 //! 4. **Directory Setup**: Creates package directory in UHPM home
-//! 5. **Symlink Creation**: Creates symbolic links based on `symlist`
-//! 6. **Database Registration**: Records package info in package database
+//! 5. **Symlink Creation**: Creates symbolic links based on `symlist` and
+//!    activates declared systemd user units
+//! 6. **Database Registration**: Records package info, provenance, the
+//!    resolved dependency closure and documentation paths in package database
+//! 7. **Archive Cache**: Keeps the archive if `cache.keep_archives` is set
+//!
+//! Steps 4 to 6 are journaled in a [`Transaction`]; if one of them fails,
+//! the package directory, created links and database rows are rolled back
+//! and files the links replaced are restored.
 //!
 //! ## Error Handling
 //!
 //! Errors are categorized into I/O errors and metadata parsing errors,
 //! both wrapped in the [`InstallError`] enumeration.
 
+use crate::archive;
+use crate::config::Config;
 use crate::db::PackageDB;
+use crate::db::storage::PackageStore;
 use crate::error::UhpmError;
-use crate::package::Package;
-use crate::symlist;
+use crate::events::{self, ProgressEvent};
+use crate::package::{
+    LinkMode, Package, Provenance, app_bundles, archive_cache, assets, closure,
+    conflicts::{self, Claim},
+    docs, elf, get_pkg_path,
+    hooks::{self, PackageHook},
+    packages_dir, requirements, systemd,
+    transaction::{self, Transaction},
+    verify,
+};
+use crate::{crash, platform, signing, symlist};
 use crate::{debug, info, warn};
-use flate2::read::GzDecoder;
 use std::fs;
 use std::path::{Path, PathBuf};
-use tar::Archive;
+use tokio::sync::Semaphore;
+use walkdir::WalkDir;
+
+/// Number of archives verified and unpacked at the same time by
+/// [`prepare_pooled`]
+pub const PREPARE_CONCURRENCY: usize = 2;
 
 /// Errors that can occur during package installation
 #[derive(Debug)]
@@ -72,24 +96,206 @@ impl From<crate::package::MetaParseError> for InstallError {
 /// 5. Creates symbolic links for package files
 /// 6. Updates package database
 pub async fn install(pkg_path: &Path, db: &PackageDB, direct: bool) -> Result<(), UhpmError> {
+    install_transactional(pkg_path, db, direct, Provenance::from_local_file(pkg_path)).await
+}
+
+/// Installs a package archive and records where it came from
+///
+/// Same as [`install()`], but lets callers such as the fetcher record the
+/// repository and URL the package was downloaded from. If any step fails,
+/// the package directory, the links created so far and the database rows
+/// are rolled back, see [`Transaction`].
+pub async fn install_transactional(
+    pkg_path: &Path,
+    db: &PackageDB,
+    direct: bool,
+    provenance: Provenance,
+) -> Result<(), UhpmError> {
+    commit(prepare(pkg_path)?, db, direct, provenance).await
+}
+
+/// Installs a package archive and records it in any [`PackageStore`]
+///
+/// The install for embedders on another storage backend (see
+/// [`crate::db::storage`]): the archive is verified, placed in the store
+/// directory and linked from its `symlist`, and the version becomes
+/// current. Dependencies, hooks, systemd units, assets and everything
+/// else only [`PackageDB`] records are left out; use [`install()`] for
+/// those.
+pub async fn install_to_store(
+    pkg_path: &Path,
+    store: &dyn PackageStore,
+    direct: bool,
+) -> Result<(), UhpmError> {
+    let prepared = prepare(pkg_path)?;
+    let meta = &prepared.meta;
+    let (name, version) = (meta.name(), meta.version().to_string());
+    if store
+        .get_current_package(name)
+        .await?
+        .is_some_and(|current| current.version() == meta.version())
+    {
+        info!("installer.install.same_version_skipped");
+        discard(prepared);
+        return Ok(());
+    }
+    let direct = direct || platform::must_copy();
+
+    let package_root = get_pkg_path(name, meta.version().clone());
+    let existed = package_root.exists();
+    place_package(&prepared.unpacked, &package_root)?;
+    let claim = Claim::for_store(&packages_dir());
+    let linked = match create_symlinks(&package_root, direct, &claim) {
+        Ok(linked) => linked,
+        Err(e) => {
+            if !existed {
+                let _ = fs::remove_dir_all(&package_root);
+            }
+            return Err(e.into());
+        }
+    };
+
+    let files: Vec<String> = linked
+        .iter()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+    store
+        .add_package(meta, &files, LinkMode::from_direct(direct))
+        .await?;
+    store.set_current_version(name, &version).await?;
+    info!("installer.install.success", name);
+    Ok(())
+}
+
+/// A package archive that was verified and unpacked but not yet installed
+#[derive(Debug)]
+pub struct PreparedPackage {
+    /// Original archive
+    pub archive: PathBuf,
+    /// Temporary directory the archive was unpacked into
+    pub unpacked: PathBuf,
+    /// Parsed `uhp.toml`
+    pub meta: Package,
+}
+
+/// First install stage: unpacks an archive and validates its metadata
+///
+/// Blocking and independent of the database, so several packages can be
+/// prepared concurrently while others are still downloading.
+pub fn prepare(pkg_path: &Path) -> Result<PreparedPackage, UhpmError> {
+    info!("installer.install.starting", pkg_path.display());
+    signing::check(pkg_path)?;
+    unpack_prepared(pkg_path)
+}
+
+/// Like [`prepare`], but for an archive built here by
+/// [`builder::build`](crate::package::builder::build) from a verified
+/// build script
+///
+/// No repository key can have signed such an archive, so the signature
+/// check is skipped even when `require_signatures` is set.
+pub fn prepare_built(pkg_path: &Path) -> Result<PreparedPackage, UhpmError> {
     info!("installer.install.starting", pkg_path.display());
+    debug!("installer.install.built_locally", pkg_path.display());
+    unpack_prepared(pkg_path)
+}
 
+/// Unpacks an archive and reads its metadata
+fn unpack_prepared(pkg_path: &Path) -> Result<PreparedPackage, UhpmError> {
     let unpacked = unpack(pkg_path)?;
     debug!("installer.install.unpacked", unpacked.display());
 
     let meta_path = unpacked.join("uhp.toml");
     debug!("installer.install.reading_meta", meta_path.display());
-    let package_meta: Package = crate::package::meta_parser(&meta_path)?;
+    let meta: Package = crate::package::meta_parser(&meta_path)?;
     info!(
         "installer.install.package_info",
-        package_meta.name(),
-        package_meta.version()
+        meta.name(),
+        meta.version()
     );
 
+    Ok(PreparedPackage {
+        archive: pkg_path.to_path_buf(),
+        unpacked,
+        meta,
+    })
+}
+
+/// Runs [`prepare`] on a blocking thread once `pool` has a free permit
+///
+/// Sharing one pool of [`PREPARE_CONCURRENCY`] permits bounds how many
+/// archives are unpacked at once, however many packages are in flight.
+pub async fn prepare_pooled(
+    archive: PathBuf,
+    pool: &Semaphore,
+) -> Result<PreparedPackage, UhpmError> {
+    let _permit = pool
+        .acquire()
+        .await
+        .map_err(|e| UhpmError::Package(e.to_string()))?;
+    tokio::task::spawn_blocking(platform::in_current_root_sync(move || prepare(&archive)))
+        .await
+        .map_err(|e| UhpmError::Package(e.to_string()))?
+}
+
+/// Removes the unpacked directory of a package that will not be committed
+pub fn discard(prepared: PreparedPackage) {
+    if prepared.unpacked.exists() {
+        let _ = fs::remove_dir_all(&prepared.unpacked);
+    }
+}
+
+/// Second install stage: moves a prepared package into the store, links it
+/// and records it in the database
+///
+/// Runs in a [`Transaction`] that is rolled back if any step fails.
+pub async fn commit(
+    prepared: PreparedPackage,
+    db: &PackageDB,
+    direct: bool,
+    provenance: Provenance,
+) -> Result<(), UhpmError> {
+    let unpacked = prepared.unpacked.clone();
+    let mut tx = Transaction::begin();
+    match commit_in(prepared, db, direct, provenance, &mut tx).await {
+        Ok(()) => {
+            tx.commit();
+            Ok(())
+        }
+        Err(e) => {
+            warn!("installer.install.rolling_back", &e);
+            tx.rollback(db).await;
+            if unpacked.exists() {
+                let _ = fs::remove_dir_all(&unpacked);
+            }
+            Err(e)
+        }
+    }
+}
+
+async fn commit_in(
+    prepared: PreparedPackage,
+    db: &PackageDB,
+    direct: bool,
+    provenance: Provenance,
+    tx: &mut Transaction,
+) -> Result<(), UhpmError> {
+    let PreparedPackage {
+        archive: pkg_path,
+        unpacked,
+        meta: package_meta,
+    } = prepared;
+
     let pkg_name = package_meta.name();
     let version = package_meta.version();
-
-    let already_installed = db.is_installed(pkg_name).await.unwrap();
+    crash::set_package(&format!("{}@{}", pkg_name, version));
+    let direct = direct || platform::must_copy();
+    events::emit(ProgressEvent::InstallStarted {
+        package: pkg_name.to_string(),
+        version: version.to_string(),
+    });
+
+    let already_installed = db.is_installed(pkg_name).await?;
     if let Some(installed_version) = &already_installed {
         info!(
             "installer.install.already_installed",
@@ -100,33 +306,63 @@ pub async fn install(pkg_path: &Path, db: &PackageDB, direct: bool) -> Result<()
             return Ok(());
         }
     }
+    if let Err(e) = requirements::enforce(
+        &package_meta,
+        Config::load_or_default().runtime_requirements,
+    ) {
+        fs::remove_dir_all(&unpacked)?;
+        return Err(e);
+    }
+    if Config::load_or_default().scan_binaries {
+        elf::warn_unloadable(&unpacked, &format!("{}@{}", pkg_name, version));
+    }
+
+    // A new version of an installed package is linked by the version
+    // switch that follows, which makes its own claim
+    let claim = match already_installed {
+        None => match conflicts::claim(&unpacked, &packages_dir(), pkg_name, db).await {
+            Ok(claim) => Some(claim),
+            Err(e) => {
+                fs::remove_dir_all(&unpacked)?;
+                return Err(e);
+            }
+        },
+        Some(_) => None,
+    };
 
-    let package_root = dirs::home_dir()
-        .unwrap()
-        .join(".uhpm/packages")
-        .join(format!("{}-{}", pkg_name, version));
+    let package_root = packages_dir().join(format!("{}-{}", pkg_name, version));
     debug!("installer.install.package_root", package_root.display());
 
+    // The directory of a version that is installed but not current is
+    // replaced; a rollback has to bring it back
     if package_root.exists() {
-        debug!(
-            "installer.install.removing_existing",
-            package_root.display()
-        );
-        fs::remove_dir_all(&package_root)?;
+        tx.guard(std::slice::from_ref(&package_root))?;
+    } else {
+        tx.created_dir(&package_root);
     }
-    fs::create_dir_all(&package_root)?;
-    debug!("installer.install.created_dir", package_root.display());
-
-    fs::rename(&unpacked, &package_root)?;
+    place_package(&unpacked, &package_root)?;
     debug!("installer.install.moved_package", package_root.display());
+    let manifest = verify::manifest(&package_root)?;
+    if let Err(e) = platform::clear_quarantine(&package_root) {
+        warn!("installer.install.quarantine_failed", e);
+    }
 
     let mut installed_files = Vec::new();
-    match already_installed {
-        None => {
+    match &claim {
+        Some(claim) => {
             info!("installer.install.creating_symlinks");
-            installed_files = create_symlinks(&package_root, direct)?;
+            tx.guard(&transaction::link_targets(&package_root, &package_meta))?;
+            installed_files = create_symlinks(&package_root, direct, claim)?;
+            installed_files.extend(systemd::activate(
+                &package_root,
+                &package_meta,
+                direct,
+                claim,
+            )?);
+            installed_files.extend(assets::link(&package_root, &package_meta, direct, claim)?);
+            installed_files.extend(app_bundles::link(&package_root, direct)?);
         }
-        Some(_) => {
+        None => {
             info!("installer.install.updating_version");
         }
     }
@@ -140,13 +376,56 @@ pub async fn install(pkg_path: &Path, db: &PackageDB, direct: bool) -> Result<()
         pkg_name,
         installed_files_str.len()
     );
-    db.add_package_full(&package_meta, &installed_files_str)
-        .await
-        .unwrap();
-    db.set_current_version(&package_meta.name(), &package_meta.version().to_string())
-        .await
-        .unwrap();
+    tx.current_version(db, pkg_name).await?;
+    tx.package_row(pkg_name, &version.to_string());
+    db.add_package_full(
+        &package_meta,
+        &installed_files_str,
+        LinkMode::from_direct(direct),
+    )
+    .await?;
+    db.set_file_hashes(
+        pkg_name,
+        &version.to_string(),
+        &verify::hash_files(&installed_files),
+    )
+    .await?;
+    db.set_current_version(package_meta.name(), &package_meta.version().to_string())
+        .await?;
+    if let Some(claim) = &claim {
+        conflicts::disown(&claim.taken_over, pkg_name, db).await?;
+    }
+    db.set_package_provenance(pkg_name, &version.to_string(), &provenance)
+        .await?;
+    db.set_package_manifest(pkg_name, &version.to_string(), &manifest)
+        .await?;
+    let dep_closure = closure::resolve(&package_meta, db).await?;
+    db.set_dependency_closure(pkg_name, &version.to_string(), &dep_closure)
+        .await?;
+    let doc_paths = docs::discover(&package_root, &package_meta);
+    db.set_package_docs(pkg_name, &version.to_string(), &doc_paths)
+        .await?;
+
+    if Config::load_or_default().cache.keep_archives {
+        if let Err(e) = archive_cache::store(&pkg_path, pkg_name, &version.to_string()) {
+            warn!("installer.install.archive_cache_failed", e);
+        }
+    }
 
+    // The package is in place; a failing script does not undo that
+    if already_installed.is_none() {
+        let hook = PackageHook::PostInstall;
+        if let Err(e) =
+            hooks::run_package_hook(hook, &package_root, pkg_name, &version.to_string()).await
+        {
+            warn!("package.hooks.script_failed", hook.file_name(), pkg_name, e);
+        }
+    }
+
+    events::emit(ProgressEvent::InstallFinished {
+        package: pkg_name.to_string(),
+        version: version.to_string(),
+    });
     info!("installer.install.success", pkg_name);
     Ok(())
 }
@@ -155,6 +434,7 @@ pub async fn install(pkg_path: &Path, db: &PackageDB, direct: bool) -> Result<()
 ///
 /// # Arguments
 /// * `package_root` - Path to the package directory
+/// * `claim` - What the links may replace, from [`conflicts::claim`]
 ///
 /// # Returns
 /// `Result<Vec<PathBuf>, std::io::Error>` - List of created symlink paths or error
@@ -162,61 +442,118 @@ pub async fn install(pkg_path: &Path, db: &PackageDB, direct: bool) -> Result<()
 /// # Process
 /// 1. Loads symlink configuration from `symlist`
 /// 2. Creates parent directories for symlink targets
-/// 3. Removes existing files at target locations
+/// 3. Clears existing files at target locations, see [`Claim::clear`]
 /// 4. Creates symbolic links from package files to target locations
-
-pub fn create_symlinks(package_root: &Path, direct: bool) -> Result<Vec<PathBuf>, std::io::Error> {
-    let mut installed_files = Vec::new();
-
+///
+/// Large symlists are split across threads.
+pub fn create_symlinks(
+    package_root: &Path,
+    direct: bool,
+    claim: &Claim,
+) -> Result<Vec<PathBuf>, std::io::Error> {
     let symlist_path = package_root.join("symlist");
     debug!("installer.symlinks.loading", symlist_path.display());
 
-    match symlist::load_symlist(&symlist_path, &package_root) {
-        Ok(symlinks) => {
-            for (src_rel, dst_abs) in symlinks {
-                let src_abs = package_root.join(&src_rel);
-                debug!(
-                    "installer.symlinks.processing",
-                    src_abs.display(),
-                    dst_abs.display()
-                );
-
-                if !src_abs.exists() {
-                    warn!("installer.symlinks.src_not_found", src_abs.display());
-                    continue;
-                }
-
-                if let Some(parent) = dst_abs.parent() {
-                    fs::create_dir_all(parent)?;
-                    debug!("installer.symlinks.created_parent", parent.display());
-                }
-
-                if dst_abs.exists() {
-                    fs::remove_file(&dst_abs)?;
-                    debug!("installer.symlinks.removed_existing", dst_abs.display());
-                }
-                if direct {
-                    std::fs::copy(&src_abs, &dst_abs)?;
-                } else {
-                    std::os::unix::fs::symlink(&src_abs, &dst_abs)?;
-                }
-                debug!(
-                    "installer.symlinks.created_link",
-                    dst_abs.display(),
-                    src_abs.display()
-                );
-                installed_files.push(dst_abs);
-            }
-        }
+    let symlinks = match symlist::load_symlist(&symlist_path, package_root) {
+        Ok(symlinks) => symlinks,
         Err(e) => {
             warn!("installer.symlinks.load_failed", e);
+            return Ok(Vec::new());
         }
-    }
+    };
+
+    let workers = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    let installed_files: Vec<PathBuf> = if symlinks.len() < PARALLEL_LINK_THRESHOLD || workers == 1
+    {
+        link_entries(package_root, &symlinks, direct, claim)?
+    } else {
+        let chunk_size = symlinks.len().div_ceil(workers);
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = symlinks
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(platform::in_current_root_sync(move || {
+                        link_entries(package_root, chunk, direct, claim)
+                    }))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("symlink worker panicked"))
+                .collect::<Result<Vec<_>, _>>()
+        })?
+        .into_iter()
+        .flatten()
+        .collect()
+    };
 
     debug!("installer.symlinks.total_created", installed_files.len());
     Ok(installed_files)
 }
 
+/// Symlist size from which [`create_symlinks`] links on several threads
+const PARALLEL_LINK_THRESHOLD: usize = 512;
+
+/// Links (or copies, in `direct` mode) a batch of symlist entries
+fn link_entries(
+    package_root: &Path,
+    entries: &[(PathBuf, PathBuf)],
+    direct: bool,
+    claim: &Claim,
+) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut created = Vec::with_capacity(entries.len());
+    for (src_rel, dst_abs) in entries {
+        let src_abs = package_root.join(src_rel);
+        debug!(
+            "installer.symlinks.processing",
+            src_abs.display(),
+            dst_abs.display()
+        );
+
+        if !src_abs.exists() {
+            warn!("installer.symlinks.src_not_found", src_abs.display());
+            continue;
+        }
+
+        if let Some(parent) = dst_abs.parent() {
+            fs::create_dir_all(parent)?;
+            debug!("installer.symlinks.created_parent", parent.display());
+        }
+
+        // `symlink_metadata` also catches dangling links left behind
+        if fs::symlink_metadata(dst_abs).is_ok() {
+            claim.clear(package_root, dst_abs)?;
+            debug!("installer.symlinks.removed_existing", dst_abs.display());
+        }
+        if direct {
+            std::fs::copy(&src_abs, dst_abs)?;
+        } else {
+            platform::symlink(&src_abs, dst_abs)?;
+        }
+        debug!(
+            "installer.symlinks.created_link",
+            dst_abs.display(),
+            src_abs.display()
+        );
+        created.push(dst_abs.clone());
+    }
+    Ok(created)
+}
+
+/// Reads package metadata (`uhp.toml`) straight from a `.uhp` archive
+///
+/// Nothing is extracted to disk; useful to inspect a package before
+/// deciding whether to install it.
+pub fn read_meta(pkg_path: &Path) -> Result<Package, UhpmError> {
+    let data = archive::read_manifest(pkg_path)?.ok_or_else(|| {
+        UhpmError::Validation(format!("uhp.toml not found in {}", pkg_path.display()))
+    })?;
+    toml::from_str(&String::from_utf8_lossy(&data))
+        .map_err(|e| UhpmError::Parse(format!("TOML parse error: {}", e)))
+}
+
 /// Extracts a package archive to a temporary directory
 ///
 /// # Arguments
@@ -228,7 +565,10 @@ pub fn create_symlinks(package_root: &Path, direct: bool) -> Result<Vec<PathBuf>
 /// # Process
 /// 1. Validates file extension (.uhp)
 /// 2. Creates temporary extraction directory
-/// 3. Extracts tar.gz archive contents
+/// 3. Extracts archive contents (gzip, zstd or plain tar), stripping a
+///    single top-level directory that holds `uhp.toml`; entries escaping
+///    the directory, devices and FIFOs, and archives beyond the `extract`
+///    limits of `config.ron` are refused (see [`archive::ExtractLimits`])
 /// 4. Returns path to extracted directory
 pub fn unpack(pkg_path: &Path) -> Result<PathBuf, std::io::Error> {
     if pkg_path.extension().and_then(|s| s.to_str()) != Some("uhp") {
@@ -238,7 +578,7 @@ pub fn unpack(pkg_path: &Path) -> Result<PathBuf, std::io::Error> {
         ));
     }
 
-    let tmp_dir = dirs::home_dir().unwrap().join(".uhpm/tmp");
+    let tmp_dir = tmp_dir();
     fs::create_dir_all(&tmp_dir)?;
 
     let package_name = pkg_path
@@ -258,99 +598,152 @@ pub fn unpack(pkg_path: &Path) -> Result<PathBuf, std::io::Error> {
         unpack_dir.display()
     );
 
-    let tar_gz = fs::File::open(pkg_path)?;
-    let decompressor = flate2::read::GzDecoder::new(tar_gz);
-    let mut archive = tar::Archive::new(decompressor);
-    archive.unpack(&unpack_dir)?;
+    if archive::extract_package(pkg_path, &unpack_dir, &Config::load_or_default().extract)? {
+        debug!("installer.unpack.stripped_root", pkg_path.display());
+    }
 
     debug!("installer.unpack.done", unpack_dir.display());
     Ok(unpack_dir)
 }
 
-pub async fn install_at(
-    pkg_path: &Path,
-    db: &PackageDB,
-    uhpm_root: &Path,
-    direct: bool,
-) -> Result<(), crate::package::installer::InstallError> {
-    info!("installer.install_at.starting", pkg_path.display());
-
-    let unpacked = unpack_at(pkg_path, uhpm_root)?;
-    debug!("installer.install_at.unpacked", unpacked.display());
-
-    let meta_path = unpacked.join("uhp.toml"); // Исправлено: uhp.ron -> uhp.toml
-    debug!("installer.install_at.reading_meta", meta_path.display());
-    let package_meta: Package = crate::package::meta_parser(&meta_path)?;
-    info!(
-        "installer.install_at.package_info",
-        package_meta.name(),
-        package_meta.version()
-    );
-
-    let pkg_name = package_meta.name();
-    let version = package_meta.version();
+/// Directory packages are unpacked into before being moved to the store
+///
+/// Defaults to `~/.uhpm/tmp`, next to the package store, so the move is a
+/// cheap rename. `Config.tmp_dir` relocates it to `<tmp_dir>/uhpm`.
+pub fn tmp_dir() -> PathBuf {
+    match Config::load_or_default().tmp_dir {
+        Some(base) => base.join("uhpm"),
+        None => platform::uhpm_home().join("tmp"),
+    }
+}
 
-    let already_installed = db.is_installed(pkg_name).await.unwrap();
-    if let Some(installed_version) = &already_installed {
-        info!(
-            "installer.install_at.already_installed",
-            pkg_name, installed_version
-        );
-        if installed_version == version {
-            info!("installer.install_at.same_version_skipped");
-            return Ok(());
+/// Moves a directory tree, copying it when `src` and `dst` are on
+/// different filesystems
+///
+/// `dst` may already exist as an empty directory.
+pub fn move_dir(src: &Path, dst: &Path) -> std::io::Result<()> {
+    match fs::rename(src, dst) {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            debug!("installer.move_dir.copying", src.display(), dst.display());
+            copy_tree(src, dst)?;
+            fs::remove_dir_all(src)
         }
+        result => result,
     }
+}
 
-    let package_root = uhpm_root
-        .join("packages")
-        .join(format!("{}-{}", pkg_name, version));
-    debug!("installer.install_at.package_root", package_root.display());
+/// Moves an unpacked package to `package_root` without exposing a
+/// half-populated directory
+///
+/// The package is first moved to a sibling `<name>-<version>.staging-<pid>`
+/// directory and checked for its `uhp.toml`; only then does it replace
+/// `package_root`. A directory already at `package_root` is moved aside to
+/// `.old-<pid>` and deleted after the swap. Leftovers of crashed installs
+/// of the same version are cleaned up first.
+pub fn place_package(unpacked: &Path, package_root: &Path) -> std::io::Result<()> {
+    let parent = package_root.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(parent)?;
+    remove_leftovers(package_root)?;
+
+    let staging = sibling(package_root, STAGING_MARKER, std::process::id());
+    debug!("installer.place.staging", staging.display());
+    move_dir(unpacked, &staging)?;
+
+    if let Err(e) = crate::package::meta_parser(&staging.join("uhp.toml")) {
+        fs::remove_dir_all(&staging)?;
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("staged package failed verification: {}", e),
+        ));
+    }
 
-    if package_root.exists() {
-        debug!(
-            "installer.install_at.removing_existing",
-            package_root.display()
-        );
-        fs::remove_dir_all(&package_root)?;
+    let old = sibling(package_root, OLD_MARKER, std::process::id());
+    let replaced = package_root.exists();
+    if replaced {
+        debug!("installer.place.replacing", package_root.display());
+        fs::rename(package_root, &old)?;
     }
-    fs::create_dir_all(&package_root)?;
-    debug!("installer.install_at.created_dir", package_root.display());
+    fs::rename(&staging, package_root)?;
+    if replaced {
+        fs::remove_dir_all(&old)?;
+    }
+    Ok(())
+}
 
-    fs::rename(&unpacked, &package_root)?;
-    debug!("installer.install_at.moved_package", package_root.display());
+/// Suffix marking a package directory that is still being populated
+const STAGING_MARKER: &str = ".staging-";
+/// Suffix marking a replaced package directory awaiting deletion
+const OLD_MARKER: &str = ".old-";
 
-    let mut installed_files = Vec::new();
-    match already_installed {
-        None => {
-            info!("installer.install_at.creating_symlinks");
-            installed_files = create_symlinks(&package_root, direct)?;
-        }
-        Some(_) => {
-            info!("installer.install_at.updating_version");
+fn sibling(package_root: &Path, marker: &str, pid: u32) -> PathBuf {
+    let mut name = package_root.file_name().unwrap_or_default().to_os_string();
+    name.push(format!("{}{}", marker, pid));
+    package_root.with_file_name(name)
+}
+
+/// Removes staging and replaced directories left by dead processes
+fn remove_leftovers(package_root: &Path) -> std::io::Result<()> {
+    let Some(parent) = package_root.parent() else {
+        return Ok(());
+    };
+    let root_name = package_root
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+
+    for entry in fs::read_dir(parent)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let Some(rest) = name.strip_prefix(root_name.as_ref()) else {
+            continue;
+        };
+        let pid = [STAGING_MARKER, OLD_MARKER]
+            .iter()
+            .find_map(|marker| rest.strip_prefix(marker))
+            .and_then(|pid| pid.parse::<u32>().ok());
+        if let Some(pid) = pid
+            && !platform::process_alive(pid)
+        {
+            warn!("installer.place.removing_leftover", path.display());
+            fs::remove_dir_all(&path)?;
         }
     }
+    Ok(())
+}
 
-    let installed_files_str: Vec<String> = installed_files
-        .iter()
-        .map(|p| p.to_string_lossy().to_string())
-        .collect();
-    info!(
-        "installer.install_at.adding_to_db",
-        pkg_name,
-        installed_files_str.len()
-    );
-    db.add_package_full(&package_meta, &installed_files_str)
-        .await
-        .unwrap();
-    db.set_current_version(&package_meta.name(), &package_meta.version().to_string())
-        .await
-        .unwrap();
-
-    info!("installer.install_at.success", pkg_name);
+/// Recursively copies `src` into `dst`, keeping symlinks and permissions
+pub(crate) fn copy_tree(src: &Path, dst: &Path) -> std::io::Result<()> {
+    for entry in WalkDir::new(src) {
+        let entry = entry?;
+        let target = dst.join(entry.path().strip_prefix(src).unwrap());
+        let file_type = entry.file_type();
+
+        if file_type.is_dir() {
+            fs::create_dir_all(&target)?;
+            fs::set_permissions(&target, entry.metadata()?.permissions())?;
+        } else if file_type.is_symlink() {
+            platform::symlink(&fs::read_link(entry.path())?, &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
     Ok(())
 }
 
+/// Installs a package into the isolated installation at `uhpm_root`
+///
+/// Same as [`install()`], but with `uhpm_root` as the [alternate
+/// root](platform::root) instead of the user's home, so that tests and
+/// embedders can install side by side without touching `$HOME`.
+pub async fn install_at(
+    pkg_path: &Path,
+    db: &PackageDB,
+    uhpm_root: &Path,
+    direct: bool,
+) -> Result<(), UhpmError> {
+    platform::with_root(uhpm_root, install(pkg_path, db, direct)).await
+}
+
 /// Распаковка пакета в указанную директорию UHPM
 pub fn unpack_at(pkg_path: &Path, uhpm_root: &Path) -> Result<PathBuf, std::io::Error> {
     if pkg_path.extension().and_then(|s| s.to_str()) != Some("uhp") {
@@ -380,11 +773,88 @@ pub fn unpack_at(pkg_path: &Path, uhpm_root: &Path) -> Result<PathBuf, std::io::
         unpack_dir.display()
     );
 
-    let tar_gz = fs::File::open(pkg_path)?;
-    let decompressor = GzDecoder::new(tar_gz);
-    let mut archive = Archive::new(decompressor);
-    archive.unpack(&unpack_dir)?;
+    if archive::extract_package(pkg_path, &unpack_dir, &Config::load_or_default().extract)? {
+        debug!("installer.unpack.stripped_root", pkg_path.display());
+    }
 
     debug!("installer.unpack_at.done", unpack_dir.display());
     Ok(unpack_dir)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_copy_tree_keeps_links_and_modes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let src = tmp.path().join("src");
+        fs::create_dir_all(src.join("bin")).unwrap();
+        fs::write(src.join("bin/tool"), "#!/bin/sh").unwrap();
+        fs::set_permissions(src.join("bin/tool"), fs::Permissions::from_mode(0o755)).unwrap();
+        std::os::unix::fs::symlink("bin/tool", src.join("tool")).unwrap();
+
+        let dst = tmp.path().join("dst");
+        fs::create_dir_all(&dst).unwrap();
+        copy_tree(&src, &dst).unwrap();
+
+        let mode = fs::metadata(dst.join("bin/tool"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o755);
+        assert_eq!(
+            fs::read_link(dst.join("tool")).unwrap(),
+            Path::new("bin/tool")
+        );
+    }
+
+    #[test]
+    fn test_place_package_replaces_and_cleans_leftovers() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().join("packages/demo-1.0.0");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("old-file"), "").unwrap();
+
+        // Left behind by a crashed install; no process has this pid
+        let leftover = sibling(&root, STAGING_MARKER, u32::MAX / 2);
+        fs::create_dir_all(&leftover).unwrap();
+
+        let unpacked = tmp.path().join("unpacked");
+        fs::create_dir_all(&unpacked).unwrap();
+        fs::write(
+            unpacked.join("uhp.toml"),
+            crate::package::test_manifest("demo", "1.0.0"),
+        )
+        .unwrap();
+
+        place_package(&unpacked, &root).unwrap();
+
+        assert!(root.join("uhp.toml").exists());
+        assert!(!root.join("old-file").exists());
+        assert!(!leftover.exists());
+        assert_eq!(
+            fs::read_dir(tmp.path().join("packages")).unwrap().count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_place_package_rejects_invalid_staging() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().join("packages/demo-1.0.0");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("uhp.toml"), "kept").unwrap();
+
+        let unpacked = tmp.path().join("unpacked");
+        fs::create_dir_all(&unpacked).unwrap();
+
+        assert!(place_package(&unpacked, &root).is_err());
+        assert_eq!(fs::read_to_string(root.join("uhp.toml")).unwrap(), "kept");
+        assert_eq!(
+            fs::read_dir(tmp.path().join("packages")).unwrap().count(),
+            1
+        );
+    }
+}
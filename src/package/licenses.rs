@@ -0,0 +1,127 @@
+//! # License Listing
+//!
+//! Summarizes the `license` field (an SPDX expression such as
+//! `MIT OR Apache-2.0`) of the current version of every installed package.
+//!
+//! Packages without a license, or with `NOASSERTION`, count as unknown.
+//! A deny list marks expressions that cannot be satisfied without one of
+//! the denied licenses: `MIT OR GPL-3.0` is allowed with `GPL-3.0` denied,
+//! `MIT AND GPL-3.0` is not. A denied identifier also covers its
+//! `-only`, `-or-later` and `+` variants.
+
+use crate::db::PackageDB;
+use crate::error::UhpmError;
+use crate::package::{get_pkg_path, meta_parser};
+use semver::Version;
+
+/// License of an installed package version
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageLicense {
+    pub package: String,
+    pub version: String,
+    /// SPDX expression from `uhp.toml`, if declared
+    pub license: Option<String>,
+}
+
+impl PackageLicense {
+    /// Whether the package declares no usable license
+    pub fn is_unknown(&self) -> bool {
+        self.license
+            .as_deref()
+            .is_none_or(|l| l.trim().is_empty() || l.trim() == "NOASSERTION")
+    }
+}
+
+/// Reads the licenses of the current versions of all installed packages
+pub async fn collect(db: &PackageDB) -> Result<Vec<PackageLicense>, UhpmError> {
+    let mut licenses = Vec::new();
+    for (name, version, current) in db.list_packages().await? {
+        if !current {
+            continue;
+        }
+        let license = Version::parse(&version)
+            .ok()
+            .map(|v| get_pkg_path(&name, v).join("uhp.toml"))
+            .and_then(|meta| meta_parser(&meta).ok())
+            .and_then(|pkg| pkg.license().map(str::to_string));
+        licenses.push(PackageLicense {
+            package: name,
+            version,
+            license,
+        });
+    }
+    licenses.sort_by(|a, b| a.package.cmp(&b.package));
+    Ok(licenses)
+}
+
+/// Whether `id` is `denied` or one of its variants
+fn matches_denied(id: &str, denied: &str) -> bool {
+    let id = id.to_ascii_lowercase();
+    let denied = denied.to_ascii_lowercase();
+    id == denied
+        || id
+            .strip_prefix(&denied)
+            .is_some_and(|rest| matches!(rest, "+" | "-only" | "-or-later"))
+}
+
+/// Whether an SPDX expression requires one of the `deny` licenses
+///
+/// Parentheses are ignored: `OR` binds looser than `AND`, and `WITH`
+/// exceptions are dropped.
+pub fn is_denied(expression: &str, deny: &[String]) -> bool {
+    if deny.is_empty() {
+        return false;
+    }
+    let expression = expression.replace(['(', ')'], " ");
+    let tokens: Vec<&str> = expression.split_whitespace().collect();
+    tokens
+        .split(|t| t.eq_ignore_ascii_case("OR"))
+        .all(|alternative| {
+            let mut ids = alternative.iter().peekable();
+            let mut required = Vec::new();
+            while let Some(token) = ids.next() {
+                if token.eq_ignore_ascii_case("AND") {
+                    continue;
+                }
+                if token.eq_ignore_ascii_case("WITH") {
+                    ids.next();
+                    continue;
+                }
+                required.push(*token);
+            }
+            required
+                .iter()
+                .any(|id| deny.iter().any(|d| matches_denied(id, d)))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_denied() {
+        let deny = vec!["GPL-3.0".to_string()];
+        assert!(is_denied("GPL-3.0", &deny));
+        assert!(is_denied("GPL-3.0-or-later", &deny));
+        assert!(is_denied("MIT AND GPL-3.0-only", &deny));
+        assert!(is_denied("(GPL-3.0+ WITH Classpath-exception-2.0)", &deny));
+        assert!(!is_denied("MIT OR GPL-3.0", &deny));
+        assert!(!is_denied("LGPL-3.0", &deny));
+        assert!(!is_denied("GPL-3.0", &[]));
+    }
+
+    #[test]
+    fn test_unknown_license() {
+        let mut entry = PackageLicense {
+            package: "x".to_string(),
+            version: "1.0.0".to_string(),
+            license: None,
+        };
+        assert!(entry.is_unknown());
+        entry.license = Some("NOASSERTION".to_string());
+        assert!(entry.is_unknown());
+        entry.license = Some("MIT".to_string());
+        assert!(!entry.is_unknown());
+    }
+}
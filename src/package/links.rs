@@ -0,0 +1,306 @@
+//! # Link Checker
+//!
+//! Finds dangling symlinks that point into the package store
+//! (`~/.uhpm/packages`), e.g. after a package directory was removed by hand
+//! or an interrupted switch.
+//!
+//! Scanned locations:
+//! - every file recorded in the `installed_files` table;
+//! - `$XDG_BIN_HOME`, the configured bin directory,
+//!   `$XDG_DATA_HOME/applications` and the systemd user unit directory,
+//!   recursively.
+//!
+//! Links pointing anywhere else are never touched, and a link is only
+//! pointed at the current version if that file resolves into the store.
+//!
+//! [`relink`] (`uhpm relink`) instead rebuilds all links of a package from
+//! its `symlist`, e.g. after `~/.local/bin` was cleaned out or the home
+//! directory moved.
+
+use crate::db::PackageDB;
+use crate::error::UhpmError;
+use crate::package::bin_layout::{self, BinLayout};
+use crate::package::installer::create_symlinks;
+use crate::package::which::package_of;
+use crate::package::{LinkMode, app_bundles, assets, conflicts, packages_dir, systemd, verify};
+use crate::symlist::expand_vars;
+use crate::{debug, info, platform, warn};
+use semver::Version;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A symlink whose target inside the package store no longer exists
+#[derive(Debug, Clone, PartialEq)]
+pub struct DanglingLink {
+    /// Location of the symlink
+    pub link: PathBuf,
+    /// Missing target the symlink points to
+    pub target: PathBuf,
+    /// Same file in the current version of the package, if it exists
+    pub replacement: Option<PathBuf>,
+}
+
+/// What to do with dangling links
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkAction {
+    /// Only report them
+    Report,
+    /// Delete them
+    Delete,
+    /// Point them at the current version where possible, delete the rest
+    Relink,
+}
+
+/// Directories scanned in addition to recorded files
+pub fn scan_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![
+        bin_layout::shared_dir(),
+        expand_vars("$XDG_DATA_HOME/applications"),
+        systemd::user_unit_dir(),
+    ];
+    let bin_dir = BinLayout::configured().dir();
+    if !dirs.contains(&bin_dir) {
+        dirs.push(bin_dir);
+    }
+    dirs
+}
+
+fn collect_links(dir: &Path, out: &mut BTreeSet<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        match entry.file_type() {
+            Ok(ft) if ft.is_symlink() => {
+                out.insert(path);
+            }
+            Ok(ft) if ft.is_dir() => collect_links(&path, out),
+            _ => {}
+        }
+    }
+}
+
+/// Maps a target in an old package version onto the given version
+fn relocate(target: &Path, store: &Path, version: &Version) -> Option<PathBuf> {
+    let (name, _) = package_of(target, store)?;
+    let rel: PathBuf = target
+        .strip_prefix(store)
+        .ok()?
+        .components()
+        .skip(1)
+        .collect();
+    Some(store.join(format!("{}-{}", name, version)).join(rel))
+}
+
+/// Whether `path` resolves to a file inside the package store
+///
+/// A package may ship symlinks of its own, which must not carry a relinked
+/// link out of the store.
+fn resolves_into(path: &Path, store: &Path) -> bool {
+    match (fs::canonicalize(path), fs::canonicalize(store)) {
+        (Ok(real), Ok(store)) => real.starts_with(store),
+        _ => false,
+    }
+}
+
+/// Scans for dangling symlinks pointing into the package store
+pub async fn find_dangling(db: &PackageDB) -> Result<Vec<DanglingLink>, UhpmError> {
+    let store = packages_dir();
+
+    let mut candidates: BTreeSet<PathBuf> = db
+        .list_all_installed_files()
+        .await?
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
+    for dir in scan_dirs() {
+        collect_links(&dir, &mut candidates);
+    }
+
+    let mut dangling = Vec::new();
+    for link in candidates {
+        let Ok(target) = fs::read_link(&link) else {
+            continue;
+        };
+        if !target.starts_with(&store) || target.exists() {
+            continue;
+        }
+        debug!("package.links.dangling", link.display(), target.display());
+
+        let mut replacement = None;
+        if let Some((name, _)) = package_of(&target, &store)
+            && let Some(current) = db.get_package_version(&name).await?
+            && let Ok(current) = Version::parse(&current)
+        {
+            replacement = relocate(&target, &store, &current).filter(|p| resolves_into(p, &store));
+        }
+
+        dangling.push(DanglingLink {
+            link,
+            target,
+            replacement,
+        });
+    }
+    Ok(dangling)
+}
+
+/// Applies `action` to the given dangling links
+///
+/// Links that no longer point where they did when they were found are
+/// left alone. Returns the number of links that were changed.
+pub fn fix(links: &[DanglingLink], action: LinkAction) -> std::io::Result<usize> {
+    if action == LinkAction::Report {
+        return Ok(0);
+    }
+
+    let mut changed = 0;
+    for l in links {
+        if fs::read_link(&l.link).ok().as_ref() != Some(&l.target) {
+            warn!(
+                "package.links.changed",
+                l.link.display(),
+                l.target.display()
+            );
+            continue;
+        }
+        fs::remove_file(&l.link)?;
+        match (&l.replacement, action) {
+            (Some(new_target), LinkAction::Relink) => {
+                crate::platform::symlink(new_target, &l.link)?;
+                info!(
+                    "package.links.relinked",
+                    l.link.display(),
+                    new_target.display()
+                );
+            }
+            _ => info!("package.links.deleted", l.link.display()),
+        }
+        changed += 1;
+    }
+    Ok(changed)
+}
+
+/// What [`relink`] did for a package
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relinked {
+    /// Links removed and created
+    Links { removed: usize, created: usize },
+    /// The package was installed as copies (`--direct`), which are left
+    /// alone; `uhpm verify --repair` restores missing ones
+    Copies,
+}
+
+/// Removes the links of the current version of `pkg_name` and creates them
+/// again from its `symlist`, systemd units, assets and app bundles
+///
+/// The destinations are checked as on install (see
+/// [`conflicts::claim`]). Recorded symlinks into the package directory, or
+/// pointing nowhere, are removed first; other files in the way are
+/// handled by `replace_policy`. Units are only linked again: systemd is
+/// neither reloaded nor asked to enable or start them.
+pub async fn relink(db: &PackageDB, pkg_name: &str) -> Result<Relinked, UhpmError> {
+    let version = db
+        .get_package_version(pkg_name)
+        .await?
+        .ok_or_else(|| UhpmError::NotFound(pkg_name.to_string()))?;
+    let copies = db
+        .get_link_mode(pkg_name, &version)
+        .await?
+        .is_some_and(LinkMode::is_copy);
+    if copies || platform::must_copy() {
+        return Ok(Relinked::Copies);
+    }
+    let root = packages_dir().join(format!("{}-{}", pkg_name, version));
+    if !root.exists() {
+        return Err(UhpmError::NotFound(root.display().to_string()));
+    }
+    let claim = conflicts::claim(&root, &packages_dir(), pkg_name, db).await?;
+
+    let mut removed = 0;
+    for (file, _) in db.get_installed_files_with_mode(pkg_name, &version).await? {
+        let link = PathBuf::from(file);
+        let Ok(target) = fs::read_link(&link) else {
+            continue;
+        };
+        if target.starts_with(&root) || !link.exists() {
+            fs::remove_file(&link)?;
+            debug!("package.links.removed", link.display());
+            removed += 1;
+        }
+    }
+
+    let mut linked = create_symlinks(&root, false, &claim)?;
+    if let Some(meta) = systemd::installed_meta(&root) {
+        linked.extend(systemd::link_units(&root, &meta, false, &claim)?);
+        linked.extend(assets::link(&root, &meta, false, &claim)?);
+    }
+    linked.extend(app_bundles::link(&root, false)?);
+    let hashes = verify::hash_files(&linked);
+    let files: Vec<String> = linked
+        .iter()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+    db.set_installed_files(pkg_name, &version, &files, LinkMode::Symlink)
+        .await?;
+    db.set_file_hashes(pkg_name, &version, &hashes).await?;
+    conflicts::disown(&claim.taken_over, pkg_name, db).await?;
+    info!(
+        "package.links.relinked_package",
+        pkg_name,
+        &version,
+        linked.len()
+    );
+    Ok(Relinked::Links {
+        removed,
+        created: linked.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relocate() {
+        let store = Path::new("/home/u/.uhpm/packages");
+        let target = store.join("tool-1.0.0/bin/tool");
+        let relocated = relocate(&target, store, &Version::parse("1.1.0").unwrap()).unwrap();
+        assert_eq!(relocated, store.join("tool-1.1.0/bin/tool"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fix_checks_link_targets() {
+        let tmp = tempfile::tempdir().unwrap();
+        let store = tmp.path().join("packages");
+        let bin = store.join("tool-1.1.0/bin");
+        fs::create_dir_all(&bin).unwrap();
+        fs::write(bin.join("tool"), "").unwrap();
+        std::os::unix::fs::symlink("/etc/hostname", bin.join("escape")).unwrap();
+        assert!(resolves_into(&bin.join("tool"), &store));
+        assert!(!resolves_into(&bin.join("escape"), &store));
+
+        let old = store.join("tool-1.0.0/bin/tool");
+        let link = tmp.path().join("tool");
+        std::os::unix::fs::symlink(&old, &link).unwrap();
+        let dangling = DanglingLink {
+            link: link.clone(),
+            target: old,
+            replacement: Some(bin.join("tool")),
+        };
+
+        // Pointed elsewhere since it was found: left alone
+        fs::remove_file(&link).unwrap();
+        std::os::unix::fs::symlink("/elsewhere", &link).unwrap();
+        let found = std::slice::from_ref(&dangling);
+        assert_eq!(fix(found, LinkAction::Relink).unwrap(), 0);
+        assert_eq!(fs::read_link(&link).unwrap(), Path::new("/elsewhere"));
+
+        fs::remove_file(&link).unwrap();
+        std::os::unix::fs::symlink(&dangling.target, &link).unwrap();
+        assert_eq!(fix(&[dangling], LinkAction::Relink).unwrap(), 1);
+        assert_eq!(fs::read_link(&link).unwrap(), bin.join("tool"));
+    }
+}
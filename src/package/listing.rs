@@ -0,0 +1,163 @@
+//! # Package Listing
+//!
+//! What `uhpm list` shows about each installed package version: whether
+//! it is current, when it was installed, its size on disk, the repository
+//! it came from and whether it was requested or pulled in as a dependency.
+//! [`ListFilter`] narrows the list down by name patterns (the wildcards
+//! of [`pattern`]) and to explicitly installed packages; `--outdated` is
+//! applied by the service, which knows the repositories. Sizes are only
+//! measured when asked for, walking the store is slow for large packages.
+
+use crate::db::PackageDB;
+use crate::error::UhpmError;
+use crate::package::{InstallReason, get_pkg_path, pattern, tree};
+use semver::Version;
+use serde::Serialize;
+
+/// An installed package version
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct InstalledPackage {
+    pub name: String,
+    pub version: String,
+    pub current: bool,
+    /// RFC 3339 time stamp, if recorded
+    pub installed_at: Option<String>,
+    /// Size of the store directory, `None` if it is missing
+    pub size: Option<u64>,
+    /// Repository it was installed from, `None` for local files
+    pub repo: Option<String>,
+    /// `explicit` or `dependency`
+    pub reason: String,
+    /// Newest version in the repositories, only looked up for `--outdated`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latest: Option<String>,
+}
+
+/// Which installed versions to list
+#[derive(Debug, Clone, Default)]
+pub struct ListFilter {
+    /// Name patterns, any of which must match; every name when empty
+    pub patterns: Vec<String>,
+    /// Only packages installed on request
+    pub explicit: bool,
+    /// Only current versions with a newer version in the repositories
+    pub outdated: bool,
+    /// Measure the store directories, which walks every installed file
+    pub sizes: bool,
+}
+
+impl ListFilter {
+    /// Whether the package `name` passes the name patterns
+    pub fn matches_name(&self, name: &str) -> bool {
+        self.patterns.is_empty() || self.patterns.iter().any(|p| pattern::matches(p, name))
+    }
+
+    /// Whether `package` passes the filter, `--outdated` aside
+    pub fn matches(&self, package: &InstalledPackage) -> bool {
+        self.matches_name(&package.name)
+            && (!self.explicit || package.reason == InstallReason::Explicit.as_str())
+    }
+}
+
+/// Every installed version, sorted by name and newest version first
+///
+/// The store directories are only measured with `sizes`, `size` is `None`
+/// otherwise.
+pub async fn collect(db: &PackageDB, sizes: bool) -> Result<Vec<InstalledPackage>, UhpmError> {
+    let mut packages = Vec::new();
+    for record in db.list_package_records().await? {
+        let provenance = db
+            .get_package_provenance(&record.name, &record.version)
+            .await?;
+        let size = Version::parse(&record.version)
+            .ok()
+            .filter(|_| sizes)
+            .and_then(|v| tree::dir_size(&get_pkg_path(&record.name, v)));
+        packages.push(InstalledPackage {
+            repo: provenance
+                .as_ref()
+                .and_then(|p| p.repo())
+                .map(str::to_string),
+            size,
+            installed_at: record.installed_at,
+            reason: record.reason,
+            current: record.current,
+            name: record.name,
+            version: record.version,
+            latest: None,
+        });
+    }
+    packages.sort_by(|a, b| {
+        a.name.cmp(&b.name).then_with(|| {
+            Version::parse(&b.version)
+                .ok()
+                .cmp(&Version::parse(&a.version).ok())
+        })
+    });
+    Ok(packages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::{LinkMode, Package, Source};
+
+    #[tokio::test]
+    async fn test_collect_and_filter() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = PackageDB::new(&tmp.path().join("packages.db"))
+            .unwrap()
+            .init()
+            .await
+            .unwrap();
+        for (name, version) in [("tool", "1.2.0"), ("tool", "1.10.0"), ("libfoo", "1.0.0")] {
+            let pkg = Package::new(
+                name,
+                Version::parse(version).unwrap(),
+                "a",
+                Source::Raw(String::new()),
+                "",
+                vec![],
+            );
+            db.add_package_full(&pkg, &[], LinkMode::Symlink)
+                .await
+                .unwrap();
+        }
+        db.set_current_version("tool", "1.10.0").await.unwrap();
+        db.set_install_reason("libfoo", InstallReason::Dependency)
+            .await
+            .unwrap();
+
+        let packages = collect(&db, true).await.unwrap();
+        let listed: Vec<(&str, &str, bool)> = packages
+            .iter()
+            .map(|p| (p.name.as_str(), p.version.as_str(), p.current))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                ("libfoo", "1.0.0", false),
+                ("tool", "1.10.0", true),
+                ("tool", "1.2.0", false),
+            ]
+        );
+        assert_eq!(packages[0].reason, "dependency");
+        assert_eq!(packages[0].size, None);
+
+        let filter = ListFilter {
+            patterns: vec!["lib*".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(packages.iter().filter(|p| filter.matches(p)).count(), 1);
+        let filter = ListFilter {
+            explicit: true,
+            ..Default::default()
+        };
+        assert!(
+            packages
+                .iter()
+                .filter(|p| filter.matches(p))
+                .all(|p| p.name == "tool")
+        );
+    }
+}
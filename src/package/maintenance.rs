@@ -0,0 +1,270 @@
+//! # Maintenance Runs
+//!
+//! `uhpm maintain` bundles the housekeeping an installation needs now and
+//! then into one unattended run, meant for a cron job or systemd timer:
+//!
+//! | Step      | What it does                                                   |
+//! |-----------|----------------------------------------------------------------|
+//! | `cache`   | deletes stale repository caches and expired `uhpm run` copies  |
+//! | `refresh` | downloads the repository databases that changed               |
+//! | `gc`      | removes versions unused for `gc_unused_days`                   |
+//! | `vacuum`  | compacts the package database                                  |
+//! | `links`   | looks for dangling links into the package store (reports only) |
+//! | `updates` | looks for newer versions of installed packages (reports only)  |
+//!
+//! Steps run in this order and independently: a failing step is recorded
+//! and the next one still runs. The [`MaintenanceReport`] serializes to the
+//! JSON printed by `uhpm maintain --json`.
+
+use serde::Serialize;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// A step of a maintenance run
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Step {
+    Cache,
+    Refresh,
+    Gc,
+    Vacuum,
+    Links,
+    Updates,
+}
+
+impl Step {
+    /// Every step, in the order they run
+    pub const ALL: [Step; 6] = [
+        Step::Cache,
+        Step::Refresh,
+        Step::Gc,
+        Step::Vacuum,
+        Step::Links,
+        Step::Updates,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Step::Cache => "cache",
+            Step::Refresh => "refresh",
+            Step::Gc => "gc",
+            Step::Vacuum => "vacuum",
+            Step::Links => "links",
+            Step::Updates => "updates",
+        }
+    }
+}
+
+impl FromStr for Step {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Step::ALL
+            .into_iter()
+            .find(|step| step.as_str() == s)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Step::ALL.iter().map(|step| step.as_str()).collect();
+                format!("unknown step `{}`, expected one of {}", s, names.join(", "))
+            })
+    }
+}
+
+/// How a step ended
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Failed,
+    Skipped,
+}
+
+/// Something a step could not do, e.g. a repository that failed to refresh
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Failure {
+    pub name: String,
+    pub error: String,
+}
+
+/// A newer version of an installed package
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct AvailableUpdate {
+    pub name: String,
+    pub installed: String,
+    pub latest: String,
+    pub repo: String,
+}
+
+/// What a step did
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Details {
+    Cache {
+        /// Repositories whose stale caches were removed
+        repo_caches_removed: Vec<String>,
+        bytes_freed: u64,
+        /// Expired packages fetched by `uhpm run` that were removed
+        run_cache_purged: usize,
+    },
+    Refresh {
+        updated: Vec<String>,
+        unchanged: Vec<String>,
+        failed: Vec<Failure>,
+    },
+    Gc {
+        /// Removed versions as `name@version`
+        removed: Vec<String>,
+        bytes_freed: u64,
+        failed: Vec<Failure>,
+    },
+    Vacuum {
+        size_before: u64,
+        size_after: u64,
+    },
+    Links {
+        dangling: Vec<PathBuf>,
+    },
+    Updates {
+        available: Vec<AvailableUpdate>,
+    },
+}
+
+impl Details {
+    fn failures(&self) -> &[Failure] {
+        match self {
+            Details::Refresh { failed, .. } | Details::Gc { failed, .. } => failed,
+            _ => &[],
+        }
+    }
+}
+
+/// Result of one step
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct StepReport {
+    pub step: Step,
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(flatten)]
+    pub details: Option<Details>,
+}
+
+/// Result of a maintenance run
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MaintenanceReport {
+    /// RFC 3339 time stamps
+    pub started_at: String,
+    pub finished_at: String,
+    pub steps: Vec<StepReport>,
+}
+
+impl MaintenanceReport {
+    /// Starts a report now
+    pub fn start() -> Self {
+        Self {
+            started_at: chrono::Local::now().to_rfc3339(),
+            finished_at: String::new(),
+            steps: Vec::new(),
+        }
+    }
+
+    /// Records the result of `step`; a step with failures in its details
+    /// counts as failed
+    pub fn record(&mut self, step: Step, result: Result<Details, String>) {
+        let report = match result {
+            Ok(details) => StepReport {
+                step,
+                status: if details.failures().is_empty() {
+                    Status::Ok
+                } else {
+                    Status::Failed
+                },
+                error: None,
+                details: Some(details),
+            },
+            Err(error) => StepReport {
+                step,
+                status: Status::Failed,
+                error: Some(error),
+                details: None,
+            },
+        };
+        self.steps.push(report);
+    }
+
+    /// Records that `step` was skipped
+    pub fn skip(&mut self, step: Step) {
+        self.steps.push(StepReport {
+            step,
+            status: Status::Skipped,
+            error: None,
+            details: None,
+        });
+    }
+
+    /// Stamps the end of the run
+    pub fn finish(mut self) -> Self {
+        self.finished_at = chrono::Local::now().to_rfc3339();
+        self
+    }
+
+    /// Steps that failed
+    pub fn failed(&self) -> impl Iterator<Item = &StepReport> {
+        self.steps.iter().filter(|s| s.status == Status::Failed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_json() {
+        assert_eq!("gc".parse::<Step>(), Ok(Step::Gc));
+        assert!("tidy".parse::<Step>().is_err());
+
+        let mut report = MaintenanceReport::start();
+        report.record(
+            Step::Vacuum,
+            Ok(Details::Vacuum {
+                size_before: 4096,
+                size_after: 2048,
+            }),
+        );
+        report.record(
+            Step::Refresh,
+            Ok(Details::Refresh {
+                updated: vec!["main".to_string()],
+                unchanged: Vec::new(),
+                failed: vec![Failure {
+                    name: "extra".to_string(),
+                    error: "timed out".to_string(),
+                }],
+            }),
+        );
+        report.record(Step::Links, Err("no database".to_string()));
+        report.skip(Step::Updates);
+        let report = report.finish();
+        assert_eq!(report.failed().count(), 2);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(
+            json["steps"][0],
+            serde_json::json!({
+                "step": "vacuum",
+                "status": "ok",
+                "size_before": 4096,
+                "size_after": 2048,
+            })
+        );
+        assert_eq!(json["steps"][1]["status"], "failed");
+        assert_eq!(json["steps"][1]["failed"][0]["name"], "extra");
+        assert_eq!(
+            json["steps"][2],
+            serde_json::json!({"step": "links", "status": "failed", "error": "no database"})
+        );
+        assert_eq!(
+            json["steps"][3],
+            serde_json::json!({"step": "updates", "status": "skipped"})
+        );
+    }
+}
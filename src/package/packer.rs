@@ -0,0 +1,275 @@
+//! # Package Authoring
+//!
+//! Library side of the `uhpm pkg` subcommands (and of the standalone
+//! `uhpmk` tool):
+//!
+//! - [`init()`] creates a package skeleton (`uhp.toml`, `symlist`, `bin/`);
+//! - [`lint()`] checks a package directory for common mistakes;
+//! - [`pack()`] packs a directory into a `.uhp` archive with the given
+//!   [`ArchiveOptions`] (compression and level, excluded paths,
+//!   deterministic timestamps), refusing when [`lint()`] reports errors.
+
+use crate::archive::{self, ArchiveOptions};
+use crate::error::UhpmError;
+use crate::package::{Package, Source, meta_parser, requirements};
+use crate::{info, lformat, symlist};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Severity of a lint finding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintLevel {
+    Warning,
+    Error,
+}
+
+/// A single problem found by [`lint()`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintIssue {
+    pub level: LintLevel,
+    pub message: String,
+}
+
+impl LintIssue {
+    fn error(message: String) -> Self {
+        Self {
+            level: LintLevel::Error,
+            message,
+        }
+    }
+
+    fn warning(message: String) -> Self {
+        Self {
+            level: LintLevel::Warning,
+            message,
+        }
+    }
+}
+
+/// Creates a package skeleton in `dir`
+///
+/// The package is named after the directory. Existing `uhp.toml` files
+/// are never overwritten.
+pub fn init(dir: &Path) -> Result<PathBuf, UhpmError> {
+    let meta_path = dir.join("uhp.toml");
+    if meta_path.exists() {
+        return Err(UhpmError::Validation(format!(
+            "{} already exists",
+            meta_path.display()
+        )));
+    }
+
+    fs::create_dir_all(dir.join("bin"))?;
+
+    let mut pkg = Package::template();
+    if let Some(name) = fs::canonicalize(dir)
+        .ok()
+        .and_then(|d| d.file_name().map(|n| n.to_string_lossy().to_string()))
+    {
+        pkg.name = name;
+    }
+    pkg.save_to_toml(&meta_path)
+        .map_err(|e| UhpmError::Package(e.to_string()))?;
+
+    let symlist_path = dir.join("symlist");
+    if !symlist_path.exists() {
+        fs::write(
+            &symlist_path,
+            format!(
+                "# <path in package> <target>\n# bin/{0} $XDG_BIN_HOME/{0}\n",
+                pkg.name
+            ),
+        )?;
+    }
+
+    info!("package.packer.initialized", pkg.name(), dir.display());
+    Ok(meta_path)
+}
+
+/// Checks a package directory for problems
+///
+/// Errors make [`pack()`] fail; warnings are informational.
+pub fn lint(dir: &Path) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+
+    let meta_path = dir.join("uhp.toml");
+    let pkg = match meta_parser(&meta_path) {
+        Ok(pkg) => pkg,
+        Err(e) => {
+            issues.push(LintIssue::error(lformat!(
+                "package.packer.lint.invalid_meta",
+                meta_path.display(),
+                e.to_string()
+            )));
+            return issues;
+        }
+    };
+
+    if pkg.name().is_empty() || pkg.name().contains(['/', ' ', '@']) {
+        issues.push(LintIssue::error(lformat!(
+            "package.packer.lint.invalid_name",
+            pkg.name()
+        )));
+    }
+    if pkg.checksum() == "TODO" {
+        issues.push(LintIssue::warning(lformat!(
+            "package.packer.lint.placeholder",
+            "checksum"
+        )));
+    }
+    if matches!(pkg.src(), Source::Raw(s) if s == "TODO") {
+        issues.push(LintIssue::warning(lformat!(
+            "package.packer.lint.placeholder",
+            "src"
+        )));
+    }
+
+    for (name, spec) in pkg.requires() {
+        if !requirements::is_valid_name(name) || requirements::parse_spec(spec).is_err() {
+            issues.push(LintIssue::error(lformat!(
+                "package.packer.lint.invalid_requirement",
+                name,
+                spec
+            )));
+        }
+    }
+
+    if pkg.license().is_none() {
+        issues.push(LintIssue::warning(lformat!(
+            "package.packer.lint.no_license"
+        )));
+    }
+
+    let symlist_path = dir.join("symlist");
+    if symlist_path.exists() {
+        match symlist::load_symlist(&symlist_path, dir) {
+            Ok(entries) => {
+                for (src, _) in entries {
+                    if !src.exists() {
+                        issues.push(LintIssue::error(lformat!(
+                            "package.packer.lint.missing_file",
+                            "symlist",
+                            src.display()
+                        )));
+                    }
+                }
+            }
+            Err(e) => issues.push(LintIssue::error(lformat!(
+                "package.packer.lint.invalid_symlist",
+                e.to_string()
+            ))),
+        }
+    } else {
+        issues.push(LintIssue::warning(lformat!(
+            "package.packer.lint.no_symlist"
+        )));
+    }
+
+    let declared = pkg
+        .systemd_units()
+        .iter()
+        .map(|u| ("systemd", u.unit.as_str()))
+        .chain(
+            pkg.assets()
+                .fonts
+                .iter()
+                .map(|f| ("assets.fonts", f.as_str())),
+        )
+        .chain(
+            pkg.assets()
+                .icons
+                .iter()
+                .map(|i| ("assets.icons", i.file.as_str())),
+        )
+        .chain(
+            pkg.assets()
+                .mime
+                .iter()
+                .map(|m| ("assets.mime", m.as_str())),
+        )
+        .chain(pkg.bin().map(|b| ("bin", b)));
+    for (field, rel) in declared {
+        if !dir.join(rel).exists() {
+            issues.push(LintIssue::error(lformat!(
+                "package.packer.lint.missing_file",
+                field,
+                rel
+            )));
+        }
+    }
+
+    issues
+}
+
+/// Packs `dir` into a `.uhp` archive
+///
+/// Without `output` the archive is written to `<name>-<version>.uhp` in
+/// the current directory. Returns the path of the written archive.
+pub fn pack(
+    dir: &Path,
+    output: Option<&Path>,
+    opts: &ArchiveOptions,
+) -> Result<PathBuf, UhpmError> {
+    if let Some(issue) = lint(dir).into_iter().find(|i| i.level == LintLevel::Error) {
+        return Err(UhpmError::Validation(issue.message));
+    }
+
+    let pkg = meta_parser(&dir.join("uhp.toml"))?;
+    let output = output
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from(format!("{}-{}.uhp", pkg.name(), pkg.version())));
+
+    // Build outside `dir` first: `output` is often inside it when packing
+    // from within the package directory
+    let staging = std::env::temp_dir().join(format!("uhpm-pack-{}.uhp", std::process::id()));
+    archive::create(dir, &staging, opts)?;
+    if archive::read_manifest(&staging)?.is_none() {
+        fs::remove_file(&staging)?;
+        return Err(UhpmError::Validation(
+            "uhp.toml is excluded from the archive".to_string(),
+        ));
+    }
+    fs::copy(&staging, &output)?;
+    fs::remove_file(&staging)?;
+
+    info!("package.packer.packed", pkg.name(), output.display());
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_lint_pack() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("hello");
+        init(&dir).unwrap();
+        assert!(init(&dir).is_err());
+
+        let issues = lint(&dir);
+        assert!(issues.iter().all(|i| i.level == LintLevel::Warning));
+
+        let archive = pack(
+            &dir,
+            Some(&tmp.path().join("hello.uhp")),
+            &ArchiveOptions::new(),
+        )
+        .unwrap();
+        let meta = crate::package::installer::read_meta(&archive).unwrap();
+        assert_eq!(meta.name(), "hello");
+
+        fs::write(dir.join("notes.txt"), "draft").unwrap();
+        let opts = ArchiveOptions::new().exclude("*.txt").deterministic(true);
+        let a = pack(&dir, Some(&tmp.path().join("a.uhp")), &opts).unwrap();
+        let b = pack(&dir, Some(&tmp.path().join("b.uhp")), &opts).unwrap();
+        assert_eq!(fs::read(&a).unwrap(), fs::read(&b).unwrap());
+        assert!(
+            !archive::list(&a)
+                .unwrap()
+                .contains(&PathBuf::from("notes.txt"))
+        );
+        let opts = ArchiveOptions::new().exclude("*.toml");
+        assert!(pack(&dir, Some(&tmp.path().join("c.uhp")), &opts).is_err());
+    }
+}
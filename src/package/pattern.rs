@@ -0,0 +1,95 @@
+//! # Package Name Patterns
+//!
+//! Shell-style wildcards for selecting installed packages by name, e.g.
+//! `uhpm remove 'node-*'`. Patterns are matched by uhpm itself against
+//! package names, never expanded against the filesystem.
+//!
+//! | Syntax  | Matches                               |
+//! |---------|---------------------------------------|
+//! | `*`     | any run of characters, including none |
+//! | `?`     | exactly one character                 |
+//! | `[abc]` | one of the listed characters          |
+//! | `[a-z]` | one character in the range            |
+//! | `[!ab]` | one character not listed              |
+
+/// Returns `true` if `s` contains wildcard characters
+pub fn is_pattern(s: &str) -> bool {
+    s.contains(['*', '?', '['])
+}
+
+/// Returns `true` if `name` matches the wildcard `pattern`
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    match_from(&pattern, &name)
+}
+
+fn match_from(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|skip| match_from(&pattern[1..], &name[skip..])),
+        Some('?') => !name.is_empty() && match_from(&pattern[1..], &name[1..]),
+        Some('[') => match (class_end(pattern), name.first()) {
+            (Some(end), Some(&c)) => {
+                class_matches(&pattern[1..end], c) && match_from(&pattern[end + 1..], &name[1..])
+            }
+            (Some(_), None) => false,
+            // An unterminated `[` is a literal character
+            (None, _) => name.first() == Some(&'[') && match_from(&pattern[1..], &name[1..]),
+        },
+        Some(&p) => name.first() == Some(&p) && match_from(&pattern[1..], &name[1..]),
+    }
+}
+
+/// Index of the `]` closing the class that starts at `pattern[0]`
+fn class_end(pattern: &[char]) -> Option<usize> {
+    // A `]` right after `[` or `[!` is part of the class
+    let start = if pattern.get(1) == Some(&'!') { 3 } else { 2 };
+    (start..pattern.len()).find(|&i| pattern[i] == ']')
+}
+
+fn class_matches(class: &[char], c: char) -> bool {
+    let (negated, class) = match class.split_first() {
+        Some(('!', rest)) => (true, rest),
+        _ => (false, class),
+    };
+
+    let mut found = false;
+    let mut i = 0;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == '-' {
+            found |= (class[i]..=class[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            found |= class[i] == c;
+            i += 1;
+        }
+    }
+    found != negated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcards() {
+        assert!(matches("node-*", "node-18"));
+        assert!(matches("node-*", "node-"));
+        assert!(!matches("node-*", "nodejs"));
+        assert!(matches("py?hon", "python"));
+        assert!(matches("*grep*", "ripgrep-all"));
+        assert!(!matches("vim", "neovim"));
+    }
+
+    #[test]
+    fn test_classes() {
+        assert!(matches("lib[ab]", "liba"));
+        assert!(!matches("lib[ab]", "libc"));
+        assert!(matches("v[0-9]", "v7"));
+        assert!(matches("v[!0-9]", "vx"));
+        assert!(matches("a[", "a["));
+        assert!(is_pattern("node-*"));
+        assert!(!is_pattern("node"));
+    }
+}
@@ -0,0 +1,202 @@
+//! # Profile Link Swapping
+//!
+//! Every [profile](crate::config::Profiles) has its own database and
+//! package store, but all of them link into the same home directory. Only
+//! the active profile has its links in place; `uhpm profile use` takes the
+//! links of the old profile out with [`swap_out`] and puts those of the new
+//! one in with [`swap_in`].
+//!
+//! Taking links out removes every file the database records as installed
+//! by a package version, as long as it was still placed by that version
+//! (see [`remover`]). A copy the user modified is moved aside to
+//! `<file>.uhpm-save`, as when switching versions. Putting links in creates
+//! them from the symlists again: all links of the current versions,
+//! including systemd units, assets and app bundles, and the versioned links
+//! of the other installed versions. Their destinations are checked as on
+//! install (see [`conflicts::claim`]) before anything is replaced.
+
+use crate::db::PackageDB;
+use crate::error::UhpmError;
+use crate::package::installer::create_symlinks;
+use crate::package::{LinkMode, app_bundles, assets, conflicts, remover, systemd, verify};
+use crate::{info, platform, symlist, warn};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Targets of the versioned links in the symlist of the package at `root`
+fn versioned_links(root: &Path) -> Vec<symlist::Link> {
+    symlist::load_links(&root.join("symlist"), root)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|link| link.versioned)
+        .collect()
+}
+
+/// Removes the links of the packages in `db`, whose store is `store`;
+/// returns the number of files removed
+pub async fn swap_out(db: &PackageDB, store: &Path) -> Result<usize, UhpmError> {
+    let mut removed = 0;
+    for (name, version, current) in db.list_packages().await? {
+        let root = store.join(format!("{}-{}", name, version));
+        let direct = db
+            .get_link_mode(&name, &version)
+            .await?
+            .is_some_and(LinkMode::is_copy);
+        // Of other versions only the versioned links are in place; their
+        // other recorded files belong to the current version by now
+        let versioned: Option<HashSet<PathBuf>> = (!current).then(|| {
+            versioned_links(&root)
+                .into_iter()
+                .map(|link| link.target)
+                .collect()
+        });
+        if current && let Some(meta) = systemd::installed_meta(&root) {
+            systemd::deactivate(&root, &meta, direct)?;
+            assets::unlink(&root, &meta, direct)?;
+        }
+        let files = db.get_installed_files_with_mode(&name, &version).await?;
+        if current {
+            app_bundles::unlink(&root, &files)?;
+        }
+
+        let hashes = db.get_file_hashes(&name, &version).await?;
+        for (file, mode) in files {
+            let path = PathBuf::from(&file);
+            if versioned.as_ref().is_some_and(|v| !v.contains(&path))
+                || fs::symlink_metadata(&path).is_err()
+                || !remover::placed_by_package(&path, mode, &root)
+            {
+                continue;
+            }
+            let modified = mode == Some(LinkMode::Copy)
+                && !remover::removing_modified_allowed()
+                && hashes
+                    .get(&file)
+                    .is_some_and(|hash| verify::is_modified(&path, hash));
+            if modified {
+                let saved = remover::save_path(&path);
+                fs::rename(&path, &saved)?;
+                warn!(
+                    "package.profile.saved_modified_copy",
+                    path.display(),
+                    saved.display()
+                );
+            } else if path.is_dir() && !path.is_symlink() {
+                fs::remove_dir_all(&path)?;
+            } else {
+                fs::remove_file(&path)?;
+            }
+            removed += 1;
+        }
+        info!("package.profile.swapped_out", &name, &version);
+    }
+    Ok(removed)
+}
+
+/// Creates the links of the packages in `db`, whose store is `store`, and
+/// records them; returns the number of files linked
+///
+/// Fails without replacing anything of a package whose links would take
+/// files of other packages or, under the `error` replace policy, of the
+/// user.
+pub async fn swap_in(db: &PackageDB, store: &Path) -> Result<usize, UhpmError> {
+    let mut linked_total = 0;
+    for (name, version, current) in db.list_packages().await? {
+        let root = store.join(format!("{}-{}", name, version));
+        if !root.is_dir() {
+            warn!("package.profile.missing_dir", root.display());
+            continue;
+        }
+        let mode = db.get_link_mode(&name, &version).await?;
+        let direct = mode.is_some_and(LinkMode::is_copy) || platform::must_copy();
+        let claim = conflicts::claim(&root, store, &name, db).await?;
+
+        if !current {
+            for link in versioned_links(&root) {
+                if let Some(parent) = link.target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                claim.clear(&root, &link.target)?;
+                if direct {
+                    fs::copy(&link.source, &link.target)?;
+                } else {
+                    platform::symlink(&link.source, &link.target)?;
+                }
+                linked_total += 1;
+            }
+            continue;
+        }
+
+        let mut linked = create_symlinks(&root, direct, &claim)?;
+        if let Some(meta) = systemd::installed_meta(&root) {
+            linked.extend(systemd::activate(&root, &meta, direct, &claim)?);
+            linked.extend(assets::link(&root, &meta, direct, &claim)?);
+        }
+        linked.extend(app_bundles::link(&root, direct)?);
+        linked_total += linked.len();
+        let hashes = verify::hash_files(&linked);
+        let linked: Vec<String> = linked
+            .iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .collect();
+        db.set_installed_files(&name, &version, &linked, LinkMode::from_direct(direct))
+            .await?;
+        db.set_file_hashes(&name, &version, &hashes).await?;
+        conflicts::disown(&claim.taken_over, &name, db).await?;
+        info!("package.profile.swapped_in", &name, &version);
+    }
+    Ok(linked_total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_swap_links_out_and_in() {
+        let tmp = tempfile::tempdir().unwrap();
+        let store = tmp.path().join("packages");
+        let bin = tmp.path().join("bin");
+        let root = store.join("tool-1.0.0");
+        fs::create_dir_all(root.join("bin")).unwrap();
+        fs::write(root.join("bin/tool"), "v1").unwrap();
+        fs::write(
+            root.join("symlist"),
+            format!("bin/tool {}/tool\n", bin.display()),
+        )
+        .unwrap();
+
+        let db = PackageDB::new(&tmp.path().join("packages.db"))
+            .unwrap()
+            .init()
+            .await
+            .unwrap();
+        let pkg = crate::package::Package::new(
+            "tool",
+            semver::Version::new(1, 0, 0),
+            "a",
+            crate::package::Source::Raw(String::new()),
+            "",
+            Vec::new(),
+        );
+        db.add_package_full(&pkg, &[], LinkMode::Symlink)
+            .await
+            .unwrap();
+        db.set_current_version("tool", "1.0.0").await.unwrap();
+
+        assert_eq!(swap_in(&db, &store).await.unwrap(), 1);
+        assert_eq!(
+            fs::read_link(bin.join("tool")).unwrap(),
+            root.join("bin/tool")
+        );
+
+        // A file the user put in place of the link is left alone
+        assert_eq!(swap_out(&db, &store).await.unwrap(), 1);
+        assert!(fs::symlink_metadata(bin.join("tool")).is_err());
+        fs::write(bin.join("tool"), "mine").unwrap();
+        assert_eq!(swap_out(&db, &store).await.unwrap(), 0);
+        assert_eq!(fs::read_to_string(bin.join("tool")).unwrap(), "mine");
+    }
+}
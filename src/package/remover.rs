@@ -10,20 +10,60 @@
 //!
 //! ## Removal Process
 //!
-//! 1. **Database Check**: Verifies if package exists in database
-//! 2. **Directory Removal**: Deletes package installation directory
-//! 3. **File Cleanup**: Removes all installed files and symlinks
+//! 1. **Database Check**: Verifies if package exists in database, then
+//!    runs the package's `hooks/pre-remove` script (see
+//!    [`hooks`](crate::package::hooks)); a failing script aborts the removal
+//! 2. **Directory Removal**: Stops declared systemd user units and deletes
+//!    the package installation directory
+//! 3. **File Cleanup**: Removes all installed files and symlinks. Each file
+//!    is checked against the link mode it was installed with, so a user file
+//!    that replaced a package symlink (or a symlink that replaced a copy) is
+//!    left alone. So is a copy whose content no longer matches the SHA-256
+//!    recorded at install time, unless [`allow_removing_modified`] was
+//!    called (`uhpm remove --force`)
 //! 4. **Database Update**: Removes package record from database
 //!
+//! Every removal returns a [`RemovalSummary`] with the number of removed
+//! files and symlinks and the bytes reclaimed; it is also emitted as
+//! [`ProgressEvent::RemovalSummary`].
+//!
 //! ## Error Handling
 //!
 //! Errors are categorized into I/O errors and database errors,
 //! both wrapped in the [`DeleteError`] enumeration.
 
 use crate::db::PackageDB;
+use crate::db::storage::PackageStore;
 use crate::error::UhpmError;
-use crate::package::switcher;
-use crate::{error, info, warn};
+use crate::events::{self, ProgressEvent};
+use crate::package::hooks::{self, PackageHook};
+use crate::package::{
+    LinkMode, app_bundles, assets, get_pkg_path, packages_dir, switcher, systemd, verify,
+};
+use crate::{crash, error, info, platform, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static REMOVE_MODIFIED: AtomicBool = AtomicBool::new(false);
+
+/// Lets removals and version switches delete installed copies the user
+/// modified
+pub fn allow_removing_modified() {
+    REMOVE_MODIFIED.store(true, Ordering::Relaxed);
+}
+
+/// Whether installed copies the user modified may be deleted
+pub fn removing_modified_allowed() -> bool {
+    REMOVE_MODIFIED.load(Ordering::Relaxed)
+}
+
+/// Where a modified copy is moved instead of being replaced
+pub fn save_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".uhpm-save");
+    path.with_file_name(name)
+}
 
 /// Errors that can occur during package removal
 #[derive(Debug)]
@@ -46,6 +86,73 @@ impl From<sqlx::Error> for DeleteError {
     }
 }
 
+/// What a removal deleted and how much disk space it freed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RemovalSummary {
+    /// Regular files removed (package directory and installed copies)
+    pub files: u64,
+    /// Symlinks removed
+    pub symlinks: u64,
+    /// Bytes reclaimed
+    pub bytes: u64,
+}
+
+impl RemovalSummary {
+    /// Adds the counts of another summary to this one
+    pub fn merge(&mut self, other: RemovalSummary) {
+        self.files += other.files;
+        self.symlinks += other.symlinks;
+        self.bytes += other.bytes;
+    }
+
+    /// Accounts for `path` (recursively for directories) before it is removed
+    fn record(&mut self, path: &Path) {
+        let Ok(meta) = fs::symlink_metadata(path) else {
+            return;
+        };
+        if meta.file_type().is_symlink() {
+            self.symlinks += 1;
+        } else if meta.is_dir() {
+            if let Ok(entries) = fs::read_dir(path) {
+                for entry in entries.flatten() {
+                    self.record(&entry.path());
+                }
+            }
+        } else {
+            self.files += 1;
+            self.bytes += meta.len();
+        }
+    }
+}
+
+/// Formats a byte count with binary units, e.g. `1.5 MiB`
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Whether `path` is still what the package placed there
+///
+/// Files recorded before link modes were tracked are always treated as
+/// the package's own.
+pub(crate) fn placed_by_package(path: &Path, mode: Option<LinkMode>, pkg_dir: &Path) -> bool {
+    match mode {
+        Some(LinkMode::Symlink) => fs::read_link(path).is_ok_and(|t| t.starts_with(pkg_dir)),
+        Some(LinkMode::Copy) => !path.is_symlink(),
+        None => true,
+    }
+}
+
 /// Removes an installed package and all its associated files
 ///
 /// # Arguments
@@ -53,7 +160,7 @@ impl From<sqlx::Error> for DeleteError {
 /// * `db` - Reference to the package database
 ///
 /// # Returns
-/// `Result<(), DeleteError>` - Success or error result
+/// [`RemovalSummary`] of what was deleted
 ///
 /// # Process
 /// 1. Checks if package exists in database
@@ -65,16 +172,75 @@ impl From<sqlx::Error> for DeleteError {
 /// - If package directory doesn't exist, removal continues with file cleanup
 /// - Non-existent files are skipped during cleanup
 /// - Database record is always removed if package exists in database
-pub async fn remove(pkg_name: &str, db: &PackageDB, direct: bool) -> Result<(), UhpmError> {
+pub async fn remove(
+    pkg_name: &str,
+    db: &PackageDB,
+    direct: bool,
+) -> Result<RemovalSummary, UhpmError> {
     let version = db.get_package_version(pkg_name).await?;
     if version.is_none() {
         warn!("uhpm.remove.pkg_not_found_db", pkg_name);
-        return Ok(());
+        return Ok(RemovalSummary::default());
     }
     let version = version.unwrap();
 
-    remove_by_version(pkg_name, &version, db, direct).await?;
-    Ok(())
+    remove_by_version(pkg_name, &version, db, direct).await
+}
+
+/// Removes the current version of a package recorded in any
+/// [`PackageStore`]
+///
+/// Counterpart of [`install_to_store`]: the links it placed and its store
+/// directory are deleted, then the records of every version of the
+/// package.
+///
+/// [`install_to_store`]: crate::package::installer::install_to_store
+pub async fn remove_from_store(
+    pkg_name: &str,
+    store: &dyn PackageStore,
+) -> Result<RemovalSummary, UhpmError> {
+    let mut summary = RemovalSummary::default();
+    let Some(current) = store.get_current_package(pkg_name).await? else {
+        warn!("uhpm.remove.pkg_not_found_db", pkg_name);
+        return Ok(summary);
+    };
+    let version = current.version().to_string();
+    let pkg_dir = get_pkg_path(pkg_name, current.version().clone());
+    let mode = store.get_link_mode(pkg_name).await?;
+
+    for f in store.get_installed_files(pkg_name, &version).await? {
+        let path = PathBuf::from(&f);
+        if fs::symlink_metadata(&path).is_err() {
+            continue;
+        }
+        if !placed_by_package(&path, mode, &pkg_dir) {
+            warn!("uhpm.remove.file_replaced", path.display());
+            continue;
+        }
+        summary.record(&path);
+        fs::remove_file(&path)?;
+        info!("uhpm.remove.file_removed", path.display());
+    }
+    if pkg_dir.exists() {
+        summary.record(&pkg_dir);
+        fs::remove_dir_all(&pkg_dir)?;
+        info!("uhpm.remove.pkg_dir_removed", pkg_dir.display());
+    }
+    store.remove_package(pkg_name).await?;
+    info!("uhpm.remove.pkg_entry_removed", pkg_name);
+    Ok(summary)
+}
+
+/// Removes a package from the isolated installation at `uhpm_root`
+///
+/// Same as [`remove()`], with `uhpm_root` in place of the user's home.
+pub async fn remove_at(
+    pkg_name: &str,
+    db: &PackageDB,
+    uhpm_root: &Path,
+    direct: bool,
+) -> Result<RemovalSummary, UhpmError> {
+    platform::with_root(uhpm_root, remove(pkg_name, db, direct)).await
 }
 
 pub async fn remove_by_version(
@@ -82,25 +248,58 @@ pub async fn remove_by_version(
     version: &str,
     db: &PackageDB,
     direct: bool,
-) -> Result<(), UhpmError> {
+) -> Result<RemovalSummary, UhpmError> {
     info!("uhpm.remove.attempting_remove", pkg_name, &version);
+    crash::set_package(&format!("{}@{}", pkg_name, version));
+    let mut summary = RemovalSummary::default();
+    events::emit(ProgressEvent::RemoveStarted {
+        package: pkg_name.to_string(),
+        version: version.to_string(),
+    });
 
-    let mut pkg_dir = dirs::home_dir().unwrap();
-    pkg_dir.push(".uhpm/packages");
-    pkg_dir.push(format!("{}-{}", pkg_name, version));
+    let pkg_dir = packages_dir().join(format!("{}-{}", pkg_name, version));
 
+    // The mode the version was installed with wins over the caller's flag
+    let direct = db
+        .get_link_mode(pkg_name, version)
+        .await?
+        .map_or(direct, LinkMode::is_copy);
+
+    let files = db.get_installed_files_with_mode(pkg_name, version).await?;
     if pkg_dir.exists() {
+        hooks::run_package_hook(PackageHook::PreRemove, &pkg_dir, pkg_name, version)
+            .await
+            .map_err(|e| UhpmError::Package(e.to_string()))?;
+        if let Some(meta) = systemd::installed_meta(&pkg_dir) {
+            systemd::deactivate(&pkg_dir, &meta, direct)?;
+            assets::unlink(&pkg_dir, &meta, direct)?;
+        }
+        app_bundles::unlink(&pkg_dir, &files)?;
+        summary.record(&pkg_dir);
         std::fs::remove_dir_all(&pkg_dir)?;
         info!("uhpm.remove.pkg_dir_removed", pkg_dir.display());
     } else {
         warn!("uhpm.remove.pkg_dir_not_found", pkg_name, pkg_dir.display());
     }
 
-    let files: Vec<String> = db.get_installed_files(pkg_name, version).await?;
-    for f in files {
-        let path = std::path::PathBuf::from(f);
-        if path.exists() {
-            if path.is_dir() {
+    let hashes = db.get_file_hashes(pkg_name, version).await?;
+    for (f, mode) in files {
+        let path = PathBuf::from(&f);
+        if fs::symlink_metadata(&path).is_ok() {
+            if !placed_by_package(&path, mode, &pkg_dir) {
+                warn!("uhpm.remove.file_replaced", path.display());
+                continue;
+            }
+            let modified = mode == Some(LinkMode::Copy)
+                && hashes
+                    .get(&f)
+                    .is_some_and(|hash| verify::is_modified(&path, hash));
+            if modified && !removing_modified_allowed() {
+                warn!("uhpm.remove.file_modified", path.display());
+                continue;
+            }
+            summary.record(&path);
+            if path.is_dir() && !path.is_symlink() {
                 std::fs::remove_dir_all(&path)?;
             } else {
                 std::fs::remove_file(&path)?;
@@ -111,10 +310,13 @@ pub async fn remove_by_version(
 
     db.remove_package(pkg_name).await?;
     let lastpkg = db.get_latest_package_version(pkg_name).await?;
-    if lastpkg.is_some() {
-        match switcher::switch_version(pkg_name, lastpkg.unwrap().version().to_owned(), db, direct)
-            .await
-        {
+    if let Some(lastpkg) = lastpkg {
+        let last_version = lastpkg.version().to_owned();
+        let direct = db
+            .get_link_mode(pkg_name, &last_version.to_string())
+            .await?
+            .map_or(direct, LinkMode::is_copy);
+        match switcher::switch_version(pkg_name, last_version, db, direct).await {
             Ok(_) => {
                 info!("remover.remove_by_version.succes_switch_after_remove");
             }
@@ -124,6 +326,76 @@ pub async fn remove_by_version(
         }
     }
     info!("uhpm.remove.pkg_entry_removed", pkg_name);
+    events::emit(ProgressEvent::RemoveFinished {
+        package: pkg_name.to_string(),
+        version: version.to_string(),
+    });
+    events::emit(ProgressEvent::RemovalSummary {
+        package: pkg_name.to_string(),
+        version: version.to_string(),
+        files: summary.files,
+        symlinks: summary.symlinks,
+        bytes: summary.bytes,
+    });
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_human_size() {
+        assert_eq!(human_size(512), "512 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(3 * 1024 * 1024), "3.0 MiB");
+    }
+
+    #[test]
+    fn test_placed_by_package() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pkg_dir = tmp.path().join("pkg-1.0.0");
+        fs::create_dir(&pkg_dir).unwrap();
+        fs::write(pkg_dir.join("tool"), b"").unwrap();
+
+        let link = tmp.path().join("link");
+        std::os::unix::fs::symlink(pkg_dir.join("tool"), &link).unwrap();
+        let user_file = tmp.path().join("user");
+        fs::write(&user_file, b"mine").unwrap();
+
+        assert!(placed_by_package(&link, Some(LinkMode::Symlink), &pkg_dir));
+        assert!(!placed_by_package(
+            &user_file,
+            Some(LinkMode::Symlink),
+            &pkg_dir
+        ));
+        assert!(placed_by_package(
+            &user_file,
+            Some(LinkMode::Copy),
+            &pkg_dir
+        ));
+        assert!(!placed_by_package(&link, Some(LinkMode::Copy), &pkg_dir));
+        assert!(placed_by_package(&user_file, None, &pkg_dir));
+    }
+
+    #[test]
+    fn test_summary_counts_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(tmp.path().join("a"), b"hello").unwrap();
+        fs::create_dir(tmp.path().join("sub")).unwrap();
+        fs::write(tmp.path().join("sub/b"), b"abc").unwrap();
+        std::os::unix::fs::symlink("a", tmp.path().join("link")).unwrap();
 
-    Ok(())
+        let mut summary = RemovalSummary::default();
+        summary.record(tmp.path());
+        assert_eq!(
+            summary,
+            RemovalSummary {
+                files: 2,
+                symlinks: 1,
+                bytes: 8,
+            }
+        );
+    }
 }
@@ -0,0 +1,307 @@
+//! # Runtime Requirements
+//!
+//! A package declares the programs it needs on the machine in `uhp.toml`:
+//!
+//! ```toml
+//! [requires]
+//! python3 = ">=3.10"
+//! glibc = ">=2.31"
+//! git = "*"
+//! ```
+//!
+//! Versions are [`VersionSpec`]s, so a bare version is exact; most
+//! requirements want `>=`. Before a package is placed, every requirement
+//! is looked up on this machine: `glibc` through `getconf
+//! GNU_LIBC_VERSION` (or `ldd --version`), any other name as a command on
+//! `PATH`. Only when a version is required is the command run, with
+//! `--version` and for at most five seconds; the first `x.y` or `x.y.z` in
+//! its output is its version. A command whose version cannot be read
+//! satisfies any requirement, as uhpm cannot tell otherwise.
+//!
+//! `runtime_requirements` in `config.ron` decides what happens to unmet
+//! requirements: `warn` (the default) reports them and installs anyway,
+//! `fail` refuses the package before anything of it or, for repository
+//! installs, of its dependencies is placed, `ignore` skips the check.
+
+use crate::error::UhpmError;
+use crate::package::which::find_on_path;
+use crate::package::{Package, VersionSpec};
+use crate::{debug, warn};
+use once_cell::sync::Lazy;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fmt;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long a program may take to print its version
+const VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What to do about unmet runtime requirements
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RequirementPolicy {
+    #[default]
+    Warn,
+    Fail,
+    Ignore,
+}
+
+/// What was found for a required program
+#[derive(Debug, Clone, PartialEq)]
+pub enum Found {
+    Missing,
+    /// Present, but its version was not needed or could not be read
+    Unknown,
+    Version(Version),
+}
+
+/// Why a requirement is not met
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reason {
+    Missing,
+    /// The version found does not match
+    Mismatch(Version),
+    /// The name or version in `uhp.toml` is invalid
+    Invalid,
+}
+
+/// A runtime requirement this machine does not meet
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unmet {
+    pub name: String,
+    /// As declared in `uhp.toml`
+    pub required: String,
+    pub reason: Reason,
+}
+
+impl fmt::Display for Unmet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.required)?;
+        match &self.reason {
+            Reason::Missing => write!(f, " (not found)"),
+            Reason::Mismatch(version) => write!(f, " (found {})", version),
+            Reason::Invalid => write!(f, " (invalid requirement)"),
+        }
+    }
+}
+
+/// Whether `name` can be looked up: a plain command name, never a path
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(['/', '\\']) && !name.starts_with('-')
+}
+
+/// Parses the version of a requirement, `*` or nothing meaning any
+pub fn parse_spec(spec: &str) -> Result<Option<VersionSpec>, semver::Error> {
+    match spec.trim() {
+        "" | "*" => Ok(None),
+        spec => spec.parse().map(Some),
+    }
+}
+
+/// The first `x.y` or `x.y.z` in `text`, missing parts as zero
+pub fn find_version(text: &str) -> Option<Version> {
+    let bytes = text.as_bytes();
+    let mut start = 0;
+    while start < bytes.len() {
+        if !bytes[start].is_ascii_digit() || (start > 0 && bytes[start - 1].is_ascii_digit()) {
+            start += 1;
+            continue;
+        }
+        let mut parts = Vec::new();
+        let mut pos = start;
+        while parts.len() < 3 {
+            let end = pos
+                + bytes[pos..]
+                    .iter()
+                    .take_while(|b| b.is_ascii_digit())
+                    .count();
+            if end == pos {
+                break;
+            }
+            parts.push(text[pos..end].parse::<u64>().ok()?);
+            pos = end;
+            if bytes.get(pos) != Some(&b'.') || !bytes.get(pos + 1).is_some_and(u8::is_ascii_digit)
+            {
+                break;
+            }
+            pos += 1;
+        }
+        if parts.len() >= 2 {
+            return Some(Version::new(
+                parts[0],
+                parts[1],
+                parts.get(2).copied().unwrap_or(0),
+            ));
+        }
+        start = pos.max(start + 1);
+    }
+    None
+}
+
+/// Combined output of `program args`, `None` if it cannot be started or
+/// does not finish within [`VERSION_TIMEOUT`]
+fn output(program: impl AsRef<OsStr>, args: &[&str]) -> Option<String> {
+    let program = program.as_ref();
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+    let deadline = Instant::now() + VERSION_TIMEOUT;
+    while child.try_wait().ok()?.is_none() {
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            debug!("package.requirements.timed_out", program.to_string_lossy());
+            return None;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    let out = child.wait_with_output().ok()?;
+    let mut text = String::from_utf8_lossy(&out.stdout).to_string();
+    text.push_str(&String::from_utf8_lossy(&out.stderr));
+    Some(text)
+}
+
+fn lookup(name: &str, versioned: bool) -> Found {
+    if name == "glibc" {
+        let text = output("getconf", &["GNU_LIBC_VERSION"])
+            .filter(|text| find_version(text).is_some())
+            .or_else(|| output("ldd", &["--version"]));
+        return match text {
+            None => Found::Missing,
+            Some(text) => find_version(&text).map_or(Found::Unknown, Found::Version),
+        };
+    }
+    let Some(program) = find_on_path(name).into_iter().next() else {
+        return Found::Missing;
+    };
+    if !versioned {
+        return Found::Unknown;
+    }
+    output(&program, &["--version"])
+        .and_then(|text| find_version(&text))
+        .map_or(Found::Unknown, Found::Version)
+}
+
+static DETECTED: Lazy<Mutex<HashMap<(String, bool), Found>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Looks `name` up on this machine, once per process; its version is only
+/// read if `versioned` is set
+pub fn detect(name: &str, versioned: bool) -> Found {
+    let key = (name.to_string(), versioned);
+    if let Some(found) = DETECTED.lock().unwrap().get(&key) {
+        return found.clone();
+    }
+    let found = lookup(name, versioned);
+    debug!("package.requirements.detected", name, &found);
+    DETECTED.lock().unwrap().insert(key, found.clone());
+    found
+}
+
+/// Requirements of `pkg` this machine does not meet; an invalid
+/// requirement is never met
+pub fn unmet(pkg: &Package) -> Vec<Unmet> {
+    let mut unmet = Vec::new();
+    for (name, required) in pkg.requires() {
+        let unmet_with = |reason| Unmet {
+            name: name.clone(),
+            required: required.clone(),
+            reason,
+        };
+        let spec = match parse_spec(required) {
+            Ok(spec) if is_valid_name(name) => spec,
+            _ => {
+                unmet.push(unmet_with(Reason::Invalid));
+                continue;
+            }
+        };
+        match detect(name, spec.is_some()) {
+            Found::Missing => unmet.push(unmet_with(Reason::Missing)),
+            Found::Version(version) if spec.as_ref().is_some_and(|s| !s.matches(&version)) => {
+                unmet.push(unmet_with(Reason::Mismatch(version)))
+            }
+            _ => {}
+        }
+    }
+    unmet
+}
+
+/// Error for packages with unmet requirements, by `name@version`
+pub fn error(unmet: &[(String, Vec<Unmet>)]) -> UhpmError {
+    let lines: Vec<String> = unmet
+        .iter()
+        .map(|(pkg, reqs)| {
+            let reqs: Vec<String> = reqs.iter().map(Unmet::to_string).collect();
+            format!("{} needs {}", pkg, reqs.join(", "))
+        })
+        .collect();
+    UhpmError::Validation(format!(
+        "runtime requirements not met: {}",
+        lines.join("; ")
+    ))
+}
+
+/// Checks the requirements of `pkg` as `policy` says
+pub fn enforce(pkg: &Package, policy: RequirementPolicy) -> Result<(), UhpmError> {
+    if policy == RequirementPolicy::Ignore {
+        return Ok(());
+    }
+    let unmet = unmet(pkg);
+    if unmet.is_empty() {
+        return Ok(());
+    }
+    let id = format!("{}@{}", pkg.name(), pkg.version());
+    if policy == RequirementPolicy::Fail {
+        return Err(error(&[(id, unmet)]));
+    }
+    for req in &unmet {
+        warn!("package.requirements.unmet", &id, req.to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_version() {
+        let v = |text: &str| find_version(text).map(|v| v.to_string());
+        assert_eq!(v("Python 3.10.12").as_deref(), Some("3.10.12"));
+        assert_eq!(v("glibc 2.31").as_deref(), Some("2.31.0"));
+        assert_eq!(
+            v("ldd (GNU libc) 2.35\nCopyright").as_deref(),
+            Some("2.35.0")
+        );
+        assert_eq!(v("tool v12 build 4.2.1.7").as_deref(), Some("4.2.1"));
+        assert_eq!(v("version 7"), None);
+    }
+
+    #[test]
+    fn test_unmet_requirements() {
+        let pkg: Package = toml::from_str(&format!(
+            "{}\n[requires]\nsh = \"*\"\nuhpm-surely-missing = \">=1\"\n\"../x\" = \"*\"\nbroken = \">=x\"\n",
+            crate::package::test_manifest("tool", "1.0.0")
+        ))
+        .unwrap();
+        let unmet = unmet(&pkg);
+        let names: Vec<&str> = unmet.iter().map(|u| u.name.as_str()).collect();
+        assert_eq!(names, ["../x", "broken", "uhpm-surely-missing"]);
+        assert_eq!(unmet[2].to_string(), "uhpm-surely-missing >=1 (not found)");
+
+        assert!(enforce(&pkg, RequirementPolicy::Ignore).is_ok());
+        assert!(enforce(&pkg, RequirementPolicy::Warn).is_ok());
+        assert!(matches!(
+            enforce(&pkg, RequirementPolicy::Fail),
+            Err(UhpmError::Validation(_))
+        ));
+    }
+}
@@ -0,0 +1,119 @@
+//! # Old Version Retention
+//!
+//! Installing or updating a package keeps its older versions, so `uhpm
+//! switch` can go back to them. `uhpm gc` removes the ones nobody uses
+//! anymore.
+//!
+//! A version counts as used when it was installed, was the current version
+//! (recorded when switching away from it), or was started by `uhpm run`.
+//! Where the file system records access times, the last access of its
+//! `bin/` executables counts as well, which catches use through the bin
+//! links.
+//!
+//! A version is removed once it has not been used for `gc_unused_days`
+//! (30 by default, `--older-than` on the command line). The current
+//! version and the most recently used other one, the rollback target, are
+//! always kept, as is a version with no usage information at all.
+
+use crate::db::PackageDB;
+use crate::error::UhpmError;
+use crate::package::get_pkg_path;
+use chrono::{DateTime, FixedOffset, Local};
+use semver::Version;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// An installed package version and when it was last used
+#[derive(Debug, Clone, PartialEq)]
+pub struct VersionUse {
+    pub name: String,
+    pub version: Version,
+    pub current: bool,
+    pub last_used: Option<DateTime<FixedOffset>>,
+}
+
+/// Latest access time of the executables in `root/bin`
+fn last_access(root: &Path) -> Option<DateTime<FixedOffset>> {
+    fs::read_dir(root.join("bin"))
+        .ok()?
+        .filter_map(|entry| entry.ok()?.metadata().ok()?.accessed().ok())
+        .max()
+        .map(|time| DateTime::<Local>::from(time).fixed_offset())
+}
+
+/// Versions of `versions` to remove when keeping those used since `cutoff`
+///
+/// Per package the current version and the most recently used other one
+/// are kept.
+pub fn stale(versions: &[VersionUse], cutoff: DateTime<FixedOffset>) -> Vec<&VersionUse> {
+    let mut by_name: BTreeMap<&str, Vec<&VersionUse>> = BTreeMap::new();
+    for version in versions {
+        by_name.entry(&version.name).or_default().push(version);
+    }
+
+    let mut stale = Vec::new();
+    for mut versions in by_name.into_values() {
+        versions.retain(|v| !v.current);
+        versions.sort_by(|a, b| (a.last_used, &a.version).cmp(&(b.last_used, &b.version)));
+        versions.pop();
+        stale.extend(
+            versions
+                .into_iter()
+                .filter(|v| v.last_used.is_some_and(|used| used < cutoff)),
+        );
+    }
+    stale.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+    stale
+}
+
+/// Installed versions with their last use, including bin access times
+pub async fn collect(db: &PackageDB) -> Result<Vec<VersionUse>, UhpmError> {
+    let mut versions = db.list_version_use().await?;
+    for version in &mut versions {
+        let root = get_pkg_path(&version.name, version.version.clone());
+        version.last_used = version.last_used.max(last_access(&root));
+    }
+    Ok(versions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn used(name: &str, version: &str, current: bool, day: Option<u32>) -> VersionUse {
+        VersionUse {
+            name: name.to_string(),
+            version: Version::parse(version).unwrap(),
+            current,
+            last_used: day.map(|d| {
+                DateTime::parse_from_rfc3339(&format!("2026-01-{:02}T00:00:00+00:00", d)).unwrap()
+            }),
+        }
+    }
+
+    #[test]
+    fn test_stale_keeps_current_and_rollback_target() {
+        let versions = [
+            used("tool", "1.0.0", false, Some(1)),
+            used("tool", "1.1.0", false, Some(5)),
+            used("tool", "1.2.0", false, Some(3)),
+            used("tool", "2.0.0", true, Some(2)),
+            used("lib", "0.1.0", false, None),
+            used("lib", "0.2.0", false, Some(1)),
+            used("lib", "0.3.0", false, Some(2)),
+            used("lib", "0.4.0", true, Some(1)),
+            used("solo", "1.0.0", true, Some(1)),
+        ];
+        let cutoff = DateTime::parse_from_rfc3339("2026-01-10T00:00:00+00:00").unwrap();
+
+        let stale: Vec<String> = stale(&versions, cutoff)
+            .iter()
+            .map(|v| format!("{}@{}", v.name, v.version))
+            .collect();
+        assert_eq!(stale, ["lib@0.2.0", "tool@1.0.0", "tool@1.2.0"]);
+
+        let early = DateTime::parse_from_rfc3339("2026-01-02T00:00:00+00:00").unwrap();
+        assert_eq!(super::stale(&versions, early).len(), 2);
+    }
+}
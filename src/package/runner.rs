@@ -0,0 +1,204 @@
+//! # Package Runner
+//!
+//! Executes a package's primary binary without linking it into the user's
+//! environment — an `npx`/`pipx run` style workflow for one-off tool usage.
+//!
+//! ## Resolution
+//! 1. If the package is installed, its current version is used.
+//! 2. Otherwise the package is fetched from the configured repositories and
+//!    unpacked into `~/.uhpm/run/<name>-<version>` without being registered
+//!    in the database or creating any symlinks.
+//!
+//! Ephemeral packages are reused until they are older than
+//! `Config.run_cache_ttl_hours`, then removed and fetched again.
+//!
+//! ## Primary binary
+//! The `bin` field of `uhp.toml` wins; otherwise `bin/<name>` is used, or
+//! the only file in `bin/` if there is exactly one.
+
+use crate::config::Config;
+use crate::db::PackageDB;
+use crate::error::UhpmError;
+use crate::package::{Package, get_pkg_path, installer, meta_parser};
+use crate::repo::{cache_repo, find_package, parse_repos};
+use crate::{access, debug, fetcher, info, platform, signing};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Marker file recording when an ephemeral package was fetched
+const STAMP_FILE: &str = ".uhpm-run-stamp";
+
+/// Directory holding ephemeral packages (`~/.uhpm/run`)
+pub fn ephemeral_root() -> PathBuf {
+    platform::uhpm_home().join("run")
+}
+
+/// Removes ephemeral packages older than `ttl`; returns how many
+pub fn purge_expired(ttl: Duration) -> std::io::Result<usize> {
+    let root = ephemeral_root();
+    if !root.exists() {
+        return Ok(0);
+    }
+    let mut purged = 0;
+
+    for entry in fs::read_dir(&root)? {
+        let dir = entry?.path();
+        if is_expired(&dir, ttl) {
+            debug!("package.runner.purging", dir.display());
+            fs::remove_dir_all(&dir)?;
+            purged += 1;
+        }
+    }
+    Ok(purged)
+}
+
+fn is_expired(dir: &Path, ttl: Duration) -> bool {
+    fs::metadata(dir.join(STAMP_FILE))
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|fetched| SystemTime::now().duration_since(fetched).ok())
+        .is_none_or(|age| age > ttl)
+}
+
+/// Locates the primary binary of an unpacked package
+pub fn primary_binary(package_root: &Path, pkg: &Package) -> Result<PathBuf, UhpmError> {
+    if let Some(bin) = pkg.bin() {
+        return Ok(package_root.join(bin));
+    }
+
+    let bin_dir = package_root.join("bin");
+    let named = bin_dir.join(pkg.name());
+    if named.is_file() {
+        return Ok(named);
+    }
+
+    let files: Vec<PathBuf> = fs::read_dir(&bin_dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.is_file())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    match files.as_slice() {
+        [only] => Ok(only.clone()),
+        _ => Err(UhpmError::Validation(format!(
+            "Package {} does not declare a primary binary",
+            pkg.name()
+        ))),
+    }
+}
+
+/// Resolves a package directory for running, fetching it if necessary
+///
+/// Returns the package root and its metadata.
+pub async fn resolve(
+    pkg_name: &str,
+    version: Option<&str>,
+    db: &PackageDB,
+) -> Result<(PathBuf, Package), UhpmError> {
+    let installed = match version {
+        Some(v) => db.get_package_by_version(pkg_name, v).await?,
+        None => db.get_current_package(pkg_name).await?,
+    };
+    if let Some(pkg) = installed {
+        let root = get_pkg_path(pkg_name, pkg.version().clone());
+        if root.exists() {
+            debug!("package.runner.using_installed", root.display());
+            if !access::is_read_only() {
+                let now = chrono::Local::now().to_rfc3339();
+                db.set_last_run(pkg_name, &pkg.version().to_string(), &now)
+                    .await?;
+            }
+            let meta = meta_parser(&root.join("uhp.toml"))?;
+            return Ok((root, meta));
+        }
+    }
+
+    let config = Config::load_or_default();
+    purge_expired(Duration::from_secs(config.run_cache_ttl_hours * 3600))?;
+
+    let repos_path = platform::uhpm_home().join("repos.ron");
+    let repo_dbs = cache_repo(parse_repos(&repos_path)?).await;
+    let (found_version, url) = find_package(&repo_dbs, pkg_name, version)
+        .await?
+        .ok_or_else(|| UhpmError::NotFound(pkg_name.to_string()))?;
+
+    let root = ephemeral_root().join(format!("{}-{}", pkg_name, found_version));
+    if root.exists() {
+        debug!("package.runner.using_cached", root.display());
+    } else {
+        info!(
+            "package.runner.fetching",
+            pkg_name,
+            &found_version.to_string()
+        );
+        let downloaded = fetcher::fetch_packages(std::slice::from_ref(&url)).await;
+        let archive = downloaded
+            .get(&url)
+            .ok_or_else(|| UhpmError::Package(format!("Failed to download {}", url)))?;
+
+        signing::check(archive)?;
+        let unpacked = installer::unpack(archive)?;
+        fs::create_dir_all(ephemeral_root())?;
+        installer::move_dir(&unpacked, &root)?;
+        fs::write(root.join(STAMP_FILE), "")?;
+    }
+
+    let meta = meta_parser(&root.join("uhp.toml"))?;
+    Ok((root, meta))
+}
+
+/// Runs a package's primary binary with the given arguments
+///
+/// On success this function does not return: the current process is
+/// replaced by the package binary.
+pub async fn run(
+    pkg_name: &str,
+    version: Option<&str>,
+    args: &[String],
+    db: &PackageDB,
+) -> Result<(), UhpmError> {
+    let (root, meta) = resolve(pkg_name, version, db).await?;
+    let binary = primary_binary(&root, &meta)?;
+    info!("package.runner.executing", binary.display());
+
+    let err = platform::exec(std::process::Command::new(&binary).args(args));
+    Err(UhpmError::Io(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::Source;
+    use semver::Version;
+
+    #[test]
+    fn test_primary_binary_fallbacks() {
+        let tmp = tempfile::tempdir().unwrap();
+        let bin_dir = tmp.path().join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        fs::write(bin_dir.join("tool"), "").unwrap();
+
+        let pkg = Package::new(
+            "example",
+            Version::parse("1.0.0").unwrap(),
+            "author",
+            Source::Raw("test".to_string()),
+            "",
+            vec![],
+        );
+        assert_eq!(
+            primary_binary(tmp.path(), &pkg).unwrap(),
+            bin_dir.join("tool")
+        );
+
+        fs::write(bin_dir.join("example"), "").unwrap();
+        assert_eq!(
+            primary_binary(tmp.path(), &pkg).unwrap(),
+            bin_dir.join("example")
+        );
+    }
+}
@@ -0,0 +1,190 @@
+//! # Package Script and Build Sandbox
+//!
+//! Package scripts and build scripts are arbitrary shell commands from
+//! package authors. Where
+//! unprivileged user namespaces are available they run in a restricted
+//! environment:
+//!
+//! - no network access (unless `allow_network` is set);
+//! - the home directory is read-only, except for the package directory
+//!   the command works on.
+//!
+//! The namespace is set up with `bwrap` (bubblewrap) or, failing that,
+//! util-linux `unshare`. Configured in `config.ron`:
+//!
+//! ```ron
+//! sandbox: (mode: auto, allow_network: false),
+//! ```
+//!
+//! | Mode     | Without a usable backend                   |
+//! |----------|--------------------------------------------|
+//! | `auto`   | the command runs unrestricted (default)    |
+//! | `strict` | the command is refused                     |
+//! | `off`    | never sandboxed                            |
+
+use crate::config::Config;
+use crate::package::which::find_on_path;
+use crate::{debug, platform};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// When to sandbox commands
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SandboxMode {
+    /// Never
+    Off,
+    /// Whenever a backend is usable
+    #[default]
+    Auto,
+    /// Always; commands are refused without a usable backend
+    Strict,
+}
+
+/// Sandbox settings
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct SandboxConfig {
+    pub mode: SandboxMode,
+    /// Keep network access inside the sandbox
+    pub allow_network: bool,
+}
+
+/// Tool used to enter the namespace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Bwrap,
+    Unshare,
+}
+
+impl Backend {
+    /// Program and arguments that run `inner` restricted to `writable`
+    fn wrap(
+        self,
+        inner: &[String],
+        writable: &[&Path],
+        allow_network: bool,
+    ) -> (String, Vec<String>) {
+        let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("/"));
+        let home = home.to_string_lossy().to_string();
+        let mut args = Vec::new();
+        match self {
+            Backend::Bwrap => {
+                args.extend(["--dev-bind", "/", "/", "--ro-bind", &home, &home].map(String::from));
+                for dir in writable {
+                    let dir = dir.to_string_lossy().to_string();
+                    args.extend(["--bind".to_string(), dir.clone(), dir]);
+                }
+                if !allow_network {
+                    args.push("--unshare-net".to_string());
+                }
+                args.extend(["--die-with-parent", "--"].map(String::from));
+                args.extend(inner.iter().cloned());
+                ("bwrap".to_string(), args)
+            }
+            Backend::Unshare => {
+                // Mounts are set up by a shell inside the new namespace:
+                // `$1` is the home directory, the writable directories
+                // follow up to `--`, then the command itself. They are
+                // bound before home turns read-only, or they would inherit it
+                let setup = "home=$1; shift; \
+                    mount --rbind \"$home\" \"$home\" || exit 125; \
+                    while [ \"$1\" != -- ]; do mount --bind \"$1\" \"$1\" || exit 125; shift; done; \
+                    shift; mount -o remount,bind,ro \"$home\" || exit 125; exec \"$@\"";
+                args.extend(["--user", "--map-current-user", "--mount"].map(String::from));
+                if !allow_network {
+                    args.push("--net".to_string());
+                }
+                args.extend(["--", "sh", "-c", setup, "sh", &home].map(String::from));
+                args.extend(writable.iter().map(|d| d.to_string_lossy().to_string()));
+                args.push("--".to_string());
+                args.extend(inner.iter().cloned());
+                ("unshare".to_string(), args)
+            }
+        }
+    }
+}
+
+/// The first backend that can actually create a namespace here
+///
+/// Probed once per process by running `true` inside it.
+pub fn backend() -> Option<Backend> {
+    static BACKEND: Lazy<Option<Backend>> = Lazy::new(|| {
+        [Backend::Bwrap, Backend::Unshare]
+            .into_iter()
+            .filter(|b| {
+                let (program, _) = b.wrap(&[], &[], false);
+                !find_on_path(&program).is_empty()
+            })
+            .find(|b| {
+                let (program, args) = b.wrap(&["true".to_string()], &[], false);
+                let works = std::process::Command::new(&program)
+                    .args(&args)
+                    .stdout(std::process::Stdio::null())
+                    .stderr(std::process::Stdio::null())
+                    .status()
+                    .is_ok_and(|s| s.success());
+                debug!("package.sandbox.probed", &program, works);
+                works
+            })
+    });
+    *BACKEND
+}
+
+/// Builds a command running `cmd` through the platform shell, sandboxed
+/// according to the configuration with `writable` left writable
+///
+/// Writable directories that do not exist are skipped.
+///
+/// # Errors
+/// In `strict` mode, fails when no backend is usable.
+pub fn shell_command(cmd: &str, writable: &[&Path]) -> io::Result<tokio::process::Command> {
+    let config = Config::load_or_default().sandbox;
+    if config.mode == SandboxMode::Off {
+        return Ok(platform::shell_command(cmd));
+    }
+
+    match backend() {
+        Some(backend) => {
+            let inner = ["sh", "-c", cmd].map(String::from);
+            let writable: Vec<&Path> = writable.iter().copied().filter(|d| d.is_dir()).collect();
+            let (program, args) = backend.wrap(&inner, &writable, config.allow_network);
+            debug!("package.sandbox.wrapping", cmd, &program);
+            let mut command = tokio::process::Command::new(program);
+            command.args(args);
+            Ok(command)
+        }
+        None if config.mode == SandboxMode::Strict => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "sandbox mode is strict, but neither bwrap nor unshare can create a user namespace",
+        )),
+        None => {
+            debug!("package.sandbox.unavailable", cmd);
+            Ok(platform::shell_command(cmd))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bwrap_arguments() {
+        let inner = ["sh", "-c", "make"].map(String::from);
+        let pkg = Path::new("/pkgs/app-1.0.0");
+        let (program, args) = Backend::Bwrap.wrap(&inner, &[pkg], false);
+        assert_eq!(program, "bwrap");
+        assert!(
+            args.windows(3)
+                .any(|w| w == ["--bind", "/pkgs/app-1.0.0", "/pkgs/app-1.0.0"])
+        );
+        assert!(args.contains(&"--unshare-net".to_string()));
+        assert_eq!(&args[args.len() - 4..], ["--", "sh", "-c", "make"]);
+
+        let (_, args) = Backend::Bwrap.wrap(&inner, &[pkg], true);
+        assert!(!args.contains(&"--unshare-net".to_string()));
+    }
+}
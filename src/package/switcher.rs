@@ -6,18 +6,29 @@
 //! version.
 //!
 //! ## Responsibilities
-//! - Remove symlinks of the currently active version.
-//! - Validate existence of the target version directory.
+//! - Remove symlinks (or recorded copies) of the currently active version,
+//!   using the link mode that version was installed with. Versioned links
+//!   (see [`crate::symlist`]) are kept, so installed versions stay
+//!   reachable side by side. A copy the user changed since it was installed
+//!   is moved aside to `<file>.uhpm-save` instead of being deleted.
+//! - Validate existence of the target version directory and check its link
+//!   destinations for conflicts (see [`conflicts::claim`]).
 //! - Create symlinks for the target version.
+//! - Record the files of the target version together with their link mode.
 //! - Update the package database with the new current version.
 //!
 //! Errors are unified under [`SwitchError`] for consistency.
 
 use crate::db::PackageDB;
 use crate::error::SwitchError;
+use crate::error::UhpmError;
 use crate::package::installer::create_symlinks;
-use crate::{info, warn};
+use crate::package::{
+    LinkMode, app_bundles, assets, conflicts, packages_dir, remover, systemd, verify,
+};
+use crate::{info, platform, warn};
 use semver::Version;
+use std::path::Path;
 
 /// Errors that may occur when switching package versions.
 // #[derive(Debug)]
@@ -58,20 +69,29 @@ use semver::Version;
 /// - `db`: Reference to the [`PackageDB`] instance.
 ///
 /// # Workflow
-/// 1. Remove symlinks of the current active version (if present).
+/// 1. Verify that the target package directory exists and claim its link
+///    destinations with [`conflicts::claim`].
+///    - If it does not exist, returns [`SwitchError::MissingPackageDir`].
+///    - Files of other packages or of the user are a
+///      [`SwitchError::Conflict`] unless overwriting is allowed.
+/// 2. Remove symlinks of the current active version (if present).
 ///    - Ensures only symlinks created by UHPM are removed.
-///    - Non-matching symlinks or regular files are skipped safely.
-/// 2. Verify that the target package directory exists.
-///    - If not, returns [`SwitchError::MissingPackageDir`].
-/// 3. Create symlinks for the target version using [`create_symlinks`].
-/// 4. Update the package database with the new current version.
+///    - Versioned symlist targets are left in place.
+///    - Non-matching symlinks or regular files are skipped safely, except
+///      for recorded copies of a version installed in `direct` mode; when
+///      linking they are handled by `replace_policy` like any user file.
+/// 3. Create symlinks for the target version using [`create_symlinks`] and
+///    move declared systemd user units over to it.
+/// 4. Record the created files with the link mode implied by `direct` and
+///    update the package database with the new current version.
 ///
 /// # Errors
 /// Returns [`SwitchError`] if:
 /// - Filesystem operations (removing files, reading symlinks) fail.
 /// - Database operations fail.
-/// - `symlist.ron` is missing or invalid.
+/// - `symlist` is invalid.
 /// - Target package directory does not exist.
+/// - Linking the target version would replace files it may not.
 ///
 /// # Logging
 /// - Logs removed or skipped symlinks from the old version.
@@ -82,26 +102,59 @@ pub async fn switch_version(
     db: &PackageDB,
     direct: bool,
 ) -> Result<(), SwitchError> {
-    // Get home directory safely
-    let home_dir = dirs::home_dir().ok_or_else(|| {
-        SwitchError::Io(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "HOME directory not found",
-        ))
-    })?;
+    let direct = direct || crate::platform::must_copy();
+    crate::crash::set_package(&format!("{}@{}", pkg_name, target_version));
+
+    let new_pkg_dir = packages_dir().join(format!("{}-{}", pkg_name, target_version));
+    if !new_pkg_dir.exists() {
+        return Err(SwitchError::MissingPackageDir(new_pkg_dir));
+    }
+    let claim = conflicts::claim(&new_pkg_dir, &packages_dir(), pkg_name, db)
+        .await
+        .map_err(|e| match e {
+            UhpmError::Database(e) => SwitchError::Db(e),
+            UhpmError::Io(e) => SwitchError::Io(e),
+            UhpmError::Conflict(message) => SwitchError::Conflict(message),
+            e => SwitchError::Conflict(e.to_string()),
+        })?;
 
     // Remove symlinks from the current version if available
     if let Some(current_package) = db.get_current_package(pkg_name).await? {
         let current_version_str = current_package.version().to_string();
-        let current_pkg_dir = home_dir
-            .join(".uhpm/packages")
-            .join(format!("{}-{}", pkg_name, current_version_str));
+        let current_pkg_dir = packages_dir().join(format!("{}-{}", pkg_name, current_version_str));
 
         if current_pkg_dir.exists() {
-            let symlist_path = current_pkg_dir.join("symlist.ron");
-            match crate::symlist::load_symlist(&symlist_path, &current_pkg_dir) {
-                Ok(symlinks) => {
-                    for (src_abs, dst_abs) in symlinks {
+            let old_files = db
+                .get_installed_files_with_mode(pkg_name, &current_version_str)
+                .await?;
+            let old_direct = db
+                .get_link_mode(pkg_name, &current_version_str)
+                .await?
+                .map_or(direct, LinkMode::is_copy);
+            let is_recorded_copy = |path: &std::path::Path| {
+                old_files.iter().any(|(f, mode)| {
+                    *mode == Some(LinkMode::Copy) && std::path::Path::new(f) == path
+                })
+            };
+            let old_hashes = db.get_file_hashes(pkg_name, &current_version_str).await?;
+            let is_modified_copy = |path: &std::path::Path| {
+                !remover::removing_modified_allowed()
+                    && old_hashes
+                        .get(&*path.to_string_lossy())
+                        .is_some_and(|hash| verify::is_modified(path, hash))
+            };
+
+            if let Some(meta) = systemd::installed_meta(&current_pkg_dir) {
+                systemd::deactivate(&current_pkg_dir, &meta, old_direct)?;
+                assets::unlink(&current_pkg_dir, &meta, old_direct)?;
+            }
+            app_bundles::unlink(&current_pkg_dir, &old_files)?;
+            let symlist_path = current_pkg_dir.join("symlist");
+            match crate::symlist::load_links(&symlist_path, &current_pkg_dir) {
+                Ok(links) => {
+                    // Versioned links stay with their version
+                    for link in links.into_iter().filter(|link| !link.versioned) {
+                        let (src_abs, dst_abs) = (link.source, link.target);
                         if !dst_abs.exists() {
                             continue;
                         }
@@ -140,6 +193,32 @@ pub async fn switch_version(
                                     }
                                 }
                             }
+                            Ok(_) if is_recorded_copy(&dst_abs) && is_modified_copy(&dst_abs) => {
+                                let saved = remover::save_path(&dst_abs);
+                                match std::fs::rename(&dst_abs, &saved) {
+                                    Ok(()) => warn!(
+                                        "package.switcher.saved_modified_copy",
+                                        dst_abs.display(),
+                                        saved.display()
+                                    ),
+                                    Err(e) => warn!(
+                                        "package.switcher.remove_symlink_failed",
+                                        dst_abs.display(),
+                                        e
+                                    ),
+                                }
+                            }
+                            Ok(_) if is_recorded_copy(&dst_abs) => {
+                                if let Err(e) = std::fs::remove_file(&dst_abs) {
+                                    warn!(
+                                        "package.switcher.remove_symlink_failed",
+                                        dst_abs.display(),
+                                        e
+                                    );
+                                } else {
+                                    info!("package.switcher.removed_old_copy", dst_abs.display());
+                                }
+                            }
                             Ok(_) => {
                                 info!("package.switcher.skipping_not_symlink", dst_abs.display())
                             }
@@ -166,17 +245,30 @@ pub async fn switch_version(
         info!("package.switcher.no_current_version_cleanup_skip");
     }
 
-    // Verify target package directory exists
-    let new_pkg_dir = home_dir
-        .join(".uhpm/packages")
-        .join(format!("{}-{}", pkg_name, target_version));
-
-    if !new_pkg_dir.exists() {
-        return Err(SwitchError::MissingPackageDir(new_pkg_dir));
-    }
-
     // Create symlinks for the new version
-    create_symlinks(&new_pkg_dir, direct)?;
+    let mut linked = create_symlinks(&new_pkg_dir, direct, &claim)?;
+    if let Some(meta) = systemd::installed_meta(&new_pkg_dir) {
+        linked.extend(systemd::activate(&new_pkg_dir, &meta, direct, &claim)?);
+        linked.extend(assets::link(&new_pkg_dir, &meta, direct, &claim)?);
+    }
+    linked.extend(app_bundles::link(&new_pkg_dir, direct)?);
+    let hashes = verify::hash_files(&linked);
+    let linked: Vec<String> = linked
+        .iter()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+    db.set_installed_files(
+        pkg_name,
+        &target_version.to_string(),
+        &linked,
+        LinkMode::from_direct(direct),
+    )
+    .await?;
+    db.set_file_hashes(pkg_name, &target_version.to_string(), &hashes)
+        .await?;
+    for path in &claim.taken_over {
+        db.disown_file(&path.to_string_lossy(), pkg_name).await?;
+    }
 
     // Update database with the new current version
     db.set_current_version(pkg_name, &target_version.to_string())
@@ -186,3 +278,21 @@ pub async fn switch_version(
 
     Ok(())
 }
+
+/// Switches a package version in the isolated installation at `uhpm_root`
+///
+/// Same as [`switch_version()`], with `uhpm_root` in place of the user's
+/// home.
+pub async fn switch_version_at(
+    pkg_name: &str,
+    target_version: Version,
+    db: &PackageDB,
+    uhpm_root: &Path,
+    direct: bool,
+) -> Result<(), SwitchError> {
+    platform::with_root(
+        uhpm_root,
+        switch_version(pkg_name, target_version, db, direct),
+    )
+    .await
+}
@@ -0,0 +1,245 @@
+//! # Systemd User Units
+//!
+//! Packages may ship systemd user units and declare them in `uhp.toml`:
+//!
+//! ```toml
+//! [[systemd]]
+//! unit = "share/systemd/my-daemon.service"
+//! enable = true
+//! start = true
+//! ```
+//!
+//! On install (and when switching to a version) the units are linked into
+//! the user unit directory (`$XDG_CONFIG_HOME/systemd/user`), systemd is
+//! reloaded, and units are enabled/started as requested. Removal and
+//! switching away stop, disable and unlink them again. A unit file already
+//! at a destination goes through the replace policy of
+//! [`conflicts`](crate::package::conflicts) like any other link target.
+//!
+//! A missing or failing `systemctl` is logged but never aborts the
+//! package operation. With an alternate root (`--root`) units are linked
+//! below the root and `systemctl` is not run at all.
+
+use crate::package::Package;
+use crate::package::conflicts::Claim;
+use crate::{debug, info, platform, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A systemd user unit declared in package metadata
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SystemdUnit {
+    /// Path of the unit file, relative to the package root
+    pub unit: String,
+    /// Run `systemctl --user enable` after linking
+    #[serde(default)]
+    pub enable: bool,
+    /// Run `systemctl --user start` after linking
+    #[serde(default)]
+    pub start: bool,
+}
+
+impl SystemdUnit {
+    /// File name of the unit, e.g. `my-daemon.service`
+    pub fn name(&self) -> Option<&str> {
+        Path::new(&self.unit).file_name().and_then(|n| n.to_str())
+    }
+}
+
+/// Returns the systemd user unit directory
+///
+/// With an alternate root it is `<root>/.config/systemd/user`.
+pub fn user_unit_dir() -> PathBuf {
+    if let Some(root) = platform::root() {
+        return root.join(".config/systemd/user");
+    }
+    std::env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| dirs::home_dir().unwrap().join(".config"))
+        .join("systemd/user")
+}
+
+/// Runs `systemctl --user <args>`, logging failures
+///
+/// Under an alternate root the units are only linked: the user's systemd
+/// instance manages the real home, not the root.
+fn systemctl(args: &[&str]) -> bool {
+    if let Some(root) = platform::root() {
+        debug!(
+            "package.systemd.systemctl_skipped",
+            args.join(" "),
+            root.display()
+        );
+        return false;
+    }
+    debug!("package.systemd.systemctl", args.join(" "));
+    match Command::new("systemctl").arg("--user").args(args).status() {
+        Ok(status) if status.success() => true,
+        Ok(status) => {
+            warn!(
+                "package.systemd.systemctl_failed",
+                args.join(" "),
+                status.code()
+            );
+            false
+        }
+        Err(e) => {
+            warn!("package.systemd.systemctl_unavailable", e);
+            false
+        }
+    }
+}
+
+/// Links the package's units into the user unit directory without
+/// touching systemd
+///
+/// A unit already at a destination is only replaced as `claim` allows, so
+/// a unit the user wrote is kept under the default replace policy.
+/// Returns the created unit paths so they can be tracked as installed files.
+pub fn link_units(
+    package_root: &Path,
+    pkg: &Package,
+    direct: bool,
+    claim: &Claim,
+) -> std::io::Result<Vec<PathBuf>> {
+    let units = pkg.systemd_units();
+    if units.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let unit_dir = user_unit_dir();
+    fs::create_dir_all(&unit_dir)?;
+
+    let mut linked = Vec::new();
+    for unit in units {
+        let Some(name) = unit.name() else {
+            warn!("package.systemd.invalid_unit", &unit.unit);
+            continue;
+        };
+        let src = package_root.join(&unit.unit);
+        if !src.exists() {
+            warn!("package.systemd.unit_not_found", src.display());
+            continue;
+        }
+
+        let dst = unit_dir.join(name);
+        claim.clear(package_root, &dst)?;
+        if direct {
+            fs::copy(&src, &dst)?;
+        } else {
+            crate::platform::symlink(&src, &dst)?;
+        }
+        info!("package.systemd.linked", name);
+        linked.push(dst);
+    }
+    Ok(linked)
+}
+
+/// Links the package's units into the user unit directory and activates them
+///
+/// Returns the created unit paths so they can be tracked as installed files.
+pub fn activate(
+    package_root: &Path,
+    pkg: &Package,
+    direct: bool,
+    claim: &Claim,
+) -> std::io::Result<Vec<PathBuf>> {
+    let units = pkg.systemd_units();
+    if units.is_empty() {
+        return Ok(Vec::new());
+    }
+    let linked = link_units(package_root, pkg, direct, claim)?;
+
+    systemctl(&["daemon-reload"]);
+    for unit in units {
+        let Some(name) = unit.name() else { continue };
+        if unit.enable {
+            systemctl(&["enable", name]);
+        }
+        if unit.start {
+            systemctl(&["start", name]);
+        }
+    }
+
+    Ok(linked)
+}
+
+/// Stops, disables and unlinks the package's units
+///
+/// Only unit files that are symlinks into `package_root` (or plain copies
+/// when `direct` is set) are removed.
+pub fn deactivate(package_root: &Path, pkg: &Package, direct: bool) -> std::io::Result<()> {
+    let units = pkg.systemd_units();
+    if units.is_empty() {
+        return Ok(());
+    }
+
+    let unit_dir = user_unit_dir();
+    for unit in units {
+        let Some(name) = unit.name() else { continue };
+        if unit.start || unit.enable {
+            systemctl(&["disable", "--now", name]);
+        }
+
+        let dst = unit_dir.join(name);
+        let owned = match fs::read_link(&dst) {
+            Ok(target) => target.starts_with(package_root),
+            Err(_) => direct && dst.is_file(),
+        };
+        if owned {
+            fs::remove_file(&dst)?;
+            info!("package.systemd.unlinked", name);
+        }
+    }
+
+    systemctl(&["daemon-reload"]);
+    Ok(())
+}
+
+/// Reads package metadata from an installed package directory
+pub fn installed_meta(package_root: &Path) -> Option<Package> {
+    crate::package::meta_parser(&package_root.join("uhp.toml")).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_user_unit_dir_under_root() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        platform::with_root(&root, async {
+            assert_eq!(user_unit_dir(), root.join(".config/systemd/user"));
+            assert!(!systemctl(&["daemon-reload"]));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_user_units_are_not_replaced() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path().join("home");
+        let package_root = tmp.path().join("daemon-1.0.0");
+        fs::create_dir_all(package_root.join("units")).unwrap();
+        fs::write(package_root.join("units/daemon.service"), "[Service]").unwrap();
+        let pkg: Package = toml::from_str(&format!(
+            "{}\n[[systemd]]\nunit = \"units/daemon.service\"\n",
+            crate::package::test_manifest("daemon", "1.0.0")
+        ))
+        .unwrap();
+
+        platform::with_root(&root, async {
+            let unit = user_unit_dir().join("daemon.service");
+            fs::create_dir_all(unit.parent().unwrap()).unwrap();
+            fs::write(&unit, "mine").unwrap();
+
+            assert!(link_units(&package_root, &pkg, false, &Claim::default()).is_err());
+            assert_eq!(fs::read_to_string(&unit).unwrap(), "mine");
+        })
+        .await;
+    }
+}
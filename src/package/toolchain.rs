@@ -0,0 +1,193 @@
+//! # Project Toolchains
+//!
+//! A project declares the tools it needs in a `uhpm.toml` at its root,
+//! much like `rust-toolchain.toml` but for any uhpm package:
+//!
+//! ```toml
+//! [tools]
+//! ripgrep = "^14"
+//! jq = "1.7.1"
+//! ```
+//!
+//! Versions are [`VersionSpec`]s. `uhpm toolchain check` looks for the
+//! file in the working directory and its parents and compares every tool
+//! with the installation: the current version may satisfy it, another
+//! installed version may (the tool only has to be switched), or it has to
+//! be installed. `--install` does what is needed, `--json` reports the
+//! result for editors.
+
+use crate::error::UhpmError;
+use crate::package::VersionSpec;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the project toolchain file
+pub const FILE_NAME: &str = "uhpm.toml";
+
+/// Tools required by a project
+#[derive(Debug, Clone, PartialEq)]
+pub struct Toolchain {
+    pub path: PathBuf,
+    pub tools: BTreeMap<String, VersionSpec>,
+}
+
+#[derive(Deserialize)]
+struct ToolchainFile {
+    #[serde(default)]
+    tools: BTreeMap<String, String>,
+}
+
+/// How a required tool is met by the installation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolState {
+    /// The current version satisfies the requirement
+    Ok,
+    /// Another installed version does; it has to be switched to
+    Inactive,
+    /// Installed, but no installed version satisfies the requirement
+    Mismatch,
+    /// Not installed
+    Missing,
+}
+
+/// A required tool compared with the installation
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ToolStatus {
+    pub name: String,
+    pub required: String,
+    /// Current installed version
+    pub current: Option<Version>,
+    /// Highest installed version satisfying the requirement
+    pub matching: Option<Version>,
+    pub state: ToolState,
+}
+
+/// Result of `uhpm toolchain check`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ToolchainReport {
+    pub file: PathBuf,
+    pub tools: Vec<ToolStatus>,
+}
+
+impl ToolchainReport {
+    /// Whether every tool is satisfied
+    pub fn is_satisfied(&self) -> bool {
+        self.tools.iter().all(|t| t.state == ToolState::Ok)
+    }
+}
+
+/// Finds `uhpm.toml` in `start` or its parents
+pub fn find(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+impl Toolchain {
+    /// Reads a toolchain file
+    pub fn load(path: &Path) -> Result<Self, UhpmError> {
+        let content = fs::read_to_string(path)?;
+        let parse_error = |e: String| UhpmError::Parse(format!("{}: {}", path.display(), e));
+        let file: ToolchainFile =
+            toml::from_str(&content).map_err(|e| parse_error(e.to_string()))?;
+        let mut tools = BTreeMap::new();
+        for (name, spec) in file.tools {
+            let spec: VersionSpec = spec
+                .parse()
+                .map_err(|e| parse_error(format!("{}: {}", name, e)))?;
+            tools.insert(name, spec);
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            tools,
+        })
+    }
+
+    /// Compares the tools with `installed` `(name, version, is_current)`
+    /// entries
+    pub fn check(&self, installed: &[(String, String, bool)]) -> ToolchainReport {
+        let tools = self
+            .tools
+            .iter()
+            .map(|(name, spec)| {
+                let versions: Vec<(Version, bool)> = installed
+                    .iter()
+                    .filter(|(pkg, _, _)| pkg == name)
+                    .filter_map(|(_, v, current)| Version::parse(v).ok().map(|v| (v, *current)))
+                    .collect();
+                let current = versions.iter().find(|(_, c)| *c).map(|(v, _)| v);
+                let matching = spec.best_match(versions.iter().map(|(v, _)| v));
+                let state = match (current, matching) {
+                    _ if versions.is_empty() => ToolState::Missing,
+                    (Some(current), _) if spec.matches(current) => ToolState::Ok,
+                    (_, Some(_)) => ToolState::Inactive,
+                    (_, None) => ToolState::Mismatch,
+                };
+                ToolStatus {
+                    name: name.clone(),
+                    required: spec.to_string(),
+                    current: current.cloned(),
+                    matching: matching.cloned(),
+                    state,
+                }
+            })
+            .collect();
+        ToolchainReport {
+            file: self.path.clone(),
+            tools,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn installed(name: &str, version: &str, current: bool) -> (String, String, bool) {
+        (name.to_string(), version.to_string(), current)
+    }
+
+    #[test]
+    fn test_find_load_and_check() {
+        let tmp = tempfile::tempdir().unwrap();
+        let nested = tmp.path().join("src/bin");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(
+            tmp.path().join(FILE_NAME),
+            "[tools]\nripgrep = \"^14\"\njq = \"1.7.1\"\nfd = \"^9\"\nbat = \"~0.24\"\n",
+        )
+        .unwrap();
+
+        let path = find(&nested).unwrap();
+        let report = Toolchain::load(&path).unwrap().check(&[
+            installed("ripgrep", "14.1.0", true),
+            installed("jq", "1.6.0", true),
+            installed("jq", "1.7.1", false),
+            installed("fd", "8.7.0", true),
+        ]);
+        let states: Vec<(&str, ToolState)> = report
+            .tools
+            .iter()
+            .map(|t| (t.name.as_str(), t.state))
+            .collect();
+        assert_eq!(
+            states,
+            [
+                ("bat", ToolState::Missing),
+                ("fd", ToolState::Mismatch),
+                ("jq", ToolState::Inactive),
+                ("ripgrep", ToolState::Ok),
+            ]
+        );
+        assert_eq!(report.tools[2].matching, Some(Version::new(1, 7, 1)));
+        assert!(!report.is_satisfied());
+
+        fs::write(tmp.path().join(FILE_NAME), "[tools]\njq = \"latest\"\n").unwrap();
+        assert!(matches!(Toolchain::load(&path), Err(UhpmError::Parse(_))));
+    }
+}
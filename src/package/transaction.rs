@@ -0,0 +1,294 @@
+//! # Install Transactions
+//!
+//! An install touches the package store, files all over the user's home
+//! and several database tables. [`Transaction`] journals each of these
+//! steps as it happens so that a failure halfway can be undone:
+//!
+//! | Journal entry        | Rollback                                       |
+//! |----------------------|------------------------------------------------|
+//! | directory created    | directory removed                              |
+//! | path guarded         | new file removed, previous file restored       |
+//! | package row added    | the version's rows removed from the database   |
+//! | current version set  | the previous current version restored          |
+//!
+//! Files that a link is about to replace are moved into a backup directory
+//! under [`installer::tmp_dir()`](super::installer::tmp_dir) rather than
+//! deleted, and only discarded once the transaction is committed. By then
+//! [`conflicts::claim`](super::conflicts::claim) has applied the replace
+//! policy to every link target, so these are uhpm's own files or ones the
+//! policy lets it overwrite; user files it backs up are kept next to their
+//! place, outside the transaction.
+
+use crate::db::PackageDB;
+use crate::error::UhpmError;
+use crate::package::{Package, app_bundles, assets, installer, systemd};
+use crate::{debug, info, platform, symlist, warn};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A step that can be undone
+#[derive(Debug)]
+enum Entry {
+    /// A directory that did not exist before
+    CreatedDir(PathBuf),
+    /// A path the install may create a file, link or directory at, and
+    /// where its previous content was moved, if there was any
+    Guarded {
+        path: PathBuf,
+        backup: Option<PathBuf>,
+    },
+    /// Database rows of a package version
+    PackageRow { name: String, version: String },
+    /// The current version of a package before it was changed
+    CurrentVersion {
+        name: String,
+        previous: Option<String>,
+    },
+}
+
+/// Journal of an install in progress
+#[derive(Debug)]
+pub struct Transaction {
+    journal: Vec<Entry>,
+    backup_dir: PathBuf,
+}
+
+impl Transaction {
+    /// Starts an empty transaction
+    pub fn begin() -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or_default();
+        Self {
+            journal: Vec::new(),
+            backup_dir: installer::tmp_dir().join(format!("txn-{}-{}", std::process::id(), nanos)),
+        }
+    }
+
+    /// Records a directory created by the install
+    pub fn created_dir(&mut self, path: &Path) {
+        self.journal.push(Entry::CreatedDir(path.to_path_buf()));
+    }
+
+    /// Moves whatever is at `paths` aside so it can be restored on rollback
+    ///
+    /// Everything found at these paths afterwards is considered created by
+    /// the install.
+    pub fn guard(&mut self, paths: &[PathBuf]) -> io::Result<()> {
+        for path in paths {
+            let backup = if fs::symlink_metadata(path).is_ok() {
+                let backup = self.backup_dir.join(self.journal.len().to_string());
+                fs::create_dir_all(&self.backup_dir)?;
+                move_entry(path, &backup)?;
+                debug!("package.transaction.backed_up", path.display());
+                Some(backup)
+            } else {
+                None
+            };
+            self.journal.push(Entry::Guarded {
+                path: path.clone(),
+                backup,
+            });
+        }
+        Ok(())
+    }
+
+    /// Records that the rows of a package version are about to be written
+    pub fn package_row(&mut self, name: &str, version: &str) {
+        self.journal.push(Entry::PackageRow {
+            name: name.to_string(),
+            version: version.to_string(),
+        });
+    }
+
+    /// Records the current version of `name` before it is changed
+    pub async fn current_version(&mut self, db: &PackageDB, name: &str) -> Result<(), UhpmError> {
+        let previous = db.get_package_version(name).await?;
+        self.journal.push(Entry::CurrentVersion {
+            name: name.to_string(),
+            previous,
+        });
+        Ok(())
+    }
+
+    /// Keeps all changes and discards the backups
+    pub fn commit(self) {
+        if self.backup_dir.exists()
+            && let Err(e) = fs::remove_dir_all(&self.backup_dir)
+        {
+            warn!(
+                "package.transaction.cleanup_failed",
+                self.backup_dir.display(),
+                e
+            );
+        }
+    }
+
+    /// Undoes the journaled steps, newest first
+    ///
+    /// Every step is attempted even if an earlier one fails; failures are
+    /// logged, and the backup directory is kept if something could not be
+    /// restored.
+    pub async fn rollback(mut self, db: &PackageDB) {
+        info!("package.transaction.rolling_back", self.journal.len());
+        let mut clean = true;
+        while let Some(entry) = self.journal.pop() {
+            if let Err(e) = undo(&entry, db).await {
+                warn!("package.transaction.undo_failed", format!("{:?}", entry), e);
+                clean = false;
+            }
+        }
+        if clean {
+            self.commit();
+        } else {
+            warn!(
+                "package.transaction.backups_kept",
+                self.backup_dir.display()
+            );
+        }
+    }
+}
+
+async fn undo(entry: &Entry, db: &PackageDB) -> Result<(), UhpmError> {
+    match entry {
+        Entry::CreatedDir(path) => remove_entry(path)?,
+        Entry::Guarded { path, backup } => restore(path, backup.as_deref())?,
+        Entry::PackageRow { name, version } => db.remove_package_version(name, version).await?,
+        Entry::CurrentVersion {
+            name,
+            previous: Some(previous),
+        } => db.set_current_version(name, previous).await?,
+        Entry::CurrentVersion { previous: None, .. } => {}
+    }
+    Ok(())
+}
+
+/// Removes what the install created at `path` and moves `backup` back
+fn restore(path: &Path, backup: Option<&Path>) -> io::Result<()> {
+    remove_entry(path)?;
+    if let Some(backup) = backup {
+        move_entry(backup, path)?;
+    }
+    Ok(())
+}
+
+/// Removes a file, link or directory; a missing path is not an error
+fn remove_entry(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Moves a file, link or directory, also across filesystems
+fn move_entry(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            let meta = fs::symlink_metadata(from)?;
+            if meta.is_symlink() {
+                platform::symlink(&fs::read_link(from)?, to)?;
+            } else if meta.is_dir() {
+                installer::copy_tree(from, to)?;
+            } else {
+                fs::copy(from, to)?;
+            }
+            remove_entry(from)
+        }
+        result => result,
+    }
+}
+
+/// Every path linking `pkg` from `package_root` may create
+///
+/// App bundles that exist and are not links are left out: the installer
+/// never replaces them.
+pub fn link_targets(package_root: &Path, pkg: &Package) -> Vec<PathBuf> {
+    let mut targets: Vec<PathBuf> =
+        symlist::load_symlist(&package_root.join("symlist"), package_root)
+            .map(|entries| entries.into_iter().map(|(_, dst)| dst).collect())
+            .unwrap_or_default();
+    targets.extend(
+        pkg.systemd_units()
+            .iter()
+            .filter_map(|unit| unit.name())
+            .map(|name| systemd::user_unit_dir().join(name)),
+    );
+    targets.extend(
+        assets::placements(package_root, pkg)
+            .into_iter()
+            .map(|(_, dst)| dst),
+    );
+    targets.extend(
+        app_bundles::targets(package_root)
+            .into_iter()
+            .filter(|dst| fs::symlink_metadata(dst).is_err() || dst.is_symlink()),
+    );
+    targets.sort();
+    targets.dedup();
+    targets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guard_restores_replaced_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let existing = tmp.path().join("existing");
+        let fresh = tmp.path().join("fresh");
+        fs::write(&existing, "user file").unwrap();
+
+        let mut tx = Transaction {
+            journal: Vec::new(),
+            backup_dir: tmp.path().join("backup"),
+        };
+        tx.guard(&[existing.clone(), fresh.clone()]).unwrap();
+        assert!(!existing.exists());
+
+        fs::write(&existing, "from package").unwrap();
+        fs::write(&fresh, "from package").unwrap();
+        while let Some(entry) = tx.journal.pop() {
+            if let Entry::Guarded { path, backup } = entry {
+                restore(&path, backup.as_deref()).unwrap();
+            }
+        }
+
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "user file");
+        assert!(!fresh.exists());
+    }
+
+    #[test]
+    fn test_guard_restores_replaced_dirs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let version_dir = tmp.path().join("packages/tool-1.0.0");
+        fs::create_dir_all(&version_dir).unwrap();
+        fs::write(version_dir.join("uhp.toml"), "installed before").unwrap();
+
+        let mut tx = Transaction {
+            journal: Vec::new(),
+            backup_dir: tmp.path().join("backup"),
+        };
+        tx.guard(std::slice::from_ref(&version_dir)).unwrap();
+        assert!(!version_dir.exists());
+
+        fs::create_dir_all(&version_dir).unwrap();
+        fs::write(version_dir.join("uhp.toml"), "reinstalled").unwrap();
+        let Some(Entry::Guarded { path, backup }) = tx.journal.pop() else {
+            panic!("guard did not journal the directory");
+        };
+        restore(&path, backup.as_deref()).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(version_dir.join("uhp.toml")).unwrap(),
+            "installed before"
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! # Package Tree
+//!
+//! Groups installed package versions under their package for
+//! `uhpm list --tree`. Each version carries whether it is current, whether
+//! its store directory still exists, its size on disk, and where it was
+//! installed from.
+
+use crate::db::PackageDB;
+use crate::error::UhpmError;
+use crate::package::{Provenance, get_pkg_path};
+use semver::Version;
+use std::collections::BTreeMap;
+use walkdir::WalkDir;
+
+/// One installed version of a package
+#[derive(Debug, Clone)]
+pub struct VersionNode {
+    pub version: String,
+    pub current: bool,
+    /// Size of the store directory, `None` if it is missing
+    pub size: Option<u64>,
+    /// Where the version was installed from, if recorded
+    pub provenance: Option<Provenance>,
+}
+
+impl VersionNode {
+    /// Returns `true` if the version's store directory exists
+    pub fn on_disk(&self) -> bool {
+        self.size.is_some()
+    }
+}
+
+/// A package and all its installed versions, newest first
+#[derive(Debug, Clone)]
+pub struct PackageNode {
+    pub name: String,
+    pub versions: Vec<VersionNode>,
+}
+
+/// Total size of regular files below `dir`, `None` if it does not exist
+pub fn dir_size(dir: &std::path::Path) -> Option<u64> {
+    if !dir.is_dir() {
+        return None;
+    }
+    Some(
+        WalkDir::new(dir)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .filter_map(|e| e.metadata().ok())
+            .map(|m| m.len())
+            .sum(),
+    )
+}
+
+/// Groups `(name, version, current)` rows by package name
+///
+/// Packages are sorted by name, versions newest first; unparsable
+/// versions sort after valid ones.
+pub fn group(rows: Vec<(String, String, bool)>) -> BTreeMap<String, Vec<(String, bool)>> {
+    let mut grouped: BTreeMap<String, Vec<(String, bool)>> = BTreeMap::new();
+    for (name, version, current) in rows {
+        grouped.entry(name).or_default().push((version, current));
+    }
+    for versions in grouped.values_mut() {
+        versions.sort_by(|(a, _), (b, _)| Version::parse(b).ok().cmp(&Version::parse(a).ok()));
+    }
+    grouped
+}
+
+/// Builds the package tree from the database
+pub async fn build(db: &PackageDB) -> Result<Vec<PackageNode>, UhpmError> {
+    let mut tree = Vec::new();
+    for (name, versions) in group(db.list_packages().await?) {
+        let mut nodes = Vec::new();
+        for (version, current) in versions {
+            let size = Version::parse(&version)
+                .ok()
+                .and_then(|v| dir_size(&get_pkg_path(&name, v)));
+            let provenance = db.get_package_provenance(&name, &version).await?;
+            nodes.push(VersionNode {
+                version,
+                current,
+                size,
+                provenance,
+            });
+        }
+        tree.push(PackageNode {
+            name,
+            versions: nodes,
+        });
+    }
+    Ok(tree)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_sorts_versions() {
+        let rows = vec![
+            ("b".to_string(), "1.0.0".to_string(), true),
+            ("a".to_string(), "1.2.0".to_string(), false),
+            ("a".to_string(), "1.10.0".to_string(), true),
+        ];
+        let grouped = group(rows);
+
+        assert_eq!(grouped.keys().collect::<Vec<_>>(), vec!["a", "b"]);
+        assert_eq!(
+            grouped["a"],
+            vec![("1.10.0".to_string(), true), ("1.2.0".to_string(), false)]
+        );
+    }
+}
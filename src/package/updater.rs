@@ -2,22 +2,52 @@
 //!
 //! This module provides functionality to check for and install newer versions
 //! of installed packages from configured repositories.
+//!
+//! Repositories are looked up in their cached databases, refreshed from
+//! `http(s)://`, `file://` and local repositories alike the way installs
+//! do (see [`cache_repo`]).
+//!
+//! Versions on the `ignore` list of `config.ron` are never offered as
+//! updates (see [`ignore`](crate::package::ignore)). Packages pinned with
+//! `uhpm pin` are only updated to versions matching their pin, so a pin to
+//! an exact version holds them where they are.
 
+use crate::config::Config;
 use crate::db::PackageDB;
 use crate::error::UpdaterError;
+use crate::events::{self, ProgressEvent};
 use crate::fetcher;
-use crate::repo::{RepoDB, parse_repos};
-use crate::{info, warn};
+use crate::package::hooks::{self, PackageHook};
+use crate::package::ignore::IgnoreList;
+use crate::package::{
+    InstallOrigin, LinkMode, Provenance, VersionSpec, archive_cache, get_pkg_path, installer,
+    switcher,
+};
+use crate::repo::index::{self, RepoIndex};
+use crate::repo::{self, RepoMap, RepoPolicy, cache_repo, parse_repos};
+use crate::usage::{self, UsageKind};
+use crate::{crash, debug, info, platform, warn};
+use chrono::{Datelike, Local, NaiveDateTime, NaiveTime, Weekday};
 use semver::Version;
-use std::path::Path;
-
-/// Errors that may occur during package update.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 /// Check for updates and return download URL if newer version exists
 pub async fn check_for_update(
     pkg_name: &str,
     package_db: &PackageDB,
 ) -> Result<String, UpdaterError> {
+    latest_update(pkg_name, package_db)
+        .await
+        .map(|(url, _)| url)
+}
+
+/// Download URL and version of the newest allowed version above the installed one
+async fn latest_update(
+    pkg_name: &str,
+    package_db: &PackageDB,
+) -> Result<(String, Version), UpdaterError> {
     // Step 1: check installed version
     let installed_version = package_db.get_package_version(pkg_name).await?;
     if installed_version.is_none() {
@@ -32,85 +62,169 @@ pub async fn check_for_update(
     );
 
     // Step 2: parse repository configuration
-    let repos_path = dirs::home_dir().unwrap().join(".uhpm/repos.ron");
-    let repos = parse_repos(&repos_path).unwrap();
+    let repos_path = platform::uhpm_home().join("repos.ron");
+    let repos = parse_repos(&repos_path)?;
+    let policy = RepoPolicy::load();
+    let ignore = IgnoreList::load();
+    let pins = pins(package_db).await?;
+    let inst_ver = Version::parse(&installed_version).unwrap_or(Version::new(0, 0, 0));
+
+    let preferred =
+        preferred_repo(pkg_name, &installed_version, &repos, &policy, package_db).await?;
 
     let mut latest_url = None;
-    let mut latest_version: Option<Version> = None;
+    let mut latest_version: Option<(i32, Version)> = None;
 
     // Step 3: iterate through repositories
-    for (repo_name, repo_url) in repos {
-        info!("package.updater.checking_repo", &repo_name, &repo_url);
-
-        // Определяем путь к репозиторию
-        let repo_path = if repo_url.starts_with("file://") {
-            Path::new(repo_url.strip_prefix("file://").unwrap()).to_path_buf()
-        } else if repo_url.starts_with("http://") || repo_url.starts_with("https://") {
-            // Для HTTP репозиториев используем базовый URL
-            // База данных должна быть доступна по {repo_url}/repository.db
-            continue; // Пропускаем HTTP пока что, нужна дополнительная логика
-        } else {
-            // Прямой путь
-            Path::new(&repo_url).to_path_buf()
-        };
-
-        let repo_db_path = repo_path.join("repository.db");
-
-        // if !repo_db_path.exists() {
-        //     warn!("package.updater.repo_db_not_found", &repo_name);
-        //     continue;
-        // }
-
-        // Используем наш новый метод для загрузки репозитория
-        let repo_db = match RepoDB::from_repo_path(&repo_path).await {
-            Ok(db) => db,
-            Err(e) => {
-                warn!("package.updater.repo_load_failed", &repo_name, e);
-                continue;
-            }
-        };
+    let repos: RepoMap = repos
+        .into_iter()
+        .filter(|(name, _)| preferred.as_ref().is_none_or(|p| p == name))
+        .collect();
+    for (repo_name, repo_index) in repo_indexes(&repos).await {
+        info!(
+            "package.updater.checking_repo",
+            &repo_name,
+            repos.get(&repo_name).map_or("", String::as_str)
+        );
 
-        let pkg_list = match repo_db.list_packages().await {
-            Ok(list) => list,
-            Err(e) => {
-                warn!("package.updater.repo_list_failed", &repo_name, e);
+        // Ищем пакеты в репозитории
+        for entry in repo_index.find(pkg_name) {
+            if ignore.ignores(pkg_name, &entry.version) {
+                debug!("package.updater.ignored_version", pkg_name, &entry.version);
                 continue;
             }
-        };
-
-        // Ищем пакеты в репозитории
-        for (name, ver_str, url) in pkg_list {
-            if name == pkg_name {
-                match Version::parse(&ver_str) {
-                    Ok(ver) => {
-                        let inst_ver =
-                            Version::parse(&installed_version).unwrap_or(Version::new(0, 0, 0));
-
-                        // Используем clone для сравнения без перемещения
-                        let current_latest = latest_version.as_ref();
-                        if current_latest.is_none() || &ver > current_latest.unwrap() {
-                            latest_version = Some(ver);
-                            latest_url = Some(url);
-                            info!(
-                                "package.updater.newer_version_found",
-                                pkg_name, &ver_str, &repo_name
-                            );
-                        }
-                    }
-                    Err(e) => {
-                        warn!("package.updater.version_parse_failed", &ver_str, e);
-                        continue;
+            match Version::parse(&entry.version) {
+                Ok(ver) if !pin_allows(pins.get(pkg_name), &ver) => {
+                    debug!("package.updater.pinned_version", pkg_name, &entry.version);
+                }
+                Ok(ver) if ver <= inst_ver => {}
+                Ok(ver) => {
+                    // Приоритет репозитория важнее версии
+                    let candidate = (policy.priority(&repo_name), ver);
+                    if latest_version
+                        .as_ref()
+                        .is_none_or(|latest| &candidate > latest)
+                    {
+                        latest_version = Some(candidate);
+                        latest_url = Some(entry.url.clone());
+                        info!(
+                            "package.updater.newer_version_found",
+                            pkg_name, &entry.version, &repo_name
+                        );
                     }
                 }
+                Err(e) => {
+                    warn!("package.updater.version_parse_failed", &entry.version, e);
+                    continue;
+                }
             }
         }
     }
 
     // Return URL if newer version found
-    latest_url.ok_or_else(|| UpdaterError::NoNewVersion(pkg_name.to_string()))
+    latest_url
+        .zip(latest_version.map(|(_, ver)| ver))
+        .ok_or_else(|| UpdaterError::NoNewVersion(pkg_name.to_string()))
+}
+
+/// Indexes of `repos`, by repository name
+///
+/// As for installs, the repository databases are read from the cache,
+/// downloaded or refreshed by [`cache_repo`] when needed, so `http(s)://`
+/// repositories are checked like local ones. Repositories whose database
+/// is unavailable are skipped.
+async fn repo_indexes(repos: &RepoMap) -> Vec<(String, Arc<RepoIndex>)> {
+    let mut indexes = Vec::new();
+    for db_path in cache_repo(repos.clone()).await {
+        if !db_path.exists() {
+            continue;
+        }
+        let repo_name = repo::cache_name(&db_path);
+        match index::load_repo(&repo_name, &db_path).await {
+            Ok(repo_index) => indexes.push((repo_name, repo_index)),
+            Err(e) => warn!("package.updater.repo_load_failed", &repo_name, e),
+        }
+    }
+    indexes
+}
+
+/// Returns the repository a package is pinned to, or else the one it was
+/// installed from, if it is still configured
+///
+/// Updates are then looked up only in that repository, so a package does
+/// not silently jump to another repository carrying the same name.
+async fn preferred_repo(
+    pkg_name: &str,
+    installed_version: &str,
+    repos: &HashMap<String, String>,
+    policy: &RepoPolicy,
+    package_db: &PackageDB,
+) -> Result<Option<String>, UpdaterError> {
+    if let Some(pinned) = policy.pinned_repo(pkg_name) {
+        debug!("package.updater.preferred_repo", pkg_name, pinned);
+        return Ok(Some(pinned.to_string()));
+    }
+    let provenance = package_db
+        .get_package_provenance(pkg_name, installed_version)
+        .await?;
+    let repo = provenance
+        .as_ref()
+        .and_then(Provenance::repo)
+        .filter(|name| repos.contains_key(*name))
+        .map(str::to_string);
+    if let Some(name) = &repo {
+        debug!("package.updater.preferred_repo", pkg_name, name);
+    }
+    Ok(repo)
+}
+
+/// Pins recorded by `uhpm pin`, by package; a pin that is no longer a
+/// valid version holds its package entirely
+async fn pins(
+    package_db: &PackageDB,
+) -> Result<HashMap<String, Option<VersionSpec>>, UpdaterError> {
+    Ok(package_db
+        .list_pins()
+        .await?
+        .into_iter()
+        .map(|(name, version)| (name, version.parse().ok()))
+        .collect())
+}
+
+/// Whether `version` may replace a package with `pin`
+fn pin_allows(pin: Option<&Option<VersionSpec>>, version: &Version) -> bool {
+    match pin {
+        None => true,
+        Some(Some(spec)) => spec.matches(version),
+        Some(None) => false,
+    }
+}
+
+/// Fails if the pin of `pkg_name` holds it away from `version`
+async fn check_pin(
+    pkg_name: &str,
+    version: &Version,
+    package_db: &PackageDB,
+) -> Result<(), UpdaterError> {
+    let pin = package_db
+        .list_pins()
+        .await?
+        .into_iter()
+        .find(|(name, _)| name == pkg_name);
+    match pin {
+        Some((_, pin)) if !pin_allows(Some(&pin.parse().ok()), version) => {
+            Err(UpdaterError::Held(pkg_name.to_string(), pin))
+        }
+        _ => Ok(()),
+    }
 }
 
 /// Check for updates in all installed packages
+///
+/// Only current versions are considered. Packages installed from local
+/// files have no repository origin and are skipped (see
+/// [`local_packages`]); pinned packages are only offered versions matching
+/// their pin.
 pub async fn check_all_updates(
     package_db: &PackageDB,
 ) -> Result<Vec<(String, String, String, String)>, UpdaterError> {
@@ -118,49 +232,56 @@ pub async fn check_all_updates(
     let installed_packages = package_db.list_packages().await?;
     let mut updates = Vec::new();
 
-    // Парсим конфигурацию репозиториев
-    let repos_path = dirs::home_dir().unwrap().join(".uhpm/repos.ron");
-    let repos = parse_repos(&repos_path).unwrap();
+    // Парсим конфигурацию репозиториев; без repos.ron обновлений нет
+    let repos_path = platform::uhpm_home().join("repos.ron");
+    if !repos_path.exists() {
+        return Ok(Vec::new());
+    }
+    let repos = parse_repos(&repos_path)?;
+    let policy = RepoPolicy::load();
+    let ignore = IgnoreList::load();
+    let pins = pins(package_db).await?;
+    let indexes = repo_indexes(&repos).await;
+
+    for (pkg_name, installed_version, current) in installed_packages {
+        if !current {
+            continue;
+        }
+        if package_db.get_package_origin(&pkg_name).await? == Some(InstallOrigin::File) {
+            continue;
+        }
 
-    for (pkg_name, installed_version, _) in installed_packages {
-        let mut latest_version: Option<Version> = None;
+        let inst_ver = Version::parse(&installed_version).unwrap_or(Version::new(0, 0, 0));
+        let preferred =
+            preferred_repo(&pkg_name, &installed_version, &repos, &policy, package_db).await?;
+        let mut latest_version: Option<(i32, Version)> = None;
         let mut latest_repo = String::new();
 
-        for (repo_name, repo_url) in &repos {
-            let repo_path = if repo_url.starts_with("file://") {
-                Path::new(repo_url.strip_prefix("file://").unwrap()).to_path_buf()
-            } else {
-                continue; // Пропускаем не-file репозитории для упрощения
-            };
-
-            let repo_db = match RepoDB::from_repo_path(&repo_path).await {
-                Ok(db) => db,
-                Err(_) => continue,
-            };
-
-            let pkg_list = match repo_db.list_packages().await {
-                Ok(list) => list,
-                Err(_) => continue,
-            };
-
-            for (name, ver_str, _) in pkg_list {
-                if name == pkg_name {
-                    if let Ok(ver) = Version::parse(&ver_str) {
-                        let inst_ver =
-                            Version::parse(&installed_version).unwrap_or(Version::new(0, 0, 0));
-
-                        // Используем as_ref для сравнения без перемещения
-                        let current_latest = latest_version.as_ref();
-                        if current_latest.is_none() || &ver > current_latest.unwrap() {
-                            latest_version = Some(ver);
-                            latest_repo = repo_name.clone();
-                        }
+        for (repo_name, repo_index) in &indexes {
+            if preferred.as_ref().is_some_and(|p| p != repo_name) {
+                continue;
+            }
+            for entry in repo_index.find(&pkg_name) {
+                if ignore.ignores(&pkg_name, &entry.version) {
+                    continue;
+                }
+                if let Ok(ver) = Version::parse(&entry.version)
+                    && ver > inst_ver
+                    && pin_allows(pins.get(&pkg_name), &ver)
+                {
+                    let candidate = (policy.priority(repo_name), ver);
+                    if latest_version
+                        .as_ref()
+                        .is_none_or(|latest| &candidate > latest)
+                    {
+                        latest_version = Some(candidate);
+                        latest_repo = repo_name.clone();
                     }
                 }
             }
         }
 
-        if let Some(latest_ver) = latest_version {
+        if let Some((_, latest_ver)) = latest_version {
             updates.push((
                 pkg_name.clone(),
                 installed_version,
@@ -173,6 +294,17 @@ pub async fn check_all_updates(
     Ok(updates)
 }
 
+/// Returns names of current packages that were installed from local files
+pub async fn local_packages(package_db: &PackageDB) -> Result<Vec<String>, UpdaterError> {
+    let mut local = Vec::new();
+    for (pkg_name, _, current) in package_db.list_packages().await? {
+        if current && package_db.get_package_origin(&pkg_name).await? == Some(InstallOrigin::File) {
+            local.push(pkg_name);
+        }
+    }
+    Ok(local)
+}
+
 /// Update package from local file
 pub async fn update_from_file(
     pkg_path: &Path,
@@ -181,11 +313,9 @@ pub async fn update_from_file(
 ) -> Result<(), UpdaterError> {
     info!("package.updater.updating_from_file", pkg_path.display());
 
-    // Convert Path to string URL for fetcher
-    let url = format!("file://{}", pkg_path.display());
-
-    // Фетчер сам должен уметь извлекать имя пакета из метаданных
-    fetcher::fetch_and_install_parallel(&[url], package_db, direct).await?;
+    installer::install(pkg_path, package_db, direct)
+        .await
+        .map_err(|e| UpdaterError::Install(e.to_string()))?;
 
     info!(
         "package.updater.update_from_file_success",
@@ -194,16 +324,165 @@ pub async fn update_from_file(
     Ok(())
 }
 
+/// Updates a package from a local file in the isolated installation at
+/// `uhpm_root`
+///
+/// Same as [`update_from_file()`], with `uhpm_root` in place of the user's
+/// home.
+pub async fn update_from_file_at(
+    pkg_path: &Path,
+    package_db: &PackageDB,
+    uhpm_root: &Path,
+    direct: bool,
+) -> Result<(), UpdaterError> {
+    platform::with_root(uhpm_root, update_from_file(pkg_path, package_db, direct)).await
+}
+
+/// A recurring local time window during which updates are refused.
+///
+/// Written as `<days> <HH:MM>-<HH:MM>` where `<days>` is a weekday
+/// (`Mon`), a range (`Mon-Fri`) or `*` for every day. A window whose end
+/// is before its start wraps around midnight.
+#[derive(Debug, PartialEq)]
+struct BlackoutWindow {
+    /// Days the window applies to; empty means every day
+    days: Vec<Weekday>,
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl BlackoutWindow {
+    fn parse(spec: &str) -> Option<Self> {
+        let (days, times) = spec.trim().split_once(' ')?;
+        let (start, end) = times.trim().split_once('-')?;
+        let start = NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?;
+        let end = NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?;
+
+        let days = match days {
+            "*" => Vec::new(),
+            d => match d.split_once('-') {
+                Some((from, to)) => {
+                    let from: Weekday = from.parse().ok()?;
+                    let to: Weekday = to.parse().ok()?;
+                    let mut days = vec![from];
+                    let mut day = from;
+                    while day != to {
+                        day = day.succ();
+                        days.push(day);
+                    }
+                    days
+                }
+                None => vec![d.parse().ok()?],
+            },
+        };
+
+        Some(Self { days, start, end })
+    }
+
+    fn contains(&self, now: NaiveDateTime) -> bool {
+        let day_matches = self.days.is_empty() || self.days.contains(&now.weekday());
+        let time = now.time();
+        let time_matches = if self.start <= self.end {
+            time >= self.start && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        };
+        day_matches && time_matches
+    }
+}
+
+/// Returns the first blackout window covering `now`, if any
+///
+/// Malformed entries are logged and ignored.
+fn active_blackout(windows: &[String], now: NaiveDateTime) -> Option<String> {
+    windows
+        .iter()
+        .find_map(|spec| match BlackoutWindow::parse(spec) {
+            Some(window) if window.contains(now) => Some(spec.clone()),
+            Some(_) => None,
+            None => {
+                warn!("package.updater.invalid_blackout", spec);
+                None
+            }
+        })
+}
+
+/// Checks blackout windows and runs the package's `pre` update hook
+async fn before_update(
+    config: &Config,
+    pkg_name: &str,
+    package_db: &PackageDB,
+) -> Result<(), UpdaterError> {
+    if let Some(window) = active_blackout(&config.update.blackout, Local::now().naive_local()) {
+        warn!("package.updater.blackout_active", pkg_name, &window);
+        return Err(UpdaterError::Blackout(window));
+    }
+
+    if let Some(cmd) = config
+        .update
+        .hooks
+        .get(pkg_name)
+        .and_then(|h| h.pre.as_ref())
+    {
+        let version = package_db
+            .get_package_version(pkg_name)
+            .await?
+            .unwrap_or_default();
+        hooks::run_command(cmd, pkg_name, &version).await?;
+    }
+    Ok(())
+}
+
+/// Runs the `hooks/post-upgrade` script of the new version, then the
+/// package's `post` update hook
+async fn after_update(
+    config: &Config,
+    pkg_name: &str,
+    package_db: &PackageDB,
+) -> Result<(), UpdaterError> {
+    if let Some(version) = package_db.get_package_version(pkg_name).await?
+        && let Ok(parsed) = Version::parse(&version)
+    {
+        let root = get_pkg_path(pkg_name, parsed);
+        let hook = PackageHook::PostUpgrade;
+        if let Err(e) = hooks::run_package_hook(hook, &root, pkg_name, &version).await {
+            warn!("package.hooks.script_failed", hook.file_name(), pkg_name, e);
+        }
+    }
+    if let Some(cmd) = config
+        .update
+        .hooks
+        .get(pkg_name)
+        .and_then(|h| h.post.as_ref())
+    {
+        let version = package_db
+            .get_package_version(pkg_name)
+            .await?
+            .unwrap_or_default();
+        hooks::run_command(cmd, pkg_name, &version).await?;
+    }
+    Ok(())
+}
+
 /// Update a package to the latest version available in repositories.
+///
+/// Refused while a configured blackout window is active. The package's
+/// `pre` hook runs before downloading and aborts the update on failure;
+/// the `post` hook runs once the new version is installed and switched to
+/// via [`switcher::switch_version`].
 pub async fn update_package(
     pkg_name: &str,
     package_db: &PackageDB,
     direct: bool,
 ) -> Result<(), UpdaterError> {
     info!("package.updater.starting_update", pkg_name);
+    crash::set_package(pkg_name);
 
     // Check for updates
-    let download_url = check_for_update(pkg_name, package_db).await?;
+    let (download_url, new_version) = latest_update(pkg_name, package_db).await?;
+
+    let config = Config::load_or_default();
+    before_update(&config, pkg_name, package_db).await?;
 
     info!(
         "package.updater.downloading_update",
@@ -212,33 +491,388 @@ pub async fn update_package(
 
     // Download and install
     fetcher::fetch_and_install_parallel(&[download_url], package_db, direct).await?;
+    switcher::switch_version(pkg_name, new_version, package_db, direct).await?;
+    after_update(&config, pkg_name, package_db).await?;
+    usage::record(package_db, UsageKind::Update, pkg_name).await;
     info!("package.updater.update_success", pkg_name);
+    events::emit(ProgressEvent::UpdateFinished {
+        package: pkg_name.to_string(),
+    });
+
+    Ok(())
+}
+
+/// Updates a package in the isolated installation at `uhpm_root`
+///
+/// Same as [`update_package()`], with `uhpm_root` in place of the user's
+/// home.
+pub async fn update_package_at(
+    pkg_name: &str,
+    package_db: &PackageDB,
+    uhpm_root: &Path,
+    direct: bool,
+) -> Result<(), UpdaterError> {
+    platform::with_root(uhpm_root, update_package(pkg_name, package_db, direct)).await
+}
+
+/// Find the download URL of an exact package version in configured repositories
+///
+/// Repositories are tried by priority; a pinned package is only looked up
+/// in the repository it is pinned to.
+pub async fn find_version_url(
+    pkg_name: &str,
+    target_version: &Version,
+) -> Result<Option<String>, UpdaterError> {
+    let repos_path = platform::uhpm_home().join("repos.ron");
+    let policy = RepoPolicy::load();
+    let repos: RepoMap = parse_repos(&repos_path)?
+        .into_iter()
+        .filter(|(name, _)| policy.allows(pkg_name, name))
+        .collect();
+    let mut indexes = repo_indexes(&repos).await;
+    indexes.sort_by_key(|(name, _)| std::cmp::Reverse(policy.priority(name)));
+
+    for (_, repo_index) in indexes {
+        for entry in repo_index.find(pkg_name) {
+            if Version::parse(&entry.version).ok().as_ref() == Some(target_version) {
+                return Ok(Some(entry.url.clone()));
+            }
+        }
+    }
+
+    Ok(None)
+}
 
+/// Update (or downgrade) a package to a specific version.
+///
+/// If the version is already present in the package store it is simply
+/// switched to; otherwise it is downloaded from the first repository that
+/// carries it, installed, and then activated via [`switcher::switch_version`].
+/// A package pinned to other versions is refused with
+/// [`UpdaterError::Held`].
+pub async fn update_package_to(
+    pkg_name: &str,
+    target_version: &Version,
+    package_db: &PackageDB,
+    direct: bool,
+) -> Result<(), UpdaterError> {
+    info!(
+        "package.updater.starting_targeted_update",
+        pkg_name, target_version
+    );
+    crash::set_package(&format!("{}@{}", pkg_name, target_version));
+
+    let installed_version = match package_db.get_package_version(pkg_name).await? {
+        Some(v) => v,
+        None => {
+            warn!("package.updater.package_not_installed", pkg_name);
+            return Err(UpdaterError::NotFound(pkg_name.to_string()));
+        }
+    };
+
+    if Version::parse(&installed_version).ok().as_ref() == Some(target_version) {
+        info!("package.updater.already_up_to_date", pkg_name);
+        return Ok(());
+    }
+    check_pin(pkg_name, target_version, package_db).await?;
+
+    let config = Config::load_or_default();
+    before_update(&config, pkg_name, package_db).await?;
+
+    let target_str = target_version.to_string();
+    if package_db
+        .get_package_by_version(pkg_name, &target_str)
+        .await?
+        .is_none()
+    {
+        if let Some(archive) = archive_cache::lookup(pkg_name, &target_str) {
+            info!(
+                "package.updater.using_cached_archive",
+                pkg_name, &target_str
+            );
+            installer::install_transactional(
+                &archive,
+                package_db,
+                direct,
+                Provenance::Cache {
+                    archive: archive.to_string_lossy().to_string(),
+                },
+            )
+            .await
+            .map_err(|e| UpdaterError::Install(e.to_string()))?;
+        } else {
+            let download_url = find_version_url(pkg_name, target_version)
+                .await?
+                .ok_or_else(|| {
+                    UpdaterError::VersionNotFound(pkg_name.to_string(), target_str.clone())
+                })?;
+
+            info!(
+                "package.updater.downloading_update",
+                pkg_name, &download_url
+            );
+            fetcher::fetch_and_install_parallel(&[download_url], package_db, direct).await?;
+        }
+    } else {
+        info!(
+            "package.updater.version_already_in_store",
+            pkg_name, &target_str
+        );
+    }
+
+    switcher::switch_version(pkg_name, target_version.clone(), package_db, direct).await?;
+    after_update(&config, pkg_name, package_db).await?;
+    usage::record(package_db, UsageKind::Update, pkg_name).await;
+
+    info!("package.updater.update_success", pkg_name);
+    events::emit(ProgressEvent::UpdateFinished {
+        package: pkg_name.to_string(),
+    });
     Ok(())
 }
 
+/// Outcome of a bulk update run
+#[derive(Debug, Default)]
+pub struct UpdateSummary {
+    /// Packages that were updated
+    pub updated: Vec<String>,
+    /// Packages installed from local files, skipped because they have no repository
+    pub skipped_local: Vec<String>,
+    /// Packages whose update failed, with the error message
+    pub failed: Vec<(String, String)>,
+    /// Pinned packages with their pin, only updated within it
+    pub held: Vec<(String, String)>,
+}
+
 /// Update all packages that have newer versions available
-pub async fn update_all_packages(package_db: &PackageDB, direct: bool) -> Result<(), UpdaterError> {
+///
+/// Packages installed from local files are not looked up in repositories;
+/// they are reported in [`UpdateSummary::skipped_local`] instead, pinned
+/// packages in [`UpdateSummary::held`]. Each
+/// package keeps its recorded link mode unless `link` overrides it (see
+/// [`LinkMode::resolve`]). Unless `keep_going` is set, the run stops at the
+/// first failed update.
+pub async fn update_all_packages(
+    package_db: &PackageDB,
+    link: Option<LinkMode>,
+    keep_going: bool,
+) -> Result<UpdateSummary, UpdaterError> {
+    let skipped_local = local_packages(package_db).await?;
+    for pkg_name in &skipped_local {
+        info!("package.updater.skipped_local", pkg_name);
+    }
+    let held = held_packages(package_db).await?;
+
     let updates = check_all_updates(package_db).await?;
 
     if updates.is_empty() {
         info!("package.updater.no_updates_available");
-        return Ok(());
+        return Ok(UpdateSummary {
+            skipped_local,
+            held,
+            ..Default::default()
+        });
     }
 
     info!("package.updater.updates_found", updates.len());
+    let mut summary = apply_updates(package_db, updates, link, keep_going).await?;
+    summary.skipped_local = skipped_local;
+    summary.held = held;
+    Ok(summary)
+}
+
+/// Current packages that have a pin, with the pin
+pub async fn held_packages(package_db: &PackageDB) -> Result<Vec<(String, String)>, UpdaterError> {
+    let installed: Vec<String> = package_db
+        .list_packages()
+        .await?
+        .into_iter()
+        .filter(|(_, _, current)| *current)
+        .map(|(name, _, _)| name)
+        .collect();
+    let mut held = package_db.list_pins().await?;
+    held.retain(|(name, _)| installed.contains(name));
+    for (name, pin) in &held {
+        info!("package.updater.held", name, pin);
+    }
+    Ok(held)
+}
 
+/// Update packages to the versions found by [`check_all_updates`]
+///
+/// Takes `(name, current, new, repo)` entries, so callers can show or
+/// narrow the list first. Link modes and `keep_going` work as in
+/// [`update_all_packages`].
+pub async fn apply_updates(
+    package_db: &PackageDB,
+    updates: Vec<(String, String, String, String)>,
+    link: Option<LinkMode>,
+    keep_going: bool,
+) -> Result<UpdateSummary, UpdaterError> {
+    let mut summary = UpdateSummary::default();
     for (pkg_name, current_version, new_version, repo_name) in updates {
+        events::emit(ProgressEvent::UpdateAvailable {
+            package: pkg_name.clone(),
+            current: current_version.clone(),
+            latest: new_version.clone(),
+        });
         info!(
             "package.updater.updating_package",
             &pkg_name, &current_version, &new_version, &repo_name
         );
 
-        if let Err(e) = update_package(&pkg_name, package_db, direct).await {
-            warn!("package.updater.update_failed", &pkg_name, e);
+        let direct = LinkMode::resolve(package_db, &pkg_name, link)
+            .await?
+            .is_copy();
+        match update_package(&pkg_name, package_db, direct).await {
+            Ok(()) => summary.updated.push(pkg_name),
+            Err(e) => {
+                warn!("package.updater.update_failed", &pkg_name, &e);
+                summary.failed.push((pkg_name, e.to_string()));
+                if !keep_going {
+                    info!("package.updater.stopping");
+                    break;
+                }
+            }
         }
     }
 
     info!("package.updater.all_updates_completed");
-    Ok(())
+    Ok(summary)
+}
+
+/// Update locally installed packages from a directory of `.uhp` files
+///
+/// Every archive in `dir` is inspected; for each package that is currently
+/// installed from a local file, the newest archive with a higher version
+/// than the installed one, and allowed by its pin, is installed and
+/// switched to, keeping the link mode of the package unless `link`
+/// overrides it. Unless `keep_going` is set, the run stops at the first
+/// failed update.
+pub async fn update_local_from_dir(
+    dir: &Path,
+    package_db: &PackageDB,
+    link: Option<LinkMode>,
+    keep_going: bool,
+) -> Result<UpdateSummary, UpdaterError> {
+    info!("package.updater.scanning_local_dir", dir.display());
+
+    let local = local_packages(package_db).await?;
+    let pins = pins(package_db).await?;
+    let mut candidates: HashMap<String, (Version, PathBuf)> = HashMap::new();
+
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("uhp") {
+            continue;
+        }
+
+        let meta = match installer::read_meta(&path) {
+            Ok(meta) => meta,
+            Err(e) => {
+                warn!("package.updater.local_meta_failed", path.display(), e);
+                continue;
+            }
+        };
+
+        if !local.iter().any(|name| name == meta.name())
+            || !pin_allows(pins.get(meta.name()), meta.version())
+        {
+            continue;
+        }
+
+        let newer = candidates
+            .get(meta.name())
+            .is_none_or(|(version, _)| meta.version() > version);
+        if newer {
+            candidates.insert(meta.name().to_string(), (meta.version().clone(), path));
+        }
+    }
+
+    let mut summary = UpdateSummary::default();
+    for (pkg_name, (version, path)) in candidates {
+        let installed = package_db
+            .get_package_version(&pkg_name)
+            .await?
+            .and_then(|v| Version::parse(&v).ok());
+        if installed.is_some_and(|installed| version <= installed) {
+            continue;
+        }
+
+        info!("package.updater.updating_from_file", path.display());
+        let direct = LinkMode::resolve(package_db, &pkg_name, link)
+            .await?
+            .is_copy();
+        let result = match installer::install(&path, package_db, direct).await {
+            Ok(()) => switcher::switch_version(&pkg_name, version, package_db, direct)
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+
+        match result {
+            Ok(()) => summary.updated.push(pkg_name),
+            Err(e) => {
+                warn!("package.updater.update_failed", &pkg_name, &e);
+                summary.failed.push((pkg_name, e));
+                if !keep_going {
+                    info!("package.updater.stopping");
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .unwrap()
+            .and_hms_opt(h, min, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_blackout_parse() {
+        let window = BlackoutWindow::parse("Mon-Wed 09:00-17:00").unwrap();
+        assert_eq!(window.days, vec![Weekday::Mon, Weekday::Tue, Weekday::Wed]);
+        assert!(
+            BlackoutWindow::parse("* 22:00-06:00")
+                .unwrap()
+                .days
+                .is_empty()
+        );
+        assert!(BlackoutWindow::parse("Someday 09:00-17:00").is_none());
+        assert!(BlackoutWindow::parse("Mon 9-17").is_none());
+    }
+
+    #[test]
+    fn test_blackout_contains() {
+        // 2024-01-01 is a Monday
+        let window = BlackoutWindow::parse("Mon 09:00-17:00").unwrap();
+        assert!(window.contains(at(2024, 1, 1, 12, 0)));
+        assert!(!window.contains(at(2024, 1, 1, 17, 0)));
+        assert!(!window.contains(at(2024, 1, 2, 12, 0)));
+
+        let night = BlackoutWindow::parse("* 22:00-06:00").unwrap();
+        assert!(night.contains(at(2024, 1, 3, 23, 30)));
+        assert!(night.contains(at(2024, 1, 3, 5, 59)));
+        assert!(!night.contains(at(2024, 1, 3, 12, 0)));
+    }
+
+    #[test]
+    fn test_active_blackout() {
+        let windows = vec!["bogus".to_string(), "Mon 09:00-17:00".to_string()];
+        assert_eq!(
+            active_blackout(&windows, at(2024, 1, 1, 10, 0)),
+            Some("Mon 09:00-17:00".to_string())
+        );
+        assert_eq!(active_blackout(&windows, at(2024, 1, 1, 18, 0)), None);
+    }
 }
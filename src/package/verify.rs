@@ -0,0 +1,360 @@
+//! # Installation Verification
+//!
+//! `uhpm verify` checks every installed package version:
+//!
+//! - its directory in the package store exists;
+//! - every file recorded as installed by it exists, and recorded symlinks
+//!   point at the package file the `symlist` links there (for files not
+//!   from the symlist, such as systemd units, only that they lead
+//!   somewhere). Files installed as copies must still have the SHA-256
+//!   recorded for them in `installed_files`;
+//! - the files in its directory still match the manifest of SHA-256 sums
+//!   taken when it was installed. Versions installed before manifests were
+//!   recorded have none and are reported as such.
+//!
+//! Missing links and links pointing elsewhere can be repaired
+//! (`--repair`): they are created again, as symlinks or copies like at
+//! install time. Modified files are never overwritten; a modified package
+//! file needs the package to be reinstalled.
+
+use crate::db::PackageDB;
+use crate::error::UhpmError;
+use crate::package::{LinkMode, archive_cache, get_pkg_path};
+use crate::{platform, symlist};
+use semver::Version;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Something wrong with an installed package version
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// The package directory is gone
+    MissingDir { root: PathBuf },
+    /// A recorded link or copy is gone; `source` is the package file it
+    /// was made from, if known
+    MissingFile {
+        path: PathBuf,
+        source: Option<PathBuf>,
+        mode: LinkMode,
+    },
+    /// A recorded symlink points at `actual` instead of `expected`, or at
+    /// nothing
+    WrongTarget {
+        path: PathBuf,
+        expected: Option<PathBuf>,
+        actual: PathBuf,
+    },
+    /// A package file no longer matches the manifest, or an installed
+    /// copy the hash recorded for it
+    Modified { file: PathBuf },
+    /// A package file in the manifest is gone
+    Deleted { file: PathBuf },
+    /// No manifest was recorded for the version
+    NoManifest,
+}
+
+impl Problem {
+    /// Whether [`repair`] can fix the problem
+    pub fn is_repairable(&self) -> bool {
+        match self {
+            Problem::MissingFile { source, .. } => source.as_ref().is_some_and(|s| s.exists()),
+            Problem::WrongTarget { expected, .. } => expected.as_ref().is_some_and(|e| e.exists()),
+            _ => false,
+        }
+    }
+}
+
+/// Result of verifying a package version
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageCheck {
+    pub name: String,
+    pub version: String,
+    pub problems: Vec<Problem>,
+}
+
+impl PackageCheck {
+    /// Whether anything is wrong besides a missing manifest
+    pub fn is_broken(&self) -> bool {
+        self.problems.iter().any(|p| *p != Problem::NoManifest)
+    }
+}
+
+/// SHA-256 and size of the content of `path`, following symlinks
+///
+/// `None` for anything but a regular file.
+pub fn file_hash(path: &Path) -> io::Result<Option<(String, u64)>> {
+    let meta = fs::metadata(path)?;
+    if !meta.is_file() {
+        return Ok(None);
+    }
+    Ok(Some((archive_cache::sha256_file(path)?, meta.len())))
+}
+
+/// Hashes of the installed `files` for
+/// [`PackageDB::set_file_hashes`]; files that cannot be read are left out
+pub fn hash_files(files: &[PathBuf]) -> Vec<(String, String, u64)> {
+    files
+        .iter()
+        .filter_map(|file| {
+            let (sha256, size) = file_hash(file).ok().flatten()?;
+            Some((file.to_string_lossy().to_string(), sha256, size))
+        })
+        .collect()
+}
+
+/// Whether the file at `path` differs from the recorded `hash`
+///
+/// A missing file counts as unmodified.
+pub fn is_modified(path: &Path, hash: &(String, u64)) -> bool {
+    match fs::metadata(path) {
+        Ok(meta) if meta.is_file() => {
+            meta.len() != hash.1
+                || archive_cache::sha256_file(path).map_or(true, |sha256| sha256 != hash.0)
+        }
+        Ok(_) => true,
+        Err(_) => false,
+    }
+}
+
+/// SHA-256 of every regular file below `root`, by path relative to it
+pub fn manifest(root: &Path) -> io::Result<Vec<(String, String)>> {
+    let mut entries = Vec::new();
+    for entry in WalkDir::new(root).sort_by_file_name() {
+        let entry = entry.map_err(io::Error::other)?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let rel = entry
+            .path()
+            .strip_prefix(root)
+            .map_err(io::Error::other)?
+            .to_string_lossy()
+            .to_string();
+        entries.push((rel, archive_cache::sha256_file(entry.path())?));
+    }
+    Ok(entries)
+}
+
+/// Files of the package directory `root` that differ from `manifest`
+pub fn check_manifest(root: &Path, manifest: &[(String, String)]) -> Vec<Problem> {
+    manifest
+        .iter()
+        .filter_map(|(rel, sha256)| {
+            let file = root.join(rel);
+            match archive_cache::sha256_file(&file) {
+                Ok(actual) if actual == *sha256 => None,
+                Ok(_) => Some(Problem::Modified { file }),
+                Err(_) => Some(Problem::Deleted { file }),
+            }
+        })
+        .collect()
+}
+
+/// Problems of the recorded files `installed` of the package at `root`
+///
+/// `current` tells whether the version is the current one, which owns the
+/// unversioned links of the symlist; of other versions only the versioned
+/// links are checked. `hashes` are the recorded hashes of the files.
+pub fn check_links(
+    root: &Path,
+    current: bool,
+    installed: &[(String, Option<LinkMode>)],
+    hashes: &BTreeMap<String, (String, u64)>,
+) -> Vec<Problem> {
+    let expected: HashMap<PathBuf, PathBuf> = symlist::load_links(&root.join("symlist"), root)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|link| current || link.versioned)
+        .map(|link| (link.target, link.source))
+        .collect();
+
+    let mut problems = Vec::new();
+    for (file, mode) in installed {
+        let path = PathBuf::from(file);
+        let mode = mode.unwrap_or(LinkMode::Symlink);
+        let source = expected.get(&path).cloned();
+        if !current && source.is_none() {
+            continue;
+        }
+        let Ok(meta) = fs::symlink_metadata(&path) else {
+            problems.push(Problem::MissingFile { path, source, mode });
+            continue;
+        };
+        if mode == LinkMode::Copy {
+            if hashes
+                .get(file)
+                .is_some_and(|hash| is_modified(&path, hash))
+            {
+                problems.push(Problem::Modified { file: path });
+            }
+            continue;
+        }
+        if !meta.file_type().is_symlink() {
+            continue;
+        }
+        let Ok(actual) = fs::read_link(&path) else {
+            continue;
+        };
+        let misdirected = source.as_ref().is_some_and(|source| actual != *source);
+        if misdirected || !path.exists() {
+            problems.push(Problem::WrongTarget {
+                path,
+                expected: source,
+                actual,
+            });
+        }
+    }
+    problems
+}
+
+/// Verifies every installed package version, by name and version
+pub async fn verify(db: &PackageDB) -> Result<Vec<PackageCheck>, UhpmError> {
+    let mut packages = db.list_packages().await?;
+    packages.sort();
+
+    let mut checks = Vec::new();
+    for (name, version, current) in packages {
+        let parsed = Version::parse(&version).map_err(|e| UhpmError::Parse(e.to_string()))?;
+        let root = get_pkg_path(&name, parsed);
+        let mut problems = Vec::new();
+        if root.is_dir() {
+            let installed = db.get_installed_files_with_mode(&name, &version).await?;
+            let hashes = db.get_file_hashes(&name, &version).await?;
+            problems.extend(check_links(&root, current, &installed, &hashes));
+            let manifest = db.get_package_manifest(&name, &version).await?;
+            if manifest.is_empty() {
+                problems.push(Problem::NoManifest);
+            } else {
+                problems.extend(check_manifest(&root, &manifest));
+            }
+        } else {
+            problems.push(Problem::MissingDir { root });
+        }
+        checks.push(PackageCheck {
+            name,
+            version,
+            problems,
+        });
+    }
+    Ok(checks)
+}
+
+/// Recreates a missing or misdirected link
+///
+/// Only [repairable](Problem::is_repairable) problems are fixed; returns
+/// whether anything was done. A file that is not a symlink is never
+/// replaced.
+pub fn repair(problem: &Problem) -> io::Result<bool> {
+    if !problem.is_repairable() {
+        return Ok(false);
+    }
+    match problem {
+        Problem::MissingFile {
+            path,
+            source: Some(source),
+            mode,
+        } => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            match mode {
+                LinkMode::Copy => fs::copy(source, path).map(|_| ()),
+                LinkMode::Symlink => platform::symlink(source, path),
+            }?;
+            Ok(true)
+        }
+        Problem::WrongTarget {
+            path,
+            expected: Some(expected),
+            ..
+        } => {
+            fs::remove_file(path)?;
+            platform::symlink(expected, path)?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_and_repair_links_and_manifest() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().join("tool-1.0.0");
+        let bin = tmp.path().join("bin");
+        fs::create_dir_all(root.join("bin")).unwrap();
+        fs::write(root.join("bin/tool"), "v1").unwrap();
+        fs::write(root.join("bin/helper"), "v1").unwrap();
+        fs::write(
+            root.join("symlist"),
+            format!("bin/tool {0}/tool\nbin/helper {0}/helper\n", bin.display()),
+        )
+        .unwrap();
+        let manifest = manifest(&root).unwrap();
+        assert_eq!(manifest.len(), 3);
+
+        fs::create_dir_all(&bin).unwrap();
+        std::os::unix::fs::symlink(root.join("bin/helper"), bin.join("tool")).unwrap();
+        let installed: Vec<(String, Option<LinkMode>)> = ["tool", "helper"]
+            .iter()
+            .map(|f| (bin.join(f).to_string_lossy().to_string(), None))
+            .collect();
+
+        let problems = check_links(&root, true, &installed, &BTreeMap::new());
+        assert_eq!(
+            problems,
+            [
+                Problem::WrongTarget {
+                    path: bin.join("tool"),
+                    expected: Some(root.join("bin/tool")),
+                    actual: root.join("bin/helper"),
+                },
+                Problem::MissingFile {
+                    path: bin.join("helper"),
+                    source: Some(root.join("bin/helper")),
+                    mode: LinkMode::Symlink,
+                },
+            ]
+        );
+        for problem in &problems {
+            assert!(repair(problem).unwrap());
+        }
+        assert!(check_links(&root, true, &installed, &BTreeMap::new()).is_empty());
+
+        let copy = tmp.path().join("copy");
+        fs::write(&copy, "v1").unwrap();
+        let hashes: BTreeMap<_, _> = hash_files(std::slice::from_ref(&copy))
+            .into_iter()
+            .map(|(path, sha256, size)| (path, (sha256, size)))
+            .collect();
+        let copied = [(copy.to_string_lossy().to_string(), Some(LinkMode::Copy))];
+        assert!(check_links(&root, true, &copied, &hashes).is_empty());
+        fs::write(&copy, "v2").unwrap();
+        assert_eq!(
+            check_links(&root, true, &copied, &hashes),
+            [Problem::Modified { file: copy }]
+        );
+
+        assert!(check_manifest(&root, &manifest).is_empty());
+        fs::write(root.join("bin/tool"), "changed").unwrap();
+        fs::remove_file(root.join("bin/helper")).unwrap();
+        assert_eq!(
+            check_manifest(&root, &manifest),
+            [
+                Problem::Deleted {
+                    file: root.join("bin/helper")
+                },
+                Problem::Modified {
+                    file: root.join("bin/tool")
+                },
+            ]
+        );
+    }
+}
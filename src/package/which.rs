@@ -0,0 +1,273 @@
+//! # Command Resolution
+//!
+//! Answers "where does this command come from?" for commands on `PATH`.
+//!
+//! A command is considered uhpm-managed when its first `PATH` match is a
+//! symlink into `~/.uhpm/packages/<name>-<version>/`, or (for packages
+//! installed with `--direct`) when the file is recorded in the database
+//! as installed by a package. The report also lists later `PATH` matches
+//! that are shadowed by the first one.
+//!
+//! [`owns`] answers the same question for any path, e.g. a dangling link
+//! left in `~/.local/bin`: the path is looked up in the record of
+//! installed files as given (made absolute), as resolved through symlinks
+//! and as the link points, and otherwise attributed to the package
+//! directory it points into.
+
+use crate::db::PackageDB;
+use crate::error::UhpmError;
+use crate::package::packages_dir;
+use crate::platform;
+use semver::Version;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Package that provides a command
+#[derive(Debug, Clone, PartialEq)]
+pub struct Provider {
+    pub package: String,
+    pub version: String,
+    /// Version marked as current in the database, if the package is installed
+    pub current_version: Option<String>,
+}
+
+impl Provider {
+    /// Returns `true` if the link points at the version the database considers current
+    pub fn is_current(&self) -> bool {
+        self.current_version.as_deref() == Some(self.version.as_str())
+    }
+}
+
+/// Result of resolving a command
+#[derive(Debug, Clone)]
+pub struct WhichReport {
+    /// First match on `PATH`
+    pub path: PathBuf,
+    /// Fully resolved target of `path`
+    pub target: PathBuf,
+    /// Package providing the command, if uhpm-managed
+    pub provider: Option<Provider>,
+    /// Later `PATH` matches hidden by `path`
+    pub shadowed: Vec<PathBuf>,
+}
+
+/// Result of looking up the owner of a path
+#[derive(Debug, Clone)]
+pub struct OwnsReport {
+    /// The path asked about, made absolute
+    pub path: PathBuf,
+    /// Where `path` leads if it is a symlink, resolved as far as it exists
+    pub target: Option<PathBuf>,
+    /// Whether `path` is a symlink to nothing
+    pub dangling: bool,
+    /// Package owning `path`
+    pub provider: Option<Provider>,
+    /// Whether the database records `path`, or the file it leads to, as
+    /// installed by the provider, rather than it only pointing into the
+    /// provider's directory
+    pub recorded: bool,
+}
+
+/// Returns every executable named `command` in `PATH` order
+pub fn find_on_path(command: &str) -> Vec<PathBuf> {
+    let Some(path) = std::env::var_os("PATH") else {
+        return Vec::new();
+    };
+
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(command))
+        .filter(|candidate| is_executable(candidate))
+        .collect()
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|m| platform::is_executable(path, &m))
+}
+
+/// Splits a package directory name such as `foo-bar-1.2.3-rc.1` into name and version
+pub fn split_package_dir(dir_name: &str) -> Option<(String, Version)> {
+    dir_name.match_indices('-').find_map(|(i, _)| {
+        Version::parse(&dir_name[i + 1..])
+            .ok()
+            .map(|v| (dir_name[..i].to_string(), v))
+    })
+}
+
+/// Determines which package directory in `store` a resolved path belongs to
+pub fn package_of(target: &Path, store: &Path) -> Option<(String, Version)> {
+    let rel = target.strip_prefix(store).ok()?;
+    let dir = rel.components().next()?.as_os_str().to_str()?;
+    split_package_dir(dir)
+}
+
+/// Removes `.` and `..` components from `path` without touching the
+/// filesystem
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+async fn provider(db: &PackageDB, package: String, version: String) -> Result<Provider, UhpmError> {
+    let current_version = db.get_package_version(&package).await?;
+    Ok(Provider {
+        package,
+        version,
+        current_version,
+    })
+}
+
+/// Finds the package owning `path`, which may be relative or a dangling
+/// symlink
+pub async fn owns(path: &Path, db: &PackageDB) -> Result<OwnsReport, UhpmError> {
+    let path = normalize(&std::path::absolute(path)?);
+    let link = fs::read_link(&path).ok().map(|target| match path.parent() {
+        Some(dir) => normalize(&dir.join(target)),
+        None => target,
+    });
+    let resolved = fs::canonicalize(&path).ok().filter(|r| *r != path);
+    let dangling = link.is_some() && !path.exists();
+
+    let mut owner = None;
+    for candidate in [Some(&path), resolved.as_ref(), link.as_ref()]
+        .into_iter()
+        .flatten()
+    {
+        owner = db.find_file_owner(&candidate.to_string_lossy()).await?;
+        if owner.is_some() {
+            break;
+        }
+    }
+    let recorded = owner.is_some();
+    if owner.is_none() {
+        let stores = [
+            packages_dir(),
+            fs::canonicalize(packages_dir()).unwrap_or_else(|_| packages_dir()),
+        ];
+        owner = [link.as_ref(), resolved.as_ref(), Some(&path)]
+            .into_iter()
+            .flatten()
+            .find_map(|p| stores.iter().find_map(|store| package_of(p, store)))
+            .map(|(name, version)| (name, version.to_string()));
+    }
+
+    let provider = match owner {
+        Some((package, version)) => Some(provider(db, package, version).await?),
+        None => None,
+    };
+    Ok(OwnsReport {
+        path,
+        target: resolved.or(link),
+        dangling,
+        provider,
+        recorded,
+    })
+}
+
+/// Resolves a command on `PATH` to the package providing it
+///
+/// Returns `Ok(None)` if the command is not on `PATH` at all.
+pub async fn which(command: &str, db: &PackageDB) -> Result<Option<WhichReport>, UhpmError> {
+    let mut matches = find_on_path(command).into_iter();
+    let Some(path) = matches.next() else {
+        return Ok(None);
+    };
+    let shadowed = matches.collect();
+    let target = fs::canonicalize(&path)?;
+
+    let store = fs::canonicalize(packages_dir()).unwrap_or_else(|_| packages_dir());
+    let owner = match package_of(&target, &store) {
+        Some((name, version)) => Some((name, version.to_string())),
+        None => db.find_file_owner(&path.to_string_lossy()).await?,
+    };
+
+    let provider = match owner {
+        Some((package, version)) => Some(provider(db, package, version).await?),
+        None => None,
+    };
+
+    Ok(Some(WhichReport {
+        path,
+        target,
+        provider,
+        shadowed,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_package_dir() {
+        assert_eq!(
+            split_package_dir("foo-bar-1.2.3-rc.1"),
+            Some(("foo-bar".to_string(), Version::parse("1.2.3-rc.1").unwrap()))
+        );
+        assert_eq!(split_package_dir("nover"), None);
+    }
+
+    #[tokio::test]
+    async fn test_owns_resolves_links() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = PackageDB::new(&tmp.path().join("packages.db"))
+            .unwrap()
+            .init()
+            .await
+            .unwrap();
+        let file = tmp.path().join("share/tool.conf");
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, "").unwrap();
+        db.set_installed_files(
+            "tool",
+            "1.0.0",
+            &[file.to_string_lossy().to_string()],
+            crate::package::LinkMode::Copy,
+        )
+        .await
+        .unwrap();
+
+        let report = owns(&tmp.path().join("share/../share/tool.conf"), &db)
+            .await
+            .unwrap();
+        assert_eq!(report.path, file);
+        assert!(report.recorded);
+        assert_eq!(report.provider.unwrap().package, "tool");
+
+        #[cfg(unix)]
+        {
+            let link = tmp.path().join("tool.conf");
+            std::os::unix::fs::symlink("share/tool.conf", &link).unwrap();
+            let report = owns(&link, &db).await.unwrap();
+            assert_eq!(report.target.as_deref(), Some(file.as_path()));
+            assert_eq!(report.provider.unwrap().version, "1.0.0");
+
+            fs::remove_file(&file).unwrap();
+            let report = owns(&link, &db).await.unwrap();
+            assert!(report.dangling);
+            assert_eq!(report.provider.unwrap().package, "tool");
+
+            let stray = tmp.path().join("stray");
+            std::os::unix::fs::symlink("nowhere", &stray).unwrap();
+            assert!(owns(&stray, &db).await.unwrap().provider.is_none());
+        }
+    }
+
+    #[test]
+    fn test_package_of() {
+        let store = Path::new("/home/u/.uhpm/packages");
+        assert_eq!(
+            package_of(&store.join("tool-0.4.0/bin/tool"), store),
+            Some(("tool".to_string(), Version::parse("0.4.0").unwrap()))
+        );
+        assert_eq!(package_of(Path::new("/usr/bin/tool"), store), None);
+    }
+}
@@ -0,0 +1,402 @@
+//! # Platform Layer
+//!
+//! Everything that differs between Unix and Windows lives here, so the
+//! rest of the crate stays free of `cfg` attributes.
+//!
+//! | Concern          | Unix                   | Windows                                  |
+//! |------------------|------------------------|------------------------------------------|
+//! | uhpm home        | `~/.uhpm`              | `%LOCALAPPDATA%\uhpm`                    |
+//! | Links            | symlinks               | `symlink_file`, junctions for dirs       |
+//! | Without links    | —                      | files are copied (as with `--direct`)    |
+//! | Hook commands    | `sh -c`                | `powershell -NoProfile -Command`         |
+//! | Executables      | any `x` permission bit | extensions listed in `PATHEXT`           |
+//!
+//! Creating symlinks on Windows needs Developer Mode or the
+//! `SeCreateSymbolicLinkPrivilege`; [`must_copy()`] probes this once so the
+//! installer can fall back to copying and record the package accordingly.
+//!
+//! macOS follows its own conventions on top of the Unix ones:
+//! - data lives in `~/Library/Application Support/uhpm`, unless an existing
+//!   `~/.uhpm` is found, and `$XDG_DATA_HOME` defaults to
+//!   `~/Library/Application Support`;
+//! - fonts go to `~/Library/Fonts`;
+//! - `.app` bundles are linked into `~/Applications`;
+//! - `com.apple.quarantine` is removed from installed files so Gatekeeper
+//!   does not block them.
+//!
+//! An alternate [root](root) (`--root <dir>` or `$UHPM_ROOT`) replaces the
+//! uhpm home on every platform and stands in for the home directory when
+//! link targets are expanded, so that it holds a complete, isolated
+//! installation: `<root>/packages.db`, `<root>/packages`,
+//! `<root>/.local/bin` and so on.
+//!
+//! Tests and embedders pass the root explicitly instead of changing it
+//! for the whole process:
+//! [`install_at`](crate::package::installer::install_at),
+//! [`remove_at`](crate::package::remover::remove_at),
+//! [`switch_version_at`](crate::package::switcher::switch_version_at) and
+//! the `_at` update functions take it as a parameter, so isolated
+//! installations can run side by side without touching `$HOME`. They
+//! apply it to the operation with [`with_root`]; work handed to other
+//! tasks or threads carries it along through [`in_current_root`] and
+//! [`in_current_root_sync`].
+//!
+//! Within that home, a named [profile](crate::config::Profiles) other than
+//! `default` moves the data to `profiles/<name>`; link targets keep
+//! pointing into the home directory.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+static ROOT: once_cell::sync::OnceCell<PathBuf> = once_cell::sync::OnceCell::new();
+
+tokio::task_local! {
+    static SCOPED_ROOT: PathBuf;
+}
+
+/// Runs `fut` with `dir` as the alternate root
+///
+/// Takes precedence over [`set_root`] and `$UHPM_ROOT` within `fut` only;
+/// other tasks are not affected.
+pub async fn with_root<F: Future>(dir: &Path, fut: F) -> F::Output {
+    match std::path::absolute(dir) {
+        Ok(dir) => SCOPED_ROOT.scope(dir, fut).await,
+        Err(_) => SCOPED_ROOT.scope(dir.to_path_buf(), fut).await,
+    }
+}
+
+/// Wraps `fut` so it runs with the root of the calling task, for futures
+/// passed to `tokio::spawn`
+pub fn in_current_root<F: Future>(fut: F) -> impl Future<Output = F::Output> {
+    let root = SCOPED_ROOT.try_with(Clone::clone).ok();
+    async move {
+        match root {
+            Some(root) => SCOPED_ROOT.scope(root, fut).await,
+            None => fut.await,
+        }
+    }
+}
+
+/// Wraps `f` so it runs with the root of the calling task, for closures
+/// run on other threads
+pub fn in_current_root_sync<T>(f: impl FnOnce() -> T) -> impl FnOnce() -> T {
+    let root = SCOPED_ROOT.try_with(Clone::clone).ok();
+    move || match root {
+        Some(root) => SCOPED_ROOT.sync_scope(root, f),
+        None => f(),
+    }
+}
+
+/// Uses `dir` as the alternate root for the rest of the process
+///
+/// Takes precedence over `$UHPM_ROOT`; only the first call has an effect.
+pub fn set_root(dir: &Path) -> io::Result<()> {
+    let _ = ROOT.set(std::path::absolute(dir)?);
+    Ok(())
+}
+
+/// The alternate root set with [`with_root`], [`set_root`] or
+/// `$UHPM_ROOT`, if any
+pub fn root() -> Option<PathBuf> {
+    if let Ok(root) = SCOPED_ROOT.try_with(Clone::clone) {
+        return Some(root);
+    }
+    if let Some(root) = ROOT.get() {
+        return Some(root.clone());
+    }
+    std::env::var_os("UHPM_ROOT")
+        .filter(|v| !v.is_empty())
+        .and_then(|v| std::path::absolute(v).ok())
+}
+
+/// Home directory link targets are expanded against: the alternate root
+/// if one is set, the user's home otherwise
+pub fn link_home() -> Option<PathBuf> {
+    root().or_else(dirs::home_dir)
+}
+
+/// Root of the uhpm data directory (database, packages, caches, config)
+/// of the active profile
+///
+/// This is the [`base_home`] unless a profile other than `default` is
+/// active.
+pub fn uhpm_home() -> PathBuf {
+    let base = base_home();
+    match crate::config::Profiles::load(&base).active {
+        Some(name) => Some(crate::config::profile_home(&base, &name))
+            .filter(|home| home.is_dir())
+            .unwrap_or(base),
+        None => base,
+    }
+}
+
+/// The uhpm home without a profile applied, which also holds the profiles
+///
+/// This is the alternate [root] if one is set.
+pub fn base_home() -> PathBuf {
+    if let Some(root) = root() {
+        return root;
+    }
+    #[cfg(windows)]
+    {
+        dirs::data_local_dir().unwrap().join("uhpm")
+    }
+    #[cfg(target_os = "macos")]
+    {
+        // Installations made before the macOS layout keep their location
+        let legacy = dirs::home_dir().unwrap().join(".uhpm");
+        if legacy.exists() {
+            legacy
+        } else {
+            dirs::data_dir().unwrap().join("uhpm")
+        }
+    }
+    #[cfg(not(any(windows, target_os = "macos")))]
+    {
+        dirs::home_dir().unwrap().join(".uhpm")
+    }
+}
+
+/// Default user data directory, used when `$XDG_DATA_HOME` is unset
+///
+/// `<root>/.local/share` with an alternate root.
+pub fn default_data_home() -> PathBuf {
+    if let Some(root) = root() {
+        return root.join(".local/share");
+    }
+    #[cfg(target_os = "macos")]
+    {
+        dirs::data_dir().unwrap()
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        dirs::home_dir().unwrap().join(".local/share")
+    }
+}
+
+/// User font directory
+pub fn fonts_dir() -> PathBuf {
+    #[cfg(target_os = "macos")]
+    {
+        dirs::font_dir().unwrap()
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        std::env::var("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| default_data_home())
+            .join("fonts")
+    }
+}
+
+/// Directory `.app` bundles are linked into, on platforms that have one
+pub fn applications_dir() -> Option<PathBuf> {
+    #[cfg(target_os = "macos")]
+    {
+        dirs::home_dir().map(|home| home.join("Applications"))
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        None
+    }
+}
+
+/// Removes the quarantine attribute from `path` and everything below it,
+/// so Gatekeeper does not block downloaded executables
+///
+/// Does nothing outside macOS.
+pub fn clear_quarantine(path: &Path) -> io::Result<()> {
+    #[cfg(target_os = "macos")]
+    {
+        // xattr fails when no file carries the attribute, which is fine
+        let output = std::process::Command::new("xattr")
+            .args(["-d", "-r", "com.apple.quarantine"])
+            .arg(path)
+            .output()?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if output.status.success() || stderr.trim().is_empty() || stderr.contains("No such xattr") {
+            Ok(())
+        } else {
+            Err(io::Error::other(stderr.trim().to_string()))
+        }
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        let _ = path;
+        Ok(())
+    }
+}
+
+/// Creates a link at `dst` pointing to `src`
+///
+/// On Windows, directories are linked with a junction when a directory
+/// symlink cannot be created.
+pub fn symlink(src: &Path, dst: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(src, dst)
+    }
+    #[cfg(windows)]
+    {
+        let resolved = dst
+            .parent()
+            .map_or_else(|| src.to_path_buf(), |p| p.join(src));
+        if resolved.is_dir() {
+            std::os::windows::fs::symlink_dir(src, dst).or_else(|_| junction(&resolved, dst))
+        } else {
+            std::os::windows::fs::symlink_file(src, dst)
+        }
+    }
+}
+
+#[cfg(windows)]
+fn junction(src: &Path, dst: &Path) -> io::Result<()> {
+    let status = std::process::Command::new("cmd")
+        .arg("/C")
+        .arg("mklink")
+        .arg("/J")
+        .arg(dst)
+        .arg(src)
+        .stdout(std::process::Stdio::null())
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "mklink /J {} failed",
+            dst.display()
+        )))
+    }
+}
+
+/// Whether links cannot be created and files have to be copied instead
+pub fn must_copy() -> bool {
+    #[cfg(unix)]
+    {
+        false
+    }
+    #[cfg(windows)]
+    {
+        static MUST_COPY: once_cell::sync::Lazy<bool> = once_cell::sync::Lazy::new(|| {
+            let dir = std::env::temp_dir().join(format!("uhpm-link-probe-{}", std::process::id()));
+            let _ = fs::create_dir_all(&dir);
+            let target = dir.join("target");
+            let _ = fs::write(&target, b"");
+            let supported = std::os::windows::fs::symlink_file(&target, dir.join("link")).is_ok();
+            let _ = fs::remove_dir_all(&dir);
+            !supported
+        });
+        *MUST_COPY
+    }
+}
+
+/// Whether a file can be executed
+pub fn is_executable(path: &Path, meta: &fs::Metadata) -> bool {
+    if !meta.is_file() {
+        return false;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = path;
+        meta.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(windows)]
+    {
+        let pathext = std::env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".into());
+        path.extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|ext| {
+                pathext
+                    .split(';')
+                    .any(|p| p.trim_start_matches('.').eq_ignore_ascii_case(ext))
+            })
+    }
+}
+
+/// Whether executables are ELF files run through a dynamic loader, see
+/// [`elf`](crate::package::elf)
+pub fn loads_elf() -> bool {
+    cfg!(all(unix, not(target_os = "macos")))
+}
+
+/// Builds a command that runs `cmd` through the platform shell
+pub fn shell_command(cmd: &str) -> tokio::process::Command {
+    #[cfg(unix)]
+    {
+        let mut command = tokio::process::Command::new("sh");
+        command.arg("-c").arg(cmd);
+        command
+    }
+    #[cfg(windows)]
+    {
+        let mut command = tokio::process::Command::new("powershell");
+        command.arg("-NoProfile").arg("-Command").arg(cmd);
+        command
+    }
+}
+
+/// Whether a process with the given id is running
+pub fn process_alive(pid: u32) -> bool {
+    if pid == std::process::id() {
+        return true;
+    }
+    #[cfg(unix)]
+    {
+        // Signal 0 only checks that the process exists
+        unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+    }
+    #[cfg(windows)]
+    {
+        std::process::Command::new("tasklist")
+            .args(["/FI", &format!("PID eq {}", pid), "/NH"])
+            .output()
+            .is_ok_and(|out| String::from_utf8_lossy(&out.stdout).contains(&pid.to_string()))
+    }
+}
+
+/// Replaces the current process with `command`
+///
+/// Windows has no `exec`: the command runs as a child and the current
+/// process exits with its status. Only returns on failure.
+pub fn exec(command: &mut std::process::Command) -> io::Error {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.exec()
+    }
+    #[cfg(windows)]
+    {
+        match command.status() {
+            Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+            Err(e) => e,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scoped_root_reaches_spawned_work() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        let (home, spawned, threaded) = with_root(&root, async {
+            let spawned = tokio::spawn(in_current_root(async { uhpm_home() }));
+            let threaded = std::thread::spawn(in_current_root_sync(base_home));
+            (
+                uhpm_home(),
+                spawned.await.unwrap(),
+                threaded.join().unwrap(),
+            )
+        })
+        .await;
+        assert_eq!(home, root);
+        assert_eq!(spawned, root);
+        assert_eq!(threaded, root);
+        // Outside the scope nothing is set
+        assert!(SCOPED_ROOT.try_with(|_| ()).is_err());
+    }
+}
@@ -0,0 +1,535 @@
+//! # Package Queries
+//!
+//! `uhpm query` selects fields of the installed package versions and
+//! filters them, for reports that would otherwise need direct access to
+//! the package database:
+//!
+//! ```text
+//! uhpm query 'name,version,size where current=1 and size>10MB' --json
+//! ```
+//!
+//! A query is a comma-separated list of [fields](Field) (`*` for all of
+//! them), optionally followed by `where` and conditions joined with `and`.
+//! A condition compares a field with a value using `=`, `!=`, `<`, `<=`,
+//! `>`, `>=` or `~` (shell-style wildcards, as in `uhpm remove`). Values
+//! containing spaces or operator characters are quoted with `'` or `"`.
+//!
+//! `version` compares as a semantic version, `files` and `size` as numbers
+//! (sizes take the units `KB`, `MB`, `GB`, `TB` and `KiB`, `MiB`, `GiB`,
+//! `TiB`), `current` as a boolean (`1`/`0` or `true`/`false`) and the
+//! other fields as text. Timestamps are RFC 3339, so a prefix such as
+//! `installed_at>=2025-06` works. A field without a value (e.g. `last_run`
+//! of a version never run) only matches `!=`.
+
+use crate::db::PackageDB;
+use crate::error::UhpmError;
+use crate::package::{packages_dir, pattern};
+use semver::Version;
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::fmt;
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// A field of an installed package version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Name,
+    Version,
+    /// Whether the version is the current one
+    Current,
+    Author,
+    /// `repo` or `file`
+    Origin,
+    /// `explicit` or `dependency`
+    Reason,
+    InstalledAt,
+    LastRun,
+    /// Number of files installed outside the package directory
+    Files,
+    /// Size of the package directory in bytes
+    Size,
+}
+
+impl Field {
+    /// All fields, in the order `*` selects them
+    pub const ALL: [Field; 10] = [
+        Field::Name,
+        Field::Version,
+        Field::Current,
+        Field::Author,
+        Field::Origin,
+        Field::Reason,
+        Field::InstalledAt,
+        Field::LastRun,
+        Field::Files,
+        Field::Size,
+    ];
+
+    /// Name of the field in queries
+    pub fn name(self) -> &'static str {
+        match self {
+            Field::Name => "name",
+            Field::Version => "version",
+            Field::Current => "current",
+            Field::Author => "author",
+            Field::Origin => "origin",
+            Field::Reason => "reason",
+            Field::InstalledAt => "installed_at",
+            Field::LastRun => "last_run",
+            Field::Files => "files",
+            Field::Size => "size",
+        }
+    }
+
+    /// The field called `name`, case-insensitively
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|f| f.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Comparison operator of a condition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    /// Wildcard match
+    Glob,
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Glob => "~",
+        })
+    }
+}
+
+/// Value a condition compares with, parsed for its field
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Text(String),
+    Number(u64),
+    Bool(bool),
+    Version(Version),
+}
+
+/// A condition of the `where` clause
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub field: Field,
+    pub op: Op,
+    /// The value as written
+    pub value: String,
+    operand: Operand,
+}
+
+impl Condition {
+    /// Builds a condition, checking that `value` suits the field
+    pub fn new(field: Field, op: Op, value: String) -> Result<Self, UhpmError> {
+        let invalid = |kind: &str| {
+            UhpmError::Validation(format!(
+                "`{}` needs {}, got `{}`",
+                field.name(),
+                kind,
+                value
+            ))
+        };
+        let operand = match field {
+            _ if op == Op::Glob => Operand::Text(value.clone()),
+            Field::Files => Operand::Number(value.parse().map_err(|_| invalid("a number"))?),
+            Field::Size => Operand::Number(parse_size(&value).ok_or_else(|| invalid("a size"))?),
+            Field::Current => Operand::Bool(match value.to_ascii_lowercase().as_str() {
+                "1" | "true" | "yes" => true,
+                "0" | "false" | "no" => false,
+                _ => return Err(invalid("1 or 0")),
+            }),
+            Field::Version => match Version::parse(&value) {
+                Ok(version) => Operand::Version(version),
+                Err(_) => Operand::Text(value.clone()),
+            },
+            _ => Operand::Text(value.clone()),
+        };
+        Ok(Self {
+            field,
+            op,
+            value,
+            operand,
+        })
+    }
+
+    fn ordering(&self, value: &Value) -> Option<Ordering> {
+        match (&self.operand, value) {
+            (Operand::Number(n), Value::Number(v)) => v.as_u64().map(|v| v.cmp(n)),
+            (Operand::Bool(b), Value::Bool(v)) => Some(v.cmp(b)),
+            (Operand::Version(version), Value::String(v)) => Some(match Version::parse(v) {
+                Ok(v) => v.cmp(version),
+                Err(_) => v.as_str().cmp(self.value.as_str()),
+            }),
+            (_, Value::String(v)) => Some(v.as_str().cmp(self.value.as_str())),
+            _ => None,
+        }
+    }
+
+    /// Whether a field value satisfies the condition
+    pub fn matches(&self, value: &Value) -> bool {
+        if self.op == Op::Glob {
+            return match value {
+                Value::Null => false,
+                Value::String(s) => pattern::matches(&self.value, s),
+                other => pattern::matches(&self.value, &other.to_string()),
+            };
+        }
+        match self.ordering(value) {
+            None => self.op == Op::Ne,
+            Some(ordering) => match self.op {
+                Op::Eq => ordering.is_eq(),
+                Op::Ne => ordering.is_ne(),
+                Op::Lt => ordering.is_lt(),
+                Op::Le => ordering.is_le(),
+                Op::Gt => ordering.is_gt(),
+                Op::Ge => ordering.is_ge(),
+                Op::Glob => false,
+            },
+        }
+    }
+}
+
+/// A parsed query
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub fields: Vec<Field>,
+    pub conditions: Vec<Condition>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Op(Op),
+    Comma,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(w) => write!(f, "`{}`", w),
+            Token::Quoted(q) => write!(f, "'{}'", q),
+            Token::Op(op) => write!(f, "`{}`", op),
+            Token::Comma => f.write_str("`,`"),
+        }
+    }
+}
+
+fn invalid(message: String) -> UhpmError {
+    UhpmError::Validation(format!("invalid query: {}", message))
+}
+
+fn found(token: Option<Token>) -> String {
+    token.map_or_else(|| "the end of the query".to_string(), |t| t.to_string())
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, UhpmError> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            ',' => tokens.push(Token::Comma),
+            '\'' | '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some(ch) => value.push(ch),
+                        None => return Err(invalid(format!("unterminated {} quote", c))),
+                    }
+                }
+                tokens.push(Token::Quoted(value));
+            }
+            '=' | '!' | '<' | '>' | '~' => {
+                let op = match (c, chars.next_if_eq(&'=').is_some()) {
+                    ('=', _) => Op::Eq,
+                    ('!', true) => Op::Ne,
+                    ('<', false) => Op::Lt,
+                    ('<', true) => Op::Le,
+                    ('>', false) => Op::Gt,
+                    ('>', true) => Op::Ge,
+                    ('~', false) => Op::Glob,
+                    _ => return Err(invalid(format!("unknown operator `{}`", c))),
+                };
+                tokens.push(Token::Op(op));
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(ch) =
+                    chars.next_if(|ch| !ch.is_whitespace() && !",=!<>~'\"".contains(*ch))
+                {
+                    word.push(ch);
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn field(token: Option<Token>) -> Result<Field, UhpmError> {
+    match token {
+        Some(Token::Word(name)) => Field::parse(&name).ok_or_else(|| {
+            let known: Vec<&str> = Field::ALL.iter().map(|f| f.name()).collect();
+            invalid(format!(
+                "unknown field `{}` (known: {})",
+                name,
+                known.join(", ")
+            ))
+        }),
+        other => Err(invalid(format!("expected a field, found {}", found(other)))),
+    }
+}
+
+impl Query {
+    /// Parses `fields [where condition [and condition]...]`
+    pub fn parse(query: &str) -> Result<Self, UhpmError> {
+        let mut tokens = tokenize(query)?.into_iter().peekable();
+
+        let mut fields = Vec::new();
+        loop {
+            if tokens.next_if_eq(&Token::Word("*".to_string())).is_some() {
+                fields.extend(Field::ALL);
+            } else {
+                fields.push(field(tokens.next())?);
+            }
+            if tokens.next_if_eq(&Token::Comma).is_none() {
+                break;
+            }
+        }
+
+        let mut conditions = Vec::new();
+        match tokens.next() {
+            None => {}
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("where") => loop {
+                let field = field(tokens.next())?;
+                let op = match tokens.next() {
+                    Some(Token::Op(op)) => op,
+                    other => {
+                        return Err(invalid(format!(
+                            "expected an operator after `{}`, found {}",
+                            field.name(),
+                            found(other)
+                        )));
+                    }
+                };
+                let value = match tokens.next() {
+                    Some(Token::Word(v) | Token::Quoted(v)) => v,
+                    other => {
+                        return Err(invalid(format!(
+                            "expected a value after `{} {}`, found {}",
+                            field.name(),
+                            op,
+                            found(other)
+                        )));
+                    }
+                };
+                conditions.push(Condition::new(field, op, value)?);
+                match tokens.next() {
+                    None => break,
+                    Some(Token::Word(w)) if w.eq_ignore_ascii_case("and") => {}
+                    other => {
+                        return Err(invalid(format!("expected `and`, found {}", found(other))));
+                    }
+                }
+            },
+            other => return Err(invalid(format!("expected `where`, found {}", found(other)))),
+        }
+
+        Ok(Self { fields, conditions })
+    }
+
+    /// Whether the query selects or filters on `field`
+    pub fn uses(&self, field: Field) -> bool {
+        self.fields.contains(&field) || self.conditions.iter().any(|c| c.field == field)
+    }
+
+    /// The selected fields of `rows` as JSON objects
+    pub fn to_json(&self, rows: &[Vec<Value>]) -> Value {
+        rows.iter()
+            .map(|row| {
+                let object: Map<String, Value> = self
+                    .fields
+                    .iter()
+                    .zip(row)
+                    .map(|(field, value)| (field.name().to_string(), value.clone()))
+                    .collect();
+                Value::Object(object)
+            })
+            .collect()
+    }
+}
+
+/// Parses a size such as `512`, `10MB` or `1.5GiB` into bytes
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let factor: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1_000,
+        "m" | "mb" => 1_000_000,
+        "g" | "gb" => 1_000_000_000,
+        "t" | "tb" => 1_000_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        _ => return None,
+    };
+    Some((number * factor as f64) as u64)
+}
+
+/// An installed package version as seen by queries
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageRecord {
+    pub name: String,
+    pub version: String,
+    pub current: bool,
+    pub author: String,
+    pub origin: String,
+    pub reason: String,
+    pub installed_at: Option<String>,
+    pub last_run: Option<String>,
+    pub files: u64,
+}
+
+impl PackageRecord {
+    /// Value of `field`; `size` is the size of the package directory if
+    /// it was measured
+    pub fn value(&self, field: Field, size: Option<u64>) -> Value {
+        let text = |s: &Option<String>| s.clone().map_or(Value::Null, Value::String);
+        match field {
+            Field::Name => Value::String(self.name.clone()),
+            Field::Version => Value::String(self.version.clone()),
+            Field::Current => Value::Bool(self.current),
+            Field::Author => Value::String(self.author.clone()),
+            Field::Origin => Value::String(self.origin.clone()),
+            Field::Reason => Value::String(self.reason.clone()),
+            Field::InstalledAt => text(&self.installed_at),
+            Field::LastRun => text(&self.last_run),
+            Field::Files => Value::from(self.files),
+            Field::Size => size.map_or(Value::Null, Value::from),
+        }
+    }
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.metadata().ok())
+        .map(|meta| meta.len())
+        .sum()
+}
+
+/// Runs `query` over the installed package versions, sorted by name and
+/// version; each row holds the values of the selected fields
+pub async fn run(db: &PackageDB, query: &Query) -> Result<Vec<Vec<Value>>, UhpmError> {
+    let mut records = db.list_package_records().await?;
+    records.sort_by(|a, b| {
+        a.name.cmp(&b.name).then_with(|| {
+            Version::parse(&a.version)
+                .ok()
+                .cmp(&Version::parse(&b.version).ok())
+        })
+    });
+
+    let measure = query.uses(Field::Size);
+    let mut rows = Vec::new();
+    for record in records {
+        let size = measure
+            .then(|| dir_size(&packages_dir().join(format!("{}-{}", record.name, record.version))));
+        if query
+            .conditions
+            .iter()
+            .all(|c| c.matches(&record.value(c.field, size)))
+        {
+            rows.push(
+                query
+                    .fields
+                    .iter()
+                    .map(|f| record.value(*f, size))
+                    .collect(),
+            );
+        }
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &str, version: &str, current: bool) -> PackageRecord {
+        PackageRecord {
+            name: name.to_string(),
+            version: version.to_string(),
+            current,
+            author: "someone".to_string(),
+            origin: "repo".to_string(),
+            reason: "explicit".to_string(),
+            installed_at: Some("2025-06-01T12:00:00+00:00".to_string()),
+            last_run: None,
+            files: 3,
+        }
+    }
+
+    fn select(query: &Query, record: &PackageRecord, size: u64) -> bool {
+        query
+            .conditions
+            .iter()
+            .all(|c| c.matches(&record.value(c.field, Some(size))))
+    }
+
+    #[test]
+    fn test_parse_and_filter() {
+        let query = Query::parse("name,version,size where current=1 and size>10MB").unwrap();
+        assert_eq!(query.fields, [Field::Name, Field::Version, Field::Size]);
+        assert_eq!(query.conditions.len(), 2);
+        assert!(select(&query, &record("jq", "1.7.0", true), 20_000_000));
+        assert!(!select(&query, &record("jq", "1.7.0", true), 2_000_000));
+        assert!(!select(&query, &record("jq", "1.6.0", false), 20_000_000));
+
+        let query =
+            Query::parse("* WHERE version>=1.10.0 and name~'node-*' and last_run!=x").unwrap();
+        assert_eq!(query.fields.len(), Field::ALL.len());
+        assert!(select(&query, &record("node-lts", "1.10.0", true), 0));
+        assert!(!select(&query, &record("node-lts", "1.9.0", true), 0));
+        assert!(!select(&query, &record("deno", "2.0.0", true), 0));
+
+        let query = Query::parse("name where installed_at>=2025-06 and files<4").unwrap();
+        assert!(select(&query, &record("jq", "1.7.0", true), 0));
+
+        assert_eq!(parse_size("1.5KiB"), Some(1536));
+        assert_eq!(parse_size("10 MB"), Some(10_000_000));
+        assert!(Query::parse("").is_err());
+        assert!(Query::parse("name where").is_err());
+        assert!(Query::parse("nmae").is_err());
+        assert!(Query::parse("name where size>lots").is_err());
+        assert!(Query::parse("name where current=1 or current=0").is_err());
+    }
+}
@@ -3,19 +3,30 @@
 //! This module defines [`RepoDB`] and related utilities for managing package
 //! repositories in **UHPM (Universal Home Package Manager)**.
 
-use crate::error::RepoError;
-use crate::fetcher;
-use dirs;
+pub mod cache;
+pub mod config;
+pub mod index;
+pub mod integrity;
+pub mod not_found;
+pub mod resolve;
+pub mod share;
+
+pub use config::{RepoConfig, RepoEntry, RepoPolicy};
+
+use crate::error::{FetchError, RepoError, UhpmError};
+use crate::fetcher::Validators;
+use crate::{access, archive, error, fetcher, platform, warn};
+use once_cell::sync::Lazy;
 use reqwest::Url;
 use ron::from_str;
 use serde::{Deserialize, Serialize};
 use sqlx::{Row, SqlitePool};
 use std::collections::HashMap;
-use std::env::home_dir;
 use std::fs::{self, File};
 use std::io::copy;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::Mutex;
 
 /// SQLite-backed package repository database.
 pub struct RepoDB {
@@ -95,6 +106,7 @@ impl RepoDB {
                 packagename TEXT NOT NULL,
                 pkgver TEXT NOT NULL,
                 url TEXT NOT NULL,
+                description TEXT,
                 created_at DATETIME DEFAULT CURRENT_TIMESTAMP
             )
             "#,
@@ -110,6 +122,7 @@ impl RepoDB {
                 packagename TEXT NOT NULL,
                 pkgver TEXT NOT NULL,
                 url TEXT NOT NULL,
+                sha256 TEXT,
                 created_at DATETIME DEFAULT CURRENT_TIMESTAMP
             )
             "#,
@@ -177,9 +190,39 @@ impl RepoDB {
         Ok(packages)
     }
 
+    /// Lists all packages with their descriptions
+    ///
+    /// Repositories created before descriptions existed have no
+    /// `description` column; their packages have none.
+    pub async fn list_described(
+        &self,
+    ) -> Result<Vec<(String, String, String, Option<String>)>, sqlx::Error> {
+        let rows = sqlx::query("SELECT * FROM packages")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|r| {
+                (
+                    r.get::<String, _>("packagename"),
+                    r.get::<String, _>("pkgver"),
+                    r.get::<String, _>("url"),
+                    r.try_get::<Option<String>, _>("description").ok().flatten(),
+                )
+            })
+            .collect())
+    }
+
     /// Список всех исходников в репозитории
-    pub async fn list_sources(&self) -> Result<Vec<(String, String, String)>, sqlx::Error> {
-        let rows = sqlx::query("SELECT packagename, pkgver, url FROM sources")
+    ///
+    /// Each source comes with the SHA-256 of its build script, if the
+    /// repository lists one; repositories created before checksums existed
+    /// have no `sha256` column.
+    pub async fn list_sources(
+        &self,
+    ) -> Result<Vec<(String, String, String, Option<String>)>, sqlx::Error> {
+        let rows = sqlx::query("SELECT * FROM sources")
             .fetch_all(&self.pool)
             .await?;
 
@@ -190,6 +233,7 @@ impl RepoDB {
                     r.get::<String, _>("packagename"),
                     r.get::<String, _>("pkgver"),
                     r.get::<String, _>("url"),
+                    r.try_get::<Option<String>, _>("sha256").ok().flatten(),
                 )
             })
             .collect();
@@ -239,45 +283,318 @@ impl RepoDB {
         Ok(())
     }
 
-    /// Добавить исходники в репозиторий (совместимо с нашим uhprepo)
-    pub async fn add_source(
+    /// Sets the description shown by `uhpm search` for a package version
+    pub async fn set_description(
         &self,
         packagename: &str,
         pkgver: &str,
-        url: &str,
+        description: &str,
     ) -> Result<(), sqlx::Error> {
-        sqlx::query("INSERT OR REPLACE INTO sources (packagename, pkgver, url) VALUES (?, ?, ?)")
+        sqlx::query("UPDATE packages SET description = ? WHERE packagename = ? AND pkgver = ?")
+            .bind(description)
             .bind(packagename)
             .bind(pkgver)
-            .bind(url)
             .execute(&self.pool)
             .await?;
         Ok(())
     }
+
+    /// Добавить исходники в репозиторий (совместимо с нашим uhprepo)
+    pub async fn add_source(
+        &self,
+        packagename: &str,
+        pkgver: &str,
+        url: &str,
+        sha256: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO sources (packagename, pkgver, url, sha256) VALUES (?, ?, ?, ?)",
+        )
+        .bind(packagename)
+        .bind(pkgver)
+        .bind(url)
+        .bind(sha256)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
 }
 
 /// Парсит конфигурацию репозиториев из RON файла
 pub fn parse_repos<P: AsRef<Path>>(path: P) -> Result<RepoMap, RepoError> {
     let content = fs::read_to_string(path)?;
-    let repos: HashMap<String, String> = from_str(&content).unwrap();
-    Ok(repos)
+    let entries: HashMap<String, RepoEntry> =
+        from_str(&content).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    Ok(entries
+        .into_iter()
+        .map(|(name, entry)| (name, entry.url))
+        .collect())
+}
+
+/// Repository URLs this process tried to refresh, and whether it worked
+static REFRESHED: Lazy<Mutex<HashMap<String, bool>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Whether this process refreshed the repository at `url`; `None` if it
+/// did not try
+pub fn refresh_outcome(url: &str) -> Option<bool> {
+    REFRESHED.lock().unwrap().get(url).copied()
+}
+
+/// Location of the cached database of repository `name`
+pub fn cache_db_path(name: &str) -> PathBuf {
+    cache::cache_dir().join(name).join("repository.db")
+}
+
+/// What refreshing a repository database did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Refresh {
+    /// The server reported the cached database as current
+    Unchanged,
+    /// A new database was downloaded and verified
+    Updated,
+}
+
+/// File holding the HTTP validators of the cached database `db`
+fn validators_path(db: &Path) -> PathBuf {
+    db.with_file_name("validators.ron")
+}
+
+/// Published forms of a repository database, most compact first
+const INDEX_FILES: [&str; 3] = ["repository.db.zst", "repository.db.gz", "repository.db"];
+
+/// Validators of the last download and the published file they belong to
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CachedValidators {
+    file: String,
+    validators: Validators,
+}
+
+/// Whether a download failed because the file is not published
+fn is_absent(error: &FetchError) -> bool {
+    match error {
+        FetchError::Http(e) => e.status() == Some(reqwest::StatusCode::NOT_FOUND),
+        FetchError::Io(e) => e.kind() == std::io::ErrorKind::NotFound,
+        _ => false,
+    }
+}
+
+/// Brings the cached database `db` of repository `name` at `url` up to date
+///
+/// A compressed `repository.db.zst` or `repository.db.gz` is preferred
+/// over `repository.db` when the repository publishes one; it is checked
+/// against its own published checksum and unpacked locally. Unless `force`
+/// is set, the ETag and Last-Modified date of the last verified download
+/// are sent along, so an unchanged database is not downloaded again,
+/// unless it was cached from another URL. A new download replaces `db`
+/// only once it passes [`integrity::accept`]; the URL is recorded with it
+/// (see [`cache`]).
+pub async fn refresh_repo(
+    name: &str,
+    url: &str,
+    db: &Path,
+    force: bool,
+) -> Result<Refresh, UhpmError> {
+    let path = validators_path(db);
+    let moved = cache::source(db).is_some_and(|source| source != url);
+    let cached: CachedValidators = if !force && !moved && integrity::check_cached(db).is_ok() {
+        fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| from_str(&s).ok())
+            .unwrap_or_default()
+    } else {
+        CachedValidators::default()
+    };
+
+    let new = db.with_extension("db.new");
+    let packed = db.with_extension("db.packed");
+    for file in INDEX_FILES {
+        let compressed = file != "repository.db";
+        let file_url = format!("{}/{}", url, file);
+        let known = if cached.file == file {
+            cached.validators.clone()
+        } else {
+            Validators::default()
+        };
+        let target = if compressed { &packed } else { &new };
+        let validators = match fetcher::download_if_changed(&file_url, target, &known).await {
+            Ok(Some(validators)) => validators,
+            Ok(None) => {
+                cache::record_source(db, url)?;
+                return Ok(Refresh::Unchanged);
+            }
+            Err(e) if compressed && is_absent(&e) => continue,
+            Err(source) => {
+                return Err(RepoError::Fetch {
+                    repo: name.to_string(),
+                    url: file_url,
+                    source,
+                }
+                .into());
+            }
+        };
+
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        if compressed {
+            let unpacked = match integrity::check_checksum(name, &file_url, &packed).await {
+                Ok(_) => archive::decompress(
+                    &packed,
+                    &new,
+                    &crate::config::Config::load_or_default().extract,
+                )
+                .map_err(UhpmError::from),
+                Err(e) => Err(e),
+            };
+            fs::remove_file(&packed)?;
+            if let Err(e) = unpacked {
+                let _ = fs::remove_file(&new);
+                return Err(e);
+            }
+        }
+        integrity::accept(name, url, &new, db).await?;
+        cache::record_source(db, url)?;
+        if !validators.is_empty() {
+            let cached = CachedValidators {
+                file: file.to_string(),
+                validators,
+            };
+            let content = ron::to_string(&cached).map_err(|e| UhpmError::Parse(e.to_string()))?;
+            fs::write(&path, content)?;
+        }
+        return Ok(Refresh::Updated);
+    }
+    unreachable!("the plain repository.db is always tried")
 }
 
+/// Downloads repository databases into `~/.uhpm/cache/repo`
+///
+/// Each repository is refreshed (see [`refresh_repo`]) at most once per
+/// process; later calls reuse the cached file; in read-only mode nothing
+/// is downloaded. Cached files that fail their integrity check (see
+/// [`integrity`]) are left out.
 pub async fn cache_repo(repos: RepoMap) -> Vec<PathBuf> {
     let mut repo_dbs: Vec<PathBuf> = Vec::new();
+    if !access::is_read_only()
+        && let Err(e) = cache::ensure_layout()
+    {
+        warn!("repo.cache_layout.failed", e);
+    }
     for (name, url) in repos {
-        let pathstr = format!(
-            "{}/.uhpm/cache/repo/{}/repository.db",
-            home_dir().unwrap().to_str().unwrap(),
-            name,
-        );
-        let pathdb: PathBuf = PathBuf::from(pathstr);
-        fetcher::download_file_to_path_with_dirs(&format!("{}/repository.db", url), &pathdb).await;
+        let pathdb = cache_db_path(&name);
+        let fresh = access::is_read_only()
+            || pathdb.exists() && REFRESHED.lock().unwrap().contains_key(&url);
+        if !fresh {
+            let refreshed = match refresh_repo(&name, &url, &pathdb, false).await {
+                Ok(_) => true,
+                Err(
+                    e @ (UhpmError::Network(_)
+                    | UhpmError::Io(_)
+                    | UhpmError::Repository(RepoError::Fetch { .. })),
+                ) => {
+                    warn!("repo.cache.download_failed", &name, e.to_string());
+                    false
+                }
+                Err(e) => {
+                    error!("repo.cache.rejected", &name, e.to_string());
+                    false
+                }
+            };
+            REFRESHED.lock().unwrap().insert(url, refreshed);
+        }
+        if pathdb.exists()
+            && let Err(e) = integrity::check_cached(&pathdb)
+        {
+            error!("repo.cache.rejected", &name, e.to_string());
+            continue;
+        }
         repo_dbs.push(pathdb);
     }
     return repo_dbs;
 }
 
+/// Name of the repository a cached database belongs to, as laid out by
+/// [`cache_repo`]
+pub fn cache_name(db_path: &Path) -> String {
+    db_path
+        .parent()
+        .and_then(|dir| dir.file_name())
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Returns the repository databases already present in the local cache
+///
+/// Unlike [`cache_repo`], nothing is downloaded. Databases failing their
+/// integrity check are left out.
+pub fn cached_repo_dbs() -> Vec<PathBuf> {
+    fs::read_dir(cache::cache_dir())
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path().join("repository.db"))
+                .filter(|p| p.exists() && integrity::check_cached(p).is_ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the name of the configured repository a package URL belongs to
+///
+/// The repository whose URL is the longest prefix of `url` wins.
+pub fn repo_name_for_url(url: &str) -> Option<String> {
+    let repos_path = platform::uhpm_home().join("repos.ron");
+    let repos = parse_repos(&repos_path).ok()?;
+    match_repo(&repos, url)
+}
+
+fn match_repo(repos: &RepoMap, url: &str) -> Option<String> {
+    repos
+        .iter()
+        .filter(|(_, repo_url)| url.starts_with(repo_url.trim_end_matches('/')))
+        .max_by_key(|(_, repo_url)| repo_url.len())
+        .map(|(name, _)| name.clone())
+}
+
+/// Finds a package in cached repository databases
+///
+/// Returns `(version, url)` for the requested version, or for the highest
+/// version across all repositories when `version` is `None`. Missing
+/// database files are skipped.
+pub async fn find_package(
+    repo_dbs: &[PathBuf],
+    name: &str,
+    version: Option<&str>,
+) -> Result<Option<(semver::Version, String)>, RepoError> {
+    let mut best: Option<(semver::Version, String)> = None;
+
+    for db_path in repo_dbs {
+        if !db_path.exists() {
+            continue;
+        }
+        let repo_index = index::load_repo(&cache_name(db_path), db_path).await?;
+
+        for entry in repo_index.find(name) {
+            let Ok(parsed) = semver::Version::parse(&entry.version) else {
+                continue;
+            };
+            match version {
+                Some(wanted) if wanted == entry.version => {
+                    return Ok(Some((parsed, entry.url.clone())));
+                }
+                Some(_) => {}
+                None => {
+                    if best.as_ref().is_none_or(|(v, _)| &parsed > v) {
+                        best = Some((parsed, entry.url.clone()));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(best)
+}
+
 /// Информация о репозитории из нашего info.json
 #[derive(Serialize, Deserialize)]
 pub struct RepositoryInfo {
@@ -296,3 +613,54 @@ impl RepositoryInfo {
         Ok(info)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_refresh_prefers_compressed_database() {
+        let tmp = tempfile::tempdir().unwrap();
+        let served = tmp.path().join("served");
+        fs::create_dir_all(&served).unwrap();
+        fs::write(served.join("repository.db"), "plain").unwrap();
+        let compressed = zstd::encode_all("index".as_bytes(), 0).unwrap();
+        fs::write(served.join("repository.db.zst"), &compressed).unwrap();
+        let url = format!("file://{}", served.display());
+        let db = tmp.path().join("cache/main/repository.db");
+        fs::create_dir_all(db.parent().unwrap()).unwrap();
+
+        refresh_repo("main", &url, &db, true).await.unwrap();
+        assert_eq!(fs::read_to_string(&db).unwrap(), "index");
+        assert!(!db.with_extension("db.packed").exists());
+
+        fs::write(served.join("repository.db.zst.sha256"), "0000\n").unwrap();
+        assert!(matches!(
+            refresh_repo("main", &url, &db, true).await,
+            Err(UhpmError::Repository(RepoError::Integrity(_)))
+        ));
+
+        fs::remove_file(served.join("repository.db.zst")).unwrap();
+        refresh_repo("main", &url, &db, true).await.unwrap();
+        assert_eq!(fs::read_to_string(&db).unwrap(), "plain");
+    }
+
+    #[tokio::test]
+    async fn test_refresh_failure_names_repository() {
+        let tmp = tempfile::tempdir().unwrap();
+        let url = format!("file://{}", tmp.path().join("gone").display());
+        let db = tmp.path().join("cache/extra/repository.db");
+
+        let err = refresh_repo("extra", &url, &db, true).await.unwrap_err();
+        let UhpmError::Repository(repo_err @ RepoError::Fetch { url: file_url, .. }) = &err else {
+            panic!("unexpected error: {:?}", err);
+        };
+        assert_eq!(repo_err.repo(), Some("extra"));
+        assert_eq!(file_url, &format!("{}/repository.db", url));
+        assert!(
+            err.to_string()
+                .contains("Repository extra: failed to fetch file://")
+        );
+        assert_eq!(cache_name(&db), "extra");
+    }
+}
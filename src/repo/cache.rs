@@ -0,0 +1,213 @@
+//! # Repository Cache Layout
+//!
+//! Downloaded repository databases live in `~/.uhpm/cache/repo`:
+//!
+//! ```text
+//! cache/repo/
+//! ├── .layout              layout version of the directory
+//! └── <repo>/
+//!     ├── repository.db    verified database
+//!     ├── validators.ron   ETag / Last-Modified of the last download
+//!     └── source           URL the database was downloaded from
+//! ```
+//!
+//! The `.layout` file lets a later uhpm change the layout: an older
+//! layout is migrated when the cache is next used, a layout this version
+//! does not know is discarded (it is only a cache). Directories written
+//! before the layout was versioned count as version 0.
+//!
+//! A directory belongs to a configured repository when the name matches
+//! and the recorded `source` is the configured URL. Directories left
+//! behind by repositories removed from `repos.ron` by hand, or cached from
+//! a URL the repository no longer has, are [stale](stale) and removed by
+//! `uhpm clean --repo-cache`.
+
+use crate::repo::RepoMap;
+use crate::{info, platform, warn};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Version of the cache layout this uhpm writes
+pub const LAYOUT_VERSION: u32 = 1;
+
+/// The repository cache directory
+pub fn cache_dir() -> PathBuf {
+    platform::uhpm_home().join("cache/repo")
+}
+
+fn layout_file(dir: &Path) -> PathBuf {
+    dir.join(".layout")
+}
+
+/// Layout version of the cache in `dir`; `None` if there is no cache
+pub fn layout_version(dir: &Path) -> Option<u32> {
+    if !dir.exists() {
+        return None;
+    }
+    Some(
+        fs::read_to_string(layout_file(dir))
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .unwrap_or(0),
+    )
+}
+
+/// Brings the cache in `dir` to the current layout
+pub fn ensure_layout_in(dir: &Path) -> io::Result<()> {
+    match layout_version(dir) {
+        Some(LAYOUT_VERSION) => return Ok(()),
+        // Version 1 only added `.layout` and the `source` files, which are
+        // recorded on the next refresh.
+        Some(0) | None => {}
+        Some(version) => {
+            warn!("repo.cache_layout.unknown", version, dir.display());
+            fs::remove_dir_all(dir)?;
+        }
+    }
+    fs::create_dir_all(dir)?;
+    fs::write(layout_file(dir), format!("{}\n", LAYOUT_VERSION))
+}
+
+/// Brings the repository cache to the current layout
+pub fn ensure_layout() -> io::Result<()> {
+    ensure_layout_in(&cache_dir())
+}
+
+fn source_file(db: &Path) -> PathBuf {
+    db.with_file_name("source")
+}
+
+/// Records that the cached database `db` was downloaded from `url`
+pub fn record_source(db: &Path, url: &str) -> io::Result<()> {
+    fs::write(source_file(db), format!("{}\n", url))
+}
+
+/// URL the cached database `db` was downloaded from, if recorded
+pub fn source(db: &Path) -> Option<String> {
+    fs::read_to_string(source_file(db))
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+/// A cache directory no configured repository uses
+#[derive(Debug, Clone, PartialEq)]
+pub struct StaleCache {
+    pub name: String,
+    pub dir: PathBuf,
+    /// URL the cache was downloaded from, if recorded
+    pub source: Option<String>,
+    /// Total size of the files in bytes
+    pub size: u64,
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| match e.metadata() {
+                    Ok(meta) if meta.is_dir() => dir_size(&e.path()),
+                    Ok(meta) => meta.len(),
+                    Err(_) => 0,
+                })
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Cache directories in `dir` that do not belong to one of `repos`, by name
+pub fn stale_in(dir: &Path, repos: &RepoMap) -> Vec<StaleCache> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut stale: Vec<StaleCache> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_dir())
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            let path = e.path();
+            let source = source(&path.join("repository.db"));
+            let current = match (repos.get(&name), &source) {
+                (Some(url), Some(source)) => url == source,
+                (Some(_), None) => true,
+                (None, _) => false,
+            };
+            (!current).then(|| StaleCache {
+                size: dir_size(&path),
+                name,
+                dir: path,
+                source,
+            })
+        })
+        .collect();
+    stale.sort_by(|a, b| a.name.cmp(&b.name));
+    stale
+}
+
+/// Cache directories no configured repository uses
+pub fn stale(repos: &RepoMap) -> Vec<StaleCache> {
+    stale_in(&cache_dir(), repos)
+}
+
+/// Deletes the cache directories no configured repository uses and
+/// returns them
+pub fn clean(repos: &RepoMap) -> io::Result<Vec<StaleCache>> {
+    ensure_layout()?;
+    let stale = stale(repos);
+    for cache in &stale {
+        fs::remove_dir_all(&cache.dir)?;
+        info!(
+            "repo.cache_layout.removed",
+            &cache.name,
+            cache.dir.display()
+        );
+    }
+    Ok(stale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_and_stale_caches() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("repo");
+        for name in ["main", "old", "moved", "unrecorded"] {
+            fs::create_dir_all(dir.join(name)).unwrap();
+            fs::write(dir.join(name).join("repository.db"), "db").unwrap();
+        }
+        record_source(&dir.join("main/repository.db"), "https://example.com/main").unwrap();
+        record_source(&dir.join("old/repository.db"), "https://example.com/old").unwrap();
+        record_source(
+            &dir.join("moved/repository.db"),
+            "https://old.example/moved",
+        )
+        .unwrap();
+
+        assert_eq!(layout_version(&dir), Some(0));
+        ensure_layout_in(&dir).unwrap();
+        assert_eq!(layout_version(&dir), Some(LAYOUT_VERSION));
+
+        let repos: RepoMap = [
+            ("main", "https://example.com/main"),
+            ("moved", "https://new.example/moved"),
+            ("unrecorded", "https://example.com/unrecorded"),
+        ]
+        .into_iter()
+        .map(|(n, u)| (n.to_string(), u.to_string()))
+        .collect();
+        let stale = stale_in(&dir, &repos);
+        let names: Vec<&str> = stale.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["moved", "old"]);
+        assert_eq!(stale[1].source.as_deref(), Some("https://example.com/old"));
+        assert!(stale[1].size > 0);
+
+        fs::write(layout_file(&dir), "99\n").unwrap();
+        ensure_layout_in(&dir).unwrap();
+        assert!(!dir.join("main").exists());
+        assert_eq!(layout_version(&dir), Some(LAYOUT_VERSION));
+    }
+}
@@ -0,0 +1,381 @@
+//! # Repository Configuration
+//!
+//! [`RepoConfig`] reads and changes `~/.uhpm/repos.ron`, the map of
+//! repository names to URLs used by `uhpm repo add/remove/list`, the setup
+//! wizard and `uhpm repo import`.
+//!
+//! A repository is either just its URL or a record with a priority and
+//! the packages pinned to it:
+//!
+//! ```ron
+//! {
+//!     "main": "https://example.com/uhpm",
+//!     "testing": (url: "https://example.com/testing", priority: -10),
+//!     "vendor": (url: "https://vendor.example/uhpm", pins: ["vendor-cli"]),
+//! }
+//! ```
+//!
+//! When several repositories carry a package, [`RepoPolicy`] decides: a
+//! pinned package only ever comes from its repository, otherwise the
+//! repository with the highest priority (default 0) wins, and the newest
+//! version among equal priorities.
+//!
+//! Names become directory and key file names (`cache/repo/<name>/`,
+//! `keys/<name>.pub`), so they are limited to letters, digits, `-`, `_`
+//! and `.`. URLs must be `http://`, `https://`, `file://` or absolute
+//! paths. The file is written to a temporary file first and renamed over
+//! the old one, so it is never left half-written.
+
+use crate::error::UhpmError;
+use crate::platform;
+use crate::repo::RepoMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A configured repository
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredEntry", into = "StoredEntry")]
+pub struct RepoEntry {
+    pub url: String,
+    /// Repositories with a higher priority are preferred
+    pub priority: i32,
+    /// Packages that must come from this repository
+    pub pins: Vec<String>,
+}
+
+/// How an entry is written in `repos.ron`: just the URL when nothing else
+/// is set
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum StoredEntry {
+    Url(String),
+    Full {
+        url: String,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        priority: i32,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pins: Vec<String>,
+    },
+}
+
+fn is_zero(n: &i32) -> bool {
+    *n == 0
+}
+
+impl From<StoredEntry> for RepoEntry {
+    fn from(stored: StoredEntry) -> Self {
+        match stored {
+            StoredEntry::Url(url) => RepoEntry {
+                url,
+                ..Default::default()
+            },
+            StoredEntry::Full {
+                url,
+                priority,
+                pins,
+            } => RepoEntry {
+                url,
+                priority,
+                pins,
+            },
+        }
+    }
+}
+
+impl From<RepoEntry> for StoredEntry {
+    fn from(entry: RepoEntry) -> Self {
+        if entry.priority == 0 && entry.pins.is_empty() {
+            StoredEntry::Url(entry.url)
+        } else {
+            StoredEntry::Full {
+                url: entry.url,
+                priority: entry.priority,
+                pins: entry.pins,
+            }
+        }
+    }
+}
+
+/// Priorities and pins deciding between repositories
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RepoPolicy {
+    priorities: HashMap<String, i32>,
+    /// Package name to repository name
+    pins: HashMap<String, String>,
+}
+
+impl RepoPolicy {
+    /// The policy of the configured repositories; none if they cannot be
+    /// read
+    pub fn load() -> Self {
+        RepoConfig::open()
+            .map(|config| config.policy())
+            .unwrap_or_default()
+    }
+
+    /// Priority of repository `repo`
+    pub fn priority(&self, repo: &str) -> i32 {
+        self.priorities.get(repo).copied().unwrap_or(0)
+    }
+
+    /// Repository `package` is pinned to, if any
+    pub fn pinned_repo(&self, package: &str) -> Option<&str> {
+        self.pins.get(package).map(String::as_str)
+    }
+
+    /// Whether `package` may come from repository `repo`
+    pub fn allows(&self, package: &str, repo: &str) -> bool {
+        self.pinned_repo(package)
+            .is_none_or(|pinned| pinned == repo)
+    }
+}
+
+/// The configured repositories and the file they are kept in
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepoConfig {
+    path: PathBuf,
+    entries: BTreeMap<String, RepoEntry>,
+}
+
+impl RepoConfig {
+    /// Location of `repos.ron`
+    pub fn default_path() -> PathBuf {
+        platform::uhpm_home().join("repos.ron")
+    }
+
+    /// Loads `repos.ron` from the uhpm home
+    pub fn open() -> Result<Self, UhpmError> {
+        Self::load(&Self::default_path())
+    }
+
+    /// Loads the repositories from `path`; a missing file has none
+    pub fn load(path: &Path) -> Result<Self, UhpmError> {
+        let entries = match fs::read_to_string(path) {
+            Ok(content) => ron::from_str(&content)
+                .map_err(|e| UhpmError::Parse(format!("{}: {}", path.display(), e)))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    /// Repository names and URLs
+    pub fn repos(&self) -> RepoMap {
+        self.entries
+            .iter()
+            .map(|(name, entry)| (name.clone(), entry.url.clone()))
+            .collect()
+    }
+
+    /// The repositories sorted by name
+    pub fn entries(&self) -> &BTreeMap<String, RepoEntry> {
+        &self.entries
+    }
+
+    /// Priorities and pins of the repositories
+    pub fn policy(&self) -> RepoPolicy {
+        let mut policy = RepoPolicy::default();
+        for (name, entry) in &self.entries {
+            policy.priorities.insert(name.clone(), entry.priority);
+            for package in &entry.pins {
+                policy.pins.insert(package.clone(), name.clone());
+            }
+        }
+        policy
+    }
+
+    /// Checks a repository name and URL
+    pub fn validate(name: &str, url: &str) -> Result<(), UhpmError> {
+        let name_ok = !name.is_empty()
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !name_ok {
+            return Err(UhpmError::Validation(format!(
+                "invalid repository name {:?}: use letters, digits, '-', '_' and '.'",
+                name
+            )));
+        }
+        let url_ok = ["http://", "https://", "file://"]
+            .iter()
+            .any(|scheme| url.len() > scheme.len() && url.starts_with(scheme))
+            || Path::new(url).is_absolute();
+        if !url_ok {
+            return Err(UhpmError::Validation(format!(
+                "invalid repository URL {:?}: expected http(s)://, file:// or an absolute path",
+                url
+            )));
+        }
+        Ok(())
+    }
+
+    /// Adds a repository, or changes its URL if `replace` is set; the
+    /// priority and pins of an existing repository are kept
+    ///
+    /// # Errors
+    /// [`UhpmError::Validation`] for a bad name or URL,
+    /// [`UhpmError::Conflict`] if the name is taken by another URL and
+    /// `replace` is not set.
+    pub fn add(&mut self, name: &str, url: &str, replace: bool) -> Result<(), UhpmError> {
+        let url = url.trim_end_matches('/');
+        Self::validate(name, url)?;
+        match self.entries.get_mut(name) {
+            Some(existing) if existing.url != url && !replace => Err(UhpmError::Conflict(format!(
+                "repository {} is already configured with {}",
+                name, existing.url
+            ))),
+            Some(existing) => {
+                existing.url = url.to_string();
+                Ok(())
+            }
+            None => {
+                self.entries.insert(
+                    name.to_string(),
+                    RepoEntry {
+                        url: url.to_string(),
+                        ..Default::default()
+                    },
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Removes a repository and returns its URL
+    pub fn remove(&mut self, name: &str) -> Result<String, UhpmError> {
+        self.entries
+            .remove(name)
+            .map(|entry| entry.url)
+            .ok_or_else(|| not_configured(name))
+    }
+
+    /// Sets the priority of a repository
+    pub fn set_priority(&mut self, name: &str, priority: i32) -> Result<(), UhpmError> {
+        let entry = self
+            .entries
+            .get_mut(name)
+            .ok_or_else(|| not_configured(name))?;
+        entry.priority = priority;
+        Ok(())
+    }
+
+    /// Pins `package` to repository `name`, replacing an earlier pin
+    pub fn pin(&mut self, package: &str, name: &str) -> Result<(), UhpmError> {
+        if !self.entries.contains_key(name) {
+            return Err(not_configured(name));
+        }
+        self.unpin(package);
+        self.entries
+            .get_mut(name)
+            .expect("checked above")
+            .pins
+            .push(package.to_string());
+        Ok(())
+    }
+
+    /// Removes the pin of `package`; returns whether it was pinned
+    pub fn unpin(&mut self, package: &str) -> bool {
+        let mut found = false;
+        for entry in self.entries.values_mut() {
+            let before = entry.pins.len();
+            entry.pins.retain(|p| p != package);
+            found |= entry.pins.len() != before;
+        }
+        found
+    }
+
+    /// Writes the repositories back, atomically
+    pub fn save(&self) -> Result<(), UhpmError> {
+        let content = ron::ser::to_string_pretty(&self.entries, ron::ser::PrettyConfig::default())
+            .map_err(|e| UhpmError::Parse(e.to_string()))?;
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = self.path.with_extension("ron.tmp");
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+fn not_configured(name: &str) -> UhpmError {
+    UhpmError::Validation(format!("repository {} is not configured", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_remove_and_save() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("repos.ron");
+        let mut config = RepoConfig::load(&path).unwrap();
+        assert!(config.repos().is_empty());
+
+        config
+            .add("main", "https://example.com/uhpm/", false)
+            .unwrap();
+        config.add("local", "/srv/uhpm", false).unwrap();
+        assert!(matches!(
+            config.add("main", "https://other.org", false),
+            Err(UhpmError::Conflict(_))
+        ));
+        assert!(matches!(
+            config.add("../keys", "https://x.org", false),
+            Err(UhpmError::Validation(_))
+        ));
+        assert!(matches!(
+            config.add("ftp", "ftp://x.org", false),
+            Err(UhpmError::Validation(_))
+        ));
+        config.save().unwrap();
+
+        let mut config = RepoConfig::load(&path).unwrap();
+        assert_eq!(config.repos()["main"], "https://example.com/uhpm");
+        assert_eq!(config.remove("local").unwrap(), "/srv/uhpm");
+        assert!(matches!(
+            config.remove("local"),
+            Err(UhpmError::Validation(_))
+        ));
+        config.save().unwrap();
+        assert_eq!(RepoConfig::load(&path).unwrap().repos().len(), 1);
+        assert!(!tmp.path().join("repos.ron.tmp").exists());
+    }
+
+    #[test]
+    fn test_priorities_and_pins() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("repos.ron");
+        fs::write(
+            &path,
+            r#"{
+                "main": "https://example.com/uhpm",
+                "testing": (url: "https://example.com/testing", priority: -10),
+            }"#,
+        )
+        .unwrap();
+
+        let mut config = RepoConfig::load(&path).unwrap();
+        assert_eq!(config.entries()["testing"].priority, -10);
+        config.pin("tool", "testing").unwrap();
+        config.pin("tool", "main").unwrap();
+        assert!(config.pin("tool", "missing").is_err());
+        config.set_priority("main", 5).unwrap();
+        config.save().unwrap();
+
+        let policy = RepoConfig::load(&path).unwrap().policy();
+        assert_eq!(policy.priority("main"), 5);
+        assert_eq!(policy.priority("unknown"), 0);
+        assert_eq!(policy.pinned_repo("tool"), Some("main"));
+        assert!(!policy.allows("tool", "testing"));
+        assert!(policy.allows("other", "testing"));
+    }
+}
@@ -0,0 +1,278 @@
+//! # In-Memory Repository Index
+//!
+//! Parsing a `repository.db` means opening an SQLite pool and reading the
+//! whole `packages` table. Resolution, search and update often need the
+//! same repositories several times per invocation, so parsed indexes are
+//! cached for the lifetime of the process.
+//!
+//! A cached index is reused while the database file keeps the same
+//! modification time and size; a refreshed download invalidates it
+//! automatically.
+
+use crate::debug;
+use crate::error::RepoError;
+use crate::repo::RepoDB;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// A package listed in a repository
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexEntry {
+    pub name: String,
+    pub version: String,
+    pub url: String,
+    pub description: Option<String>,
+}
+
+/// Parsed `packages` table of one repository database
+#[derive(Debug, Default)]
+pub struct RepoIndex {
+    entries: Vec<IndexEntry>,
+    by_name: HashMap<String, Vec<usize>>,
+}
+
+impl RepoIndex {
+    pub fn new(entries: Vec<IndexEntry>) -> Self {
+        let mut by_name: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, entry) in entries.iter().enumerate() {
+            by_name.entry(entry.name.clone()).or_default().push(i);
+        }
+        Self { entries, by_name }
+    }
+
+    /// All packages in repository order
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    /// All versions of a package
+    pub fn find(&self, name: &str) -> impl Iterator<Item = &IndexEntry> {
+        self.by_name
+            .get(name)
+            .into_iter()
+            .flatten()
+            .map(|&i| &self.entries[i])
+    }
+}
+
+/// A package matching a search query
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub name: String,
+    /// Newest version across the searched repositories
+    pub version: String,
+    /// Repository carrying that version
+    pub repo: String,
+    /// Every version available, newest first
+    pub versions: Vec<String>,
+    /// Description of the newest version that has one
+    pub description: Option<String>,
+}
+
+impl std::fmt::Display for SearchHit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} ({})", self.name, self.version, self.repo)?;
+        if let Some(description) = &self.description {
+            write!(f, " - {}", description)?;
+        }
+        Ok(())
+    }
+}
+
+/// A search match: parsed version, repository and entry
+type Match<'a> = (Option<semver::Version>, &'a str, &'a IndexEntry);
+
+/// Finds packages whose name contains `query`, ignoring case
+///
+/// `indexes` pairs each repository name with its index. Every package is
+/// reported once, with its newest version and all versions found in any
+/// repository; hits are sorted by name.
+pub fn search(indexes: &[(String, Arc<RepoIndex>)], query: &str) -> Vec<SearchHit> {
+    let query = query.to_lowercase();
+    let mut found: HashMap<&str, Vec<Match>> = HashMap::new();
+
+    for (repo, index) in indexes {
+        for entry in index.entries() {
+            if entry.name.to_lowercase().contains(&query) {
+                let parsed = semver::Version::parse(&entry.version).ok();
+                found
+                    .entry(&entry.name)
+                    .or_default()
+                    .push((parsed, repo, entry));
+            }
+        }
+    }
+
+    let mut hits: Vec<SearchHit> = found
+        .into_values()
+        .map(|mut matches| {
+            // Newest first; on equal versions the earlier repository wins
+            matches.sort_by(|a, b| b.0.cmp(&a.0));
+            let (_, repo, newest) = matches[0];
+            let mut versions: Vec<String> = Vec::new();
+            for (_, _, entry) in &matches {
+                if !versions.contains(&entry.version) {
+                    versions.push(entry.version.clone());
+                }
+            }
+            SearchHit {
+                name: newest.name.clone(),
+                version: newest.version.clone(),
+                repo: repo.to_string(),
+                versions,
+                description: matches.iter().find_map(|(_, _, e)| e.description.clone()),
+            }
+        })
+        .collect();
+    hits.sort_by(|a, b| a.name.cmp(&b.name));
+    hits
+}
+
+/// File identity used to detect a changed database
+type Stamp = (SystemTime, u64);
+
+/// Loaded indexes with the stamp of their database, by database path
+type Cache = HashMap<PathBuf, (Stamp, Arc<RepoIndex>)>;
+
+static CACHE: Lazy<Mutex<Cache>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn stamp(path: &Path) -> std::io::Result<Stamp> {
+    let meta = fs::metadata(path)?;
+    Ok((meta.modified()?, meta.len()))
+}
+
+/// Loads the index of a repository database, reusing the cached copy
+/// while the file is unchanged
+pub async fn load(db_path: &Path) -> Result<Arc<RepoIndex>, RepoError> {
+    let current = stamp(db_path)?;
+    if let Some((cached, index)) = CACHE.lock().unwrap().get(db_path)
+        && *cached == current
+    {
+        debug!("repo.index.hit", db_path.display());
+        return Ok(index.clone());
+    }
+
+    debug!("repo.index.loading", db_path.display());
+    let repo_db = RepoDB::new(db_path).await?;
+    let entries = repo_db
+        .list_described()
+        .await?
+        .into_iter()
+        .map(|(name, version, url, description)| IndexEntry {
+            name,
+            version,
+            url,
+            description,
+        })
+        .collect();
+    repo_db.pool().close().await;
+
+    let index = Arc::new(RepoIndex::new(entries));
+    CACHE
+        .lock()
+        .unwrap()
+        .insert(db_path.to_path_buf(), (current, index.clone()));
+    Ok(index)
+}
+
+/// Loads the index of repository `repo` like [`load`], naming the
+/// repository in errors
+pub async fn load_repo(repo: &str, db_path: &Path) -> Result<Arc<RepoIndex>, RepoError> {
+    load(db_path).await.map_err(|e| RepoError::Index {
+        repo: repo.to_string(),
+        path: db_path.to_path_buf(),
+        source: Box::new(e),
+    })
+}
+
+/// Drops all cached indexes
+pub fn clear() {
+    CACHE.lock().unwrap().clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_by_name() {
+        let index = RepoIndex::new(vec![
+            IndexEntry {
+                name: "a".into(),
+                version: "1.0.0".into(),
+                url: "u1".into(),
+                description: None,
+            },
+            IndexEntry {
+                name: "b".into(),
+                version: "1.0.0".into(),
+                url: "u2".into(),
+                description: None,
+            },
+            IndexEntry {
+                name: "a".into(),
+                version: "2.0.0".into(),
+                url: "u3".into(),
+                description: None,
+            },
+        ]);
+
+        let urls: Vec<&str> = index.find("a").map(|e| e.url.as_str()).collect();
+        assert_eq!(urls, vec!["u1", "u3"]);
+        assert_eq!(index.find("missing").count(), 0);
+    }
+
+    #[test]
+    fn test_search_keeps_newest_version() {
+        let entry = |name: &str, version: &str| IndexEntry {
+            name: name.into(),
+            version: version.into(),
+            url: String::new(),
+            description: None,
+        };
+        let main = Arc::new(RepoIndex::new(vec![
+            entry("Firefox", "1.0.0"),
+            entry("vim", "9.0.0"),
+        ]));
+        let mut newest = entry("Firefox", "1.2.0");
+        newest.description = Some("Web browser".to_string());
+        let extra = Arc::new(RepoIndex::new(vec![newest, entry("Firefox", "1.0.0")]));
+        let indexes = vec![("main".to_string(), main), ("extra".to_string(), extra)];
+
+        let hits = search(&indexes, "fire");
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].version, "1.2.0");
+        assert_eq!(hits[0].repo, "extra");
+        assert_eq!(hits[0].versions, ["1.2.0", "1.0.0"]);
+        assert_eq!(hits[0].description.as_deref(), Some("Web browser"));
+        assert_eq!(search(&indexes, "").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_load_reuses_until_changed() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db_path = tmp.path().join("repository.db");
+        let repo_db = RepoDB::new(&db_path).await.unwrap();
+        repo_db.add_package("a", "1.0.0", "u1").await.unwrap();
+
+        let first = load(&db_path).await.unwrap();
+        let second = load(&db_path).await.unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+
+        repo_db.add_package("a", "2.0.0", "u2").await.unwrap();
+        repo_db.set_description("a", "2.0.0", "Tool").await.unwrap();
+        repo_db.pool().close().await;
+
+        let reloaded = load(&db_path).await.unwrap();
+        assert_eq!(reloaded.find("a").count(), 2);
+        let described: Vec<_> = reloaded
+            .find("a")
+            .filter_map(|e| e.description.clone())
+            .collect();
+        assert_eq!(described, ["Tool"]);
+    }
+}
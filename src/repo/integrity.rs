@@ -0,0 +1,247 @@
+//! # Repository Database Integrity
+//!
+//! Repository databases are cached in `~/.uhpm/cache/repo/<name>/` and
+//! decide which URLs packages are downloaded from, so a tampered cache
+//! would redirect installs. A downloaded `repository.db` is only put in
+//! place once it checks out against what the repository publishes next to
+//! it, both optional:
+//!
+//! - `repository.db.sha256`: its SHA-256 (the first word of the file);
+//!   a compressed `repository.db.zst` or `.gz` may have its own
+//!   `.sha256`, checked before it is unpacked;
+//! - `repository.db.sig`: a detached minisign or GPG signature, checked
+//!   against the repository's key (`~/.uhpm/keys/<name>.pub` or `.asc`)
+//!   or, if it has none, all trusted keys. With `require_signatures: true`
+//!   unsigned databases are refused.
+//!
+//! The hash of the accepted database is stored in `~/.uhpm/repo_hashes.ron`,
+//! outside the cache directory, so whatever rewrites a cached database
+//! does not rewrite its hash along with it; a cached database that no
+//! longer matches is refused until it is downloaded and verified again.
+//!
+//! Caches from before this file existed are migrated when first checked:
+//! the hash they kept in `verified.sha256` beside the database is moved
+//! over, and a database without one has its current hash recorded.
+
+use crate::config::Config;
+use crate::error::{RepoError, SigningError, UhpmError};
+use crate::package::archive_cache::sha256_file;
+use crate::repo::{cache_name, share};
+use crate::signing::{self, SignatureFormat, TrustedKey};
+use crate::{info, platform};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File holding the hashes of the verified databases, by repository
+fn hashes_path() -> PathBuf {
+    platform::uhpm_home().join("repo_hashes.ron")
+}
+
+/// Where older versions kept the hash of `db`
+fn legacy_hash_path(db: &Path) -> PathBuf {
+    db.with_file_name("verified.sha256")
+}
+
+fn load_hashes() -> BTreeMap<String, String> {
+    fs::read_to_string(hashes_path())
+        .ok()
+        .and_then(|s| ron::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+/// Records `hash` as the verified hash of `db`
+fn record_hash(db: &Path, hash: &str) -> std::io::Result<()> {
+    let mut hashes = load_hashes();
+    hashes.insert(cache_name(db), hash.to_string());
+    let path = hashes_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let data = ron::ser::to_string_pretty(&hashes, ron::ser::PrettyConfig::default())
+        .map_err(std::io::Error::other)?;
+    fs::write(path, data)
+}
+
+/// Checks a cached database against its stored hash
+///
+/// A database without a stored hash is migrated, see the module docs.
+pub fn check_cached(db: &Path) -> Result<(), RepoError> {
+    let actual = sha256_file(db)?;
+    let stored = match load_hashes().remove(&cache_name(db)) {
+        Some(stored) => stored,
+        None => {
+            let legacy = legacy_hash_path(db);
+            let stored = fs::read_to_string(&legacy)
+                .map(|s| s.trim().to_string())
+                .unwrap_or_else(|_| actual.clone());
+            record_hash(db, &stored)?;
+            let _ = fs::remove_file(&legacy);
+            info!("repo.cache.hash_migrated", cache_name(db));
+            stored
+        }
+    };
+    if stored != actual {
+        return Err(RepoError::Integrity(format!(
+            "{} does not match its verified hash",
+            db.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Verifies the freshly downloaded database `new` of repository `name` at
+/// `url` and moves it to `db`, recording its hash
+///
+/// `new` is deleted if it fails verification.
+pub async fn accept(name: &str, url: &str, new: &Path, db: &Path) -> Result<(), UhpmError> {
+    let result = verify(name, url, new).await;
+    let sig = signing::signature_path(new);
+    if sig.exists() {
+        fs::remove_file(&sig)?;
+    }
+    let hash = match result {
+        Ok(hash) => hash,
+        Err(e) => {
+            fs::remove_file(new)?;
+            return Err(e);
+        }
+    };
+    fs::rename(new, db)?;
+    record_hash(db, &hash)?;
+    Ok(())
+}
+
+/// Checks `path`, downloaded from `file_url` of repository `name`, against
+/// the checksum published as `<file_url>.sha256`, if any, and returns its
+/// hash
+pub async fn check_checksum(name: &str, file_url: &str, path: &Path) -> Result<String, UhpmError> {
+    let hash = sha256_file(path)?;
+    if let Some(published) = fetch_optional(&format!("{}.sha256", file_url)).await? {
+        let published = String::from_utf8_lossy(&published);
+        if published.split_whitespace().next() != Some(hash.as_str()) {
+            let file = file_url.rsplit('/').next().unwrap_or(file_url);
+            return Err(RepoError::Integrity(format!(
+                "{} of {} does not match its published checksum",
+                file, name
+            ))
+            .into());
+        }
+    }
+    Ok(hash)
+}
+
+/// Checks `new` against the published checksum and signature and returns
+/// its hash
+async fn verify(name: &str, url: &str, new: &Path) -> Result<String, UhpmError> {
+    let hash = check_checksum(name, &format!("{}/repository.db", url), new).await?;
+
+    match fetch_optional(&format!("{}/repository.db.sig", url)).await? {
+        Some(signature) => fs::write(signing::signature_path(new), signature)?,
+        None if !Config::load_or_default().require_signatures => return Ok(hash),
+        None => {}
+    }
+    let keys = match repo_key(name) {
+        Some(key) => vec![key],
+        None => signing::trusted_keys(),
+    };
+    match signing::verify_with(new, &keys) {
+        Ok(_) => Ok(hash),
+        Err(SigningError::NoTrustedKeys(_)) if !Config::load_or_default().require_signatures => {
+            Ok(hash)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The trusted key of repository `name`, if it has one
+fn repo_key(name: &str) -> Option<TrustedKey> {
+    let path = share::key_file(&signing::keys_dir(), name)?;
+    let format = match path.extension()?.to_str()? {
+        "pub" => SignatureFormat::Minisign,
+        _ => SignatureFormat::Gpg,
+    };
+    Some(TrustedKey { path, format })
+}
+
+/// Reads a file published by a repository, `None` if there is none
+async fn fetch_optional(url: &str) -> Result<Option<Vec<u8>>, UhpmError> {
+    if url.starts_with("http://") || url.starts_with("https://") {
+        let resp = reqwest::get(url).await?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let resp = resp.error_for_status()?;
+        return Ok(Some(resp.bytes().await?.to_vec()));
+    }
+    let path = Path::new(url.strip_prefix("file://").unwrap_or(url));
+    match fs::read(path) {
+        Ok(data) => Ok(Some(data)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_checksum_is_verified_and_recorded() {
+        let tmp = tempfile::tempdir().unwrap();
+        platform::with_root(tmp.path(), async {
+            let repo = tmp.path().join("repo");
+            fs::create_dir_all(&repo).unwrap();
+            fs::write(repo.join("repository.db"), "index").unwrap();
+            let published = sha256_file(&repo.join("repository.db")).unwrap();
+            fs::write(
+                repo.join("repository.db.sha256"),
+                format!("{}  repository.db\n", published),
+            )
+            .unwrap();
+
+            let cache = tmp.path().join("cache");
+            fs::create_dir_all(&cache).unwrap();
+            let db = cache.join("repository.db");
+            let url = format!("file://{}", repo.display());
+
+            let new = cache.join("repository.db.new");
+            fs::write(&new, "index").unwrap();
+            accept("main", &url, &new, &db).await.unwrap();
+            assert!(check_cached(&db).is_ok());
+
+            fs::write(&db, "poisoned").unwrap();
+            assert!(matches!(check_cached(&db), Err(RepoError::Integrity(_))));
+
+            fs::write(&new, "poisoned").unwrap();
+            assert!(matches!(
+                accept("main", &url, &new, &db).await,
+                Err(UhpmError::Repository(RepoError::Integrity(_)))
+            ));
+            assert!(!new.exists());
+            assert!(!cache.join("verified.sha256").exists());
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_old_caches_are_migrated() {
+        let tmp = tempfile::tempdir().unwrap();
+        platform::with_root(tmp.path(), async {
+            let kept = tmp.path().join("cache/kept/repository.db");
+            let bare = tmp.path().join("cache/bare/repository.db");
+            for db in [&kept, &bare] {
+                fs::create_dir_all(db.parent().unwrap()).unwrap();
+                fs::write(db, "index").unwrap();
+            }
+            fs::write(legacy_hash_path(&kept), "0000\n").unwrap();
+
+            assert!(check_cached(&bare).is_ok());
+            assert!(check_cached(&bare).is_ok());
+            assert!(matches!(check_cached(&kept), Err(RepoError::Integrity(_))));
+            assert!(!legacy_hash_path(&kept).exists());
+            assert_eq!(load_hashes()["kept"], "0000");
+        })
+        .await;
+    }
+}
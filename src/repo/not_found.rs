@@ -0,0 +1,147 @@
+//! # "Not Found" Reports
+//!
+//! A package missing from every repository is reported with what was
+//! searched, so the user knows where to look next: the repositories in the
+//! order the resolver consults them (a pinned package only in its
+//! repository, otherwise by priority, highest first), and the state of each
+//! cached index. An index that could not be refreshed in this run, or was
+//! never downloaded at all, may well be why the package was not found.
+//! Near-miss names from the indexes come with it (see [`crate::suggest`]).
+
+use crate::repo::{RepoMap, RepoPolicy, cache_db_path, integrity, refresh_outcome};
+use chrono::{DateTime, Local};
+use std::cmp::Reverse;
+use std::fs;
+
+/// State of the cached index of a repository
+#[derive(Debug, Clone, PartialEq)]
+pub enum CacheState {
+    /// Downloaded or confirmed current by this run
+    Refreshed,
+    /// Not refreshed by this run, because refreshing failed or the process
+    /// runs read-only; the copy from `modified` was used
+    Stale { modified: Option<DateTime<Local>> },
+    /// Failed its integrity check and was left out
+    Rejected,
+    /// Never downloaded
+    Missing,
+    /// Not consulted, the package is pinned to another repository
+    Excluded { pinned_to: String },
+}
+
+/// A repository consulted for a package
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchedRepo {
+    pub name: String,
+    pub url: String,
+    pub priority: i32,
+    pub cache: CacheState,
+}
+
+/// What was searched for a package that was not found
+#[derive(Debug, Clone, PartialEq)]
+pub struct NotFoundReport {
+    pub package: String,
+    /// Repositories in resolution order, excluded ones last
+    pub searched: Vec<SearchedRepo>,
+    /// Similar package names
+    pub suggestions: Vec<String>,
+}
+
+impl NotFoundReport {
+    /// Whether an index that was not refreshed may hide the package
+    pub fn has_outdated_index(&self) -> bool {
+        self.searched.iter().any(|repo| {
+            matches!(
+                repo.cache,
+                CacheState::Stale { .. } | CacheState::Rejected | CacheState::Missing
+            )
+        })
+    }
+}
+
+fn cache_state(name: &str, url: &str) -> CacheState {
+    let db = cache_db_path(name);
+    if !db.exists() {
+        return CacheState::Missing;
+    }
+    if integrity::check_cached(&db).is_err() {
+        return CacheState::Rejected;
+    }
+    match refresh_outcome(url) {
+        Some(true) => CacheState::Refreshed,
+        _ => CacheState::Stale {
+            modified: fs::metadata(&db)
+                .and_then(|meta| meta.modified())
+                .ok()
+                .map(DateTime::from),
+        },
+    }
+}
+
+/// The repositories searched for `package`, in resolution order
+pub fn searched(repos: &RepoMap, policy: &RepoPolicy, package: &str) -> Vec<SearchedRepo> {
+    let mut searched: Vec<SearchedRepo> = repos
+        .iter()
+        .map(|(name, url)| SearchedRepo {
+            name: name.clone(),
+            url: url.clone(),
+            priority: policy.priority(name),
+            cache: match policy.pinned_repo(package) {
+                Some(pinned) if pinned != name => CacheState::Excluded {
+                    pinned_to: pinned.to_string(),
+                },
+                _ => cache_state(name, url),
+            },
+        })
+        .collect();
+    searched.sort_by(|a, b| {
+        let key = |repo: &SearchedRepo| {
+            let excluded = matches!(repo.cache, CacheState::Excluded { .. });
+            (excluded, Reverse(repo.priority))
+        };
+        key(a).cmp(&key(b)).then_with(|| a.name.cmp(&b.name))
+    });
+    searched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::RepoConfig;
+
+    #[test]
+    fn test_searched_in_resolution_order() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("repos.ron");
+        fs::write(
+            &path,
+            r#"{
+                "main": "https://example.com/main",
+                "testing": (url: "https://example.com/testing", priority: -5),
+                "vendor": (url: "https://vendor.example", priority: 10, pins: ["vendor-cli"]),
+                "extra": "https://example.com/extra",
+            }"#,
+        )
+        .unwrap();
+        let config = RepoConfig::load(&path).unwrap();
+        let policy = config.policy();
+
+        let names = |pkg: &str| -> Vec<String> {
+            searched(&config.repos(), &policy, pkg)
+                .into_iter()
+                .map(|repo| repo.name)
+                .collect()
+        };
+        assert_eq!(names("tool"), ["vendor", "extra", "main", "testing"]);
+
+        let pinned = searched(&config.repos(), &policy, "vendor-cli");
+        assert_eq!(pinned[0].name, "vendor");
+        assert_eq!(
+            pinned[1].cache,
+            CacheState::Excluded {
+                pinned_to: "vendor".to_string()
+            }
+        );
+    }
+}
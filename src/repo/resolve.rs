@@ -0,0 +1,179 @@
+//! # Repository-Side Resolution
+//!
+//! Resolving locally needs the whole `repository.db` of every configured
+//! repository. An HTTP repository may spare thin clients that download by
+//! answering `GET <repo>/resolve?pkg=<name>&ver=<version>` (`ver` only when
+//! a version was asked for) with the package and its dependency closure:
+//!
+//! ```json
+//! {
+//!   "packages": [
+//!     { "name": "app", "version": "1.4.0", "url": "app-1.4.0.uhp" },
+//!     { "name": "lib", "version": "1.2.3", "url": "https://cdn.example/lib-1.2.3.uhp" }
+//!   ]
+//! }
+//! ```
+//!
+//! URLs are resolved against the repository URL. The set is treated as a
+//! small index of its own: the [`Resolver`](crate::resolver::Resolver)
+//! still checks versions, skips installed dependencies and orders the
+//! result, and archives are verified as usual. Repositories are asked in
+//! the order of their pins and priorities; when none answers, or the
+//! answer does not resolve, the caller falls back to the local indexes.
+//!
+//! The answer is not signed, so repositories with a signing key of their
+//! own (see [`share`](crate::repo::share)) are never asked, and no
+//! repository is when `require_signatures` is set: their packages are
+//! resolved from the verified `repository.db`.
+
+use crate::config::Config;
+use crate::error::FetchError;
+use crate::repo::index::{IndexEntry, RepoIndex};
+use crate::repo::{RepoMap, RepoPolicy, share};
+use crate::{debug, info, signing};
+use reqwest::{StatusCode, Url};
+use serde::Deserialize;
+use std::cmp::Reverse;
+
+/// Answer of a `resolve` endpoint
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ResolvedSet {
+    pub packages: Vec<ResolvedPackage>,
+}
+
+/// A package of a [`ResolvedSet`]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ResolvedPackage {
+    pub name: String,
+    pub version: String,
+    pub url: String,
+}
+
+/// URL of the `resolve` endpoint of repository `repo_url`; only HTTP
+/// repositories have one
+pub fn endpoint(repo_url: &str, pkg: &str, version: Option<&str>) -> Option<Url> {
+    if !repo_url.starts_with("http://") && !repo_url.starts_with("https://") {
+        return None;
+    }
+    let mut url = Url::parse(&format!("{}/resolve", repo_url.trim_end_matches('/'))).ok()?;
+    url.query_pairs_mut().append_pair("pkg", pkg);
+    if let Some(version) = version {
+        url.query_pairs_mut().append_pair("ver", version);
+    }
+    Some(url)
+}
+
+/// Turns a resolved set into an index, resolving URLs against the
+/// repository URL
+pub fn to_index(repo_url: &str, set: ResolvedSet) -> RepoIndex {
+    let base = Url::parse(&format!("{}/", repo_url.trim_end_matches('/'))).ok();
+    RepoIndex::new(
+        set.packages
+            .into_iter()
+            .map(|pkg| IndexEntry {
+                url: base
+                    .as_ref()
+                    .and_then(|base| base.join(&pkg.url).ok())
+                    .map(String::from)
+                    .unwrap_or(pkg.url),
+                name: pkg.name,
+                version: pkg.version,
+                description: None,
+            })
+            .collect(),
+    )
+}
+
+/// Asks repository `repo_url` to resolve `pkg`; `None` if it has no
+/// `resolve` endpoint
+pub async fn fetch(
+    repo_url: &str,
+    pkg: &str,
+    version: Option<&str>,
+) -> Result<Option<RepoIndex>, FetchError> {
+    let Some(url) = endpoint(repo_url, pkg, version) else {
+        return Ok(None);
+    };
+    let response = reqwest::get(url).await?;
+    if matches!(
+        response.status(),
+        StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+    ) {
+        return Ok(None);
+    }
+    let body = response.error_for_status()?.bytes().await?;
+    let set: ResolvedSet = serde_json::from_slice(&body)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    Ok(Some(to_index(repo_url, set)))
+}
+
+/// Asks the configured repositories in turn until one resolves `pkg`
+///
+/// Returns the repository name and the resolved set. Repositories the
+/// package may not come from or that sign their index are skipped, and
+/// none is asked when signatures are required; failures are logged and
+/// skipped.
+pub async fn remote_index(
+    repos: &RepoMap,
+    policy: &RepoPolicy,
+    pkg: &str,
+    version: Option<&str>,
+) -> Option<(String, RepoIndex)> {
+    if Config::load_or_default().require_signatures {
+        return None;
+    }
+    let keys_dir = signing::keys_dir();
+    let mut candidates: Vec<(&String, &String)> = repos
+        .iter()
+        .filter(|(name, _)| policy.allows(pkg, name))
+        .filter(|(name, _)| share::key_file(&keys_dir, name).is_none())
+        .collect();
+    candidates.sort_by_key(|(name, _)| (Reverse(policy.priority(name)), name.as_str()));
+
+    for (name, url) in candidates {
+        match fetch(url, pkg, version).await {
+            Ok(Some(index)) if index.find(pkg).next().is_some() => {
+                info!("repo.resolve.used", pkg, name);
+                return Some((name.clone(), index));
+            }
+            Ok(_) => debug!("repo.resolve.unavailable", name),
+            Err(e) => debug!("repo.resolve.failed", name, e),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_and_index() {
+        assert_eq!(
+            endpoint("https://example.com/uhpm/", "app", Some("1.4.0"))
+                .unwrap()
+                .as_str(),
+            "https://example.com/uhpm/resolve?pkg=app&ver=1.4.0"
+        );
+        assert!(endpoint("file:///srv/uhpm", "app", None).is_none());
+
+        let set: ResolvedSet = serde_json::from_str(
+            r#"{"packages": [
+                {"name": "app", "version": "1.4.0", "url": "app-1.4.0.uhp"},
+                {"name": "lib", "version": "1.2.3", "url": "https://cdn.example/lib.uhp"},
+                {"name": "zlib", "version": "1.3.1", "url": "/pool/zlib-1.3.1.uhp"}
+            ]}"#,
+        )
+        .unwrap();
+        let index = to_index("https://example.com/uhpm", set);
+        let urls: Vec<&str> = index.entries().iter().map(|e| e.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "https://example.com/uhpm/app-1.4.0.uhp",
+                "https://cdn.example/lib.uhp",
+                "https://example.com/pool/zlib-1.3.1.uhp"
+            ]
+        );
+    }
+}
@@ -0,0 +1,636 @@
+//! # Dependency Resolver
+//!
+//! Turns "install `foo`" into an ordered list of packages to install: the
+//! dependency closure of `foo` that is not installed yet, dependencies
+//! first, `foo` last.
+//!
+//! ## Resolution
+//! - A declared dependency `lib 1.2.0` is satisfied by any installed or
+//!   available version compatible with `^1.2.0`; the declared version
+//!   itself is preferred, otherwise the newest compatible one is taken.
+//! - Installed dependencies that satisfy the requirement end the walk,
+//!   unless the resolver [ignores installed packages](Resolver::ignoring_installed).
+//! - Repository databases only list names, versions and URLs, so the
+//!   archives of the packages to install are downloaded while walking to
+//!   read their `uhp.toml`; the installer later reuses those downloads.
+//! - When several repositories carry a package, pins and priorities from
+//!   `repos.ron` decide (see [`RepoPolicy`]).
+//! - Versions on the `ignore` list of `config.ron` never satisfy a
+//!   dependency (see [`crate::package::ignore`]); a dependency only
+//!   available in ignored versions fails the resolution.
+//! - HTTP repositories may resolve a package themselves, sparing the
+//!   download of their databases (see [`crate::repo::resolve`]); their
+//!   answer goes through the same checks.
+//! - Two incompatible requirements for the same package, a dependency that
+//!   no repository provides, or a dependency cycle fail the resolution
+//!   before anything is installed.
+//!
+//! ## Conflicts
+//! The first version selected for a package stays; a later requirement it
+//! does not satisfy is a [`Conflict`]. Its message shows every requirement
+//! involved with the chain of packages that led to it, e.g.
+//!
+//! ```text
+//! no version of lib satisfies every requirement
+//!   lib ^1.2.0 (selected 1.4.0) is required by
+//!     foo 1.0.0
+//!     └── app 1.0.0
+//!   lib ^2.0.0 is required by
+//!     bar 1.0.0
+//!     └── app 1.0.0
+//! ```
+
+use crate::db::PackageDB;
+use crate::error::UhpmError;
+use crate::package::ignore::IgnoreList;
+use crate::package::{Package, Provenance, installer};
+use crate::repo::index::{self, IndexEntry, RepoIndex};
+use crate::repo::{self, RepoPolicy};
+use crate::{debug, fetcher};
+use semver::{Version, VersionReq};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// A package the resolver decided to install
+#[derive(Debug)]
+pub struct Step {
+    pub name: String,
+    pub version: Version,
+    /// Repository URL the archive was fetched from
+    pub url: String,
+    /// Origin recorded for the installed package
+    pub provenance: Provenance,
+    /// Downloaded archive
+    pub archive: PathBuf,
+    /// Metadata read from the archive
+    pub meta: Package,
+}
+
+/// A dependency declared by a package, with the packages that led to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Requirement {
+    /// Declared version, meaning `^declared`
+    pub declared: Version,
+    /// The declaring package first, then the package that required it,
+    /// and so on up to the package being installed
+    pub path: Vec<(String, Version)>,
+}
+
+/// Requirements for a package that no single version satisfies
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    pub package: String,
+    /// Version selected for the package first
+    pub selected: Version,
+    /// Requirements `selected` satisfies; empty when the package is the
+    /// one being installed
+    pub selected_for: Vec<Requirement>,
+    /// The requirement `selected` does not satisfy
+    pub rejected: Requirement,
+}
+
+impl Conflict {
+    fn write_requirement(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        req: &Requirement,
+        selected: bool,
+    ) -> fmt::Result {
+        write!(f, "\n  {} ^{}", self.package, req.declared)?;
+        if selected {
+            write!(f, " (selected {})", self.selected)?;
+        }
+        write!(f, " is required by")?;
+        for (depth, (name, version)) in req.path.iter().enumerate() {
+            let branch = if depth == 0 { "" } else { "└── " };
+            let indent = "    ".repeat(depth.saturating_sub(1));
+            write!(f, "\n    {}{}{} {}", indent, branch, name, version)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "no version of {} satisfies every requirement",
+            self.package
+        )?;
+        if self.selected_for.is_empty() {
+            write!(
+                f,
+                "\n  {} {} is the package being installed",
+                self.package, self.selected
+            )?;
+        }
+        for req in &self.selected_for {
+            self.write_requirement(f, req, true)?;
+        }
+        self.write_requirement(f, &self.rejected, false)
+    }
+}
+
+impl From<Box<Conflict>> for UhpmError {
+    fn from(conflict: Box<Conflict>) -> Self {
+        UhpmError::Conflict(conflict.to_string())
+    }
+}
+
+/// Requirements accepted while walking, for explaining conflicts
+#[derive(Default)]
+struct Requirements {
+    /// Selected version of each package
+    chosen: HashMap<String, Version>,
+    /// Declared versions and declaring packages, first requirement first
+    of: HashMap<String, Vec<(Version, String)>>,
+}
+
+impl Requirements {
+    /// `name` and the packages that led to it, up to the package being
+    /// installed
+    ///
+    /// Dependency cycles make the package being installed a dependency
+    /// too, so the walk stops at the first package it already passed.
+    fn path(&self, name: &str) -> Vec<(String, Version)> {
+        let mut path = Vec::new();
+        let mut visited = HashSet::new();
+        let mut current = Some(name.to_string());
+        while let Some(name) = current.filter(|name| visited.insert(name.clone())) {
+            current = self
+                .of
+                .get(&name)
+                .and_then(|reqs| reqs.first())
+                .map(|(_, parent)| parent.clone());
+            let version = self
+                .chosen
+                .get(&name)
+                .cloned()
+                .unwrap_or(Version::new(0, 0, 0));
+            path.push((name, version));
+        }
+        path
+    }
+
+    fn requirement(&self, declared: &Version, parent: &str) -> Requirement {
+        Requirement {
+            declared: declared.clone(),
+            path: self.path(parent),
+        }
+    }
+
+    /// Records that `parent` requires `dep` as `declared`, which the
+    /// version selected for `dep`, if any, must satisfy
+    fn require(
+        &mut self,
+        dep: &str,
+        declared: &Version,
+        parent: &str,
+    ) -> Result<(), Box<Conflict>> {
+        if let Some(selected) = self.chosen.get(dep)
+            && !satisfies(selected, declared)
+        {
+            return Err(Box::new(Conflict {
+                package: dep.to_string(),
+                selected: selected.clone(),
+                selected_for: self
+                    .of
+                    .get(dep)
+                    .into_iter()
+                    .flatten()
+                    .map(|(declared, parent)| self.requirement(declared, parent))
+                    .collect(),
+                rejected: self.requirement(declared, parent),
+            }));
+        }
+        self.of
+            .entry(dep.to_string())
+            .or_default()
+            .push((declared.clone(), parent.to_string()));
+        Ok(())
+    }
+}
+
+/// Whether `version` satisfies a dependency declared as `declared`
+pub fn satisfies(version: &Version, declared: &Version) -> bool {
+    version == declared
+        || VersionReq::parse(&format!("^{}", declared)).is_ok_and(|req| req.matches(version))
+}
+
+/// Dependencies of `pkg` that are not installed in a compatible version
+pub async fn unmet_dependencies(
+    pkg: &Package,
+    db: &PackageDB,
+) -> Result<Vec<(String, Version)>, UhpmError> {
+    let mut unmet = Vec::new();
+    for (name, declared) in pkg.dependencies() {
+        let installed = db
+            .get_package_version(&name)
+            .await?
+            .and_then(|v| Version::parse(&v).ok());
+        if !installed.is_some_and(|v| satisfies(&v, &declared)) {
+            unmet.push((name, declared));
+        }
+    }
+    Ok(unmet)
+}
+
+/// Resolves packages against a set of repository indexes
+pub struct Resolver<'a> {
+    db: &'a PackageDB,
+    /// Repository names and their indexes
+    indexes: Vec<(String, Arc<RepoIndex>)>,
+    policy: RepoPolicy,
+    ignore: IgnoreList,
+    /// Resolve installed dependencies from the repositories as well
+    ignore_installed: bool,
+}
+
+impl<'a> Resolver<'a> {
+    /// Loads the indexes of the given repository databases
+    ///
+    /// Databases that do not exist are skipped. Each database is named after
+    /// its directory, as laid out by [`crate::repo::cache_repo`].
+    pub async fn new(db: &'a PackageDB, repo_dbs: &[PathBuf]) -> Result<Self, UhpmError> {
+        let mut indexes = Vec::new();
+        for path in repo_dbs.iter().filter(|p| p.exists()) {
+            let name = repo::cache_name(path);
+            let index = index::load_repo(&name, path).await?;
+            indexes.push((name, index));
+        }
+        Ok(Self {
+            db,
+            indexes,
+            policy: RepoPolicy::load(),
+            ignore: IgnoreList::load(),
+            ignore_installed: false,
+        })
+    }
+
+    /// Resolves against a single index, such as the set a repository
+    /// resolved itself (see [`crate::repo::resolve`])
+    pub fn with_index(db: &'a PackageDB, repo: String, index: RepoIndex) -> Self {
+        Self {
+            db,
+            indexes: vec![(repo, Arc::new(index))],
+            policy: RepoPolicy::load(),
+            ignore: IgnoreList::load(),
+            ignore_installed: false,
+        }
+    }
+
+    /// Resolves the whole dependency closure from the repositories, even
+    /// dependencies that are installed here, as for a bundle (see
+    /// [`crate::package::bundle`])
+    pub fn ignoring_installed(mut self) -> Self {
+        self.ignore_installed = true;
+        self
+    }
+
+    /// Picks the entry for `name` matching `filter`: from the repository it
+    /// is pinned to, if any, otherwise the newest version from the
+    /// repositories with the highest priority
+    fn pick(&self, name: &str, filter: impl Fn(&Version) -> bool) -> Option<(IndexEntry, Version)> {
+        self.indexes
+            .iter()
+            .filter(|(repo, _)| self.policy.allows(name, repo))
+            .flat_map(|(repo, idx)| idx.find(name).map(move |e| (self.policy.priority(repo), e)))
+            .filter_map(|(priority, e)| {
+                Version::parse(&e.version)
+                    .ok()
+                    .map(|v| (priority, e.clone(), v))
+            })
+            .filter(|(_, _, v)| filter(v))
+            .max_by(|a, b| (a.0, &a.2).cmp(&(b.0, &b.2)))
+            .map(|(_, entry, version)| (entry, version))
+    }
+
+    /// Picks the entry satisfying a declared dependency, leaving out
+    /// ignored versions
+    fn pick_compatible(&self, name: &str, declared: &Version) -> Option<(IndexEntry, Version)> {
+        let allowed = |v: &Version| !self.ignore.ignores(name, &v.to_string());
+        self.pick(name, |v| v == declared && allowed(v))
+            .or_else(|| self.pick(name, |v| satisfies(v, declared) && allowed(v)))
+    }
+
+    /// Error for a dependency no repository provides in a version that
+    /// is not ignored
+    fn unavailable(&self, dep: &str, declared: &Version, parent: &str) -> UhpmError {
+        let ignored = self
+            .pick(dep, |v| satisfies(v, declared))
+            .and_then(|(_, version)| self.ignore.rule(dep, &version.to_string()));
+        match ignored {
+            Some(rule) => UhpmError::Conflict(format!(
+                "{} {} (required by {}) is only available in versions ignored by `{}` in config.ron",
+                dep, declared, parent, rule
+            )),
+            None => UhpmError::NotFound(format!("{} {} (required by {})", dep, declared, parent)),
+        }
+    }
+
+    /// Plans the installation of `name` (the newest version, or exactly
+    /// `version`) together with its missing dependencies
+    pub async fn plan(&self, name: &str, version: Option<&str>) -> Result<Vec<Step>, UhpmError> {
+        let (entry, parsed) = self
+            .pick(name, |v| version.is_none_or(|want| v.to_string() == want))
+            .ok_or_else(|| UhpmError::NotFound(name.to_string()))?;
+        let root = self.download(vec![(entry, parsed)]).await?.remove(0);
+        let (name, version, deps) = (
+            root.name.clone(),
+            root.version.clone(),
+            root.meta.dependencies(),
+        );
+        self.walk(&name, &version, deps, Some(root)).await
+    }
+
+    /// Plans the installation of the missing dependencies of `pkg`, which
+    /// itself is installed by the caller
+    pub async fn plan_dependencies(&self, pkg: &Package) -> Result<Vec<Step>, UhpmError> {
+        self.walk(pkg.name(), pkg.version(), pkg.dependencies(), None)
+            .await
+    }
+
+    /// Downloads archives and reads their metadata
+    async fn download(&self, picks: Vec<(IndexEntry, Version)>) -> Result<Vec<Step>, UhpmError> {
+        let urls: Vec<String> = picks.iter().map(|(e, _)| e.url.clone()).collect();
+        let downloaded = fetcher::fetch_packages(&urls).await;
+
+        let mut steps = Vec::new();
+        for (entry, version) in picks {
+            let archive = downloaded
+                .get(&entry.url)
+                .ok_or_else(|| UhpmError::Package(format!("Failed to download {}", entry.url)))?
+                .clone();
+            let meta = installer::read_meta(&archive)?;
+            steps.push(Step {
+                name: entry.name,
+                version,
+                provenance: Provenance::from_url(&entry.url),
+                url: entry.url,
+                archive,
+                meta,
+            });
+        }
+        Ok(steps)
+    }
+
+    /// Walks the dependencies of `root` breadth first, one download batch
+    /// per level, and orders the result
+    async fn walk(
+        &self,
+        root_name: &str,
+        root_version: &Version,
+        root_deps: Vec<(String, Version)>,
+        root_step: Option<Step>,
+    ) -> Result<Vec<Step>, UhpmError> {
+        let root_name = root_name.to_string();
+        let mut reqs = Requirements::default();
+        reqs.chosen.insert(root_name.clone(), root_version.clone());
+        let mut steps: BTreeMap<String, Step> = BTreeMap::new();
+        let mut edges: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        if let Some(step) = root_step {
+            steps.insert(root_name.clone(), step);
+        }
+
+        let mut level: Vec<(String, Version, String)> = root_deps
+            .into_iter()
+            .map(|(dep, declared)| (dep, declared, root_name.clone()))
+            .collect();
+
+        while !level.is_empty() {
+            let mut picks = Vec::new();
+            for (dep, declared, parent) in level.drain(..) {
+                if reqs.chosen.contains_key(&dep) {
+                    reqs.require(&dep, &declared, &parent)?;
+                    edges.entry(parent).or_default().insert(dep);
+                    continue;
+                }
+
+                let installed = if self.ignore_installed {
+                    None
+                } else {
+                    self.db
+                        .get_package_version(&dep)
+                        .await?
+                        .and_then(|v| Version::parse(&v).ok())
+                };
+                if let Some(installed) = installed
+                    && satisfies(&installed, &declared)
+                {
+                    debug!("resolver.already_installed", &dep, &installed.to_string());
+                    continue;
+                }
+
+                let (entry, version) = self
+                    .pick_compatible(&dep, &declared)
+                    .ok_or_else(|| self.unavailable(&dep, &declared, &parent))?;
+                debug!("resolver.selected", &dep, &version.to_string(), &parent);
+                reqs.chosen.insert(dep.clone(), version.clone());
+                reqs.require(&dep, &declared, &parent)?;
+                edges.entry(parent).or_default().insert(dep);
+                picks.push((entry, version));
+            }
+
+            for step in self.download(picks).await? {
+                for (dep, declared) in step.meta.dependencies() {
+                    level.push((dep, declared, step.name.clone()));
+                }
+                steps.insert(step.name.clone(), step);
+            }
+        }
+
+        let order = topo_order(&steps.keys().cloned().collect(), &edges)?;
+        Ok(order
+            .into_iter()
+            .filter_map(|name| steps.remove(&name))
+            .collect())
+    }
+}
+
+/// Orders `nodes` so that every package comes after its dependencies
+///
+/// Edges point from a package to its dependencies; edges to packages
+/// outside `nodes` (already installed or the caller's package) are ignored.
+fn topo_order(
+    nodes: &BTreeSet<String>,
+    edges: &BTreeMap<String, BTreeSet<String>>,
+) -> Result<Vec<String>, UhpmError> {
+    let deps_of = |name: &String| -> BTreeSet<String> {
+        edges
+            .get(name)
+            .map(|deps| deps.intersection(nodes).cloned().collect())
+            .unwrap_or_default()
+    };
+
+    let mut remaining: BTreeMap<String, BTreeSet<String>> =
+        nodes.iter().map(|n| (n.clone(), deps_of(n))).collect();
+    let mut order = Vec::new();
+    while !remaining.is_empty() {
+        let ready: Vec<String> = remaining
+            .iter()
+            .filter(|(_, deps)| deps.is_empty())
+            .map(|(name, _)| name.clone())
+            .collect();
+        if ready.is_empty() {
+            let cycle: Vec<&str> = remaining.keys().map(String::as_str).collect();
+            return Err(UhpmError::Conflict(format!(
+                "dependency cycle between {}",
+                cycle.join(", ")
+            )));
+        }
+        for name in &ready {
+            remaining.remove(name);
+        }
+        for deps in remaining.values_mut() {
+            for name in &ready {
+                deps.remove(name);
+            }
+        }
+        order.extend(ready);
+    }
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_satisfies() {
+        let declared = Version::parse("1.2.0").unwrap();
+        assert!(satisfies(&Version::parse("1.2.0").unwrap(), &declared));
+        assert!(satisfies(&Version::parse("1.4.1").unwrap(), &declared));
+        assert!(!satisfies(&Version::parse("2.0.0").unwrap(), &declared));
+        assert!(!satisfies(&Version::parse("1.1.9").unwrap(), &declared));
+    }
+
+    #[test]
+    fn test_conflict_explains_requirements() {
+        let v = |s: &str| Version::parse(s).unwrap();
+        let mut reqs = Requirements::default();
+        for (name, version) in [("app", "1.0.0"), ("foo", "1.0.0"), ("bar", "2.0.0")] {
+            reqs.chosen.insert(name.to_string(), v(version));
+        }
+        reqs.require("foo", &v("1.0.0"), "app").unwrap();
+        reqs.require("bar", &v("2.0.0"), "app").unwrap();
+        reqs.chosen.insert("lib".to_string(), v("1.4.0"));
+        reqs.require("lib", &v("1.2.0"), "foo").unwrap();
+        reqs.require("lib", &v("1.4.0"), "app").unwrap();
+
+        let conflict = reqs.require("lib", &v("2.0.0"), "bar").unwrap_err();
+        assert_eq!(conflict.selected_for.len(), 2);
+        assert_eq!(
+            conflict.to_string(),
+            "no version of lib satisfies every requirement
+  lib ^1.2.0 (selected 1.4.0) is required by
+    foo 1.0.0
+    └── app 1.0.0
+  lib ^1.4.0 (selected 1.4.0) is required by
+    app 1.0.0
+  lib ^2.0.0 is required by
+    bar 2.0.0
+    └── app 1.0.0"
+        );
+
+        let cyclic = reqs.require("app", &v("2.0.0"), "lib").unwrap_err();
+        assert_eq!(
+            cyclic.to_string(),
+            "no version of app satisfies every requirement
+  app 1.0.0 is the package being installed
+  app ^2.0.0 is required by
+    lib 1.4.0
+    └── foo 1.0.0
+        └── app 1.0.0"
+        );
+    }
+
+    #[test]
+    fn test_requirement_path_stops_at_cycles() {
+        let v = |s: &str| Version::parse(s).unwrap();
+        let mut reqs = Requirements::default();
+        reqs.chosen.insert("app".to_string(), v("1.0.0"));
+        reqs.chosen.insert("lib".to_string(), v("1.0.0"));
+        reqs.require("lib", &v("1.0.0"), "app").unwrap();
+        reqs.require("app", &v("1.0.0"), "lib").unwrap();
+
+        let names: Vec<String> = reqs.path("lib").into_iter().map(|(n, _)| n).collect();
+        assert_eq!(names, ["lib", "app"]);
+    }
+
+    #[test]
+    fn test_topo_order_dependencies_first() {
+        let nodes = set(&["app", "lib", "base"]);
+        let edges = BTreeMap::from([
+            ("app".to_string(), set(&["lib", "base"])),
+            ("lib".to_string(), set(&["base", "installed"])),
+        ]);
+        assert_eq!(
+            topo_order(&nodes, &edges).unwrap(),
+            vec!["base", "lib", "app"]
+        );
+
+        let cyclic = BTreeMap::from([
+            ("a".to_string(), set(&["b"])),
+            ("b".to_string(), set(&["a"])),
+        ]);
+        assert!(matches!(
+            topo_order(&set(&["a", "b"]), &cyclic),
+            Err(UhpmError::Conflict(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_pick_honors_priority_and_pins() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = PackageDB::new(&tmp.path().join("packages.db"))
+            .unwrap()
+            .init()
+            .await
+            .unwrap();
+        let entry = |version: &str, repo: &str| IndexEntry {
+            name: "tool".into(),
+            version: version.into(),
+            url: format!("{}/tool-{}.uhp", repo, version),
+            description: None,
+        };
+        let mut config = crate::repo::RepoConfig::load(&tmp.path().join("repos.ron")).unwrap();
+        config
+            .add("stable", "https://stable.example", false)
+            .unwrap();
+        config.add("edge", "https://edge.example", false).unwrap();
+        config.set_priority("stable", 10).unwrap();
+
+        let mut resolver = Resolver {
+            db: &db,
+            indexes: vec![
+                (
+                    "stable".to_string(),
+                    Arc::new(RepoIndex::new(vec![entry("1.0.0", "stable")])),
+                ),
+                (
+                    "edge".to_string(),
+                    Arc::new(RepoIndex::new(vec![entry("2.0.0", "edge")])),
+                ),
+            ],
+            policy: config.policy(),
+            ignore: IgnoreList::default(),
+            ignore_installed: false,
+        };
+        let picked = |r: &Resolver| r.pick("tool", |_| true).unwrap().0.url;
+        assert_eq!(picked(&resolver), "stable/tool-1.0.0.uhp");
+
+        config.set_priority("stable", 0).unwrap();
+        resolver.policy = config.policy();
+        assert_eq!(picked(&resolver), "edge/tool-2.0.0.uhp");
+
+        config.pin("tool", "stable").unwrap();
+        resolver.policy = config.policy();
+        assert_eq!(picked(&resolver), "stable/tool-1.0.0.uhp");
+    }
+}
@@ -1,10 +1,47 @@
+use crate::config::{self, Config, Profiles};
 use crate::db::PackageDB;
-use crate::error::{ConfigError, UhpmError};
-use crate::package::{installer, remover, switcher, updater};
-use crate::repo::{RepoDB, cache_repo, parse_repos};
-use crate::{fetcher, repo};
+use crate::error::{UhpmError, UpdaterError};
+use crate::history::{self, Snapshot, Transaction, UndoSource, UndoStep, VersionChange};
+use crate::package::bundle::{self, BundleManifest, OpenedBundle};
+use crate::package::dry_run::{self, Change};
+use crate::package::env::{self, Environment};
+use crate::package::ignore::IgnoreList;
+use crate::package::info::{self, PackageInfo};
+use crate::package::licenses::{self, PackageLicense};
+use crate::package::links::{self, DanglingLink, Relinked};
+use crate::package::listing::{self, InstalledPackage, ListFilter};
+use crate::package::maintenance::{
+    AvailableUpdate, Details, Failure, MaintenanceReport, Step as MaintenanceStep,
+};
+use crate::package::remover::RemovalSummary;
+use crate::package::requirements::{self, RequirementPolicy};
+use crate::package::retention::{self, VersionUse};
+use crate::package::toolchain::{ToolState, ToolStatus, Toolchain, ToolchainReport};
+use crate::package::tree::{self, PackageNode};
+use crate::package::updater::UpdateSummary;
+use crate::package::verify::{self, PackageCheck, Problem};
+use crate::package::which::{self, OwnsReport, WhichReport};
+use crate::package::{
+    InstallReason, LinkMode, Provenance, VersionSpec, archive_cache, autoremove, bin_layout,
+    builder, docs, installer, packages_dir, profile, remover, runner, switcher, updater,
+};
+use crate::query::{self, Query};
+use crate::repo::cache::StaleCache;
+use crate::repo::index::{self, RepoIndex, SearchHit};
+use crate::repo::not_found::{self, NotFoundReport};
+use crate::repo::share::{self, SharedRepos};
+use crate::repo::{
+    Refresh, RepoConfig, RepoEntry, RepoPolicy, cache_repo, cached_repo_dbs, parse_repos, resolve,
+};
+use crate::resolver::{self, Resolver};
+use crate::usage::{self, InstallStats, UsageKind, UsageReport};
+use crate::{access, archive, fetcher, info, package, platform, repo, signing, suggest, warn};
 use semver::Version;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
 
 pub struct PackageService {
     db: PackageDB,
@@ -15,77 +52,360 @@ impl PackageService {
         Self { db }
     }
 
+    /// Path of the package database.
+    pub fn db_path(&self) -> &Path {
+        self.db.path()
+    }
+
     pub async fn install_from_file(&self, path: &Path, direct: bool) -> Result<(), UhpmError> {
+        let meta = installer::read_meta(path)?;
+        self.install_dependencies(&meta, direct).await?;
         installer::install(path, &self.db, direct).await?;
+        self.db
+            .set_install_reason(meta.name(), InstallReason::Explicit)
+            .await?;
+        usage::record(&self.db, UsageKind::Install, meta.name()).await;
         Ok(())
     }
 
-    pub async fn extract_package(&self, path: &Path) -> Result<(), UhpmError> {
-        installer::unpack(path)?;
-        Ok(())
+    /// Extracts a package archive without registering it
+    ///
+    /// `dest` defaults to `./<name>-<version>` and must not be a non-empty
+    /// directory. Returns the directory the package was extracted to.
+    pub async fn extract_package(
+        &self,
+        path: &Path,
+        dest: Option<&Path>,
+    ) -> Result<PathBuf, UhpmError> {
+        let dest = match dest {
+            Some(dest) => dest.to_path_buf(),
+            None => {
+                let meta = installer::read_meta(path)?;
+                PathBuf::from(format!("{}-{}", meta.name(), meta.version()))
+            }
+        };
+        let occupied = dest.is_file()
+            || std::fs::read_dir(&dest).is_ok_and(|mut entries| entries.next().is_some());
+        if occupied {
+            return Err(UhpmError::Conflict(format!(
+                "{} already exists",
+                dest.display()
+            )));
+        }
+        archive::extract_package(path, &dest, &Config::load_or_default().extract)?;
+        Ok(dest)
     }
 
+    /// Installs a package from the repositories together with its missing
+    /// dependencies, dependencies first.
     pub async fn install_from_repo(
         &self,
         package_name: &str,
         version: Option<&str>,
         direct: bool,
     ) -> Result<(), UhpmError> {
-        let repos = cache_repo(self.load_repositories().await.unwrap()).await;
-        let mut urls_to_download = Vec::new();
-        let mut found = false;
-
-        for repo_path in &repos {
-            if !repo_path.exists() {
-                tracing::warn!(
-                    "Repository database not found: {}",
-                    repo_path.to_str().unwrap()
-                );
-                continue;
+        if let Some(archive) = version.and_then(|v| archive_cache::lookup(package_name, v)) {
+            self.install_dependencies(&installer::read_meta(&archive)?, direct)
+                .await?;
+            installer::install_transactional(
+                &archive,
+                &self.db,
+                direct,
+                Provenance::Cache {
+                    archive: archive.to_string_lossy().to_string(),
+                },
+            )
+            .await?;
+            self.db
+                .set_install_reason(package_name, InstallReason::Explicit)
+                .await?;
+            usage::record(&self.db, UsageKind::Install, package_name).await;
+            return Ok(());
+        }
+
+        let repos = self.load_repositories().await?;
+        let plan = match self.plan_remote(&repos, package_name, version).await {
+            Some(plan) => plan,
+            None => {
+                let repos = cache_repo(repos).await;
+                Resolver::new(&self.db, &repos)
+                    .await?
+                    .plan(package_name, version)
+                    .await?
             }
+        };
+        self.install_steps(plan, direct).await?;
+        self.db
+            .set_install_reason(package_name, InstallReason::Explicit)
+            .await?;
+        Ok(())
+    }
 
-            let repo_db = RepoDB::new(&repo_path).await?;
-            let packages = repo_db.list_packages().await?;
+    /// Builds a package from the build script a repository offers for it
+    /// and installs the result, missing dependencies first; returns the
+    /// version built.
+    pub async fn install_from_source(
+        &self,
+        package_name: &str,
+        spec: Option<&VersionSpec>,
+        direct: bool,
+    ) -> Result<Version, UhpmError> {
+        let repos = cache_repo(self.load_repositories().await?).await;
+        let source = builder::find_source(&repos, package_name, spec).await?;
+        let work = builder::work_dir(&source);
+        let archive = match builder::build(&source, &work).await {
+            Ok(archive) => archive,
+            Err(e) => {
+                warn!("service.build.kept", work.display());
+                return Err(e);
+            }
+        };
 
-            for (name, pkg_version, url) in packages {
-                if name == package_name {
-                    // Если версия не указана - берем первую найденную
-                    // Если версия указана - проверяем совпадение
-                    if version.is_none() || version.unwrap() == pkg_version {
-                        urls_to_download.push(url);
-                        found = true;
+        self.install_dependencies(&installer::read_meta(&archive)?, direct)
+            .await?;
+        installer::commit(
+            installer::prepare_built(&archive)?,
+            &self.db,
+            direct,
+            Provenance::Repo {
+                repo: Some(source.repo.clone()),
+                url: source.url.clone(),
+            },
+        )
+        .await?;
+        self.db
+            .set_install_reason(package_name, InstallReason::Explicit)
+            .await?;
+        usage::record(&self.db, UsageKind::Install, package_name).await;
+        let _ = std::fs::remove_dir_all(&work);
+        Ok(source.version)
+    }
 
-                        // Если версия указана явно, выходим после нахождения
-                        if version.is_some() {
-                            break;
-                        }
-                    }
+    /// Writes a bundle of `package_name` (the newest version, or exactly
+    /// `version`) and its whole dependency closure to `output`, by default
+    /// `./<name>-<version>.bundle.tar`; returns the bundle path and its
+    /// manifest.
+    pub async fn bundle(
+        &self,
+        package_name: &str,
+        version: Option<&str>,
+        output: Option<&Path>,
+    ) -> Result<(PathBuf, BundleManifest), UhpmError> {
+        let repos = cache_repo(self.load_repositories().await?).await;
+        let plan = Resolver::new(&self.db, &repos)
+            .await?
+            .ignoring_installed()
+            .plan(package_name, version)
+            .await?;
+        let root = plan
+            .iter()
+            .find(|s| s.name == package_name)
+            .ok_or_else(|| UhpmError::NotFound(package_name.to_string()))?;
+        let output = match output {
+            Some(output) => output.to_path_buf(),
+            None => PathBuf::from(format!("{}-{}.bundle.tar", root.name, root.version)),
+        };
+        let archives: Vec<(String, Version, PathBuf)> = plan
+            .iter()
+            .map(|s| (s.name.clone(), s.version.clone(), s.archive.clone()))
+            .collect();
+        let manifest = bundle::create(package_name, &archives, &output)?;
+        Ok((output, manifest))
+    }
+
+    /// Installs the package of a bundle with the dependencies it carries,
+    /// without contacting any repository; dependencies already installed
+    /// in a compatible version are kept. Returns the package installed.
+    pub async fn install_from_bundle(
+        &self,
+        path: &Path,
+        link: Option<LinkMode>,
+    ) -> Result<(String, Version), UhpmError> {
+        let opened = bundle::open(path)?;
+        let result = async {
+            let direct = self.direct_for(&opened.manifest.package, link).await?;
+            self.install_bundle_steps(path, &opened, direct).await
+        }
+        .await;
+        opened.discard();
+        result
+    }
+
+    async fn install_bundle_steps(
+        &self,
+        path: &Path,
+        opened: &OpenedBundle,
+        direct: bool,
+    ) -> Result<(String, Version), UhpmError> {
+        let manifest = &opened.manifest;
+        let root = manifest
+            .root()
+            .ok_or_else(|| UhpmError::NotFound(manifest.package.clone()))?;
+        let mut plan = Vec::new();
+        for entry in &manifest.packages {
+            if entry.name != manifest.package {
+                let installed = self
+                    .db
+                    .get_package_version(&entry.name)
+                    .await?
+                    .and_then(|v| Version::parse(&v).ok());
+                if installed.is_some_and(|v| resolver::satisfies(&v, &entry.version)) {
+                    continue;
                 }
             }
+            // Recorded as installed from the bundle file, not from a
+            // repository: there is no URL to update it from
+            let archive = opened.archive(entry);
+            plan.push(resolver::Step {
+                name: entry.name.clone(),
+                version: entry.version.clone(),
+                url: path.join(&entry.file).to_string_lossy().to_string(),
+                provenance: Provenance::from_local_file(path),
+                meta: installer::read_meta(&archive)?,
+                archive,
+            });
+        }
+        self.install_steps(plan, direct).await?;
+        self.db
+            .set_install_reason(&manifest.package, InstallReason::Explicit)
+            .await?;
+        Ok((root.name.clone(), root.version.clone()))
+    }
 
-            // Если нашли пакет и версия была указана явно, выходим из цикла по репозиториям
-            if found && version.is_some() {
-                break;
+    /// Plans the installation with the set a repository resolved, if one
+    /// can; `None` means resolving with the local indexes
+    async fn plan_remote(
+        &self,
+        repos: &repo::RepoMap,
+        package_name: &str,
+        version: Option<&str>,
+    ) -> Option<Vec<resolver::Step>> {
+        let (repo, index) =
+            resolve::remote_index(repos, &RepoPolicy::load(), package_name, version).await?;
+        match Resolver::with_index(&self.db, repo, index)
+            .plan(package_name, version)
+            .await
+        {
+            Ok(plan) => Some(plan),
+            Err(e) => {
+                info!("repo.resolve.fallback", package_name, e);
+                None
             }
         }
+    }
 
-        if urls_to_download.is_empty() {
-            return Err(UhpmError::NotFound(format!(
-                "Package {} not found in repositories",
-                package_name
-            )));
+    /// Installs the dependencies of `pkg` that are missing, if any.
+    async fn install_dependencies(
+        &self,
+        pkg: &package::Package,
+        direct: bool,
+    ) -> Result<(), UhpmError> {
+        if resolver::unmet_dependencies(pkg, &self.db)
+            .await?
+            .is_empty()
+        {
+            return Ok(());
+        }
+        let repos = cache_repo(self.load_repositories().await?).await;
+        let plan = Resolver::new(&self.db, &repos)
+            .await?
+            .plan_dependencies(pkg)
+            .await?;
+        self.install_steps(plan, direct).await
+    }
+
+    /// Installs resolved packages in order.
+    ///
+    /// The archives are verified and unpacked in parallel (see
+    /// [`installer::prepare_pooled`]) while the packages are committed one
+    /// by one in plan order, dependencies first. Packages that were not
+    /// installed before are recorded as dependencies; callers mark the
+    /// package the user asked for as explicit afterwards.
+    async fn install_steps(
+        &self,
+        plan: Vec<resolver::Step>,
+        direct: bool,
+    ) -> Result<(), UhpmError> {
+        let names: Vec<String> = plan
+            .iter()
+            .map(|s| format!("{}@{}", s.name, s.version))
+            .collect();
+        info!("service.install.plan", names.join(", "));
+
+        // With `fail`, nothing of the plan is placed if any package would
+        // be refused; `warn` is reported per package as it is committed
+        if Config::load_or_default().runtime_requirements == RequirementPolicy::Fail {
+            let unmet: Vec<(String, Vec<requirements::Unmet>)> = plan
+                .iter()
+                .map(|s| {
+                    (
+                        format!("{}@{}", s.name, s.version),
+                        requirements::unmet(&s.meta),
+                    )
+                })
+                .filter(|(_, unmet)| !unmet.is_empty())
+                .collect();
+            if !unmet.is_empty() {
+                return Err(requirements::error(&unmet));
+            }
         }
 
-        // Добавим отладочную информацию
-        tracing::info!("Found packages to download: {:?}", urls_to_download);
+        let mut new = Vec::new();
+        for step in &plan {
+            new.push(self.db.get_install_reason(&step.name).await?.is_none());
+        }
 
-        fetcher::fetch_and_install_parallel(&urls_to_download, &self.db, direct).await?;
+        let pool = Arc::new(Semaphore::new(installer::PREPARE_CONCURRENCY));
+        let mut prepared: VecDeque<_> = plan
+            .iter()
+            .map(|step| {
+                let (archive, pool) = (step.archive.clone(), pool.clone());
+                tokio::spawn(platform::in_current_root(async move {
+                    installer::prepare_pooled(archive, &pool).await
+                }))
+            })
+            .collect();
+        for (step, new) in plan.iter().zip(new) {
+            let Some(next) = prepared.pop_front() else {
+                break;
+            };
+            let result = match next.await {
+                Ok(Ok(next)) => {
+                    installer::commit(next, &self.db, direct, step.provenance.clone()).await
+                }
+                Ok(Err(e)) => Err(e),
+                Err(e) => Err(UhpmError::Package(e.to_string())),
+            };
+            if let Err(e) = result {
+                for pending in prepared {
+                    if let Ok(Ok(pending)) = pending.await {
+                        installer::discard(pending);
+                    }
+                }
+                return Err(e);
+            }
+            if new {
+                self.db
+                    .set_install_reason(&step.name, InstallReason::Dependency)
+                    .await?;
+            }
+            usage::record(&self.db, UsageKind::Install, &step.name).await;
+        }
         Ok(())
     }
 
-    pub async fn remove_package(&self, package_name: &str, direct: bool) -> Result<(), UhpmError> {
-        remover::remove(package_name, &self.db, direct).await?;
-        Ok(())
+    /// Packages installed only as dependencies that nothing needs anymore,
+    /// in the order they can be removed
+    pub async fn orphaned_packages(&self) -> Result<Vec<String>, UhpmError> {
+        autoremove::find(&self.db).await
+    }
+
+    pub async fn remove_package(
+        &self,
+        package_name: &str,
+        direct: bool,
+    ) -> Result<RemovalSummary, UhpmError> {
+        remover::remove(package_name, &self.db, direct).await
     }
 
     pub async fn remove_package_version(
@@ -93,9 +413,8 @@ impl PackageService {
         package_name: &str,
         version: &str,
         direct: bool,
-    ) -> Result<(), UhpmError> {
-        remover::remove_by_version(package_name, version, &self.db, direct).await?;
-        Ok(())
+    ) -> Result<RemovalSummary, UhpmError> {
+        remover::remove_by_version(package_name, version, &self.db, direct).await
     }
 
     pub async fn update_package(&self, package_name: &str, direct: bool) -> Result<(), UhpmError> {
@@ -103,6 +422,332 @@ impl PackageService {
         Ok(())
     }
 
+    pub async fn update_all(
+        &self,
+        link: Option<LinkMode>,
+        keep_going: bool,
+    ) -> Result<UpdateSummary, UhpmError> {
+        Ok(updater::update_all_packages(&self.db, link, keep_going).await?)
+    }
+
+    /// Newer versions of current repository packages, only for `names`
+    /// unless it is empty
+    pub async fn available_updates(
+        &self,
+        names: &[String],
+    ) -> Result<Vec<AvailableUpdate>, UhpmError> {
+        Ok(updater::check_all_updates(&self.db)
+            .await?
+            .into_iter()
+            .filter(|(name, ..)| names.is_empty() || names.contains(name))
+            .map(|(name, installed, latest, repo)| AvailableUpdate {
+                name,
+                installed,
+                latest,
+                repo,
+            })
+            .collect())
+    }
+
+    /// Updates packages to the versions found by [`Self::available_updates`]
+    pub async fn upgrade(
+        &self,
+        updates: Vec<AvailableUpdate>,
+        link: Option<LinkMode>,
+        keep_going: bool,
+    ) -> Result<UpdateSummary, UhpmError> {
+        let updates = updates
+            .into_iter()
+            .map(|u| (u.name, u.installed, u.latest, u.repo))
+            .collect();
+        Ok(updater::apply_updates(&self.db, updates, link, keep_going).await?)
+    }
+
+    /// Holds a package at `version`, an exact version or a constraint
+    /// updates must match, or at its current version; returns the pin
+    pub async fn pin_version(
+        &self,
+        name: &str,
+        version: Option<&str>,
+    ) -> Result<String, UhpmError> {
+        let current = self
+            .db
+            .get_package_version(name)
+            .await?
+            .ok_or_else(|| UhpmError::NotFound(name.to_string()))?;
+        let pin = match version {
+            Some(version) => version
+                .parse::<VersionSpec>()
+                .map_err(|e| UhpmError::Parse(format!("{}: {}", version, e)))?
+                .to_string(),
+            None => current,
+        };
+        self.db.set_pin(name, &pin).await?;
+        Ok(pin)
+    }
+
+    /// Removes the version pin of a package; `false` if it had none
+    pub async fn unpin_version(&self, name: &str) -> Result<bool, UhpmError> {
+        Ok(self.db.remove_pin(name).await?)
+    }
+
+    /// Every version pin as `(package, pin)`
+    pub async fn version_pins(&self) -> Result<Vec<(String, String)>, UhpmError> {
+        Ok(self.db.list_pins().await?)
+    }
+
+    /// Installed packages that have a version pin, as `(package, pin)`
+    pub async fn held_packages(&self) -> Result<Vec<(String, String)>, UhpmError> {
+        Ok(updater::held_packages(&self.db).await?)
+    }
+
+    /// Current packages installed from local files, which have no
+    /// repository to update from
+    pub async fn local_packages(&self) -> Result<Vec<String>, UhpmError> {
+        Ok(updater::local_packages(&self.db).await?)
+    }
+
+    pub async fn update_local_from_dir(
+        &self,
+        dir: &Path,
+        link: Option<LinkMode>,
+        keep_going: bool,
+    ) -> Result<UpdateSummary, UhpmError> {
+        Ok(updater::update_local_from_dir(dir, &self.db, link, keep_going).await?)
+    }
+
+    /// Whether to place the files of `package_name` as copies: `link` from
+    /// `--direct`/`--symlink`, else as recorded for the package, else as
+    /// configured
+    pub async fn direct_for(
+        &self,
+        package_name: &str,
+        link: Option<LinkMode>,
+    ) -> Result<bool, UhpmError> {
+        Ok(LinkMode::resolve(&self.db, package_name, link)
+            .await?
+            .is_copy())
+    }
+
+    pub async fn update_package_to(
+        &self,
+        package_name: &str,
+        version: &Version,
+        direct: bool,
+    ) -> Result<(), UhpmError> {
+        updater::update_package_to(package_name, version, &self.db, direct).await?;
+        Ok(())
+    }
+
+    /// Changes installing the archive at `path` would make, missing
+    /// dependencies first (see [`dry_run`])
+    pub async fn plan_install_from_file(&self, path: &Path) -> Result<Vec<Change>, UhpmError> {
+        let meta = installer::read_meta(path)?;
+        let mut changes = Vec::new();
+        if !resolver::unmet_dependencies(&meta, &self.db)
+            .await?
+            .is_empty()
+        {
+            let repos = cache_repo(self.load_repositories().await?).await;
+            let plan = Resolver::new(&self.db, &repos)
+                .await?
+                .plan_dependencies(&meta)
+                .await?;
+            changes.extend(self.plan_steps(&plan).await?);
+        }
+        changes.extend(self.plan_install(&meta, path, None).await?);
+        Ok(changes)
+    }
+
+    /// Changes [`install_from_repo`](Self::install_from_repo) would make
+    pub async fn plan_install_from_repo(
+        &self,
+        package_name: &str,
+        version: Option<&str>,
+    ) -> Result<Vec<Change>, UhpmError> {
+        if let Some(archive) = version.and_then(|v| archive_cache::lookup(package_name, v)) {
+            return self.plan_install_from_file(&archive).await;
+        }
+
+        let repos = self.load_repositories().await?;
+        let plan = match self.plan_remote(&repos, package_name, version).await {
+            Some(plan) => plan,
+            None => {
+                let repos = cache_repo(repos).await;
+                Resolver::new(&self.db, &repos)
+                    .await?
+                    .plan(package_name, version)
+                    .await?
+            }
+        };
+        self.plan_steps(&plan).await
+    }
+
+    async fn plan_steps(&self, plan: &[resolver::Step]) -> Result<Vec<Change>, UhpmError> {
+        let mut changes = Vec::new();
+        for step in plan {
+            changes.extend(
+                self.plan_install(&step.meta, &step.archive, Some(&step.url))
+                    .await?,
+            );
+        }
+        Ok(changes)
+    }
+
+    /// Changes installing `meta` would make; none if the version is the
+    /// installed one
+    async fn plan_install(
+        &self,
+        meta: &package::Package,
+        archive: &Path,
+        url: Option<&str>,
+    ) -> Result<Vec<Change>, UhpmError> {
+        match self.db.is_installed(meta.name()).await? {
+            Some(installed) if &installed == meta.version() => Ok(Vec::new()),
+            installed => dry_run::install(meta, archive, url, installed.is_none()),
+        }
+    }
+
+    /// Changes removing `version` of a package, by default the current
+    /// one, would make
+    pub async fn plan_removal(
+        &self,
+        package_name: &str,
+        version: Option<&str>,
+    ) -> Result<Vec<Change>, UhpmError> {
+        let version = match version {
+            Some(version) => version.to_string(),
+            None => match self.db.get_package_version(package_name).await? {
+                Some(version) => version,
+                None => return Ok(Vec::new()),
+            },
+        };
+        dry_run::removal(package_name, &version, &self.db).await
+    }
+
+    /// Changes updating a package would make, to `version` or else to the
+    /// latest version in the repositories
+    pub async fn plan_update(
+        &self,
+        package_name: &str,
+        version: Option<&Version>,
+    ) -> Result<Vec<Change>, UhpmError> {
+        let Some(target) = version else {
+            let url = updater::check_for_update(package_name, &self.db).await?;
+            let archive = Self::fetch_archive(&url).await?;
+            let meta = installer::read_meta(&archive)?;
+            return self.plan_install(&meta, &archive, Some(&url)).await;
+        };
+
+        let current = self
+            .db
+            .get_package_version(package_name)
+            .await?
+            .ok_or_else(|| UpdaterError::NotFound(package_name.to_string()))?;
+        if Version::parse(&current).ok().as_ref() == Some(target) {
+            return Ok(Vec::new());
+        }
+
+        let target_str = target.to_string();
+        let mut changes = Vec::new();
+        let links = if self
+            .db
+            .get_package_by_version(package_name, &target_str)
+            .await?
+            .is_some()
+        {
+            dry_run::installed_links(&package::get_pkg_path(package_name, target.clone()))
+        } else {
+            let (archive, url) = match archive_cache::lookup(package_name, &target_str) {
+                Some(archive) => (archive, None),
+                None => {
+                    let url = updater::find_version_url(package_name, target)
+                        .await?
+                        .ok_or_else(|| {
+                            UpdaterError::VersionNotFound(
+                                package_name.to_string(),
+                                target_str.clone(),
+                            )
+                        })?;
+                    (Self::fetch_archive(&url).await?, Some(url))
+                }
+            };
+            let meta = installer::read_meta(&archive)?;
+            changes.extend(dry_run::place(&meta, url.as_deref()));
+            dry_run::archive_links(&archive, &meta)?
+        };
+        let from = package::packages_dir().join(format!("{}-{}", package_name, current));
+        changes.extend(dry_run::switch(package_name, Some(&from), links, target));
+        Ok(changes)
+    }
+
+    /// Fetches a single archive to the temporary directory
+    async fn fetch_archive(url: &str) -> Result<PathBuf, UhpmError> {
+        fetcher::fetch_packages(&[url.to_string()])
+            .await
+            .remove(url)
+            .ok_or_else(|| UhpmError::Package(format!("Failed to download {}", url)))
+    }
+
+    /// Returns the currently active version of a package, if installed.
+    pub async fn current_version(&self, package_name: &str) -> Result<Option<String>, UhpmError> {
+        self.db
+            .get_package_version(package_name)
+            .await
+            .map_err(UhpmError::from)
+    }
+
+    /// Resolves a version spec to the highest matching version available in
+    /// the configured repositories.
+    pub async fn resolve_available(
+        &self,
+        package_name: &str,
+        spec: &VersionSpec,
+    ) -> Result<Version, UhpmError> {
+        if let VersionSpec::Exact(version) = spec {
+            return Ok(version.clone());
+        }
+
+        let mut versions = Vec::new();
+        for db_path in cache_repo(self.load_repositories().await?).await {
+            if !db_path.exists() {
+                continue;
+            }
+            let repo_index = index::load_repo(&repo::cache_name(&db_path), &db_path).await?;
+            versions.extend(
+                repo_index
+                    .find(package_name)
+                    .filter_map(|e| Version::parse(&e.version).ok()),
+            );
+        }
+        spec.best_match(&versions)
+            .cloned()
+            .ok_or_else(|| UhpmError::NotFound(format!("{}@{}", package_name, spec)))
+    }
+
+    /// Resolves a version spec to the highest matching installed version.
+    pub async fn resolve_installed(
+        &self,
+        package_name: &str,
+        spec: &VersionSpec,
+    ) -> Result<Version, UhpmError> {
+        if let VersionSpec::Exact(version) = spec {
+            return Ok(version.clone());
+        }
+
+        let versions: Vec<Version> = self
+            .db
+            .list_packages()
+            .await?
+            .into_iter()
+            .filter(|(name, _, _)| name == package_name)
+            .filter_map(|(_, version, _)| Version::parse(&version).ok())
+            .collect();
+        spec.best_match(&versions)
+            .cloned()
+            .ok_or_else(|| UhpmError::NotFound(format!("{}@{}", package_name, spec)))
+    }
+
     pub async fn switch_version(
         &self,
         package_name: &str,
@@ -113,20 +758,704 @@ impl PackageService {
         Ok(())
     }
 
-    pub async fn list_packages(&self) -> Result<Vec<(String, String, bool)>, UhpmError> {
-        self.db.list_packages().await.map_err(UhpmError::from)
+    /// Returns the documentation files of the current version of a package,
+    /// the primary document first.
+    ///
+    /// Packages installed before docs were indexed are scanned on the fly.
+    pub async fn package_docs(&self, package_name: &str) -> Result<Vec<PathBuf>, UhpmError> {
+        let pkg = self
+            .db
+            .get_current_package(package_name)
+            .await?
+            .ok_or_else(|| UhpmError::NotFound(package_name.to_string()))?;
+        let version = pkg.version().to_string();
+        let root = package::get_pkg_path(package_name, pkg.version().clone());
+
+        let mut paths = self.db.get_package_docs(package_name, &version).await?;
+        if paths.is_empty() {
+            let meta = package::meta_parser(&root.join("uhp.toml"))?;
+            paths = docs::discover(&root, &meta);
+        }
+        Ok(paths.into_iter().map(|p| root.join(p)).collect())
+    }
+
+    /// Runs a package's primary binary; does not return on success.
+    pub async fn run_package(
+        &self,
+        package_name: &str,
+        version: Option<&str>,
+        args: &[String],
+    ) -> Result<(), UhpmError> {
+        runner::run(package_name, version, args, &self.db).await
+    }
+
+    /// Resolves a command on PATH to the package that provides it.
+    pub async fn which(&self, command: &str) -> Result<Option<WhichReport>, UhpmError> {
+        which::which(command, &self.db).await
+    }
+
+    /// Finds the package owning a file or link.
+    pub async fn owns(&self, path: &Path) -> Result<OwnsReport, UhpmError> {
+        which::owns(path, &self.db).await
+    }
+
+    /// Checks the package directories, recorded links and package files of
+    /// every installed version.
+    pub async fn verify(&self) -> Result<Vec<PackageCheck>, UhpmError> {
+        verify::verify(&self.db).await
+    }
+
+    /// Recreates a missing or misdirected link found by
+    /// [`verify`](Self::verify); returns whether anything was done.
+    pub fn repair(&self, problem: &Problem) -> Result<bool, UhpmError> {
+        Ok(verify::repair(problem)?)
+    }
+
+    /// Runs a query over the installed package versions.
+    pub async fn query(&self, query: &Query) -> Result<Vec<Vec<Value>>, UhpmError> {
+        query::run(&self.db, query).await
+    }
+
+    /// Finds dangling symlinks pointing into the package store.
+    pub async fn find_dangling_links(&self) -> Result<Vec<DanglingLink>, UhpmError> {
+        links::find_dangling(&self.db).await
+    }
+
+    /// Removes and recreates the links of the current version of a package
+    /// from its symlist.
+    pub async fn relink(&self, package_name: &str) -> Result<Relinked, UhpmError> {
+        links::relink(&self.db, package_name).await
+    }
+
+    /// Returns `true` if any version of the package is installed.
+    pub async fn is_installed(&self, package_name: &str) -> Result<bool, UhpmError> {
+        Ok(self.db.get_package_version(package_name).await?.is_some())
+    }
+
+    /// Returns the names of installed packages matching a wildcard pattern.
+    pub async fn installed_matching(&self, pattern: &str) -> Result<Vec<String>, UhpmError> {
+        let mut names: Vec<String> = self
+            .db
+            .list_packages()
+            .await?
+            .into_iter()
+            .map(|(name, _, _)| name)
+            .filter(|name| package::pattern::matches(pattern, name))
+            .collect();
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    /// Installed package versions passing `filter`, sorted by name and
+    /// newest version first
+    ///
+    /// With `filter.outdated` only current versions with a newer version in
+    /// the repositories are kept, with that version in `latest`.
+    pub async fn list_packages(
+        &self,
+        filter: &ListFilter,
+    ) -> Result<Vec<InstalledPackage>, UhpmError> {
+        let mut packages: Vec<InstalledPackage> = listing::collect(&self.db, filter.sizes)
+            .await?
+            .into_iter()
+            .filter(|p| filter.matches(p))
+            .collect();
+        if filter.outdated {
+            let latest: HashMap<String, String> = updater::check_all_updates(&self.db)
+                .await?
+                .into_iter()
+                .map(|(name, _, latest, _)| (name, latest))
+                .collect();
+            packages.retain_mut(|p| {
+                p.latest = latest.get(&p.name).filter(|_| p.current).cloned();
+                p.latest.is_some()
+            });
+        }
+        Ok(packages)
+    }
+
+    /// Searches all configured repositories for packages whose name contains `query`.
+    ///
+    /// Repository databases are downloaded or refreshed first as needed;
+    /// results from several repositories are merged into one hit per package.
+    /// Ignored versions (see [`package::ignore`]) are left out.
+    pub async fn search(&self, query: &str) -> Result<Vec<SearchHit>, UhpmError> {
+        let repos = cache_repo(self.load_repositories().await?).await;
+        let ignore = IgnoreList::load();
+        let mut indexes = Vec::new();
+        for db_path in repos {
+            if !db_path.exists() {
+                continue;
+            }
+            let repo_name = repo::cache_name(&db_path);
+            let mut repo_index = index::load_repo(&repo_name, &db_path).await?;
+            if !ignore.is_empty() {
+                let visible = repo_index
+                    .entries()
+                    .iter()
+                    .filter(|e| !ignore.ignores(&e.name, &e.version))
+                    .cloned()
+                    .collect();
+                repo_index = Arc::new(RepoIndex::new(visible));
+            }
+            indexes.push((repo_name, repo_index));
+        }
+        Ok(index::search(&indexes, query))
+    }
+
+    /// Describes a package: from the local database if it is installed,
+    /// otherwise from the repositories, which are refreshed as needed.
+    pub async fn package_info(&self, name: &str) -> Result<PackageInfo, UhpmError> {
+        if let Some(installed) = info::installed(&self.db, name).await? {
+            return Ok(PackageInfo::Installed(Box::new(installed)));
+        }
+        self.search(name)
+            .await?
+            .into_iter()
+            .find(|hit| hit.name == name)
+            .map(PackageInfo::Available)
+            .ok_or_else(|| UhpmError::NotFound(name.to_string()))
+    }
+
+    /// Suggests known package names close to a mistyped `name`.
+    ///
+    /// Installed packages and packages in cached repository indexes are
+    /// considered; repositories are not refreshed.
+    pub async fn suggest_packages(&self, name: &str) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .db
+            .list_packages()
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, _, _)| name)
+            .collect();
+        for db_path in cached_repo_dbs() {
+            if let Ok(repo_index) = index::load(&db_path).await {
+                names.extend(repo_index.entries().iter().map(|e| e.name.clone()));
+            }
+        }
+        names.sort();
+        names.dedup();
+        suggest::closest(name, names.iter().map(String::as_str))
+    }
+
+    /// What was searched for a package the repositories do not carry: the
+    /// repositories in resolution order with the state of their indexes,
+    /// and similar names
+    pub async fn not_found_report(&self, package_name: &str) -> Result<NotFoundReport, UhpmError> {
+        let repos = self.load_repositories().await?;
+        Ok(NotFoundReport {
+            package: package_name.to_string(),
+            searched: not_found::searched(&repos, &RepoPolicy::load(), package_name),
+            suggestions: self.suggest_packages(package_name).await,
+        })
+    }
+
+    /// Moves bin links if the configured bin layout changed since they were
+    /// created; returns the number of links moved. Nothing is moved in
+    /// read-only mode.
+    pub async fn sync_bin_layout(&self) -> Result<usize, UhpmError> {
+        if access::is_read_only() {
+            return Ok(0);
+        }
+        bin_layout::sync(&self.db).await
+    }
+
+    /// Counts a command, install or update in the local usage statistics.
+    pub async fn record_usage(&self, kind: UsageKind, name: &str) {
+        usage::record(&self.db, kind, name).await;
+    }
+
+    /// Returns the recorded usage counters.
+    pub async fn usage_report(&self) -> Result<UsageReport, UhpmError> {
+        usage::report(&self.db).await
+    }
+
+    /// Deletes the recorded usage counters.
+    pub async fn clear_usage(&self) -> Result<(), UhpmError> {
+        Ok(self.db.clear_usage().await?)
+    }
+
+    /// Current versions of the installed packages, to record what a
+    /// command changes with [`record_history`](Self::record_history).
+    pub async fn history_snapshot(&self) -> Result<Snapshot, UhpmError> {
+        history::snapshot(&self.db).await
+    }
+
+    /// Records the changes since `before` as a transaction of `command`.
+    pub async fn record_history(&self, command: &str, before: &Snapshot) -> Option<i64> {
+        history::record(&self.db, command, before).await
+    }
+
+    /// Returns the recorded transactions, newest first.
+    pub async fn history(&self) -> Result<Vec<Transaction>, UhpmError> {
+        history::list(&self.db).await
+    }
+
+    /// Returns the transaction with `id`.
+    pub async fn transaction(&self, id: i64) -> Result<Transaction, UhpmError> {
+        history::get(&self.db, id).await
+    }
+
+    /// Changes that bring the packages of transaction `id` back to their
+    /// versions from before it
+    ///
+    /// Packages already in that state are left out; removals come last, so
+    /// that nothing still needed by a restored package goes first.
+    pub async fn rollback_plan(&self, id: i64) -> Result<Vec<VersionChange>, UhpmError> {
+        let current = history::snapshot(&self.db).await?;
+        let mut plan: Vec<VersionChange> = history::get(&self.db, id)
+            .await?
+            .changes
+            .into_iter()
+            .filter(|change| current.get(&change.name) != change.before.as_ref())
+            .map(|change| VersionChange {
+                before: current.get(&change.name).cloned(),
+                after: change.before,
+                name: change.name,
+            })
+            .collect();
+        plan.sort_by_key(|change| change.after.is_none());
+        Ok(plan)
+    }
+
+    /// Applies a change of a [rollback plan](Self::rollback_plan): a
+    /// version still in the store becomes the current one, another one is
+    /// installed from the repositories, a package without a version to go
+    /// back to is removed.
+    pub async fn rollback_change(
+        &self,
+        change: &VersionChange,
+        direct: bool,
+    ) -> Result<(), UhpmError> {
+        let Some(version) = &change.after else {
+            self.remove_package(&change.name, direct).await?;
+            return Ok(());
+        };
+        if change.before.is_some()
+            && self
+                .db
+                .get_package_by_version(&change.name, version)
+                .await?
+                .is_some()
+        {
+            let version = Version::parse(version).map_err(|e| UhpmError::Parse(e.to_string()))?;
+            return self.switch_version(&change.name, version, direct).await;
+        }
+        self.install_from_repo(&change.name, Some(version), direct)
+            .await
+    }
+
+    /// The last `last` transactions, newest first, and the steps that
+    /// undo them
+    ///
+    /// Packages already in their earlier state are left out; removals come
+    /// last, as in a rollback.
+    pub async fn undo_plan(
+        &self,
+        last: usize,
+    ) -> Result<(Vec<Transaction>, Vec<UndoStep>), UhpmError> {
+        let mut transactions = history::list(&self.db).await?;
+        transactions.truncate(last);
+        let current = history::snapshot(&self.db).await?;
+        let mut steps = Vec::new();
+        for (name, target) in history::undo_targets(&transactions) {
+            let before = current.get(&name).cloned();
+            if before == target {
+                continue;
+            }
+            let source = match &target {
+                None => UndoSource::Remove,
+                Some(version)
+                    if before.is_some()
+                        && self
+                            .db
+                            .get_package_by_version(&name, version)
+                            .await?
+                            .is_some() =>
+                {
+                    UndoSource::Switch
+                }
+                Some(version) if archive_cache::lookup(&name, version).is_some() => {
+                    UndoSource::Cache
+                }
+                Some(_) => UndoSource::Repository,
+            };
+            steps.push(UndoStep {
+                change: VersionChange {
+                    name,
+                    before,
+                    after: target,
+                },
+                source,
+            });
+        }
+        steps.sort_by_key(|step| step.change.after.is_none());
+        Ok((transactions, steps))
+    }
+
+    /// Counts installed packages, versions and tracked files.
+    pub async fn install_stats(&self) -> Result<InstallStats, UhpmError> {
+        usage::install_stats(&self.db).await
+    }
+
+    /// Returns the declared licenses of the current package versions.
+    pub async fn licenses(&self) -> Result<Vec<PackageLicense>, UhpmError> {
+        licenses::collect(&self.db).await
+    }
+
+    /// Compares the tools of a project toolchain with the installation.
+    pub async fn check_toolchain(
+        &self,
+        toolchain: &Toolchain,
+    ) -> Result<ToolchainReport, UhpmError> {
+        Ok(toolchain.check(&self.db.list_packages().await?))
+    }
+
+    /// Installs or switches to a version of a toolchain tool that meets
+    /// `spec`, returning that version.
+    pub async fn satisfy_tool(
+        &self,
+        tool: &ToolStatus,
+        spec: &VersionSpec,
+        direct: bool,
+    ) -> Result<Version, UhpmError> {
+        match (tool.state, &tool.current, &tool.matching) {
+            (ToolState::Ok, Some(current), _) => Ok(current.clone()),
+            (ToolState::Inactive, _, Some(matching)) => {
+                self.update_package_to(&tool.name, matching, direct).await?;
+                Ok(matching.clone())
+            }
+            (ToolState::Missing, _, _) => {
+                let version = self.resolve_available(&tool.name, spec).await?;
+                self.install_from_repo(&tool.name, Some(&version.to_string()), direct)
+                    .await?;
+                Ok(version)
+            }
+            _ => {
+                let version = self.resolve_available(&tool.name, spec).await?;
+                self.update_package_to(&tool.name, &version, direct).await?;
+                Ok(version)
+            }
+        }
+    }
+
+    /// Returns the old versions unused for `unused_days` days, which `uhpm
+    /// gc` removes; current and rollback versions are never included.
+    pub async fn stale_versions(&self, unused_days: u64) -> Result<Vec<VersionUse>, UhpmError> {
+        let Some(cutoff) = chrono::Local::now().checked_sub_days(chrono::Days::new(unused_days))
+        else {
+            return Ok(Vec::new());
+        };
+        let versions = retention::collect(&self.db).await?;
+        Ok(retention::stale(&versions, cutoff.fixed_offset())
+            .into_iter()
+            .cloned()
+            .collect())
+    }
+
+    /// Runs the maintenance steps not in `skip`, see
+    /// [`maintenance`](crate::package::maintenance). A failing step does
+    /// not stop the others; the report records how each one ended.
+    pub async fn maintain(&self, skip: &[MaintenanceStep]) -> MaintenanceReport {
+        let mut report = MaintenanceReport::start();
+        for step in MaintenanceStep::ALL {
+            if skip.contains(&step) {
+                report.skip(step);
+                continue;
+            }
+            info!("service.maintain.step", step.as_str());
+            let result = match step {
+                MaintenanceStep::Cache => self.maintain_cache().await,
+                MaintenanceStep::Refresh => self.maintain_refresh().await,
+                MaintenanceStep::Gc => self.maintain_gc().await,
+                MaintenanceStep::Vacuum => self
+                    .db
+                    .vacuum()
+                    .await
+                    .map(|(size_before, size_after)| Details::Vacuum {
+                        size_before,
+                        size_after,
+                    })
+                    .map_err(UhpmError::from),
+                MaintenanceStep::Links => {
+                    self.find_dangling_links()
+                        .await
+                        .map(|dangling| Details::Links {
+                            dangling: dangling.into_iter().map(|d| d.link).collect(),
+                        })
+                }
+                MaintenanceStep::Updates => self
+                    .available_updates(&[])
+                    .await
+                    .map(|available| Details::Updates { available }),
+            };
+            report.record(step, result.map_err(|e| e.to_string()));
+        }
+        report.finish()
+    }
+
+    async fn maintain_cache(&self) -> Result<Details, UhpmError> {
+        let removed = self.clean_repo_cache().await?;
+        let ttl = Config::load_or_default().run_cache_ttl_hours * 3600;
+        let run_cache_purged = runner::purge_expired(std::time::Duration::from_secs(ttl))?;
+        Ok(Details::Cache {
+            bytes_freed: removed.iter().map(|c| c.size).sum(),
+            repo_caches_removed: removed.into_iter().map(|c| c.name).collect(),
+            run_cache_purged,
+        })
+    }
+
+    async fn maintain_refresh(&self) -> Result<Details, UhpmError> {
+        let (mut updated, mut unchanged, mut failed) = (Vec::new(), Vec::new(), Vec::new());
+        // Without repos.ron there is nothing to refresh
+        let results = if platform::uhpm_home().join("repos.ron").exists() {
+            self.refresh_repos(false).await?
+        } else {
+            Vec::new()
+        };
+        for (name, result) in results {
+            match result {
+                Ok(Refresh::Updated) => updated.push(name),
+                Ok(Refresh::Unchanged) => unchanged.push(name),
+                Err(e) => failed.push(Failure {
+                    name,
+                    error: e.to_string(),
+                }),
+            }
+        }
+        Ok(Details::Refresh {
+            updated,
+            unchanged,
+            failed,
+        })
+    }
+
+    async fn maintain_gc(&self) -> Result<Details, UhpmError> {
+        let days = Config::load_or_default().gc_unused_days;
+        let (mut removed, mut bytes_freed, mut failed) = (Vec::new(), 0, Vec::new());
+        for v in self.stale_versions(days).await? {
+            let name = format!("{}@{}", v.name, v.version);
+            match self
+                .remove_package_version(&v.name, &v.version.to_string(), false)
+                .await
+            {
+                Ok(summary) => {
+                    bytes_freed += summary.bytes;
+                    removed.push(name);
+                }
+                Err(e) => failed.push(Failure {
+                    name,
+                    error: e.to_string(),
+                }),
+            }
+        }
+        Ok(Details::Gc {
+            removed,
+            bytes_freed,
+            failed,
+        })
+    }
+
+    /// Returns the environment of the installed packages.
+    pub async fn environment(&self) -> Result<Environment, UhpmError> {
+        env::collect(&self.db).await
+    }
+
+    /// Lists installed packages grouped by name with per-version details.
+    pub async fn package_tree(&self) -> Result<Vec<PackageNode>, UhpmError> {
+        tree::build(&self.db).await
+    }
+
+    /// Renders the configured repositories and their keys as a shareable
+    /// TOML snippet.
+    pub async fn export_repos(&self) -> Result<String, UhpmError> {
+        let repos = self.load_repositories().await?;
+        share::to_toml(&share::export(&repos, &signing::keys_dir())?)
+    }
+
+    /// Adds checked repositories to `repos.ron` and trusts their keys.
+    ///
+    /// Returns the names of the added or changed repositories.
+    pub async fn import_repos(
+        &self,
+        shared: &SharedRepos,
+        replace: bool,
+    ) -> Result<Vec<String>, UhpmError> {
+        let mut config = RepoConfig::open()?;
+        let changed = share::import(shared, &mut config, &signing::keys_dir(), replace)?;
+        config.save()?;
+        Ok(changed)
+    }
+
+    /// Returns the configured repositories sorted by name.
+    pub fn list_repos(&self) -> Result<Vec<(String, RepoEntry)>, UhpmError> {
+        Ok(RepoConfig::open()?
+            .entries()
+            .iter()
+            .map(|(name, entry)| (name.clone(), entry.clone()))
+            .collect())
+    }
+
+    /// Sets the priority of a repository.
+    pub fn set_repo_priority(&self, name: &str, priority: i32) -> Result<(), UhpmError> {
+        let mut config = RepoConfig::open()?;
+        config.set_priority(name, priority)?;
+        config.save()
+    }
+
+    /// Pins a package to a repository, or unpins it when `repo` is `None`;
+    /// returns whether anything changed.
+    pub fn pin_package(&self, package: &str, repo: Option<&str>) -> Result<bool, UhpmError> {
+        let mut config = RepoConfig::open()?;
+        let changed = match repo {
+            Some(repo) => config.pin(package, repo).map(|()| true)?,
+            None => config.unpin(package),
+        };
+        config.save()?;
+        Ok(changed)
+    }
+
+    /// Adds a repository to `repos.ron`.
+    pub fn add_repo(&self, name: &str, url: &str, replace: bool) -> Result<(), UhpmError> {
+        let mut config = RepoConfig::open()?;
+        config.add(name, url, replace)?;
+        config.save()
+    }
+
+    /// Removes a repository from `repos.ron` along with its cached
+    /// database; returns its URL.
+    pub fn remove_repo(&self, name: &str) -> Result<String, UhpmError> {
+        let mut config = RepoConfig::open()?;
+        let url = config.remove(name)?;
+        config.save()?;
+        let cache = repo::cache::cache_dir().join(name);
+        if cache.exists() {
+            std::fs::remove_dir_all(cache)?;
+        }
+        Ok(url)
+    }
+
+    /// Names of the profiles and the name of the active one.
+    pub fn list_profiles(&self) -> (Vec<String>, String) {
+        let base = platform::base_home();
+        let active = Profiles::load(&base).active_name().to_string();
+        (config::list_profiles(&base), active)
+    }
+
+    /// Creates a profile with the configuration and repositories of the
+    /// active one; returns its data directory.
+    pub fn create_profile(&self, name: &str) -> Result<PathBuf, UhpmError> {
+        Ok(config::create_profile(
+            &platform::base_home(),
+            name,
+            &platform::uhpm_home(),
+        )?)
+    }
+
+    /// Data directory of an inactive profile that can be deleted.
+    pub fn deletable_profile(&self, name: &str) -> Result<PathBuf, UhpmError> {
+        let base = platform::base_home();
+        let home = config::existing_profile(&base, name)?;
+        if name == config::DEFAULT_PROFILE {
+            return Err(UhpmError::Validation(
+                "the default profile cannot be deleted".to_string(),
+            ));
+        }
+        if Profiles::load(&base).active_name() == name {
+            return Err(UhpmError::Conflict(format!(
+                "profile {} is active, switch to another one first",
+                name
+            )));
+        }
+        Ok(home)
+    }
+
+    /// Deletes an inactive profile with its database and packages.
+    ///
+    /// Its links are not in place, so nothing outside its data directory
+    /// is touched.
+    pub fn delete_profile(&self, name: &str) -> Result<(), UhpmError> {
+        std::fs::remove_dir_all(self.deletable_profile(name)?)?;
+        Ok(())
+    }
+
+    /// Makes `name` the active profile, swapping the links of the packages
+    /// of the active profile for its own; returns the number of files
+    /// removed and linked, or `None` if it is active already.
+    ///
+    /// If the links of the old profile cannot be taken out, or those of the
+    /// new one cannot be put in, the links of the old one are put back.
+    pub async fn use_profile(&self, name: &str) -> Result<Option<(usize, usize)>, UhpmError> {
+        let base = platform::base_home();
+        let mut profiles = Profiles::load(&base);
+        if profiles.active_name() == name {
+            return Ok(None);
+        }
+        let home = config::existing_profile(&base, name)?;
+        let db = PackageDB::new(&home.join("packages.db"))?.init().await?;
+
+        let store = packages_dir();
+        let removed = match profile::swap_out(&self.db, &store).await {
+            Ok(removed) => removed,
+            Err(e) => {
+                profile::swap_in(&self.db, &store).await?;
+                return Err(e);
+            }
+        };
+        let linked = match profile::swap_in(&db, &home.join("packages")).await {
+            Ok(linked) => linked,
+            Err(e) => {
+                profile::swap_out(&db, &home.join("packages")).await?;
+                profile::swap_in(&self.db, &store).await?;
+                return Err(e);
+            }
+        };
+        profiles.set_active(name);
+        profiles.save(&base)?;
+        Ok(Some((removed, linked)))
+    }
+
+    /// Cached databases of repositories that are no longer configured, or
+    /// were cached from a URL they no longer have.
+    pub async fn stale_repo_cache(&self) -> Result<Vec<StaleCache>, UhpmError> {
+        let repos = RepoConfig::open()?.repos();
+        Ok(repo::cache::stale(&repos))
+    }
+
+    /// Deletes the cached databases of repositories that are no longer
+    /// configured, or were cached from a URL they no longer have.
+    pub async fn clean_repo_cache(&self) -> Result<Vec<StaleCache>, UhpmError> {
+        let repos = RepoConfig::open()?.repos();
+        Ok(repo::cache::clean(&repos)?)
+    }
+
+    /// Refreshes the databases of all repositories now, sorted by name.
+    ///
+    /// `force` downloads them even if the servers report them unchanged.
+    pub async fn refresh_repos(
+        &self,
+        force: bool,
+    ) -> Result<Vec<(String, Result<Refresh, UhpmError>)>, UhpmError> {
+        let mut repos: Vec<(String, String)> =
+            self.load_repositories().await?.into_iter().collect();
+        repos.sort();
+        repo::cache::ensure_layout()?;
+        let mut results = Vec::new();
+        for (name, url) in repos {
+            let db = repo::cache_db_path(&name);
+            let result = repo::refresh_repo(&name, &url, &db, force).await;
+            results.push((name, result));
+        }
+        Ok(results)
     }
 
     async fn load_repositories(
         &self,
     ) -> Result<std::collections::HashMap<String, String>, UhpmError> {
-        let repos_path = dirs::home_dir()
-            .ok_or_else(|| {
-                UhpmError::Config(ConfigError::NotFound(
-                    "Home directory not found".to_string(),
-                ))
-            })?
-            .join(".uhpm/repos.ron");
+        let repos_path = platform::uhpm_home().join("repos.ron");
 
         parse_repos(&repos_path).map_err(|e| UhpmError::Repository(e.into()))
     }
@@ -0,0 +1,327 @@
+//! # Package Signatures
+//!
+//! Packages may come with a detached signature next to the archive,
+//! `<name>-<version>.uhp.sig`, made with [minisign] or GPG. Signatures are
+//! checked against the trusted keys in `~/.uhpm/keys/`:
+//!
+//! | File                 | Key                          |
+//! |----------------------|------------------------------|
+//! | `*.pub`              | minisign public key          |
+//! | `*.asc`, `*.gpg`     | GPG public key (armored or binary) |
+//!
+//! GPG keys are imported into a keyring private to uhpm, a temporary
+//! directory under `~/.uhpm/keys/` built for every verification, so the
+//! user's own keyring is neither used nor modified and removed keys stop
+//! being trusted. Verification and signing run the `minisign` and `gpg` tools.
+//!
+//! A signature that is present is always verified when trusted keys exist.
+//! With `require_signatures: true` in `config.ron`, unsigned packages and
+//! packages signed by none of the trusted keys are refused.
+//!
+//! [minisign]: https://jedisct1.github.io/minisign/
+
+use crate::config::Config;
+use crate::error::SigningError;
+use crate::{debug, info, platform};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use tempfile::TempDir;
+
+/// Signature scheme of a key or signature file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureFormat {
+    Minisign,
+    Gpg,
+}
+
+impl SignatureFormat {
+    fn tool(self) -> &'static str {
+        match self {
+            SignatureFormat::Minisign => "minisign",
+            SignatureFormat::Gpg => "gpg",
+        }
+    }
+}
+
+/// A trusted public key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrustedKey {
+    pub path: PathBuf,
+    pub format: SignatureFormat,
+}
+
+/// Directory holding the trusted keys
+pub fn keys_dir() -> PathBuf {
+    platform::uhpm_home().join("keys")
+}
+
+/// Location of the detached signature of `archive`
+pub fn signature_path(archive: &Path) -> PathBuf {
+    let mut name = OsString::from(archive.as_os_str());
+    name.push(".sig");
+    PathBuf::from(name)
+}
+
+/// Tells minisign signatures from GPG ones by their content
+pub fn detect_format(signature: &[u8]) -> SignatureFormat {
+    if signature.starts_with(b"untrusted comment:") {
+        SignatureFormat::Minisign
+    } else {
+        SignatureFormat::Gpg
+    }
+}
+
+/// Trusted keys found in `dir`, sorted by path
+pub fn trusted_keys_in(dir: &Path) -> Vec<TrustedKey> {
+    let mut keys: Vec<TrustedKey> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| e.path())
+                .filter(|p| p.is_file())
+                .filter_map(|path| {
+                    let format = match path.extension()?.to_str()? {
+                        "pub" => SignatureFormat::Minisign,
+                        "asc" | "gpg" => SignatureFormat::Gpg,
+                        _ => return None,
+                    };
+                    Some(TrustedKey { path, format })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    keys.sort_by(|a, b| a.path.cmp(&b.path));
+    keys
+}
+
+/// Trusted keys in `~/.uhpm/keys/`
+pub fn trusted_keys() -> Vec<TrustedKey> {
+    trusted_keys_in(&keys_dir())
+}
+
+/// Maps a failure to start `tool` to an error
+fn spawn_error(e: std::io::Error, tool: &str) -> SigningError {
+    match e.kind() {
+        std::io::ErrorKind::NotFound => SigningError::ToolMissing(tool.to_string()),
+        _ => SigningError::Io(e),
+    }
+}
+
+/// Runs a command quietly and tells whether it succeeded
+fn run(command: &mut Command, tool: &str) -> Result<bool, SigningError> {
+    command
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .map_err(|e| spawn_error(e, tool))
+}
+
+/// Builds a keyring from the GPG keys in a fresh temporary directory
+///
+/// Every verification gets its own keyring, removed when the returned
+/// directory is dropped, so concurrent verifications do not interfere.
+fn gpg_home(keys: &[&TrustedKey]) -> Result<TempDir, SigningError> {
+    let parent = keys_dir();
+    fs::create_dir_all(&parent)?;
+    // Created with mode 0700, as gpg expects of its home directory
+    let home = tempfile::Builder::new()
+        .prefix("gnupg-")
+        .tempdir_in(&parent)?;
+    for key in keys {
+        let imported = run(
+            Command::new("gpg")
+                .arg("--homedir")
+                .arg(home.path())
+                .args(["--batch", "--quiet", "--import"])
+                .arg(&key.path),
+            "gpg",
+        )?;
+        if !imported {
+            debug!("signing.gpg_import_failed", key.path.display());
+        }
+    }
+    Ok(home)
+}
+
+/// Verifies the detached signature of `archive` against `keys`
+///
+/// Returns the key that made the signature; for GPG that is the first
+/// trusted GPG key file, as the keyring does not tell which one matched.
+pub fn verify_with(archive: &Path, keys: &[TrustedKey]) -> Result<TrustedKey, SigningError> {
+    let sig = signature_path(archive);
+    if !sig.exists() {
+        return Err(SigningError::Unsigned(archive.to_path_buf()));
+    }
+    let format = detect_format(&fs::read(&sig)?);
+    let candidates: Vec<&TrustedKey> = keys.iter().filter(|k| k.format == format).collect();
+    if candidates.is_empty() {
+        return Err(SigningError::NoTrustedKeys(keys_dir()));
+    }
+
+    match format {
+        SignatureFormat::Minisign => {
+            for key in candidates {
+                let valid = run(
+                    Command::new("minisign")
+                        .args(["-V", "-q", "-p"])
+                        .arg(&key.path)
+                        .arg("-m")
+                        .arg(archive)
+                        .arg("-x")
+                        .arg(&sig),
+                    format.tool(),
+                )?;
+                if valid {
+                    return Ok(key.clone());
+                }
+            }
+        }
+        SignatureFormat::Gpg => {
+            let home = gpg_home(&candidates)?;
+            let output = Command::new("gpg")
+                .arg("--homedir")
+                .arg(home.path())
+                .args(["--batch", "--status-fd", "1", "--verify"])
+                .arg(&sig)
+                .arg(archive)
+                .stderr(Stdio::null())
+                .output()
+                .map_err(|e| spawn_error(e, format.tool()))?;
+            let status = String::from_utf8_lossy(&output.stdout);
+            if output.status.success() && status.contains("[GNUPG:] VALIDSIG") {
+                return Ok(candidates[0].clone());
+            }
+        }
+    }
+    Err(SigningError::Invalid(archive.to_path_buf()))
+}
+
+/// Verifies the detached signature of `archive` against the trusted keys
+pub fn verify(archive: &Path) -> Result<TrustedKey, SigningError> {
+    verify_with(archive, &trusted_keys())
+}
+
+/// Checks an archive before installation
+///
+/// Unsigned archives, and signatures no trusted key can check, pass unless
+/// signatures are required; otherwise a signature that is present must be
+/// valid.
+pub fn check(archive: &Path) -> Result<(), SigningError> {
+    let required = Config::load_or_default().require_signatures;
+    match verify(archive) {
+        Ok(key) => {
+            info!("signing.verified", archive.display(), key.path.display());
+            Ok(())
+        }
+        Err(SigningError::Unsigned(_) | SigningError::NoTrustedKeys(_)) if !required => {
+            debug!("signing.not_checked", archive.display());
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Default minisign secret key, `~/.minisign/minisign.key`
+fn default_minisign_key() -> Option<PathBuf> {
+    dirs::home_dir()
+        .map(|home| home.join(".minisign/minisign.key"))
+        .filter(|p| p.exists())
+}
+
+/// Writes a detached signature for `archive`
+///
+/// `key` is a minisign secret key file or a GPG key id. Without it the
+/// default minisign key is used if there is one, otherwise GPG's default
+/// key. The tools may prompt for a passphrase.
+pub fn sign(archive: &Path, key: Option<&str>) -> Result<PathBuf, SigningError> {
+    let sig = signature_path(archive);
+    let minisign_key = match key {
+        Some(key) if Path::new(key).is_file() => Some(PathBuf::from(key)),
+        Some(_) => None,
+        None => default_minisign_key(),
+    };
+
+    let (tool, mut command) = match &minisign_key {
+        Some(secret) => {
+            let mut command = Command::new("minisign");
+            command
+                .arg("-S")
+                .arg("-s")
+                .arg(secret)
+                .arg("-m")
+                .arg(archive)
+                .arg("-x")
+                .arg(&sig);
+            ("minisign", command)
+        }
+        None => {
+            let mut command = Command::new("gpg");
+            command.args(["--yes", "--detach-sign"]);
+            if let Some(id) = key {
+                command.arg("--local-user").arg(id);
+            }
+            command.arg("--output").arg(&sig).arg(archive);
+            ("gpg", command)
+        }
+    };
+    let status = command.status().map_err(|e| spawn_error(e, tool))?;
+    if !status.success() {
+        return Err(SigningError::SignFailed(archive.to_path_buf()));
+    }
+    Ok(sig)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trusted_keys_and_formats() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(tmp.path().join("alice.pub"), "").unwrap();
+        fs::write(tmp.path().join("bob.asc"), "").unwrap();
+        fs::write(tmp.path().join("notes.txt"), "").unwrap();
+
+        let keys = trusted_keys_in(tmp.path());
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].format, SignatureFormat::Minisign);
+        assert_eq!(keys[1].format, SignatureFormat::Gpg);
+
+        assert_eq!(
+            detect_format(b"untrusted comment: signature from minisign\n"),
+            SignatureFormat::Minisign
+        );
+        assert_eq!(
+            detect_format(b"-----BEGIN PGP SIGNATURE-----"),
+            SignatureFormat::Gpg
+        );
+    }
+
+    #[test]
+    fn test_verify_rejects_unsigned_and_untrusted() {
+        let tmp = tempfile::tempdir().unwrap();
+        let archive = tmp.path().join("pkg-1.0.0.uhp");
+        fs::write(&archive, "archive").unwrap();
+        assert_eq!(
+            signature_path(&archive),
+            tmp.path().join("pkg-1.0.0.uhp.sig")
+        );
+        assert!(matches!(
+            verify_with(&archive, &[]),
+            Err(SigningError::Unsigned(_))
+        ));
+
+        fs::write(signature_path(&archive), "untrusted comment: x\n").unwrap();
+        let gpg_only = [TrustedKey {
+            path: tmp.path().join("key.asc"),
+            format: SignatureFormat::Gpg,
+        }];
+        assert!(matches!(
+            verify_with(&archive, &gpg_only),
+            Err(SigningError::NoTrustedKeys(_))
+        ));
+    }
+}
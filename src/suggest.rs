@@ -0,0 +1,75 @@
+//! # Name Suggestions
+//!
+//! "Did you mean …?" hints for mistyped package names, ranked by
+//! Levenshtein distance. Subcommand typos are already handled by clap.
+
+/// Maximum number of suggestions returned
+pub const MAX_SUGGESTIONS: usize = 3;
+
+/// Edit distance between two strings, counted in characters
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        curr[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != *cb);
+            curr[j + 1] = substitution.min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    prev[b.len()]
+}
+
+/// Returns the candidates closest to `name`, best first
+///
+/// A candidate qualifies when it is within a third of `name`'s length
+/// (at least one edit) or when it contains `name`. Exact matches
+/// are not suggested.
+pub fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let name = name.to_lowercase();
+    let max_distance = (name.chars().count() / 3).max(1);
+
+    let mut ranked: Vec<(usize, &str)> = candidates
+        .into_iter()
+        .filter_map(|candidate| {
+            let lower = candidate.to_lowercase();
+            if lower == name {
+                return None;
+            }
+            let distance = levenshtein(&name, &lower);
+            (distance <= max_distance || lower.contains(&name)).then_some((distance, candidate))
+        })
+        .collect();
+
+    ranked.sort();
+    ranked.dedup_by(|a, b| a.1 == b.1);
+    ranked
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein("ripgrep", "ripgrep"), 0);
+        assert_eq!(levenshtein("ripgerp", "ripgrep"), 2);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn test_closest() {
+        let names = ["ripgrep", "grep", "fd", "ripgrep-all", "bat"];
+        assert_eq!(closest("rigprep", names), vec!["ripgrep".to_string()]);
+        assert_eq!(closest("ripgrep", names), vec!["ripgrep-all".to_string()]);
+        assert!(closest("zzzzzz", names).is_empty());
+    }
+}
@@ -12,12 +12,32 @@
 //!
 //! ## Supported variables
 //! - `$HOME` — user home directory
-//! - `$XDG_DATA_HOME` — user data directory (defaults to `~/.local/share`)
+//! - `$XDG_DATA_HOME` — user data directory (defaults to `~/.local/share`,
+//!   `~/Library/Application Support` on macOS)
 //! - `$XDG_CONFIG_HOME` — user config directory (defaults to `~/.config`)
 //! - `$XDG_BIN_HOME` — user bin directory (defaults to `~/.local/bin`)
 //!
-//! These variables are automatically expanded in target paths.
-
+//! These variables are automatically expanded in target paths. Targets
+//! directly inside `$XDG_BIN_HOME` then follow the configured
+//! [`BinLayout`](crate::package::bin_layout::BinLayout).
+//!
+//! ## Versioned targets
+//! A target may contain `{version}`, `{major}`, `{minor}` and `{patch}`,
+//! filled in from the package version in `uhp.toml`:
+//!
+//! ```text
+//! bin/python3 $XDG_BIN_HOME/python3
+//! bin/python3 $XDG_BIN_HOME/python{major}.{minor}
+//! ```
+//!
+//! Such links belong to their version rather than to the current one: each
+//! installed version keeps its `python3.11`, `python3.12`, ... side by side,
+//! switching versions only moves the unversioned links, and the versioned
+//! ones go away when their version is removed.
+
+use crate::package::bin_layout::BinLayout;
+use crate::package::meta_parser;
+use semver::Version;
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -44,26 +64,71 @@ pub struct SymlinkEntry {
     pub target: String,
 }
 
+/// Placeholders of versioned targets
+const VERSION_VARS: [&str; 4] = ["{version}", "{major}", "{minor}", "{patch}"];
+
+impl SymlinkEntry {
+    /// Whether the target names the package version
+    pub fn is_versioned(&self) -> bool {
+        VERSION_VARS.iter().any(|var| self.target.contains(var))
+    }
+}
+
+/// A link of an installed package
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    /// Absolute path inside the package
+    pub source: PathBuf,
+    /// Expanded target path
+    pub target: PathBuf,
+    /// Whether the target is versioned and stays when switching versions
+    pub versioned: bool,
+}
+
+/// Fills in the version placeholders of `target`
+fn expand_version(target: &str, version: &Version) -> String {
+    target
+        .replace("{version}", &version.to_string())
+        .replace("{major}", &version.major.to_string())
+        .replace("{minor}", &version.minor.to_string())
+        .replace("{patch}", &version.patch.to_string())
+}
+
 /// Expands variables (`$HOME`, `$XDG_*`) in paths
-fn expand_vars(path: &str) -> PathBuf {
+///
+/// With an alternate root (see [`crate::platform::root`]) `$HOME` is the
+/// root and the `$XDG_*` variables of the environment are ignored.
+pub(crate) fn expand_vars(path: &str) -> PathBuf {
     let mut vars = HashMap::new();
+    let isolated = crate::platform::root().is_some();
+    let env = |name: &str| {
+        if isolated {
+            None
+        } else {
+            std::env::var(name).ok()
+        }
+    };
 
-    if let Some(home) = dirs::home_dir() {
+    if let Some(home) = crate::platform::link_home() {
         let home_str = home.to_string_lossy().to_string();
 
         vars.insert("HOME".to_string(), home_str.clone());
 
         vars.insert(
             "XDG_DATA_HOME".to_string(),
-            std::env::var("XDG_DATA_HOME").unwrap_or_else(|_| format!("{}/.local/share", home_str)),
+            env("XDG_DATA_HOME").unwrap_or_else(|| {
+                crate::platform::default_data_home()
+                    .to_string_lossy()
+                    .to_string()
+            }),
         );
         vars.insert(
             "XDG_CONFIG_HOME".to_string(),
-            std::env::var("XDG_CONFIG_HOME").unwrap_or_else(|_| format!("{}/.config", home_str)),
+            env("XDG_CONFIG_HOME").unwrap_or_else(|| format!("{}/.config", home_str)),
         );
         vars.insert(
             "XDG_BIN_HOME".to_string(),
-            std::env::var("XDG_BIN_HOME").unwrap_or_else(|_| format!("{}/.local/bin", home_str)),
+            env("XDG_BIN_HOME").unwrap_or_else(|| format!("{}/.local/bin", home_str)),
         );
     }
 
@@ -125,6 +190,12 @@ pub fn save_template(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
 #   $XDG_CONFIG_HOME - user config directory (~/.config)
 #   $XDG_BIN_HOME - user bin directory (~/.local/bin)
 
+#
+# Targets may contain {version}, {major}, {minor} and {patch}. Such links
+# are kept for every installed version, e.g. to have my_binary1.2 and
+# my_binary1.3 next to the unversioned my_binary of the current version:
+#   bin/my_binary $XDG_BIN_HOME/my_binary{major}.{minor}
+
 bin/my_binary $HOME/.local/bin/my_binary
 share/applications/my_app.desktop $XDG_DATA_HOME/applications/my_app.desktop
 "#;
@@ -137,7 +208,8 @@ share/applications/my_app.desktop $XDG_DATA_HOME/applications/my_app.desktop
 /// - `path` — path to the symlist file
 /// - `package_root` — root directory of the package
 ///
-/// Returns a vector of `(source_path, target_path)`.
+/// Returns a vector of `(source_path, target_path)`; bin targets are
+/// mapped through the configured bin layout.
 ///
 /// # Errors
 /// - [`SymlistError::Io`] — if the file cannot be read
@@ -157,8 +229,34 @@ pub fn load_symlist(
     path: &Path,
     package_root: &Path,
 ) -> Result<Vec<(PathBuf, PathBuf)>, SymlistError> {
+    Ok(load_links(path, package_root)?
+        .into_iter()
+        .map(|link| (link.source, link.target))
+        .collect())
+}
+
+/// Loads a symlink list like [`load_symlist`], telling versioned links
+/// apart
+///
+/// Versioned targets are filled in from the version in
+/// `package_root/uhp.toml`; without one they are left out.
+pub fn load_links(path: &Path, package_root: &Path) -> Result<Vec<Link>, SymlistError> {
     let content = fs::read_to_string(path)?;
+    let version = meta_parser(&package_root.join("uhp.toml"))
+        .ok()
+        .map(|meta| meta.version().clone());
+    parse_links(&content, package_root, version.as_ref())
+}
 
+/// Parses symlist `content` of a package to be placed at `package_root`
+///
+/// Like [`load_links`], but with the package `version` given rather than
+/// read from `uhp.toml`, for symlists that are not installed yet.
+pub fn parse_links(
+    content: &str,
+    package_root: &Path,
+    version: Option<&Version>,
+) -> Result<Vec<Link>, SymlistError> {
     let mut entries = Vec::new();
 
     for (line_num, line) in content.lines().enumerate() {
@@ -174,12 +272,21 @@ pub fn load_symlist(
         }
     }
 
+    let layout = BinLayout::configured();
     Ok(entries
         .into_iter()
-        .map(|e| {
-            let src = package_root.join(e.source);
-            let dst = expand_vars(&e.target);
-            (src, dst)
+        .filter_map(|e| {
+            let versioned = e.is_versioned();
+            let target = match version {
+                Some(version) if versioned => expand_version(&e.target, version),
+                None if versioned => return None,
+                _ => e.target,
+            };
+            Some(Link {
+                source: package_root.join(e.source),
+                target: layout.relocate(expand_vars(&target)),
+                versioned,
+            })
         })
         .collect())
 }
@@ -201,14 +308,36 @@ mod tests {
 
     #[test]
     fn test_expand_vars_xdg() {
-        let home = dirs::home_dir().unwrap();
         let xdg_data = std::env::var("XDG_DATA_HOME")
-            .unwrap_or_else(|_| format!("{}/.local/share", home.to_string_lossy()));
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| crate::platform::default_data_home());
         let path = "$XDG_DATA_HOME/some_dir";
         let expanded = expand_vars(path);
         assert_eq!(expanded, PathBuf::from(xdg_data).join("some_dir"));
     }
 
+    #[tokio::test]
+    async fn test_expand_vars_under_root() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        crate::platform::with_root(&root, async {
+            assert_eq!(expand_vars("$HOME/bin/foo"), root.join("bin/foo"));
+            assert_eq!(
+                expand_vars("$XDG_CONFIG_HOME/foo"),
+                root.join(".config/foo")
+            );
+            assert_eq!(
+                expand_vars("$XDG_DATA_HOME/foo"),
+                root.join(".local/share/foo")
+            );
+            assert_eq!(
+                expand_vars("$XDG_BIN_HOME/foo"),
+                root.join(".local/bin/foo")
+            );
+        })
+        .await;
+    }
+
     #[test]
     fn test_parse_symlist_line() {
         let line = "/package/bin/foo $HOME/.local/bin/foo";
@@ -266,4 +395,37 @@ share/data $XDG_DATA_HOME/app_data
         assert!(symlinks[1].1.to_string_lossy().ends_with("bar"));
         assert!(symlinks[2].1.to_string_lossy().ends_with("app_data"));
     }
+
+    #[test]
+    fn test_load_links_versioned_targets() {
+        let tmp_dir = tempdir().unwrap();
+        let symlist_path = tmp_dir.path().join("symlist");
+        fs::write(
+            &symlist_path,
+            "bin/py $HOME/py\nbin/py $HOME/py{major}.{minor}\nlib $HOME/lib-{version}\n",
+        )
+        .unwrap();
+
+        let links = load_links(&symlist_path, tmp_dir.path()).unwrap();
+        assert_eq!(links.len(), 1);
+        assert!(!links[0].versioned);
+
+        fs::write(
+            tmp_dir.path().join("uhp.toml"),
+            crate::package::test_manifest("py", "3.12.1"),
+        )
+        .unwrap();
+        let links = load_links(&symlist_path, tmp_dir.path()).unwrap();
+        let home = dirs::home_dir().unwrap();
+        let targets: Vec<(PathBuf, bool)> =
+            links.into_iter().map(|l| (l.target, l.versioned)).collect();
+        assert_eq!(
+            targets,
+            [
+                (home.join("py"), false),
+                (home.join("py3.12"), true),
+                (home.join("lib-3.12.1"), true),
+            ]
+        );
+    }
 }
@@ -0,0 +1,159 @@
+//! # Local Usage Statistics
+//!
+//! Counts how often each command is run and each package is installed or
+//! updated, so users can review their own workflow with
+//! `uhpm stats --usage`.
+//!
+//! The counters live in the `usage_counters` table of the local package
+//! database and are never transmitted anywhere; uhpm has no telemetry.
+//! Recording is disabled with `record_usage: false` in `config.ron`, and
+//! `uhpm stats --usage --reset` deletes what was recorded.
+//!
+//! Plain `uhpm stats` summarizes the installation itself, see
+//! [`install_stats()`].
+
+use crate::access;
+use crate::config::Config;
+use crate::db::PackageDB;
+use crate::debug;
+use crate::error::UhpmError;
+
+/// What a counter counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageKind {
+    /// A CLI command was run
+    Command,
+    /// A package was installed
+    Install,
+    /// A package was updated
+    Update,
+}
+
+impl UsageKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UsageKind::Command => "command",
+            UsageKind::Install => "install",
+            UsageKind::Update => "update",
+        }
+    }
+}
+
+/// A single counter
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageEntry {
+    /// Command or package name
+    pub name: String,
+    pub count: u64,
+    /// RFC 3339 timestamp of the last use
+    pub last_used: String,
+}
+
+/// All counters, most used first within each kind
+#[derive(Debug, Clone, Default)]
+pub struct UsageReport {
+    pub commands: Vec<UsageEntry>,
+    pub installs: Vec<UsageEntry>,
+    pub updates: Vec<UsageEntry>,
+}
+
+impl UsageReport {
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty() && self.installs.is_empty() && self.updates.is_empty()
+    }
+}
+
+/// Size of the installation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InstallStats {
+    /// Installed packages
+    pub packages: usize,
+    /// Installed versions across all packages
+    pub versions: usize,
+    /// Files and links tracked in the database
+    pub files: usize,
+}
+
+/// Counts installed packages, versions and tracked files
+pub async fn install_stats(db: &PackageDB) -> Result<InstallStats, UhpmError> {
+    let versions = db.list_packages().await?;
+    let mut names: Vec<&str> = versions.iter().map(|(name, _, _)| name.as_str()).collect();
+    names.sort();
+    names.dedup();
+    Ok(InstallStats {
+        packages: names.len(),
+        versions: versions.len(),
+        files: db.list_all_installed_files().await?.len(),
+    })
+}
+
+/// Increments the counter for `name`, unless recording is disabled
+///
+/// Statistics are best effort: failures are logged and never affect the
+/// operation being counted.
+pub async fn record(db: &PackageDB, kind: UsageKind, name: &str) {
+    if access::is_read_only() || !Config::load_or_default().record_usage {
+        return;
+    }
+    let now = chrono::Local::now().to_rfc3339();
+    if let Err(e) = db.record_usage(kind.as_str(), name, &now).await {
+        debug!("usage.record_failed", kind.as_str(), name, e);
+    }
+}
+
+async fn entries(db: &PackageDB, kind: UsageKind) -> Result<Vec<UsageEntry>, UhpmError> {
+    Ok(db
+        .get_usage(kind.as_str())
+        .await?
+        .into_iter()
+        .map(|(name, count, last_used)| UsageEntry {
+            name,
+            count: count.max(0) as u64,
+            last_used,
+        })
+        .collect())
+}
+
+/// Reads all recorded counters
+pub async fn report(db: &PackageDB) -> Result<UsageReport, UhpmError> {
+    Ok(UsageReport {
+        commands: entries(db, UsageKind::Command).await?,
+        installs: entries(db, UsageKind::Install).await?,
+        updates: entries(db, UsageKind::Update).await?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_counters_accumulate() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = PackageDB::new(&tmp.path().join("packages.db"))
+            .unwrap()
+            .init()
+            .await
+            .unwrap();
+
+        db.record_usage("command", "install", "2026-01-01T00:00:00+00:00")
+            .await
+            .unwrap();
+        db.record_usage("command", "list", "2026-01-02T00:00:00+00:00")
+            .await
+            .unwrap();
+        db.record_usage("command", "list", "2026-01-03T00:00:00+00:00")
+            .await
+            .unwrap();
+
+        let report = report(&db).await.unwrap();
+        assert_eq!(report.commands[0].name, "list");
+        assert_eq!(report.commands[0].count, 2);
+        assert_eq!(report.commands[0].last_used, "2026-01-03T00:00:00+00:00");
+        assert_eq!(report.commands[1].count, 1);
+        assert!(report.installs.is_empty());
+
+        db.clear_usage().await.unwrap();
+        assert!(super::report(&db).await.unwrap().is_empty());
+    }
+}
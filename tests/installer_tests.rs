@@ -1,6 +1,7 @@
 use tempfile::tempdir;
 use uhpm::db::PackageDB;
-use uhpm::package::{Package, Source, installer, remover};
+use uhpm::package::{LinkMode, Package, Source, installer, remover};
+use uhpm::platform;
 use uhpm::{info, lprintln};
 
 // Test with maximum debugging
@@ -10,17 +11,13 @@ async fn test_installer_with_debug_output() -> Result<(), Box<dyn std::error::Er
 
     let tmp_dir = tempdir()?;
     let home_path = tmp_dir.path().to_path_buf();
-    unsafe {
-        std::env::set_var("HOME", &home_path);
-    }
-
     lprintln!("test.installer_debug.start", home_path.display());
 
     // Create necessary directories
-    std::fs::create_dir_all(home_path.join(".uhpm/packages"))?;
+    std::fs::create_dir_all(home_path.join("packages"))?;
     std::fs::create_dir_all(home_path.join(".local/bin"))?;
 
-    let db_path = home_path.join(".uhpm/packages.db");
+    let db_path = home_path.join("packages.db");
     let db = PackageDB::new(&db_path)?.init().await?;
     lprintln!("test.installer_debug.db_initialized", db_path.display());
 
@@ -102,7 +99,7 @@ async fn test_installer_with_debug_output() -> Result<(), Box<dyn std::error::Er
 
     // Install with detailed error handling
     lprintln!("test.installer_debug.calling_installer", "");
-    let result = installer::install(&archive_path, &db).await;
+    let result = installer::install_at(&archive_path, &db, &home_path, false).await;
 
     match &result {
         Ok(()) => {
@@ -122,7 +119,7 @@ async fn test_installer_with_debug_output() -> Result<(), Box<dyn std::error::Er
             );
 
             // Check if package directory was created
-            let pkg_install_dir = home_path.join(".uhpm/packages/debug-pkg-1.0.0");
+            let pkg_install_dir = home_path.join("packages/debug-pkg-1.0.0");
             lprintln!(
                 "test.installer_debug.expected_install_dir",
                 pkg_install_dir.display()
@@ -143,7 +140,7 @@ async fn test_installer_with_debug_output() -> Result<(), Box<dyn std::error::Er
             }
 
             // Cleanup
-            let _ = remover::remove("debug-pkg", &db).await;
+            let _ = remover::remove_at("debug-pkg", &db, &home_path, false).await;
         }
         Err(e) => {
             lprintln!("test.installer_debug.install_failed", format!("{}", e));
@@ -165,13 +162,9 @@ async fn test_installer_minimal_working() -> Result<(), Box<dyn std::error::Erro
 
     let tmp_dir = tempdir()?;
     let home_path = tmp_dir.path().to_path_buf();
-    unsafe {
-        std::env::set_var("HOME", &home_path);
-    }
+    std::fs::create_dir_all(home_path.join("packages"))?;
 
-    std::fs::create_dir_all(home_path.join(".uhpm/packages"))?;
-
-    let db_path = home_path.join(".uhpm/packages.db");
+    let db_path = home_path.join("packages.db");
     let db = PackageDB::new(&db_path)?.init().await?;
 
     // Create the simplest possible package
@@ -206,7 +199,7 @@ async fn test_installer_minimal_working() -> Result<(), Box<dyn std::error::Erro
     tar.finish()?;
 
     // Try to install
-    let result = installer::install(&archive_path, &db).await;
+    let result = installer::install_at(&archive_path, &db, &home_path, false).await;
 
     // For now, just check that it doesn't panic
     info!(
@@ -216,7 +209,7 @@ async fn test_installer_minimal_working() -> Result<(), Box<dyn std::error::Erro
 
     // Cleanup if installation was successful
     if result.is_ok() {
-        let _ = remover::remove("minimal", &db).await;
+        let _ = remover::remove_at("minimal", &db, &home_path, false).await;
     }
 
     Ok(())
@@ -229,13 +222,9 @@ async fn test_installer_simple() -> Result<(), Box<dyn std::error::Error>> {
 
     let tmp_dir = tempdir()?;
     let home_path = tmp_dir.path().to_path_buf();
-    unsafe {
-        std::env::set_var("HOME", &home_path);
-    }
-
-    std::fs::create_dir_all(home_path.join(".uhpm/packages"))?;
+    std::fs::create_dir_all(home_path.join("packages"))?;
 
-    let db_path = home_path.join(".uhpm/packages.db");
+    let db_path = home_path.join("packages.db");
     let db = PackageDB::new(&db_path)?.init().await?;
 
     // Create minimal package structure
@@ -267,12 +256,12 @@ async fn test_installer_simple() -> Result<(), Box<dyn std::error::Error>> {
     tar.append_path_with_name(&symlist_path, "symlist")?;
     tar.finish()?;
 
-    let result = installer::install(&archive_path, &db).await;
+    let result = installer::install_at(&archive_path, &db, &home_path, false).await;
     info!("test.installer_simple.result", format!("{:?}", result));
 
     // Cleanup
     if result.is_ok() {
-        let _ = remover::remove("simple-pkg", &db).await;
+        let _ = remover::remove_at("simple-pkg", &db, &home_path, false).await;
     }
 
     Ok(())
@@ -282,13 +271,9 @@ async fn test_installer_simple() -> Result<(), Box<dyn std::error::Error>> {
 async fn test_installer_database_only() -> Result<(), Box<dyn std::error::Error>> {
     let tmp_dir = tempdir()?;
     let home_path = tmp_dir.path().to_path_buf();
-    unsafe {
-        std::env::set_var("HOME", &home_path);
-    }
-
-    std::fs::create_dir_all(home_path.join(".uhpm/packages"))?;
+    std::fs::create_dir_all(home_path.join("packages"))?;
 
-    let db_path = home_path.join(".uhpm/packages.db");
+    let db_path = home_path.join("packages.db");
     let db = PackageDB::new(&db_path)?.init().await?;
 
     let pkg = Package::new(
@@ -300,7 +285,7 @@ async fn test_installer_database_only() -> Result<(), Box<dyn std::error::Error>
         vec![("dep1".to_string(), semver::Version::parse("1.0.0").unwrap())],
     );
 
-    db.add_package_full(&pkg, &["/fake/path/file1".to_string()])
+    db.add_package_full(&pkg, &["/fake/path/file1".to_string()], LinkMode::Symlink)
         .await?;
 
     let packages = db.list_packages().await?;
@@ -308,7 +293,73 @@ async fn test_installer_database_only() -> Result<(), Box<dyn std::error::Error>
     assert!(db_test_pkg.is_some(), "Package should be in database");
 
     // Cleanup
-    let _ = remover::remove("db-test", &db).await;
+    let _ = remover::remove_at("db-test", &db, &home_path, false).await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_relink_restores_removed_links() -> Result<(), Box<dyn std::error::Error>> {
+    use flate2::write::GzEncoder;
+    use uhpm::package::links::{self, Relinked};
+
+    let tmp_dir = tempdir()?;
+    let home_path = tmp_dir.path().to_path_buf();
+    std::fs::create_dir_all(home_path.join("packages"))?;
+
+    let db_path = home_path.join("packages.db");
+    let db = PackageDB::new(&db_path)?.init().await?;
+
+    let pkg_dir = home_path.join("relink-pkg");
+    let bin_dir = pkg_dir.join("bin");
+    std::fs::create_dir_all(&bin_dir)?;
+    std::fs::write(bin_dir.join("relink_app"), "#!/bin/sh\necho relink")?;
+
+    let pkg = Package::new(
+        "relink-pkg",
+        semver::Version::parse("1.0.0").unwrap(),
+        "Test Author",
+        Source::Raw("test://relink".to_string()),
+        "checksum789",
+        vec![],
+    );
+    let meta_path = pkg_dir.join("uhp.toml");
+    pkg.save_to_toml(&meta_path)?;
+
+    let target_path = home_path.join(".local/bin/relink_app");
+    let symlist_path = pkg_dir.join("symlist");
+    std::fs::write(
+        &symlist_path,
+        format!("bin/relink_app {}", target_path.display()),
+    )?;
+
+    let archive_path = home_path.join("relink-pkg.uhp");
+    let archive_file = std::fs::File::create(&archive_path)?;
+    let enc = GzEncoder::new(archive_file, flate2::Compression::default());
+    let mut tar = tar::Builder::new(enc);
+    tar.append_path_with_name(&meta_path, "uhp.toml")?;
+    tar.append_path_with_name(&symlist_path, "symlist")?;
+    tar.append_dir_all("bin", &bin_dir)?;
+    tar.into_inner()?.finish()?;
+
+    installer::install_at(&archive_path, &db, &home_path, false).await?;
+    assert!(target_path.exists());
+
+    // The user cleans out ~/.local/bin
+    std::fs::remove_file(&target_path)?;
+    let relinked = platform::with_root(&home_path, links::relink(&db, "relink-pkg")).await?;
+    assert_eq!(
+        relinked,
+        Relinked::Links {
+            removed: 0,
+            created: 1
+        }
+    );
+    assert_eq!(
+        std::fs::read_link(&target_path)?,
+        home_path.join("packages/relink-pkg-1.0.0/bin/relink_app")
+    );
 
+    let _ = remover::remove_at("relink-pkg", &db, &home_path, false).await;
     Ok(())
 }
@@ -1,7 +1,7 @@
 use std::path::Path;
 use tempfile::tempdir;
 use uhpm::db::PackageDB;
-use uhpm::package::{Package, Source, installer, remover};
+use uhpm::package::{LinkMode, Package, Source, installer, remover};
 use uhpm::{info, lprintln};
 
 // Вспомогательные функции для создания тестовых пакетов
@@ -29,18 +29,7 @@ fn create_test_archive(
     pkg_dir: &Path,
     output_path: &Path,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    use flate2::write::GzEncoder;
-
-    let file = std::fs::File::create(output_path)?;
-    let encoder = GzEncoder::new(file, flate2::Compression::default());
-    let mut tar_builder = tar::Builder::new(encoder);
-
-    // Добавляем файлы рекурсивно из директории пакета
-    tar_builder.append_dir_all(".", pkg_dir)?;
-
-    // Важно: завершаем создание архива
-    tar_builder.finish()?;
-
+    uhpm::archive::create(pkg_dir, output_path, &uhpm::archive::ArchiveOptions::new())?;
     Ok(())
 }
 
@@ -65,18 +54,13 @@ async fn test_package_lifecycle_simple() -> Result<(), Box<dyn std::error::Error
     let tmp_dir = tempdir()?;
     let home_path = tmp_dir.path().to_path_buf();
 
-    // Устанавливаем HOME переменную
-    unsafe {
-        std::env::set_var("HOME", &home_path);
-    }
-
     info!("test.integration.lifecycle.start", home_path.display());
 
     // Setup directories
     std::fs::create_dir_all(home_path.join(".local/bin"))?;
-    std::fs::create_dir_all(home_path.join(".uhpm/packages"))?;
+    std::fs::create_dir_all(home_path.join("packages"))?;
 
-    let db_path = home_path.join(".uhpm/packages.db");
+    let db_path = home_path.join("packages.db");
     let db = PackageDB::new(&db_path)?.init().await?;
 
     // Create and install package v1.0.0
@@ -92,7 +76,7 @@ async fn test_package_lifecycle_simple() -> Result<(), Box<dyn std::error::Error
     let metadata = std::fs::metadata(&archive_v1)?;
     assert!(metadata.len() > 0, "Archive should not be empty");
 
-    installer::install(&archive_v1, &db).await?;
+    installer::install_at(&archive_v1, &db, &home_path, false).await?;
     info!("test.integration.lifecycle.installed_v1");
 
     // Verify installation
@@ -108,7 +92,7 @@ async fn test_package_lifecycle_simple() -> Result<(), Box<dyn std::error::Error
     let archive_v2 = home_path.join("test-package-2.0.0.uhp");
     create_test_archive(&pkg_dir_v2, &archive_v2)?;
 
-    installer::install(&archive_v2, &db).await?;
+    installer::install_at(&archive_v2, &db, &home_path, false).await?;
     info!("test.integration.lifecycle.installed_v2");
 
     // Verify both versions are in database
@@ -124,7 +108,7 @@ async fn test_package_lifecycle_simple() -> Result<(), Box<dyn std::error::Error
     );
 
     // Remove package
-    remover::remove("test-package", &db).await?;
+    remover::remove_at("test-package", &db, &home_path, false).await?;
     info!("test.integration.lifecycle.removed");
 
     // Verify removal - проверяем только что пакет удален из БД
@@ -142,15 +126,11 @@ async fn test_package_lifecycle_simple() -> Result<(), Box<dyn std::error::Error
 async fn test_basic_install_remove() -> Result<(), Box<dyn std::error::Error>> {
     let tmp_dir = tempdir()?;
     let home_path = tmp_dir.path().to_path_buf();
-    unsafe {
-        std::env::set_var("HOME", &home_path);
-    }
-
     // Создаем необходимые директории
-    std::fs::create_dir_all(home_path.join(".uhpm/packages"))?;
+    std::fs::create_dir_all(home_path.join("packages"))?;
     std::fs::create_dir_all(home_path.join("target-bin"))?;
 
-    let db_path = home_path.join(".uhpm/packages.db");
+    let db_path = home_path.join("packages.db");
     let db = PackageDB::new(&db_path)?.init().await?;
 
     // Create package
@@ -190,7 +170,7 @@ async fn test_basic_install_remove() -> Result<(), Box<dyn std::error::Error>> {
     assert!(archive_metadata.len() > 0, "Archive should not be empty");
 
     // Install
-    installer::install(&archive_path, &db).await?;
+    installer::install_at(&archive_path, &db, &home_path, false).await?;
 
     // Verify installation - проверяем только базу данных
     let version = db.get_package_version("test-app").await?;
@@ -202,7 +182,7 @@ async fn test_basic_install_remove() -> Result<(), Box<dyn std::error::Error>> {
     assert!(test_app_exists, "Package should be in database");
 
     // Remove
-    remover::remove("test-app", &db).await?;
+    remover::remove_at("test-app", &db, &home_path, false).await?;
 
     let version_after = db.get_package_version("test-app").await?;
     assert!(
@@ -218,13 +198,9 @@ async fn test_basic_install_remove() -> Result<(), Box<dyn std::error::Error>> {
 async fn test_package_with_dependencies() -> Result<(), Box<dyn std::error::Error>> {
     let tmp_dir = tempdir()?;
     let home_path = tmp_dir.path().to_path_buf();
-    unsafe {
-        std::env::set_var("HOME", &home_path);
-    }
+    std::fs::create_dir_all(home_path.join("packages"))?;
 
-    std::fs::create_dir_all(home_path.join(".uhpm/packages"))?;
-
-    let db_path = home_path.join(".uhpm/packages.db");
+    let db_path = home_path.join("packages.db");
     let db = PackageDB::new(&db_path)?.init().await?;
 
     // Create package with dependencies
@@ -265,7 +241,7 @@ async fn test_package_with_dependencies() -> Result<(), Box<dyn std::error::Erro
     assert!(archive_metadata.len() > 0, "Archive should not be empty");
 
     // Install
-    installer::install(&archive_path, &db).await?;
+    installer::install_at(&archive_path, &db, &home_path, false).await?;
 
     // Verify installation and dependencies
     let installed_pkg = db.get_current_package("package-with-deps").await?;
@@ -278,7 +254,7 @@ async fn test_package_with_dependencies() -> Result<(), Box<dyn std::error::Erro
     assert_eq!(deps[1].0, "dep-package-2");
 
     // Cleanup
-    remover::remove("package-with-deps", &db).await?;
+    remover::remove_at("package-with-deps", &db, &home_path, false).await?;
 
     Ok(())
 }
@@ -290,10 +266,6 @@ async fn test_archive_creation() -> Result<(), Box<dyn std::error::Error>> {
 
     let tmp_dir = tempdir()?;
     let home_path = tmp_dir.path().to_path_buf();
-    unsafe {
-        std::env::set_var("HOME", &home_path);
-    }
-
     // Create test package directory
     let pkg_dir = home_path.join("test-archive-pkg");
     std::fs::create_dir_all(&pkg_dir)?;
@@ -334,13 +306,9 @@ async fn test_archive_creation() -> Result<(), Box<dyn std::error::Error>> {
 async fn test_database_only() -> Result<(), Box<dyn std::error::Error>> {
     let tmp_dir = tempdir()?;
     let home_path = tmp_dir.path().to_path_buf();
-    unsafe {
-        std::env::set_var("HOME", &home_path);
-    }
-
-    std::fs::create_dir_all(home_path.join(".uhpm/packages"))?;
+    std::fs::create_dir_all(home_path.join("packages"))?;
 
-    let db_path = home_path.join(".uhpm/packages.db");
+    let db_path = home_path.join("packages.db");
     let db = PackageDB::new(&db_path)?.init().await?;
 
     // Создаем пакет напрямую в базе данных
@@ -369,8 +337,10 @@ async fn test_database_only() -> Result<(), Box<dyn std::error::Error>> {
             "/fake/path/file1".to_string(),
             "/fake/path/file2".to_string(),
         ],
+        LinkMode::Symlink,
     )
     .await?;
+    db.set_current_version("db-only-test", "1.0.0").await?;
 
     // Проверяем что пакет есть в базе
     let packages = db.list_packages().await?;
@@ -393,7 +363,7 @@ async fn test_database_only() -> Result<(), Box<dyn std::error::Error>> {
     assert_eq!(installed_files.len(), 2, "Should have 2 installed files");
 
     // Удаляем пакет - используем правильное имя пакета
-    remover::remove("db-only-test", &db).await?;
+    remover::remove_at("db-only-test", &db, &home_path, false).await?;
 
     // Проверяем что пакет удален - ждем немного для асинхронных операций
     tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
@@ -429,13 +399,9 @@ async fn test_database_only() -> Result<(), Box<dyn std::error::Error>> {
 async fn test_install_remove_without_switcher() -> Result<(), Box<dyn std::error::Error>> {
     let tmp_dir = tempdir()?;
     let home_path = tmp_dir.path().to_path_buf();
-    unsafe {
-        std::env::set_var("HOME", &home_path);
-    }
-
-    std::fs::create_dir_all(home_path.join(".uhpm/packages"))?;
+    std::fs::create_dir_all(home_path.join("packages"))?;
 
-    let db_path = home_path.join(".uhpm/packages.db");
+    let db_path = home_path.join("packages.db");
     let db = PackageDB::new(&db_path)?.init().await?;
 
     // Создаем пакет
@@ -463,7 +429,7 @@ async fn test_install_remove_without_switcher() -> Result<(), Box<dyn std::error
     create_test_archive(&pkg_dir, &archive_path)?;
 
     // Install
-    installer::install(&archive_path, &db).await?;
+    installer::install_at(&archive_path, &db, &home_path, false).await?;
 
     // Verify installation
     let packages = db.list_packages().await?;
@@ -471,7 +437,7 @@ async fn test_install_remove_without_switcher() -> Result<(), Box<dyn std::error
     assert!(simple_package_exists, "Package should be in database");
 
     // Remove
-    remover::remove("simple-package", &db).await?;
+    remover::remove_at("simple-package", &db, &home_path, false).await?;
 
     // Verify removal
     let packages_after = db.list_packages().await?;
@@ -491,13 +457,9 @@ async fn test_install_remove_without_switcher() -> Result<(), Box<dyn std::error
 async fn test_multiple_packages() -> Result<(), Box<dyn std::error::Error>> {
     let tmp_dir = tempdir()?;
     let home_path = tmp_dir.path().to_path_buf();
-    unsafe {
-        std::env::set_var("HOME", &home_path);
-    }
-
-    std::fs::create_dir_all(home_path.join(".uhpm/packages"))?;
+    std::fs::create_dir_all(home_path.join("packages"))?;
 
-    let db_path = home_path.join(".uhpm/packages.db");
+    let db_path = home_path.join("packages.db");
     let db = PackageDB::new(&db_path)?.init().await?;
 
     // Создаем несколько разных пакетов
@@ -529,7 +491,7 @@ async fn test_multiple_packages() -> Result<(), Box<dyn std::error::Error>> {
         let archive_path = home_path.join(format!("{}.uhp", name));
         create_test_archive(&pkg_dir, &archive_path)?;
 
-        installer::install(&archive_path, &db).await?;
+        installer::install_at(&archive_path, &db, &home_path, false).await?;
     }
 
     // Проверяем что все пакеты установлены
@@ -542,7 +504,7 @@ async fn test_multiple_packages() -> Result<(), Box<dyn std::error::Error>> {
 
     // Удаляем все пакеты
     for (name, _) in packages {
-        remover::remove(name, &db).await?;
+        remover::remove_at(name, &db, &home_path, false).await?;
     }
 
     // Проверяем что все пакеты удалены